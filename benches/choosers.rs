@@ -0,0 +1,198 @@
+//! Benchmarks of what runs for every country and place gaia draws, each frame: the polygon color
+//! chooser in every mode, the label style chooser, classifying values into a mode's classes, and
+//! the country filter. Run with `cargo bench`, before and after a change to them, to compare.
+//!
+//! The countries are the ones in `tests/fixtures/countries.json`, repeated to about as many as
+//! Natural Earth has, and the places are made up to about as many as it has, so that no assets
+//! need to be generated first.
+
+#[macro_use]
+extern crate criterion;
+extern crate gaia_assetgen;
+extern crate gaia_demo;
+extern crate serde_json;
+
+use std::fs::File;
+use std::path::Path;
+
+use criterion::{black_box, Criterion};
+use gaia_assetgen::{FeaturesData, MultiLevelPoint, MultiLevelPolygon, Properties, MAX_LEVEL};
+use serde_json::Value;
+
+use gaia_demo::class_breaks;
+use gaia_demo::country_filter::CountryFilter;
+use gaia_demo::features::Features;
+use gaia_demo::headless::Headless;
+use gaia_demo::percentile::Percentiles;
+use gaia_demo::settings::Settings;
+use gaia_demo::split_view::View;
+use gaia_demo::state::FrameInputs;
+use gaia_demo::MapMode;
+
+/// About as many countries and places as Natural Earth has.
+const COUNTRIES: usize = 250;
+const PLACES: usize = 7000;
+
+/// The camera height places are labeled as of, low enough that most of them are.
+const CAMERA_HEIGHT: f32 = 0.1;
+
+/// `COUNTRIES` countries, each a copy of one in the fixture with an id and name of its own, and a
+/// population scaled so that they do not all tie.
+fn countries() -> Vec<Properties> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/countries.json");
+    let fixture: Vec<Properties> = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+
+    (0..COUNTRIES)
+        .map(|i| {
+            let mut country = fixture[i % fixture.len()].clone();
+            let name = format!("{} {}", country["ADMIN"].as_str().unwrap_or("Country"), i);
+            country.insert("ISO_A3".to_string(), Value::from(format!("C{:02X}", i)));
+            country.insert("ADMIN".to_string(), Value::from(name.clone()));
+            country.insert("NAME".to_string(), Value::from(name));
+            if let Some(population) = country.get("POP_EST").and_then(Value::as_f64) {
+                let population = population * (1 + i % 13) as f64 / 7.0;
+                country.insert("POP_EST".to_string(), Value::from(population.round()));
+            }
+
+            country
+        })
+        .collect()
+}
+
+/// `PLACES` places, with the properties the label style chooser reads, spread over zoom levels as
+/// Natural Earth's are.
+fn places() -> Vec<Properties> {
+    (0..PLACES)
+        .map(|i| {
+            let json = format!(
+                r#"{{"NAME": "Place {}", "NAMEASCII": "Place {}", "min_zoom": {},
+                    "ADM0CAP": {}, "SCALERANK": {}, "POP_MAX": {}}}"#,
+                i,
+                i,
+                1.7 + (i % 80) as f64 / 10.0,
+                if i % 40 == 0 { 1 } else { 0 },
+                i % 10,
+                (i * 7919) % 20_000_000
+            );
+            serde_json::from_str(&json).unwrap()
+        })
+        .collect()
+}
+
+fn headless() -> Headless {
+    let polygons = countries()
+        .into_iter()
+        .map(|properties| MultiLevelPolygon {
+            properties,
+            bounding_box: [(0.5, 0.5), (0.5, 0.5)],
+            levels: vec![vec![]; MAX_LEVEL as usize + 1],
+        })
+        .collect();
+    let points = places()
+        .into_iter()
+        .map(|properties| MultiLevelPoint {
+            properties,
+            coordinates: [0.5, 0.5],
+            levels: vec![],
+        })
+        .collect();
+    let features = Features::from_data(FeaturesData { polygons, points });
+
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/target/tmp/bench");
+    Headless::new(Settings::default(), features, Path::new(dir))
+}
+
+/// The polygon color chooser over every country, in each mode. The color cache is cleared before
+/// each pass, as it is every frame of an animated mode, and then left, as it is otherwise.
+fn polygon_colors(c: &mut Criterion) {
+    let mut headless = headless();
+    let polygons: Vec<Properties> = headless
+        .state
+        .features
+        .polygons()
+        .iter()
+        .map(|polygon| polygon.properties.clone())
+        .collect();
+
+    for &mode in &MapMode::ALL {
+        headless.state.map_mode = mode;
+        headless.state.clear_colors();
+        let state = &headless.state;
+        let frame = state.frame_inputs(View::Whole);
+
+        c.bench_function(&format!("polygon_color_chooser/{:?}", mode), |b| {
+            b.iter(|| {
+                state.clear_colors();
+                for properties in &polygons {
+                    black_box(state.polygon_color_chooser(properties, frame));
+                }
+            })
+        });
+        c.bench_function(&format!("polygon_color_chooser/{:?}/cached", mode), |b| {
+            b.iter(|| {
+                for properties in &polygons {
+                    black_box(state.polygon_color_chooser(properties, frame));
+                }
+            })
+        });
+    }
+}
+
+/// The label style chooser over every place.
+fn label_styles(c: &mut Criterion) {
+    let headless = headless();
+    let state = &headless.state;
+    let places = places();
+    let frame = FrameInputs {
+        camera_height: CAMERA_HEIGHT,
+        ..state.frame_inputs(View::Whole)
+    };
+
+    c.bench_function("label_style_chooser", |b| {
+        b.iter(|| {
+            for properties in &places {
+                black_box(state.label_style_chooser(properties, frame));
+            }
+        })
+    });
+}
+
+/// Ranking every country by population, as percentile mode does when its countries change, and
+/// classifying every country under the breaks that makes, as it does for each color.
+fn classification(c: &mut Criterion) {
+    let headless = headless();
+    let countries = headless.state.features.countries();
+
+    c.bench_function("percentiles_of_population", |b| {
+        b.iter(|| black_box(Percentiles::of_population(countries)))
+    });
+
+    let percentiles = Percentiles::of_population(countries);
+    let breaks = percentiles.quantile_breaks();
+    c.bench_function("class_breaks::class", |b| {
+        b.iter(|| {
+            for country in countries {
+                black_box(class_breaks::class(breaks, country.pop_est.unwrap_or(0.0)));
+            }
+        })
+    });
+}
+
+/// Every country filter over every country, as filtering the countries shown does.
+fn country_filter(c: &mut Criterion) {
+    let headless = headless();
+    let countries = headless.state.features.countries();
+
+    c.bench_function("country_filter", |b| {
+        b.iter(|| {
+            for filter in &CountryFilter::ALL {
+                for country in countries {
+                    black_box(filter.includes(country));
+                }
+            }
+        })
+    });
+}
+
+criterion_group!(benches, polygon_colors, label_styles, classification, country_filter);
+criterion_main!(benches);
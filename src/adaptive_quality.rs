@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+
+use settings::QualitySettings;
+
+/// How many recent frames the 95th percentile is taken over.
+const WINDOW: usize = 60;
+
+/// How long, in seconds, frames must stay over budget before the level of detail drops.
+const SUSTAIN_SECS: f64 = 2.0;
+
+/// The 95th percentile must be under this fraction of the budget for the level of detail to rise
+/// again, so that frames just under budget do not make it go back and forth.
+const HEADROOM: f32 = 0.7;
+
+/// How long, in seconds, frames must stay under `HEADROOM` before the level of detail rises.
+const RECOVER_SECS: f64 = 5.0;
+
+/// The least time, in seconds, between two changes to the level of detail, so that the frames at
+/// the new level are measured before deciding again.
+const MIN_DWELL_SECS: f64 = 3.0;
+
+/// Caps the level of detail below what the camera height calls for while frames are too slow,
+/// one level at a time, and raises the cap again once they have been fast for a while.
+#[derive(Debug)]
+pub struct AdaptiveQuality {
+    settings: QualitySettings,
+    /// Recent frame times, in milliseconds, the newest last.
+    times: VecDeque<f32>,
+    /// The most detailed level allowed, if capped.
+    cap: Option<u8>,
+    /// When frames started being over budget, if they still are.
+    over_since: Option<f64>,
+    /// When frames started having headroom, if they still do.
+    headroom_since: Option<f64>,
+    /// When the cap last changed.
+    last_change: Option<f64>,
+}
+
+impl AdaptiveQuality {
+    pub fn new(settings: QualitySettings) -> AdaptiveQuality {
+        AdaptiveQuality {
+            settings,
+            times: VecDeque::with_capacity(WINDOW),
+            cap: None,
+            over_since: None,
+            headroom_since: None,
+            last_change: None,
+        }
+    }
+
+    /// Applies changed settings. Turning adaptive quality off lifts the cap right away.
+    pub fn set_settings(&mut self, settings: QualitySettings) {
+        if !settings.adaptive {
+            self.cap = None;
+        }
+        if settings != self.settings {
+            self.reset_measurements();
+        }
+
+        self.settings = settings;
+    }
+
+    /// `level`, capped if frames have been too slow.
+    pub fn cap(&self, level: u8) -> u8 {
+        match self.cap {
+            Some(cap) => level.min(cap),
+            None => level,
+        }
+    }
+
+    /// Records a frame that took `ms` to draw at `level`, which is the capped level, and ended
+    /// `time` seconds after some fixed point.
+    pub fn frame(&mut self, time: f64, ms: f32, level: u8) {
+        if !self.settings.adaptive {
+            return;
+        }
+
+        if self.times.len() == WINDOW {
+            self.times.pop_front();
+        }
+        self.times.push_back(ms);
+        // Too few frames say little, especially right after a change.
+        if self.times.len() < WINDOW {
+            return;
+        }
+
+        let percentile_95 = self.percentile_95();
+        let budget = self.settings.frame_budget_ms;
+        if percentile_95 > budget {
+            self.over_since.get_or_insert(time);
+            self.headroom_since = None;
+        } else if percentile_95 < budget * HEADROOM {
+            self.headroom_since.get_or_insert(time);
+            self.over_since = None;
+        } else {
+            self.over_since = None;
+            self.headroom_since = None;
+        }
+
+        let dwelled = self.last_change
+            .map_or(true, |last_change| time - last_change >= MIN_DWELL_SECS);
+        let lasted =
+            |since: Option<f64>, secs: f64| since.map_or(false, |since| time - since >= secs);
+        if !dwelled {
+            return;
+        }
+
+        if lasted(self.over_since, SUSTAIN_SECS) && level > self.settings.min_level {
+            self.cap = Some(level - 1);
+            self.changed(time);
+            info!("Frames are slow, so drawing at most level {}", level - 1);
+        } else if lasted(self.headroom_since, RECOVER_SECS) {
+            if let Some(cap) = self.cap {
+                // The camera height never calls for more than the most detailed level the
+                // thresholds name.
+                let most_detailed = self.settings
+                    .levels
+                    .last()
+                    .map_or(0, |threshold| threshold.level)
+                    .min(self.settings.max_level);
+                self.cap = if cap + 1 >= most_detailed {
+                    None
+                } else {
+                    Some(cap + 1)
+                };
+                self.changed(time);
+                info!("Frames are fast again, so drawing at most level {}", cap + 1);
+            }
+        }
+    }
+
+    /// What adaptive quality is doing, as shown in the performance overlay.
+    pub fn status(&self) -> String {
+        if !self.settings.adaptive {
+            return "Adaptive quality: off".to_string();
+        }
+
+        match self.cap {
+            Some(cap) => format!(
+                "Adaptive quality: capped at level {} (budget {:.1} ms)",
+                cap, self.settings.frame_budget_ms
+            ),
+            None => format!(
+                "Adaptive quality: not capped (budget {:.1} ms)",
+                self.settings.frame_budget_ms
+            ),
+        }
+    }
+
+    fn percentile_95(&self) -> f32 {
+        let mut sorted = [0.0; WINDOW];
+        let sorted = &mut sorted[..self.times.len()];
+        for (slot, ms) in sorted.iter_mut().zip(&self.times) {
+            *slot = *ms;
+        }
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        sorted[(sorted.len() * 95 / 100).min(sorted.len() - 1)]
+    }
+
+    fn changed(&mut self, time: f64) {
+        self.last_change = Some(time);
+        self.reset_measurements();
+    }
+
+    /// Forgets the frames so far, since they were drawn at another level or with other settings.
+    fn reset_measurements(&mut self) {
+        self.times.clear();
+        self.over_since = None;
+        self.headroom_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FPS: f64 = 60.0;
+
+    /// Feeds `secs` worth of frames of `ms` each, starting at `*time`, wanting `wanted`, and
+    /// returns the levels drawn at.
+    fn run(quality: &mut AdaptiveQuality, time: &mut f64, secs: f64, ms: f32, wanted: u8) -> u8 {
+        let frames = (secs * FPS) as usize;
+        let mut level = quality.cap(wanted);
+        for _ in 0..frames {
+            *time += 1.0 / FPS;
+            level = quality.cap(wanted);
+            quality.frame(*time, ms, level);
+        }
+
+        level
+    }
+
+    fn quality() -> AdaptiveQuality {
+        AdaptiveQuality::new(QualitySettings::default())
+    }
+
+    #[test]
+    fn fast_frames_are_not_capped() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        assert_eq!(run(&mut quality, &mut time, 30.0, 10.0, 5), 5);
+    }
+
+    #[test]
+    fn sustained_slow_frames_drop_one_level() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        // A second of slow frames is not enough.
+        assert_eq!(run(&mut quality, &mut time, 1.0, 30.0, 5), 5);
+        assert_eq!(run(&mut quality, &mut time, 2.5, 30.0, 5), 4);
+    }
+
+    #[test]
+    fn brief_spikes_do_not_drop() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        for _ in 0..10 {
+            assert_eq!(run(&mut quality, &mut time, 0.5, 30.0, 5), 5);
+            assert_eq!(run(&mut quality, &mut time, 1.5, 10.0, 5), 5);
+        }
+    }
+
+    #[test]
+    fn slow_frames_keep_dropping_after_dwelling() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        assert_eq!(run(&mut quality, &mut time, 3.5, 30.0, 5), 4);
+        // Still slow at level 4: the next drop waits for the minimum dwell and another sustained
+        // stretch.
+        assert_eq!(run(&mut quality, &mut time, 2.0, 30.0, 5), 4);
+        assert_eq!(run(&mut quality, &mut time, 2.0, 30.0, 5), 3);
+    }
+
+    #[test]
+    fn never_drops_below_the_least_detailed_level() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        assert_eq!(run(&mut quality, &mut time, 60.0, 100.0, 5), 1);
+
+        let mut quality = AdaptiveQuality::new(QualitySettings {
+            min_level: 3,
+            ..QualitySettings::default()
+        });
+        assert_eq!(run(&mut quality, &mut time, 60.0, 100.0, 5), 3);
+    }
+
+    #[test]
+    fn headroom_raises_the_cap_again() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        assert_eq!(run(&mut quality, &mut time, 3.5, 30.0, 5), 4);
+        assert_eq!(run(&mut quality, &mut time, 3.0, 10.0, 5), 4);
+        assert_eq!(run(&mut quality, &mut time, 4.0, 10.0, 5), 5);
+        assert!(quality.status().contains("not capped"));
+    }
+
+    #[test]
+    fn frames_just_under_budget_do_not_raise_the_cap() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        assert_eq!(run(&mut quality, &mut time, 3.5, 30.0, 5), 4);
+        // Under the 20ms budget, but without enough headroom to go back up.
+        assert_eq!(run(&mut quality, &mut time, 60.0, 18.0, 5), 4);
+    }
+
+    #[test]
+    fn disabling_lifts_the_cap() {
+        let mut quality = quality();
+        let mut time = 0.0;
+
+        assert_eq!(run(&mut quality, &mut time, 3.5, 30.0, 5), 4);
+        quality.set_settings(QualitySettings {
+            adaptive: false,
+            ..QualitySettings::default()
+        });
+
+        assert_eq!(run(&mut quality, &mut time, 10.0, 30.0, 5), 5);
+        assert_eq!(quality.status(), "Adaptive quality: off");
+    }
+}
@@ -0,0 +1,89 @@
+use gfx;
+use piston::window::Size;
+use piston_window::draw_state::Blend;
+use piston_window::{Context, Graphics, Texture};
+
+use render_scale::ScaledTarget;
+use settings::StereoSettings;
+use Result;
+
+/// What each eye's picture is multiplied by, so that the left eye is seen through the red filter
+/// of the glasses and the right eye through the cyan one.
+const TINTS: [[f32; 4]; 2] = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 1.0, 1.0]];
+
+/// How far each eye is moved along the camera's right, in world units, from `camera_height` with
+/// `settings`. The left eye comes first.
+pub fn eye_offsets(settings: StereoSettings, camera_height: f32) -> [f32; 2] {
+    let half = settings.eye_separation * camera_height / 2.0;
+
+    [-half, half]
+}
+
+/// The offscreen targets each eye's picture is drawn to, kept the size that the render scale and
+/// the window's size call for.
+pub struct Anaglyph<R: gfx::Resources> {
+    eyes: Option<([u16; 2], [ScaledTarget<R>; 2])>,
+}
+
+impl<R: gfx::Resources> Anaglyph<R> {
+    pub fn new() -> Anaglyph<R> {
+        Anaglyph { eyes: None }
+    }
+
+    /// Makes `eyes` draw at `scale` times `draw_size`, in pixels, or frees them if `enabled` is
+    /// off.
+    pub fn update<F>(
+        &mut self,
+        factory: &mut F,
+        draw_size: Size,
+        scale: f32,
+        enabled: bool,
+    ) -> Result<()>
+    where
+        F: gfx::Factory<R>,
+    {
+        if !enabled {
+            self.eyes = None;
+            return Ok(());
+        }
+
+        let scaled = |pixels: u32| {
+            (pixels as f32 * scale.min(1.0)).round().max(1.0).min(65535.0) as u16
+        };
+        let size = [scaled(draw_size.width), scaled(draw_size.height)];
+        if self.eyes.as_ref().map(|&(old, _)| old) != Some(size) {
+            self.eyes = None;
+            let left = ScaledTarget::new(factory, size)?;
+            let right = ScaledTarget::new(factory, size)?;
+            self.eyes = Some((size, [left, right]));
+        }
+
+        Ok(())
+    }
+
+    /// The targets to draw the left and right eyes' pictures to, or `None` while the mode is off.
+    pub fn eyes(&self) -> Option<&[ScaledTarget<R>; 2]> {
+        self.eyes.as_ref().map(|(_, eyes)| eyes)
+    }
+}
+
+impl<R: gfx::Resources> Default for Anaglyph<R> {
+    fn default() -> Anaglyph<R> {
+        Anaglyph::new()
+    }
+}
+
+/// Draws the eyes' pictures over `area`, the part of the view of `context` the map is drawn in,
+/// the left one in red and the right one added over it in cyan.
+pub fn draw<R, G>(eyes: &[ScaledTarget<R>; 2], area: [f64; 4], context: Context, graphics: &mut G)
+where
+    R: gfx::Resources,
+    G: Graphics<Texture = Texture<R>>,
+{
+    eyes[0].draw_tinted(area, TINTS[0], context, graphics);
+    let added = Context {
+        draw_state: context.draw_state.blend(Blend::Add),
+        ..context
+    };
+    eyes[1].draw_tinted(area, TINTS[1], added, graphics);
+}
@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// How far one step moves the frozen clock on, in seconds: one frame at 60 frames per second.
+pub const STEP_SECS: f64 = 1.0 / 60.0;
+
+/// The clock that everything animated on the map runs by: the animated map modes, day and night
+/// mode's clock, routes playing and the tour. It follows real time, but can be frozen to look at
+/// one instant, and then stepped on a frame at a time. The camera does not run by it, so that it
+/// can still be moved while the clock is frozen.
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationClock {
+    /// The real time the clock started at.
+    started_at: SystemTime,
+    started: Instant,
+    /// How far the clock is behind real time, from the time spent frozen.
+    behind: Duration,
+    /// How far the clock had got to when it was frozen, if it is, plus any steps since.
+    frozen: Option<Duration>,
+}
+
+impl AnimationClock {
+    pub fn new() -> AnimationClock {
+        AnimationClock {
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            behind: Duration::from_secs(0),
+            frozen: None,
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.is_some()
+    }
+
+    /// How far the clock has got since it started.
+    pub fn elapsed(&self) -> Duration {
+        self.frozen
+            .unwrap_or_else(|| self.started.elapsed().saturating_sub(self.behind))
+    }
+
+    /// The time the clock shows, which is the real time it started at plus how far it has got.
+    pub fn now(&self) -> SystemTime {
+        self.started_at + self.elapsed()
+    }
+
+    /// Freezes the clock if it is running, and starts it again where it was if it is frozen.
+    pub fn toggle(&mut self) {
+        match self.frozen.take() {
+            // If steps took the clock past real time, it goes back to real time.
+            Some(frozen) => self.behind = self.started.elapsed().saturating_sub(frozen),
+            None => self.frozen = Some(self.elapsed()),
+        }
+    }
+
+    /// Moves the frozen clock on by `STEP_SECS`. Returns whether it did, which it only does
+    /// while the clock is frozen.
+    pub fn step(&mut self) -> bool {
+        match self.frozen {
+            Some(ref mut frozen) => {
+                *frozen += Duration::from_secs_f64(STEP_SECS);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Makes `by` more real time seem to have passed, for tests that cannot wait for it.
+    #[cfg(test)]
+    pub fn pass(&mut self, by: Duration) {
+        self.started -= by;
+        self.started_at -= by;
+    }
+}
+
+impl Default for AnimationClock {
+    fn default() -> AnimationClock {
+        AnimationClock::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_frozen_clock_only_moves_when_stepped() {
+        let mut clock = AnimationClock::new();
+        assert!(!clock.step());
+
+        clock.toggle();
+        assert!(clock.is_frozen());
+        let frozen = clock.elapsed();
+        clock.pass(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), frozen);
+
+        assert!(clock.step() && clock.step());
+        let stepped = clock.elapsed() - frozen;
+        assert!((stepped.as_secs_f64() - 2.0 * STEP_SECS).abs() < 1e-6, "{:?}", stepped);
+        assert_eq!(clock.now(), clock.started_at + clock.elapsed());
+    }
+
+    #[test]
+    fn a_clock_started_again_carries_on_from_where_it_was() {
+        let mut clock = AnimationClock::new();
+        clock.toggle();
+        let frozen = clock.elapsed();
+        clock.pass(Duration::from_secs(10));
+
+        clock.toggle();
+        assert!(!clock.is_frozen());
+        // The time spent frozen is skipped, so the clock is behind real time.
+        let elapsed = clock.elapsed();
+        assert!(elapsed >= frozen && elapsed < frozen + Duration::from_secs(5));
+        assert!(elapsed < clock.started.elapsed());
+
+        // Steps past real time are taken back once the clock runs again.
+        clock.toggle();
+        for _ in 0..600 {
+            clock.step();
+        }
+        clock.toggle();
+        let real = clock.started.elapsed();
+        assert!(clock.elapsed() <= real, "{:?} past {:?}", clock.elapsed(), real);
+        assert!(clock.elapsed() > real - Duration::from_secs(1), "{:?}", clock.elapsed());
+    }
+}
@@ -0,0 +1,187 @@
+//! Approximate solar position, accurate to within about a minute of sunrise and sunset times over
+//! the next few decades. See the "Low precision formulas for the Sun" in the Astronomical Almanac.
+
+use std::f64::consts::PI;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// The Julian date of the Unix epoch.
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2_440_587.5;
+
+/// The Julian date of J2000.0, from which the formulas below count days.
+const J2000_JULIAN_DATE: f64 = 2_451_545.0;
+
+/// Where the sun is at `unix_time` seconds since the Unix epoch.
+struct SunPosition {
+    /// The sun's mean longitude, in degrees.
+    mean_longitude: f64,
+    /// In radians.
+    declination: f64,
+    /// In radians.
+    right_ascension: f64,
+}
+
+impl SunPosition {
+    fn at(unix_time: f64) -> SunPosition {
+        let days = unix_time / SECONDS_PER_DAY + UNIX_EPOCH_JULIAN_DATE - J2000_JULIAN_DATE;
+
+        let mean_longitude = 280.460 + 0.985_647_4 * days;
+        let mean_anomaly = (357.528 + 0.985_600_3 * days).to_radians();
+        let ecliptic_longitude = (mean_longitude + 1.915 * mean_anomaly.sin()
+            + 0.020 * (2.0 * mean_anomaly).sin())
+            .to_radians();
+        let obliquity = (23.439 - 0.000_000_4 * days).to_radians();
+
+        SunPosition {
+            mean_longitude,
+            declination: (obliquity.sin() * ecliptic_longitude.sin()).asin(),
+            right_ascension: (obliquity.cos() * ecliptic_longitude.sin())
+                .atan2(ecliptic_longitude.cos()),
+        }
+    }
+}
+
+/// The sun's declination at `unix_time`, in degrees north of the celestial equator.
+pub fn solar_declination(unix_time: f64) -> f64 {
+    SunPosition::at(unix_time).declination.to_degrees()
+}
+
+/// How far apparent solar time is ahead of mean solar time at `unix_time`, in minutes.
+pub fn equation_of_time(unix_time: f64) -> f64 {
+    let sun = SunPosition::at(unix_time);
+    let degrees = sun.mean_longitude - sun.right_ascension.to_degrees();
+
+    // The sun's hour angle moves a degree every four minutes.
+    ((degrees + 180.0).rem_euclid(360.0) - 180.0) * 4.0
+}
+
+/// The point where the sun is directly overhead at `unix_time`, as a latitude and longitude in
+/// degrees.
+pub fn subsolar_point(unix_time: f64) -> (f64, f64) {
+    // It is apparent solar noon under the sun, which is mean solar noon corrected by the
+    // equation of time.
+    let utc_hours = unix_time.rem_euclid(SECONDS_PER_DAY) / 3600.0;
+    let longitude = -15.0 * (utc_hours - 12.0 + equation_of_time(unix_time) / 60.0);
+
+    (
+        solar_declination(unix_time),
+        (longitude + 180.0).rem_euclid(360.0) - 180.0,
+    )
+}
+
+/// The sun's elevation above the horizon, in degrees, seen from `latitude` and `longitude` (both
+/// in degrees) at `unix_time` seconds since the Unix epoch. Refraction is not accounted for.
+pub fn solar_elevation(unix_time: f64, latitude: f64, longitude: f64) -> f64 {
+    elevation_below(subsolar_point(unix_time), latitude, longitude)
+}
+
+/// The elevation, in degrees, of a sun directly over `subsolar`, seen from `latitude` and
+/// `longitude`. Working out `subsolar_point` once makes this cheap for many places at once.
+pub fn elevation_below(subsolar: (f64, f64), latitude: f64, longitude: f64) -> f64 {
+    let (declination, subsolar_longitude) = (subsolar.0.to_radians(), subsolar.1);
+    let hour_angle = (longitude - subsolar_longitude).to_radians();
+
+    let latitude = latitude.to_radians();
+    let sin_elevation = latitude.sin() * declination.sin()
+        + latitude.cos() * declination.cos() * hour_angle.cos();
+
+    sin_elevation.asin() * 180.0 / PI
+}
+
+/// The mean solar time at `longitude` (in degrees) at `unix_time`, as hours and minutes. This is
+/// UTC shifted by an hour for every 15 degrees of longitude.
+pub fn local_solar_time(unix_time: f64, longitude: f64) -> (u32, u32) {
+    let seconds = (unix_time + longitude / 15.0 * 3600.0).rem_euclid(SECONDS_PER_DAY);
+    let minutes = (seconds / 60.0) as u32;
+
+    (minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sun's center is this far below the horizon at sunrise and sunset, by the usual
+    /// convention that allows for refraction and the sun's radius.
+    const SUNRISE_ELEVATION: f64 = -0.833;
+
+    /// Published times are rounded to the minute, during which the sun moves up to about a
+    /// quarter degree.
+    const TOLERANCE: f64 = 0.5;
+
+    fn assert_near_horizon(unix_time: f64, latitude: f64, longitude: f64) {
+        let elevation = solar_elevation(unix_time, latitude, longitude);
+
+        assert!(
+            (elevation - SUNRISE_ELEVATION).abs() < TOLERANCE,
+            "expected the sun near the horizon, but it was at {} degrees",
+            elevation
+        );
+    }
+
+    #[test]
+    fn london_summer_solstice() {
+        // Sunrise at 04:43 BST and sunset at 21:21 BST on 2020-06-21.
+        assert_near_horizon(1_592_710_980.0, 51.5074, -0.1278);
+        assert_near_horizon(1_592_770_860.0, 51.5074, -0.1278);
+    }
+
+    #[test]
+    fn new_york_winter_solstice() {
+        // Sunrise at 07:16 EST on 2021-12-21.
+        assert_near_horizon(1_640_088_960.0, 40.7128, -74.0060);
+    }
+
+    #[test]
+    fn singapore_equinox() {
+        // Sunrise at 07:09 SGT on 2019-03-20.
+        assert_near_horizon(1_553_036_940.0, 1.3521, 103.8198);
+    }
+
+    #[test]
+    fn sun_is_up_at_noon_and_down_at_midnight() {
+        // 2020-06-21 12:00 UTC, on the prime meridian.
+        let noon = 1_592_740_800.0;
+
+        assert!(solar_elevation(noon, 51.5, 0.0) > 50.0);
+        assert!(solar_elevation(noon + SECONDS_PER_DAY / 2.0, 51.5, 0.0) < -10.0);
+    }
+
+    #[test]
+    fn declination_at_the_solstices_and_equinox() {
+        // The June solstice at 21:44 UTC on 2020-06-20, the December solstice at 15:59 UTC on
+        // 2021-12-21, and the March equinox at 21:58 UTC on 2019-03-20. The obliquity was
+        // 23.44 degrees.
+        assert!((solar_declination(1_592_689_440.0) - 23.44).abs() < 0.01);
+        assert!((solar_declination(1_640_102_340.0) + 23.44).abs() < 0.01);
+        assert!(solar_declination(1_553_119_080.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn equation_of_time_at_its_extremes() {
+        // The almanac gives +16m 30s at noon UTC on 2021-11-03, and -14m 12s on 2021-02-11.
+        assert!((equation_of_time(1_635_940_800.0) - 16.5).abs() < 0.2);
+        assert!((equation_of_time(1_613_044_800.0) + 14.2).abs() < 0.2);
+    }
+
+    #[test]
+    fn subsolar_point_follows_the_clock() {
+        // At the 2020 June solstice, 9h 44m after noon UTC, with the equation of time at -1m 40s.
+        let (latitude, longitude) = subsolar_point(1_592_689_440.0);
+
+        assert!((latitude - 23.44).abs() < 0.01);
+        assert!((longitude - -15.0 * (9.0 + 44.0 / 60.0 - 1.67 / 60.0)).abs() < 0.1);
+        assert!(solar_elevation(1_592_689_440.0, latitude, longitude) > 89.9);
+    }
+
+    #[test]
+    fn solar_time_follows_longitude() {
+        // 2020-06-21 12:00 UTC.
+        let noon = 1_592_740_800.0;
+
+        assert_eq!(local_solar_time(noon, 0.0), (12, 0));
+        assert_eq!(local_solar_time(noon, 90.0), (18, 0));
+        assert_eq!(local_solar_time(noon, -180.0), (0, 0));
+        assert_eq!(local_solar_time(noon, 7.5), (12, 30));
+    }
+}
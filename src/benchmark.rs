@@ -0,0 +1,486 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use serde_json;
+use toml;
+
+use frame_times::{Phase, PhaseTimes};
+use {Result, ResultExt};
+
+/// How many frames are drawn per simulated second. The event loop's benchmark mode advances its
+/// clock by exactly this much each frame, however long the frame really took, so every run draws
+/// the same frames.
+pub const FRAMES_PER_SECOND: u64 = 60;
+
+/// Where the camera is at a point in a segment.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Keyframe {
+    /// Seconds since the start of the segment.
+    pub time: f64,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub height: f32,
+    /// Degrees clockwise from north.
+    #[serde(default)]
+    pub heading: f32,
+}
+
+/// A named part of the path, reported on separately.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Segment {
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// The camera's path through a benchmark: segments one after the other, each moving between its
+/// keyframes.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CameraPath {
+    pub segments: Vec<Segment>,
+}
+
+impl CameraPath {
+    /// The path used unless another is given: orbiting the whole globe, diving to street level
+    /// over Europe, then panning quickly across Asia.
+    pub fn built_in() -> CameraPath {
+        let keyframe = |time, latitude, longitude, height| Keyframe {
+            time,
+            latitude,
+            longitude,
+            height,
+            heading: 0.0,
+        };
+
+        CameraPath {
+            segments: vec![
+                Segment {
+                    name: "orbit".to_string(),
+                    keyframes: vec![
+                        keyframe(0.0, 20.0, -180.0, 1.0),
+                        keyframe(20.0, 20.0, 180.0, 1.0),
+                    ],
+                },
+                Segment {
+                    name: "dive".to_string(),
+                    keyframes: vec![
+                        keyframe(0.0, 48.0, 10.0, 1.0),
+                        keyframe(15.0, 48.0, 10.0, 0.05),
+                    ],
+                },
+                Segment {
+                    name: "pan".to_string(),
+                    keyframes: vec![
+                        keyframe(0.0, 35.0, 60.0, 0.08),
+                        keyframe(8.0, 35.0, 140.0, 0.08),
+                    ],
+                },
+            ],
+        }
+    }
+
+    /// Reads a path from a TOML file, with a `[[segments]]` table for each segment and a
+    /// `[[segments.keyframes]]` table for each of its keyframes.
+    pub fn load(path: &Path) -> Result<CameraPath> {
+        let contents = fs::read_to_string(path)
+            .chain_err(|| format!("Could not read benchmark path {}", path.display()))?;
+        let camera_path: CameraPath = toml::from_str(&contents)
+            .chain_err(|| format!("Could not parse benchmark path {}", path.display()))?;
+
+        camera_path
+            .validate()
+            .chain_err(|| format!("Invalid benchmark path {}", path.display()))?;
+        Ok(camera_path)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.segments.is_empty() {
+            bail!("there are no segments");
+        }
+
+        for segment in &self.segments {
+            let keyframes = &segment.keyframes;
+            match keyframes.first() {
+                Some(first) if first.time == 0.0 => {}
+                Some(_) => bail!("segment `{}` does not start at time 0", segment.name),
+                None => bail!("segment `{}` has no keyframes", segment.name),
+            }
+
+            if keyframes.windows(2).any(|pair| pair[1].time <= pair[0].time) {
+                bail!("the keyframes of segment `{}` are not in time order", segment.name);
+            }
+
+            let above_ground = |keyframe: &Keyframe| {
+                keyframe.height.is_finite() && keyframe.height > 0.0
+            };
+            if !keyframes.iter().all(above_ground) {
+                bail!("segment `{}` has a keyframe that is not above the ground", segment.name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Which segment is being run `time` seconds into the path, and where the camera is then.
+    /// `None` once the path is over.
+    fn at(&self, mut time: f64) -> Option<(usize, Keyframe)> {
+        for (index, segment) in self.segments.iter().enumerate() {
+            let keyframes = &segment.keyframes;
+            let duration = keyframes[keyframes.len() - 1].time;
+
+            if time < duration {
+                let next = keyframes.iter().position(|keyframe| keyframe.time > time).unwrap();
+                return Some((index, interpolate(&keyframes[next - 1], &keyframes[next], time)));
+            }
+
+            time -= duration;
+        }
+
+        None
+    }
+}
+
+/// Where the camera is `time` seconds into a segment, between `from` and `to`. The height changes
+/// geometrically, so that zooming looks steady.
+fn interpolate(from: &Keyframe, to: &Keyframe, time: f64) -> Keyframe {
+    let t = ((time - from.time) / (to.time - from.time)) as f32;
+    let lerp = |from: f32, to: f32| from + t * (to - from);
+
+    Keyframe {
+        time,
+        latitude: lerp(from.latitude, to.latitude),
+        longitude: lerp(from.longitude, to.longitude),
+        height: lerp(from.height.ln(), to.height.ln()).exp(),
+        heading: lerp(from.heading, to.heading),
+    }
+}
+
+/// How the results are printed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+/// One drawn frame.
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    segment: usize,
+    ms: f64,
+    level: u8,
+    phases: PhaseTimes,
+}
+
+/// Moves the camera along a path one frame at a time, timing each frame.
+#[derive(Debug)]
+pub struct Benchmark {
+    path: CameraPath,
+    frame: u64,
+    /// The segment and level of detail of the frame being drawn, which is timed by the next call
+    /// to `next_view`.
+    current: Option<(usize, u8)>,
+    last_frame: Option<Instant>,
+    samples: Vec<Sample>,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    name: String,
+    frames: usize,
+    average_ms: f64,
+    median_ms: f64,
+    percentile_95_ms: f64,
+    percentile_99_ms: f64,
+    /// How many frames each level of detail was drawn at.
+    levels: BTreeMap<u8, usize>,
+    /// How long frames spent in each phase, in the order of `Phase::ALL`.
+    phases: Vec<PhaseStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct PhaseStats {
+    name: &'static str,
+    average_ms: f64,
+    percentile_95_ms: f64,
+    percentile_99_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Report<'a> {
+    context: &'a str,
+    simulated_seconds: f64,
+    total: Stats,
+    segments: Vec<Stats>,
+}
+
+impl Benchmark {
+    pub fn new(path: CameraPath) -> Benchmark {
+        Benchmark {
+            path,
+            frame: 0,
+            current: None,
+            last_frame: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Times the frame before this one, which spent `phases` in each phase, and says which
+    /// segment the next frame is in and where the camera should be for it. `None` once the path
+    /// is over.
+    pub fn next_view(&mut self, phases: PhaseTimes) -> Option<(usize, Keyframe)> {
+        let now = Instant::now();
+        if let (Some((segment, level)), Some(last_frame)) = (self.current, self.last_frame) {
+            let elapsed = now.duration_since(last_frame);
+            let ms = elapsed.as_secs() as f64 * 1000.0 + f64::from(elapsed.subsec_nanos()) * 1e-6;
+
+            self.samples.push(Sample {
+                segment,
+                ms,
+                level,
+                phases,
+            });
+        }
+        self.last_frame = Some(now);
+
+        let time = self.frame as f64 / FRAMES_PER_SECOND as f64;
+        let view = self.path.at(time);
+        self.frame += 1;
+        self.current = view.map(|(segment, _)| (segment, 0));
+        view
+    }
+
+    /// Records the level of detail the frame being drawn calls for.
+    pub fn set_level(&mut self, level: u8) {
+        if let Some((_, ref mut current)) = self.current {
+            *current = level;
+        }
+    }
+
+    /// The results so far, as JSON or as CSV with a row for the whole run and one for each
+    /// segment. `context` describes the OpenGL context, so that runs can be told apart.
+    pub fn report(&self, format: Format, context: &str) -> Result<String> {
+        let total = stats("total", self.samples.iter());
+        let segments = self.path
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                let samples = self.samples.iter().filter(|sample| sample.segment == index);
+                stats(&segment.name, samples)
+            })
+            .collect();
+
+        let report = Report {
+            context,
+            simulated_seconds: self.frame.saturating_sub(1) as f64 / FRAMES_PER_SECOND as f64,
+            total,
+            segments,
+        };
+
+        match format {
+            Format::Json => serde_json::to_string_pretty(&report).map_err(|e| e.to_string().into()),
+            Format::Csv => Ok(to_csv(&report)),
+        }
+    }
+}
+
+fn stats<'a, I>(name: &str, samples: I) -> Stats
+where
+    I: Iterator<Item = &'a Sample>,
+{
+    let mut times = Vec::new();
+    let mut levels = BTreeMap::new();
+    let mut phase_times = vec![Vec::new(); Phase::ALL.len()];
+    for sample in samples {
+        times.push(sample.ms);
+        *levels.entry(sample.level).or_insert(0) += 1;
+        for (times, &ms) in phase_times.iter_mut().zip(&sample.phases) {
+            times.push(f64::from(ms));
+        }
+    }
+
+    let phases = Phase::ALL
+        .iter()
+        .zip(&mut phase_times)
+        .map(|(phase, times)| {
+            sort(times);
+            PhaseStats {
+                name: phase.name(),
+                average_ms: average(times),
+                percentile_95_ms: percentile(times, 95.0),
+                percentile_99_ms: percentile(times, 99.0),
+            }
+        })
+        .collect();
+
+    sort(&mut times);
+    Stats {
+        name: name.to_string(),
+        frames: times.len(),
+        average_ms: average(&times),
+        median_ms: percentile(&times, 50.0),
+        percentile_95_ms: percentile(&times, 95.0),
+        percentile_99_ms: percentile(&times, 99.0),
+        levels,
+        phases,
+    }
+}
+
+fn sort(times: &mut [f64]) {
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+}
+
+/// The average of `times`, or 0 if it is empty.
+fn average(times: &[f64]) -> f64 {
+    if times.is_empty() {
+        0.0
+    } else {
+        times.iter().sum::<f64>() / times.len() as f64
+    }
+}
+
+/// The nearest-rank `p`th percentile of `sorted`, or 0 if it is empty.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+fn to_csv(report: &Report) -> String {
+    let max_level = report.total.levels.keys().cloned().max().unwrap_or(0);
+
+    let header = "segment,frames,average_ms,median_ms,percentile_95_ms,percentile_99_ms";
+    let mut csv = header.to_string();
+    for level in 1..=max_level {
+        write!(csv, ",level_{}_frames", level).unwrap();
+    }
+    for phase in &Phase::ALL {
+        let name = phase.name();
+        write!(
+            csv,
+            ",{0}_average_ms,{0}_percentile_95_ms,{0}_percentile_99_ms",
+            name
+        ).unwrap();
+    }
+    csv.push('\n');
+
+    for stats in Some(&report.total).into_iter().chain(&report.segments) {
+        write!(
+            csv,
+            "{},{},{:.3},{:.3},{:.3},{:.3}",
+            stats.name,
+            stats.frames,
+            stats.average_ms,
+            stats.median_ms,
+            stats.percentile_95_ms,
+            stats.percentile_99_ms
+        ).unwrap();
+
+        for level in 1..=max_level {
+            write!(csv, ",{}", stats.levels.get(&level).cloned().unwrap_or(0)).unwrap();
+        }
+        for phase in &stats.phases {
+            write!(
+                csv,
+                ",{:.3},{:.3},{:.3}",
+                phase.average_ms, phase.percentile_95_ms, phase.percentile_99_ms
+            ).unwrap();
+        }
+        csv.push('\n');
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Result<CameraPath> {
+        let camera_path: CameraPath = toml::from_str(toml).map_err(|e| e.to_string())?;
+        camera_path.validate()?;
+
+        Ok(camera_path)
+    }
+
+    #[test]
+    fn segments_run_one_after_the_other() {
+        let path = CameraPath::built_in();
+
+        let (segment, view) = path.at(10.0).unwrap();
+        assert_eq!((segment, view.longitude), (0, 0.0));
+
+        // The dive starts once the 20 second orbit is over, and zooms in geometrically.
+        let (segment, view) = path.at(20.0).unwrap();
+        assert_eq!((segment, view.height), (1, 1.0));
+        let (_, view) = path.at(27.5).unwrap();
+        assert!((view.height - 0.05f32.sqrt()).abs() < 1e-5, "{}", view.height);
+
+        assert_eq!(path.at(42.99).map(|(segment, _)| segment), Some(2));
+        assert!(path.at(43.0).is_none());
+    }
+
+    #[test]
+    fn invalid_paths_say_what_is_wrong() {
+        let error = |toml: &str| parse(toml).unwrap_err().to_string();
+
+        assert!(error("segments = []").contains("no segments"));
+        assert!(error("[[segments]]\nname = \"a\"\nkeyframes = []\n").contains("no keyframes"));
+
+        let keyframe = |time, height| {
+            format!(
+                "[[segments.keyframes]]\ntime = {}\nlatitude = 0.0\nlongitude = 0.0\n\
+                 height = {}\n",
+                time, height
+            )
+        };
+        let segment =
+            |keyframes: &[String]| format!("[[segments]]\nname = \"a\"\n{}", keyframes.concat());
+        assert!(error(&segment(&[keyframe(1.0, 1.0)])).contains("does not start at time 0"));
+        assert!(error(&segment(&[keyframe(0.0, 1.0), keyframe(0.0, 1.0)])).contains("time order"));
+        assert!(error(&segment(&[keyframe(0.0, 0.0)])).contains("not above the ground"));
+
+        let path = parse(&segment(&[keyframe(0.0, 1.0), keyframe(2.0, 0.5)])).unwrap();
+        assert_eq!(path.at(1.0).map(|(_, view)| view.heading), Some(0.0));
+    }
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        let times: Vec<f64> = (1..=100).map(f64::from).collect();
+
+        assert_eq!(percentile(&times, 50.0), 50.0);
+        assert_eq!(percentile(&times, 99.0), 99.0);
+        assert_eq!(percentile(&times[..1], 99.0), 1.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+        assert_eq!(average(&times), 50.5);
+        assert_eq!(average(&[]), 0.0);
+    }
+
+    #[test]
+    fn reports_have_a_row_for_the_run_and_each_segment() {
+        let mut benchmark = Benchmark::new(CameraPath::built_in());
+        for frame in 0..4 {
+            benchmark.next_view([1.0; 5]);
+            benchmark.set_level(frame % 2 + 1);
+        }
+
+        let csv = benchmark.report(Format::Csv, "test").unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 5, "{}", csv);
+        assert!(rows[0].contains(",level_2_frames,events_average_ms,"), "{}", rows[0]);
+        // The first frame is not timed, since nothing came before it.
+        assert!(rows[1].starts_with("total,3,"), "{}", rows[1]);
+        assert!(rows[1].contains(",1.000,1.000,1.000"), "{}", rows[1]);
+        assert!(rows[3].starts_with("dive,0,"), "{}", rows[3]);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&benchmark.report(Format::Json, "test").unwrap()).unwrap();
+        assert_eq!(json["total"]["levels"]["1"], 2);
+        assert_eq!(json["segments"][0]["name"], "orbit");
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+
+use piston::input::keyboard::Key;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use serde_json;
+
+use {MapMode, Result};
+
+/// Everything the user can do, whether from the keyboard, by clicking a HUD widget, or with a
+/// chord of modifiers and a key. `State::handle_action` applies each one. In the settings file,
+/// actions are written in snake case, such as `toggle_graticule`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    ShowTerrain,
+    ShowAllCountries,
+    ShowOecd,
+    ShowIncome,
+    ShowExceptional,
+    /// Color countries with the script in `modes.script`.
+    ShowScripted,
+    /// Darken the countries where it is night.
+    ShowDaylight,
+    /// Color countries by where their population ranks.
+    ShowPercentile,
+    ToggleLabels,
+    /// Show a small overview of the whole map, marking what the main view shows.
+    ToggleOverview,
+    /// Draw the map in red and cyan, for 3D glasses.
+    ToggleStereo,
+    /// Split the window in two, to compare the current map mode with another.
+    ToggleSplit,
+    /// Switch clicks between selecting countries and picking the endpoints of routes.
+    ToggleRouteTool,
+    /// Delete the route under the cursor.
+    DeleteRoute,
+    /// Move a marker along the selected route, or pause or resume it.
+    PlayRoute,
+    /// Switch the camera following a playing route on or off.
+    ToggleFollow,
+    SnapNorth,
+    ToggleGraticule,
+    /// Draw stars behind the map, or stop drawing them.
+    ToggleStars,
+    ToggleCrosshair,
+    ToggleHud,
+    TogglePerformance,
+    ToggleLegend,
+    ToggleHelp,
+    ToggleSettings,
+    /// Open the list of named sessions, to save or load one.
+    ToggleSessions,
+    /// Switch to the next of the asset sets in the settings.
+    NextAssetSet,
+    ToggleFullscreen,
+    Screenshot,
+    /// Save the frames kept by the replay buffer as an animated GIF.
+    SaveReplay,
+    /// Write the selected country, or every country the map mode shows, to CSV and JSON.
+    Export,
+    /// Leave fullscreen or quit, depending on settings.
+    Escape,
+    /// Copy the selected country, with everything known about it, as JSON.
+    CopySelected,
+    /// Start or stop recording frames to disk.
+    ToggleRecording,
+    /// Save a screenshot of the map at a multiple of the window's resolution.
+    HiResScreenshot,
+    /// Save the legend and scale bar as an image.
+    ExportLegend,
+    /// Switch to the next of the map modes shown, in the order of `modes.order`.
+    NextMode,
+    /// Switch to the previous of the map modes shown.
+    PreviousMode,
+    /// Show a map mode in the right half of a split-screen.
+    ShowInRightHalf(MapMode),
+    /// Show or hide a category of the legend, by its index.
+    ToggleCategory(usize),
+    /// Show or hide a category of the right half's legend, in split-screen.
+    ToggleRightCategory(usize),
+    /// Fly to a latitude and longitude, in degrees, keeping the height and heading.
+    FlyTo { latitude: f64, longitude: f64 },
+    /// Select the country at a cursor position, or pick a route endpoint there with the route
+    /// tool.
+    ClickMap([f64; 2]),
+    /// Select the country before the selected one, in alphabetical order.
+    PreviousCountry,
+    /// Select the country after the selected one, in alphabetical order.
+    NextCountry,
+    /// Fly to where the selected country fills the view, facing north.
+    FrameSelected,
+    /// Start or stop the tour of every capital.
+    ToggleTour,
+    /// Skip back to the previous capital on the tour.
+    PreviousCapital,
+    /// Skip forward to the next capital on the tour.
+    NextCapital,
+    /// Switch the high contrast profile on or off.
+    ToggleHighContrast,
+    /// Freeze or unfreeze everything animated on the map, leaving the camera free to move.
+    ToggleFreeze,
+    /// Move the frozen animations on by one frame.
+    StepFrozen,
+    /// Open the selected country's web page in the browser.
+    OpenSelectedLink,
+    /// Freeze how the map mode colors every country, to compare other modes against.
+    TakeSnapshot,
+    /// Forget the snapshot of the map mode's colors.
+    ClearSnapshot,
+    /// Color countries by whether they changed since the snapshot, instead of as the map mode
+    /// has them.
+    ToggleSnapshotDiff,
+    /// Select the map mode's previous tunable parameter.
+    PreviousParameter,
+    /// Select the map mode's next tunable parameter.
+    NextParameter,
+    /// Raise the map mode's selected parameter by a step.
+    IncreaseParameter,
+    /// Lower the map mode's selected parameter by a step.
+    DecreaseParameter,
+    /// Start or stop editing the class breaks of the map mode's legend with the arrow keys.
+    EditBreaks,
+    /// Return the map mode's class breaks to the ones it works out for itself.
+    ResetBreaks,
+    /// Letterbox the map to the aspect ratio given with `--lock-aspect`, or stop.
+    ToggleAspectLock,
+    /// Sweep the map mode's selected parameter back and forth across its range, or stop
+    /// animating a parameter.
+    AnimateParameter,
+    /// Pin a clock showing the local time at the selected country's capital, or unpin it.
+    PinClock,
+    /// Open the command palette, to find and run any action by name, or close it.
+    TogglePalette,
+}
+
+impl Action {
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Action::ShowTerrain => "Terrain only",
+            Action::ShowAllCountries => "Color all countries",
+            Action::ShowOecd => "Color OECD countries",
+            Action::ShowIncome => "Color countries by income group",
+            Action::ShowExceptional => "Exceptional mode",
+            Action::ShowScripted => "Color countries with the color script",
+            Action::ShowDaylight => "Day and night",
+            Action::ShowPercentile => "Color countries by population rank",
+            Action::ToggleRouteTool => "Toggle the route tool",
+            Action::DeleteRoute => "Delete the route under the cursor",
+            Action::PlayRoute => "Play or pause the selected route",
+            Action::ToggleFollow => "Toggle following the route played",
+            Action::ToggleLabels => "Toggle labels",
+            Action::ToggleOverview => "Toggle the overview inset",
+            Action::ToggleStereo => "Toggle red and cyan 3D",
+            Action::ToggleSplit => "Compare two map modes side by side",
+            Action::SnapNorth => "Turn to face north",
+            Action::ToggleGraticule => "Toggle latitude/longitude grid",
+            Action::ToggleStars => "Toggle stars",
+            Action::ToggleCrosshair => "Toggle crosshair and coordinates",
+            Action::ToggleHud => "Hide or show the HUD",
+            Action::TogglePerformance => "Toggle frame time graph",
+            Action::ToggleLegend => "Toggle legend",
+            Action::ToggleHelp => "Toggle this help",
+            Action::ToggleSettings => "Toggle settings",
+            Action::ToggleSessions => "Save or load a named session",
+            Action::NextAssetSet => "Switch to the next asset set",
+            Action::ToggleFullscreen => "Toggle fullscreen",
+            Action::Screenshot => "Save a screenshot",
+            Action::SaveReplay => "Save the last seconds as a GIF",
+            Action::Export => "Export the selected or shown countries",
+            Action::Escape => "Leave fullscreen, or quit",
+            Action::CopySelected => "Copy the selected country as JSON",
+            Action::ToggleRecording => "Start or stop recording frames",
+            Action::HiResScreenshot => "Save a high-resolution screenshot of the map",
+            Action::ExportLegend => "Save the legend and scale bar as an image",
+            Action::NextMode => "Switch to the next map mode",
+            Action::PreviousMode => "Switch to the previous map mode",
+            Action::ShowInRightHalf(_) => "Pick the right half's map mode",
+            Action::ToggleCategory(_) => "Toggle legend category",
+            Action::ToggleRightCategory(_) => "Toggle the right legend's category",
+            Action::FlyTo { .. } => "Fly to a place",
+            Action::ClickMap(_) => "Select a country, or pick a route's endpoints",
+            Action::PreviousCountry => "Select the previous country, alphabetically",
+            Action::NextCountry => "Select the next country, alphabetically",
+            Action::FrameSelected => "Fly to the selected country",
+            Action::ToggleTour => "Start or stop the tour of capitals",
+            Action::PreviousCapital => "Skip back a capital on the tour",
+            Action::NextCapital => "Skip to the next capital on the tour",
+            Action::ToggleHighContrast => "Toggle high contrast",
+            Action::ToggleFreeze => "Freeze or unfreeze animation",
+            Action::StepFrozen => "Step frozen animation by one frame",
+            Action::OpenSelectedLink => "Open the selected country's web page",
+            Action::TakeSnapshot => "Snapshot the mode's colors",
+            Action::ClearSnapshot => "Forget the snapshot",
+            Action::ToggleSnapshotDiff => "Toggle coloring by changes since the snapshot",
+            Action::PreviousParameter => "Select the mode's previous parameter",
+            Action::NextParameter => "Select the mode's next parameter",
+            Action::IncreaseParameter => "Raise the mode's parameter",
+            Action::DecreaseParameter => "Lower the mode's parameter",
+            Action::EditBreaks => "Edit the legend's class breaks",
+            Action::ResetBreaks => "Reset the legend's class breaks",
+            Action::ToggleAspectLock => "Lock or unlock the map's aspect ratio",
+            Action::AnimateParameter => "Sweep the mode's parameter, or stop",
+            Action::PinClock => "Pin or unpin a clock at the selected capital",
+            Action::TogglePalette => "Find and run any command",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Binding {
+    pub key: Key,
+    pub action: Action,
+}
+
+/// The built-in key bindings, in the order they are listed in the help overlay. The number keys
+/// are given to the map modes shown, in their order, by `with_overrides`.
+pub const DEFAULT_BINDINGS: [Binding; 49] = [
+    Binding { key: Key::D1, action: Action::ShowTerrain },
+    Binding { key: Key::D2, action: Action::ShowAllCountries },
+    Binding { key: Key::D3, action: Action::ShowOecd },
+    Binding { key: Key::D4, action: Action::ShowIncome },
+    Binding { key: Key::D5, action: Action::ShowExceptional },
+    Binding { key: Key::D6, action: Action::ShowScripted },
+    Binding { key: Key::D7, action: Action::ShowDaylight },
+    Binding { key: Key::D8, action: Action::ShowPercentile },
+    Binding { key: Key::D0, action: Action::ToggleLabels },
+    Binding { key: Key::M, action: Action::NextMode },
+    Binding { key: Key::N, action: Action::SnapNorth },
+    Binding { key: Key::G, action: Action::ToggleGraticule },
+    Binding { key: Key::K, action: Action::ToggleStars },
+    Binding { key: Key::C, action: Action::ToggleCrosshair },
+    Binding { key: Key::L, action: Action::ToggleLegend },
+    Binding { key: Key::B, action: Action::EditBreaks },
+    Binding { key: Key::S, action: Action::ToggleSplit },
+    Binding { key: Key::O, action: Action::ToggleOverview },
+    Binding { key: Key::A, action: Action::ToggleStereo },
+    Binding { key: Key::R, action: Action::ToggleRouteTool },
+    Binding { key: Key::Delete, action: Action::DeleteRoute },
+    Binding { key: Key::P, action: Action::PlayRoute },
+    Binding { key: Key::F, action: Action::ToggleFollow },
+    Binding { key: Key::T, action: Action::ToggleTour },
+    Binding { key: Key::Space, action: Action::ToggleFreeze },
+    Binding { key: Key::E, action: Action::Export },
+    Binding { key: Key::Comma, action: Action::PreviousCountry },
+    Binding { key: Key::Period, action: Action::NextCountry },
+    Binding { key: Key::Return, action: Action::OpenSelectedLink },
+    Binding { key: Key::V, action: Action::TakeSnapshot },
+    Binding { key: Key::D, action: Action::ToggleSnapshotDiff },
+    Binding { key: Key::PageUp, action: Action::PreviousParameter },
+    Binding { key: Key::PageDown, action: Action::NextParameter },
+    Binding { key: Key::Equals, action: Action::IncreaseParameter },
+    Binding { key: Key::NumPadPlus, action: Action::IncreaseParameter },
+    Binding { key: Key::Minus, action: Action::DecreaseParameter },
+    Binding { key: Key::NumPadMinus, action: Action::DecreaseParameter },
+    Binding { key: Key::F1, action: Action::ToggleHelp },
+    Binding { key: Key::H, action: Action::ToggleHelp },
+    Binding { key: Key::F2, action: Action::ToggleSettings },
+    Binding { key: Key::F3, action: Action::TogglePerformance },
+    Binding { key: Key::F4, action: Action::ToggleSessions },
+    Binding { key: Key::F6, action: Action::NextAssetSet },
+    Binding { key: Key::F7, action: Action::ToggleHighContrast },
+    Binding { key: Key::F9, action: Action::SaveReplay },
+    Binding { key: Key::F10, action: Action::ToggleHud },
+    Binding { key: Key::F11, action: Action::ToggleFullscreen },
+    Binding { key: Key::F12, action: Action::Screenshot },
+    Binding { key: Key::Escape, action: Action::Escape },
+];
+
+/// Controls that are not plain key presses, and so are not in the bindings table, but are listed
+/// alongside the key bindings. Those that are not camera moves are turned into an `Action` too.
+pub const EXTRA_CONTROLS: [(&str, &str); 24] = [
+    ("Ctrl+P", "Find and run any command"),
+    ("Shift+1-9", "Toggle legend category"),
+    ("Shift+M", "Switch to the previous map mode"),
+    ("Ctrl+mode key, in split-screen", "Pick the right half's map mode"),
+    ("Ctrl+Shift+1-9, in split-screen", "Toggle the right legend's category"),
+    ("Shift+F12", "Save a high-resolution screenshot of the map"),
+    ("Shift+F11", "Lock or unlock the map's aspect ratio, with --lock-aspect"),
+    ("Shift+L", "Save the legend and scale bar as an image"),
+    ("Arrow keys, editing breaks", "Select a class break, and move it"),
+    ("Shift+B", "Reset the legend's class breaks"),
+    ("Shift+P", "Sweep the mode's parameter across its range, or stop"),
+    ("Shift+T", "Pin or unpin a clock at the selected country's capital"),
+    ("Ctrl+R", "Start or stop recording frames"),
+    ("Ctrl+Shift+C", "Copy the selected country as JSON"),
+    ("Shift+Enter", "Fly to the selected country"),
+    ("Shift+Space, while frozen", "Step animation by one frame"),
+    ("Shift+V", "Forget the snapshot"),
+    ("Left/Right, on the tour", "Skip back or forward a capital"),
+    ("Click compass", "Turn to face north"),
+    ("Click overview", "Fly to the place clicked"),
+    ("Click, with the route tool", "Pick a route's endpoints"),
+    ("Scroll", "Zoom in and out"),
+    ("Middle drag", "Pan"),
+    ("Right drag", "Rotate"),
+];
+
+/// The actions the command palette lists, in the order it lists them when nothing is typed:
+/// every action that takes nothing, but those switching map modes, which are listed by mode.
+pub const PALETTE_ACTIONS: [Action; 52] = [
+    Action::ToggleLabels,
+    Action::ToggleOverview,
+    Action::ToggleStereo,
+    Action::ToggleSplit,
+    Action::ToggleRouteTool,
+    Action::DeleteRoute,
+    Action::PlayRoute,
+    Action::ToggleFollow,
+    Action::SnapNorth,
+    Action::ToggleGraticule,
+    Action::ToggleStars,
+    Action::ToggleCrosshair,
+    Action::ToggleHud,
+    Action::TogglePerformance,
+    Action::ToggleLegend,
+    Action::ToggleHelp,
+    Action::ToggleSettings,
+    Action::ToggleSessions,
+    Action::NextAssetSet,
+    Action::ToggleFullscreen,
+    Action::Screenshot,
+    Action::SaveReplay,
+    Action::Export,
+    Action::Escape,
+    Action::CopySelected,
+    Action::ToggleRecording,
+    Action::HiResScreenshot,
+    Action::ExportLegend,
+    Action::NextMode,
+    Action::PreviousMode,
+    Action::PreviousCountry,
+    Action::NextCountry,
+    Action::FrameSelected,
+    Action::ToggleTour,
+    Action::PreviousCapital,
+    Action::NextCapital,
+    Action::ToggleHighContrast,
+    Action::ToggleFreeze,
+    Action::StepFrozen,
+    Action::OpenSelectedLink,
+    Action::TakeSnapshot,
+    Action::ClearSnapshot,
+    Action::ToggleSnapshotDiff,
+    Action::PreviousParameter,
+    Action::NextParameter,
+    Action::IncreaseParameter,
+    Action::DecreaseParameter,
+    Action::EditBreaks,
+    Action::ResetBreaks,
+    Action::ToggleAspectLock,
+    Action::AnimateParameter,
+    Action::PinClock,
+];
+
+/// The number keys, in the order they are given to the map modes.
+const MODE_KEYS: [Key; 9] = [
+    Key::D1,
+    Key::D2,
+    Key::D3,
+    Key::D4,
+    Key::D5,
+    Key::D6,
+    Key::D7,
+    Key::D8,
+    Key::D9,
+];
+
+/// The default bindings, with the number keys given to `modes` in order and `overrides` from
+/// the settings file applied. Modes left out of `modes` have no key, unless an override gives
+/// them one. An override replaces any default binding on the same key, and is listed after the
+/// defaults.
+pub fn with_overrides(
+    overrides: &BTreeMap<String, Action>,
+    modes: &[MapMode],
+) -> Result<Vec<Binding>> {
+    let mode_bindings = modes.iter().zip(&MODE_KEYS).map(|(&mode, &key)| Binding {
+        key,
+        action: show_mode(mode),
+    });
+    let others = DEFAULT_BINDINGS
+        .iter()
+        .filter(|binding| mode_shown_by(binding.action).is_none())
+        .cloned();
+    let mut bindings: Vec<Binding> = mode_bindings.chain(others).collect();
+
+    for (name, &action) in overrides {
+        let deserializer: StrDeserializer<ValueError> = name.as_str().into_deserializer();
+        let key = Key::deserialize(deserializer)
+            .map_err(|_| format!("Invalid key {:?} in bindings", name))?;
+
+        bindings.retain(|binding| binding.key != key);
+        bindings.push(Binding { key, action });
+    }
+
+    Ok(bindings)
+}
+
+/// The action that switches to `mode`.
+pub fn show_mode(mode: MapMode) -> Action {
+    match mode {
+        MapMode::Terrain => Action::ShowTerrain,
+        MapMode::All => Action::ShowAllCountries,
+        MapMode::Oecd => Action::ShowOecd,
+        MapMode::Income => Action::ShowIncome,
+        MapMode::Exceptional => Action::ShowExceptional,
+        MapMode::Scripted => Action::ShowScripted,
+        MapMode::Daylight => Action::ShowDaylight,
+        MapMode::Percentile => Action::ShowPercentile,
+    }
+}
+
+/// The map mode `action` switches to, if it is one of the mode keys.
+pub fn mode_shown_by(action: Action) -> Option<MapMode> {
+    match action {
+        Action::ShowTerrain => Some(MapMode::Terrain),
+        Action::ShowAllCountries => Some(MapMode::All),
+        Action::ShowOecd => Some(MapMode::Oecd),
+        Action::ShowIncome => Some(MapMode::Income),
+        Action::ShowExceptional => Some(MapMode::Exceptional),
+        Action::ShowScripted => Some(MapMode::Scripted),
+        Action::ShowDaylight => Some(MapMode::Daylight),
+        Action::ShowPercentile => Some(MapMode::Percentile),
+        _ => None,
+    }
+}
+
+pub fn action_for(bindings: &[Binding], key: Key) -> Option<Action> {
+    bindings
+        .iter()
+        .find(|binding| binding.key == key)
+        .map(|binding| binding.action)
+}
+
+/// The first key bound to `action`, if any.
+pub fn key_for(bindings: &[Binding], action: Action) -> Option<Key> {
+    bindings
+        .iter()
+        .find(|binding| binding.action == action)
+        .map(|binding| binding.key)
+}
+
+/// The chord that triggers `action`, if it is one of those that take precedence over the
+/// bindings table, as `EXTRA_CONTROLS` lists them.
+pub fn chord_for(action: Action) -> Option<&'static str> {
+    let chord = match action {
+        Action::CopySelected => "Ctrl+Shift+C",
+        Action::ToggleRecording => "Ctrl+R",
+        Action::TogglePalette => "Ctrl+P",
+        Action::HiResScreenshot => "Shift+F12",
+        Action::ToggleAspectLock => "Shift+F11",
+        Action::ExportLegend => "Shift+L",
+        Action::ClearSnapshot => "Shift+V",
+        Action::PreviousMode => "Shift+M",
+        Action::ResetBreaks => "Shift+B",
+        Action::AnimateParameter => "Shift+P",
+        Action::PinClock => "Shift+T",
+        Action::FrameSelected => "Shift+Enter",
+        Action::StepFrozen => "Shift+Space",
+        Action::PreviousCapital => "Left",
+        Action::NextCapital => "Right",
+        _ => return None,
+    };
+
+    Some(chord)
+}
+
+/// The name `action` is bound by in the settings file, such as "toggle_labels", or `None` for
+/// actions that take something.
+pub fn action_name(action: Action) -> Option<String> {
+    match serde_json::to_value(action) {
+        Ok(serde_json::Value::String(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// A short, human-readable name for `key`.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::D0 => "0".to_string(),
+        Key::D1 => "1".to_string(),
+        Key::D2 => "2".to_string(),
+        Key::D3 => "3".to_string(),
+        Key::D4 => "4".to_string(),
+        Key::D5 => "5".to_string(),
+        Key::D6 => "6".to_string(),
+        Key::D7 => "7".to_string(),
+        Key::D8 => "8".to_string(),
+        Key::D9 => "9".to_string(),
+        Key::Escape => "Esc".to_string(),
+        Key::Comma => ",".to_string(),
+        Key::Period => ".".to_string(),
+        Key::Equals => "+".to_string(),
+        Key::Minus => "-".to_string(),
+        _ => format!("{:?}", key),
+    }
+}
@@ -0,0 +1,53 @@
+use std::process::{Command, Stdio};
+
+use {Result, ResultExt};
+
+/// Somewhere web pages are opened. The demo opens them in the system browser, and headless runs
+/// in `MemoryBrowser`, since there may be no browser where they run.
+pub trait Browser {
+    /// Opens `url`, which must already have been checked to be a web address.
+    fn open(&mut self, url: &str) -> Result<()>;
+}
+
+/// The system's default browser, opened with whatever opens files and addresses on the platform.
+/// The demo does not wait for it, so that it keeps running while the page loads.
+#[derive(Debug, Default)]
+pub struct SystemBrowser;
+
+impl Browser for SystemBrowser {
+    fn open(&mut self, url: &str) -> Result<()> {
+        let mut command = Command::new(OPENER[0]);
+        command
+            .args(&OPENER[1..])
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        command
+            .spawn()
+            .map(|_| ())
+            .chain_err(|| format!("Could not run {} to open the browser", OPENER[0]))
+    }
+}
+
+#[cfg(target_os = "macos")]
+const OPENER: &[&str] = &["open"];
+// `start` takes its first quoted argument as the window's title.
+#[cfg(windows)]
+const OPENER: &[&str] = &["cmd", "/C", "start", ""];
+#[cfg(not(any(target_os = "macos", windows)))]
+const OPENER: &[&str] = &["xdg-open"];
+
+/// A browser of its own, which only keeps the addresses opened in it, for headless runs.
+#[derive(Debug, Default)]
+pub struct MemoryBrowser {
+    pub opened: Vec<String>,
+}
+
+impl Browser for MemoryBrowser {
+    fn open(&mut self, url: &str) -> Result<()> {
+        self.opened.push(url.to_string());
+        Ok(())
+    }
+}
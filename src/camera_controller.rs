@@ -4,15 +4,14 @@ use cgmath::Matrix4;
 use piston::input::mouse::MouseButton;
 use piston::input::{Button, GenericEvent};
 
+use settings::CameraSettings;
+
 // Many of these constants only make sense based on values specified in `demo.rs`.
 
 /// Determines for how long height changes after a scroll event. If set to 0.01, then height
 /// velocity will decrease by 99% every second.
 const HEIGHT_VELOCITY_AFTER_SECOND: f32 = 0.01;
 
-/// Determines how quickly height changes the instant after a scroll event.
-const INITIAL_HEIGHT_VELOCITY: f32 = 0.05;
-
 /// When the user drags the mouse for one pixel with the camera at minimum height, this is the
 /// resulting change in `look_at`.
 const DRAG_DISTANCE_PER_PIXEL_MIN_HEIGHT: f32 = 0.0001;
@@ -20,10 +19,31 @@ const DRAG_DISTANCE_PER_PIXEL_MIN_HEIGHT: f32 = 0.0001;
 /// Same as `DRAG_DISTANCE_PER_PIXEL_MIN_HEIGHT`, but when the camera is at maximum height.
 const DRAG_DISTANCE_PER_PIXEL_MAX_HEIGHT: f32 = 0.002;
 
-/// The lowest the camera can go.
+/// When the user drags the mouse for one pixel while rotating, this is the resulting change in
+/// heading, in radians.
+const ROTATE_RADIANS_PER_PIXEL: f32 = 0.005;
+
+/// Below this height velocity, zooming is too slow to see, and the camera counts as settled.
+const SETTLED_VELOCITY: f32 = 0.001;
+
+/// While snapping north, the remaining turn shrinks to this fraction of itself every second.
+const SNAP_REMAINING_AFTER_SECOND: f32 = 0.001;
+
+/// A snap north finishes once the heading is within this many radians of north.
+const SNAP_EPSILON: f32 = 0.001;
+
+/// How long flying to a position with `fly_to` takes, in seconds.
+const FLIGHT_SECS: f32 = 1.5;
+
+/// While following something with `follow`, the remaining distance to where the camera should be
+/// shrinks to this fraction of itself every second.
+const FOLLOW_REMAINING_AFTER_SECOND: f32 = 0.01;
+
+/// The lowest the camera goes by default. Drag speed and viewing angle are set relative to this,
+/// even if the settings allow going lower.
 const MIN_HEIGHT: f32 = 0.05;
 
-/// The highest the camera can go.
+/// The highest the camera goes by default, and the height drag speed is set relative to.
 const MAX_HEIGHT: f32 = 1.0;
 
 /// The height above which the viewing angle will always be `MAX_ANGLE`.
@@ -41,6 +61,36 @@ const MAX_Y: f32 = 1.0;
 /// The furthest "down" (south) the camera can be looking at.
 const MIN_Y: f32 = 0.0;
 
+/// The fastest `ln(height)` may change per second, however much is scrolled at once. Far beyond
+/// what crosses the whole range of heights in a frame, it only keeps the velocity finite.
+const MAX_HEIGHT_VELOCITY: f32 = 1000.0;
+
+/// How far zoomed out the globe starts spinning when flicked, from 0 at the lowest height to 1 at
+/// the highest, measured on `ln(height)`. Flicks below it only pan.
+const SPIN_FROM_ZOOM: f32 = 0.6;
+
+/// How far zoomed out a flick spins the globe in full. Between `SPIN_FROM_ZOOM` and this, the
+/// spin fades in, so that zooming during a spin speeds it up or slows it down smoothly.
+const SPIN_FULL_ZOOM: f32 = 0.9;
+
+/// How fast the map must be moving sideways when a pan is let go for it to spin, in X units per
+/// second. The map repeats every 2 units, so this is a turn every 10 seconds.
+const MIN_FLICK_SPEED: f32 = 0.2;
+
+/// The fastest the globe spins, in X units per second.
+const MAX_SPIN: f32 = 4.0;
+
+/// Below this spin, in X units per second, the globe counts as stopped.
+const SETTLED_SPIN: f32 = 0.005;
+
+/// How much of the latest update's pan speed goes into the speed a flick is measured by, so that
+/// one uneven frame does not decide it.
+const DRAG_RATE_SMOOTHING: f32 = 0.5;
+
+/// The most pixels a single mouse motion is taken to move by, so that a bogus one cannot send the
+/// camera off to where positions lose their precision.
+const MAX_MOTION_PIXELS: f32 = 10_000.0;
+
 fn clamp(min: f32, max: f32, n: f32) -> f32 {
     min.max(max.min(n))
 }
@@ -49,21 +99,212 @@ fn linear_interpolate(min: f32, max: f32, t: f32) -> f32 {
     min + t * (max - min)
 }
 
+/// Unit vectors on the ground plane pointing "forward" (the direction the camera faces) and
+/// "right" of the camera, when it faces `heading`.
+fn ground_axes(heading: f32) -> ([f32; 2], [f32; 2]) {
+    let (sin, cos) = heading.sin_cos();
+
+    ([sin, cos], [cos, -sin])
+}
+
+/// The height and height velocity `dt` seconds after zooming from `height` at `velocity`.
+fn zoom_step(height: f32, velocity: f32, dt: f32, settings: &CameraSettings) -> (f32, f32) {
+    let new_height = height * (velocity * dt).exp();
+
+    (
+        clamp(settings.min_height, settings.max_height, new_height),
+        velocity * HEIGHT_VELOCITY_AFTER_SECOND.powf(dt),
+    )
+}
+
+/// The height velocity after scrolling `notches` down from `velocity`.
+fn scrolled(velocity: f32, notches: f32, settings: &CameraSettings) -> f32 {
+    if !notches.is_finite() {
+        return velocity;
+    }
+
+    // The velocity decays by `HEIGHT_VELOCITY_AFTER_SECOND` every second, so over its whole
+    // lifetime an initial velocity `v` moves `ln(height)` by
+    // `v / -ln(HEIGHT_VELOCITY_AFTER_SECOND)`. Choose `v` such that each notch multiplies the
+    // height by exactly `zoom_per_notch`.
+    let log_step = settings.zoom_per_notch.ln();
+    let velocity = velocity + notches * log_step * -HEIGHT_VELOCITY_AFTER_SECOND.ln();
+
+    clamp(-MAX_HEIGHT_VELOCITY, MAX_HEIGHT_VELOCITY, velocity)
+}
+
+/// How much of a spin to apply with the camera at `height`, from 0 when close to the ground to 1
+/// when zoomed all the way out.
+fn spin_weight(height: f32, settings: &CameraSettings) -> f32 {
+    let range = (settings.max_height / settings.min_height).ln();
+    let zoom = if range > 0.0 {
+        (height / settings.min_height).ln() / range
+    } else {
+        1.0
+    };
+
+    let t = clamp(0.0, 1.0, (zoom - SPIN_FROM_ZOOM) / (SPIN_FULL_ZOOM - SPIN_FROM_ZOOM));
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Where the camera looks after dragging the map `motion` pixels while looking at `look_at`
+/// from `height`, facing `heading`.
+fn panned(
+    look_at: [f32; 2],
+    height: f32,
+    heading: f32,
+    motion: [f32; 2],
+    settings: &CameraSettings,
+) -> [f32; 2] {
+    if !motion[0].is_finite() || !motion[1].is_finite() {
+        return look_at;
+    }
+    let x = clamp(-MAX_MOTION_PIXELS, MAX_MOTION_PIXELS, motion[0]);
+    let y = clamp(-MAX_MOTION_PIXELS, MAX_MOTION_PIXELS, motion[1]);
+
+    let t = (height.max(MIN_HEIGHT) - MIN_HEIGHT) / (MAX_HEIGHT - MIN_HEIGHT);
+    let drag_distance_per_pixel = settings.pan_sensitivity
+        * linear_interpolate(
+            DRAG_DISTANCE_PER_PIXEL_MIN_HEIGHT,
+            DRAG_DISTANCE_PER_PIXEL_MAX_HEIGHT,
+            t,
+        );
+
+    let (forward, right) = ground_axes(heading);
+    [
+        look_at[0] + (y * forward[0] - x * right[0]) * drag_distance_per_pixel,
+        clamp(
+            MIN_Y,
+            MAX_Y,
+            look_at[1] + (y * forward[1] - x * right[1]) * drag_distance_per_pixel,
+        ),
+    ]
+}
+
+/// The heading after dragging `motion_x` pixels sideways while rotating from `heading`.
+fn rotated(heading: f32, motion_x: f32, settings: &CameraSettings) -> f32 {
+    if !motion_x.is_finite() {
+        return heading;
+    }
+    let x = clamp(-MAX_MOTION_PIXELS, MAX_MOTION_PIXELS, motion_x);
+
+    (heading + x * ROTATE_RADIANS_PER_PIXEL * settings.rotate_sensitivity) % (2.0 * PI)
+}
+
+/// The heading `dt` seconds into snapping north from `heading`, or `None` once it is close
+/// enough to north to stop.
+fn snap_step(heading: f32, dt: f32) -> Option<f32> {
+    // Turn whichever way is shorter.
+    let mut remaining = heading % (2.0 * PI);
+    if remaining > PI {
+        remaining -= 2.0 * PI;
+    } else if remaining < -PI {
+        remaining += 2.0 * PI;
+    }
+
+    remaining *= SNAP_REMAINING_AFTER_SECOND.powf(dt);
+    if remaining.abs() < SNAP_EPSILON {
+        None
+    } else {
+        Some(remaining)
+    }
+}
+
+/// Where the camera is looking from, as animated between by a flight.
+#[derive(Clone, Copy, Debug)]
+struct Pose {
+    look_at: [f32; 2],
+    height: f32,
+    heading: f32,
+}
+
+/// A smooth move from one pose to another, started by `fly_to`.
+#[derive(Clone, Copy, Debug)]
+struct Flight {
+    from: Pose,
+    to: Pose,
+    /// In seconds since the flight started.
+    elapsed: f32,
+}
+
+impl Flight {
+    /// The pose `elapsed` into the flight. It eases in and out, and changes height
+    /// geometrically, so that zooming looks as even as scrolling does.
+    fn pose(&self) -> Pose {
+        let t = clamp(0.0, 1.0, self.elapsed / FLIGHT_SECS);
+        let t = t * t * (3.0 - 2.0 * t);
+        let (from, to) = (self.from, self.to);
+
+        Pose {
+            look_at: [
+                linear_interpolate(from.look_at[0], to.look_at[0], t),
+                linear_interpolate(from.look_at[1], to.look_at[1], t),
+            ],
+            height: linear_interpolate(from.height.ln(), to.height.ln(), t).exp(),
+            heading: linear_interpolate(from.heading, to.heading, t),
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.elapsed >= FLIGHT_SECS
+    }
+}
+
 #[derive(Debug)]
 pub struct CameraController {
     look_at: [f32; 2],
     height: f32,
+    /// The direction the camera faces, in radians clockwise from north.
+    heading: f32,
+    /// The first two components are unused; the third is the rate of change of `ln(height)`.
     velocity: [f32; 3],
-    dragging: bool,
+    /// How fast the globe spins about its axis after a flick, in X units per second, before
+    /// `spin_weight`.
+    spin: f32,
+    /// How far panning has moved X since the last update.
+    drag_x: f32,
+    /// How fast panning has been moving X lately, in units per second.
+    drag_rate: f32,
+    panning: bool,
+    rotating: bool,
+    /// Whether the heading is being animated back to north.
+    snapping_north: bool,
+    flight: Option<Flight>,
+    /// Whether the camera was moved, other than by `follow`, since `take_interrupted`.
+    interrupted: bool,
+    /// The least height the terrain under the camera allows, from `set_floor`.
+    floor: f32,
+    /// Whether the last update kept the camera up at `floor`, above `settings.min_height`.
+    held_up: bool,
+    settings: CameraSettings,
 }
 
 impl CameraController {
-    pub fn new() -> CameraController {
+    pub fn new(settings: CameraSettings) -> CameraController {
         CameraController {
             look_at: [0.0, 0.0],
-            height: MAX_HEIGHT,
+            height: settings.max_height,
+            heading: 0.0,
             velocity: [0.0, 0.0, 0.0],
-            dragging: false,
+            spin: 0.0,
+            drag_x: 0.0,
+            drag_rate: 0.0,
+            panning: false,
+            rotating: false,
+            snapping_north: false,
+            flight: None,
+            interrupted: false,
+            floor: 0.0,
+            held_up: false,
+            settings,
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: CameraSettings) {
+        self.settings = settings;
+        self.height = clamp(settings.min_height, settings.max_height, self.height);
+        if !settings.spin {
+            self.spin = 0.0;
         }
     }
 
@@ -73,53 +314,145 @@ impl CameraController {
     {
         e.update(|args| {
             let dt = args.dt as f32;
-            let velocity_loss_factor = HEIGHT_VELOCITY_AFTER_SECOND.powf(dt);
 
-            let new_height = self.height + self.velocity[2] * dt;
-            self.height = clamp(MIN_HEIGHT, MAX_HEIGHT, new_height);
-            self.velocity[2] *= velocity_loss_factor;
+            if let Some(ref mut flight) = self.flight {
+                flight.elapsed += dt;
+                let pose = flight.pose();
+                self.look_at = pose.look_at;
+                self.height = pose.height;
+                self.heading = pose.heading;
+            }
+            if self.flight.map_or(false, |flight| flight.is_over()) {
+                self.flight = None;
+                self.heading %= 2.0 * PI;
+            }
+
+            let (height, velocity) = zoom_step(self.height, self.velocity[2], dt, &self.settings);
+            self.height = height;
+            self.velocity[2] = velocity;
+
+            // The floor changes gradually, so keeping to it eases the camera up.
+            let floor = self.floor.min(self.settings.max_height);
+            self.held_up = floor > self.settings.min_height && self.height <= floor;
+            if self.held_up {
+                self.height = floor;
+                self.velocity[2] = self.velocity[2].max(0.0);
+            }
+
+            if self.panning && dt > 0.0 {
+                let rate = self.drag_x / dt;
+                self.drag_rate = linear_interpolate(self.drag_rate, rate, DRAG_RATE_SMOOTHING);
+                self.drag_x = 0.0;
+            }
+            if self.spin != 0.0 {
+                self.look_at[0] += self.spin * spin_weight(self.height, &self.settings) * dt;
+                self.spin *= self.settings.spin_decay.powf(dt);
+                if self.spin.abs() < SETTLED_SPIN {
+                    self.spin = 0.0;
+                }
+            }
+
+            if self.snapping_north {
+                match snap_step(self.heading, dt) {
+                    Some(heading) => self.heading = heading,
+                    None => {
+                        self.heading = 0.0;
+                        self.snapping_north = false;
+                    }
+                }
+            }
         });
 
         e.mouse_scroll(|_scroll_x, scroll_y| {
-            let scroll = -(scroll_y as f32);
-            self.velocity[2] += scroll * INITIAL_HEIGHT_VELOCITY;
+            self.flight = None;
+            self.interrupted = true;
+            self.velocity[2] = scrolled(self.velocity[2], -(scroll_y as f32), &self.settings);
         });
 
+        // Any button stops the globe spinning, as a hand would.
         e.press(|button| {
-            self.set_drag_if_middle(button, true);
+            self.spin = 0.0;
+            self.set_drag(button, true);
         });
 
         e.release(|button| {
-            self.set_drag_if_middle(button, false);
+            self.set_drag(button, false);
+        });
+
+        // Button releases are not delivered while the window is unfocused, so a drag in progress
+        // would otherwise never end.
+        e.focus(|focused| {
+            if !focused {
+                self.release();
+            }
         });
 
         e.mouse_relative(|x, y| {
-            if self.dragging {
-                let t = (self.height - MIN_HEIGHT) / (MAX_HEIGHT - MIN_HEIGHT);
-                let drag_distance_per_pixel = linear_interpolate(
-                    DRAG_DISTANCE_PER_PIXEL_MIN_HEIGHT,
-                    DRAG_DISTANCE_PER_PIXEL_MAX_HEIGHT,
-                    t,
-                );
-
-                self.look_at[0] -= x as f32 * drag_distance_per_pixel;
-                self.look_at[1] += y as f32 * drag_distance_per_pixel;
-
-                self.look_at[1] = clamp(MIN_Y, MAX_Y, self.look_at[1]);
+            let motion = [x as f32, y as f32];
+
+            if self.panning {
+                let x = self.look_at[0];
+                self.look_at =
+                    panned(self.look_at, self.height, self.heading, motion, &self.settings);
+                self.drag_x += self.look_at[0] - x;
+            }
+
+            if self.rotating {
+                self.heading = rotated(self.heading, motion[0], &self.settings);
             }
         });
     }
 
-    /// If `button` is the middle mouse button, set dragging state to `dragging`.
-    fn set_drag_if_middle(&mut self, button: Button, dragging: bool) {
+    /// Keeps the camera at least `floor` high from the next update on, such as over high
+    /// terrain. It should change gradually, or the camera jumps up with it.
+    pub fn set_floor(&mut self, floor: f32) {
+        self.floor = floor;
+    }
+
+    /// Whether the camera is being held up above `min_height` by the floor.
+    pub fn is_held_up(&self) -> bool {
+        self.held_up
+    }
+
+    /// Lets go of whatever the user was moving the camera with, as when the window loses focus:
+    /// drags end without flicking the globe, and zooming and spinning stop where they are.
+    /// Flights carry on.
+    pub fn release(&mut self) {
+        self.panning = false;
+        self.rotating = false;
+        self.drag_x = 0.0;
+        self.drag_rate = 0.0;
+        self.spin = 0.0;
+        self.velocity = [0.0, 0.0, 0.0];
+    }
+
+    /// If `button` is the middle (pan) or right (rotate) mouse button, set that drag state to
+    /// `dragging`. Letting go of a pan that was still moving sideways while zoomed out spins the
+    /// globe.
+    fn set_drag(&mut self, button: Button, dragging: bool) {
         match button {
-            Button::Mouse(mouse_button) => {
-                match mouse_button {
-                    MouseButton::Middle => {
-                        self.dragging = dragging;
-                    }
-                    _ => {}
-                };
+            Button::Mouse(MouseButton::Middle) => {
+                let flicked = self.panning && !dragging && self.drag_rate.abs() >= MIN_FLICK_SPEED;
+                if flicked && self.settings.spin && spin_weight(self.height, &self.settings) > 0.0 {
+                    self.spin = clamp(-MAX_SPIN, MAX_SPIN, self.drag_rate);
+                }
+                self.panning = dragging;
+                self.drag_x = 0.0;
+                self.drag_rate = 0.0;
+
+                if dragging {
+                    self.flight = None;
+                    self.interrupted = true;
+                }
+            }
+            Button::Mouse(MouseButton::Right) => {
+                self.rotating = dragging;
+
+                if dragging {
+                    self.snapping_north = false;
+                    self.flight = None;
+                    self.interrupted = true;
+                }
             }
             _ => {}
         };
@@ -127,19 +460,166 @@ impl CameraController {
 
     pub fn view_matrix(&self) -> Matrix4<f32> {
         let camera_look_at = [self.look_at[0], self.look_at[1], 0.0];
+        let (forward, _) = ground_axes(self.heading);
 
         Matrix4::look_at(
             self.camera_position().into(),
             camera_look_at.into(),
-            [0.0, 1.0, 0.0].into(),
+            [forward[0], forward[1], 0.0].into(),
+        )
+    }
+
+    /// The view matrix for one eye of a stereo pair, moved `offset` along the camera's right, in
+    /// world units. Both eyes turn in to look at the point on the line of sight `convergence`
+    /// times as far away as the look-at point.
+    pub fn eye_view_matrix(&self, offset: f32, convergence: f32) -> Matrix4<f32> {
+        let position = self.camera_position();
+        let (forward, right) = ground_axes(self.heading);
+        let look_at = [self.look_at[0], self.look_at[1], 0.0];
+
+        let mut eye = position;
+        let mut target = position;
+        for axis in 0..3 {
+            target[axis] += (look_at[axis] - position[axis]) * convergence;
+        }
+        eye[0] += right[0] * offset;
+        eye[1] += right[1] * offset;
+
+        Matrix4::look_at(
+            eye.into(),
+            target.into(),
+            [forward[0], forward[1], 0.0].into(),
         )
     }
 
     pub fn camera_position(&self) -> [f32; 3] {
         let angle = self.viewing_angle();
-        let offset_y = self.height * (1.0 / angle.tan());
+        let offset = self.height * (1.0 / angle.tan());
+        let (forward, _) = ground_axes(self.heading);
+
+        [
+            self.look_at[0] - offset * forward[0],
+            self.look_at[1] - offset * forward[1],
+            self.height,
+        ]
+    }
+
+    /// Jumps to looking at `latitude` and `longitude`, in degrees, from `height`.
+    pub fn move_to(&mut self, latitude: f32, longitude: f32, height: f32) {
+        self.look_at = [
+            2.0 * (longitude + 180.0) / 360.0,
+            clamp(MIN_Y, MAX_Y, (latitude + 90.0) / 180.0),
+        ];
+        self.height = clamp(self.settings.min_height, self.settings.max_height, height);
+        self.velocity = [0.0, 0.0, 0.0];
+        self.spin = 0.0;
+        self.flight = None;
+        self.interrupted = true;
+    }
+
+    /// Turns to face `heading`, in degrees clockwise from north.
+    pub fn set_heading(&mut self, heading: f32) {
+        self.heading = heading.to_radians() % (2.0 * PI);
+        self.snapping_north = false;
+        self.flight = None;
+        self.interrupted = true;
+    }
+
+    /// Starts smoothly moving to look at `latitude` and `longitude`, in degrees, from `height`,
+    /// facing `heading`, in degrees clockwise from north, instead of jumping there. Zooming,
+    /// panning or rotating stops the flight where it is.
+    pub fn fly_to(&mut self, latitude: f32, longitude: f32, height: f32, heading: f32) {
+        let from = Pose {
+            look_at: self.look_at,
+            height: self.height,
+            heading: self.heading,
+        };
+
+        // The map repeats every 2 units of X, so go the shorter way around, which may end up on
+        // another copy of it.
+        let mut x = 2.0 * (longitude + 180.0) / 360.0;
+        x += 2.0 * ((from.look_at[0] - x) / 2.0).round();
+        // Likewise, turn whichever way is shorter.
+        let mut heading = heading.to_radians();
+        heading += 2.0 * PI * ((from.heading - heading) / (2.0 * PI)).round();
+
+        let to = Pose {
+            look_at: [x, clamp(MIN_Y, MAX_Y, (latitude + 90.0) / 180.0)],
+            height: clamp(self.settings.min_height, self.settings.max_height, height),
+            heading,
+        };
+
+        self.velocity = [0.0, 0.0, 0.0];
+        self.spin = 0.0;
+        self.snapping_north = false;
+        self.interrupted = true;
+        self.flight = Some(Flight {
+            from,
+            to,
+            elapsed: 0.0,
+        });
+    }
+
+    /// Moves `dt` seconds of the way toward looking at `latitude` and `longitude`, in degrees,
+    /// from `height`, facing `heading`, in degrees clockwise from north. Called every update, the
+    /// camera follows something that moves, catching up without jumping when it starts.
+    pub fn follow(&mut self, latitude: f32, longitude: f32, height: f32, heading: f32, dt: f32) {
+        // As with `fly_to`, go the shorter way around, and turn whichever way is shorter.
+        let mut x = 2.0 * (longitude + 180.0) / 360.0;
+        x += 2.0 * ((self.look_at[0] - x) / 2.0).round();
+        let mut heading = heading.to_radians();
+        heading += 2.0 * PI * ((self.heading - heading) / (2.0 * PI)).round();
+        let y = clamp(MIN_Y, MAX_Y, (latitude + 90.0) / 180.0);
+        let height = clamp(self.settings.min_height, self.settings.max_height, height);
+
+        let remaining = FOLLOW_REMAINING_AFTER_SECOND.powf(dt);
+        self.look_at = [
+            linear_interpolate(x, self.look_at[0], remaining),
+            linear_interpolate(y, self.look_at[1], remaining),
+        ];
+        self.height = linear_interpolate(height.ln(), self.height.ln(), remaining).exp();
+        self.heading = linear_interpolate(heading, self.heading, remaining) % (2.0 * PI);
+
+        self.velocity = [0.0, 0.0, 0.0];
+        self.spin = 0.0;
+        self.snapping_north = false;
+        self.flight = None;
+    }
+
+    /// Whether the camera was zoomed, panned, rotated or moved by anything but `follow` since
+    /// the last call.
+    pub fn take_interrupted(&mut self) -> bool {
+        ::std::mem::replace(&mut self.interrupted, false)
+    }
+
+    /// Starts smoothly turning the camera to face north.
+    pub fn snap_north(&mut self) {
+        self.flight = None;
+        self.snapping_north = true;
+        self.interrupted = true;
+    }
 
-        [self.look_at[0], self.look_at[1] - offset_y, self.height]
+    /// The direction the camera faces, in radians clockwise from north.
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.rotating
+    }
+
+    /// Whether a flight started with `fly_to` is still on its way.
+    pub fn is_flying(&self) -> bool {
+        self.flight.is_some()
+    }
+
+    /// Whether the camera is still moving on its own, from zoom inertia, a spinning globe,
+    /// snapping north or a flight.
+    pub fn is_animating(&self) -> bool {
+        self.velocity[2].abs() > SETTLED_VELOCITY
+            || self.spin != 0.0
+            || self.snapping_north
+            || self.flight.is_some()
     }
 
     pub fn camera_height(&self) -> f32 {
@@ -147,13 +627,286 @@ impl CameraController {
     }
 
     pub fn look_at(&self) -> [f32; 2] {
-        self.look_at.clone()
+        self.look_at
     }
 
-    fn viewing_angle(&self) -> f32 {
-        let h = MAX_ANGLE_HEIGHT.min(self.height);
+    /// The longitude of `look_at`, in degrees between -180 and 180. The map repeats horizontally
+    /// every 2 units, so this wraps around.
+    pub fn longitude(&self) -> f32 {
+        let x = (self.look_at[0] / 2.0).rem_euclid(1.0);
+
+        x * 360.0 - 180.0
+    }
+
+    /// The latitude of `look_at`, in degrees between -90 and 90.
+    pub fn latitude(&self) -> f32 {
+        self.look_at[1] * 180.0 - 90.0
+    }
+
+    /// The angle between the ground and the line of sight, in radians. Looking straight down
+    /// is `PI / 2`.
+    pub fn viewing_angle(&self) -> f32 {
+        let h = clamp(MIN_HEIGHT, MAX_ANGLE_HEIGHT, self.height);
         let t = (h - MIN_HEIGHT) / (MAX_ANGLE_HEIGHT - MIN_HEIGHT);
 
         linear_interpolate(MIN_ANGLE, MAX_ANGLE, t)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{Matrix, Matrix3, SquareMatrix};
+    use piston::input::{ButtonArgs, ButtonState, Event, Input, Loop, Motion, UpdateArgs};
+
+    const EPSILON: f32 = 1e-4;
+
+    fn update(dt: f64) -> Event {
+        Event::Loop(Loop::Update(UpdateArgs { dt }))
+    }
+
+    fn scroll(notches: f64) -> Event {
+        Event::Input(Input::Move(Motion::MouseScroll(0.0, notches)))
+    }
+
+    fn motion(x: f64, y: f64) -> Event {
+        Event::Input(Input::Move(Motion::MouseRelative(x, y)))
+    }
+
+    fn button(button: MouseButton, state: ButtonState) -> Event {
+        Event::Input(Input::Button(ButtonArgs {
+            state,
+            button: Button::Mouse(button),
+            scancode: None,
+        }))
+    }
+
+    fn controller() -> CameraController {
+        let mut camera = CameraController::new(CameraSettings::default());
+        camera.move_to(20.0, 10.0, 0.2);
+        camera
+    }
+
+    /// Runs updates `dt` apart until zoom inertia has all but worn off.
+    fn settle(camera: &mut CameraController, dt: f64) {
+        for _ in 0..(20.0 / dt) as usize {
+            camera.event(&update(dt));
+        }
+    }
+
+    fn drag(camera: &mut CameraController, with: MouseButton, motions: &[[f64; 2]]) {
+        camera.event(&button(with, ButtonState::Press));
+        for &[x, y] in motions {
+            camera.event(&motion(x, y));
+        }
+        camera.event(&button(with, ButtonState::Release));
+    }
+
+    fn assert_finite(camera: &CameraController) {
+        let position = camera.camera_position();
+        let view: [[f32; 4]; 4] = camera.view_matrix().into();
+
+        assert!(camera.camera_height().is_finite(), "{:?}", camera);
+        assert!(camera.heading().is_finite(), "{:?}", camera);
+        assert!(camera.look_at().iter().all(|n| n.is_finite()), "{:?}", camera);
+        assert!(position.iter().all(|n| n.is_finite()), "{:?}", camera);
+        assert!(view.iter().flatten().all(|n| n.is_finite()), "{:?}", camera);
+    }
+
+    #[test]
+    fn view_matrix_is_orthonormal() {
+        let mut camera = controller();
+
+        for &height in &[0.05, 0.08, 0.2, 1.0] {
+            for &heading in &[0.0, 45.0, 170.0, 260.0, -30.0] {
+                camera.move_to(-35.0, 120.0, height);
+                camera.set_heading(heading);
+
+                let view = camera.view_matrix();
+                let rotation =
+                    Matrix3::from_cols(view.x.truncate(), view.y.truncate(), view.z.truncate());
+                let product: [[f32; 3]; 3] = (rotation.transpose() * rotation).into();
+                let identity: [[f32; 3]; 3] = Matrix3::identity().into();
+
+                for (actual, expected) in product.iter().flatten().zip(identity.iter().flatten()) {
+                    assert!((actual - expected).abs() < EPSILON, "{:?}", product);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn each_notch_multiplies_the_height() {
+        let mut camera = controller();
+        let zoom_per_notch = CameraSettings::default().zoom_per_notch;
+
+        camera.event(&scroll(-3.0));
+        settle(&mut camera, 0.001);
+
+        let expected = 0.2 * zoom_per_notch.powi(3);
+        assert!((camera.camera_height() / expected - 1.0).abs() < 0.01);
+        assert!(!camera.is_animating());
+    }
+
+    #[test]
+    fn zooming_in_and_out_returns_to_the_same_height() {
+        let mut camera = controller();
+
+        for &notches in &[-2.0, 5.0, 1.0, -4.0] {
+            camera.event(&scroll(notches));
+            settle(&mut camera, 0.016);
+        }
+
+        assert!((camera.camera_height() - 0.2).abs() < EPSILON, "{:?}", camera);
+    }
+
+    #[test]
+    fn height_stays_within_the_settings() {
+        let settings = CameraSettings::default();
+        let mut camera = controller();
+
+        camera.event(&scroll(-200.0));
+        settle(&mut camera, 0.016);
+        assert_eq!(camera.camera_height(), settings.max_height);
+
+        camera.event(&scroll(400.0));
+        settle(&mut camera, 0.016);
+        assert_eq!(camera.camera_height(), settings.min_height);
+
+        camera.move_to(0.0, 0.0, 10.0);
+        assert_eq!(camera.camera_height(), settings.max_height);
+
+        camera.set_settings(CameraSettings {
+            max_height: 0.5,
+            ..settings
+        });
+        assert_eq!(camera.camera_height(), 0.5);
+    }
+
+    #[test]
+    fn panning_back_returns_to_the_same_place() {
+        let mut camera = controller();
+
+        for &heading in &[0.0, 90.0, 200.0] {
+            camera.set_heading(heading);
+            let start = camera.look_at();
+
+            drag(&mut camera, MouseButton::Middle, &[[30.0, -20.0], [12.5, 4.0]]);
+            assert_ne!(camera.look_at(), start);
+            drag(&mut camera, MouseButton::Middle, &[[-12.5, -4.0], [-30.0, 20.0]]);
+
+            let end = camera.look_at();
+            assert!((end[0] - start[0]).abs() < EPSILON && (end[1] - start[1]).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn panning_stops_at_the_poles() {
+        let mut camera = controller();
+
+        drag(&mut camera, MouseButton::Middle, &[[0.0, 5000.0]; 10]);
+        assert_eq!(camera.look_at()[1], MAX_Y);
+
+        drag(&mut camera, MouseButton::Middle, &[[0.0, -5000.0]; 20]);
+        assert_eq!(camera.look_at()[1], MIN_Y);
+    }
+
+    #[test]
+    fn snapping_north_finishes() {
+        let mut camera = controller();
+        camera.set_heading(135.0);
+
+        camera.snap_north();
+        for _ in 0..100 {
+            camera.event(&update(0.05));
+        }
+
+        assert_eq!(camera.heading(), 0.0);
+        assert!(!camera.is_animating());
+        assert_eq!(snap_step(0.0, 0.016), None);
+    }
+
+    #[test]
+    fn adversarial_events_leave_everything_finite() {
+        let mut camera = controller();
+        let huge = [1e30, -1e30, ::std::f64::MAX, ::std::f64::INFINITY, ::std::f64::NAN];
+
+        for &n in &huge {
+            camera.event(&scroll(n));
+            camera.event(&update(0.0));
+            camera.event(&update(1e-9));
+            drag(&mut camera, MouseButton::Middle, &[[n, n], [-n, 1.0]]);
+            drag(&mut camera, MouseButton::Right, &[[n, 0.0], [1.0, n]]);
+            camera.event(&update(0.016));
+            camera.event(&update(1e6));
+            camera.snap_north();
+            camera.event(&update(0.0));
+            camera.fly_to(10.0, 10.0, 0.3, 90.0);
+            camera.event(&update(0.0));
+            camera.event(&update(0.5));
+            assert_finite(&camera);
+        }
+
+        for _ in 0..1000 {
+            camera.event(&scroll(-1e30));
+        }
+        camera.event(&update(0.016));
+        assert_finite(&camera);
+    }
+
+    #[test]
+    fn a_floor_holds_the_camera_up_while_it_zooms_in() {
+        let settings = CameraSettings::default();
+        let mut camera = controller();
+        camera.set_floor(0.08);
+
+        camera.event(&scroll(400.0));
+        settle(&mut camera, 0.016);
+        assert_eq!(camera.camera_height(), 0.08);
+        assert!(camera.is_held_up());
+
+        // Zooming out leaves the floor behind...
+        camera.event(&scroll(-20.0));
+        settle(&mut camera, 0.016);
+        assert!(camera.camera_height() > 0.08);
+        assert!(!camera.is_held_up());
+
+        // ...and a floor below the least height makes no difference.
+        camera.set_floor(settings.min_height / 2.0);
+        camera.event(&scroll(400.0));
+        settle(&mut camera, 0.016);
+        assert_eq!(camera.camera_height(), settings.min_height);
+        assert!(!camera.is_held_up());
+    }
+
+    #[test]
+    fn losing_focus_mid_drag_leaves_the_camera_still() {
+        for &with in &[MouseButton::Middle, MouseButton::Right] {
+            let mut camera = controller();
+            camera.event(&scroll(-3.0));
+            camera.event(&button(with, ButtonState::Press));
+            camera.event(&motion(80.0, 20.0));
+            camera.event(&update(0.016));
+
+            camera.event(&Event::Input(Input::Focus(false)));
+            let (look_at, heading, height) =
+                (camera.look_at(), camera.heading(), camera.camera_height());
+
+            // Motion while unfocused, as after switching windows mid-drag, moves nothing...
+            camera.event(&motion(300.0, -40.0));
+            for _ in 0..100 {
+                camera.event(&update(0.016));
+            }
+            // ...nor does the release, if it is delivered on coming back.
+            camera.event(&Event::Input(Input::Focus(true)));
+            camera.event(&button(with, ButtonState::Release));
+            camera.event(&update(0.016));
+
+            assert_eq!(camera.look_at(), look_at, "{:?}", with);
+            assert_eq!(camera.heading(), heading, "{:?}", with);
+            assert_eq!(camera.camera_height(), height, "{:?}", with);
+            assert!(!camera.is_animating(), "{:?}", camera);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! A quick benchmark of the choosers gaia calls for every country and place it draws, run at
+//! startup so that a dataset that makes them too slow for 60 FPS is noticed before it is
+//! deployed, rather than as a dropped frame rate.
+
+use std::hint;
+use std::time::{Duration, Instant};
+
+use gaia_assetgen::{MultiLevelPoint, Properties};
+
+use split_view::View;
+use state::{FrameInputs, State};
+
+/// How many passes over the features each chooser is timed for, unless `TIME_BUDGET` runs out
+/// first.
+const ITERATIONS: usize = 1000;
+
+/// How long each chooser is timed for at most, so that the whole benchmark keeps under about
+/// 50 ms however large the dataset is.
+const TIME_BUDGET: Duration = Duration::from_millis(20);
+
+/// At most this many of each kind of feature are timed. Larger datasets are sampled evenly, and
+/// the averages scaled up to all of them.
+const MAX_SAMPLED: usize = 2000;
+
+/// The camera height places are labeled as of. Low enough that nearly every place passes its
+/// `min_zoom`, so that most calls go the whole way through the chooser.
+const CAMERA_HEIGHT: f32 = 0.1;
+
+/// How long either chooser can take over every feature in a frame before it threatens 60 FPS,
+/// in milliseconds. Each is allowed about a quarter of a frame.
+const FRAME_SHARE_MS: f64 = 4.0;
+
+/// The average time of one chooser's call, and how many features it is called for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    /// How many features the dataset has, and so how many calls a frame makes.
+    pub features: usize,
+    /// How many calls were timed.
+    pub calls: usize,
+    /// The average call, in microseconds.
+    pub average_us: f64,
+}
+
+impl Timing {
+    /// How long the chooser would take over every feature in one frame, in milliseconds.
+    pub fn frame_ms(&self) -> f64 {
+        self.average_us * self.features as f64 / 1000.0
+    }
+
+    pub fn is_slow(&self) -> bool {
+        self.frame_ms() > FRAME_SHARE_MS
+    }
+
+    fn describe(&self, name: &str) -> String {
+        format!(
+            "{} {:.2} us per feature, {:.2} ms for {} features",
+            name,
+            self.average_us,
+            self.frame_ms(),
+            self.features
+        )
+    }
+}
+
+/// How fast both choosers were.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Results {
+    pub colors: Timing,
+    pub labels: Timing,
+}
+
+impl Results {
+    pub fn is_slow(&self) -> bool {
+        self.colors.is_slow() || self.labels.is_slow()
+    }
+
+    /// Such as "colors 0.40 us per feature, 0.10 ms for 255 features; labels ...".
+    pub fn summary(&self) -> String {
+        format!(
+            "{}; {}",
+            self.colors.describe("colors"),
+            self.labels.describe("labels")
+        )
+    }
+
+    /// Logs the results, with a warning if either chooser threatens 60 FPS.
+    pub fn log(&self) {
+        if self.is_slow() {
+            warn!(
+                "Choosers may be too slow for 60 FPS with this dataset, over {:.0} ms a frame: {}",
+                FRAME_SHARE_MS,
+                self.summary()
+            );
+        } else {
+            info!("Chooser throughput: {}", self.summary());
+        }
+    }
+}
+
+/// The places `run` times the label style chooser over. They are taken from the dataset before
+/// it is handed over, since the demo keeps no places of its own.
+#[derive(Clone, Debug, Default)]
+pub struct Places {
+    /// The properties of up to `MAX_SAMPLED` of the places, evenly spread.
+    sampled: Vec<Properties>,
+    /// How many places there are in all.
+    count: usize,
+}
+
+impl Places {
+    pub fn sample(points: &[MultiLevelPoint]) -> Places {
+        Places {
+            sampled: sample(points).into_iter().map(|point| point.properties.clone()).collect(),
+            count: points.len(),
+        }
+    }
+}
+
+/// Up to `MAX_SAMPLED` of `items`, every so many.
+fn sample<T>(items: &[T]) -> Vec<&T> {
+    let stride = (items.len() + MAX_SAMPLED - 1) / MAX_SAMPLED;
+    items.iter().step_by(stride.max(1)).collect()
+}
+
+/// Times `state`'s polygon color chooser over every country in the mode it is in, and its label
+/// style chooser over `places`. The color cache is cleared before each pass, so that colors are
+/// worked out each time, as they are in every frame of an animated mode.
+pub fn run(state: &State, places: &Places) -> Results {
+    let frame = FrameInputs {
+        camera_height: CAMERA_HEIGHT,
+        ..state.frame_inputs(View::Whole)
+    };
+
+    let polygons = state.features.polygons();
+    let countries: Vec<_> = sample(polygons)
+        .into_iter()
+        .map(|polygon| &polygon.properties)
+        .collect();
+    let choose_color = |properties: &Properties| {
+        hint::black_box(state.polygon_color_chooser(properties, frame));
+    };
+    let colors = time(polygons.len(), &countries, choose_color, || state.clear_colors());
+
+    let sampled: Vec<_> = places.sampled.iter().collect();
+    let choose_label = |properties: &Properties| {
+        hint::black_box(state.label_style_chooser(properties, frame));
+    };
+    let labels = time(places.count, &sampled, choose_label, || {});
+
+    Results { colors, labels }
+}
+
+/// Times `choose` over `sampled`, of `features` in all, calling `before_pass` before each pass.
+fn time<C, B>(features: usize, sampled: &[&Properties], choose: C, before_pass: B) -> Timing
+where
+    C: Fn(&Properties),
+    B: Fn(),
+{
+    let started = Instant::now();
+    let mut elapsed = Duration::from_secs(0);
+    let mut calls = 0;
+
+    for _ in 0..ITERATIONS {
+        if sampled.is_empty() || started.elapsed() >= TIME_BUDGET {
+            break;
+        }
+        before_pass();
+
+        // Only the calls are timed, not clearing the cache.
+        let pass = Instant::now();
+        for properties in sampled {
+            choose(properties);
+        }
+        elapsed += pass.elapsed();
+        calls += sampled.len();
+    }
+
+    let average_us = if calls > 0 {
+        elapsed.as_secs_f64() * 1e6 / calls as f64
+    } else {
+        0.0
+    };
+    Timing {
+        features,
+        calls,
+        average_us,
+    }
+}
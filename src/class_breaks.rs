@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use formatting;
+use settings::FormattingSettings;
+use MapMode;
+
+/// How much one press moves a break by, as a fraction of its value, since the values a mode
+/// classifies, such as populations, span many orders of magnitude.
+const NUDGE: f64 = 0.05;
+
+/// How many significant digits nudged breaks are rounded to, so that they read as round numbers.
+const SIGNIFICANT_DIGITS: i32 = 3;
+
+/// Class breaks edited by hand in the legend, which replace the ones a mode works out for
+/// itself. By mode, then by the name in the dataset of the property they split.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeBreaks {
+    pub percentile: BTreeMap<String, Vec<f64>>,
+}
+
+impl ModeBreaks {
+    fn values(&self, mode: MapMode) -> Option<&BTreeMap<String, Vec<f64>>> {
+        match mode {
+            MapMode::Percentile => Some(&self.percentile),
+            _ => None,
+        }
+    }
+
+    fn values_mut(&mut self, mode: MapMode) -> Option<&mut BTreeMap<String, Vec<f64>>> {
+        match mode {
+            MapMode::Percentile => Some(&mut self.percentile),
+            _ => None,
+        }
+    }
+
+    /// The breaks edited for `property` in `mode`, if any.
+    pub fn get(&self, mode: MapMode, property: &str) -> Option<&[f64]> {
+        self.values(mode)?.get(property).map(Vec::as_slice)
+    }
+
+    /// Keeps `breaks` as the ones edited for `property` in `mode`. Does nothing if `mode` has no
+    /// breaks.
+    pub fn set(&mut self, mode: MapMode, property: &str, breaks: &[f64]) {
+        if let Some(values) = self.values_mut(mode) {
+            values.insert(property.to_string(), breaks.to_vec());
+        }
+    }
+
+    /// Forgets the breaks edited for `property` in `mode`, so that it works them out again.
+    /// Returns whether there were any.
+    pub fn reset(&mut self, mode: MapMode, property: &str) -> bool {
+        self.values_mut(mode)
+            .and_then(|values| values.remove(property))
+            .is_some()
+    }
+
+    /// Leaves out breaks that are not finite, and puts the rest in order, so that no two cross.
+    pub fn validated(self) -> ModeBreaks {
+        let validate = |values: BTreeMap<String, Vec<f64>>| {
+            values
+                .into_iter()
+                .map(|(property, breaks)| {
+                    let mut breaks: Vec<f64> =
+                        breaks.into_iter().filter(|value| value.is_finite()).collect();
+                    breaks.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                    (property, breaks)
+                })
+                .collect()
+        };
+
+        ModeBreaks {
+            percentile: validate(self.percentile),
+        }
+    }
+}
+
+/// Which class `value` is in under `breaks`, which are in order: how many of them it is at or
+/// above.
+pub fn class(breaks: &[f64], value: f64) -> usize {
+    breaks.iter().filter(|&&limit| value >= limit).count()
+}
+
+/// Moves break `index` of `breaks` up by `steps` presses, or down if `steps` is negative, but no
+/// further than the breaks beside it, or than the lowest and highest values in `range` for the
+/// first and last, so that breaks never cross.
+pub fn nudge(breaks: &mut [f64], index: usize, steps: i32, range: (f64, f64)) {
+    let value = breaks[index];
+    let moved = if value > 0.0 {
+        round_to_significant(value * (1.0 + NUDGE).powi(steps))
+    } else {
+        value + f64::from(steps)
+    };
+
+    let low = if index > 0 {
+        breaks[index - 1]
+    } else {
+        range.0
+    };
+    let high = breaks.get(index + 1).cloned().unwrap_or(range.1);
+    breaks[index] = moved.max(low).min(high);
+}
+
+fn round_to_significant(value: f64) -> f64 {
+    let digits = SIGNIFICANT_DIGITS - 1 - value.abs().log10().floor() as i32;
+    let scale = 10f64.powi(digits);
+    (value * scale).round() / scale
+}
+
+/// What the classes under `breaks` are called in the legend, in order: one more than there are
+/// breaks, as in "Under 1.2M", "1.2M to 5M" and "5M and over".
+pub fn labels(breaks: &[f64], format: FormattingSettings) -> Vec<String> {
+    let value = |value: f64| formatting::count(value, format);
+
+    (0..=breaks.len())
+        .map(|class| match (class.checked_sub(1), breaks.get(class)) {
+            (None, Some(&high)) => format!("Under {}", value(high)),
+            (Some(low), Some(&high)) => format!("{} to {}", value(breaks[low]), value(high)),
+            (Some(low), None) => format!("{} and over", value(breaks[low])),
+            (None, None) => "Every value".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANGE: (f64, f64) = (10_000.0, 1e9);
+
+    fn nudged(index: usize, steps: i32) -> Vec<f64> {
+        let mut breaks = vec![1_000_000.0, 5_000_000.0, 20_000_000.0];
+        nudge(&mut breaks, index, steps, RANGE);
+        breaks
+    }
+
+    #[test]
+    fn nudged_breaks_move_by_a_fraction_and_stay_round() {
+        assert_eq!(nudged(1, 1), [1_000_000.0, 5_250_000.0, 20_000_000.0]);
+        assert_eq!(nudged(1, -1), [1_000_000.0, 4_760_000.0, 20_000_000.0]);
+        assert_eq!(nudged(0, 0), [1_000_000.0, 5_000_000.0, 20_000_000.0]);
+
+        let mut breaks = vec![1_234_567.0];
+        nudge(&mut breaks, 0, 0, RANGE);
+        assert_eq!(breaks, [1_230_000.0]);
+
+        // Breaks at or below zero move by whole steps, since a fraction of them is nothing.
+        let mut breaks = vec![0.0, 10.0];
+        nudge(&mut breaks, 0, 3, (-100.0, 100.0));
+        assert_eq!(breaks, [3.0, 10.0]);
+    }
+
+    #[test]
+    fn nudged_breaks_stop_at_their_neighbors_and_the_range() {
+        assert_eq!(nudged(1, 100), [1_000_000.0, 20_000_000.0, 20_000_000.0]);
+        assert_eq!(nudged(1, -100), [1_000_000.0, 1_000_000.0, 20_000_000.0]);
+        assert_eq!(nudged(0, -1000), [RANGE.0, 5_000_000.0, 20_000_000.0]);
+        assert_eq!(nudged(2, 1000), [1_000_000.0, 5_000_000.0, RANGE.1]);
+    }
+
+    #[test]
+    fn values_at_a_break_are_in_the_class_above_it() {
+        let breaks = [10.0, 20.0];
+        assert_eq!(class(&breaks, 5.0), 0);
+        assert_eq!(class(&breaks, 10.0), 1);
+        assert_eq!(class(&breaks, 19.9), 1);
+        assert_eq!(class(&breaks, 20.0), 2);
+        assert_eq!(class(&[], 20.0), 0);
+    }
+
+    #[test]
+    fn labels_name_every_class() {
+        let format = FormattingSettings::default();
+        assert_eq!(
+            labels(&[1_200_000.0, 5_000_000.0], format),
+            ["Under 1.2M", "1.2M to 5M", "5M and over"]
+        );
+        assert_eq!(labels(&[12_500.0], format), ["Under 12,500", "12,500 and over"]);
+        assert_eq!(labels(&[], format), ["Every value"]);
+    }
+
+    #[test]
+    fn edited_breaks_are_put_in_order() {
+        let mut breaks = ModeBreaks::default();
+        breaks.set(MapMode::Percentile, "POP_EST", &[5.0, ::std::f64::NAN, 1.0]);
+        breaks.set(MapMode::Income, "POP_EST", &[1.0]);
+
+        let breaks = breaks.validated();
+        assert_eq!(breaks.get(MapMode::Percentile, "POP_EST"), Some(&[1.0, 5.0][..]));
+        assert_eq!(breaks.get(MapMode::Income, "POP_EST"), None);
+    }
+}
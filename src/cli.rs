@@ -0,0 +1,1050 @@
+use std::env;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::DateTime;
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind};
+use gaia_assetgen::MAX_LEVEL;
+use piston_window::OpenGL;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use benchmark::Format;
+use exec_script::ExecScript;
+use formatting::{self, NUMBER_STYLES};
+use gl_context::SAMPLE_COUNTS;
+use hi_res;
+use input_recording::ReplaySpeed;
+use letterbox::AspectLock;
+use named_session;
+use profile::Profile;
+use settings::{self, HudSettings, QualitySettings, Settings, StartPosition, Units};
+use {MapMode, Result, ResultExt};
+
+const MODE_NAMES: &str = "terrain, all, oecd, income, exceptional, scripted, daylight, percentile";
+
+const DEFAULT_RENDER_TIMEOUT_SECS: f64 = 30.0;
+
+/// Options given on the command line. Those that are also settings are kept as overrides, to be
+/// applied over the settings file with `apply`.
+#[derive(Debug)]
+pub struct Args {
+    pub config: PathBuf,
+    pub screenshot_dir: PathBuf,
+    /// How many times the window's resolution Shift+F12 saves screenshots at, and
+    /// `render_once` renders at, if given. Also how many times the HUD's size the legend is
+    /// exported at.
+    pub screenshot_scale: Option<u32>,
+    pub hud_scale: Option<f32>,
+    /// Whether to use the high contrast profile, whatever the settings say.
+    pub high_contrast: bool,
+    /// The only OpenGL version to try, instead of falling back through several.
+    pub gl_version: Option<OpenGL>,
+    pub print_config: bool,
+    /// Whether to check everything the demo needs to start, short of opening a window, and
+    /// exit.
+    pub check: bool,
+    /// Whether to describe the properties of the map's features and exit.
+    pub list_properties: bool,
+    /// The property to list every value of, with `list_properties`.
+    pub property: Option<String>,
+    /// Where to also write log messages, for bug reports.
+    pub log_file: Option<PathBuf>,
+    /// Where to export the countries the map mode shows to, as CSV or JSON, and exit.
+    pub export: Option<PathBuf>,
+    /// Whether to run the benchmark and exit, instead of being interactive.
+    pub benchmark: bool,
+    /// The camera path to benchmark with, instead of the built-in one.
+    pub benchmark_path: Option<PathBuf>,
+    pub benchmark_format: Format,
+    /// Whether to not print a summary of the run, or the benchmark's results, when it is over.
+    pub quiet: bool,
+    /// Whether properties that the start mode or labels need, but some features lack, are an
+    /// error instead of a warning.
+    pub strict: bool,
+    /// Where to also write the summary of the run, or the benchmark's results.
+    pub stats_out: Option<PathBuf>,
+    /// Where to write a single rendered frame, instead of being interactive.
+    pub render_once: Option<PathBuf>,
+    /// A GeoJSON file of points to draw over the map, reloaded whenever it changes.
+    pub points: Option<PathBuf>,
+    /// Where to export the legend and scale bar as an image, instead of being interactive.
+    pub export_legend: Option<PathBuf>,
+    /// The time to draw the map as of, instead of the current time.
+    pub clock: Option<SystemTime>,
+    /// How long to wait for tiles to load before rendering anyway with `render_once`.
+    pub render_timeout: Duration,
+    /// The named session to start in, instead of where the last run ended.
+    pub session: Option<String>,
+    /// Where to record every frame drawn to, from the start.
+    pub record_frames: Option<PathBuf>,
+    /// Where to record every event to.
+    pub record_input: Option<PathBuf>,
+    /// A recording to replay instead of handling input.
+    pub replay_input: Option<PathBuf>,
+    pub replay_speed: ReplaySpeed,
+    /// Commands to run once the demo has started, given with `--exec`.
+    pub exec: Option<ExecScript>,
+    /// The aspect ratio to letterbox the map to, whatever the window's.
+    pub lock_aspect: Option<AspectLock>,
+    width: Option<u32>,
+    height: Option<u32>,
+    title: Option<String>,
+    vsync: Option<bool>,
+    samples: Option<u8>,
+    srgb: Option<bool>,
+    fps_limit: Option<u32>,
+    window_pos: Option<[i32; 2]>,
+    monitor: Option<usize>,
+    fullscreen: bool,
+    mode: Option<MapMode>,
+    labels: bool,
+    background: Option<[f32; 3]>,
+    stars: Option<bool>,
+    start: Option<StartPosition>,
+    /// The position and mode given with `--view`.
+    view: Option<(StartPosition, Option<MapMode>)>,
+    assets_dir: Option<PathBuf>,
+    font: Option<PathBuf>,
+    font_fallback: Option<PathBuf>,
+    /// The index in `NUMBER_STYLES` of the style given with `--number-format`.
+    number_style: Option<usize>,
+    compact_numbers: Option<bool>,
+    units: Option<Units>,
+    adaptive_quality: Option<bool>,
+    replay_buffer: Option<bool>,
+    min_level: Option<u8>,
+    max_level: Option<u8>,
+    render_scale: Option<f32>,
+}
+
+impl Args {
+    pub fn from_env() -> Result<Args> {
+        let defaults = Settings::default();
+        let (min_scale, max_scale) = HudSettings::SCALE_RANGE;
+        let default_config = settings::default_path();
+
+        let width_help = format!("Window width, in points [default: {}]", defaults.window.width);
+        let height_help = format!("Window height, in points [default: {}]", defaults.window.height);
+        let title_help = format!("Window title [default: {}]", defaults.window.title);
+        let mode_help = format!("Map mode to start in: {} [default: terrain]", MODE_NAMES);
+        let font_help =
+            format!("Font to draw the HUD with [default: {}]", defaults.hud.font.display());
+        let config_help = format!(
+            "Settings file to read and save [default: {}]",
+            default_config.display()
+        );
+        let render_timeout_help = format!(
+            "Longest to wait for tiles with --render-once, or for each --exec command to settle, \
+             before going on anyway [default: {}]",
+            DEFAULT_RENDER_TIMEOUT_SECS
+        );
+        let styles: Vec<String> =
+            NUMBER_STYLES.iter().map(|style| format!("\"{}\"", style.0)).collect();
+        let number_format_help = format!(
+            "How to write numbers, as 1234.5 would be: {} [default: from settings]",
+            styles.join(", ")
+        );
+        let hud_scale_help = format!(
+            "How much larger to draw the HUD, from {} to {} [default: from settings]",
+            min_scale, max_scale
+        );
+
+        let (min_render_scale, max_render_scale) = QualitySettings::RENDER_SCALE_RANGE;
+        let render_scale_help = format!(
+            "Fraction of the window's resolution to draw the map at, from {} to {} \
+             [default: from settings]",
+            min_render_scale, max_render_scale
+        );
+        let min_level_help = format!(
+            "Least detailed level to draw, from 0 to {} [default: from settings]",
+            MAX_LEVEL
+        );
+        let (min_screenshot_scale, max_screenshot_scale) = hi_res::SCALE_RANGE;
+        let screenshot_scale_help = format!(
+            "How many times the window's resolution Shift+F12 saves screenshots at, and \
+             --render-once renders at, and the legend's scale with Shift+L and --export-legend, \
+             from {} to {} [default: {} for Shift+F12 and the legend]",
+            min_screenshot_scale,
+            max_screenshot_scale,
+            hi_res::DEFAULT_SCALE
+        );
+        let max_level_help = format!(
+            "Most detailed level to draw, from 0 to {} [default: from settings]",
+            MAX_LEVEL
+        );
+
+        let app = App::new("gaia_demo")
+            .about("An interactive map of the world")
+            .setting(AppSettings::ColorNever)
+            .arg(Arg::with_name("width").long("width").value_name("POINTS").help(&width_help))
+            .arg(Arg::with_name("height").long("height").value_name("POINTS").help(&height_help))
+            .arg(Arg::with_name("title").long("title").value_name("TITLE").help(&title_help))
+            .arg(
+                Arg::with_name("fullscreen")
+                    .long("fullscreen")
+                    .help("Start in fullscreen [default: off]"),
+            )
+            .arg(
+                Arg::with_name("lock-aspect")
+                    .long("lock-aspect")
+                    .value_name("W:H")
+                    .help(
+                        "Keep the map at this aspect ratio, such as 16:9, with bars filling the \
+                         rest of the window; Shift+F11 turns it off and on [default: off]",
+                    ),
+            )
+            .arg(
+                Arg::with_name("vsync")
+                    .long("vsync")
+                    .value_name("on|off")
+                    .help("Wait for the display's refresh before each frame [default: off]"),
+            )
+            .arg(
+                Arg::with_name("samples")
+                    .long("samples")
+                    .value_name("0|2|4|8")
+                    .help("Multisampling samples per pixel [default: 0]"),
+            )
+            .arg(
+                Arg::with_name("srgb")
+                    .long("srgb")
+                    .value_name("on|off")
+                    .help("Ask for an sRGB framebuffer [default: on]"),
+            )
+            .arg(
+                Arg::with_name("fps-limit")
+                    .long("fps-limit")
+                    .value_name("FPS")
+                    .help("Most frames to draw per second, or 0 for no limit [default: 0]"),
+            )
+            .arg(
+                Arg::with_name("gl-version")
+                    .long("gl-version")
+                    .value_name("VERSION")
+                    .help("Only try this OpenGL version, such as 3.3 [default: from settings]"),
+            )
+            .arg(
+                Arg::with_name("monitor")
+                    .long("monitor")
+                    .value_name("INDEX")
+                    .help("Monitor to open the window and go fullscreen on [default: the current]"),
+            )
+            .arg(
+                Arg::with_name("window-pos")
+                    .long("window-pos")
+                    .value_name("X,Y")
+                    .allow_hyphen_values(true)
+                    .help("Where the window's top-left corner opens [default: where it last was]"),
+            )
+            .arg(Arg::with_name("mode").long("mode").value_name("NAME").help(&mode_help))
+            .arg(
+                Arg::with_name("labels")
+                    .long("labels")
+                    .help("Start with labels shown [default: off]"),
+            )
+            .arg(
+                Arg::with_name("background")
+                    .long("background")
+                    .value_name("R,G,B")
+                    .help("Color behind the map, each from 0 to 1 [default: 0.3,0.3,0.3]"),
+            )
+            .arg(
+                Arg::with_name("stars")
+                    .long("stars")
+                    .value_name("on|off")
+                    .help("Draw stars behind the map [default: off]"),
+            )
+            .arg(
+                Arg::with_name("start")
+                    .long("start")
+                    .value_name("LAT,LON,HEIGHT")
+                    .allow_hyphen_values(true)
+                    .help("Where the camera starts [default: zoomed out, at 90\u{b0}S 180\u{b0}W]"),
+            )
+            .arg(
+                Arg::with_name("assets-dir")
+                    .long("assets-dir")
+                    .value_name("DIR")
+                    .help("The assets directory, which must be named `assets` [default: assets]"),
+            )
+            .arg(Arg::with_name("font").long("font").value_name("FILE").help(&font_help))
+            .arg(
+                Arg::with_name("font-fallback")
+                    .long("font-fallback")
+                    .value_name("FILE")
+                    .help("Font to draw HUD text with that the font cannot, such as a CJK font"),
+            )
+            .arg(Arg::with_name("config").long("config").value_name("FILE").help(&config_help))
+            .arg(
+                Arg::with_name("log-file")
+                    .long("log-file")
+                    .value_name("FILE")
+                    .help("Also write log messages to this file, filtered by RUST_LOG"),
+            )
+            .arg(
+                Arg::with_name("export")
+                    .long("export")
+                    .value_name("FILE")
+                    .conflicts_with_all(&["benchmark", "render-once"])
+                    .help(
+                        "Write the countries the map mode shows to this .csv or .json file, \
+                         without opening a window, and exit",
+                    ),
+            )
+            .arg(
+                Arg::with_name("benchmark")
+                    .long("benchmark")
+                    .help("Fly the camera along a fixed path, print frame statistics and exit"),
+            )
+            .arg(
+                Arg::with_name("benchmark-path")
+                    .long("benchmark-path")
+                    .value_name("FILE")
+                    .requires("benchmark")
+                    .help("Benchmark with the camera path in this TOML file [default: built-in]"),
+            )
+            .arg(
+                Arg::with_name("benchmark-format")
+                    .long("benchmark-format")
+                    .value_name("json|csv")
+                    .requires("benchmark")
+                    .help("How to print the benchmark's results [default: json]"),
+            )
+            .arg(
+                Arg::with_name("quiet")
+                    .long("quiet")
+                    .help("Do not print a summary of the run, or the benchmark's results, at exit"),
+            )
+            .arg(
+                Arg::with_name("strict")
+                    .long("strict")
+                    .help("Fail if the dataset lacks properties the start mode or labels need"),
+            )
+            .arg(
+                Arg::with_name("stats-out")
+                    .long("stats-out")
+                    .value_name("FILE")
+                    .help("Also write the run summary, or the benchmark's results, to this file"),
+            )
+            .arg(
+                Arg::with_name("render-once")
+                    .long("render-once")
+                    .value_name("FILE")
+                    .conflicts_with("benchmark")
+                    .help("Render one frame to this PNG once the tiles have loaded, and exit"),
+            )
+            .arg(
+                Arg::with_name("points")
+                    .long("points")
+                    .value_name("FILE")
+                    .help("Draw the points in this GeoJSON file, reloading it when it changes"),
+            )
+            .arg(
+                Arg::with_name("export-legend")
+                    .long("export-legend")
+                    .value_name("FILE")
+                    .conflicts_with_all(&["benchmark", "render-once"])
+                    .help("Write the legend and scale bar to this transparent PNG, and exit"),
+            )
+            .arg(
+                Arg::with_name("view")
+                    .long("view")
+                    .value_name("\"lat=.. lon=.. height=.. [mode=..]\"")
+                    .allow_hyphen_values(true)
+                    .requires("render-once")
+                    .help("Where the camera is, and the map mode, for --render-once"),
+            )
+            .arg(
+                Arg::with_name("render-timeout")
+                    .long("render-timeout")
+                    .value_name("SECONDS")
+                    .help(&render_timeout_help),
+            )
+            .arg(
+                Arg::with_name("exec")
+                    .long("exec")
+                    .value_name("\"COMMAND; ..\"")
+                    .conflicts_with_all(&["benchmark", "render-once", "replay-input"])
+                    .help(
+                        "Run these commands once started: mode NAME, labels on|off, \
+                         goto LAT,LON[,HEIGHT], wait SECONDS, animate MODE.PARAMETER FROM TO \
+                         SECONDS [linear|ease_in_out] [once|loop|bounce], stop_animation, \
+                         wait animation, screenshot FILE, quit, or any action such as \
+                         toggle_graticule",
+                    ),
+            )
+            .arg(
+                Arg::with_name("clock")
+                    .long("clock")
+                    .value_name("UNIX_SECONDS")
+                    .help("Draw the map as of this time, so that renders repeat [default: now]"),
+            )
+            .arg(
+                Arg::with_name("datetime")
+                    .long("datetime")
+                    .value_name("RFC3339")
+                    .conflicts_with("clock")
+                    .help("Like --clock, as a date and time such as 2024-06-21T12:00:00Z"),
+            )
+            .arg(
+                Arg::with_name("session")
+                    .long("session")
+                    .value_name("NAME")
+                    .conflicts_with("benchmark")
+                    .help("Start in this session, saved with F4, not where the last run ended"),
+            )
+            .arg(
+                Arg::with_name("record-frames")
+                    .long("record-frames")
+                    .value_name("DIR")
+                    .conflicts_with("render-once")
+                    .help("Record every frame drawn to PNGs in this directory, from the start"),
+            )
+            .arg(
+                Arg::with_name("record-input")
+                    .long("record-input")
+                    .value_name("FILE")
+                    .help("Record every event to this file, to replay with --replay-input"),
+            )
+            .arg(
+                Arg::with_name("replay-input")
+                    .long("replay-input")
+                    .value_name("FILE")
+                    .conflicts_with_all(&["record-input", "benchmark", "render-once"])
+                    .help("Replay the events recorded in this file instead of handling input"),
+            )
+            .arg(
+                Arg::with_name("replay-speed")
+                    .long("replay-speed")
+                    .value_name("SPEED|max")
+                    .requires("replay-input")
+                    .help("How many times real time to replay at, or max [default: 1]"),
+            )
+            .arg(
+                Arg::with_name("list-properties")
+                    .long("list-properties")
+                    .help("Print the properties of countries and places, with examples, and exit"),
+            )
+            .arg(
+                Arg::with_name("property")
+                    .long("property")
+                    .value_name("KEY")
+                    .requires("list-properties")
+                    .help("With --list-properties, print every value of this property instead"),
+            )
+            .arg(
+                Arg::with_name("check")
+                    .long("check")
+                    .help("Check the settings, assets, font and dataset, without opening a window"),
+            )
+            .arg(
+                Arg::with_name("print-config")
+                    .long("print-config")
+                    .help("Print the settings, with command-line options applied, and exit"),
+            )
+            .arg(
+                Arg::with_name("screenshot-dir")
+                    .long("screenshot-dir")
+                    .value_name("DIR")
+                    .help("Where F12 saves screenshots [default: screenshots]"),
+            )
+            .arg(
+                Arg::with_name("screenshot-scale")
+                    .long("screenshot-scale")
+                    .value_name("N")
+                    .help(&screenshot_scale_help),
+            )
+            .arg(
+                Arg::with_name("adaptive-quality")
+                    .long("adaptive-quality")
+                    .value_name("on|off")
+                    .help("Draw less detail while frames are slow [default: from settings]"),
+            )
+            .arg(
+                Arg::with_name("replay-buffer")
+                    .long("replay-buffer")
+                    .value_name("on|off")
+                    .help("Keep recent frames to save as a GIF with F9 [default: from settings]"),
+            )
+            .arg(
+                Arg::with_name("render-scale")
+                    .long("render-scale")
+                    .value_name("SCALE")
+                    .help(&render_scale_help),
+            )
+            .arg(
+                Arg::with_name("min-level")
+                    .long("min-level")
+                    .value_name("LEVEL")
+                    .help(&min_level_help),
+            )
+            .arg(
+                Arg::with_name("max-level")
+                    .long("max-level")
+                    .value_name("LEVEL")
+                    .help(&max_level_help),
+            )
+            .arg(
+                Arg::with_name("number-format")
+                    .long("number-format")
+                    .value_name("STYLE")
+                    .help(&number_format_help),
+            )
+            .arg(
+                Arg::with_name("compact-numbers")
+                    .long("compact-numbers")
+                    .value_name("on|off")
+                    .help("Shorten large counts, as in 1.2M [default: from settings]"),
+            )
+            .arg(
+                Arg::with_name("units")
+                    .long("units")
+                    .value_name("metric|imperial")
+                    .help("Units to show distances in [default: from settings]"),
+            )
+            .arg(
+                Arg::with_name("hud-scale")
+                    .long("hud-scale")
+                    .value_name("SCALE")
+                    .help(&hud_scale_help),
+            )
+            .arg(
+                Arg::with_name("high-contrast")
+                    .long("high-contrast")
+                    .help("Use the high contrast profile [default: from settings]"),
+            );
+
+        let matches = match app.get_matches_safe() {
+            Ok(matches) => matches,
+            Err(e) => match e.kind {
+                ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+                _ => {
+                    let message = e.message.lines().next().unwrap_or("");
+                    bail!("{}", message.trim_start_matches("error: "));
+                }
+            },
+        };
+
+        let hud_scale: Option<f32> = parse(&matches, "hud-scale")?;
+        if let Some(scale) = hud_scale {
+            if !(min_scale <= scale && scale <= max_scale) {
+                bail!("Invalid --hud-scale {}: must be from {} to {}", scale, min_scale, max_scale);
+            }
+        }
+
+        let width: Option<u32> = parse(&matches, "width")?;
+        let height: Option<u32> = parse(&matches, "height")?;
+        if width == Some(0) || height == Some(0) {
+            bail!("Invalid window size: --width and --height must be at least 1");
+        }
+
+        let samples: Option<u8> = parse(&matches, "samples")?;
+        if let Some(samples) = samples {
+            if !SAMPLE_COUNTS.contains(&samples) {
+                bail!("Invalid --samples {}: must be 0, 2, 4 or 8", samples);
+            }
+        }
+
+        let render_scale: Option<f32> = parse(&matches, "render-scale")?;
+        if let Some(scale) = render_scale {
+            if !(min_render_scale <= scale && scale <= max_render_scale) {
+                bail!(
+                    "Invalid --render-scale {}: must be from {} to {}",
+                    scale,
+                    min_render_scale,
+                    max_render_scale
+                );
+            }
+        }
+
+        let screenshot_scale: Option<u32> = parse(&matches, "screenshot-scale")?;
+        if let Some(scale) = screenshot_scale {
+            if scale < min_screenshot_scale || scale > max_screenshot_scale {
+                bail!(
+                    "Invalid --screenshot-scale {}: must be from {} to {}",
+                    scale,
+                    min_screenshot_scale,
+                    max_screenshot_scale
+                );
+            }
+        }
+
+        let min_level: Option<u8> = parse(&matches, "min-level")?;
+        let max_level: Option<u8> = parse(&matches, "max-level")?;
+        for &(name, level) in &[("min-level", min_level), ("max-level", max_level)] {
+            if let Some(level) = level {
+                if level > MAX_LEVEL {
+                    bail!("Invalid --{} {}: must be from 0 to {}", name, level, MAX_LEVEL);
+                }
+            }
+        }
+        if let (Some(min_level), Some(max_level)) = (min_level, max_level) {
+            if min_level > max_level {
+                bail!("Invalid levels: --min-level must not be more than --max-level");
+            }
+        }
+
+        let mode = match matches.value_of("mode") {
+            Some(name) => Some(parse_mode("--mode", name)?),
+            None => None,
+        };
+
+        let window_pos = match matches.value_of("window-pos") {
+            Some(position) => Some(parse_window_pos(position)?),
+            None => None,
+        };
+
+        let background = match matches.value_of("background") {
+            Some(color) => Some(parse_background(color)?),
+            None => None,
+        };
+
+        let start = match matches.value_of("start") {
+            Some(start) => Some(parse_start(start)?),
+            None => None,
+        };
+
+        let view = match matches.value_of("view") {
+            Some(view) => Some(parse_view(view)?),
+            None => None,
+        };
+
+        let session = matches.value_of("session").map(str::to_string);
+        if let Some(ref name) = session {
+            named_session::check_name(name).chain_err(|| "Invalid --session")?;
+        }
+
+        let clock: Option<u64> = parse(&matches, "clock")?;
+        let clock = match matches.value_of("datetime") {
+            Some(text) => {
+                let datetime = DateTime::parse_from_rfc3339(text).map_err(|e| {
+                    format!("Invalid --datetime {:?}: {}, expected RFC 3339", text, e)
+                })?;
+                if datetime.timestamp() < 0 {
+                    bail!("Invalid --datetime {:?}: must not be before 1970", text);
+                }
+                Some(datetime.timestamp() as u64)
+            }
+            None => clock,
+        };
+
+        let exec = match matches.value_of("exec") {
+            Some(text) => {
+                let dir = env::current_dir().unwrap_or_default();
+                Some(ExecScript::parse(text, &dir)?)
+            }
+            None => None,
+        };
+
+        let render_timeout: Option<f64> = parse(&matches, "render-timeout")?;
+        if render_timeout.is_some() && !matches.is_present("render-once") && exec.is_none() {
+            bail!("--render-timeout is only used with --render-once or --exec");
+        }
+        let render_timeout = render_timeout.unwrap_or(DEFAULT_RENDER_TIMEOUT_SECS);
+        if !(render_timeout.is_finite() && render_timeout >= 0.0) {
+            bail!("Invalid --render-timeout {}: must be 0 or more", render_timeout);
+        }
+
+        let benchmark_format = match matches.value_of("benchmark-format") {
+            Some("json") | None => Format::Json,
+            Some("csv") => Format::Csv,
+            Some(format) => {
+                bail!("Invalid --benchmark-format {:?}: expected \"json\" or \"csv\"", format)
+            }
+        };
+
+        let replay_speed = match matches.value_of("replay-speed") {
+            Some("max") => ReplaySpeed::Max,
+            Some(speed) => match speed.parse::<f64>() {
+                Ok(speed) if speed.is_finite() && speed > 0.0 => ReplaySpeed::Scaled(speed),
+                _ => bail!("Invalid --replay-speed {:?}: expected a number above 0, or max", speed),
+            },
+            None => ReplaySpeed::Scaled(1.0),
+        };
+
+        let number_style = match matches.value_of("number-format") {
+            Some(style) => match NUMBER_STYLES.iter().position(|&(name, _, _)| name == style) {
+                Some(index) => Some(index),
+                None => bail!(
+                    "Invalid --number-format {:?}: expected one of {}",
+                    style,
+                    styles.join(", ")
+                ),
+            },
+            None => None,
+        };
+
+        let units = match matches.value_of("units") {
+            Some("metric") => Some(Units::Metric),
+            Some("imperial") => Some(Units::Imperial),
+            Some(units) => {
+                bail!("Invalid --units {:?}: expected \"metric\" or \"imperial\"", units)
+            }
+            None => None,
+        };
+
+        let config = matches.value_of("config").map_or(default_config, absolute);
+        let screenshot_dir = matches.value_of("screenshot-dir").unwrap_or("screenshots");
+
+        Ok(Args {
+            config,
+            screenshot_dir: absolute(screenshot_dir),
+            screenshot_scale,
+            hud_scale,
+            high_contrast: matches.is_present("high-contrast"),
+            gl_version: parse(&matches, "gl-version")?,
+            print_config: matches.is_present("print-config"),
+            check: matches.is_present("check"),
+            list_properties: matches.is_present("list-properties"),
+            property: matches.value_of("property").map(str::to_string),
+            log_file: matches.value_of("log-file").map(absolute),
+            export: matches.value_of("export").map(absolute),
+            benchmark: matches.is_present("benchmark"),
+            benchmark_path: matches.value_of("benchmark-path").map(absolute),
+            benchmark_format,
+            quiet: matches.is_present("quiet"),
+            strict: matches.is_present("strict"),
+            stats_out: matches.value_of("stats-out").map(absolute),
+            render_once: matches.value_of("render-once").map(absolute),
+            points: matches.value_of("points").map(absolute),
+            export_legend: matches.value_of("export-legend").map(absolute),
+            clock: clock.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            render_timeout: Duration::from_millis((render_timeout * 1000.0) as u64),
+            session,
+            record_frames: matches.value_of("record-frames").map(absolute),
+            record_input: matches.value_of("record-input").map(absolute),
+            replay_input: matches.value_of("replay-input").map(absolute),
+            replay_speed,
+            exec,
+            lock_aspect: parse(&matches, "lock-aspect")?,
+            width,
+            height,
+            title: matches.value_of("title").map(str::to_string),
+            vsync: parse_on_off(&matches, "vsync")?,
+            samples,
+            srgb: parse_on_off(&matches, "srgb")?,
+            fps_limit: parse(&matches, "fps-limit")?,
+            window_pos,
+            monitor: parse(&matches, "monitor")?,
+            fullscreen: matches.is_present("fullscreen"),
+            mode,
+            labels: matches.is_present("labels"),
+            background,
+            stars: parse_on_off(&matches, "stars")?,
+            start,
+            view,
+            assets_dir: matches.value_of("assets-dir").map(absolute),
+            font: matches.value_of("font").map(absolute),
+            font_fallback: matches.value_of("font-fallback").map(absolute),
+            number_style,
+            compact_numbers: parse_on_off(&matches, "compact-numbers")?,
+            units,
+            adaptive_quality: parse_on_off(&matches, "adaptive-quality")?,
+            replay_buffer: parse_on_off(&matches, "replay-buffer")?,
+            min_level,
+            max_level,
+            render_scale,
+        })
+    }
+
+    /// Overrides `settings`, from the settings file, with whatever was given on the command line.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(width) = self.width {
+            settings.window.width = width;
+        }
+        if let Some(height) = self.height {
+            settings.window.height = height;
+        }
+        if let Some(ref title) = self.title {
+            settings.window.title = title.clone();
+        }
+        if self.fullscreen {
+            settings.window.fullscreen = true;
+        }
+        if let Some(vsync) = self.vsync {
+            settings.window.vsync = vsync;
+        }
+        if let Some(samples) = self.samples {
+            settings.window.samples = samples;
+        }
+        if let Some(srgb) = self.srgb {
+            settings.window.srgb = srgb;
+        }
+        if let Some(fps_limit) = self.fps_limit {
+            settings.window.fps_limit = fps_limit;
+        }
+        if self.window_pos.is_some() {
+            settings.window.position = self.window_pos;
+        }
+        if self.monitor.is_some() {
+            settings.window.monitor = self.monitor;
+        }
+        if let Some(mode) = self.mode {
+            settings.modes.default = mode;
+        }
+        if self.labels {
+            settings.labels.enabled = true;
+        }
+        if let Some(color) = self.background {
+            settings.sky.color = color;
+        }
+        if let Some(stars) = self.stars {
+            settings.sky.stars = stars;
+        }
+        if let Some(ref dir) = self.assets_dir {
+            settings.paths.assets_dir = dir.clone();
+        }
+        if let Some(ref font) = self.font {
+            settings.hud.font = font.clone();
+        }
+        if let Some(ref font) = self.font_fallback {
+            settings.hud.font_fallback = Some(font.clone());
+        }
+        if let Some(scale) = self.hud_scale {
+            settings.hud.scale = scale;
+        }
+        if self.high_contrast {
+            settings.profiles.active = Some(Profile::HighContrast);
+        }
+        if let Some(index) = self.number_style {
+            settings.formatting = formatting::with_style(settings.formatting, index);
+        }
+        if let Some(compact) = self.compact_numbers {
+            settings.formatting.compact_numbers = compact;
+        }
+        if let Some(units) = self.units {
+            settings.formatting.units = units;
+        }
+        if self.start.is_some() {
+            settings.camera.start = self.start;
+        }
+        if let Some((start, mode)) = self.view {
+            settings.camera.start = Some(start);
+            if let Some(mode) = mode {
+                settings.modes.default = mode;
+            }
+        }
+
+        if let Some(replay_buffer) = self.replay_buffer {
+            settings.diagnostics.replay_buffer = replay_buffer;
+        }
+        if let Some(adaptive) = self.adaptive_quality {
+            settings.quality.adaptive = adaptive;
+        }
+        if let Some(level) = self.min_level {
+            settings.quality.min_level = level;
+        }
+        if let Some(level) = self.max_level {
+            settings.quality.max_level = level;
+        }
+        if let Some(scale) = self.render_scale {
+            settings.quality.render_scale = scale;
+        }
+
+        // Nothing may hold frames back while benchmarking.
+        if self.benchmark {
+            settings.window.vsync = false;
+            settings.window.fps_limit = 0;
+            settings.window.idle_fps = 0;
+        }
+
+        // Frame times differ from run to run, so what is drawn must not depend on them.
+        if self.benchmark
+            || self.render_once.is_some()
+            || self.record_input.is_some()
+            || self.replay_input.is_some()
+            || self.exec.is_some()
+        {
+            settings.quality.adaptive = false;
+            settings.quality.hud_budget_ms = 0.0;
+        }
+    }
+}
+
+/// The value of the option `name`, if given.
+fn parse<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match matches.value_of(name) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("Invalid --{} {:?}: {}", name, value, e).into()),
+        None => Ok(None),
+    }
+}
+
+/// The value of the on/off switch `name`, if given.
+fn parse_on_off(matches: &ArgMatches, name: &str) -> Result<Option<bool>> {
+    match matches.value_of(name) {
+        Some("on") => Ok(Some(true)),
+        Some("off") => Ok(Some(false)),
+        Some(value) => bail!("Invalid --{} {:?}: expected \"on\" or \"off\"", name, value),
+        None => Ok(None),
+    }
+}
+
+/// The map mode named `name`, given with `option`. Names are not case sensitive.
+fn parse_mode(option: &str, name: &str) -> Result<MapMode> {
+    let lowercase = name.to_lowercase();
+    let deserializer: StrDeserializer<ValueError> = lowercase.as_str().into_deserializer();
+
+    MapMode::deserialize(deserializer).map_err(|_| {
+        format!("Invalid {} {:?}: expected one of {}", option, name, MODE_NAMES).into()
+    })
+}
+
+fn parse_window_pos(position: &str) -> Result<[i32; 2]> {
+    let parts = position
+        .split(',')
+        .map(|part| part.trim().parse::<i32>())
+        .collect::<::std::result::Result<Vec<_>, _>>();
+
+    match parts.as_ref().map(|parts| &parts[..]) {
+        Ok(&[x, y]) => Ok([x, y]),
+        _ => bail!("Invalid --window-pos {:?}: expected \"X,Y\"", position),
+    }
+}
+
+fn parse_background(color: &str) -> Result<[f32; 3]> {
+    let parts = color
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<::std::result::Result<Vec<_>, _>>();
+
+    match parts.as_ref().map(|parts| &parts[..]) {
+        Ok(&[r, g, b]) if [r, g, b].iter().all(|c| (0.0..=1.0).contains(c)) => Ok([r, g, b]),
+        _ => bail!("Invalid --background {:?}: expected \"R,G,B\", each from 0 to 1", color),
+    }
+}
+
+fn parse_start(start: &str) -> Result<StartPosition> {
+    let invalid = || format!("Invalid --start {:?}: expected \"LAT,LON,HEIGHT\"", start);
+
+    let parts = start
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<::std::result::Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+
+    match parts[..] {
+        [latitude, longitude, height] => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                bail!(
+                    "Invalid --start {:?}: latitude must be from -90 to 90, and longitude from \
+                     -180 to 180",
+                    start
+                );
+            }
+
+            Ok(StartPosition {
+                latitude,
+                longitude,
+                height,
+            })
+        }
+        _ => Err(invalid().into()),
+    }
+}
+
+/// Parses `--view`: space-separated `lat=`, `lon=` and `height=`, and optionally `mode=`.
+fn parse_view(view: &str) -> Result<(StartPosition, Option<MapMode>)> {
+    let invalid = || format!("Invalid --view {:?}: expected \"lat=.. lon=.. height=..\"", view);
+
+    let (mut latitude, mut longitude, mut height, mut mode) = (None, None, None, None);
+    for part in view.split_whitespace() {
+        let mut key_value = part.splitn(2, '=');
+        let (key, value) = match (key_value.next(), key_value.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => return Err(invalid().into()),
+        };
+
+        let number = || {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid --view {:?}: `{}` is not a number", view, key))
+        };
+        match key {
+            "lat" => latitude = Some(number()?),
+            "lon" => longitude = Some(number()?),
+            "height" => height = Some(number()?),
+            "mode" => mode = Some(parse_mode("--view mode", value)?),
+            _ => bail!("Invalid --view {:?}: unknown key `{}`", view, key),
+        }
+    }
+
+    match (latitude, longitude, height) {
+        (Some(latitude), Some(longitude), Some(height)) => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                bail!(
+                    "Invalid --view {:?}: lat must be from -90 to 90, and lon from -180 to 180",
+                    view
+                );
+            }
+
+            let start = StartPosition {
+                latitude,
+                longitude,
+                height,
+            };
+            Ok((start, mode))
+        }
+        _ => Err(invalid().into()),
+    }
+}
+
+/// `path` relative to the directory the demo was started in, so that it still refers to the same
+/// place after changing to the assets directory's parent.
+fn absolute(path: &str) -> PathBuf {
+    match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_give_the_camera_and_optionally_the_mode() {
+        let paris = StartPosition {
+            latitude: 48.85,
+            longitude: 2.35,
+            height: 0.2,
+        };
+
+        assert_eq!(parse_view("lat=48.85 lon=2.35 height=0.2").unwrap(), (paris, None));
+        assert_eq!(
+            parse_view("  height=0.2 mode=Income lat=48.85   lon=2.35 ").unwrap(),
+            (paris, Some(MapMode::Income))
+        );
+    }
+
+    #[test]
+    fn invalid_views_say_what_is_wrong() {
+        let error = |view: &str| parse_view(view).unwrap_err().to_string();
+
+        assert!(error("lat=48.85 lon=2.35").contains("expected"));
+        assert!(error("lat=48.85 lon=2.35 height").contains("expected"));
+        assert!(error("lat=north lon=2.35 height=0.2").contains("`lat` is not a number"));
+        assert!(error("lat=48.85 lon=2.35 height=0.2 zoom=3").contains("unknown key `zoom`"));
+        assert!(error("lat=95 lon=2.35 height=0.2").contains("from -90 to 90"));
+        assert!(error("lat=0 lon=0 height=0.2 mode=sepia").contains(MODE_NAMES));
+    }
+
+    #[test]
+    fn starts_are_three_numbers() {
+        let start = parse_start("48.85, 2.35, 0.2").unwrap();
+        assert_eq!((start.latitude, start.longitude, start.height), (48.85, 2.35, 0.2));
+
+        assert!(parse_start("48.85,2.35").is_err());
+        assert!(parse_start("48.85,181,0.2").is_err());
+    }
+}
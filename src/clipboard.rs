@@ -0,0 +1,57 @@
+use arboard;
+
+use {Result, ResultExt};
+
+/// Somewhere copied text goes. The demo copies to the system clipboard, and headless runs to
+/// `MemoryClipboard`, since there may be no clipboard to open where they run.
+pub trait TextClipboard {
+    /// Replaces what is on the clipboard with `text`.
+    fn set_text(&mut self, text: String) -> Result<()>;
+}
+
+/// The system clipboard. It is only opened the first time something is copied, since that fails
+/// where there is none, such as under some Wayland compositors, and it is then kept open, since
+/// on X11 what was copied is only pasteable for as long as the demo holds on to it.
+pub struct Clipboard {
+    opened: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Clipboard {
+        Clipboard { opened: None }
+    }
+}
+
+impl TextClipboard for Clipboard {
+    fn set_text(&mut self, text: String) -> Result<()> {
+        let mut clipboard = match self.opened.take() {
+            Some(clipboard) => clipboard,
+            None => arboard::Clipboard::new().chain_err(|| "No clipboard is available")?,
+        };
+        let copied = clipboard
+            .set_text(text)
+            .chain_err(|| "Could not copy to the clipboard");
+        self.opened = Some(clipboard);
+
+        copied
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Clipboard {
+        Clipboard::new()
+    }
+}
+
+/// A clipboard of its own, which only keeps the last text copied to it, for headless runs.
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    pub last: Option<String>,
+}
+
+impl TextClipboard for MemoryClipboard {
+    fn set_text(&mut self, text: String) -> Result<()> {
+        self.last = Some(text);
+        Ok(())
+    }
+}
@@ -0,0 +1,464 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use gaia_assetgen::Properties;
+use hsl::HSL;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::Value;
+
+use {Result, ResultExt};
+
+/// How often the script's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How many animation ticks `ColorScript::clock` advances by each second.
+pub const TICKS_PER_SEC: u64 = 10;
+
+/// The function each country's color is read from.
+const COLOR_FN: &str = "color";
+
+/// The constant a script sets to `true` to say its colors do not depend on `clock()`, so that they
+/// are worked out once per country instead of every tick.
+const TIME_INVARIANT: &str = "TIME_INVARIANT";
+
+/// The opacity of colors the script gives without one, the same as the built-in modes'.
+const DEFAULT_ALPHA: u8 = 100;
+
+/// What a country is drawn with when the script fails for it.
+pub const ERROR_COLOR: [u8; 4] = [128, 128, 128, DEFAULT_ALPHA];
+
+/// The most rhai operations coloring every country may take each time the map is drawn, so
+/// that a slow script, or one stuck in a loop, fails instead of freezing the frame. Countries
+/// colored once it is used up get `ERROR_COLOR`.
+const MAX_OPERATIONS_PER_DRAW: u64 = 2_000_000;
+
+/// A script defining `fn color(props)`, which scripted mode colors each country with. `props` is
+/// a map of the country's properties, and the result is `[r, g, b]` or `[r, g, b, a]` from 0 to
+/// 255, or `()` to leave the country uncolored. The script can also call:
+///
+/// - `hsl(h, s, l)`, with `h` in degrees and `s` and `l` from 0 to 1,
+/// - `ramp(colors, t)`, the color `t` of the way along evenly spaced `colors`, and
+/// - `clock()`, the animation clock in seconds.
+///
+/// The script is reloaded whenever its file is changed.
+pub struct ColorScript {
+    path: PathBuf,
+    /// When the file was last modified, as of the last check. `None` until it is first loaded.
+    modified: Option<SystemTime>,
+    last_poll: Option<Instant>,
+    engine: Engine,
+    /// The compiled script, or `None` until it first compiles.
+    ast: Option<AST>,
+    time_invariant: bool,
+    /// The animation clock, in ticks, as of the country being colored.
+    tick: Rc<Cell<u64>>,
+    /// The first error since the script was loaded, and whether it was already taken.
+    first_error: RefCell<(Option<String>, bool)>,
+    /// Each country's properties as the rhai map the script is given, by country ID, so that
+    /// they are not converted again every time it is colored.
+    maps: RefCell<HashMap<String, Map>>,
+    /// How many operations the calls since `start_drawing` have taken, and how many the one
+    /// running has so far.
+    operations: Rc<Cell<(u64, u64)>>,
+}
+
+impl ColorScript {
+    /// The script in `path`, which is loaded by the first `poll`.
+    pub fn new(path: PathBuf) -> ColorScript {
+        let tick = Rc::new(Cell::new(0));
+        let operations = Rc::new(Cell::new((0, 0)));
+
+        let mut engine = Engine::new();
+        let spent = operations.clone();
+        engine.on_progress(move |running| {
+            let (before, _) = spent.get();
+            spent.set((before, running));
+            if before + running > MAX_OPERATIONS_PER_DRAW {
+                Some(Dynamic::UNIT)
+            } else {
+                None
+            }
+        });
+        engine.register_fn("hsl", hsl);
+        engine.register_fn("ramp", ramp);
+        let clock = tick.clone();
+        engine.register_fn("clock", move || clock.get() as f64 / TICKS_PER_SEC as f64);
+
+        ColorScript {
+            path,
+            modified: None,
+            last_poll: None,
+            engine,
+            ast: None,
+            time_invariant: false,
+            tick,
+            first_error: RefCell::new((None, false)),
+            maps: RefCell::new(HashMap::new()),
+            operations,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the script said its colors do not change over time.
+    pub fn is_time_invariant(&self) -> bool {
+        self.time_invariant
+    }
+
+    /// Reloads the script if the file was modified since the last call. If it cannot be read, or
+    /// does not compile, the script is left as it was.
+    pub fn poll(&mut self) -> Option<Result<()>> {
+        if self.last_poll.map_or(false, |last| last.elapsed() < POLL_INTERVAL) {
+            return None;
+        }
+        self.last_poll = Some(Instant::now());
+
+        // A file that is briefly missing while an editor replaces it leaves the script as it was.
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+
+        Some(self.load())
+    }
+
+    /// Loads the script now, whether or not it changed.
+    pub fn load(&mut self) -> Result<()> {
+        let text = fs::read_to_string(&self.path)
+            .chain_err(|| format!("Could not read color script {}", self.path.display()))?;
+        let ast = self
+            .engine
+            .compile(text)
+            .map_err(|e| format!("Could not compile {}: {}", self.path.display(), e))?;
+        if !ast.iter_functions().any(|f| f.name == COLOR_FN && f.params.len() == 1) {
+            bail!("{} does not define fn {}(props)", self.path.display(), COLOR_FN);
+        }
+
+        self.time_invariant = ast
+            .iter_literal_variables(true, false)
+            .any(|(name, _, value)| name == TIME_INVARIANT && value.as_bool() == Ok(true));
+        self.ast = Some(ast);
+        *self.first_error.borrow_mut() = (None, false);
+        Ok(())
+    }
+
+    /// Starts drawing the map again, with all of `MAX_OPERATIONS_PER_DRAW` to color it with.
+    pub fn start_drawing(&self) {
+        self.operations.set((0, 0));
+    }
+
+    /// Forgets the countries' properties, after they were replaced by another asset set's.
+    pub fn forget_countries(&self) {
+        self.maps.borrow_mut().clear();
+    }
+
+    /// The color the script gives the country `id`, with `properties`, at animation clock `tick`,
+    /// or `None` if it leaves it uncolored or has not loaded. If the script fails, the country
+    /// gets `ERROR_COLOR`, and the first failure is kept for `take_error`.
+    pub fn color(&self, id: &str, properties: &Properties, tick: Option<u64>) -> Option<[u8; 4]> {
+        let ast = self.ast.as_ref()?;
+        self.tick.set(tick.unwrap_or(0));
+
+        let props = {
+            let mut maps = self.maps.borrow_mut();
+            let map = maps.entry(id.to_string()).or_insert_with(|| to_map(properties));
+            Dynamic::from_map(map.clone())
+        };
+        let called = self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), ast, COLOR_FN, (props,));
+        let (before, running) = self.operations.get();
+        self.operations.set((before + running, 0));
+
+        let colored = called
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(..) => format!(
+                    "coloring the countries took more than {} operations",
+                    MAX_OPERATIONS_PER_DRAW
+                ),
+                ref e => e.to_string(),
+            })
+            .and_then(|result| {
+                if result.is_unit() {
+                    Ok(None)
+                } else {
+                    to_color(&result).map(Some)
+                }
+            });
+
+        match colored {
+            Ok(color) => color,
+            Err(e) => {
+                let mut first_error = self.first_error.borrow_mut();
+                if first_error.0.is_none() {
+                    let name = properties.get("NAME").and_then(Value::as_str).unwrap_or("?");
+                    first_error.0 = Some(format!("{}: {}", name, e));
+                }
+                Some(ERROR_COLOR)
+            }
+        }
+    }
+
+    /// The first time the script failed for a country since it was loaded, once.
+    pub fn take_error(&self) -> Option<String> {
+        let mut first_error = self.first_error.borrow_mut();
+        match *first_error {
+            (Some(ref error), false) => {
+                let error = error.clone();
+                first_error.1 = true;
+                Some(error)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The properties as a rhai map, with numbers as floats.
+fn to_map(properties: &Properties) -> Map {
+    properties
+        .iter()
+        .map(|(key, value)| (key.as_str().into(), to_dynamic(value)))
+        .collect()
+}
+
+fn to_dynamic(value: &Value) -> Dynamic {
+    match *value {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(b) => b.into(),
+        Value::Number(ref n) => n.as_f64().unwrap_or(0.0).into(),
+        Value::String(ref s) => s.as_str().into(),
+        Value::Array(ref values) => values.iter().map(to_dynamic).collect::<Array>().into(),
+        Value::Object(ref values) => Dynamic::from_map(
+            values
+                .iter()
+                .map(|(key, value)| (key.as_str().into(), to_dynamic(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// A number given to or returned from the script, which may be an integer or a float.
+fn to_number(value: &Dynamic) -> ::std::result::Result<f64, String> {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|n| n as f64))
+        .map_err(|type_name| format!("expected a number, got {}", type_name))
+}
+
+/// A color returned from the script, as `[r, g, b]` or `[r, g, b, a]` from 0 to 255.
+fn to_color(value: &Dynamic) -> ::std::result::Result<[u8; 4], String> {
+    let parts = match value.read_lock::<Array>() {
+        Some(parts) if parts.len() == 3 || parts.len() == 4 => parts.clone(),
+        _ => return Err(format!("expected [r, g, b] or [r, g, b, a], got {}", value)),
+    };
+
+    let mut color = [0, 0, 0, DEFAULT_ALPHA];
+    for (channel, part) in color.iter_mut().zip(&parts) {
+        *channel = to_number(part)?.round().max(0.0).min(255.0) as u8;
+    }
+    Ok(color)
+}
+
+fn from_color(color: [u8; 4]) -> Array {
+    color.iter().map(|&channel| Dynamic::from(i64::from(channel))).collect()
+}
+
+type Returned<T> = ::std::result::Result<T, Box<EvalAltResult>>;
+
+fn hsl(h: Dynamic, s: Dynamic, l: Dynamic) -> Returned<Array> {
+    let (r, g, b) = HSL {
+        h: to_number(&h)?.rem_euclid(360.0),
+        s: to_number(&s)?.max(0.0).min(1.0),
+        l: to_number(&l)?.max(0.0).min(1.0),
+    }.to_rgb();
+
+    Ok(from_color([r, g, b, DEFAULT_ALPHA]))
+}
+
+fn ramp(colors: Array, t: Dynamic) -> Returned<Array> {
+    let colors = colors
+        .iter()
+        .map(to_color)
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    if colors.is_empty() {
+        return Err("ramp needs at least one color".into());
+    }
+
+    Ok(from_color(ramp_color(&colors, to_number(&t)?)))
+}
+
+/// The color `t` of the way along evenly spaced `colors`, as the script's `ramp` has it. `t` is
+/// clamped from 0 to 1. Panics if `colors` is empty.
+pub fn ramp_color(colors: &[[u8; 4]], t: f64) -> [u8; 4] {
+    let along = t.max(0.0).min(1.0) * (colors.len() - 1) as f64;
+    let (low, high) = (along.floor() as usize, along.ceil() as usize);
+    let fraction = along - along.floor();
+
+    let mut color = [0; 4];
+    for (i, channel) in color.iter_mut().enumerate() {
+        let (from, to) = (f64::from(colors[low][i]), f64::from(colors[high][i]));
+        *channel = (from + (to - from) * fraction).round() as u8;
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    fn script(name: &str, text: &str) -> ColorScript {
+        let file = format!("gaia-demo-script-{}-{}.rhai", process::id(), name);
+        let path = env::temp_dir().join(file);
+        fs::write(&path, text).unwrap();
+        let mut script = ColorScript::new(path);
+        script.load().unwrap();
+
+        script
+    }
+
+    fn properties(json: &str) -> Properties {
+        ::serde_json::from_str(json).unwrap()
+    }
+
+    fn color(array: Returned<Array>) -> [u8; 4] {
+        to_color(&Dynamic::from_array(array.unwrap())).unwrap()
+    }
+
+    #[test]
+    fn hsl_takes_degrees_and_fractions() {
+        let hsl_of = |h: f64, s: f64, l: f64| color(hsl(h.into(), s.into(), l.into()));
+        assert_eq!(hsl_of(0.0, 1.0, 0.5), [255, 0, 0, DEFAULT_ALPHA]);
+        assert_eq!(hsl_of(480.0, 1.0, 0.5), hsl_of(120.0, 1.0, 0.5));
+        assert_eq!(hsl_of(-120.0, 1.0, 0.5), [0, 0, 255, DEFAULT_ALPHA]);
+        assert_eq!(hsl_of(0.0, 5.0, 2.0), [255, 255, 255, DEFAULT_ALPHA]);
+        // Integers are numbers too.
+        let whole = hsl(Dynamic::from(120_i64), Dynamic::from(1_i64), 0.5.into());
+        assert_eq!(color(whole), [0, 255, 0, DEFAULT_ALPHA]);
+        assert!(hsl("red".into(), 1.0.into(), 0.5.into()).is_err());
+    }
+
+    #[test]
+    fn ramps_blend_between_evenly_spaced_colors() {
+        let colors = [[0, 0, 0, 0], [100, 200, 50, 255], [200, 0, 250, 255]];
+        assert_eq!(ramp_color(&colors, 0.0), [0, 0, 0, 0]);
+        assert_eq!(ramp_color(&colors, 0.25), [50, 100, 25, 128]);
+        assert_eq!(ramp_color(&colors, 0.5), [100, 200, 50, 255]);
+        assert_eq!(ramp_color(&colors, 1.0), [200, 0, 250, 255]);
+        assert_eq!(ramp_color(&colors, -3.0), ramp_color(&colors, 0.0));
+        assert_eq!(ramp_color(&colors, 3.0), ramp_color(&colors, 1.0));
+        assert_eq!(ramp_color(&colors[..1], 0.7), [0, 0, 0, 0]);
+
+        let from_script = |colors: Vec<Vec<i64>>| -> Array {
+            colors
+                .into_iter()
+                .map(|color| Dynamic::from_array(color.into_iter().map(Dynamic::from).collect()))
+                .collect()
+        };
+        let ramped = ramp(from_script(vec![vec![0, 0, 0], vec![255, 255, 255]]), 0.5.into());
+        assert_eq!(color(ramped), [128, 128, 128, DEFAULT_ALPHA]);
+        assert!(ramp(from_script(vec![]), 0.5.into()).is_err());
+        assert!(ramp(from_script(vec![vec![1, 2]]), 0.5.into()).is_err());
+    }
+
+    #[test]
+    fn colors_are_three_or_four_channels_from_0_to_255() {
+        let parse = |channels: Vec<Dynamic>| to_color(&Dynamic::from_array(channels));
+        let three = vec![Dynamic::from(10_i64), Dynamic::from(20.4), Dynamic::from(20.6)];
+        assert_eq!(parse(three), Ok([10, 20, 21, DEFAULT_ALPHA]));
+        let four = vec![(-5.0).into(), 300.0.into(), 0.0.into(), Dynamic::from(7_i64)];
+        assert_eq!(parse(four), Ok([0, 255, 0, 7]));
+
+        assert!(parse(vec![1.0.into(), 2.0.into()]).is_err());
+        assert!(parse(vec![1.0.into(), "2".into(), 3.0.into()]).is_err());
+        assert!(to_color(&Dynamic::from("red")).is_err());
+    }
+
+    #[test]
+    fn countries_the_script_fails_for_are_shown_as_failing() {
+        let script = script(
+            "failing",
+            "fn color(props) {\n\
+             if props.NAME == \"France\" { throw \"no\"; }\n\
+             if props.NAME == \"Chad\" { return (); }\n\
+             [props.MAPCOLOR13 * 10, 0, 0]\n\
+             }\n",
+        );
+        assert!(!script.is_time_invariant());
+
+        let norway = properties(r#"{"NAME": "Norway", "MAPCOLOR13": 12}"#);
+        let france = properties(r#"{"NAME": "France", "MAPCOLOR13": 3}"#);
+        let chad = properties(r#"{"NAME": "Chad", "MAPCOLOR13": 1}"#);
+        assert_eq!(script.color("NOR", &norway, None), Some([120, 0, 0, DEFAULT_ALPHA]));
+        assert_eq!(script.color("FRA", &france, None), Some(ERROR_COLOR));
+        assert_eq!(script.color("TCD", &chad, None), None);
+        assert_eq!(script.color("FRA", &france, None), Some(ERROR_COLOR));
+
+        // Only the first failure is shown, and only once.
+        let error = script.take_error().unwrap();
+        assert!(error.starts_with("France: "), "{}", error);
+        assert_eq!(script.take_error(), None);
+    }
+
+    #[test]
+    fn properties_are_converted_once_per_country() {
+        let script = script("cached", "fn color(props) { [props.MAPCOLOR13, 0, 0] }\n");
+        let before = properties(r#"{"MAPCOLOR13": 1}"#);
+        let after = properties(r#"{"MAPCOLOR13": 2}"#);
+
+        assert_eq!(script.color("NOR", &before, None), Some([1, 0, 0, DEFAULT_ALPHA]));
+        assert_eq!(script.color("NOR", &after, None), Some([1, 0, 0, DEFAULT_ALPHA]));
+        script.forget_countries();
+        assert_eq!(script.color("NOR", &after, None), Some([2, 0, 0, DEFAULT_ALPHA]));
+    }
+
+    #[test]
+    fn scripts_say_whether_their_colors_change_over_time() {
+        let animated = script("animated", "fn color(props) { hsl(clock() * 10.0, 1.0, 0.5) }\n");
+        assert!(!animated.is_time_invariant());
+        let country = properties("{}");
+        assert_ne!(
+            animated.color("NOR", &country, Some(0)),
+            animated.color("NOR", &country, Some(TICKS_PER_SEC))
+        );
+
+        let invariant = script(
+            "invariant",
+            "const TIME_INVARIANT = true;\nfn color(props) { [1, 2, 3] }\n",
+        );
+        assert!(invariant.is_time_invariant());
+        let not_invariant = script(
+            "not-invariant",
+            "const TIME_INVARIANT = false;\nfn color(props) { [1, 2, 3] }\n",
+        );
+        assert!(!not_invariant.is_time_invariant());
+    }
+
+    #[test]
+    fn each_drawing_has_a_budget_of_operations() {
+        let script = script("looping", "fn color(props) { loop {} }\n");
+        let country = properties(r#"{"NAME": "Norway"}"#);
+
+        assert_eq!(script.color("NOR", &country, None), Some(ERROR_COLOR));
+        let error = script.take_error().unwrap();
+        assert!(error.contains("operations"), "{}", error);
+        // The budget is spent, so the rest fail at once, until the map is drawn again.
+        assert_eq!(script.operations.get().0, MAX_OPERATIONS_PER_DRAW + 1);
+        assert_eq!(script.color("SWE", &country, None), Some(ERROR_COLOR));
+        assert_eq!(script.operations.get().0, MAX_OPERATIONS_PER_DRAW + 2);
+
+        script.start_drawing();
+        assert_eq!(script.operations.get(), (0, 0));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use legend::LegendEntry;
+use props::CountryProps;
+use MapMode;
+
+/// What countries whose class is the same in the snapshot and the live mode are drawn in.
+const UNCHANGED_COLOR: [u8; 4] = [128, 128, 128, 100];
+
+/// What countries whose class changed are drawn in, from `FAINT_CHANGED_COLOR` for the least
+/// they could have moved to `CHANGED_COLOR` for the most.
+const FAINT_CHANGED_COLOR: [u8; 3] = [255, 210, 160];
+const CHANGED_COLOR: [u8; 3] = [255, 110, 0];
+const CHANGED_ALPHA: u8 = 230;
+
+/// The legend labels of the ordinal moves, by how many classes a country moved, from 1.
+const MOVED_LABELS: [&str; 4] = [
+    "Moved 1 class",
+    "Moved 2 classes",
+    "Moved 3 classes",
+    "Moved 4 classes",
+];
+
+/// How a map mode showed a country: its color, if it was colored, and which legend category
+/// that was, if the mode has a legend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Class {
+    pub color: Option<[u8; 4]>,
+    pub category: Option<usize>,
+}
+
+impl Class {
+    /// How `mode` shows `country`, colored `color`.
+    pub fn of(mode: MapMode, country: &CountryProps, color: Option<[u8; 4]>) -> Class {
+        Class {
+            color,
+            category: color.and(mode.category(country)),
+        }
+    }
+
+    const UNCOLORED: Class = Class {
+        color: None,
+        category: None,
+    };
+}
+
+/// Whether `mode`'s categories are in order, so that how far apart two are means something.
+fn is_ordinal(mode: MapMode) -> bool {
+    mode == MapMode::Income
+}
+
+/// How every country was shown in a map mode, frozen to compare other modes against. Kept until
+/// it is cleared or replaced, whatever modes are switched to.
+#[derive(Clone, Debug)]
+pub struct ColorSnapshot {
+    mode: MapMode,
+    /// By `feature_id`.
+    classes: HashMap<String, Class>,
+}
+
+impl ColorSnapshot {
+    /// A snapshot of `mode`, with each country's id and class.
+    pub fn new<I>(mode: MapMode, classes: I) -> ColorSnapshot
+    where
+        I: IntoIterator<Item = (String, Class)>,
+    {
+        ColorSnapshot {
+            mode,
+            classes: classes.into_iter().collect(),
+        }
+    }
+
+    pub fn mode(&self) -> MapMode {
+        self.mode
+    }
+
+    /// How much the country `id` changed from the snapshot to being shown as `live` in `mode`,
+    /// from 0 for not at all to 1 for the most it could. Between two ordinal modes, it is how many
+    /// classes it moved as a fraction of the most it could have. Otherwise any change counts in
+    /// full: gaining or losing its color, or its category within the same mode. Countries in
+    /// modes without categories change when their color does.
+    pub fn change(&self, id: &str, mode: MapMode, live: Class) -> f32 {
+        let snapshot = self.classes.get(id).cloned().unwrap_or(Class::UNCOLORED);
+
+        match (snapshot.color, live.color) {
+            (None, None) => return 0.0,
+            (Some(_), None) | (None, Some(_)) => return 1.0,
+            (Some(_), Some(_)) => {}
+        }
+
+        match (snapshot.category, live.category) {
+            (Some(before), Some(after)) if is_ordinal(self.mode) && is_ordinal(mode) => {
+                let classes = self.mode.legend_entries().len().max(2) - 1;
+                let moved = (before as isize - after as isize).abs() as f32;
+                (moved / classes as f32).min(1.0)
+            }
+            (Some(before), Some(after)) if self.mode == mode => changed(before != after),
+            (None, None) => {
+                let rgb = |color: Option<[u8; 4]>| color.map(|[r, g, b, _]| [r, g, b]);
+                changed(rgb(snapshot.color) != rgb(live.color))
+            }
+            // The categories of different modes cannot be compared, so only whether the country
+            // is colored counts.
+            _ => 0.0,
+        }
+    }
+
+    /// The legend of the diff view between this snapshot and `mode`.
+    pub fn legend_entries(&self, mode: MapMode) -> Vec<LegendEntry> {
+        let mut entries = vec![LegendEntry {
+            label: "Unchanged since the snapshot".to_string(),
+            color: UNCHANGED_COLOR,
+        }];
+
+        if is_ordinal(self.mode) && is_ordinal(mode) {
+            let classes = self.mode.legend_entries().len().max(2) - 1;
+            for (moved, &label) in MOVED_LABELS.iter().enumerate().take(classes) {
+                entries.push(LegendEntry {
+                    label: label.to_string(),
+                    color: diff_color((moved + 1) as f32 / classes as f32),
+                });
+            }
+        }
+        entries.push(LegendEntry {
+            label: "Changed class, or colored in only one".to_string(),
+            color: diff_color(1.0),
+        });
+
+        entries
+    }
+}
+
+fn changed(changed: bool) -> f32 {
+    if changed {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// What a country that changed by `change`, as `ColorSnapshot::change` measures it, is drawn in.
+pub fn diff_color(change: f32) -> [u8; 4] {
+    if change <= 0.0 {
+        return UNCHANGED_COLOR;
+    }
+
+    let change = change.min(1.0);
+    let mix = |i: usize| {
+        let (faint, full) = (f32::from(FAINT_CHANGED_COLOR[i]), f32::from(CHANGED_COLOR[i]));
+        (faint + (full - faint) * change).round() as u8
+    };
+    [mix(0), mix(1), mix(2), CHANGED_ALPHA]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(color: u8, category: Option<usize>) -> Class {
+        Class {
+            color: Some([color, color, color, 255]),
+            category,
+        }
+    }
+
+    fn snapshot(mode: MapMode) -> ColorSnapshot {
+        ColorSnapshot::new(
+            mode,
+            vec![
+                ("ISL".to_string(), class(10, Some(0))),
+                ("TCD".to_string(), class(20, Some(3))),
+                ("ATA".to_string(), Class::UNCOLORED),
+            ],
+        )
+    }
+
+    #[test]
+    fn nothing_changes_against_the_mode_the_snapshot_was_taken_in() {
+        let snapshot = snapshot(MapMode::Income);
+        assert_eq!(snapshot.mode(), MapMode::Income);
+
+        for &(id, live) in &[
+            ("ISL", class(10, Some(0))),
+            ("TCD", class(20, Some(3))),
+            ("ATA", Class::UNCOLORED),
+        ] {
+            assert_eq!(snapshot.change(id, MapMode::Income, live), 0.0, "{}", id);
+        }
+        assert_eq!(diff_color(0.0), UNCHANGED_COLOR);
+    }
+
+    #[test]
+    fn ordinal_moves_count_by_how_many_classes() {
+        let snapshot = snapshot(MapMode::Income);
+        let classes = (MapMode::Income.legend_entries().len().max(2) - 1) as f32;
+
+        assert_eq!(snapshot.change("ISL", MapMode::Income, class(10, Some(1))), 1.0 / classes);
+        assert_eq!(snapshot.change("TCD", MapMode::Income, class(20, Some(0))), 3.0 / classes);
+
+        let legend = snapshot.legend_entries(MapMode::Income);
+        assert_eq!(legend.len(), classes as usize + 2);
+        assert_eq!(legend[1].label, "Moved 1 class");
+        assert_eq!(legend.last().unwrap().color, diff_color(1.0));
+    }
+
+    #[test]
+    fn gaining_or_losing_a_color_is_a_full_change() {
+        let snapshot = snapshot(MapMode::Income);
+
+        assert_eq!(snapshot.change("ISL", MapMode::Oecd, Class::UNCOLORED), 1.0);
+        assert_eq!(snapshot.change("ATA", MapMode::Oecd, class(10, Some(0))), 1.0);
+        // Countries the snapshot does not know of were not colored in it.
+        assert_eq!(snapshot.change("XKX", MapMode::Oecd, class(10, Some(0))), 1.0);
+        assert_eq!(snapshot.change("XKX", MapMode::Oecd, Class::UNCOLORED), 0.0);
+
+        // Categories of different modes are not compared.
+        assert_eq!(snapshot.change("TCD", MapMode::Oecd, class(30, Some(1))), 0.0);
+        assert_eq!(snapshot.legend_entries(MapMode::Oecd).len(), 2);
+    }
+
+    #[test]
+    fn modes_without_categories_change_with_their_colors() {
+        let snapshot = ColorSnapshot::new(MapMode::All, vec![("ISL".to_string(), class(10, None))]);
+
+        let faded = Class {
+            color: Some([10, 10, 10, 100]),
+            category: None,
+        };
+        assert_eq!(snapshot.change("ISL", MapMode::All, faded), 0.0);
+        assert_eq!(snapshot.change("ISL", MapMode::All, class(11, None)), 1.0);
+    }
+
+    #[test]
+    fn diff_colors_blend_from_faint_to_full() {
+        assert_eq!(diff_color(1.0), [255, 110, 0, CHANGED_ALPHA]);
+        assert_eq!(diff_color(2.0), diff_color(1.0));
+        assert_eq!(diff_color(0.5), [255, 160, 80, CHANGED_ALPHA]);
+    }
+}
@@ -0,0 +1,322 @@
+use piston::input::keyboard::Key;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use bindings::Action;
+use hud::{HudScale, HudStyle};
+use MapMode;
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+const WIDTH: f64 = 360.0;
+
+/// The most matches listed at once. The list scrolls to keep the highlighted one shown.
+const MAX_SHOWN: usize = 12;
+
+/// The longest query that can be typed.
+const MAX_QUERY_LEN: usize = 60;
+
+/// What running one of the palette's entries does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PaletteCommand {
+    Run(Action),
+    ShowMode(MapMode),
+    /// Selects the country with this polygon and flies to it.
+    SelectCountry(usize),
+}
+
+/// One thing the palette can run, as it is listed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaletteEntry {
+    /// Such as "Toggle labels", "Mode: Income" or "Country: France".
+    pub label: String,
+    /// Another name the entry is found by, such as the action's name in the settings file.
+    pub alias: Option<String>,
+    /// The key or chord that does the same, if any, such as "F2", for learning them.
+    pub keys: Option<String>,
+    pub command: PaletteCommand,
+}
+
+impl PaletteEntry {
+    /// How well `query` matches the entry's label or alias, whichever is better.
+    fn score(&self, query: &str) -> Option<i64> {
+        let alias = self.alias.as_ref().and_then(|alias| score(query, alias));
+        score(query, &self.label).into_iter().chain(alias).max()
+    }
+}
+
+/// How well `query` matches `text`, ignoring case, or `None` if the characters of `query` are not
+/// all in `text` in order. The higher the better: characters at the start of words and runs of
+/// consecutive characters count for most, characters skipped count against, and of otherwise
+/// equal texts the shortest wins. Of the ways `query` can be found in `text`, the best counts.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let query = query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace());
+    let word_start = |j: usize| j == 0 || !text[j - 1].is_alphanumeric();
+    // Skipping more than this many characters counts against a match no further.
+    let skipped = |n: usize| n.min(5) as i64;
+
+    // The best score of each way of finding the query so far that ends at each character.
+    let mut best: Option<Vec<Option<i64>>> = None;
+    for wanted in query {
+        let ending: Vec<Option<i64>> = (0..text.len())
+            .map(|j| {
+                if text[j] != wanted {
+                    return None;
+                }
+                let before = match best {
+                    None => Some(-skipped(j)),
+                    Some(ref best) => (0..j)
+                        .filter_map(|k| {
+                            let run = if k + 1 == j { 5 } else { -skipped(j - k - 1) };
+                            best[k].map(|score| score + run)
+                        })
+                        .max(),
+                };
+
+                before.map(|score| score + if word_start(j) { 9 } else { 1 })
+            })
+            .collect();
+        best = Some(ending);
+    }
+
+    let score = match best {
+        Some(best) => best.into_iter().flatten().max()?,
+        None => 0,
+    };
+    Some(score * 100 - text.len() as i64)
+}
+
+/// Ctrl+P's list of every action, map mode and country, narrowed down by what is typed into it,
+/// to run any of them without knowing their keys. Up/Down and Return pick one.
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    visible: bool,
+    entries: Vec<PaletteEntry>,
+    query: String,
+    /// Indices into `entries` of those `query` matches, best first.
+    matches: Vec<usize>,
+    /// The index in `matches` of the highlighted one.
+    highlighted: usize,
+}
+
+impl CommandPalette {
+    pub fn new() -> CommandPalette {
+        CommandPalette::default()
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Opens the palette, listing `entries`.
+    pub fn open(&mut self, entries: Vec<PaletteEntry>) {
+        self.visible = true;
+        self.entries = entries;
+        self.query.clear();
+        self.refilter();
+    }
+
+    pub fn close(&mut self) {
+        self.visible = false;
+        self.entries.clear();
+    }
+
+    /// The entries matching what was typed, best first.
+    pub fn matches(&self) -> Vec<&PaletteEntry> {
+        self.matches.iter().map(|&i| &self.entries[i]).collect()
+    }
+
+    /// Handles a key press while the palette is open. Every key is swallowed, so that nothing
+    /// happens behind the palette as its query is typed. Running an entry closes the palette
+    /// first, so that one that opens another overlay leaves that one open.
+    pub fn key_press(&mut self, key: Key) -> Option<PaletteCommand> {
+        let count = self.matches.len();
+        match key {
+            Key::Escape => self.close(),
+            Key::Up if count > 0 => self.highlighted = (self.highlighted + count - 1) % count,
+            Key::Down if count > 0 => self.highlighted = (self.highlighted + 1) % count,
+            Key::Backspace => {
+                self.query.pop();
+                self.refilter();
+            }
+            Key::Return | Key::NumPadEnter => {
+                let command = self
+                    .matches
+                    .get(self.highlighted)
+                    .map(|&i| self.entries[i].command);
+                if command.is_some() {
+                    self.close();
+                }
+
+                return command;
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Adds what was typed to the query, skipping control characters.
+    pub fn text(&mut self, text: &str) {
+        let typed = text.chars().filter(|c| !c.is_control());
+        let room = MAX_QUERY_LEN.saturating_sub(self.query.chars().count());
+        self.query.extend(typed.take(room));
+        self.refilter();
+    }
+
+    /// Works out again which entries match the query, and highlights the best.
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| entry.score(&self.query).map(|score| (score, i)))
+            .collect();
+        // With nothing typed, the entries stay in the order they were given.
+        if !self.query.trim().is_empty() {
+            scored.sort_by(|a, b| b.0.cmp(&a.0));
+        }
+
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.highlighted = 0;
+    }
+
+    /// How much room `draw` needs.
+    pub fn size(&self, scale: HudScale) -> [f64; 2] {
+        let rows = self.matches.len().clamp(1, MAX_SHOWN);
+
+        [scale.len(WIDTH), scale.len(LINE_HEIGHT * (rows + 3) as f64)]
+    }
+
+    /// Draws the palette with its top-left corner at `origin`.
+    pub fn draw(
+        &self,
+        origin: [f64; 2],
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let HudStyle { scale, theme } = style;
+        let [width, height] = self.size(scale);
+        let context = context.trans(origin[0], origin[1]);
+        let line_height = scale.len(LINE_HEIGHT);
+        let indent = scale.len(10.0);
+
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [0.0, 0.0, width, height],
+            context.transform,
+            graphics,
+        );
+
+        let row_context = |x: f64, row: usize| context.trans(x, line_height * (row + 1) as f64);
+        scale.draw_text(
+            &format!("> {}_", self.query),
+            FONT_SIZE,
+            theme.accent,
+            row_context(indent, 0),
+            glyphs,
+            graphics,
+        );
+
+        if self.matches.is_empty() {
+            let text = "No commands match";
+            scale.draw_text(text, FONT_SIZE, theme.text, row_context(indent, 2), glyphs, graphics);
+            return;
+        }
+
+        let first = (self.highlighted + 1).saturating_sub(MAX_SHOWN);
+        let shown = self.matches.iter().enumerate().skip(first).take(MAX_SHOWN);
+        for (row, (i, &entry)) in shown.enumerate() {
+            let entry = &self.entries[entry];
+            let (marker, color) = if i == self.highlighted {
+                ("> ", theme.accent)
+            } else {
+                ("  ", theme.text)
+            };
+            let label = format!("{}{}", marker, entry.label);
+            let at = row_context(indent, row + 2);
+            scale.draw_text(&label, FONT_SIZE, color, at, glyphs, graphics);
+
+            if let Some(ref keys) = entry.keys {
+                let x = width - indent - scale.text_width(FONT_SIZE, keys, glyphs);
+                let at = row_context(x, row + 2);
+                scale.draw_text(keys, FONT_SIZE, theme.text, at, glyphs, graphics);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(label: &str, command: PaletteCommand) -> PaletteEntry {
+        PaletteEntry {
+            label: label.to_string(),
+            alias: None,
+            keys: None,
+            command,
+        }
+    }
+
+    fn labels(palette: &CommandPalette) -> Vec<&str> {
+        palette.matches().iter().map(|entry| entry.label.as_str()).collect()
+    }
+
+    #[test]
+    fn queries_match_in_order_ignoring_case() {
+        assert!(score("tl", "Toggle labels").is_some());
+        assert!(score("TOGLAB", "Toggle labels").is_some());
+        assert!(score("", "Toggle labels").is_some());
+        assert_eq!(score("lt", "Toggle labels"), None);
+        assert_eq!(score("toggles", "Toggle label"), None);
+
+        // Word starts and runs of characters beat scattered ones...
+        assert!(score("tl", "Toggle labels") > score("tl", "Settle"));
+        assert!(score("lab", "Toggle labels") > score("lab", "Play a route back"));
+        // ...and of otherwise equal texts the shorter wins.
+        assert!(score("fr", "Country: France") > score("fr", "Country: French Guiana"));
+    }
+
+    #[test]
+    fn the_best_match_is_highlighted_and_run() {
+        let mut palette = CommandPalette::new();
+        palette.open(vec![
+            entry("Toggle settings", PaletteCommand::Run(Action::ToggleSettings)),
+            entry("Toggle labels", PaletteCommand::Run(Action::ToggleLabels)),
+            entry("Mode: Income", PaletteCommand::ShowMode(MapMode::Income)),
+            entry("Country: Iceland", PaletteCommand::SelectCountry(7)),
+        ]);
+        // With nothing typed, everything is listed as given.
+        assert_eq!(labels(&palette).len(), 4);
+        assert_eq!(labels(&palette)[0], "Toggle settings");
+
+        palette.text("inc");
+        assert_eq!(labels(&palette), ["Mode: Income"]);
+        palette.key_press(Key::Backspace);
+        palette.key_press(Key::Backspace);
+        assert_eq!(labels(&palette), ["Mode: Income", "Country: Iceland", "Toggle settings"]);
+
+        palette.key_press(Key::Down);
+        assert_eq!(palette.key_press(Key::Return), Some(PaletteCommand::SelectCountry(7)));
+        assert!(!palette.is_visible());
+    }
+
+    #[test]
+    fn every_key_is_swallowed_until_it_closes() {
+        let mut palette = CommandPalette::new();
+        palette.open(vec![entry("Toggle labels", PaletteCommand::Run(Action::ToggleLabels))]);
+
+        // Control characters, such as Ctrl+P may type, are left out of the query.
+        palette.text("\u{10}xyz");
+        assert!(palette.matches().is_empty());
+        assert_eq!(palette.key_press(Key::Return), None);
+        assert_eq!(palette.key_press(Key::F2), None);
+        assert!(palette.is_visible());
+
+        palette.key_press(Key::Escape);
+        assert!(!palette.is_visible());
+    }
+}
@@ -0,0 +1,93 @@
+use piston_window::{Context, Ellipse, G2d, Glyphs, Transformed};
+
+use hud::{HudScale, HudStyle};
+
+/// The compass's radius at a HUD scale of 1.
+const RADIUS: f64 = 20.0;
+
+/// How far from the center the widget extends, including the "N" label, as a multiple of
+/// `RADIUS`.
+const EXTENT: f64 = 1.5;
+
+const FONT_SIZE: u32 = 10;
+
+/// How much room `draw` needs at `scale`.
+pub fn size(scale: HudScale) -> [f64; 2] {
+    let side = scale.len(2.0 * EXTENT * RADIUS);
+
+    [side, side]
+}
+
+/// Where the compass's center is when drawn with its top-left corner at `origin`.
+pub fn center(origin: [f64; 2], scale: HudScale) -> [f64; 2] {
+    let offset = scale.len(EXTENT * RADIUS);
+
+    [origin[0] + offset, origin[1] + offset]
+}
+
+/// Whether `point` is on the compass centered at `center`.
+pub fn contains(center: [f64; 2], scale: HudScale, point: [f64; 2]) -> bool {
+    (point[0] - center[0]).hypot(point[1] - center[1]) <= scale.len(RADIUS)
+}
+
+/// Draws a compass rose centered at `center`, whose needle points north given the camera's
+/// `heading`. The rose is squashed vertically by `viewing_angle`, hinting at how tilted the
+/// camera is.
+pub fn draw(
+    heading: f32,
+    viewing_angle: f32,
+    center: [f64; 2],
+    style: HudStyle,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+    let [x, y] = center;
+    let radius = scale.len(RADIUS);
+    let squash = f64::from(viewing_angle.sin());
+    let transform = context.transform.trans(x, y);
+
+    // Exactly 0 only happens when facing north on purpose, such as after snapping north.
+    let ring_color = if heading == 0.0 {
+        theme.accent
+    } else {
+        theme.text
+    };
+
+    let ellipse_bounds = [-radius, -radius * squash, 2.0 * radius, 2.0 * radius * squash];
+    ::piston_window::ellipse(theme.panel_background, ellipse_bounds, transform, graphics);
+    Ellipse::new_border(ring_color, scale.len(1.0)).draw(
+        ellipse_bounds,
+        &context.draw_state,
+        transform,
+        graphics,
+    );
+
+    // North is counterclockwise of straight up by the heading.
+    let (sin, cos) = f64::from(heading).sin_cos();
+    let point = |along: f64, across: f64| {
+        [
+            (-sin * along + cos * across) * radius,
+            (-cos * along - sin * across) * radius * squash,
+        ]
+    };
+
+    let north = [point(0.8, 0.0), point(0.0, 0.2), point(0.0, -0.2)];
+    let south = [point(-0.8, 0.0), point(0.0, 0.2), point(0.0, -0.2)];
+    ::piston_window::polygon([1.0, 0.2, 0.2, 1.0], &north, transform, graphics);
+    ::piston_window::polygon(theme.muted_text(), &south, transform, graphics);
+
+    let [label_x, label_y] = point(1.2, 0.0);
+    let label_width = scale.text_width(FONT_SIZE, "N", glyphs);
+    let label_height = scale.len(f64::from(FONT_SIZE));
+
+    scale.draw_text(
+        "N",
+        FONT_SIZE,
+        ring_color,
+        context.trans(x + label_x - label_width / 2.0, y + label_y + label_height / 2.0),
+        glyphs,
+        graphics,
+    );
+}
@@ -0,0 +1,103 @@
+use formatting;
+use settings::{CoordinateFormat, FormattingSettings};
+
+/// Formats a latitude and longitude, both in degrees, with hemisphere letters, such as
+/// "51.50740°N 0.12780°W" or "51°30'26.6\"N 0°07'40.1\"W". Decimal degrees are written with
+/// the decimal separator of `numbers`.
+pub fn format(
+    latitude: f64,
+    longitude: f64,
+    format: CoordinateFormat,
+    numbers: FormattingSettings,
+) -> String {
+    format!(
+        "{}{} {}{}",
+        format_angle(latitude, format, numbers),
+        if latitude < 0.0 { 'S' } else { 'N' },
+        format_angle(longitude, format, numbers),
+        if longitude < 0.0 { 'W' } else { 'E' },
+    )
+}
+
+/// The map-space `point` as a latitude and longitude in degrees.
+pub fn from_map_point(point: [f32; 2]) -> (f64, f64) {
+    let [x, y] = point;
+
+    (f64::from(y) * 180.0 - 90.0, f64::from(x) * 360.0 - 180.0)
+}
+
+/// The latitude and longitude, in degrees, as a world-space point. The map is twice as wide there
+/// as in map space, so x is between 0 and 2.
+pub fn to_world_point(latitude: f64, longitude: f64) -> [f32; 2] {
+    [
+        (2.0 * (longitude + 180.0) / 360.0) as f32,
+        ((latitude + 90.0) / 180.0) as f32,
+    ]
+}
+
+fn format_angle(degrees: f64, format: CoordinateFormat, numbers: FormattingSettings) -> String {
+    match format {
+        CoordinateFormat::Decimal => {
+            format!("{}\u{b0}", formatting::number(degrees.abs(), 5, numbers))
+        }
+        CoordinateFormat::Dms => {
+            // Round once, to tenths of an arcsecond, so that 59.96" carries into the minutes
+            // instead of showing as 60.0".
+            let tenths = (degrees.abs() * 36_000.0).round() as u64;
+            let seconds = tenths % 600;
+
+            format!(
+                "{}\u{b0}{:02}'{:02}.{}\"",
+                tenths / 36_000,
+                tenths / 600 % 60,
+                seconds / 10,
+                seconds % 10
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinates_are_formatted_with_hemispheres() {
+        let numbers = FormattingSettings::default();
+        let london = |coordinate_format| format(51.5074, -0.1278, coordinate_format, numbers);
+
+        assert_eq!(london(CoordinateFormat::Decimal), "51.50740\u{b0}N 0.12780\u{b0}W");
+        assert_eq!(london(CoordinateFormat::Dms), "51\u{b0}30'26.6\"N 0\u{b0}07'40.1\"W");
+        assert_eq!(
+            format(-33.9, 151.2, CoordinateFormat::Decimal, numbers),
+            "33.90000\u{b0}S 151.20000\u{b0}E"
+        );
+
+        let commas = FormattingSettings {
+            decimal_separator: ',',
+            thousands_separator: '.',
+            ..numbers
+        };
+        assert_eq!(
+            format(51.5074, -0.1278, CoordinateFormat::Decimal, commas),
+            "51,50740\u{b0}N 0,12780\u{b0}W"
+        );
+    }
+
+    #[test]
+    fn seconds_that_round_up_carry_into_the_minutes() {
+        let numbers = FormattingSettings::default();
+
+        assert_eq!(
+            format(10.99999, 0.0, CoordinateFormat::Dms, numbers),
+            "11\u{b0}00'00.0\"N 0\u{b0}00'00.0\"E"
+        );
+    }
+
+    #[test]
+    fn map_points_are_latitudes_and_longitudes() {
+        assert_eq!(from_map_point([0.5, 0.5]), (0.0, 0.0));
+        assert_eq!(from_map_point([0.0, 0.0]), (-90.0, -180.0));
+        assert_eq!(from_map_point([1.0, 1.0]), (90.0, 180.0));
+    }
+}
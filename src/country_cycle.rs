@@ -0,0 +1,140 @@
+use piston::input::keyboard::Key;
+
+use bindings::Action;
+
+/// How long a cycling key has to be held before it starts repeating, in seconds.
+const REPEAT_DELAY: f64 = 0.4;
+
+/// How long between repeats while a cycling key is held, in seconds.
+const REPEAT_INTERVAL: f64 = 0.12;
+
+/// Repeats `Action::PreviousCountry` and `Action::NextCountry` while their key is held, at its
+/// own rate rather than the system's, whose repeated presses are ignored. The camera only flies
+/// to the country selected once the key is let go, instead of once for every country passed.
+#[derive(Debug, Default)]
+pub struct CountryCycle {
+    held: Option<Held>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Held {
+    key: Key,
+    action: Action,
+    /// How long until the action is repeated next, in seconds.
+    until_repeat: f64,
+}
+
+impl CountryCycle {
+    pub fn new() -> CountryCycle {
+        CountryCycle { held: None }
+    }
+
+    /// `key`, bound to the cycling `action`, was pressed. Returns whether to do `action` now:
+    /// only for the first press, and not the system's repeats of it.
+    pub fn press(&mut self, key: Key, action: Action) -> bool {
+        if self.held.map_or(false, |held| held.key == key) {
+            return false;
+        }
+
+        self.held = Some(Held {
+            key,
+            action,
+            until_repeat: REPEAT_DELAY,
+        });
+        true
+    }
+
+    /// The actions to repeat now that `dt` more seconds have passed.
+    pub fn update(&mut self, dt: f64) -> Vec<Action> {
+        let held = match self.held {
+            // A time that is not finite would never be caught up on.
+            Some(ref mut held) if dt.is_finite() => held,
+            _ => return vec![],
+        };
+
+        let mut repeats = vec![];
+        held.until_repeat -= dt;
+        while held.until_repeat <= 0.0 {
+            repeats.push(held.action);
+            held.until_repeat += REPEAT_INTERVAL;
+        }
+        repeats
+    }
+
+    /// `key` was let go. Returns whether it was the cycling key held, so that the camera should
+    /// now fly to the country selected.
+    pub fn release(&mut self, key: Key) -> bool {
+        if self.held.map_or(false, |held| held.key == key) {
+            self.held = None;
+            return true;
+        }
+
+        false
+    }
+
+    /// Forgets the key held, whose release would be missed, such as when focus is lost.
+    pub fn cancel(&mut self) {
+        self.held = None;
+    }
+}
+
+/// The polygon `steps` on from `current` in `order`, wrapping around either end. With nothing
+/// selected, or one not in `order`, going forward starts at the first and back at the last.
+pub fn step(order: &[usize], current: Option<usize>, steps: isize) -> Option<usize> {
+    if order.is_empty() {
+        return None;
+    }
+
+    let len = order.len() as isize;
+    let index = match current.and_then(|current| order.iter().position(|&i| i == current)) {
+        Some(index) => index as isize + steps,
+        None if steps > 0 => steps - 1,
+        None => len + steps,
+    };
+
+    Some(order[index.rem_euclid(len) as usize])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn held_keys_repeat_after_a_delay_and_fly_once_let_go() {
+        let mut cycle = CountryCycle::new();
+
+        assert!(cycle.press(Key::Right, Action::NextCountry));
+        // The system's own repeats are ignored.
+        assert!(!cycle.press(Key::Right, Action::NextCountry));
+        assert_eq!(cycle.update(0.3), []);
+        assert_eq!(cycle.update(0.15), [Action::NextCountry]);
+        assert_eq!(cycle.update(0.25), [Action::NextCountry, Action::NextCountry]);
+        assert_eq!(cycle.update(::std::f64::INFINITY), []);
+
+        assert!(!cycle.release(Key::Left));
+        assert!(cycle.release(Key::Right));
+        assert_eq!(cycle.update(1.0), []);
+
+        // Pressing the other key takes over from the one held.
+        cycle.press(Key::Right, Action::NextCountry);
+        assert!(cycle.press(Key::Left, Action::PreviousCountry));
+        assert_eq!(cycle.update(0.4), [Action::PreviousCountry]);
+
+        cycle.cancel();
+        assert!(!cycle.release(Key::Left));
+    }
+
+    #[test]
+    fn steps_wrap_around_the_order() {
+        let order = [4, 2, 7];
+
+        assert_eq!(step(&order, Some(2), 1), Some(7));
+        assert_eq!(step(&order, Some(7), 1), Some(4));
+        assert_eq!(step(&order, Some(4), -1), Some(7));
+        assert_eq!(step(&order, Some(4), 5), Some(7));
+        assert_eq!(step(&order, None, 1), Some(4));
+        assert_eq!(step(&order, Some(9), -1), Some(7));
+        assert_eq!(step(&[], Some(4), 1), None);
+    }
+}
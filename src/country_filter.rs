@@ -0,0 +1,98 @@
+use props::CountryProps;
+
+/// Which countries cycling through them, exports and statistics such as "73rd percentile of 194
+/// countries" go by. The map still draws every country.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountryFilter {
+    #[default]
+    All,
+    /// Countries that are their own sovereign, leaving out dependencies and disputed
+    /// territories.
+    Sovereign,
+    /// Sovereign states with a UN code.
+    UnMembers,
+}
+
+impl CountryFilter {
+    pub const ALL: [CountryFilter; 3] = [
+        CountryFilter::All,
+        CountryFilter::Sovereign,
+        CountryFilter::UnMembers,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CountryFilter::All => "All countries",
+            CountryFilter::Sovereign => "Sovereign states",
+            CountryFilter::UnMembers => "UN members",
+        }
+    }
+
+    /// What the countries it keeps are called in counts of them, as in "194 countries".
+    pub fn noun(&self) -> &'static str {
+        match *self {
+            CountryFilter::All => "countries",
+            CountryFilter::Sovereign => "sovereign states",
+            CountryFilter::UnMembers => "UN members",
+        }
+    }
+
+    /// The filter `steps` on from this one in `ALL`, wrapping around.
+    pub fn cycle(&self, steps: isize) -> CountryFilter {
+        let len = CountryFilter::ALL.len() as isize;
+        let index = CountryFilter::ALL.iter().position(|filter| filter == self).unwrap() as isize;
+
+        CountryFilter::ALL[(index + steps).rem_euclid(len) as usize]
+    }
+
+    pub fn includes(&self, country: &CountryProps) -> bool {
+        match *self {
+            CountryFilter::All => true,
+            CountryFilter::Sovereign => country.sovereign,
+            CountryFilter::UnMembers => country.un_member,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gaia_assetgen::Properties;
+    use serde_json;
+
+    fn country(json: &str) -> CountryProps {
+        let properties: Properties = serde_json::from_str(json).unwrap();
+        CountryProps::from_properties(&properties, &mut vec![])
+    }
+
+    #[test]
+    fn filters_keep_sovereign_states_and_un_members() {
+        let chad = country(r#"{"ADMIN": "Chad", "SOVEREIGNT": "Chad", "UN_A3": "148"}"#);
+        let taiwan = country(r#"{"ADMIN": "Taiwan", "SOVEREIGNT": "Taiwan", "UN_A3": "-099"}"#);
+        let greenland = country(
+            r#"{"ADMIN": "Greenland", "SOVEREIGNT": "Denmark", "TYPE": "Country",
+                "UN_A3": "304"}"#,
+        );
+
+        let kept = |filter: CountryFilter| {
+            [&chad, &taiwan, &greenland]
+                .iter()
+                .filter(|country| filter.includes(country))
+                .map(|country| country.admin.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kept(CountryFilter::All), ["Chad", "Taiwan", "Greenland"]);
+        assert_eq!(kept(CountryFilter::Sovereign), ["Chad", "Taiwan"]);
+        assert_eq!(kept(CountryFilter::UnMembers), ["Chad"]);
+    }
+
+    #[test]
+    fn filters_cycle_and_wrap_around() {
+        assert_eq!(CountryFilter::All.cycle(1), CountryFilter::Sovereign);
+        assert_eq!(CountryFilter::UnMembers.cycle(1), CountryFilter::All);
+        assert_eq!(CountryFilter::All.cycle(-1), CountryFilter::UnMembers);
+        assert_eq!(CountryFilter::Sovereign.cycle(3), CountryFilter::Sovereign);
+    }
+}
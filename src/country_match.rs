@@ -0,0 +1,449 @@
+//! Finding a country from however something outside the dataset names it, such as a session
+//! saved with another asset set.
+//!
+//! A name is matched, from first to last, by:
+//!
+//! 1. Being the country's `features::feature_id`.
+//! 2. Being its ISO 3166-1 alpha-3 or alpha-2 code, in any case, as `iso_a3` and `iso_a2` recover
+//!    them.
+//! 3. Being its `NAME` or `ADMIN`, ignoring case, accents, punctuation and a leading "the", and
+//!    reading "Korea, Republic of" as "Republic of Korea".
+//! 4. Being another name of it in `ALIASES`.
+//! 5. Being spelled close enough to its `NAME` or `ADMIN`, by `MIN_CONFIDENCE`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use gaia_assetgen::Properties;
+use serde_json::Value;
+
+/// How alike, from 0 to 1, a name must be to a country's for a fuzzy match to count.
+pub const MIN_CONFIDENCE: f32 = 0.8;
+
+/// Countries Natural Earth gives an ISO_A3 and ISO_A2 of "-99" in some releases, whose codes are
+/// recovered from ADM0_A3. Other countries' ADM0_A3 are not always ISO codes, such as Kosovo's.
+const RECOVERABLE_CODES: [(&str, &str); 2] = [("FRA", "FR"), ("NOR", "NO")];
+
+/// The other English names of countries, each group naming one country, including the names
+/// Natural Earth has given it in different releases. Written as `normalize` leaves them.
+const ALIASES: &[&[&str]] = &[
+    &["united states of america", "united states", "usa", "us", "america"],
+    &["united kingdom", "uk", "great britain", "britain", "england"],
+    &["russia", "russian federation"],
+    &["south korea", "korea", "republic of korea", "korea rep", "korea south"],
+    &[
+        "north korea",
+        "democratic peoples republic of korea",
+        "korea dem peoples rep",
+        "dprk",
+        "korea north",
+    ],
+    &["iran", "islamic republic of iran"],
+    &["syria", "syrian arab republic"],
+    &["laos", "lao pdr", "lao peoples democratic republic"],
+    &["vietnam", "viet nam"],
+    &["czechia", "czech republic"],
+    &["slovakia", "slovak republic"],
+    &["ivory coast", "cote divoire"],
+    &["democratic republic of the congo", "dem rep congo", "dr congo", "drc", "congo kinshasa"],
+    &["republic of the congo", "republic of congo", "congo", "congo brazzaville"],
+    &["united republic of tanzania", "tanzania"],
+    &["republic of serbia", "serbia"],
+    &["north macedonia", "macedonia", "republic of north macedonia"],
+    &["eswatini", "swaziland", "kingdom of eswatini"],
+    &["myanmar", "burma"],
+    &["east timor", "timor leste"],
+    &["cape verde", "cabo verde"],
+    &["taiwan", "republic of china", "chinese taipei"],
+    &["china", "peoples republic of china", "prc"],
+    &["moldova", "republic of moldova"],
+    &["bolivia", "plurinational state of bolivia", "bolivia plurinational state of"],
+    &["venezuela", "bolivarian republic of venezuela"],
+    &["brunei", "brunei darussalam"],
+    &["palestine", "state of palestine"],
+    &["vatican", "holy see", "vatican city"],
+    &["federated states of micronesia", "micronesia"],
+    &["falkland islands", "falklands", "islas malvinas"],
+    &["bosnia and herzegovina", "bosnia"],
+    &["united arab emirates", "uae"],
+    &["netherlands", "holland"],
+    &["kyrgyzstan", "kyrgyz republic"],
+    &["turkey", "turkiye"],
+];
+
+/// Which way a name was matched to a country.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    Id,
+    IsoCode,
+    Name,
+    Alias,
+    /// Spelled this alike, from `MIN_CONFIDENCE` to 1.
+    Fuzzy(f32),
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Strategy::Id => write!(f, "ID"),
+            Strategy::IsoCode => write!(f, "ISO code"),
+            Strategy::Name => write!(f, "name"),
+            Strategy::Alias => write!(f, "another name"),
+            Strategy::Fuzzy(confidence) => {
+                write!(f, "similar spelling ({:.0}% alike)", 100.0 * confidence)
+            }
+        }
+    }
+}
+
+/// What a name matched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CountryMatch {
+    /// The country `index`, as given to `CountryMatcher::new`.
+    Found { index: usize, strategy: Strategy },
+    /// Nothing, with the country spelled most alike and how alike, if any was.
+    Unmatched { closest: Option<(usize, f32)> },
+}
+
+impl CountryMatch {
+    pub fn index(&self) -> Option<usize> {
+        match *self {
+            CountryMatch::Found { index, .. } => Some(index),
+            CountryMatch::Unmatched { .. } => None,
+        }
+    }
+}
+
+/// The ISO 3166-1 alpha-3 code of the country with `properties`, in capitals: its ISO_A3, or if
+/// Natural Earth marks it missing with "-99", its ADM0_A3 for the few countries where that is the
+/// ISO code.
+pub fn iso_a3(properties: &Properties) -> Option<String> {
+    if let Some(code) = code(properties, "ISO_A3", 3) {
+        return Some(code);
+    }
+
+    let recovered = code(properties, "ADM0_A3", 3)?;
+    RECOVERABLE_CODES
+        .iter()
+        .find(|&&(a3, _)| a3 == recovered)
+        .map(|&(a3, _)| a3.to_string())
+}
+
+/// The ISO 3166-1 alpha-2 code of the country with `properties`, in capitals, recovered as
+/// `iso_a3` recovers the alpha-3 code.
+pub fn iso_a2(properties: &Properties) -> Option<String> {
+    if let Some(code) = code(properties, "ISO_A2", 2) {
+        return Some(code);
+    }
+
+    let a3 = iso_a3(properties)?;
+    RECOVERABLE_CODES
+        .iter()
+        .find(|&&(recoverable, _)| recoverable == a3)
+        .map(|&(_, a2)| a2.to_string())
+}
+
+/// The property `name`, if it is a code of `len` letters, in capitals.
+fn code(properties: &Properties, name: &str, len: usize) -> Option<String> {
+    let code = properties.get(name).and_then(Value::as_str)?.trim();
+    if code.len() != len || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(code.to_ascii_uppercase())
+}
+
+/// `name` lowercased, without accents or punctuation, with "&" read as "and", and without a
+/// leading "the" or a trailing ", The". "Korea, Republic of" is read as "Republic of Korea".
+pub fn normalize(name: &str) -> String {
+    let name = match name.find(',') {
+        Some(comma) if name[comma + 1..].trim().eq_ignore_ascii_case("the") => {
+            name[..comma].to_string()
+        }
+        Some(comma) if is_inverted(&name[comma + 1..]) => {
+            format!("{} {}", &name[comma + 1..], &name[..comma])
+        }
+        _ => name.to_string(),
+    };
+
+    let mut folded = String::new();
+    for c in name.chars().flat_map(char::to_lowercase) {
+        match c {
+            '&' => folded.push_str(" and "),
+            // Dropped rather than split on, so that "Côte d'Ivoire" reads as "cote divoire".
+            '\'' | '\u{2019}' | '.' => {}
+            c if c.is_alphanumeric() => folded.push(fold_accent(c)),
+            _ => folded.push(' '),
+        }
+    }
+
+    let mut words: Vec<&str> = folded.split_whitespace().collect();
+    if words.len() > 1 && words[0] == "the" {
+        words.remove(0);
+    }
+    words.join(" ")
+}
+
+/// Whether `after_comma`, from a name such as "Korea, Republic of", is the start of the name.
+fn is_inverted(after_comma: &str) -> bool {
+    let after_comma = after_comma.trim_end().to_lowercase();
+    after_comma.ends_with(" of") || after_comma.ends_with(" of the")
+}
+
+/// `c` without its accent, for the accented letters in countries' names.
+fn fold_accent(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+        'ç' => 'c',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => 'o',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        _ => c,
+    }
+}
+
+/// How alike `a` and `b` are spelled, from 0 to 1: 1 less their Levenshtein distance as a
+/// fraction of the longer's length. Swapping two letters next to each other counts as one edit,
+/// since "Mexcio" is as likely a typo as "Mexico" missing a letter.
+fn similarity(a: &str, b: &str) -> f32 {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    // The distances from each prefix of `a` to each prefix of `b`, a row per prefix of `a`.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        distances[0][j] = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    1.0 - distances[a.len()][b.len()] as f32 / longest as f32
+}
+
+/// Finds countries by whatever names them. See the module's documentation for how.
+#[derive(Clone, Debug, Default)]
+pub struct CountryMatcher {
+    by_id: HashMap<String, usize>,
+    by_code: HashMap<String, usize>,
+    by_name: HashMap<String, usize>,
+    /// Each country's normalized names, for fuzzy matching.
+    names: Vec<(usize, String)>,
+}
+
+impl CountryMatcher {
+    /// Matches against `countries`, each an index to find it by, its `features::feature_id`, and
+    /// its properties. Where two countries share a code or name, the first is found.
+    pub fn new<'a, I>(countries: I) -> CountryMatcher
+    where
+        I: IntoIterator<Item = (usize, &'a str, &'a Properties)>,
+    {
+        let mut matcher = CountryMatcher::default();
+        for (index, id, properties) in countries {
+            matcher.by_id.entry(id.to_string()).or_insert(index);
+            for code in iso_a3(properties).into_iter().chain(iso_a2(properties)) {
+                matcher.by_code.entry(code).or_insert(index);
+            }
+
+            for property in &["NAME", "ADMIN"] {
+                let name = match properties.get(*property).and_then(Value::as_str) {
+                    Some(name) => normalize(name),
+                    None => continue,
+                };
+                if name.is_empty() || matcher.by_name.contains_key(&name) {
+                    continue;
+                }
+                matcher.by_name.insert(name.clone(), index);
+                matcher.names.push((index, name));
+            }
+        }
+        matcher
+    }
+
+    /// The country `name` names.
+    pub fn find(&self, name: &str) -> CountryMatch {
+        let found = |index: usize, strategy| CountryMatch::Found { index, strategy };
+
+        if let Some(&index) = self.by_id.get(name) {
+            return found(index, Strategy::Id);
+        }
+        let code = name.trim().to_ascii_uppercase();
+        if let Some(&index) = self.by_code.get(&code) {
+            return found(index, Strategy::IsoCode);
+        }
+
+        let normalized = normalize(name);
+        if let Some(&index) = self.by_name.get(&normalized) {
+            return found(index, Strategy::Name);
+        }
+        let aliases = ALIASES.iter().find(|group| group.contains(&normalized.as_str()));
+        let by_alias = aliases
+            .into_iter()
+            .flat_map(|group| group.iter())
+            .filter_map(|alias| self.by_name.get(*alias))
+            .next();
+        if let Some(&index) = by_alias {
+            return found(index, Strategy::Alias);
+        }
+
+        let closest = self
+            .names
+            .iter()
+            .map(|&(index, ref candidate)| (index, similarity(&normalized, candidate)))
+            .fold(None, |best: Option<(usize, f32)>, (index, confidence)| match best {
+                Some((_, best_confidence)) if best_confidence >= confidence => best,
+                _ => Some((index, confidence)),
+            });
+        match closest {
+            Some((index, confidence)) if confidence >= MIN_CONFIDENCE => {
+                found(index, Strategy::Fuzzy(confidence))
+            }
+            _ => CountryMatch::Unmatched { closest },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Countries as Natural Earth has them, and names for them found in other datasets, each
+    /// with the full name of the country it should match, or `null` if it should match none.
+    const FIXTURE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/country_names.json"
+    ));
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        countries: Vec<Properties>,
+        spellings: Vec<(String, Option<String>)>,
+    }
+
+    fn fixture() -> (Fixture, CountryMatcher) {
+        let fixture: Fixture = ::serde_json::from_str(FIXTURE).unwrap();
+        let ids: Vec<String> = fixture
+            .countries
+            .iter()
+            .map(|properties| ::features::feature_id(properties).to_string())
+            .collect();
+        let matcher = CountryMatcher::new(
+            fixture
+                .countries
+                .iter()
+                .zip(&ids)
+                .enumerate()
+                .map(|(index, (properties, id))| (index, id.as_str(), properties)),
+        );
+
+        (fixture, matcher)
+    }
+
+    fn properties(json: &str) -> Properties {
+        ::serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn recovers_iso_codes_natural_earth_leaves_out() {
+        let france = properties(r#"{"ISO_A3": "-99", "ISO_A2": "-99", "ADM0_A3": "FRA"}"#);
+        assert_eq!(iso_a3(&france), Some("FRA".to_string()));
+        assert_eq!(iso_a2(&france), Some("FR".to_string()));
+
+        // Kosovo's ADM0_A3 is not an ISO code.
+        let kosovo = properties(r#"{"ISO_A3": "-99", "ISO_A2": "-99", "ADM0_A3": "KOS"}"#);
+        assert_eq!(iso_a3(&kosovo), None);
+        assert_eq!(iso_a2(&kosovo), None);
+
+        let brazil = properties(r#"{"ISO_A3": "bra", "ISO_A2": "BR", "ADM0_A3": "BRA"}"#);
+        assert_eq!(iso_a3(&brazil), Some("BRA".to_string()));
+        assert_eq!(iso_a2(&brazil), Some("BR".to_string()));
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize("Côte d'Ivoire"), "cote divoire");
+        assert_eq!(normalize("Korea, Republic of"), "republic of korea");
+        assert_eq!(
+            normalize("Congo, Democratic Republic of the"),
+            "democratic republic of the congo"
+        );
+        assert_eq!(normalize("The Bahamas"), "bahamas");
+        assert_eq!(normalize("  Trinidad & Tobago "), "trinidad and tobago");
+        assert_eq!(normalize("Guinea-Bissau"), "guinea bissau");
+    }
+
+    #[test]
+    fn measures_how_alike_names_are_spelled() {
+        assert_eq!(similarity("france", "france"), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("abc", ""), 0.0);
+        assert!((similarity("kitten", "sitting") - (1.0 - 3.0 / 7.0)).abs() < 1e-6);
+        assert!((similarity("mexcio", "mexico") - (1.0 - 1.0 / 6.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn matches_messy_spellings() {
+        let (fixture, matcher) = fixture();
+        assert!(fixture.spellings.len() >= 40);
+
+        let admin = |index: usize| fixture.countries[index]["ADMIN"].as_str().unwrap().to_string();
+        let mismatched: Vec<_> = fixture
+            .spellings
+            .iter()
+            .filter_map(|(spelling, expected)| {
+                let found = matcher.find(spelling).index().map(admin);
+                if found == *expected {
+                    None
+                } else {
+                    Some((spelling, found, expected))
+                }
+            })
+            .collect();
+        assert!(mismatched.is_empty(), "{:#?}", mismatched);
+    }
+
+    #[test]
+    fn says_how_each_name_was_matched() {
+        let (_, matcher) = fixture();
+        let strategy = |name| match matcher.find(name) {
+            CountryMatch::Found { strategy, .. } => Some(strategy),
+            CountryMatch::Unmatched { .. } => None,
+        };
+
+        assert_eq!(strategy("France"), Some(Strategy::Id));
+        assert_eq!(strategy("fra"), Some(Strategy::IsoCode));
+        assert_eq!(strategy("FR"), Some(Strategy::IsoCode));
+        assert_eq!(strategy("united states"), Some(Strategy::Name));
+        assert_eq!(strategy("South Korea"), Some(Strategy::Name));
+        assert_eq!(strategy("Korea, Republic of"), Some(Strategy::Alias));
+        match strategy("Brazill") {
+            Some(Strategy::Fuzzy(confidence)) => assert!(confidence >= MIN_CONFIDENCE),
+            other => panic!("{:?}", other),
+        }
+
+        // Too unlike any country to count, but the closest is still told.
+        match matcher.find("Atlantis") {
+            CountryMatch::Unmatched { closest: Some((_, confidence)) } => {
+                assert!(confidence < MIN_CONFIDENCE)
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::HudStyle;
+
+/// How far each arm of the crosshair reaches from the center.
+const ARM_LENGTH: f64 = 8.0;
+
+/// The empty space left around the exact center, so the point being read out stays visible.
+const GAP: f64 = 2.0;
+
+const FONT_SIZE: u32 = 10;
+
+/// How far below the center the readout's baseline is.
+const READOUT_OFFSET: f64 = 25.0;
+
+/// Draws a crosshair at the center of the window, with `readout` centered below it.
+pub fn draw(
+    readout: &str,
+    style: HudStyle,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+    let [view_width, view_height] = context.get_view_size();
+    let context = context.trans((view_width / 2.0).round(), (view_height / 2.0).round());
+
+    let (arm, gap, width) = (scale.len(ARM_LENGTH), scale.len(GAP), scale.len(1.0));
+    for &[x, y] in &[[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0], [0.0, -1.0]] {
+        ::piston_window::line(
+            theme.text,
+            width,
+            [x * gap, y * gap, x * arm, y * arm],
+            context.transform,
+            graphics,
+        );
+    }
+
+    let readout_width = scale.text_width(FONT_SIZE, readout, glyphs);
+    let padding = scale.len(3.0);
+    let line_height = scale.len(f64::from(FONT_SIZE));
+    let baseline = scale.len(READOUT_OFFSET);
+
+    ::piston_window::rectangle(
+        theme.panel_background,
+        [
+            -readout_width / 2.0 - padding,
+            baseline - line_height - padding,
+            readout_width + 2.0 * padding,
+            line_height + 2.0 * padding,
+        ],
+        context.transform,
+        graphics,
+    );
+
+    scale.draw_text(
+        readout,
+        FONT_SIZE,
+        theme.text,
+        context.trans((-readout_width / 2.0).round(), baseline),
+        glyphs,
+        graphics,
+    );
+}
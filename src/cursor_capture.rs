@@ -0,0 +1,59 @@
+use piston::input::GenericEvent;
+use piston_window::{AdvancedWindow, PistonWindow};
+
+/// Captures the cursor while the camera is being rotated, so that dragging can continue past the
+/// edge of the window. When released, the cursor is put back where the drag began.
+#[derive(Debug)]
+pub struct CursorCapture {
+    /// The last cursor position seen, in window coordinates.
+    cursor: [f64; 2],
+    /// Where the cursor was when it was captured, or `None` if it is not captured.
+    captured_at: Option<[f64; 2]>,
+}
+
+impl CursorCapture {
+    pub fn new() -> CursorCapture {
+        CursorCapture {
+            cursor: [0.0, 0.0],
+            captured_at: None,
+        }
+    }
+
+    pub fn event<E>(&mut self, e: &E)
+    where
+        E: GenericEvent,
+    {
+        // While captured, the window only reports relative motion.
+        e.mouse_cursor(|x, y| {
+            self.cursor = [x, y];
+        });
+    }
+
+    /// Captures or releases the cursor so that it is captured exactly when `capture` is true.
+    pub fn update(&mut self, window: &mut PistonWindow, capture: bool) {
+        match (capture, self.captured_at) {
+            (true, None) => {
+                self.captured_at = Some(self.cursor);
+                window.set_capture_cursor(true);
+            }
+            (false, Some([x, y])) => {
+                self.captured_at = None;
+                window.set_capture_cursor(false);
+
+                let gl_window = &window.window.window;
+                let hidpi_factor = f64::from(gl_window.hidpi_factor());
+                let position = ((x * hidpi_factor) as i32, (y * hidpi_factor) as i32);
+
+                // If the cursor can't be moved, it just stays wherever capturing left it.
+                gl_window.set_cursor_position(position.0, position.1).ok();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for CursorCapture {
+    fn default() -> CursorCapture {
+        CursorCapture::new()
+    }
+}
@@ -0,0 +1,164 @@
+use cgmath::{Matrix4, SquareMatrix, Vector4};
+
+/// The point on the map under the cursor, kept from one event to the next so that it is only
+/// worked out again when the cursor or the camera moved.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CursorPoint {
+    /// The matrix the point was found through, and its inverse, if it has one.
+    mvp: Option<(Matrix4<f32>, Option<Matrix4<f32>>)>,
+    cursor: [f64; 2],
+    view_size: [f64; 2],
+    /// In world space, as `ground_at` gives it.
+    ground: Option<[f32; 2]>,
+}
+
+impl CursorPoint {
+    /// Finds the point under `cursor` in a window `view_size` across, drawn through `mvp`, unless
+    /// it was last found for the same. The inverse of `mvp` is only worked out again when `mvp`
+    /// changed.
+    pub fn update(&mut self, mvp: Matrix4<f32>, cursor: [f64; 2], view_size: [f64; 2]) {
+        let inverse = match self.mvp {
+            Some((last, inverse)) if last == mvp => {
+                if cursor == self.cursor && view_size == self.view_size {
+                    return;
+                }
+                inverse
+            }
+            _ => mvp.invert(),
+        };
+
+        self.mvp = Some((mvp, inverse));
+        self.cursor = cursor;
+        self.view_size = view_size;
+        self.ground = inverse.and_then(|inverse| ground_at(inverse, cursor, view_size));
+    }
+
+    /// The point under the cursor, in map space, or `None` if the cursor is over space.
+    pub fn map_point(&self) -> Option<[f32; 2]> {
+        self.ground.map(map_point)
+    }
+}
+
+/// The point on the ground under `cursor`, in a window `view_size` across drawn through the
+/// inverse of `inverse`, in world space: x from 0 to 2 west to east on the copy of the map the
+/// cursor is over, and y from 0 to 1 south to north. `None` if the cursor is above the horizon
+/// or past a pole. Terrain elevation is ignored.
+pub fn ground_at(
+    inverse: Matrix4<f32>,
+    cursor: [f64; 2],
+    view_size: [f64; 2],
+) -> Option<[f32; 2]> {
+    let [width, height] = view_size;
+    let x = (2.0 * cursor[0] / width - 1.0) as f32;
+    let y = (1.0 - 2.0 * cursor[1] / height) as f32;
+    let near = inverse * Vector4::new(x, y, -1.0, 1.0);
+    let far = inverse * Vector4::new(x, y, 1.0, 1.0);
+    let (near, far) = (near.truncate() / near.w, far.truncate() / far.w);
+
+    // Where the ray from the near plane to the far plane meets the ground.
+    let t = near.z / (near.z - far.z);
+    if !(0.0..=1.0).contains(&t) {
+        return None;
+    }
+
+    let ground = near + (far - near) * t;
+    if ground.y < 0.0 || ground.y > 1.0 {
+        return None;
+    }
+
+    Some([ground.x, ground.y])
+}
+
+/// `ground`, from world space to map space, where x runs from 0 to 1 whichever copy of the map
+/// it is on.
+pub fn map_point(ground: [f32; 2]) -> [f32; 2] {
+    [(ground[0] / 2.0).rem_euclid(1.0), ground[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camera_controller::CameraController;
+    use piston::window::Size;
+    use projection::{self, Projection};
+    use settings::CameraSettings;
+    use split_view::View;
+
+    const VIEW_SIZE: [f64; 2] = [1280.0, 720.0];
+
+    fn mvp_at(latitude: f32, heading: f32, view: View) -> Matrix4<f32> {
+        let mut camera = CameraController::new(CameraSettings::default());
+        camera.move_to(latitude, 10.0, 0.3);
+        camera.set_heading(heading);
+        let projection = Projection::new(Size {
+            width: 1280,
+            height: 720,
+        });
+
+        view.window_matrix() * projection.matrix_in(view) * camera.view_matrix()
+    }
+
+    /// Looking over Europe.
+    fn mvp(heading: f32, view: View) -> Matrix4<f32> {
+        mvp_at(45.0, heading, view)
+    }
+
+    #[test]
+    fn points_under_the_cursor_project_back_onto_it() {
+        let mut checked = 0;
+        for &heading in &[0.0, 1.0, -2.5] {
+            for &view in &[View::Whole, View::Left, View::Right] {
+                let mvp = mvp(heading, view);
+                let inverse = mvp.invert().unwrap();
+                for row in 0..=8 {
+                    for column in 0..=8 {
+                        let cursor = [
+                            VIEW_SIZE[0] * f64::from(column) / 8.0,
+                            VIEW_SIZE[1] * f64::from(row) / 8.0,
+                        ];
+                        let ground = match ground_at(inverse, cursor, VIEW_SIZE) {
+                            Some(ground) => ground,
+                            None => continue,
+                        };
+
+                        let screen = projection::to_screen(mvp, ground, VIEW_SIZE).unwrap();
+                        let distance = (screen[0] - cursor[0]).hypot(screen[1] - cursor[1]);
+                        assert!(distance < 1.0, "{:?} came back at {:?}", cursor, screen);
+                        checked += 1;
+                    }
+                }
+            }
+        }
+
+        assert!(checked > 100, "only {} cursors were over the map", checked);
+    }
+
+    #[test]
+    fn the_point_is_only_found_again_when_something_moved() {
+        let mut point = CursorPoint::default();
+        point.update(mvp(0.0, View::Whole), [640.0, 360.0], VIEW_SIZE);
+        let center = point.map_point();
+        assert!(center.is_some());
+
+        point.update(mvp(0.0, View::Whole), [640.0, 360.0], VIEW_SIZE);
+        assert_eq!(point.map_point(), center);
+
+        point.update(mvp(0.0, View::Whole), [640.0, 600.0], VIEW_SIZE);
+        assert_ne!(point.map_point(), center);
+
+        point.update(mvp(1.0, View::Whole), [640.0, 600.0], VIEW_SIZE);
+        let turned = point.map_point();
+        point.update(mvp(0.0, View::Whole), [640.0, 600.0], VIEW_SIZE);
+        assert_ne!(point.map_point(), turned);
+    }
+
+    #[test]
+    fn there_is_no_point_past_a_pole() {
+        let mut point = CursorPoint::default();
+        point.update(mvp_at(85.0, 0.0, View::Whole), [640.0, 0.0], VIEW_SIZE);
+        assert_eq!(point.map_point(), None);
+
+        point.update(mvp_at(85.0, 0.0, View::Whole), [640.0, 700.0], VIEW_SIZE);
+        assert!(point.map_point().is_some());
+    }
+}
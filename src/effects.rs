@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+/// What an effect does to a country's color.
+#[derive(Clone, Copy)]
+pub enum Paint {
+    /// Draws this color over the country's, composited by its opacity.
+    Color([u8; 4]),
+    /// Replaces the country's color with what this makes of it. Uncolored countries are given to
+    /// it as transparent black.
+    Blend(fn([u8; 4]) -> [u8; 4]),
+}
+
+impl ::std::fmt::Debug for Paint {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Paint::Color(color) => write!(f, "Color({:?})", color),
+            Paint::Blend(_) => write!(f, "Blend(..)"),
+        }
+    }
+}
+
+/// How strongly an effect is applied over its duration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    /// In full throughout.
+    Hold,
+    /// In full at first, fading out to nothing.
+    FadeOut,
+    /// Fading in to full halfway through, then out again.
+    Pulse,
+}
+
+impl Easing {
+    /// How strongly to apply an effect `t` of the way through it, from 0 to 1.
+    fn strength(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match *self {
+            Easing::Hold => 1.0,
+            Easing::FadeOut => 1.0 - t,
+            Easing::Pulse => (PI * t).sin(),
+        }
+    }
+}
+
+/// A change to one country's color on top of whatever its map mode says, for a while.
+#[derive(Clone, Copy, Debug)]
+pub struct Effect {
+    pub paint: Paint,
+    /// How long it lasts, or `None` for until it is removed. Effects that last until removed are
+    /// applied in full.
+    pub duration: Option<Duration>,
+    pub easing: Easing,
+}
+
+/// Names an effect added to an `EffectLayer`, to remove it by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EffectId(u64);
+
+#[derive(Clone, Copy, Debug)]
+struct Started {
+    id: EffectId,
+    start: Duration,
+    effect: Effect,
+}
+
+impl Started {
+    /// How strongly the effect is applied at `now`, or `None` once it is over.
+    fn strength(&self, now: Duration) -> Option<f32> {
+        let duration = match self.effect.duration {
+            Some(duration) => duration,
+            None => return Some(1.0),
+        };
+
+        let elapsed = now.saturating_sub(self.start);
+        if elapsed >= duration {
+            return None;
+        }
+        let t = elapsed.as_secs_f32() / duration.as_secs_f32();
+        Some(self.effect.easing.strength(t))
+    }
+}
+
+/// Temporary changes to countries' colors, such as highlighting the country the tour arrived at,
+/// applied after the map mode has colored them. A country's effects are applied in the order they
+/// were added, each on top of the last, and are dropped once they are over.
+///
+/// Times are `AnimationClock::elapsed`, so that effects stop where they are while the clock is
+/// frozen.
+#[derive(Debug, Default)]
+pub struct EffectLayer {
+    /// By `features::feature_id`, in the order they were added.
+    effects: HashMap<String, Vec<Started>>,
+    next_id: u64,
+}
+
+impl EffectLayer {
+    pub fn new() -> EffectLayer {
+        EffectLayer::default()
+    }
+
+    /// Starts `effect` on the country `id` at `start`.
+    pub fn add(&mut self, id: &str, effect: Effect, start: Duration) -> EffectId {
+        let effect_id = EffectId(self.next_id);
+        self.next_id += 1;
+
+        self.effects.entry(id.to_string()).or_default().push(Started {
+            id: effect_id,
+            start,
+            effect,
+        });
+        effect_id
+    }
+
+    /// Removes the effect `id`, returning whether it was still there.
+    pub fn remove(&mut self, id: EffectId) -> bool {
+        let mut removed = false;
+        for started in self.effects.values_mut() {
+            let len = started.len();
+            started.retain(|started| started.id != id);
+            removed |= started.len() != len;
+        }
+
+        self.effects.retain(|_, started| !started.is_empty());
+        removed
+    }
+
+    /// Removes every effect on the country `id`.
+    pub fn clear_country(&mut self, id: &str) {
+        self.effects.remove(id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Whether any effect changes over time, so that frames must keep coming while it runs.
+    pub fn is_animating(&self) -> bool {
+        self.effects
+            .values()
+            .flatten()
+            .any(|started| started.effect.duration.is_some())
+    }
+
+    /// Drops the effects that are over at `now`. Returns whether there were any.
+    pub fn expire(&mut self, now: Duration) -> bool {
+        let mut expired = false;
+        for started in self.effects.values_mut() {
+            let len = started.len();
+            started.retain(|started| started.strength(now).is_some());
+            expired |= started.len() != len;
+        }
+
+        self.effects.retain(|_, started| !started.is_empty());
+        expired
+    }
+
+    /// `color`, the color of the country `id`, or `None` if it is uncolored, with its effects
+    /// applied as they are at `now`.
+    pub fn apply(&self, id: &str, color: Option<[u8; 4]>, now: Duration) -> Option<[u8; 4]> {
+        let effects = match self.effects.get(id) {
+            Some(effects) => effects,
+            None => return color,
+        };
+
+        let mut color = color.unwrap_or([0, 0, 0, 0]);
+        for started in effects {
+            let strength = match started.strength(now) {
+                Some(strength) => strength,
+                None => continue,
+            };
+
+            color = match started.effect.paint {
+                Paint::Color([r, g, b, a]) => {
+                    over([r, g, b, (f32::from(a) * strength).round() as u8], color)
+                }
+                Paint::Blend(blend) => mix(color, blend(color), strength),
+            };
+        }
+
+        Some(color).filter(|&[_, _, _, a]| a > 0)
+    }
+}
+
+/// `top` composited over `bottom`, both with straight alpha.
+pub fn over(top: [u8; 4], bottom: [u8; 4]) -> [u8; 4] {
+    let top_alpha = f32::from(top[3]) / 255.0;
+    let bottom_alpha = f32::from(bottom[3]) / 255.0 * (1.0 - top_alpha);
+    let alpha = top_alpha + bottom_alpha;
+    if alpha <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+
+    let channel = |i: usize| {
+        let value = (f32::from(top[i]) * top_alpha + f32::from(bottom[i]) * bottom_alpha) / alpha;
+        value.round() as u8
+    };
+    [channel(0), channel(1), channel(2), (alpha * 255.0).round() as u8]
+}
+
+/// `t` of the way from `from` to `to`, channel by channel.
+fn mix(from: [u8; 4], to: [u8; 4], t: f32) -> [u8; 4] {
+    let channel = |i: usize| {
+        let (from, to) = (f32::from(from[i]), f32::from(to[i]));
+        (from + (to - from) * t).round() as u8
+    };
+    [channel(0), channel(1), channel(2), channel(3)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn flash(color: [u8; 4], easing: Easing) -> Effect {
+        Effect {
+            paint: Paint::Color(color),
+            duration: Some(SECOND),
+            easing,
+        }
+    }
+
+    #[test]
+    fn composites_over_with_straight_alpha() {
+        assert_eq!(over([255, 0, 0, 255], [0, 0, 255, 255]), [255, 0, 0, 255]);
+        assert_eq!(over([255, 0, 0, 0], [0, 0, 255, 100]), [0, 0, 255, 100]);
+        assert_eq!(over([0, 0, 0, 0], [0, 0, 0, 0]), [0, 0, 0, 0]);
+
+        // Half red over opaque blue is purple, and half over nothing stays half red.
+        assert_eq!(over([255, 0, 0, 128], [0, 0, 255, 255]), [128, 0, 127, 255]);
+        assert_eq!(over([255, 0, 0, 128], [0, 0, 0, 0]), [255, 0, 0, 128]);
+
+        // Two halves make three quarters.
+        assert_eq!(over([255, 255, 255, 128], [0, 0, 0, 128]), [170, 170, 170, 192]);
+    }
+
+    #[test]
+    fn eases_effects_in_and_out() {
+        assert_eq!(Easing::Hold.strength(0.7), 1.0);
+        assert_eq!(Easing::FadeOut.strength(0.0), 1.0);
+        assert_eq!(Easing::FadeOut.strength(0.25), 0.75);
+        assert!(Easing::Pulse.strength(0.0).abs() < 1e-6);
+        assert!((Easing::Pulse.strength(0.5) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn applies_effects_only_to_their_country() {
+        let start = SECOND * 10;
+        let mut layer = EffectLayer::new();
+        layer.add("FRA", flash([255, 255, 255, 255], Easing::Hold), start);
+
+        let blue = Some([0, 0, 255, 100]);
+        assert_eq!(layer.apply("FRA", blue, start), Some([255, 255, 255, 255]));
+        assert_eq!(layer.apply("DEU", blue, start), blue);
+        // Countries the mode leaves uncolored are colored by their effects.
+        assert_eq!(layer.apply("FRA", None, start), Some([255, 255, 255, 255]));
+        assert_eq!(layer.apply("DEU", None, start), None);
+    }
+
+    #[test]
+    fn expires_effects_once_they_are_over() {
+        let start = SECOND * 10;
+        let mut layer = EffectLayer::new();
+        layer.add("FRA", flash([255, 255, 255, 255], Easing::FadeOut), start);
+        let until_removed = Effect {
+            duration: None,
+            ..flash([255, 0, 0, 255], Easing::Hold)
+        };
+        let held = layer.add("DEU", until_removed, start);
+        assert!(layer.is_animating());
+
+        let halfway = start + SECOND / 2;
+        assert_eq!(layer.apply("FRA", Some([0, 0, 0, 255]), halfway), Some([128, 128, 128, 255]));
+        assert!(!layer.expire(halfway));
+
+        let after = start + SECOND;
+        assert_eq!(layer.apply("FRA", Some([0, 0, 0, 255]), after), Some([0, 0, 0, 255]));
+        assert!(layer.expire(after));
+        assert!(!layer.is_animating());
+        assert_eq!(layer.apply("DEU", None, after + SECOND * 100), Some([255, 0, 0, 255]));
+
+        assert!(layer.remove(held));
+        assert!(!layer.remove(held));
+        assert!(layer.is_empty());
+    }
+
+    #[test]
+    fn stacks_effects_on_the_same_country_in_order() {
+        let start = SECOND * 10;
+        let mut layer = EffectLayer::new();
+        let red = layer.add("FRA", flash([255, 0, 0, 255], Easing::Hold), start);
+        layer.add("FRA", flash([0, 0, 255, 128], Easing::Hold), start);
+        let invert = Effect {
+            paint: Paint::Blend(|[r, g, b, a]| [255 - r, 255 - g, 255 - b, a]),
+            duration: Some(SECOND),
+            easing: Easing::Hold,
+        };
+        layer.add("FRA", invert, start);
+
+        // Red, then half blue over it, then inverted.
+        let green = Some([0, 255, 0, 255]);
+        assert_eq!(layer.apply("FRA", green, start), Some([128, 255, 127, 255]));
+
+        assert!(layer.remove(red));
+        assert_eq!(layer.apply("FRA", green, start), Some([255, 128, 127, 255]));
+
+        layer.clear_country("FRA");
+        assert_eq!(layer.apply("FRA", green, start), green);
+    }
+}
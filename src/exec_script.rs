@@ -0,0 +1,380 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use bindings::{self, Action};
+use parameter_animation::{Curve, ParameterAnimation, Repeat};
+use MapMode;
+use Result;
+
+/// What `--exec` commands are written as, for errors.
+const USAGE: &str = "mode NAME, labels on|off, goto LAT,LON[,HEIGHT], wait SECONDS, \
+                     animate MODE.PARAMETER FROM TO SECONDS [linear|ease_in_out] \
+                     [once|loop|bounce], stop_animation, wait animation, screenshot FILE, quit, \
+                     or an action such as toggle_graticule";
+
+/// What `animate` is written as, for errors.
+const ANIMATE_USAGE: &str = "`animate` takes MODE.PARAMETER FROM TO SECONDS, then optionally \
+                             linear or ease_in_out and once, loop or bounce";
+
+/// One of the commands given with `--exec`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Does what a key bound to the action does.
+    Act(Action),
+    /// Shows labels or hides them, whichever they are.
+    Labels(bool),
+    /// Flies to the place, keeping the camera height when none is given.
+    GoTo {
+        latitude: f32,
+        longitude: f32,
+        height: Option<f32>,
+    },
+    Wait(Duration),
+    /// Animates a mode parameter, in place of any other being animated.
+    Animate(ParameterAnimation),
+    /// Stops animating a parameter, returning it to its value from before.
+    StopAnimation,
+    /// Waits until the parameter being animated finishes, unless it loops.
+    WaitForAnimation,
+    /// Writes the frame to a PNG.
+    Screenshot(PathBuf),
+    Quit,
+}
+
+/// The commands given with `--exec`, run one after another once the demo has started, as the
+/// main loop asks for them. Each waits until the camera has stopped flying and the tiles have
+/// loaded since the last, or until it has waited long enough.
+#[derive(Clone, Debug)]
+pub struct ExecScript {
+    commands: Vec<Command>,
+    next: usize,
+    /// When the last command was handed out.
+    last_started: Option<Instant>,
+    /// Until when `wait` holds the next command back.
+    waiting_until: Option<Instant>,
+    /// Whether `wait animation` holds the next command back.
+    waiting_for_animation: bool,
+    warned: bool,
+}
+
+impl ExecScript {
+    /// Parses commands separated by semicolons, such as `mode income; wait 2; quit`. Relative
+    /// screenshot paths are taken from `dir`. Errors count commands from 1, leaving out empty
+    /// ones, such as after a semicolon at the end.
+    pub fn parse(text: &str, dir: &Path) -> Result<ExecScript> {
+        let mut commands = vec![];
+        let written = text.split(';').map(str::trim).filter(|command| !command.is_empty());
+        for (index, command) in written.enumerate() {
+            let parsed = parse_command(command, dir).map_err(|e| {
+                format!("Invalid --exec command {} ({:?}): {}", index + 1, command, e)
+            })?;
+            commands.push(parsed);
+        }
+
+        if commands.is_empty() {
+            bail!("--exec has no commands");
+        }
+        Ok(ExecScript {
+            commands,
+            next: 0,
+            last_started: None,
+            waiting_until: None,
+            waiting_for_animation: false,
+            warned: false,
+        })
+    }
+
+    /// Whether every command has run, and a `wait` at the end is over.
+    pub fn is_finished(&self) -> bool {
+        self.next == self.commands.len()
+            && self.waiting_until.is_none()
+            && !self.waiting_for_animation
+    }
+
+    /// The next command to run, once a frame has been drawn with `settled` saying whether the
+    /// camera has stopped and the tiles have loaded, and `animating` whether a parameter is being
+    /// animated that will finish, or `None` if it is not time to yet. Returns at most one command
+    /// per frame, so that the frame written by `screenshot` shows the ones before it. Commands
+    /// only wait up to `timeout` to settle.
+    pub fn next(
+        &mut self,
+        settled: bool,
+        animating: bool,
+        timeout: Duration,
+        now: Instant,
+    ) -> Option<Command> {
+        if let (Some(last_started), false) = (self.last_started, settled) {
+            if now.duration_since(last_started) < timeout {
+                return None;
+            }
+            if !self.warned {
+                warn!(
+                    "Still flying or loading tiles after {:.1}s, running the next --exec command \
+                     anyway",
+                    timeout.as_secs_f64()
+                );
+                self.warned = true;
+            }
+        }
+        if let Some(until) = self.waiting_until {
+            if now < until {
+                return None;
+            }
+            self.waiting_until = None;
+        }
+        if self.waiting_for_animation {
+            if animating {
+                return None;
+            }
+            self.waiting_for_animation = false;
+        }
+
+        let command = self.commands.get(self.next)?.clone();
+        self.next += 1;
+        match command {
+            Command::Wait(duration) => {
+                self.waiting_until = Some(now + duration);
+                return None;
+            }
+            Command::WaitForAnimation => {
+                self.waiting_for_animation = true;
+                return None;
+            }
+            _ => {}
+        }
+
+        self.last_started = Some(now);
+        self.warned = false;
+        Some(command)
+    }
+}
+
+fn parse_command(command: &str, dir: &Path) -> Result<Command> {
+    let mut words = command.splitn(2, char::is_whitespace);
+    let verb = words.next().unwrap_or("").to_lowercase();
+    let argument = words.next().map(str::trim).unwrap_or("");
+    let argument = || {
+        if argument.is_empty() {
+            Err(format!("`{}` needs an argument", verb))
+        } else {
+            Ok(argument)
+        }
+    };
+
+    let parsed = match verb.as_str() {
+        "mode" => {
+            let name = argument()?;
+            let mode = deserialize::<MapMode>(&name.to_lowercase())
+                .map_err(|_| format!("unknown map mode {:?}", name))?;
+            Command::Act(bindings::show_mode(mode))
+        }
+        "labels" => match argument()?.to_lowercase().as_str() {
+            "on" => Command::Labels(true),
+            "off" => Command::Labels(false),
+            _ => bail!("`labels` takes on or off"),
+        },
+        "goto" => parse_goto(argument()?)?,
+        "wait" if argument()?.eq_ignore_ascii_case("animation") => Command::WaitForAnimation,
+        "wait" => {
+            let secs = argument()?
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                .ok_or("`wait` takes a number of seconds")?;
+            Command::Wait(Duration::from_secs_f64(secs))
+        }
+        "animate" => parse_animate(argument()?)?,
+        "stop_animation" => Command::StopAnimation,
+        "screenshot" => Command::Screenshot(dir.join(argument()?)),
+        "quit" => Command::Quit,
+        _ => match deserialize::<Action>(&verb) {
+            Ok(action) if argument().is_err() => Command::Act(action),
+            Ok(_) => bail!("`{}` takes no argument", verb),
+            Err(_) => bail!("unknown command `{}`, expected {}", verb, USAGE),
+        },
+    };
+    if (parsed == Command::Quit || parsed == Command::StopAnimation) && argument().is_ok() {
+        bail!("`{}` takes no argument", verb);
+    }
+    Ok(parsed)
+}
+
+/// Parses `goto`'s `LAT,LON[,HEIGHT]`.
+fn parse_goto(argument: &str) -> Result<Command> {
+    let parts = argument
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<::std::result::Result<Vec<_>, _>>()
+        .map_err(|_| "`goto` takes LAT,LON or LAT,LON,HEIGHT")?;
+
+    let (latitude, longitude, height) = match parts[..] {
+        [latitude, longitude] => (latitude, longitude, None),
+        [latitude, longitude, height] => (latitude, longitude, Some(height)),
+        _ => bail!("`goto` takes LAT,LON or LAT,LON,HEIGHT"),
+    };
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        bail!("`goto`'s latitude must be from -90 to 90, and its longitude from -180 to 180");
+    }
+
+    Ok(Command::GoTo {
+        latitude,
+        longitude,
+        height,
+    })
+}
+
+/// Parses `animate`'s `MODE.PARAMETER FROM TO SECONDS [CURVE] [REPEAT]`.
+fn parse_animate(argument: &str) -> Result<Command> {
+    let words: Vec<&str> = argument.split_whitespace().collect();
+    if words.len() < 4 || words.len() > 6 {
+        bail!(ANIMATE_USAGE);
+    }
+
+    let (mode, name) = match words[0].find('.') {
+        Some(dot) => (&words[0][..dot], &words[0][dot + 1..]),
+        None => bail!(ANIMATE_USAGE),
+    };
+    let mode = deserialize::<MapMode>(&mode.to_lowercase())
+        .map_err(|_| format!("unknown map mode {:?}", mode))?;
+    let number = |word: &str| word.parse::<f32>().ok().filter(|value| value.is_finite());
+    let (from, to, secs) = match (number(words[1]), number(words[2]), number(words[3])) {
+        (Some(from), Some(to), Some(secs)) if secs > 0.0 => (from, to, secs),
+        _ => bail!(ANIMATE_USAGE),
+    };
+
+    let mut animation =
+        ParameterAnimation::new(mode, name, from, to, Duration::from_secs_f32(secs))?;
+    for &word in &words[4..] {
+        let word = word.to_lowercase();
+        if let Ok(curve) = deserialize::<Curve>(&word) {
+            animation.curve = curve;
+        } else if let Ok(repeat) = deserialize::<Repeat>(&word) {
+            animation.repeat = repeat;
+        } else {
+            bail!("unknown `animate` option {:?}: {}", word, ANIMATE_USAGE);
+        }
+    }
+
+    Ok(Command::Animate(animation))
+}
+
+fn deserialize<'de, T: Deserialize<'de>>(name: &'de str) -> ::std::result::Result<T, ValueError> {
+    let deserializer: StrDeserializer<ValueError> = name.into_deserializer();
+    T::deserialize(deserializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn parse(text: &str) -> Result<ExecScript> {
+        ExecScript::parse(text, Path::new("/shots"))
+    }
+
+    fn error(text: &str) -> String {
+        parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn commands_are_parsed_in_order() {
+        let script = parse(
+            "mode Income; labels on;; goto 35.68, 139.69, 0.05; wait 2; toggle_graticule; \
+             animate exceptional.hue_speed 0 360 4 linear loop; wait animation; stop_animation; \
+             screenshot tokyo.png; QUIT;",
+        )
+        .unwrap();
+        let mut animation = ParameterAnimation::new(
+            MapMode::Exceptional,
+            "hue_speed",
+            0.0,
+            360.0,
+            Duration::from_secs(4),
+        )
+        .unwrap();
+        animation.curve = Curve::Linear;
+        animation.repeat = Repeat::Loop;
+
+        assert_eq!(
+            script.commands,
+            [
+                Command::Act(Action::ShowIncome),
+                Command::Labels(true),
+                Command::GoTo {
+                    latitude: 35.68,
+                    longitude: 139.69,
+                    height: Some(0.05),
+                },
+                Command::Wait(Duration::from_secs(2)),
+                Command::Act(Action::ToggleGraticule),
+                Command::Animate(animation),
+                Command::WaitForAnimation,
+                Command::StopAnimation,
+                Command::Screenshot(PathBuf::from("/shots/tokyo.png")),
+                Command::Quit,
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_say_which_command_is_wrong() {
+        // Empty commands are not counted.
+        assert_eq!(
+            error("; mode income;; labels maybe"),
+            "Invalid --exec command 2 (\"labels maybe\"): `labels` takes on or off"
+        );
+        assert!(error("mode income; wait -1").starts_with("Invalid --exec command 2 "));
+        assert!(error("goto 95,0").contains("latitude must be from -90 to 90"));
+        assert!(error("goto 1").contains("LAT,LON or LAT,LON,HEIGHT"));
+        assert!(error("mode sepia").contains("unknown map mode \"sepia\""));
+        assert!(error("quit now").contains("`quit` takes no argument"));
+        assert!(error("toggle_graticule twice").contains("takes no argument"));
+        assert!(error("fly").contains("unknown command `fly`"));
+        assert!(error("screenshot").contains("`screenshot` needs an argument"));
+        assert!(error("animate income.hue_speed 0 1 2").contains("has no parameter"));
+        assert!(error("animate exceptional.hue_speed 0 1 0").contains("`animate` takes"));
+        assert!(error("animate exceptional.hue_speed 0 1 2 sideways").contains("option"));
+        assert_eq!(error(" ; ;"), "--exec has no commands");
+    }
+
+    #[test]
+    fn commands_wait_to_settle_up_to_the_timeout() {
+        let mut script = parse("labels on; labels off; quit").unwrap();
+        let start = Instant::now();
+
+        assert_eq!(script.next(false, false, TIMEOUT, start), Some(Command::Labels(true)));
+        // Still flying or loading, so the next waits...
+        assert_eq!(script.next(false, false, TIMEOUT, start + TIMEOUT / 2), None);
+        // ...until the timeout, and runs anyway.
+        assert_eq!(
+            script.next(false, false, TIMEOUT, start + TIMEOUT),
+            Some(Command::Labels(false))
+        );
+        // Once settled, there is no waiting.
+        let settled = start + TIMEOUT + Duration::from_millis(1);
+        assert_eq!(script.next(true, false, TIMEOUT, settled), Some(Command::Quit));
+        assert!(script.is_finished());
+        assert_eq!(script.next(true, false, TIMEOUT, settled), None);
+    }
+
+    #[test]
+    fn waits_hold_the_next_command_back() {
+        let mut script = parse("wait 2; wait animation; quit").unwrap();
+        let start = Instant::now();
+
+        assert_eq!(script.next(true, false, TIMEOUT, start), None);
+        assert_eq!(script.next(true, false, TIMEOUT, start + Duration::from_secs(1)), None);
+        // The wait is over, and `wait animation` holds the rest back while it animates.
+        let later = start + Duration::from_secs(2);
+        assert_eq!(script.next(true, true, TIMEOUT, later), None);
+        assert_eq!(script.next(true, true, TIMEOUT, later), None);
+        assert!(!script.is_finished());
+        assert_eq!(script.next(true, false, TIMEOUT, later), Some(Command::Quit));
+        assert!(script.is_finished());
+    }
+}
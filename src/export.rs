@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use gaia_assetgen::Properties;
+use serde_json::{self, Value};
+
+use geo_index::CountryGeo;
+use props::{CountryProps, IncomeClass};
+use {MapMode, Result, ResultExt};
+
+/// The columns of an export, in the order they are written, named as in the JSON.
+pub const COLUMNS: [&str; 10] = [
+    "name",
+    "iso_a3",
+    "admin",
+    "income_group",
+    "map_color",
+    "population",
+    "mode",
+    "value",
+    "category",
+    "color",
+];
+
+/// What an export says about one country: its main Natural Earth properties, and how the map
+/// mode classifies and colors it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Row {
+    pub name: String,
+    /// Empty if Natural Earth gives it none.
+    pub iso_a3: String,
+    pub admin: String,
+    pub income_group: String,
+    pub map_color: u8,
+    pub population: Option<f64>,
+    /// The map mode's name.
+    pub mode: &'static str,
+    /// What the mode shows about the country in the info panel, if anything.
+    pub value: Option<String>,
+    /// The mode's legend entry for the country, if it has a legend.
+    pub category: Option<String>,
+    /// As `#rrggbbaa`, or `None` if the mode does not color the country.
+    pub color: Option<String>,
+}
+
+impl Row {
+    /// The row for `country` as `mode` shows it, colored `color`.
+    pub fn new(country: &CountryProps, mode: MapMode, color: Option<[u8; 4]>) -> Row {
+        let legend_entries = mode.legend_entries();
+
+        Row {
+            name: country.name.clone(),
+            iso_a3: country.iso_a3.clone().unwrap_or_default(),
+            admin: country.admin.clone(),
+            income_group: country.income.map_or("", IncomeClass::value).to_string(),
+            map_color: country.mapcolor13.unwrap_or(0),
+            population: country.pop_est,
+            mode: mode.name(),
+            value: mode.describe(country),
+            category: mode
+                .category(country)
+                .and_then(|category| legend_entries.get(category))
+                .map(|entry| entry.label.clone()),
+            color: color.map(|[r, g, b, a]| format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)),
+        }
+    }
+
+    fn csv_fields(&self) -> [String; 10] {
+        let optional = |value: Option<&str>| value.unwrap_or("").to_string();
+
+        [
+            self.name.clone(),
+            self.iso_a3.clone(),
+            self.admin.clone(),
+            self.income_group.clone(),
+            self.map_color.to_string(),
+            self.population.map_or_else(String::new, |population| population.to_string()),
+            self.mode.to_string(),
+            optional(self.value.as_deref()),
+            optional(self.category.as_deref()),
+            optional(self.color.as_deref()),
+        ]
+    }
+}
+
+/// The rows for `countries` that `color` gives a color, as the map shows them, sorted by name.
+/// Countries made of several polygons are only listed once.
+pub fn shown_rows<'a, I, F>(countries: I, mode: MapMode, color: F) -> Vec<Row>
+where
+    I: IntoIterator<Item = &'a CountryProps>,
+    F: Fn(&CountryProps) -> Option<[u8; 4]>,
+{
+    let mut seen = HashSet::new();
+    let mut rows: Vec<Row> = countries
+        .into_iter()
+        .filter(|country| seen.insert(country.id.clone()))
+        .filter_map(|country| color(country).map(|color| Row::new(country, mode, Some(color))))
+        .collect();
+    rows.sort_by(|a, b| (&a.name, &a.iso_a3).cmp(&(&b.name, &b.iso_a3)));
+
+    rows
+}
+
+/// Writes `rows` to `path`, as CSV or JSON depending on its extension.
+pub fn write(path: &Path, rows: &[Row]) -> Result<()> {
+    let text = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("csv") => to_csv(rows),
+        Some("json") => to_json(rows)?,
+        _ => bail!(
+            "Cannot export to {}: the file name must end in .csv or .json",
+            path.display()
+        ),
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .chain_err(|| format!("Could not create directory {}", dir.display()))?;
+    }
+    fs::write(path, text).chain_err(|| format!("Could not write {}", path.display()))?;
+
+    info!("Exported {} countries to {}", rows.len(), path.display());
+    Ok(())
+}
+
+/// `rows` as CSV, with a header line, quoting fields as RFC 4180 does.
+pub fn to_csv(rows: &[Row]) -> String {
+    let mut text = COLUMNS.join(",");
+    text.push_str("\r\n");
+
+    for row in rows {
+        let fields: Vec<String> = row.csv_fields().iter().map(|field| csv_field(field)).collect();
+        text.push_str(&fields.join(","));
+        text.push_str("\r\n");
+    }
+
+    text
+}
+
+/// `rows` as a JSON array of objects, with their keys in the order of `COLUMNS`.
+pub fn to_json(rows: &[Row]) -> Result<String> {
+    let mut text =
+        serde_json::to_string_pretty(rows).chain_err(|| "Could not write the export as JSON")?;
+    text.push('\n');
+
+    Ok(text)
+}
+
+/// Everything known about `country`, whose features have `properties`, as pretty-printed JSON:
+/// every one of its properties, where `geo` says it is, and what `mode` shows about it.
+///
+/// Strings are escaped by `serde_json`, control characters included, so whatever is in the data
+/// makes valid JSON.
+pub fn country_json(
+    properties: &Properties,
+    country: &CountryProps,
+    geo: Option<&CountryGeo>,
+    mode: MapMode,
+) -> Result<String> {
+    let row = Row::new(country, mode, None);
+    let mut object = serde_json::Map::new();
+    object.insert("properties".to_string(), Value::Object(properties.clone()));
+    if let Some(geo) = geo {
+        let (latitude, longitude) = geo.centroid;
+        let boxes: Vec<Value> = geo
+            .boxes
+            .iter()
+            .map(|b| Value::from(vec![b.west, b.south, b.east, b.north]))
+            .collect();
+
+        object.insert("centroid".to_string(), Value::from(vec![longitude, latitude]));
+        object.insert("bbox".to_string(), Value::from(boxes));
+        object.insert("area_km2".to_string(), Value::from(geo.area));
+    }
+    object.insert("mode".to_string(), Value::from(row.mode));
+    object.insert("value".to_string(), row.value.map_or(Value::Null, Value::from));
+    object.insert("category".to_string(), row.category.map_or(Value::Null, Value::from));
+
+    serde_json::to_string_pretty(&object).chain_err(|| "Could not write the country as JSON")
+}
+
+/// `field`, quoted if it has anything that would otherwise end it early.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\r' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str) -> Row {
+        Row {
+            name: name.to_string(),
+            iso_a3: "FRA".to_string(),
+            admin: "France".to_string(),
+            income_group: "1. High income: OECD".to_string(),
+            map_color: 7,
+            population: Some(67_059_887.0),
+            mode: "income",
+            value: Some("High income: OECD".to_string()),
+            category: Some("High income: OECD".to_string()),
+            color: Some("#00ff0064".to_string()),
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_they_need_to_be() {
+        assert_eq!(csv_field("France"), "France");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("Korea, Republic of"), "\"Korea, Republic of\"");
+        assert_eq!(csv_field("The \"Gambia\""), "\"The \"\"Gambia\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("two\r\nlines"), "\"two\r\nlines\"");
+    }
+
+    #[test]
+    fn csv_has_a_header_and_a_line_per_row() {
+        let mut unknown = row("Somewhere, \"else\"");
+        unknown.iso_a3 = String::new();
+        unknown.population = None;
+        unknown.value = None;
+        unknown.category = None;
+        unknown.color = None;
+
+        assert_eq!(
+            to_csv(&[row("France"), unknown]),
+            "name,iso_a3,admin,income_group,map_color,population,mode,value,category,color\r\n\
+             France,FRA,France,1. High income: OECD,7,67059887,income,High income: OECD,\
+             High income: OECD,#00ff0064\r\n\
+             \"Somewhere, \"\"else\"\"\",,France,1. High income: OECD,7,,income,,,\r\n"
+        );
+        assert_eq!(to_csv(&[]), format!("{}\r\n", COLUMNS.join(",")));
+    }
+
+    #[test]
+    fn json_keeps_the_columns_in_order_and_reads_back() {
+        let mut tricky = row("Line\nbreak, \"quoted\"");
+        tricky.population = None;
+        let text = to_json(&[row("France"), tricky]).unwrap();
+
+        // Each object's keys come in the order of `COLUMNS`.
+        let object = &text[text.find('{').unwrap()..text.find('}').unwrap()];
+        let positions: Vec<usize> = COLUMNS
+            .iter()
+            .map(|column| object.find(&format!("\"{}\":", column)).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{}", object);
+
+        let parsed: Value = serde_json::from_str(&text).unwrap();
+        let rows = parsed.as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["name"], "France");
+        assert_eq!(rows[0]["population"], 67_059_887.0);
+        assert_eq!(rows[0]["map_color"], 7);
+        assert_eq!(rows[1]["name"], "Line\nbreak, \"quoted\"");
+        assert_eq!(rows[1]["population"], Value::Null);
+        for row in rows {
+            let keys: HashSet<&str> = row.as_object().unwrap().keys().map(|k| &k[..]).collect();
+            assert_eq!(keys, COLUMNS.iter().cloned().collect());
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+
+use gaia_assetgen::{FeaturesData, MultiLevelPolygon, Properties};
+use serde_json;
+
+use country_filter::CountryFilter;
+use country_match::{CountryMatch, CountryMatcher};
+use font_fallback::LabelFallbacks;
+use geo_index::PolygonGrid;
+use label_declutter::{self, Candidate};
+use props::{CountryProps, PlaceProps};
+use tour::{self, Capital};
+use {Result, ResultExt};
+
+/// Where the asset pipeline writes country polygons and city points. This is the same file the
+/// renderer loads them from.
+const FEATURES_PATH: &str = "assets/generated/features.json";
+
+/// The country polygons the map is drawn from, for looking up what is at a point.
+///
+/// All coordinates here are in map space: x from 0 to 1 west to east, and y from 0 to 1 south to
+/// north.
+pub struct Features {
+    polygons: Vec<MultiLevelPolygon>,
+    /// What the map modes read about each of `polygons`, in the same order.
+    countries: Vec<CountryProps>,
+    /// Indices into `countries`, by `feature_id`.
+    by_id: HashMap<String, usize>,
+    /// One polygon of each country with a full name that `filter` keeps, in alphabetical order
+    /// of it.
+    alphabetical: Vec<usize>,
+    filter: CountryFilter,
+    /// Finds the polygons of countries named as other datasets name them.
+    matcher: CountryMatcher,
+    /// The capitals among the places, from west to east.
+    capitals: Vec<Capital>,
+    /// The name of every place, once each, for working out how long ones are labeled.
+    place_names: Vec<String>,
+    /// The Latin name of each place that has one unlike its name, by its name.
+    latin_names: HashMap<String, String>,
+    /// The places that can be labeled, for thinning out those that would overlap.
+    label_candidates: Vec<Candidate>,
+    /// Which of `polygons` might be at each point, for picking.
+    grid: PolygonGrid,
+    /// The properties of countries and places that could not be read.
+    warnings: Vec<String>,
+}
+
+/// A name for the country with `properties` that stays the same across runs and map modes: its
+/// ISO code, or if it has none, its full name.
+pub fn feature_id(properties: &Properties) -> &str {
+    iso_code(properties)
+        .unwrap_or_else(|| properties.get("ADMIN").and_then(|name| name.as_str()).unwrap_or(""))
+}
+
+fn iso_code(properties: &Properties) -> Option<&str> {
+    // Natural Earth gives some countries, such as France, an ISO code of "-99".
+    match properties.get("ISO_A3").and_then(|code| code.as_str()) {
+        Some(code) if code != "-99" && !code.is_empty() => Some(code),
+        _ => None,
+    }
+}
+
+/// Reads every country polygon and city point the asset pipeline generated.
+pub fn load_data() -> Result<FeaturesData> {
+    let path = env::current_dir()
+        .map(|dir| dir.join(FEATURES_PATH))
+        .unwrap_or_else(|_| FEATURES_PATH.into());
+
+    let file = File::open(&path)
+        .chain_err(|| format!("Could not open {}", path.display()))?;
+    let data: FeaturesData = serde_json::from_reader(file)
+        .chain_err(|| format!("Could not parse {}", path.display()))?;
+    info!(
+        "Loaded {} countries and {} places from {}",
+        data.polygons.len(),
+        data.points.len(),
+        path.display()
+    );
+
+    Ok(data)
+}
+
+impl Features {
+    /// The countries in `data`. Its places are left out, since the renderer has its own copy,
+    /// but are checked for properties that cannot be read, and their capitals are kept for the
+    /// capitals tour and their names and positions for labeling.
+    pub fn from_data(data: FeaturesData) -> Features {
+        let mut warnings = vec![];
+        let countries: Vec<CountryProps> = data.polygons
+            .iter()
+            .map(|polygon| CountryProps::from_properties(&polygon.properties, &mut warnings))
+            .collect();
+        let mut place_names: Vec<String> = vec![];
+        let mut latin_names = HashMap::new();
+        for point in &data.points {
+            let place = PlaceProps::from_properties(&point.properties, &mut warnings);
+            place_names.extend(place.name.map(str::to_string));
+            if let (Some(name), Some(latin)) = (place.name, place.latin_name) {
+                if latin != name {
+                    latin_names.insert(name.to_string(), latin.to_string());
+                }
+            }
+        }
+        place_names.sort();
+        place_names.dedup();
+        let by_id: HashMap<String, usize> = countries
+            .iter()
+            .enumerate()
+            .map(|(index, country)| (country.id.clone(), index))
+            .collect();
+        let alphabetical = alphabetical(&countries, &by_id, CountryFilter::All);
+
+        // One polygon of each country, in the order of the polygons, so that where two countries
+        // share a name the first is always found.
+        let matcher = CountryMatcher::new(
+            countries
+                .iter()
+                .enumerate()
+                .filter(|&(index, country)| by_id[&country.id] == index)
+                .map(|(index, country)| {
+                    (index, country.id.as_str(), &data.polygons[index].properties)
+                }),
+        );
+
+        Features {
+            grid: PolygonGrid::new(&data.polygons),
+            polygons: data.polygons,
+            countries,
+            by_id,
+            alphabetical,
+            filter: CountryFilter::All,
+            matcher,
+            capitals: tour::capitals(&data.points),
+            place_names,
+            latin_names,
+            label_candidates: label_declutter::candidates(&data.points),
+            warnings,
+        }
+    }
+
+    /// These features, with `filter` keeping the countries gone through and counted.
+    pub fn filtered(mut self, filter: CountryFilter) -> Features {
+        self.set_filter(filter);
+        self
+    }
+
+    /// Keeps only the countries `filter` does in `alphabetical`.
+    pub fn set_filter(&mut self, filter: CountryFilter) {
+        if filter != self.filter {
+            self.alphabetical = alphabetical(&self.countries, &self.by_id, filter);
+            self.filter = filter;
+        }
+    }
+
+    pub fn filter(&self) -> CountryFilter {
+        self.filter
+    }
+
+    /// The countries `filter` keeps, once each, by one of their polygons.
+    pub fn filtered_countries(&self) -> impl Iterator<Item = &CountryProps> {
+        let filter = self.filter;
+        self.countries
+            .iter()
+            .enumerate()
+            .filter(move |&(index, country)| {
+                self.by_id[&country.id] == index && filter.includes(country)
+            })
+            .map(|(_, country)| country)
+    }
+
+    pub fn polygons(&self) -> &[MultiLevelPolygon] {
+        &self.polygons
+    }
+
+    pub fn properties(&self, polygon: usize) -> &Properties {
+        &self.polygons[polygon].properties
+    }
+
+    /// Every polygon's country, in the same order as the polygons. Countries made of several
+    /// polygons are listed once for each.
+    pub fn countries(&self) -> &[CountryProps] {
+        &self.countries
+    }
+
+    pub fn country(&self, polygon: usize) -> &CountryProps {
+        &self.countries[polygon]
+    }
+
+    /// The country with `properties`, as the renderer passes them, if it is one of these.
+    pub fn country_with(&self, properties: &Properties) -> Option<&CountryProps> {
+        self.by_id
+            .get(feature_id(properties))
+            .map(|&index| &self.countries[index])
+    }
+
+    /// The index of a polygon of the country whose `feature_id` is `id`, if it is one of these.
+    pub fn polygon_of(&self, id: &str) -> Option<usize> {
+        self.by_id.get(id).cloned()
+    }
+
+    /// A polygon of the country `name` names, however another dataset or asset set names it. See
+    /// `country_match` for how.
+    pub fn find_country(&self, name: &str) -> CountryMatch {
+        self.matcher.find(name)
+    }
+
+    /// One polygon of each country that has a full name, in alphabetical order of the names, for
+    /// going through the countries one by one.
+    pub fn alphabetical(&self) -> &[usize] {
+        &self.alphabetical
+    }
+
+    pub fn place_names(&self) -> &[String] {
+        &self.place_names
+    }
+
+    /// The Latin name of the place `name`, if it has one unlike its name.
+    pub fn latin_name(&self, name: &str) -> Option<&str> {
+        self.latin_names.get(name).map(String::as_str)
+    }
+
+    /// What the places are labeled with whose names `covers` says the label font cannot draw.
+    pub fn label_fallbacks<F>(&self, covers: F) -> LabelFallbacks
+    where
+        F: Fn(&str) -> bool,
+    {
+        let names = self.place_names.iter().map(|name| (name.as_str(), self.latin_name(name)));
+        LabelFallbacks::new(names, covers)
+    }
+
+    pub fn label_candidates(&self) -> &[Candidate] {
+        &self.label_candidates
+    }
+
+    /// The capitals among the places, from west to east, as the capitals tour visits them.
+    pub fn capitals(&self) -> &[Capital] {
+        &self.capitals
+    }
+
+    /// The index of the polygon containing `point`, if any.
+    pub fn polygon_at(&self, point: [f32; 2]) -> Option<usize> {
+        self.grid.polygon_at(&self.polygons, point)
+    }
+
+    /// Why properties of countries and places could not be read, one line for each.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+/// One polygon of each of `countries` with a full name that `filter` keeps, in alphabetical order
+/// of it.
+fn alphabetical(
+    countries: &[CountryProps],
+    by_id: &HashMap<String, usize>,
+    filter: CountryFilter,
+) -> Vec<usize> {
+    let mut alphabetical: Vec<usize> = by_id
+        .values()
+        .cloned()
+        .filter(|&index| !countries[index].admin.is_empty() && filter.includes(&countries[index]))
+        .collect();
+    // By ID too, so that countries with the same full name stay in the same order.
+    alphabetical.sort_by(|&a, &b| {
+        let key = |index: usize| (&countries[index].admin, &countries[index].id);
+        key(a).cmp(&key(b))
+    });
+
+    alphabetical
+}
@@ -0,0 +1,135 @@
+//! What to draw when a font has no glyphs for a text, rather than boxes in their place.
+//!
+//! Map labels are drawn with their name if the label font covers it, or else with the place's
+//! Latin name, or else are left out. HUD text is drawn with the HUD font if it covers it, or else
+//! with `hud.font_fallback` if that does.
+//!
+//! gaia draws labels with a font of its own, which cannot be swapped, so the HUD font stands in
+//! for it when working out which names it covers. Both are FiraSans by default.
+
+use std::collections::HashMap;
+
+use rusttype::Font;
+
+/// Whether `font` has a glyph for every character of `text` but whitespace.
+pub fn covers(font: &Font, text: &str) -> bool {
+    covered_by(text, |c| font.glyph(c).map_or(false, |glyph| glyph.id().0 != 0))
+}
+
+/// Whether `has_glyph` is true of every character of `text` but whitespace.
+pub fn covered_by<F>(text: &str, has_glyph: F) -> bool
+where
+    F: Fn(char) -> bool,
+{
+    text.chars().filter(|c| !c.is_whitespace()).all(has_glyph)
+}
+
+/// What each place whose name the label font cannot draw is labeled with instead.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LabelFallbacks {
+    /// Whether the place with each name is labeled with its Latin name, or else not at all.
+    /// Names the font covers are left out.
+    latin: HashMap<String, bool>,
+}
+
+impl LabelFallbacks {
+    /// The fallbacks for `names`, each a place's name and its Latin name if it has one, where
+    /// `covers` says whether the label font can draw a text.
+    pub fn new<'a, I, F>(names: I, covers: F) -> LabelFallbacks
+    where
+        I: IntoIterator<Item = (&'a str, Option<&'a str>)>,
+        F: Fn(&str) -> bool,
+    {
+        let latin = names
+            .into_iter()
+            .filter(|&(name, _)| !covers(name))
+            .map(|(name, latin)| (name.to_string(), latin.map_or(false, &covers)))
+            .collect();
+
+        LabelFallbacks { latin }
+    }
+
+    /// What to label the place `name` with, whose Latin name is `latin`: `name` if the font
+    /// covers it, or else `latin` if the font covers that, or else `None`.
+    pub fn choose<'a>(&self, name: &'a str, latin: Option<&'a str>) -> Option<&'a str> {
+        match self.latin.get(name) {
+            None => Some(name),
+            Some(&true) => latin,
+            Some(&false) => None,
+        }
+    }
+
+    /// How many names are labeled with their Latin names instead.
+    pub fn latin_count(&self) -> usize {
+        self.latin.values().filter(|&&latin| latin).count()
+    }
+
+    /// How many names are not labeled at all.
+    pub fn skipped_count(&self) -> usize {
+        self.latin.len() - self.latin_count()
+    }
+
+    /// Such as "3 place names have characters the label font cannot draw: 2 are labeled with
+    /// their Latin names, and 1 is not labeled", or `None` if every name can be drawn.
+    pub fn summary(&self) -> Option<String> {
+        if self.latin.is_empty() {
+            return None;
+        }
+
+        let count = |n: usize| if n == 1 { "1 is".to_string() } else { format!("{} are", n) };
+        Some(format!(
+            "{} place names have characters the label font cannot draw: {} labeled with their \
+             Latin names, and {} not labeled",
+            self.latin.len(),
+            count(self.latin_count()),
+            count(self.skipped_count())
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A font with only Latin letters, as FiraSans is for the purposes of these tests.
+    fn latin_only(text: &str) -> bool {
+        covered_by(text, |c| c.is_ascii() || "éüñ".contains(c))
+    }
+
+    #[test]
+    fn whitespace_does_not_need_a_glyph() {
+        assert!(latin_only("Rio de Janeiro"));
+        assert!(latin_only("Zürich\n"));
+        assert!(latin_only(""));
+        assert!(!latin_only("東京"));
+        assert!(!latin_only("Tōkyō"));
+    }
+
+    #[test]
+    fn labels_fall_back_to_their_latin_names_or_are_left_out() {
+        let fallbacks = LabelFallbacks::new(
+            vec![
+                ("Zürich", None),
+                ("東京", Some("Tokyo")),
+                ("서울", None),
+                ("Ōsaka", Some("Ōsaka")),
+            ],
+            latin_only,
+        );
+
+        assert_eq!(fallbacks.choose("Zürich", None), Some("Zürich"));
+        assert_eq!(fallbacks.choose("東京", Some("Tokyo")), Some("Tokyo"));
+        assert_eq!(fallbacks.choose("서울", None), None);
+        assert_eq!(fallbacks.choose("Ōsaka", Some("Ōsaka")), None);
+        // Names that were not loaded are drawn as they are.
+        assert_eq!(fallbacks.choose("Paris", None), Some("Paris"));
+
+        assert_eq!((fallbacks.latin_count(), fallbacks.skipped_count()), (1, 2));
+        assert_eq!(
+            fallbacks.summary().unwrap(),
+            "3 place names have characters the label font cannot draw: 1 is labeled with their \
+             Latin names, and 2 are not labeled"
+        );
+        assert_eq!(LabelFallbacks::new(vec![("Lima", None)], latin_only).summary(), None);
+    }
+}
@@ -0,0 +1,236 @@
+use settings::{FormattingSettings, Units};
+
+const KM_PER_MILE: f64 = 1.609_344;
+
+/// The ways of writing numbers that can be picked from the command line and the settings overlay,
+/// by how they write 1234.5, as their decimal and thousands separators.
+pub const NUMBER_STYLES: [(&str, char, Option<char>); 4] = [
+    ("1,234.5", '.', Some(',')),
+    ("1.234,5", ',', Some('.')),
+    ("1 234,5", ',', Some(' ')),
+    ("1234.5", '.', None),
+];
+
+/// The name of the style in `NUMBER_STYLES` that `format` writes numbers in, or `None` if its
+/// separators were configured otherwise.
+pub fn style_name(format: FormattingSettings) -> Option<&'static str> {
+    NUMBER_STYLES
+        .iter()
+        .find(|&&(_, decimal, thousands)| {
+            decimal == format.decimal_separator && thousands == thousands_separator(format)
+        })
+        .map(|&(name, _, _)| name)
+}
+
+/// `format` with its separators set to those of the style `offset` places after its own in
+/// `NUMBER_STYLES`, wrapping around. Separators configured otherwise go to the first style.
+pub fn cycle_style(format: FormattingSettings, offset: isize) -> FormattingSettings {
+    let len = NUMBER_STYLES.len() as isize;
+    let index = match style_name(format) {
+        Some(name) => {
+            let current = NUMBER_STYLES.iter().position(|style| style.0 == name).unwrap();
+            (current as isize + offset).rem_euclid(len) as usize
+        }
+        None => 0,
+    };
+
+    with_style(format, index)
+}
+
+/// `format` with the separators of `NUMBER_STYLES[index]`.
+pub fn with_style(format: FormattingSettings, index: usize) -> FormattingSettings {
+    let (_, decimal, thousands) = NUMBER_STYLES[index];
+
+    FormattingSettings {
+        decimal_separator: decimal,
+        thousands_separator: thousands.unwrap_or(format.thousands_separator),
+        group_thousands: thousands.is_some(),
+        ..format
+    }
+}
+
+fn thousands_separator(format: FormattingSettings) -> Option<char> {
+    if format.group_thousands {
+        Some(format.thousands_separator)
+    } else {
+        None
+    }
+}
+
+/// `n` with `decimals` digits after the decimal separator, and its thousands grouped, such as
+/// "1,234,567.8" or "1.234.567,8".
+pub fn number(n: f64, decimals: usize, format: FormattingSettings) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+
+    let digits = format!("{:.*}", decimals, n.abs());
+    let (whole, fraction) = match digits.find('.') {
+        Some(index) => (&digits[..index], &digits[index + 1..]),
+        None => (&digits[..], ""),
+    };
+
+    // A negative number that rounds to zero is shown as zero, not "-0".
+    let negative = n < 0.0 && digits.chars().any(|c| c.is_ascii_digit() && c != '0');
+    let mut result = if negative { "-".to_string() } else { String::new() };
+
+    for (i, digit) in whole.chars().enumerate() {
+        if let Some(separator) = thousands_separator(format) {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                result.push(separator);
+            }
+        }
+        result.push(digit);
+    }
+
+    if !fraction.is_empty() {
+        result.push(format.decimal_separator);
+        result.push_str(fraction);
+    }
+
+    result
+}
+
+/// The suffixes large counts are shortened with, smallest first, and what each stands for.
+const COUNT_SUFFIXES: [(f64, &str); 3] = [(1e6, "M"), (1e9, "B"), (1e12, "T")];
+
+/// A count, such as a population. Large ones are shortened to one decimal with a suffix if
+/// `format` is compact, as in "1.4B", "12.3M" or "5M". Others are whole numbers with their
+/// thousands grouped, or have two decimals if they are not whole.
+pub fn count(n: f64, format: FormattingSettings) -> String {
+    let magnitude = n.abs();
+    let compact = COUNT_SUFFIXES.iter().rposition(|&(unit, _)| magnitude >= unit);
+
+    match compact {
+        Some(mut index) if format.compact_numbers => {
+            let rounded = |index: usize| (n / COUNT_SUFFIXES[index].0 * 10.0).round() / 10.0;
+            // Rounding can make a thousand of one suffix, as 999,950,000 is 1000.0M, which is
+            // written with the next one instead.
+            if rounded(index).abs() >= 1000.0 && index + 1 < COUNT_SUFFIXES.len() {
+                index += 1;
+            }
+            let shortened = rounded(index);
+            let decimals = if shortened.fract() == 0.0 { 0 } else { 1 };
+
+            format!("{}{}", number(shortened, decimals, format), COUNT_SUFFIXES[index].1)
+        }
+        _ if n.fract() == 0.0 => number(n, 0, format),
+        _ => number(n, 2, format),
+    }
+}
+
+/// How many kilometers long the distance unit of `units` is, and its abbreviation.
+pub fn distance_unit(units: Units) -> (f64, &'static str) {
+    match units {
+        Units::Metric => (1.0, "km"),
+        Units::Imperial => (KM_PER_MILE, "mi"),
+    }
+}
+
+/// `km` in the units of `format`, with `decimals` digits after the decimal separator, such as
+/// "1,234.5 km" or "767.1 mi".
+pub fn distance(km: f64, decimals: usize, format: FormattingSettings) -> String {
+    let (km_per_unit, unit_name) = distance_unit(format.units);
+
+    format!("{} {}", number(km / km_per_unit, decimals, format), unit_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english() -> FormattingSettings {
+        FormattingSettings::default()
+    }
+
+    fn german() -> FormattingSettings {
+        with_style(FormattingSettings::default(), 1)
+    }
+
+    #[test]
+    fn numbers_are_grouped() {
+        assert_eq!(number(1_234_567.8, 1, english()), "1,234,567.8");
+        assert_eq!(number(1_234_567.8, 1, german()), "1.234.567,8");
+        assert_eq!(number(123.0, 0, english()), "123");
+        assert_eq!(number(1234.0, 0, english()), "1,234");
+        assert_eq!(number(-1234.5, 2, english()), "-1,234.50");
+    }
+
+    #[test]
+    fn numbers_can_be_ungrouped() {
+        let plain = with_style(english(), 3);
+
+        assert_eq!(number(1_234_567.8, 1, plain), "1234567.8");
+    }
+
+    #[test]
+    fn rounding_carries_into_the_whole_part() {
+        assert_eq!(number(999.96, 1, english()), "1,000.0");
+    }
+
+    #[test]
+    fn negative_zero_has_no_sign() {
+        assert_eq!(number(-0.001, 2, english()), "0.00");
+    }
+
+    #[test]
+    fn non_finite_numbers_are_named() {
+        assert_eq!(number(::std::f64::NAN, 2, english()), "NaN");
+    }
+
+    #[test]
+    fn large_counts_are_compact() {
+        assert_eq!(count(1_234_567.0, english()), "1.2M");
+        assert_eq!(count(1_380_000_000.0, german()), "1,4B");
+        assert_eq!(count(21_400_000_000_000.0, english()), "21.4T");
+        assert_eq!(count(5_000_000.0, english()), "5M");
+        assert_eq!(count(-12_340_000.0, english()), "-12.3M");
+        assert_eq!(count(2_500_000_000_000_000.0, english()), "2,500T");
+        assert_eq!(count(123_456.0, english()), "123,456");
+        assert_eq!(count(12.5, german()), "12,50");
+    }
+
+    #[test]
+    fn counts_rounding_up_to_a_thousand_take_the_next_suffix() {
+        assert_eq!(count(999_940_000.0, english()), "999.9M");
+        assert_eq!(count(999_950_000.0, english()), "1B");
+        assert_eq!(count(999_960_000_000.0, english()), "1T");
+        assert_eq!(count(999_999.0, english()), "999,999");
+    }
+
+    #[test]
+    fn counts_can_be_written_out() {
+        let format = FormattingSettings {
+            compact_numbers: false,
+            ..english()
+        };
+
+        assert_eq!(count(1_380_000_000.0, format), "1,380,000,000");
+    }
+
+    #[test]
+    fn distances_are_converted() {
+        let imperial = FormattingSettings {
+            units: Units::Imperial,
+            ..english()
+        };
+
+        assert_eq!(distance(1_234_567.8, 1, english()), "1,234,567.8 km");
+        assert_eq!(distance(1_234_567.8, 1, german()), "1.234.567,8 km");
+        assert_eq!(distance(1609.344, 0, imperial), "1,000 mi");
+    }
+
+    #[test]
+    fn styles_cycle() {
+        assert_eq!(style_name(english()), Some("1,234.5"));
+        assert_eq!(style_name(cycle_style(english(), 1)), Some("1.234,5"));
+        assert_eq!(style_name(cycle_style(english(), -1)), Some("1234.5"));
+
+        let custom = FormattingSettings {
+            thousands_separator: '\'',
+            ..english()
+        };
+        assert_eq!(style_name(custom), None);
+        assert_eq!(style_name(cycle_style(custom, 1)), Some("1,234.5"));
+    }
+}
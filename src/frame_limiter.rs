@@ -0,0 +1,202 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long before a frame is due the limiter stops sleeping and spins instead, since sleeps can
+/// overshoot by a millisecond or so.
+const SPIN_TAIL: Duration = Duration::from_micros(1500);
+
+/// How long after the last input, with nothing animating, redraws slow down.
+const IDLE_AFTER: Duration = Duration::from_millis(500);
+
+/// The event loop's frame rate while frames are limited here: high enough not to get in the way.
+const UNLIMITED_FPS: u64 = 1000;
+
+/// The frame rate while throttled with `idle_fps` at 0, which would otherwise never idle.
+const THROTTLED_FPS: u32 = 10;
+
+/// Limits the frame rate, and drops it further while nothing on screen is changing.
+///
+/// The frame limit is applied by sleeping before each frame, so that it is accurate even at high
+/// rates. Idling is left to the event loop instead, which keeps handling input while it waits,
+/// so that the first event after idling is drawn right away.
+#[derive(Debug)]
+pub struct FrameLimiter {
+    /// Frames per second, or 0 for no limit.
+    fps_limit: u32,
+    /// Frames per second while idle, or 0 to never idle.
+    idle_fps: u32,
+    next_frame: Option<Instant>,
+    last_input: Instant,
+    idle: bool,
+    /// Whether to idle whatever is happening, as while the window is not focused.
+    throttled: bool,
+}
+
+impl FrameLimiter {
+    pub fn new(fps_limit: u32, idle_fps: u32) -> FrameLimiter {
+        FrameLimiter {
+            fps_limit,
+            idle_fps,
+            next_frame: None,
+            last_input: Instant::now(),
+            idle: false,
+            throttled: false,
+        }
+    }
+
+    /// Records that input arrived, and so that the view may be about to change.
+    pub fn input(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    /// Sets whether to idle even with input or animations, as while the window is not focused.
+    /// Takes effect at the next `update`.
+    pub fn set_throttled(&mut self, throttled: bool) {
+        self.throttled = throttled;
+    }
+
+    /// Decides whether to idle, given whether anything on screen is animating. Returns the
+    /// event loop's new maximum frame rate if it needs changing.
+    pub fn update(&mut self, animating: bool) -> Option<u64> {
+        let idle = self.throttled
+            || self.idle_fps > 0 && !animating && self.last_input.elapsed() >= IDLE_AFTER;
+        if idle == self.idle {
+            return None;
+        }
+
+        self.idle = idle;
+        self.next_frame = None;
+        Some(self.event_loop_fps())
+    }
+
+    /// The maximum frame rate the event loop should run at.
+    pub fn event_loop_fps(&self) -> u64 {
+        if self.idle {
+            u64::from(self.idle_rate())
+        } else {
+            UNLIMITED_FPS
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Waits until the next frame is due, and returns how long that took. Does nothing while
+    /// idle, or without a limit.
+    pub fn wait(&mut self) -> Duration {
+        if self.idle || self.fps_limit == 0 {
+            return Duration::default();
+        }
+
+        let started = Instant::now();
+        let due = due(self.next_frame, started);
+
+        loop {
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+
+            if let Some(sleep) = sleep_time(due - now) {
+                thread::sleep(sleep);
+            }
+        }
+
+        self.next_frame = Some(due + period(self.fps_limit));
+        started.elapsed()
+    }
+
+    /// The limiter's state, as shown in the performance overlay.
+    pub fn status(&self) -> String {
+        let limit = if self.fps_limit == 0 {
+            "Limit: off".to_string()
+        } else {
+            format!("Limit: {} FPS", self.fps_limit)
+        };
+
+        if self.idle {
+            format!("{} - Idle: {} FPS", limit, self.idle_rate())
+        } else {
+            limit
+        }
+    }
+
+    fn idle_rate(&self) -> u32 {
+        if self.idle_fps > 0 {
+            self.idle_fps
+        } else {
+            THROTTLED_FPS
+        }
+    }
+}
+
+/// When the frame after one due at `next_frame` is drawn, given that it is now `now`.
+///
+/// A frame that is already late is not made up for later, so that a slow frame is not followed by
+/// a burst of fast ones.
+fn due(next_frame: Option<Instant>, now: Instant) -> Instant {
+    match next_frame {
+        Some(due) if due > now => due,
+        _ => now,
+    }
+}
+
+/// How long to sleep with `remaining` left before a frame is due, or `None` to spin the rest.
+fn sleep_time(remaining: Duration) -> Option<Duration> {
+    if remaining > SPIN_TAIL {
+        Some(remaining - SPIN_TAIL)
+    } else {
+        None
+    }
+}
+
+/// The time between frames at `fps_limit` frames per second.
+fn period(fps_limit: u32) -> Duration {
+    Duration::from_nanos(1_000_000_000 / u64::from(fps_limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_due_on_schedule_unless_late() {
+        let now = Instant::now();
+        let later = now + Duration::from_millis(5);
+        assert_eq!(due(Some(later), now), later);
+        assert_eq!(due(Some(now), later), later);
+        assert_eq!(due(None, now), now);
+    }
+
+    #[test]
+    fn the_last_stretch_before_a_frame_is_spun() {
+        assert_eq!(sleep_time(Duration::from_millis(10)), Some(Duration::from_micros(8500)));
+        assert_eq!(sleep_time(SPIN_TAIL), None);
+        assert_eq!(sleep_time(Duration::from_micros(100)), None);
+    }
+
+    #[test]
+    fn frames_are_spaced_by_the_limit() {
+        assert_eq!(period(60), Duration::from_nanos(16_666_666));
+        assert_eq!(period(1000), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn nothing_is_waited_for_without_a_limit_or_while_idle() {
+        let mut limiter = FrameLimiter::new(0, 0);
+        assert_eq!(limiter.wait(), Duration::default());
+        assert_eq!(limiter.status(), "Limit: off");
+
+        let mut limiter = FrameLimiter::new(1, 0);
+        limiter.set_throttled(true);
+        assert_eq!(limiter.update(false), Some(u64::from(THROTTLED_FPS)));
+        assert_eq!(limiter.update(false), None);
+        assert_eq!(limiter.wait(), Duration::default());
+        assert_eq!(limiter.status(), "Limit: 1 FPS - Idle: 10 FPS");
+
+        limiter.set_throttled(false);
+        assert_eq!(limiter.update(true), Some(UNLIMITED_FPS));
+        assert!(!limiter.is_idle());
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use piston_window::PistonWindow;
+
+use screenshot::{self, Screenshots};
+use {Result, ResultExt};
+
+/// Recording stops once the disk it writes to has less than this free.
+const MIN_FREE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// How often free disk space is checked.
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the status shown in the window's title is updated.
+const TITLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Frames are much larger than the queue of a single screenshot allows for, so more can wait and
+/// more threads write them.
+const QUEUE_LEN: usize = 16;
+const WORKERS: usize = 4;
+
+/// What the file listing each frame and how long it was shown is called, in the recording's
+/// directory.
+pub const METADATA_FILE_NAME: &str = "frames.ffconcat";
+
+/// A frame that was queued to be written.
+struct Frame {
+    file_name: String,
+    /// When it was drawn, in seconds since the recording started.
+    time: f64,
+    /// In pixels.
+    size: [u16; 2],
+}
+
+/// Writes every frame drawn to numbered PNGs in a directory, from background threads. When
+/// stopped, it also writes a list of the frames for ffmpeg's concat demuxer, with how long each
+/// was actually shown, so that a video made from them plays at the speed they were drawn:
+///
+/// ```text
+/// ffmpeg -f concat -i frames.ffconcat -vsync vfr -pix_fmt yuv420p recording.mp4
+/// ```
+///
+/// If dropped without being stopped, such as when a benchmark ends, it is stopped then.
+pub struct FrameRecording {
+    dir: PathBuf,
+    writer: Screenshots,
+    started: Instant,
+    frames: Vec<Frame>,
+    last_space_check: Instant,
+    /// When the status was last put in the window's title.
+    last_title: Option<Instant>,
+    /// Set once the list of frames has been written.
+    stopped: bool,
+}
+
+impl FrameRecording {
+    /// Starts recording to `dir`, which is created if needed.
+    pub fn start(dir: PathBuf) -> Result<FrameRecording> {
+        fs::create_dir_all(&dir)
+            .chain_err(|| format!("Could not create recording directory {}", dir.display()))?;
+        check_free_space(&dir)?;
+
+        let writer = Screenshots::with_capacity(dir.clone(), QUEUE_LEN, WORKERS)?;
+        info!("Recording frames to {}", dir.display());
+
+        Ok(FrameRecording {
+            dir,
+            writer,
+            started: Instant::now(),
+            frames: Vec::new(),
+            last_space_check: Instant::now(),
+            last_title: None,
+            stopped: false,
+        })
+    }
+
+    /// A new directory in `parent` to record to, named after the current time.
+    pub fn dir_in(parent: &Path) -> PathBuf {
+        screenshot::timestamped_path(parent, "gaia-recording", "", "", Path::exists)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Queues the current contents of `window`'s color target to be written as the next frame.
+    /// Should be called after all drawing for the frame is complete. Fails if a frame could not
+    /// be written, or the disk is nearly full, after which recording should stop.
+    pub fn frame(&mut self, window: &mut PistonWindow) -> Result<()> {
+        if self.last_space_check.elapsed() >= SPACE_CHECK_INTERVAL {
+            self.last_space_check = Instant::now();
+            check_free_space(&self.dir)?;
+        }
+
+        for result in self.writer.finished() {
+            result?;
+        }
+
+        let time = self.started.elapsed().as_secs_f64();
+        let file_name = format!("frame-{:06}.png", self.frames.len());
+        let (width, height, _, _) = window.output_color.get_dimensions();
+
+        // Dropped frames are left out of the list, so that the frame before lasts until the one
+        // after.
+        if self.writer.capture_to(window, self.dir.join(&file_name))? {
+            self.frames.push(Frame {
+                file_name,
+                time,
+                size: [width, height],
+            });
+        }
+
+        Ok(())
+    }
+
+    /// How many frames were recorded and dropped so far.
+    pub fn status(&self) -> String {
+        format!(
+            "Recording: {} frames, {} dropped",
+            self.frames.len(),
+            self.writer.dropped()
+        )
+    }
+
+    /// The window's title, `title`, with the status after it, if it is time to update it. The
+    /// status is shown there rather than in the HUD, which would be recorded with every frame.
+    pub fn title(&mut self, title: &str) -> Option<String> {
+        if self.last_title.map_or(false, |last| last.elapsed() < TITLE_INTERVAL) {
+            return None;
+        }
+        self.last_title = Some(Instant::now());
+
+        Some(format!("{} - {}", title, self.status()))
+    }
+
+    /// Waits for the frames still being written, and writes the list of frames, even if some
+    /// could not be written. Returns where the list was written.
+    pub fn stop(mut self) -> Result<PathBuf> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<PathBuf> {
+        self.stopped = true;
+        let ended = self.started.elapsed().as_secs_f64();
+        info!("Flushing recorded frames...");
+        for result in self.writer.flush() {
+            if let Err(e) = result {
+                error!("{}", e);
+            }
+        }
+
+        let path = self.dir.join(METADATA_FILE_NAME);
+        fs::write(&path, metadata(&self.frames, ended))
+            .chain_err(|| format!("Could not write {}", path.display()))?;
+
+        info!(
+            "Recorded {} frames to {}, {} dropped",
+            self.frames.len(),
+            self.dir.display(),
+            self.writer.dropped()
+        );
+        Ok(path)
+    }
+
+}
+
+impl Drop for FrameRecording {
+    fn drop(&mut self) {
+        if !self.stopped {
+            if let Err(e) = self.finish() {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+/// Lists `frames` in the ffconcat format, each lasting until the next was drawn, and the last
+/// until `ended`. Resolution changes are noted in comments, since ffmpeg expects every frame to
+/// be the same size.
+fn metadata(frames: &[Frame], ended: f64) -> String {
+    let mut text = "ffconcat version 1.0\n".to_string();
+    writeln!(text, "# Recorded {} frames in {:.3} s", frames.len(), ended).unwrap();
+
+    let mut last_size = None;
+    for (i, frame) in frames.iter().enumerate() {
+        if last_size != Some(frame.size) {
+            writeln!(text, "# Resolution: {}x{}", frame.size[0], frame.size[1]).unwrap();
+            last_size = Some(frame.size);
+        }
+
+        let next = frames.get(i + 1).map_or(ended, |next| next.time);
+        writeln!(text, "# Drawn at {:.6} s", frame.time).unwrap();
+        writeln!(text, "file '{}'", frame.file_name).unwrap();
+        writeln!(text, "duration {:.6}", next - frame.time).unwrap();
+    }
+
+    // ffmpeg ignores the duration of the last file unless it is listed again.
+    if let Some(last) = frames.last() {
+        writeln!(text, "file '{}'", last.file_name).unwrap();
+    }
+
+    text
+}
+
+/// Fails if the disk holding `dir` is nearly full.
+fn check_free_space(dir: &Path) -> Result<()> {
+    if let Some(free) = free_space(dir) {
+        if free < MIN_FREE_BYTES {
+            bail!(
+                "Only {} MiB free on the disk holding {}",
+                free / (1024 * 1024),
+                dir.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// How many bytes are free to write to on the disk holding `dir`, if that can be found out.
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    // Safe since `stats` is only read once `statvfs` has filled it in.
+    unsafe {
+        let mut stats: ::libc::statvfs = mem::zeroed();
+        if ::libc::statvfs(path.as_ptr(), &mut stats) != 0 {
+            return None;
+        }
+
+        // These are narrower than 64 bits on some platforms.
+        #[allow(clippy::unnecessary_cast)]
+        Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+    }
+}
+
+/// Free space is not checked on other platforms.
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(index: usize, time: f64, size: [u16; 2]) -> Frame {
+        Frame {
+            file_name: format!("frame-{:06}.png", index),
+            time,
+            size,
+        }
+    }
+
+    #[test]
+    fn frames_last_until_the_next_was_drawn() {
+        let frames = [
+            frame(0, 0.0, [800, 600]),
+            frame(1, 0.016, [800, 600]),
+            // Frame 2 was dropped, so frame 1 lasts until frame 3.
+            frame(3, 0.05, [1024, 768]),
+        ];
+
+        assert_eq!(
+            metadata(&frames, 0.1),
+            "ffconcat version 1.0\n\
+             # Recorded 3 frames in 0.100 s\n\
+             # Resolution: 800x600\n\
+             # Drawn at 0.000000 s\n\
+             file 'frame-000000.png'\n\
+             duration 0.016000\n\
+             # Drawn at 0.016000 s\n\
+             file 'frame-000001.png'\n\
+             duration 0.034000\n\
+             # Resolution: 1024x768\n\
+             # Drawn at 0.050000 s\n\
+             file 'frame-000003.png'\n\
+             duration 0.050000\n\
+             file 'frame-000003.png'\n"
+        );
+        assert_eq!(metadata(&[], 1.0), "ffconcat version 1.0\n# Recorded 0 frames in 1.000 s\n");
+    }
+}
@@ -0,0 +1,434 @@
+use std::time::{Duration, Instant};
+
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::{HudScale, HudStyle};
+
+/// How many frames the graph shows.
+const CAPACITY: usize = 240;
+
+/// Frames slower than this many milliseconds are drawn in the warning color.
+const WARNING_MS: f32 = 1000.0 / 30.0;
+
+/// Guide lines are drawn at these frame times, in milliseconds: 60 and 30 FPS.
+const GUIDES_MS: [f32; 2] = [1000.0 / 60.0, 1000.0 / 30.0];
+
+/// The graph's vertical scale: a frame this slow, or slower, fills its whole height.
+const GRAPH_MAX_MS: f32 = 50.0;
+
+const GRAPH_HEIGHT: f64 = 100.0;
+const BAR_WIDTH: f64 = 1.0;
+const LINE_HEIGHT: f64 = 15.0;
+const FONT_SIZE: u32 = 10;
+
+const WIDTH: f64 = BAR_WIDTH * CAPACITY as f64;
+
+/// The side of the colored square before each phase's name, below the graph.
+const SWATCH_SIZE: f64 = 8.0;
+
+/// How much room `FrameTimes::draw` needs at a HUD scale of 1, with `details` lines of details.
+fn unscaled_size(details: usize) -> [f64; 2] {
+    // Two lines of statistics, one of phases, the details, and padding below them.
+    [WIDTH, GRAPH_HEIGHT + (4 + details) as f64 * LINE_HEIGHT]
+}
+
+/// How much room `FrameTimes::draw` needs at `scale`, with `details` lines of details.
+pub fn size(scale: HudScale, details: usize) -> [f64; 2] {
+    let [width, height] = unscaled_size(details);
+
+    [scale.len(width), scale.len(height)]
+}
+
+/// The parts of each frame that are timed separately, to tell which one is slow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Handling input and other events.
+    Events,
+    /// Moving the camera, and deciding which tiles to load.
+    Update,
+    /// gaia drawing the map.
+    Render,
+    /// Drawing the HUD over the map.
+    Hud,
+    /// Cleaning up after the frame on the GPU, and swapping buffers.
+    Swap,
+}
+
+/// How many milliseconds a frame spent in each phase, in the order of `Phase::ALL`.
+pub type PhaseTimes = [f32; 5];
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Events,
+        Phase::Update,
+        Phase::Render,
+        Phase::Hud,
+        Phase::Swap,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Events => "events",
+            Phase::Update => "update",
+            Phase::Render => "render",
+            Phase::Hud => "hud",
+            Phase::Swap => "swap",
+        }
+    }
+
+    /// The phase's part of each bar in the graph. These are the same in every theme, and
+    /// distinct from each other and from theme colors.
+    fn color(self) -> [f32; 4] {
+        match self {
+            Phase::Events => [0.6, 0.4, 0.9, 1.0],
+            Phase::Update => [0.2, 0.8, 0.7, 1.0],
+            Phase::Render => [0.3, 0.5, 1.0, 1.0],
+            Phase::Hud => [1.0, 0.6, 0.2, 1.0],
+            Phase::Swap => [0.9, 0.3, 0.6, 1.0],
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The time between recent frames, and how much of it each phase took, kept in fixed-size ring
+/// buffers so that recording them does not allocate.
+pub struct FrameTimes {
+    last_frame: Option<Instant>,
+    /// Milliseconds per frame. The oldest entry is at `next` once the buffer has filled up.
+    times: [f32; CAPACITY],
+    /// The phases of each frame in `times`, at the same index.
+    phases: [PhaseTimes; CAPACITY],
+    /// The phases of the frame in progress, so far.
+    current_phases: PhaseTimes,
+    next: usize,
+    len: usize,
+}
+
+/// Statistics of the recorded frame times, in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    /// How many frames these cover, at most `CAPACITY`.
+    pub frames: usize,
+    pub current: f32,
+    pub average: f32,
+    pub percentile_95: f32,
+    pub percentile_99: f32,
+    pub max: f32,
+}
+
+impl FrameTimes {
+    pub fn new() -> FrameTimes {
+        FrameTimes {
+            last_frame: None,
+            times: [0.0; CAPACITY],
+            phases: [[0.0; 5]; CAPACITY],
+            current_phases: [0.0; 5],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Records that a frame is starting now. The time since the previous call is the previous
+    /// frame's time, so this covers the whole of each loop iteration, not just rendering. The
+    /// phases timed since then are the previous frame's too.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+
+        if let Some(last_frame) = self.last_frame {
+            let phases = self.current_phases;
+            self.push(millis(now.duration_since(last_frame)), phases);
+        }
+
+        self.last_frame = Some(now);
+        self.current_phases = [0.0; 5];
+    }
+
+    /// Records a frame that took `ms`, `phases` of it in each phase, over the oldest one if the
+    /// buffers are full.
+    fn push(&mut self, ms: f32, phases: PhaseTimes) {
+        self.times[self.next] = ms;
+        self.phases[self.next] = phases;
+        self.next = (self.next + 1) % CAPACITY;
+        self.len = (self.len + 1).min(CAPACITY);
+    }
+
+    /// Adds the time since `started` to how long the frame in progress has spent in `phase`.
+    pub fn time(&mut self, phase: Phase, started: Instant) {
+        self.current_phases[phase.index()] += millis(started.elapsed());
+    }
+
+    /// How long the last recorded frame spent in each phase.
+    pub fn last_phases(&self) -> Option<PhaseTimes> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(self.phases[(self.next + CAPACITY - 1) % CAPACITY])
+        }
+    }
+
+    /// How long the frame in progress has spent in each phase so far.
+    pub fn current_phases(&self) -> PhaseTimes {
+        self.current_phases
+    }
+
+    /// How long the frame in progress has spent in `phase` so far.
+    pub fn current_phase(&self, phase: Phase) -> f32 {
+        self.current_phases[phase.index()]
+    }
+
+    /// The indices of the recorded frames, oldest first.
+    fn indices(&self) -> impl Iterator<Item = usize> {
+        let start = (self.next + CAPACITY - self.len) % CAPACITY;
+
+        (0..self.len).map(move |i| (start + i) % CAPACITY)
+    }
+
+    pub fn summary(&self) -> Option<Summary> {
+        self.summary_of(|index| self.times[index])
+    }
+
+    /// Statistics of the time the recorded frames spent in `phase`.
+    pub fn phase_summary(&self, phase: Phase) -> Option<Summary> {
+        self.summary_of(|index| self.phases[index][phase.index()])
+    }
+
+    /// Statistics of `ms` of each recorded frame, by its index.
+    fn summary_of<F>(&self, ms: F) -> Option<Summary>
+    where
+        F: Fn(usize) -> f32,
+    {
+        if self.len == 0 {
+            return None;
+        }
+
+        let mut sorted = [0.0; CAPACITY];
+        let sorted = &mut sorted[..self.len];
+        for (slot, index) in sorted.iter_mut().zip(self.indices()) {
+            *slot = ms(index);
+        }
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let current = ms((self.next + CAPACITY - 1) % CAPACITY);
+        let average = sorted.iter().sum::<f32>() / self.len as f32;
+        let percentile_95 = sorted[(self.len * 95 / 100).min(self.len - 1)];
+        let percentile_99 = sorted[(self.len * 99 / 100).min(self.len - 1)];
+        let max = sorted[self.len - 1];
+
+        Some(Summary {
+            frames: self.len,
+            current,
+            average,
+            percentile_95,
+            percentile_99,
+            max,
+        })
+    }
+
+    /// Draws a bar graph of recent frame times, with statistics and then `details`, such as how
+    /// the window was set up, below it, with the top-left corner at `origin`.
+    pub fn draw(
+        &self,
+        details: &[String],
+        origin: [f64; 2],
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let HudStyle { scale, theme } = style;
+        let [width, height] = unscaled_size(details.len());
+        let context = context.trans(origin[0], origin[1]);
+        // The graph is laid out at a HUD scale of 1 and scaled as a whole.
+        let transform = context.transform.scale(scale.factor, scale.factor);
+
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [0.0, 0.0, width, height],
+            transform,
+            graphics,
+        );
+
+        // Each bar is stacked from its phases, with the rest of the frame, such as waiting for
+        // the frame limit, on top.
+        let px_per_ms = GRAPH_HEIGHT / f64::from(GRAPH_MAX_MS);
+        for (i, index) in self.indices().enumerate() {
+            let ms = self.times[index];
+            let mut remaining = ms.min(GRAPH_MAX_MS);
+            let mut bottom = GRAPH_HEIGHT;
+            let mut part = |part_ms: f32, color, graphics: &mut G2d| {
+                let part_ms = part_ms.min(remaining);
+                remaining -= part_ms;
+                let part_height = f64::from(part_ms) * px_per_ms;
+                bottom -= part_height;
+
+                ::piston_window::rectangle(
+                    color,
+                    [BAR_WIDTH * i as f64, bottom, BAR_WIDTH, part_height],
+                    transform,
+                    graphics,
+                );
+            };
+
+            for (phase, &phase_ms) in Phase::ALL.iter().zip(&self.phases[index]) {
+                part(phase_ms, phase.color(), graphics);
+            }
+            let rest_color = if ms > WARNING_MS {
+                theme.warning
+            } else {
+                theme.muted_text()
+            };
+            part(GRAPH_MAX_MS, rest_color, graphics);
+        }
+
+        for &guide in &GUIDES_MS {
+            let y = GRAPH_HEIGHT - f64::from(guide) * px_per_ms;
+            ::piston_window::line(theme.muted_text(), 0.5, [0.0, y, width, y], transform, graphics);
+        }
+
+        let statistics = match self.summary() {
+            Some(summary) => [
+                format!(
+                    "Current: {:.1} ms - Average: {:.1} ms",
+                    summary.current, summary.average
+                ),
+                format!(
+                    "95th percentile: {:.1} ms - Max: {:.1} ms",
+                    summary.percentile_95, summary.max
+                ),
+            ],
+            None => ["No frames yet".to_string(), String::new()],
+        };
+        let line_y = |i: usize| scale.len(GRAPH_HEIGHT + LINE_HEIGHT * (i + 1) as f64);
+        for (i, line) in statistics.iter().enumerate() {
+            scale.draw_text(
+                line,
+                FONT_SIZE,
+                theme.text,
+                context.trans(scale.len(5.0), line_y(i)),
+                glyphs,
+                graphics,
+            );
+        }
+
+        // The average of each phase, after a swatch of its color in the graph.
+        let baseline = line_y(statistics.len());
+        let mut x = scale.len(5.0);
+        for &phase in &Phase::ALL {
+            let average = self.phase_summary(phase).map_or(0.0, |summary| summary.average);
+            let text = format!("{} {:.1}", phase.name(), average);
+
+            let swatch = scale.len(SWATCH_SIZE);
+            ::piston_window::rectangle(
+                phase.color(),
+                [x, baseline - swatch, swatch, swatch],
+                context.transform,
+                graphics,
+            );
+            x += swatch + scale.len(3.0);
+            scale.draw_text(
+                &text,
+                FONT_SIZE,
+                theme.text,
+                context.trans(x, baseline),
+                glyphs,
+                graphics,
+            );
+            x += scale.text_width(FONT_SIZE, &text, glyphs) + scale.len(8.0);
+        }
+
+        for (i, line) in details.iter().enumerate() {
+            scale.draw_text(
+                line,
+                FONT_SIZE,
+                theme.text,
+                context.trans(scale.len(5.0), line_y(statistics.len() + 1 + i)),
+                glyphs,
+                graphics,
+            );
+        }
+    }
+}
+
+impl Default for FrameTimes {
+    fn default() -> FrameTimes {
+        FrameTimes::new()
+    }
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs() as f32 * 1000.0 + duration.subsec_nanos() as f32 * 1e-6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn there_is_nothing_to_summarize_before_two_frames() {
+        let mut frame_times = FrameTimes::new();
+        assert!(frame_times.summary().is_none());
+        assert!(frame_times.last_phases().is_none());
+
+        frame_times.frame();
+        assert!(frame_times.summary().is_none());
+        frame_times.frame();
+        assert_eq!(frame_times.summary().unwrap().frames, 1);
+    }
+
+    #[test]
+    fn frames_are_summarized_from_oldest_to_newest() {
+        let mut frame_times = FrameTimes::new();
+        for ms in 1..=100 {
+            frame_times.push(ms as f32, [ms as f32, 0.0, 0.0, 0.0, 0.0]);
+        }
+
+        let summary = frame_times.summary().unwrap();
+        assert_eq!(summary.frames, 100);
+        assert_eq!(summary.current, 100.0);
+        assert_eq!(summary.average, 50.5);
+        assert_eq!(summary.percentile_95, 96.0);
+        assert_eq!(summary.percentile_99, 100.0);
+        assert_eq!(summary.max, 100.0);
+
+        assert_eq!(frame_times.phase_summary(Phase::Events).unwrap().max, 100.0);
+        assert_eq!(frame_times.phase_summary(Phase::Render).unwrap().max, 0.0);
+        assert_eq!(frame_times.last_phases().unwrap()[Phase::Events.index()], 100.0);
+    }
+
+    #[test]
+    fn the_oldest_frames_are_forgotten_once_the_buffer_is_full() {
+        let mut frame_times = FrameTimes::new();
+        frame_times.push(1000.0, [0.0; 5]);
+        for _ in 0..CAPACITY {
+            frame_times.push(10.0, [0.0; 5]);
+        }
+
+        let summary = frame_times.summary().unwrap();
+        assert_eq!(summary.frames, CAPACITY);
+        assert_eq!(summary.max, 10.0);
+        assert_eq!(frame_times.indices().count(), CAPACITY);
+        assert_eq!(frame_times.indices().next(), Some(1));
+    }
+
+    #[test]
+    fn phases_add_up_over_the_frame_in_progress() {
+        let mut frame_times = FrameTimes::new();
+        let started = Instant::now() - Duration::from_millis(2);
+        frame_times.time(Phase::Hud, started);
+        frame_times.time(Phase::Hud, started);
+        assert!(frame_times.current_phase(Phase::Hud) >= 4.0);
+        assert_eq!(frame_times.current_phase(Phase::Swap), 0.0);
+
+        frame_times.frame();
+        assert_eq!(frame_times.current_phases(), [0.0; 5]);
+    }
+
+    #[test]
+    fn durations_are_converted_to_milliseconds() {
+        assert_eq!(millis(Duration::from_millis(1500)), 1500.0);
+        assert_eq!(millis(Duration::from_micros(250)), 0.25);
+    }
+}
@@ -0,0 +1,137 @@
+use glutin::{EventsLoop, MonitorId};
+use piston_window::PistonWindow;
+
+use window_placement::WindowGeometry;
+use Result;
+
+/// The windowed-mode placement to restore when leaving fullscreen.
+#[derive(Debug)]
+struct Geometry {
+    position: Option<(i32, i32)>,
+    size: Option<(u32, u32)>,
+}
+
+/// The monitors the window system listed as the demo started.
+#[derive(Clone)]
+pub struct Monitors {
+    available: Vec<MonitorId>,
+    primary: MonitorId,
+}
+
+impl Monitors {
+    /// Lists the monitors. winit lists them only from an events loop, and glutin_window keeps the
+    /// window's own to itself, so one is made for this, once.
+    pub fn list() -> Monitors {
+        let events_loop = EventsLoop::new();
+
+        Monitors {
+            available: events_loop.get_available_monitors().collect(),
+            primary: events_loop.get_primary_monitor(),
+        }
+    }
+
+    pub fn available(&self) -> &[MonitorId] {
+        &self.available
+    }
+
+    pub fn primary(&self) -> &MonitorId {
+        &self.primary
+    }
+
+    pub fn by_index(&self, index: usize) -> Result<MonitorId> {
+        self.available.get(index).cloned().ok_or_else(|| {
+            format!(
+                "There is no monitor with index {} ({} available)",
+                index,
+                self.available.len()
+            ).into()
+        })
+    }
+}
+
+/// Switches the window between windowed and fullscreen mode. The GL context survives the switch,
+/// so the renderer and all of its loaded tiles do not need to be rebuilt.
+pub struct Fullscreen {
+    /// Which monitor to go fullscreen on. If `None`, the monitor the window is currently on is
+    /// used.
+    monitor: Option<MonitorId>,
+    windowed: Option<Geometry>,
+}
+
+impl Fullscreen {
+    pub fn new(monitor: Option<usize>, monitors: &Monitors) -> Result<Fullscreen> {
+        let monitor = match monitor {
+            Some(index) => Some(monitors.by_index(index)?),
+            None => None,
+        };
+
+        Ok(Fullscreen {
+            monitor,
+            windowed: None,
+        })
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.windowed.is_some()
+    }
+
+    /// Where `window` is in windowed mode, or would be back in it if it is fullscreen. `None` if
+    /// the window system does not say.
+    pub fn windowed_geometry(&self, window: &PistonWindow) -> Option<WindowGeometry> {
+        let gl_window = &window.window.window;
+        let (position, size) = match self.windowed {
+            Some(ref geometry) => (geometry.position, geometry.size),
+            None => (gl_window.get_position(), gl_window.get_inner_size()),
+        };
+
+        let ((x, y), (width, height)) = (position?, size?);
+        Some(WindowGeometry {
+            position: [x, y],
+            size: [width, height],
+        })
+    }
+
+    pub fn toggle(&mut self, window: &mut PistonWindow) -> Result<()> {
+        if self.is_fullscreen() {
+            self.leave(window);
+            Ok(())
+        } else {
+            self.enter(window)
+        }
+    }
+
+    pub fn enter(&mut self, window: &mut PistonWindow) -> Result<()> {
+        if self.is_fullscreen() {
+            return Ok(());
+        }
+
+        let gl_window = &window.window.window;
+        let monitor = match self.monitor {
+            Some(ref monitor) => monitor.clone(),
+            None => gl_window.get_current_monitor(),
+        };
+
+        self.windowed = Some(Geometry {
+            position: gl_window.get_position(),
+            size: gl_window.get_inner_size(),
+        });
+
+        gl_window.set_fullscreen(Some(monitor));
+        Ok(())
+    }
+
+    pub fn leave(&mut self, window: &mut PistonWindow) {
+        if let Some(geometry) = self.windowed.take() {
+            let gl_window = &window.window.window;
+            gl_window.set_fullscreen(None);
+
+            if let Some((width, height)) = geometry.size {
+                gl_window.set_inner_size(width, height);
+            }
+
+            if let Some((x, y)) = geometry.position {
+                gl_window.set_position(x, y);
+            }
+        }
+    }
+}
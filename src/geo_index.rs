@@ -0,0 +1,584 @@
+use std::collections::HashMap;
+
+use gaia_assetgen::{MultiLevelPolygon, MAX_LEVEL};
+
+use features;
+
+/// The mean radius of the earth.
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// How many cells `PolygonGrid` divides the map into across, and down. Each is a degree square.
+const GRID_COLUMNS: usize = 360;
+const GRID_ROWS: usize = 180;
+
+/// A range of longitudes and latitudes, in degrees, with `west <= east`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LonLatBox {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+}
+
+/// Where a country is, worked out from all of its polygons.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountryGeo {
+    /// The latitude and longitude, in degrees, of the country's center of area.
+    pub centroid: (f64, f64),
+    /// The smallest range of longitudes covering the country, and its latitudes. A country
+    /// spanning the antimeridian, like Fiji, gets two: the western one ending at 180°, then the
+    /// eastern one starting at -180°.
+    pub boxes: Vec<LonLatBox>,
+    /// In square kilometers.
+    pub area: f64,
+}
+
+/// Every country's `CountryGeo`, by `features::feature_id`, so that what needs to know where a
+/// country is does not have to go through its polygons.
+#[derive(Debug)]
+pub struct GeoIndex {
+    countries: HashMap<String, CountryGeo>,
+}
+
+/// What one polygon contributes to its country.
+#[derive(Clone, Copy, Debug)]
+struct Part {
+    /// In square kilometers.
+    area: f64,
+    centroid: (f64, f64),
+    /// The polygon's own longitudes and latitudes, which never cross the antimeridian.
+    bounds: LonLatBox,
+}
+
+impl GeoIndex {
+    /// Indexes the countries of `polygons`, joining those with the same id.
+    pub fn new(polygons: &[MultiLevelPolygon]) -> GeoIndex {
+        let mut parts: HashMap<&str, Vec<Part>> = HashMap::new();
+        for polygon in polygons {
+            parts
+                .entry(features::feature_id(&polygon.properties))
+                .or_insert_with(Vec::new)
+                .push(part(polygon));
+        }
+
+        GeoIndex {
+            countries: parts
+                .into_iter()
+                .map(|(id, parts)| (id.to_string(), country(&parts)))
+                .collect(),
+        }
+    }
+
+    /// The country whose `features::feature_id` is `id`, if it is one of these.
+    pub fn get(&self, id: &str) -> Option<&CountryGeo> {
+        self.countries.get(id)
+    }
+}
+
+/// Every ring of every one of `polygons` whose `features::feature_id` is `id`, at `level` of
+/// detail, or the most detailed level the polygon has if it is below that.
+pub fn country_rings<'a>(
+    polygons: &'a [MultiLevelPolygon],
+    id: &str,
+    level: u8,
+) -> Vec<&'a [(f32, f32)]> {
+    polygons
+        .iter()
+        .filter(|polygon| features::feature_id(&polygon.properties) == id)
+        .filter_map(|polygon| {
+            let last = polygon.levels.len().checked_sub(1)?;
+            Some(rings(&polygon.levels[usize::from(level).min(last)]))
+        })
+        .flatten()
+        .collect()
+}
+
+/// Which polygons might contain the points of each cell of a grid over the map, so that finding
+/// the polygon at a point only tests the few whose bounding boxes reach its cell.
+///
+/// Like the polygons, the grid is in map space: x from 0 to 1 west to east, and y from 0 to 1
+/// south to north. Polygons that reach past the antimeridian, below 0 or above 1, wrap around
+/// into the cells on the other side.
+#[derive(Debug)]
+pub struct PolygonGrid {
+    /// Indices of polygons, by cell, row by row from the south.
+    cells: Vec<Vec<usize>>,
+}
+
+impl PolygonGrid {
+    pub fn new(polygons: &[MultiLevelPolygon]) -> PolygonGrid {
+        let mut cells = vec![Vec::new(); GRID_COLUMNS * GRID_ROWS];
+        for (index, polygon) in polygons.iter().enumerate() {
+            let [(min_x, max_x), (min_y, max_y)] = polygon.bounding_box;
+            let columns = cell(min_x, GRID_COLUMNS)..=cell(max_x, GRID_COLUMNS);
+            let last_row = GRID_ROWS as i64 - 1;
+            let rows = cell(min_y, GRID_ROWS).max(0)..=cell(max_y, GRID_ROWS).min(last_row);
+
+            // A polygon wider than the map covers every column once.
+            let columns: Vec<usize> = if columns.end() - columns.start() >= GRID_COLUMNS as i64 {
+                (0..GRID_COLUMNS).collect()
+            } else {
+                columns.map(|column| column.rem_euclid(GRID_COLUMNS as i64) as usize).collect()
+            };
+            for row in rows {
+                for &column in &columns {
+                    cells[row as usize * GRID_COLUMNS + column].push(index);
+                }
+            }
+        }
+
+        PolygonGrid { cells }
+    }
+
+    /// The index of the first of `polygons`, which the grid was made from, containing `point`.
+    pub fn polygon_at(&self, polygons: &[MultiLevelPolygon], point: [f32; 2]) -> Option<usize> {
+        let [x, y] = point;
+        if !(0.0..=1.0).contains(&y) {
+            return None;
+        }
+        let x = x.rem_euclid(1.0);
+        let column = cell(x, GRID_COLUMNS).min(GRID_COLUMNS as i64 - 1) as usize;
+        let row = cell(y, GRID_ROWS).min(GRID_ROWS as i64 - 1) as usize;
+
+        self.cells[row * GRID_COLUMNS + column]
+            .iter()
+            .cloned()
+            .find(|&index| {
+                // The point is tested where the polygon is, if it wraps around the antimeridian.
+                [x, x - 1.0, x + 1.0].iter().any(|&x| {
+                    bounding_box_contains(&polygons[index], [x, y])
+                        && contains(&polygons[index], [x, y])
+                })
+            })
+    }
+}
+
+/// The grid cell `coordinate`, in map space, is in, when there are `count` cells from 0 to 1.
+/// Coordinates past either end give cells past the grid.
+fn cell(coordinate: f32, count: usize) -> i64 {
+    (f64::from(coordinate) * count as f64).floor() as i64
+}
+
+fn bounding_box_contains(polygon: &MultiLevelPolygon, point: [f32; 2]) -> bool {
+    let [(min_x, max_x), (min_y, max_y)] = polygon.bounding_box;
+
+    min_x <= point[0] && point[0] <= max_x && min_y <= point[1] && point[1] <= max_y
+}
+
+/// The rings of a level: the exterior, then any holes.
+///
+/// Each level holds them all flattened together. Every ring is closed, ending on the point it
+/// starts on, which is how they are told apart.
+fn rings(points: &[(f32, f32)]) -> Vec<&[(f32, f32)]> {
+    let mut rings = Vec::new();
+    let mut ring_start = 0;
+    for i in 1..points.len() {
+        if i > ring_start + 1 && points[i] == points[ring_start] {
+            rings.push(&points[ring_start..=i]);
+            ring_start = i + 1;
+        }
+    }
+    if ring_start < points.len() {
+        rings.push(&points[ring_start..]);
+    }
+
+    rings
+}
+
+/// Even-odd point-in-polygon test against the most detailed level, which counts holes as
+/// outside.
+///
+/// Of two polygons sharing an edge, points on it are only in one: the one to the east of a
+/// shared north-south edge, for instance.
+fn contains(polygon: &MultiLevelPolygon, point: [f32; 2]) -> bool {
+    let (x, y) = (point[0], point[1]);
+
+    let mut inside = false;
+    for ring in rings(&polygon.levels[MAX_LEVEL as usize]) {
+        for edge in ring.windows(2) {
+            let ((x0, y0), (x1, y1)) = (edge[0], edge[1]);
+            if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+/// The area, centroid and bounds of `polygon`'s most detailed level.
+///
+/// Area and centroid are worked out in the cylindrical equal-area projection, where plane area is
+/// proportional to area on the globe, and whose longitudes are those of the map. Holes wind the
+/// other way from the exterior, so they subtract from both.
+fn part(polygon: &MultiLevelPolygon) -> Part {
+    let project = |&(x, y): &(f32, f32)| {
+        let (longitude, latitude) = (f64::from(x) * 360.0 - 180.0, f64::from(y) * 180.0 - 90.0);
+        (longitude.to_radians(), latitude.to_radians().sin())
+    };
+
+    let (mut twice_area, mut x, mut y) = (0.0, 0.0, 0.0);
+    for ring in rings(&polygon.levels[MAX_LEVEL as usize]) {
+        for (i, point) in ring.iter().enumerate() {
+            let (x0, y0) = project(point);
+            let (x1, y1) = project(&ring[(i + 1) % ring.len()]);
+            let cross = x0 * y1 - x1 * y0;
+
+            twice_area += cross;
+            x += (x0 + x1) * cross;
+            y += (y0 + y1) * cross;
+        }
+    }
+
+    let [(min_x, max_x), (min_y, max_y)] = polygon.bounding_box;
+    let bounds = LonLatBox {
+        west: f64::from(min_x) * 360.0 - 180.0,
+        south: f64::from(min_y) * 180.0 - 90.0,
+        east: f64::from(max_x) * 360.0 - 180.0,
+        north: f64::from(max_y) * 180.0 - 90.0,
+    };
+
+    // A degenerate polygon has no center of area, so the middle of its bounds stands in.
+    let centroid = if twice_area.abs() > 1e-12 {
+        let (x, y) = (x / (3.0 * twice_area), y / (3.0 * twice_area));
+        (y.max(-1.0).min(1.0).asin().to_degrees(), x.to_degrees())
+    } else {
+        ((bounds.south + bounds.north) / 2.0, (bounds.west + bounds.east) / 2.0)
+    };
+
+    Part {
+        area: twice_area.abs() / 2.0 * EARTH_RADIUS_KM * EARTH_RADIUS_KM,
+        centroid,
+        bounds,
+    }
+}
+
+/// Joins the parts of a country.
+fn country(parts: &[Part]) -> CountryGeo {
+    let area: f64 = parts.iter().map(|part| part.area).sum();
+
+    // Longitudes are averaged on the side of the antimeridian the largest part is on, so that
+    // parts either side of it, like Fiji's, are not averaged to the other side of the globe.
+    let largest = parts
+        .iter()
+        .max_by(|a, b| a.area.partial_cmp(&b.area).unwrap())
+        .map_or(0.0, |part| part.centroid.1);
+    let (mut latitude, mut longitude) = (0.0, 0.0);
+    for part in parts {
+        let weight = if area > 0.0 { part.area / area } else { 1.0 / parts.len() as f64 };
+        let (part_latitude, part_longitude) = part.centroid;
+        let turns = ((part_longitude - largest) / 360.0).round();
+
+        latitude += weight * part_latitude;
+        longitude += weight * (part_longitude - 360.0 * turns);
+    }
+    let longitude = (longitude + 180.0).rem_euclid(360.0) - 180.0;
+
+    CountryGeo {
+        centroid: (latitude, longitude),
+        boxes: boxes(parts),
+        area,
+    }
+}
+
+/// The boxes covering `parts`: the latitudes of them all, and the longitudes left after taking
+/// out the widest range none of them cover.
+fn boxes(parts: &[Part]) -> Vec<LonLatBox> {
+    let south = parts.iter().map(|part| part.bounds.south).fold(90.0, f64::min);
+    let north = parts.iter().map(|part| part.bounds.north).fold(-90.0, f64::max);
+
+    let mut ranges: Vec<(f64, f64)> = parts
+        .iter()
+        .map(|part| (part.bounds.west, part.bounds.east))
+        .collect();
+    ranges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut merged: Vec<(f64, f64)> = Vec::new();
+    for (west, east) in ranges {
+        match merged.last_mut() {
+            Some(last) if west <= last.1 => last.1 = last.1.max(east),
+            _ => merged.push((west, east)),
+        }
+    }
+
+    let lon_box = |west, east| LonLatBox { west, south, east, north };
+    let (first, last) = match (merged.first(), merged.last()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return Vec::new(),
+    };
+
+    // The gap after each range, the last one wrapping around to the first.
+    let widest = (0..merged.len())
+        .map(|i| match merged.get(i + 1) {
+            Some(next) => (i, next.0 - merged[i].1),
+            None => (i, first.0 + 360.0 - last.1),
+        })
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map_or(merged.len() - 1, |(i, _)| i);
+
+    if widest == merged.len() - 1 {
+        vec![lon_box(first.0, last.1)]
+    } else {
+        vec![lon_box(merged[widest + 1].0, 180.0), lon_box(-180.0, merged[widest].1)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gaia_assetgen::Properties;
+    use serde_json::Value;
+
+    use super::*;
+
+    /// The map-space point at a longitude and latitude.
+    fn point(longitude: f32, latitude: f32) -> (f32, f32) {
+        ((longitude + 180.0) / 360.0, (latitude + 90.0) / 180.0)
+    }
+
+    /// A closed ring around the given degrees, clockwise, or counterclockwise for a hole.
+    fn ring(west: f32, south: f32, east: f32, north: f32, hole: bool) -> Vec<(f32, f32)> {
+        let mut ring = vec![
+            point(west, south),
+            point(west, north),
+            point(east, north),
+            point(east, south),
+            point(west, south),
+        ];
+        if hole {
+            ring.reverse();
+        }
+        ring
+    }
+
+    /// A polygon of `iso` spanning the given degrees, as the asset pipeline writes it: in map
+    /// space, clockwise and closed.
+    fn rectangle(iso: &str, west: f32, south: f32, east: f32, north: f32) -> MultiLevelPolygon {
+        with_holes(iso, west, south, east, north, &[])
+    }
+
+    /// Like `rectangle`, with rectangular `holes` appended to the outline.
+    fn with_holes(
+        iso: &str,
+        west: f32,
+        south: f32,
+        east: f32,
+        north: f32,
+        holes: &[[f32; 4]],
+    ) -> MultiLevelPolygon {
+        let mut outline = ring(west, south, east, north, false);
+        for &[west, south, east, north] in holes {
+            outline.extend(ring(west, south, east, north, true));
+        }
+
+        let mut properties = Properties::new();
+        properties.insert("ISO_A3".to_string(), Value::from(iso));
+
+        MultiLevelPolygon {
+            properties,
+            bounding_box: [
+                (point(west, south).0, point(east, north).0),
+                (point(west, south).1, point(east, north).1),
+            ],
+            levels: vec![outline; MAX_LEVEL as usize + 1],
+        }
+    }
+
+    /// The `feature_id` of the polygon at a longitude and latitude, if any.
+    fn pick(polygons: &[MultiLevelPolygon], longitude: f32, latitude: f32) -> Option<&str> {
+        let (x, y) = point(longitude, latitude);
+
+        PolygonGrid::new(polygons)
+            .polygon_at(polygons, [x, y])
+            .map(|index| features::feature_id(&polygons[index].properties))
+    }
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    fn assert_boxes(geo: &CountryGeo, expected: &[(f64, f64)]) {
+        let longitudes: Vec<(f64, f64)> = geo.boxes.iter().map(|b| (b.west, b.east)).collect();
+
+        assert_eq!(longitudes.len(), expected.len(), "{:?}", longitudes);
+        for (&(west, east), &(expected_west, expected_east)) in longitudes.iter().zip(expected) {
+            assert_near(west, expected_west, 1e-3);
+            assert_near(east, expected_east, 1e-3);
+        }
+    }
+
+    #[test]
+    fn a_rectangle_is_centered_by_area() {
+        let index = GeoIndex::new(&[rectangle("AAA", 0.0, 0.0, 10.0, 60.0)]);
+        let geo = index.get("AAA").unwrap();
+
+        // Half of the area between the equator and 60°N is below 25.66°N.
+        let (latitude, longitude) = geo.centroid;
+        assert_near(latitude, (60f64.to_radians().sin() / 2.0).asin().to_degrees(), 1e-3);
+        assert_near(longitude, 5.0, 1e-3);
+
+        let expected_area = 10f64.to_radians() * 60f64.to_radians().sin() * EARTH_RADIUS_KM.powi(2);
+        assert_near(geo.area, expected_area, expected_area * 1e-4);
+        assert_boxes(geo, &[(0.0, 10.0)]);
+    }
+
+    #[test]
+    fn fiji_spans_the_antimeridian() {
+        let index = GeoIndex::new(&[
+            rectangle("FJI", 177.0, -19.0, 180.0, -16.0),
+            rectangle("FJI", -180.0, -17.0, -179.0, -16.0),
+        ]);
+        let geo = index.get("FJI").unwrap();
+
+        let (latitude, longitude) = geo.centroid;
+        assert!(longitude > 178.0 && longitude < 180.0, "{}", longitude);
+        assert!(latitude > -19.0 && latitude < -16.0, "{}", latitude);
+        assert_boxes(geo, &[(177.0, 180.0), (-180.0, -179.0)]);
+        assert_near(geo.boxes[0].south, -19.0, 1e-3);
+        assert_near(geo.boxes[1].north, -16.0, 1e-3);
+    }
+
+    #[test]
+    fn russia_includes_chukotka_across_the_antimeridian() {
+        let index = GeoIndex::new(&[
+            rectangle("RUS", 27.0, 41.0, 180.0, 78.0),
+            rectangle("RUS", -180.0, 64.0, -169.0, 71.0),
+            // Kaliningrad.
+            rectangle("RUS", 19.6, 54.3, 22.9, 55.3),
+        ]);
+        let geo = index.get("RUS").unwrap();
+
+        let (_, longitude) = geo.centroid;
+        assert!(longitude > 90.0 && longitude < 110.0, "{}", longitude);
+        assert_boxes(geo, &[(19.6, 180.0), (-180.0, -169.0)]);
+    }
+
+    #[test]
+    fn usa_with_alaska_is_one_box() {
+        let index = GeoIndex::new(&[
+            rectangle("USA", -125.0, 25.0, -67.0, 49.0),
+            rectangle("USA", -168.0, 54.0, -141.0, 71.0),
+            // Hawaii.
+            rectangle("USA", -160.0, 19.0, -155.0, 22.0),
+        ]);
+        let geo = index.get("USA").unwrap();
+
+        // Alaska and Hawaii pull the center west of the contiguous states' middle.
+        let (latitude, longitude) = geo.centroid;
+        assert!(longitude < -96.0 && longitude > -120.0, "{}", longitude);
+        assert!(latitude > 37.0, "{}", latitude);
+        assert_boxes(geo, &[(-168.0, -67.0)]);
+        assert_near(geo.boxes[0].south, 19.0, 1e-3);
+        assert_near(geo.boxes[0].north, 71.0, 1e-3);
+    }
+
+    #[test]
+    fn a_shared_border_belongs_to_one_country() {
+        let polygons = [
+            rectangle("AAA", 0.0, 0.0, 10.0, 10.0),
+            rectangle("BBB", 10.0, 0.0, 20.0, 10.0),
+        ];
+        let (x, y) = point(10.0, 5.0);
+        let containing = polygons
+            .iter()
+            .filter(|polygon| contains(polygon, [x, y]))
+            .count();
+
+        assert_eq!(containing, 1);
+        assert!(pick(&polygons, 10.0, 5.0).is_some());
+        assert_eq!(pick(&polygons, 9.9, 5.0), Some("AAA"));
+        assert_eq!(pick(&polygons, 10.1, 5.0), Some("BBB"));
+    }
+
+    #[test]
+    fn an_enclave_is_picked_instead_of_the_country_around_it() {
+        let lesotho = [27.0, -30.7, 29.5, -28.5];
+        let polygons = [
+            with_holes("ZAF", 16.0, -35.0, 33.0, -22.0, &[lesotho]),
+            rectangle("LSO", lesotho[0], lesotho[1], lesotho[2], lesotho[3]),
+        ];
+
+        assert_eq!(pick(&polygons, 28.0, -29.5), Some("LSO"));
+        assert_eq!(pick(&polygons, 25.0, -29.5), Some("ZAF"));
+        assert_eq!(pick(&polygons, 40.0, -29.5), None);
+    }
+
+    #[test]
+    fn holes_after_the_first_are_outside_too() {
+        let polygons = [with_holes(
+            "AAA",
+            0.0,
+            0.0,
+            30.0,
+            10.0,
+            &[[5.0, 2.0, 8.0, 4.0], [15.0, 2.0, 18.0, 4.0], [25.0, 2.0, 28.0, 4.0]],
+        )];
+
+        assert_eq!(pick(&polygons, 16.0, 3.0), None);
+        assert_eq!(pick(&polygons, 26.0, 3.0), None);
+        assert_eq!(pick(&polygons, 12.0, 3.0), Some("AAA"));
+    }
+
+    #[test]
+    fn an_exclave_is_picked_as_its_country() {
+        let polygons = [
+            rectangle("RUS", 27.0, 41.0, 180.0, 78.0),
+            rectangle("LTU", 21.0, 53.9, 26.8, 56.4),
+            // Kaliningrad, between Lithuania and Poland.
+            rectangle("RUS", 19.6, 54.3, 21.0, 55.3),
+        ];
+
+        assert_eq!(pick(&polygons, 20.5, 54.7), Some("RUS"));
+        assert_eq!(pick(&polygons, 24.0, 55.0), Some("LTU"));
+        assert_eq!(pick(&polygons, 18.0, 54.7), None);
+    }
+
+    #[test]
+    fn a_polygon_past_the_antimeridian_is_picked_on_both_sides() {
+        let polygons = [rectangle("AAA", 170.0, -10.0, 190.0, 10.0)];
+
+        assert_eq!(pick(&polygons, 175.0, 0.0), Some("AAA"));
+        assert_eq!(pick(&polygons, -175.0, 0.0), Some("AAA"));
+        assert_eq!(pick(&polygons, -165.0, 0.0), None);
+        assert_eq!(pick(&polygons, 165.0, 0.0), None);
+    }
+
+    #[test]
+    fn the_grid_agrees_with_testing_every_polygon() {
+        let polygons = [
+            rectangle("AAA", -10.0, -10.0, 10.0, 10.0),
+            with_holes("BBB", 20.0, 20.0, 60.0, 50.0, &[[30.0, 30.0, 40.0, 40.0]]),
+            rectangle("CCC", 30.0, 30.0, 40.0, 40.0),
+            rectangle("DDD", -179.5, -89.5, -170.2, -60.0),
+        ];
+        let grid = PolygonGrid::new(&polygons);
+
+        for i in 0..=100 {
+            for j in 0..=100 {
+                let point = [i as f32 / 100.0, j as f32 / 100.0];
+                let brute_force = polygons
+                    .iter()
+                    .position(|polygon| {
+                        bounding_box_contains(polygon, point) && contains(polygon, point)
+                    });
+
+                assert_eq!(grid.polygon_at(&polygons, point), brute_force, "{:?}", point);
+            }
+        }
+    }
+
+    #[test]
+    fn countries_are_kept_apart() {
+        let index = GeoIndex::new(&[
+            rectangle("AAA", 0.0, 0.0, 1.0, 1.0),
+            rectangle("BBB", 10.0, 10.0, 12.0, 12.0),
+        ]);
+
+        assert!(index.get("AAA").unwrap().area < index.get("BBB").unwrap().area);
+        assert!(index.get("CCC").is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+
+use glutin::GlContext;
+use piston::window::WindowSettings;
+use piston_window::{OpenGL, PistonWindow};
+
+use Result;
+
+/// The versions tried, newest first, if the preferred version does not work.
+const FALLBACKS: [OpenGL; 4] = [OpenGL::V4_1, OpenGL::V3_3, OpenGL::V3_2, OpenGL::V3_1];
+
+/// The multisampling sample counts that can be asked for, highest first. Lower counts are tried
+/// in turn if the driver does not support the one asked for.
+pub const SAMPLE_COUNTS: [u8; 4] = [8, 4, 2, 0];
+
+/// What the window's OpenGL context was actually created with.
+#[derive(Clone, Copy, Debug)]
+pub struct ContextInfo {
+    pub opengl: OpenGL,
+    pub vsync: bool,
+    pub samples: u8,
+    pub srgb: bool,
+}
+
+impl fmt::Display for ContextInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on_off = |on| if on { "on" } else { "off" };
+
+        write!(
+            f,
+            "OpenGL {} - VSync {} - MSAA {}x - sRGB {}",
+            name(self.opengl),
+            on_off(self.vsync),
+            self.samples,
+            on_off(self.srgb)
+        )
+    }
+}
+
+/// Which versions to try, in order: just `forced` if given, or else `preferred` and then the
+/// fallbacks.
+pub fn candidates(preferred: OpenGL, forced: Option<OpenGL>) -> Vec<OpenGL> {
+    if let Some(version) = forced {
+        return vec![version];
+    }
+
+    let mut versions = vec![preferred];
+    versions.extend(FALLBACKS.iter().filter(|&&version| version != preferred));
+    versions
+}
+
+/// Builds a window with the first of `versions` that the driver accepts, and with as many of the
+/// `samples` in `settings` as it supports.
+///
+/// `settings` are used as given, instead of going through `WindowSettings::build` for
+/// `PistonWindow`, which always turns sRGB on.
+pub fn build_window(
+    settings: &WindowSettings,
+    versions: &[OpenGL],
+) -> Result<(PistonWindow, ContextInfo)> {
+    let mut failures = Vec::new();
+
+    for &version in versions {
+        debug!("Trying OpenGL {}", name(version));
+        match build_with_samples(&settings.clone().opengl(version)) {
+            Ok(built) => return Ok(built),
+            Err(e) => {
+                warn!("Could not create an OpenGL {} context: {}", name(version), e);
+                failures.push(format!("{} ({})", name(version), e));
+            }
+        }
+    }
+
+    bail!("Could not create an OpenGL context. Tried {}", failures.join(", "))
+}
+
+/// Builds a window, lowering the sample count until the driver supports it.
+fn build_with_samples(
+    settings: &WindowSettings,
+) -> ::std::result::Result<(PistonWindow, ContextInfo), String> {
+    let requested = settings.get_samples();
+    let counts = Some(requested)
+        .into_iter()
+        .chain(SAMPLE_COUNTS.iter().cloned().filter(|&count| count < requested));
+
+    let opengl = settings.get_maybe_opengl().unwrap_or(OpenGL::V3_2);
+
+    let mut last_error = None;
+    for count in counts {
+        let window: PistonWindow = match settings.clone().samples(count).build() {
+            Ok(window) => PistonWindow::new(opengl, count, window),
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+
+        // The window backend quietly falls back to no multisampling if the count is not
+        // supported, so the pixel format says what was actually used.
+        let format = window.window.window.get_pixel_format();
+        if count > 0 && format.multisampling.unwrap_or(0) < u16::from(count) {
+            debug!("{}x multisampling is not supported", count);
+            continue;
+        }
+
+        if count != requested {
+            warn!("Using {}x multisampling instead of {}x", count, requested);
+        }
+
+        let info = ContextInfo {
+            opengl,
+            vsync: settings.get_vsync(),
+            samples: count,
+            srgb: format.srgb,
+        };
+        return Ok((window, info));
+    }
+
+    Err(last_error.unwrap_or_else(|| "no sample count worked".to_string()))
+}
+
+/// The version as it is written on the command line, such as "3.2".
+pub fn name(version: OpenGL) -> String {
+    let (major, minor) = version.get_major_minor();
+
+    format!("{}.{}", major, minor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_preferred_version_is_tried_before_the_fallbacks() {
+        let names = |versions: Vec<OpenGL>| versions.into_iter().map(name).collect::<Vec<_>>();
+
+        assert_eq!(names(candidates(OpenGL::V3_2, None)), ["3.2", "4.1", "3.3", "3.1"]);
+        assert_eq!(names(candidates(OpenGL::V4_5, None)), ["4.5", "4.1", "3.3", "3.2", "3.1"]);
+        assert_eq!(names(candidates(OpenGL::V3_2, Some(OpenGL::V2_1))), ["2.1"]);
+    }
+}
@@ -0,0 +1,198 @@
+use cgmath::{Matrix4, Vector4};
+use piston_window::{text, Context, G2d, Glyphs, Transformed};
+
+use hud;
+use projection::{clip_to_view, MIN_W};
+
+/// Grid spacings to choose from, in degrees.
+const SPACINGS: [f32; 6] = [30.0, 15.0, 10.0, 5.0, 2.0, 1.0];
+
+/// The spacing used is the largest one no more than this many degrees per unit of camera height,
+/// so it goes from 30 degrees fully zoomed out down to 1 degree up close.
+const DEGREES_PER_HEIGHT: f32 = 30.0;
+
+/// Meridians are drawn this many units of camera height to either side of `look_at`. The map
+/// repeats horizontally, so there is no natural range to draw them over.
+const X_SPAN_PER_HEIGHT: f32 = 4.0;
+
+/// Where labels are drawn, measured in from the window edge.
+const LABEL_INSET: f64 = 5.0;
+
+/// Which latitude and longitude lines are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+    /// How far apart the lines are, in degrees.
+    spacing: f32,
+    /// How far to either side of `look_at` meridians are drawn, in world space.
+    x_span: f32,
+}
+
+impl Grid {
+    /// The grid drawn with the camera at `camera_height`.
+    pub fn new(camera_height: f32) -> Grid {
+        Grid {
+            spacing: spacing(camera_height),
+            x_span: X_SPAN_PER_HEIGHT * camera_height,
+        }
+    }
+
+    /// The grid with the next wider spacing, if there is one, so that fewer lines are drawn.
+    pub fn coarser(self) -> Grid {
+        let spacing = SPACINGS
+            .iter()
+            .cloned()
+            .rev()
+            .find(|&spacing| spacing > self.spacing)
+            .unwrap_or(self.spacing);
+
+        Grid { spacing, ..self }
+    }
+}
+
+/// Draws the latitude and longitude lines of `grid` over the map in the 2D pass.
+///
+/// The map is a plane, so each line is straight in world space and therefore stays straight when
+/// projected. Each one is drawn as a single segment, clipped against the camera plane.
+pub fn draw(
+    mvp: Matrix4<f32>,
+    look_at: [f32; 2],
+    grid: Grid,
+    color: [f32; 4],
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let Grid { spacing, x_span } = grid;
+    let view_size = context.get_view_size();
+
+    let x_step = 2.0 * spacing / 360.0;
+    let first_x = ((look_at[0] - x_span) / x_step).ceil() as i64;
+    let last_x = ((look_at[0] + x_span) / x_step).floor() as i64;
+
+    for i in first_x..=last_x {
+        let x = i as f32 * x_step;
+        let segment = project_segment(mvp, [x, 0.0], [x, 1.0], view_size);
+
+        if let Some((from, to)) = segment {
+            draw_line(color, from, to, context, graphics);
+
+            let longitude = (x / 2.0).rem_euclid(1.0) * 360.0 - 180.0;
+            let label_y = view_size[1] - LABEL_INSET;
+            if let Some(label_x) = crossing(from, to, 1, label_y) {
+                let label = format_degrees(longitude, 'E', 'W');
+                draw_label(&label, color, [label_x + 2.0, label_y], glyphs, context, graphics);
+            }
+        }
+    }
+
+    let y_step = spacing / 180.0;
+    let num_parallels = (1.0 / y_step).round() as i64;
+
+    for i in 1..num_parallels {
+        let y = i as f32 * y_step;
+        let from = [look_at[0] - x_span, y];
+        let to = [look_at[0] + x_span, y];
+
+        if let Some((from, to)) = project_segment(mvp, from, to, view_size) {
+            draw_line(color, from, to, context, graphics);
+
+            let latitude = y * 180.0 - 90.0;
+            if let Some(label_y) = crossing(from, to, 0, LABEL_INSET) {
+                let label = format_degrees(latitude, 'N', 'S');
+                draw_label(&label, color, [LABEL_INSET, label_y - 2.0], glyphs, context, graphics);
+            }
+        }
+    }
+}
+
+fn spacing(camera_height: f32) -> f32 {
+    let max_spacing = DEGREES_PER_HEIGHT * camera_height;
+
+    SPACINGS
+        .iter()
+        .cloned()
+        .find(|&spacing| spacing <= max_spacing)
+        .unwrap_or(SPACINGS[SPACINGS.len() - 1])
+}
+
+/// Projects the ground-level segment between `from` and `to` into window coordinates, cutting off
+/// whatever is behind the camera. Returns `None` if all of it is.
+fn project_segment(
+    mvp: Matrix4<f32>,
+    from: [f32; 2],
+    to: [f32; 2],
+    view_size: [f64; 2],
+) -> Option<([f64; 2], [f64; 2])> {
+    let mut a = mvp * Vector4::new(from[0], from[1], 0.0, 1.0);
+    let mut b = mvp * Vector4::new(to[0], to[1], 0.0, 1.0);
+
+    if a.w < MIN_W && b.w < MIN_W {
+        return None;
+    }
+
+    if a.w < MIN_W {
+        a = b + (a - b) * ((b.w - MIN_W) / (b.w - a.w));
+    } else if b.w < MIN_W {
+        b = a + (b - a) * ((a.w - MIN_W) / (a.w - b.w));
+    }
+
+    Some((clip_to_view(a, view_size), clip_to_view(b, view_size)))
+}
+
+/// Where the segment from `from` to `to` crosses the line where coordinate `axis` equals
+/// `value`, given as the other coordinate.
+fn crossing(from: [f64; 2], to: [f64; 2], axis: usize, value: f64) -> Option<f64> {
+    let other = 1 - axis;
+    let (a, b) = (from[axis], to[axis]);
+
+    if (a - value) * (b - value) > 0.0 || a == b {
+        return None;
+    }
+
+    let t = (value - a) / (b - a);
+    Some(from[other] + t * (to[other] - from[other]))
+}
+
+/// Formats a whole number of degrees, such as "30°N". The equator, prime meridian and
+/// antimeridian get no hemisphere letter.
+fn format_degrees(degrees: f32, positive: char, negative: char) -> String {
+    let degrees = degrees.round() as i32;
+
+    match degrees {
+        0 | 180 | -180 => format!("{}\u{b0}", degrees.abs()),
+        _ if degrees > 0 => format!("{}\u{b0}{}", degrees, positive),
+        _ => format!("{}\u{b0}{}", -degrees, negative),
+    }
+}
+
+fn draw_line(color: [f32; 4], from: [f64; 2], to: [f64; 2], context: Context, graphics: &mut G2d) {
+    ::piston_window::line(
+        color,
+        0.5,
+        [from[0], from[1], to[0], to[1]],
+        context.transform,
+        graphics,
+    );
+}
+
+fn draw_label(
+    label: &str,
+    color: [f32; 4],
+    position: [f64; 2],
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let [r, g, b, _] = color;
+
+    let drawn = text::Text::new_color([r, g, b, 1.0], 10).draw(
+        label,
+        glyphs,
+        &context.draw_state,
+        context.transform.trans(position[0], position[1]),
+        graphics,
+    );
+    if drawn.is_err() {
+        hud::text_failed(label);
+    }
+}
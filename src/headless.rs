@@ -0,0 +1,101 @@
+use std::fs::File;
+use std::path::Path;
+
+use gaia;
+use gaia_assetgen::{FeaturesData, MultiLevelPolygon, Properties, MAX_LEVEL};
+use piston::input::{Event, Loop, UpdateArgs};
+use serde_json;
+
+use bindings::Action;
+use features::{self, Features};
+use settings::Settings;
+use split_view::View;
+use state::{State, WindowRequest};
+use {Result, ResultExt};
+
+/// Runs `State` without a window or GL context, for checking from tests what the demo would
+/// draw. Actions and time go in through the same `State::handle_action` and `State::event` the
+/// window's main loop uses, and what the choosers would give gaia comes out.
+pub struct Headless {
+    pub state: State,
+}
+
+impl Headless {
+    pub fn new(settings: Settings, features: Features, dir: &Path) -> Headless {
+        Headless {
+            state: State::headless(settings, features, dir),
+        }
+    }
+
+    /// With the countries in the JSON file at `path`, an array of each country's properties.
+    /// The countries have no outlines: there is no clicking on them, and day and night mode
+    /// takes all of them to be where the map's middle is.
+    pub fn with_fixture(settings: Settings, path: &Path, dir: &Path) -> Result<Headless> {
+        let file = File::open(path)
+            .chain_err(|| format!("Could not open fixture {}", path.display()))?;
+        let properties: Vec<Properties> = serde_json::from_reader(file)
+            .chain_err(|| format!("Could not read fixture {}", path.display()))?;
+
+        let polygons = properties
+            .into_iter()
+            .map(|properties| MultiLevelPolygon {
+                properties,
+                bounding_box: [(0.5, 0.5), (0.5, 0.5)],
+                levels: vec![vec![]; MAX_LEVEL as usize + 1],
+            })
+            .collect();
+        let features = Features::from_data(FeaturesData {
+            polygons,
+            points: vec![],
+        });
+
+        Ok(Headless::new(settings, features, dir))
+    }
+
+    pub fn handle(&mut self, action: Action) {
+        self.state.handle_action(action);
+    }
+
+    /// Lets `dt` seconds pass, as a frame's update would.
+    pub fn update(&mut self, dt: f64) {
+        self.state.event(&Event::Loop(Loop::Update(UpdateArgs { dt })));
+    }
+
+    /// What the window would have done for the actions since this was last called, such as
+    /// taking screenshots, which headless runs have to do without.
+    pub fn take_window_requests(&mut self) -> Vec<WindowRequest> {
+        self.state.window_requests.drain(..).collect()
+    }
+
+    /// The color the polygon color chooser would give the country with `properties` in `view`.
+    pub fn polygon_color(&self, properties: &Properties, view: View) -> Option<[u8; 4]> {
+        self.state.polygon_color_chooser(properties, self.state.frame_inputs(view))
+    }
+
+    /// The color the polygon color chooser would give the country whose `feature_id` is `id`
+    /// in `view`, or `None` if there is no such country or it is uncolored.
+    pub fn country_color(&self, id: &str, view: View) -> Option<[u8; 4]> {
+        let polygon = self.state.features.polygon_of(id)?;
+        self.polygon_color(self.state.features.properties(polygon), view)
+    }
+
+    /// The style the label style chooser would give the place with `properties` in `view`, or
+    /// `None` if it would not be labeled, including while labels are off there.
+    pub fn label_style<'a>(
+        &self,
+        properties: &'a Properties,
+        view: View,
+    ) -> Option<gaia::LabelStyle<'a>> {
+        (self.state.label_chooser(self.state.frame_inputs(view)))(properties)
+    }
+
+    /// The `feature_id` of every country, in the order they were loaded.
+    pub fn country_ids(&self) -> Vec<&str> {
+        self.state
+            .features
+            .polygons()
+            .iter()
+            .map(|polygon| features::feature_id(&polygon.properties))
+            .collect()
+    }
+}
@@ -0,0 +1,129 @@
+use piston::input::keyboard::Key;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use bindings::{self, Binding, EXTRA_CONTROLS};
+use hud::HudStyle;
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+const MARGIN: f64 = 10.0;
+const COLUMN_WIDTH: f64 = 300.0;
+
+/// How far right of a column's start the description is drawn, leaving room for the key name.
+const DESCRIPTION_OFFSET: f64 = 80.0;
+
+/// Lists every key binding and other control in two columns. If they do not fit in the window,
+/// the list is split into pages, flipped with PageUp/PageDown.
+#[derive(Debug)]
+pub struct HelpOverlay {
+    visible: bool,
+    page: usize,
+}
+
+impl HelpOverlay {
+    pub fn new() -> HelpOverlay {
+        HelpOverlay {
+            visible: false,
+            page: 0,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.page = 0;
+    }
+
+    /// Handles a key press while the overlay is open. All keys are swallowed so that nothing
+    /// happens behind the overlay.
+    pub fn key_press(&mut self, key: Key) {
+        match key {
+            Key::F1 | Key::H | Key::Escape => self.toggle(),
+            Key::PageDown => self.page += 1,
+            Key::PageUp => self.page = self.page.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    /// Draws the overlay, listing `bindings`, with `footer` shown at the bottom.
+    pub fn draw(
+        &mut self,
+        bindings: &[Binding],
+        footer: &str,
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let lines: Vec<(String, &str)> = bindings
+            .iter()
+            .map(|binding| (bindings::key_name(binding.key), binding.action.description()))
+            .chain(
+                EXTRA_CONTROLS
+                    .iter()
+                    .map(|&(control, description)| (control.to_string(), description)),
+            )
+            .collect();
+
+        let [view_width, view_height] = context.get_view_size();
+        let HudStyle { scale, theme } = style;
+        let (margin, line_height) = (scale.len(MARGIN), scale.len(LINE_HEIGHT));
+        let (column_width, description_offset) =
+            (scale.len(COLUMN_WIDTH), scale.len(DESCRIPTION_OFFSET));
+        let top = scale.len(25.0);
+        let width = (view_width - 2.0 * margin).max(0.0);
+        let height = (view_height - top - margin).max(0.0);
+
+        // One line each for the title and the footer, plus a blank line after the title.
+        let rows_per_column = ((height / line_height) as usize).saturating_sub(4).max(1);
+        let lines_per_page = 2 * rows_per_column;
+        let num_pages = (lines.len() + lines_per_page - 1) / lines_per_page;
+        self.page = self.page.min(num_pages - 1);
+
+        let context = context.trans(margin, top);
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [0.0, 0.0, width, height],
+            context.transform,
+            graphics,
+        );
+
+        let mut draw_text = |text: &str, color, x: f64, y: f64, graphics: &mut G2d| {
+            scale.draw_text(text, FONT_SIZE, color, context.trans(x, y), glyphs, graphics);
+        };
+
+        draw_text("Key bindings (F1 to close)", theme.text, margin, line_height, graphics);
+
+        let page_lines = lines
+            .iter()
+            .skip(self.page * lines_per_page)
+            .take(lines_per_page);
+        for (i, &(ref key, description)) in page_lines.enumerate() {
+            let x = margin + column_width * (i / rows_per_column) as f64;
+            let y = line_height * (3 + i % rows_per_column) as f64;
+
+            draw_text(key, theme.accent, x, y, graphics);
+            draw_text(description, theme.text, x + description_offset, y, graphics);
+        }
+
+        let mut footer = footer.to_string();
+        if num_pages > 1 {
+            footer.push_str(&format!(
+                " - Page {}/{} (PageUp/PageDown)",
+                self.page + 1,
+                num_pages
+            ));
+        }
+
+        draw_text(&footer, theme.text, margin, height - margin, graphics);
+    }
+}
+
+impl Default for HelpOverlay {
+    fn default() -> HelpOverlay {
+        HelpOverlay::new()
+    }
+}
@@ -0,0 +1,223 @@
+use cgmath::Matrix4;
+use gfx;
+
+use projection;
+use render_scale::ScaledTarget;
+use screenshot::{self, Readback};
+use Result;
+
+/// How many times the window's resolution high-resolution screenshots are drawn at, unless
+/// given on the command line.
+pub const DEFAULT_SCALE: u32 = 4;
+
+/// The most that can be given. Larger scales take gigabytes to hold while being written.
+pub const SCALE_RANGE: (u32, u32) = (2, 8);
+
+/// The largest side, in pixels, of a part drawn at once, even if the GPU allows more, so that
+/// drawing one does not take too much of its memory.
+const MAX_REGION_SIZE: u32 = 4096;
+
+/// The most memory a picture may take, in bytes, which is enough for the largest scale of a 4K
+/// window. The whole picture is held at once while it is put together and written.
+const MAX_BYTES: u64 = 2 << 30;
+
+/// Whether a picture of `size` can be drawn, which it cannot if it would take more memory than
+/// `MAX_BYTES` to hold.
+pub fn check_size(size: [u32; 2]) -> Result<()> {
+    let bytes = (4 * u64::from(size[0])).saturating_mul(u64::from(size[1]));
+    if bytes > MAX_BYTES {
+        bail!(
+            "A {}x{} screenshot would take {} MB to hold, more than the {} MB allowed; use a \
+             smaller --screenshot-scale",
+            size[0],
+            size[1],
+            bytes >> 20,
+            MAX_BYTES >> 20
+        );
+    }
+
+    Ok(())
+}
+
+/// Splits an image of `size` into a grid of regions, as `[x, y, width, height]` in pixels from
+/// its top-left, none more than `max_size` on a side, and all about the same size.
+pub fn regions(size: [u32; 2], max_size: u32) -> Vec<[u32; 4]> {
+    let max_size = max_size.max(1);
+    let splits = |len: u32| {
+        let parts = (len + max_size - 1) / max_size;
+        (0..=parts)
+            .map(|i| (u64::from(len) * u64::from(i) / u64::from(parts.max(1))) as u32)
+            .collect::<Vec<_>>()
+    };
+
+    let (xs, ys) = (splits(size[0]), splits(size[1]));
+    let mut regions = Vec::new();
+    for y in ys.windows(2) {
+        for x in xs.windows(2) {
+            regions.push([x[0], y[0], x[1] - x[0], y[1] - y[0]]);
+        }
+    }
+
+    regions
+}
+
+/// Draws a picture of `size`, possibly larger than the GPU can draw at once, by drawing it in
+/// regions no larger than `max_texture_size` and putting them together. `render` draws to the
+/// target it is given, through the projection for the region, which only needs the view matrix
+/// applied. Each region is cleared to `background` first. Pictures too large to hold, as
+/// `check_size` says, are refused before anything is drawn.
+pub fn capture<R, C, F, D, G>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    device: &mut D,
+    size: [u32; 2],
+    max_texture_size: u32,
+    background: [f32; 4],
+    mut render: G,
+) -> Result<Readback>
+where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    F: gfx::Factory<R>,
+    D: gfx::Device<Resources = R, CommandBuffer = C>,
+    G: FnMut(&mut gfx::Encoder<R, C>, &ScaledTarget<R>, Matrix4<f32>) -> Result<()>,
+{
+    check_size(size)?;
+    let regions = regions(size, max_texture_size.min(MAX_REGION_SIZE));
+    let mut image = Readback::blank(size[0], size[1]);
+
+    for &region in &regions {
+        let [x, y, width, height] = region;
+        let target = ScaledTarget::new(factory, [width as u16, height as u16])?;
+        encoder.clear(&target.color, background);
+        encoder.clear_depth(&target.depth, 1.0);
+        encoder.clear_stencil(&target.depth, 0);
+
+        render(encoder, &target, projection::region_matrix(size, region))?;
+
+        let part = screenshot::read_back_target(factory, encoder, device, &target.color)?;
+        image.paste(&part, x, y);
+    }
+
+    if regions.len() > 1 {
+        debug!("Drew the {}x{} screenshot in {} parts", size[0], size[1], regions.len());
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+
+    use super::*;
+
+    const SIZES: [[u32; 2]; 5] = [[1, 1], [800, 600], [4096, 4096], [4097, 10], [15360, 8640]];
+
+    #[test]
+    fn regions_cover_the_image_once() {
+        for &size in &SIZES {
+            for &max_size in &[1, 300, 4096] {
+                // Comparing every pair of regions takes too long if there are very many.
+                if u64::from(size[0]) * u64::from(size[1]) / u64::from(max_size).pow(2) > 2_000 {
+                    continue;
+                }
+                let regions = regions(size, max_size);
+
+                let mut area = 0;
+                for (i, &[x, y, width, height]) in regions.iter().enumerate() {
+                    assert!(width > 0 && height > 0, "{:?} by {}", size, max_size);
+                    assert!(width <= max_size && height <= max_size, "{:?} by {}", size, max_size);
+                    assert!(x + width <= size[0] && y + height <= size[1]);
+                    area += u64::from(width) * u64::from(height);
+
+                    for &[other_x, other_y, other_width, other_height] in &regions[i + 1..] {
+                        let apart = x + width <= other_x
+                            || other_x + other_width <= x
+                            || y + height <= other_y
+                            || other_y + other_height <= y;
+                        assert!(apart, "{:?} by {}", size, max_size);
+                    }
+                }
+                // Within the image and not overlapping, they cover it if their areas add up.
+                assert_eq!(area, u64::from(size[0]) * u64::from(size[1]));
+            }
+        }
+
+        // The regions are about the same size, rather than full ones and a sliver.
+        assert_eq!(regions([4097, 10], 4096), [[0, 0, 2048, 10], [2048, 0, 2049, 10]]);
+        assert_eq!(regions([800, 600], 4096), [[0, 0, 800, 600]]);
+    }
+
+    /// Where on an image of `size` `matrix` projects `point` to, in pixels from its top-left.
+    fn pixel(matrix: Matrix4<f32>, point: Vector4<f32>, size: [f32; 2]) -> [f32; 2] {
+        let clip = matrix * point;
+        let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+
+        [(x + 1.0) / 2.0 * size[0], (1.0 - y) / 2.0 * size[1]]
+    }
+
+    #[test]
+    fn each_region_draws_its_part_of_the_whole_picture() {
+        let size = [3000, 2000];
+        let whole = projection::region_matrix(size, [0, 0, size[0], size[1]]);
+        let whole_size = [size[0] as f32, size[1] as f32];
+
+        for &[x, y, width, height] in &regions(size, 1024) {
+            let matrix = projection::region_matrix(size, [x, y, width, height]);
+            // Points at the corners and middle of the region, as the whole picture has them.
+            for &[u, v] in &[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0], [0.5, 0.5]] {
+                let at = [x as f32 + u * width as f32, y as f32 + v * height as f32];
+                let ndc = [at[0] / whole_size[0] * 2.0 - 1.0, 1.0 - at[1] / whole_size[1] * 2.0];
+                // Some point in front of the camera that the whole picture draws there.
+                let clip = Vector4::new(ndc[0], ndc[1], 0.5, 1.0);
+                let point = ::cgmath::SquareMatrix::invert(&whole).unwrap() * clip;
+                assert!((pixel(whole, point, whole_size)[0] - at[0]).abs() < 0.05);
+
+                let drawn = pixel(matrix, point, [width as f32, height as f32]);
+                let expected = [u * width as f32, v * height as f32];
+                for axis in 0..2 {
+                    assert!(
+                        (drawn[axis] - expected[axis]).abs() < 0.05,
+                        "{:?} in {:?}: {:?} != {:?}",
+                        [u, v],
+                        [x, y, width, height],
+                        drawn,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pasted_parts_make_the_whole_image() {
+        let size = [37, 23];
+        let pixels: Vec<u8> = (0..4 * size[0] * size[1]).map(|i| (i * 7 % 251) as u8).collect();
+
+        let mut image = Readback::blank(size[0], size[1]);
+        for &[x, y, width, height] in &regions(size, 10) {
+            let part: Vec<u8> = (y..y + height)
+                .flat_map(|row| {
+                    let start = 4 * (row * size[0] + x) as usize;
+                    pixels[start..start + 4 * width as usize].to_vec()
+                })
+                .collect();
+            image.paste(&Readback::from_top_down_pixels(width, height, &part), x, y);
+        }
+        assert_eq!(image.top_down_pixels(), pixels);
+
+        // What would fall outside of the image is left out.
+        let mut small = Readback::blank(2, 2);
+        small.paste(&Readback::from_top_down_pixels(3, 1, &[255; 12]), 1, 1);
+        assert_eq!(small.top_down_pixels(), [[0; 12].as_ref(), &[255; 4]].concat());
+    }
+
+    #[test]
+    fn pictures_too_large_to_hold_are_refused() {
+        // The largest scale of a 4K window, and of a 5K one.
+        assert!(check_size([3840 * 8, 2160 * 8]).is_ok());
+        assert!(check_size([5120 * 8, 2880 * 8]).is_err());
+        assert!(check_size([u32::max_value(), u32::max_value()]).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+use std::cell::RefCell;
+
+use piston_window::character::CharacterCache;
+use piston_window::math::Matrix2d;
+use piston_window::{text, Context, G2d, Glyphs, Rectangle, Transformed};
+
+use font_fallback;
+use theme::Theme;
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+
+/// Space between a panel's content and its edges.
+const PADDING: f64 = 5.0;
+
+/// The width and height of an `Icon`.
+const ICON_SIZE: f64 = 10.0;
+
+/// Space between the window edges and the panels against them.
+const MARGIN: f64 = 10.0;
+
+/// Space between panels stacked in the same corner.
+const SPACING: f64 = 5.0;
+
+/// Logs that `text` failed to draw. Only that text is left out: the rest of the frame, and the
+/// map under it, are drawn as usual.
+pub fn text_failed(text: &str) {
+    debug!("Could not draw the text {:?}", text);
+}
+
+thread_local! {
+    /// The glyphs of `hud.font_fallback`, if it was loaded, for text the HUD font cannot draw.
+    static FALLBACK_GLYPHS: RefCell<Option<Glyphs>> = RefCell::new(None);
+}
+
+/// Draws HUD text that the HUD font has no glyphs for with `glyphs`, where they have them.
+pub fn set_fallback_glyphs(glyphs: Glyphs) {
+    FALLBACK_GLYPHS.with(|fallback| *fallback.borrow_mut() = Some(glyphs));
+}
+
+/// Calls `f` with the glyphs to draw `text` with: `glyphs`, unless its font cannot draw `text`
+/// and the fallback's can.
+fn with_glyphs_for<T, F>(text: &str, glyphs: &mut Glyphs, f: F) -> T
+where
+    F: FnOnce(&mut Glyphs) -> T,
+{
+    // Every font the HUD is likely to be drawn with has ASCII glyphs, which most text is.
+    if text.is_ascii() || font_fallback::covers(&glyphs.font, text) {
+        return f(glyphs);
+    }
+
+    FALLBACK_GLYPHS.with(|fallback| match *fallback.borrow_mut() {
+        Some(ref mut fallback) if font_fallback::covers(&fallback.font, text) => f(fallback),
+        _ => f(glyphs),
+    })
+}
+
+/// How large to draw the HUD.
+///
+/// The 2D pass works in points, which the window already maps onto however many physical pixels
+/// the display has. So `factor` only needs to reflect the user's preference, while `density` is
+/// used to rasterize text at the display's real resolution so it stays crisp instead of being
+/// scaled up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HudScale {
+    /// Multiplies every text size, padding and widget dimension.
+    pub factor: f64,
+    /// Physical pixels per point, from the window's hidpi factor. See `HudScale::density`.
+    pub density: f64,
+}
+
+impl HudScale {
+    /// Physical pixels per point for a window with `hidpi_factor`, if the window system gives
+    /// one, drawn `draw_height` pixels high for a `height` in points. Without a usable factor,
+    /// the ratio of the heights is used, and 1 before any frame is drawn.
+    pub fn density(hidpi_factor: Option<f32>, draw_height: u32, height: u32) -> f64 {
+        match hidpi_factor {
+            Some(factor) if factor.is_finite() && factor > 0.0 => f64::from(factor),
+            _ if height > 0 && draw_height > 0 => f64::from(draw_height) / f64::from(height),
+            _ => 1.0,
+        }
+    }
+
+    /// Scales a length given for a factor of 1.
+    pub fn len(&self, n: f64) -> f64 {
+        n * self.factor
+    }
+
+    /// The font size to ask the glyph cache for, in physical pixels.
+    fn font_size(&self, size: u32) -> u32 {
+        (f64::from(size) * self.factor * self.density).round().max(1.0) as u32
+    }
+
+    /// How wide `text` is at font `size`, in points.
+    pub fn text_width(&self, size: u32, text: &str, glyphs: &mut Glyphs) -> f64 {
+        let font_size = self.font_size(size);
+        with_glyphs_for(text, glyphs, |glyphs| glyphs.width(font_size, text).unwrap_or(0.0))
+            / self.density
+    }
+
+    /// Draws `text` at font `size` with its baseline starting at the origin of `context`. Returns
+    /// whether it was drawn: a text that fails to draw is left out.
+    pub fn draw_text(
+        &self,
+        text: &str,
+        size: u32,
+        color: [f32; 4],
+        context: Context,
+        glyphs: &mut Glyphs,
+        graphics: &mut G2d,
+    ) -> bool {
+        let transform = context
+            .transform
+            .scale(1.0 / self.density, 1.0 / self.density);
+
+        let drawn = with_glyphs_for(text, glyphs, |glyphs| {
+            text::Text::new_color(color, self.font_size(size))
+                .draw(text, glyphs, &context.draw_state, transform, graphics)
+        });
+        if drawn.is_err() {
+            text_failed(text);
+        }
+        drawn.is_ok()
+    }
+}
+
+/// Everything about how HUD elements look this frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HudStyle {
+    pub scale: HudScale,
+    pub theme: Theme,
+}
+
+/// How far in from each window edge the HUD keeps, in points, as `[top, right, bottom, left]`.
+pub type Insets = [f64; 4];
+
+/// A window corner that HUD panels are anchored to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    fn is_top(self) -> bool {
+        match self {
+            Corner::TopLeft | Corner::TopRight => true,
+            Corner::BottomLeft | Corner::BottomRight => false,
+        }
+    }
+
+    /// The corner on the same side, at the other end.
+    fn opposite(self) -> Corner {
+        match self {
+            Corner::TopLeft => Corner::BottomLeft,
+            Corner::TopRight => Corner::BottomRight,
+            Corner::BottomLeft => Corner::TopLeft,
+            Corner::BottomRight => Corner::TopRight,
+        }
+    }
+}
+
+/// Places HUD panels against the window's corners, inside its safe area. Panels placed in the
+/// same corner stack away from it, top corners downward and bottom corners upward, so that they
+/// never overlap. A panel that would run into the stack at the other end of its side starts a
+/// new column beside its corner's, further in.
+///
+/// A new layout should be made every frame, since placement depends on the window size.
+///
+/// When the HUD is hidden, nothing can be placed, so every widget drawn through the layout hides
+/// along with it.
+#[derive(Debug)]
+pub struct HudLayout {
+    visible: bool,
+    scale: HudScale,
+    theme: Theme,
+    view_size: [f64; 2],
+    insets: Insets,
+    /// How far from the corner's edge the next panel goes, per `Corner` in declaration order.
+    used: [f64; 4],
+    /// How far from the corner's side the current column of each corner is.
+    columns: [f64; 4],
+    /// How wide the widest panel in the current column of each corner is.
+    column_widths: [f64; 4],
+}
+
+impl HudLayout {
+    /// A layout for this frame, keeping `insets` clear of panels as well as the usual margin.
+    pub fn new(context: Context, visible: bool, style: HudStyle, insets: Insets) -> HudLayout {
+        let HudStyle { scale, theme } = style;
+        let margin = scale.len(MARGIN);
+        let [top, _, bottom, _] = insets;
+
+        HudLayout {
+            visible,
+            scale,
+            theme,
+            view_size: context.get_view_size(),
+            insets,
+            used: [top + margin, top + margin, bottom + margin, bottom + margin],
+            columns: [0.0; 4],
+            column_widths: [0.0; 4],
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// The safe area's insets, for what is drawn against the window edges without being placed.
+    pub fn insets(&self) -> Insets {
+        self.insets
+    }
+
+    /// Reserves room for something of `size` in `corner`, and returns where its top-left goes.
+    /// Returns `None` if the HUD is hidden, in which case it should not be drawn.
+    pub fn place(&mut self, corner: Corner, size: [f64; 2]) -> Option<[f64; 2]> {
+        if !self.visible {
+            return None;
+        }
+
+        let [view_width, view_height] = self.view_size;
+        let [top, right, bottom, left] = self.insets;
+        let (margin, spacing) = (self.scale.len(MARGIN), self.scale.len(SPACING));
+        let start = margin + if corner.is_top() { top } else { bottom };
+        let index = corner as usize;
+
+        // The first panel of a column stays in it however little room there is, so that a tiny
+        // window does not spread panels across it.
+        let room = view_height - self.used[corner.opposite() as usize] + spacing;
+        if self.used[index] > start && self.used[index] + size[1] > room {
+            self.columns[index] += self.column_widths[index] + spacing;
+            self.column_widths[index] = 0.0;
+            self.used[index] = start;
+        }
+
+        // In a window too small for a panel, it is kept from going off the top or left edge,
+        // where its text starts, and runs off the other edges instead.
+        let (used, column) = (self.used[index], self.columns[index]);
+        let x = match corner {
+            Corner::TopLeft | Corner::BottomLeft => left + margin + column,
+            Corner::TopRight | Corner::BottomRight => {
+                (view_width - right - margin - column - size[0]).round().max(0.0)
+            }
+        };
+        let y = if corner.is_top() {
+            used
+        } else {
+            (view_height - used - size[1]).round().max(0.0)
+        };
+
+        self.used[index] += size[1] + spacing;
+        self.column_widths[index] = self.column_widths[index].max(size[0]);
+        Some([x, y])
+    }
+
+    /// Draws `lines` of text on a panel sized to fit them. Returns where the panel was drawn, as
+    /// `[x, y, width, height]`, or `None` if the HUD is hidden.
+    pub fn text_panel(
+        &mut self,
+        corner: Corner,
+        lines: &[&str],
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) -> Option<[f64; 4]> {
+        self.panel(corner, None, lines, glyphs, context, graphics)
+    }
+
+    /// Like `text_panel`, but with `icon` drawn before the first line.
+    pub fn icon_text_panel(
+        &mut self,
+        corner: Corner,
+        icon: Icon,
+        lines: &[&str],
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        self.panel(corner, Some(icon), lines, glyphs, context, graphics);
+    }
+
+    /// Where line `index` of the text panel drawn at `panel` is, for hit-testing clicks on it.
+    pub fn line_rect(&self, panel: [f64; 4], index: usize) -> [f64; 4] {
+        let line_height = self.scale.len(LINE_HEIGHT);
+        let top = panel[1] + line_height * index as f64 + self.scale.len(2.0);
+
+        [panel[0], top, panel[2], line_height]
+    }
+
+    fn panel(
+        &mut self,
+        corner: Corner,
+        icon: Option<Icon>,
+        lines: &[&str],
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) -> Option<[f64; 4]> {
+        let (scale, theme) = (self.scale, self.theme);
+        let (padding, line_height, icon_size) = (
+            scale.len(PADDING),
+            scale.len(LINE_HEIGHT),
+            scale.len(ICON_SIZE),
+        );
+
+        let text_x = padding + if icon.is_some() { icon_size + padding } else { 0.0 };
+        let text_width = lines
+            .iter()
+            .map(|line| scale.text_width(FONT_SIZE, line, glyphs))
+            .fold(0.0, f64::max);
+        let size = [
+            text_x + text_width + padding,
+            line_height * lines.len() as f64 + padding,
+        ];
+
+        let [x, y] = self.place(corner, size)?;
+        let context = context.trans(x, y);
+
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [0.0, 0.0, size[0], size[1]],
+            context.transform,
+            graphics,
+        );
+
+        if let Some(icon) = icon {
+            let bounds = [padding, line_height - icon_size, icon_size, icon_size];
+            icon.draw(bounds, &theme, context.transform, graphics);
+        }
+
+        for (i, line) in lines.iter().enumerate() {
+            let baseline = line_height * (i + 1) as f64 - scale.len(2.0);
+            scale.draw_text(
+                line,
+                FONT_SIZE,
+                theme.text,
+                context.trans(text_x, baseline),
+                glyphs,
+                graphics,
+            );
+        }
+
+        Some([x, y, size[0], size[1]])
+    }
+}
+
+/// Draws `text` on a panel beside `cursor`, below and to the right of it, unless that would go
+/// past the edge of the view.
+pub fn draw_tooltip(
+    text: &str,
+    cursor: [f64; 2],
+    style: HudStyle,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+    let (padding, line_height) = (scale.len(PADDING), scale.len(LINE_HEIGHT));
+    let size = [
+        scale.text_width(FONT_SIZE, text, glyphs) + 2.0 * padding,
+        line_height + padding,
+    ];
+
+    let [view_width, view_height] = context.get_view_size();
+    let offset = scale.len(MARGIN);
+    let x = if cursor[0] + offset + size[0] <= view_width {
+        cursor[0] + offset
+    } else {
+        cursor[0] - offset - size[0]
+    };
+    let y = if cursor[1] + offset + size[1] <= view_height {
+        cursor[1] + offset
+    } else {
+        cursor[1] - offset - size[1]
+    };
+    let context = context.trans(x.round(), y.round());
+
+    ::piston_window::rectangle(
+        theme.panel_background,
+        [0.0, 0.0, size[0], size[1]],
+        context.transform,
+        graphics,
+    );
+    scale.draw_text(
+        text,
+        FONT_SIZE,
+        theme.text,
+        context.trans(padding, line_height - scale.len(2.0)),
+        glyphs,
+        graphics,
+    );
+}
+
+/// Outlines the safe area inside `insets` in the theme's accent color, as a guide while the
+/// insets are adjusted.
+pub fn draw_safe_area_guide(insets: Insets, style: HudStyle, context: Context, graphics: &mut G2d) {
+    let HudStyle { scale, theme } = style;
+    let [view_width, view_height] = context.get_view_size();
+    let [top, right, bottom, left] = insets;
+    let rect = [
+        left,
+        top,
+        (view_width - left - right).max(0.0),
+        (view_height - top - bottom).max(0.0),
+    ];
+
+    Rectangle::new_border(theme.accent, scale.len(1.0)).draw(
+        rect,
+        &context.draw_state,
+        context.transform,
+        graphics,
+    );
+}
+
+/// Small pictures drawn with 2D primitives, for use alongside text in HUD panels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Icon {
+    Sun,
+    Moon,
+    /// A ring of dots, brightest at `phase`, which goes from 0 to 1 clockwise from the top.
+    Spinner { phase: f64 },
+}
+
+/// How many dots `Icon::Spinner` has.
+const SPINNER_DOTS: usize = 8;
+
+impl Icon {
+    fn draw(&self, bounds: [f64; 4], theme: &Theme, transform: Matrix2d, graphics: &mut G2d) {
+        match *self {
+            Icon::Sun => {
+                ::piston_window::ellipse([1.0, 0.8, 0.0, 1.0], bounds, transform, graphics);
+            }
+            Icon::Moon => {
+                // A crescent: a disc with another, offset disc of the background color over it.
+                let [x, y, width, height] = bounds;
+                let shadow = [x + width * 0.35, y - height * 0.1, width, height];
+
+                ::piston_window::ellipse([0.4, 0.4, 0.6, 1.0], bounds, transform, graphics);
+                let background = theme.opaque_background();
+                ::piston_window::ellipse(background, shadow, transform, graphics);
+            }
+            Icon::Spinner { phase } => {
+                let [x, y, width, height] = bounds;
+                let (center_x, center_y) = (x + width / 2.0, y + height / 2.0);
+                let (radius, dot_size) = (width * 0.4, width * 0.2);
+                let [r, g, b, a] = theme.accent;
+
+                for i in 0..SPINNER_DOTS {
+                    let along = i as f64 / SPINNER_DOTS as f64;
+                    // How far behind the brightest dot this one is, from 0 to 1.
+                    let behind = (phase - along).rem_euclid(1.0);
+                    let (sin, cos) = (along * 2.0 * ::std::f64::consts::PI).sin_cos();
+                    let dot = [
+                        center_x + sin * radius - dot_size / 2.0,
+                        center_y - cos * radius - dot_size / 2.0,
+                        dot_size,
+                        dot_size,
+                    ];
+
+                    let color = [r, g, b, a * (1.0 - behind as f32 * 0.8)];
+                    ::piston_window::ellipse(color, dot, transform, graphics);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style() -> HudStyle {
+        HudStyle {
+            scale: HudScale {
+                factor: 1.0,
+                density: 1.0,
+            },
+            theme: Theme::default(),
+        }
+    }
+
+    fn layout(width: f64, height: f64) -> HudLayout {
+        HudLayout::new(Context::new_abs(width, height), true, style(), [0.0; 4])
+    }
+
+    #[test]
+    fn the_density_follows_the_hidpi_factor() {
+        assert_eq!(HudScale::density(Some(2.0), 600, 600), 2.0);
+        assert_eq!(HudScale::density(Some(1.25), 0, 0), 1.25);
+
+        // Without a usable factor, the draw size says.
+        assert_eq!(HudScale::density(None, 1200, 600), 2.0);
+        assert_eq!(HudScale::density(Some(0.0), 1200, 600), 2.0);
+        assert_eq!(HudScale::density(Some(::std::f32::NAN), 900, 600), 1.5);
+        assert_eq!(HudScale::density(None, 0, 0), 1.0);
+        assert_eq!(HudScale::density(None, 0, 600), 1.0);
+    }
+
+    #[test]
+    fn panels_stack_away_from_their_corners() {
+        let mut layout = layout(800.0, 600.0);
+
+        assert_eq!(layout.place(Corner::TopRight, [100.0, 20.0]), Some([690.0, 10.0]));
+        assert_eq!(layout.place(Corner::TopRight, [50.0, 20.0]), Some([740.0, 35.0]));
+        assert_eq!(layout.place(Corner::BottomLeft, [100.0, 20.0]), Some([10.0, 570.0]));
+    }
+
+    #[test]
+    fn panels_stay_on_a_tiny_window() {
+        let mut layout = layout(1.0, 1.0);
+
+        let corners = [Corner::TopLeft, Corner::TopRight, Corner::BottomLeft, Corner::BottomRight];
+        for &corner in &corners {
+            let [x, y] = layout.place(corner, [100.0, 20.0]).unwrap();
+            assert!(x >= 0.0 && y >= 0.0, "{:?} at {:?}", corner, [x, y]);
+        }
+    }
+
+    #[test]
+    fn panels_stay_on_a_wide_short_window() {
+        let mut layout = layout(5000.0, 100.0);
+
+        assert_eq!(layout.place(Corner::TopRight, [200.0, 20.0]), Some([4790.0, 10.0]));
+        assert_eq!(layout.place(Corner::BottomRight, [200.0, 60.0]), Some([4790.0, 30.0]));
+        assert_eq!(layout.place(Corner::BottomRight, [200.0, 60.0]), Some([4585.0, 30.0]));
+    }
+
+    #[test]
+    fn panels_keep_inside_the_safe_area() {
+        let context = Context::new_abs(800.0, 600.0);
+        let mut layout = HudLayout::new(context, true, style(), [20.0, 30.0, 40.0, 50.0]);
+
+        assert_eq!(layout.place(Corner::TopLeft, [100.0, 20.0]), Some([60.0, 30.0]));
+        assert_eq!(layout.place(Corner::TopRight, [100.0, 20.0]), Some([660.0, 30.0]));
+        assert_eq!(layout.place(Corner::BottomLeft, [100.0, 20.0]), Some([60.0, 530.0]));
+        assert_eq!(layout.place(Corner::BottomRight, [100.0, 20.0]), Some([660.0, 530.0]));
+    }
+
+    #[test]
+    fn panels_that_would_overlap_start_a_new_column() {
+        let mut layout = layout(800.0, 200.0);
+
+        assert_eq!(layout.place(Corner::TopLeft, [100.0, 80.0]), Some([10.0, 10.0]));
+        assert_eq!(layout.place(Corner::BottomLeft, [120.0, 60.0]), Some([10.0, 130.0]));
+        // Stacked below the first, it would run into the one at the bottom.
+        assert_eq!(layout.place(Corner::TopLeft, [100.0, 50.0]), Some([115.0, 10.0]));
+        assert_eq!(layout.place(Corner::TopLeft, [60.0, 20.0]), Some([115.0, 65.0]));
+    }
+}
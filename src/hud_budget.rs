@@ -0,0 +1,320 @@
+use std::time::Instant;
+
+/// How many frames in a row the 2D pass must go over its budget before a widget is degraded.
+const SUSTAIN_FRAMES: u32 = 30;
+
+/// The 2D pass must take under this fraction of its budget for a degraded widget to be restored,
+/// so that restoring one does not put it straight back over.
+const HEADROOM: f32 = 0.6;
+
+/// How many frames in a row the 2D pass must stay under `HEADROOM` before a widget is restored.
+const RECOVER_FRAMES: u32 = 180;
+
+/// How much of each frame's cost is blended into the running averages.
+const SMOOTHING: f32 = 0.1;
+
+/// How many of its costliest widgets the performance overlay lists.
+const LISTED: usize = 4;
+
+/// The parts of the 2D pass whose cost is measured. Some of them are optional, and are drawn in
+/// less detail or not at all while the pass takes too long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Widget {
+    Stars,
+    Graticule,
+    Outline,
+    Routes,
+    Points,
+    Overview,
+    Legend,
+    /// The rest: panels, overlays and toasts.
+    Panels,
+}
+
+const WIDGETS: usize = 8;
+
+impl Widget {
+    pub const ALL: [Widget; WIDGETS] = [
+        Widget::Stars,
+        Widget::Graticule,
+        Widget::Outline,
+        Widget::Routes,
+        Widget::Points,
+        Widget::Overview,
+        Widget::Legend,
+        Widget::Panels,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Widget::Stars => "Stars",
+            Widget::Graticule => "Grid",
+            Widget::Outline => "Outline",
+            Widget::Routes => "Routes",
+            Widget::Points => "Points",
+            Widget::Overview => "Overview",
+            Widget::Legend => "Legend",
+            Widget::Panels => "Panels",
+        }
+    }
+
+    /// What the widget looks like at each step it can be degraded by, from the first. Empty if
+    /// it is always drawn in full.
+    fn degraded(self) -> &'static [&'static str] {
+        match self {
+            Widget::Graticule => &["coarser grid", "no grid"],
+            Widget::Stars => &["no stars"],
+            Widget::Points => &["no point labels"],
+            Widget::Overview => &["no overview"],
+            Widget::Outline | Widget::Routes | Widget::Legend | Widget::Panels => &[],
+        }
+    }
+}
+
+/// How long each widget took to draw in one frame, in milliseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Costs {
+    ms: [f32; WIDGETS],
+}
+
+impl Costs {
+    /// Adds the time since `started` to `widget`'s cost.
+    pub fn time(&mut self, widget: Widget, started: Instant) {
+        let elapsed = started.elapsed();
+        self.ms[widget.index()] += elapsed.as_secs_f32() * 1000.0;
+    }
+
+    fn total(&self) -> f32 {
+        self.ms.iter().sum()
+    }
+}
+
+/// How many steps each widget is degraded by. 0 is drawn in full.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Levels([u8; WIDGETS]);
+
+impl Levels {
+    pub fn of(&self, widget: Widget) -> u8 {
+        self.0[widget.index()]
+    }
+}
+
+/// Keeps the 2D pass within its budget: while it has been over for a while, the costliest
+/// optional widget that can be is degraded a step, and once there has been headroom for a while,
+/// the last degraded is restored a step.
+#[derive(Debug)]
+pub struct HudBudget {
+    /// 0 never degrades anything.
+    budget_ms: f32,
+    /// Each widget's running average cost, in milliseconds.
+    averages: [f32; WIDGETS],
+    /// The running average of the whole pass.
+    average_ms: f32,
+    levels: Levels,
+    /// Each step taken, in order, so that the last is undone first.
+    steps: Vec<Widget>,
+    over_frames: u32,
+    headroom_frames: u32,
+}
+
+impl HudBudget {
+    pub fn new(budget_ms: f32) -> HudBudget {
+        HudBudget {
+            budget_ms,
+            averages: [0.0; WIDGETS],
+            average_ms: 0.0,
+            levels: Levels::default(),
+            steps: vec![],
+            over_frames: 0,
+            headroom_frames: 0,
+        }
+    }
+
+    /// Applies a changed budget. Turning the budget off restores every widget right away.
+    pub fn set_budget(&mut self, budget_ms: f32) {
+        if budget_ms != self.budget_ms {
+            self.over_frames = 0;
+            self.headroom_frames = 0;
+        }
+        if budget_ms <= 0.0 {
+            self.levels = Levels::default();
+            self.steps.clear();
+        }
+
+        self.budget_ms = budget_ms;
+    }
+
+    pub fn levels(&self) -> Levels {
+        self.levels
+    }
+
+    /// Records a frame whose 2D pass took `total_ms`, of which the widgets took `costs`.
+    pub fn frame(&mut self, costs: Costs, total_ms: f32) {
+        // What the widgets timed did not take, the panels and the rest did.
+        let mut costs = costs;
+        costs.ms[Widget::Panels.index()] += (total_ms - costs.total()).max(0.0);
+        for (average, &ms) in self.averages.iter_mut().zip(&costs.ms) {
+            *average += (ms - *average) * SMOOTHING;
+        }
+        self.average_ms += (total_ms - self.average_ms) * SMOOTHING;
+
+        if self.budget_ms <= 0.0 {
+            return;
+        }
+        if total_ms > self.budget_ms {
+            self.over_frames += 1;
+            self.headroom_frames = 0;
+        } else if total_ms < HEADROOM * self.budget_ms {
+            self.headroom_frames += 1;
+            self.over_frames = 0;
+        } else {
+            self.over_frames = 0;
+            self.headroom_frames = 0;
+        }
+
+        if self.over_frames >= SUSTAIN_FRAMES {
+            self.over_frames = 0;
+            self.degrade();
+        }
+        if self.headroom_frames >= RECOVER_FRAMES {
+            self.headroom_frames = 0;
+            if let Some(widget) = self.steps.pop() {
+                self.levels.0[widget.index()] -= 1;
+                info!("2D pass has headroom, restoring {}", widget.name().to_lowercase());
+            }
+        }
+    }
+
+    /// Degrades the costliest widget that can still be, if any.
+    fn degrade(&mut self) {
+        let levels = self.levels;
+        let costliest = Widget::ALL
+            .iter()
+            .cloned()
+            .filter(|&widget| usize::from(levels.of(widget)) < widget.degraded().len())
+            .max_by(|&a, &b| {
+                let cost = |widget: Widget| self.averages[widget.index()];
+                cost(a).partial_cmp(&cost(b)).unwrap()
+            });
+
+        if let Some(widget) = costliest {
+            let level = &mut self.levels.0[widget.index()];
+            info!(
+                "2D pass over its {:.1} ms budget, drawing {}",
+                self.budget_ms,
+                widget.degraded()[usize::from(*level)]
+            );
+            *level += 1;
+            self.steps.push(widget);
+        }
+    }
+
+    /// Lines for the performance overlay: what the 2D pass costs, its costliest widgets, and
+    /// which are degraded.
+    pub fn details(&self) -> Vec<String> {
+        let budget = if self.budget_ms > 0.0 {
+            format!(" of {:.1}", self.budget_ms)
+        } else {
+            String::new()
+        };
+        let mut lines = vec![format!("2D pass: {:.1} ms{}", self.average_ms, budget)];
+
+        let mut widgets = Widget::ALL.to_vec();
+        widgets.sort_by(|&a, &b| {
+            let cost = |widget: Widget| self.averages[widget.index()];
+            cost(b).partial_cmp(&cost(a)).unwrap()
+        });
+        let costliest: Vec<_> = widgets
+            .iter()
+            .take(LISTED)
+            .map(|&widget| format!("{} {:.1}", widget.name(), self.averages[widget.index()]))
+            .collect();
+        lines.push(costliest.join(", "));
+
+        let degraded: Vec<_> = Widget::ALL
+            .iter()
+            .filter(|&&widget| self.levels.of(widget) > 0)
+            .map(|&widget| widget.degraded()[usize::from(self.levels.of(widget)) - 1])
+            .collect();
+        if !degraded.is_empty() {
+            lines.push(format!("Degraded: {}", degraded.join(", ")));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn costs(widgets: &[(Widget, f32)]) -> Costs {
+        let mut costs = Costs::default();
+        for &(widget, ms) in widgets {
+            costs.ms[widget.index()] = ms;
+        }
+        costs
+    }
+
+    fn frames(budget: &mut HudBudget, count: u32, widgets: &[(Widget, f32)], total_ms: f32) {
+        for _ in 0..count {
+            budget.frame(costs(widgets), total_ms);
+        }
+    }
+
+    const SLOW: [(Widget, f32); 2] = [(Widget::Graticule, 3.0), (Widget::Stars, 1.0)];
+
+    #[test]
+    fn the_costliest_widget_is_degraded_while_over_and_restored_with_headroom() {
+        let mut budget = HudBudget::new(4.0);
+
+        frames(&mut budget, SUSTAIN_FRAMES - 1, &SLOW, 5.0);
+        assert_eq!(budget.levels(), Levels::default());
+        frames(&mut budget, 1, &SLOW, 5.0);
+        assert_eq!(budget.levels().of(Widget::Graticule), 1);
+
+        // Once the grid is gone, the stars are next.
+        frames(&mut budget, 2 * SUSTAIN_FRAMES, &SLOW, 5.0);
+        assert_eq!(budget.levels().of(Widget::Graticule), 2);
+        assert_eq!(budget.levels().of(Widget::Stars), 1);
+        assert_eq!(budget.details()[2], "Degraded: no stars, no grid");
+
+        // What was degraded last is restored first.
+        frames(&mut budget, RECOVER_FRAMES, &[], 1.0);
+        assert_eq!(budget.levels().of(Widget::Stars), 0);
+        assert_eq!(budget.levels().of(Widget::Graticule), 2);
+    }
+
+    #[test]
+    fn frames_over_the_budget_must_be_sustained() {
+        let mut budget = HudBudget::new(4.0);
+
+        for _ in 0..3 {
+            frames(&mut budget, SUSTAIN_FRAMES - 1, &SLOW, 5.0);
+            frames(&mut budget, 1, &SLOW, 3.0);
+        }
+        assert_eq!(budget.levels(), Levels::default());
+    }
+
+    #[test]
+    fn turning_the_budget_off_restores_everything() {
+        let mut budget = HudBudget::new(4.0);
+        frames(&mut budget, SUSTAIN_FRAMES, &SLOW, 5.0);
+        assert_ne!(budget.levels(), Levels::default());
+
+        budget.set_budget(0.0);
+        assert_eq!(budget.levels(), Levels::default());
+        frames(&mut budget, 10 * SUSTAIN_FRAMES, &SLOW, 5.0);
+        assert_eq!(budget.levels(), Levels::default());
+
+        // Time the widgets did not take is the panels'.
+        let details = budget.details();
+        assert_eq!(details.len(), 2, "{:?}", details);
+        assert_eq!(details[0], "2D pass: 5.0 ms");
+        assert!(details[1].starts_with("Grid 3.0, Stars 1.0, Panels 1.0"), "{}", details[1]);
+    }
+}
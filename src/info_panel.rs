@@ -0,0 +1,140 @@
+use gaia_assetgen::Properties;
+use piston_window::Glyphs;
+use serde_json::Value;
+
+use formatting;
+use hud::HudScale;
+use settings::FormattingSettings;
+
+/// Shown in place of values the dataset doesn't have, so that the panel keeps its layout.
+const MISSING: &str = "\u{2014}";
+
+/// Country names wider than this many points, at a HUD scale of 1, are wrapped onto several lines.
+const MAX_NAME_WIDTH: f64 = 250.0;
+
+const FONT_SIZE: u32 = 10;
+
+/// The last line of the info panel while the selected country's web page can be opened, which
+/// opens it when clicked.
+pub const MORE_INFO: &str = "More info (Enter)";
+
+/// The properties shown when none are configured.
+pub fn default_fields() -> Vec<String> {
+    ["ISO_A3", "CONTINENT", "SUBREGION", "POP_EST", "GDP_MD_EST", "INCOME_GRP"]
+        .iter()
+        .map(|field| field.to_string())
+        .collect()
+}
+
+/// The lines of the info panel for a country with `properties`: its name, then each of `fields`.
+/// `mode_value` is what the active map mode says about the country, if anything.
+pub fn lines(
+    properties: &Properties,
+    fields: &[String],
+    mode_value: Option<(&str, String)>,
+    format: FormattingSettings,
+    scale: HudScale,
+    glyphs: &mut Glyphs,
+) -> Vec<String> {
+    let name = properties
+        .get("NAME")
+        .or_else(|| properties.get("ADMIN"))
+        .and_then(Value::as_str)
+        .unwrap_or(MISSING);
+
+    let mut lines = wrap(name, scale.len(MAX_NAME_WIDTH), scale, glyphs);
+    for field in fields {
+        let value = properties
+            .get(field.as_str())
+            .and_then(|value| format_value(field, value, format))
+            .unwrap_or_else(|| MISSING.to_string());
+
+        lines.push(format!("{}: {}", field_label(field), value));
+    }
+
+    if let Some((mode_name, value)) = mode_value {
+        lines.push(format!("{}: {}", mode_name, value));
+    }
+
+    lines
+}
+
+/// A friendly name for the well-known Natural Earth properties. Others are shown as-is.
+fn field_label(field: &str) -> &str {
+    match field {
+        "ISO_A3" => "ISO code",
+        "CONTINENT" => "Continent",
+        "SUBREGION" => "Subregion",
+        "REGION_UN" => "Region",
+        "POP_EST" => "Population",
+        "GDP_MD_EST" => "GDP",
+        "INCOME_GRP" => "Income group",
+        "ECONOMY" => "Economy",
+        _ => field,
+    }
+}
+
+/// Formats `value` for display, or `None` if the dataset marks it as missing.
+fn format_value(field: &str, value: &Value, format: FormattingSettings) -> Option<String> {
+    match *value {
+        Value::Null => None,
+        Value::Number(ref number) => {
+            let number = number.as_f64()?;
+
+            // Natural Earth uses -99 for unknown values.
+            if number == -99.0 {
+                return None;
+            }
+
+            Some(match field {
+                "POP_EST" => formatting::count(number, format),
+                "GDP_MD_EST" => format!("${}", formatting::count(number * 1e6, format)),
+                _ => formatting::count(number, format),
+            })
+        }
+        Value::String(ref string) => {
+            if string.is_empty() || string == "-99" {
+                None
+            } else {
+                Some(strip_rank(string).to_string())
+            }
+        }
+        ref other => Some(other.to_string()),
+    }
+}
+
+/// Natural Earth prefixes ordered categories with their rank, as in "1. High income: OECD".
+fn strip_rank(string: &str) -> &str {
+    match string.find(". ") {
+        Some(index) if string[..index].chars().all(|c| c.is_ascii_digit()) => &string[index + 2..],
+        _ => string,
+    }
+}
+
+/// Splits `text` at spaces into lines no wider than `max_width`. A single word wider than that
+/// gets a line of its own.
+pub fn wrap(text: &str, max_width: f64, scale: HudScale, glyphs: &mut Glyphs) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+
+        if !line.is_empty() && scale.text_width(FONT_SIZE, &candidate, glyphs) > max_width {
+            lines.push(line);
+            line = word.to_string();
+        } else {
+            line = candidate;
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
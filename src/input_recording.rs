@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use piston::input::{Event, Input, Loop};
+use piston::window::{AdvancedWindow, Size, Window};
+use piston_window::PistonWindow;
+use serde_json;
+
+use {Result, ResultExt};
+
+/// The longest replay sleeps at once while waiting for the next event, so that the window keeps
+/// responding.
+const MAX_SLEEP: Duration = Duration::from_millis(10);
+
+/// The first line of a recording.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    /// The window's size when recording started.
+    window_size: [u32; 2],
+}
+
+/// An event as it is recorded. Only input and loop events are, since custom events cannot be
+/// written out.
+#[derive(Debug, Serialize, Deserialize)]
+enum RecordedEvent {
+    Input(Input),
+    Loop(Loop),
+}
+
+/// Every line of a recording after the header.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// Seconds since recording started.
+    time: f64,
+    event: RecordedEvent,
+}
+
+/// Writes every event the main loop handles to a file, one JSON object per line.
+pub struct InputRecorder {
+    file: BufWriter<File>,
+    started: Instant,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path, window_size: Size) -> Result<InputRecorder> {
+        let file = File::create(path)
+            .chain_err(|| format!("Could not create input recording {}", path.display()))?;
+        let mut recorder = InputRecorder {
+            file: BufWriter::new(file),
+            started: Instant::now(),
+        };
+
+        let header = Header {
+            window_size: [window_size.width, window_size.height],
+        };
+        recorder.write_line(&header)?;
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, e: &Event) -> Result<()> {
+        let event = match *e {
+            Event::Input(ref input) => RecordedEvent::Input(input.clone()),
+            Event::Loop(ref loop_event) => RecordedEvent::Loop(*loop_event),
+            Event::Custom(..) => return Ok(()),
+        };
+
+        let elapsed = self.started.elapsed();
+        let time = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        self.write_line(&Entry { time, event })?;
+
+        // Flushed once a frame, so that little is lost if the demo crashes.
+        if let Event::Loop(Loop::AfterRender(_)) = *e {
+            self.file
+                .flush()
+                .chain_err(|| "Could not write input recording")?;
+        }
+
+        Ok(())
+    }
+
+    fn write_line<T: ::serde::Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.file, value)
+            .map_err(|e| e.to_string())
+            .and_then(|_| writeln!(self.file).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Could not write input recording: {}", e).into())
+    }
+}
+
+/// How fast a recording is replayed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplaySpeed {
+    /// This many times real time, so 1 is real time.
+    Scaled(f64),
+    /// As fast as events can be handled.
+    Max,
+}
+
+/// Feeds recorded events back in place of the window's, with the same timesteps as when
+/// recording, so that the demo does exactly what it did then.
+pub struct InputReplay {
+    entries: Vec<Entry>,
+    next: usize,
+    speed: ReplaySpeed,
+    started: Option<Instant>,
+    /// The window size last warned about not matching the recording.
+    warned_size: Option<[u32; 2]>,
+}
+
+impl InputReplay {
+    /// Reads the recording at `path`, and resizes `window` to the size it was recorded at.
+    pub fn load(path: &Path, speed: ReplaySpeed, window: &mut PistonWindow) -> Result<InputReplay> {
+        let file = File::open(path)
+            .chain_err(|| format!("Could not open input recording {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines().enumerate();
+        let invalid = |index: usize| {
+            format!("Invalid input recording {}, line {}", path.display(), index + 1)
+        };
+
+        let header: Header = match lines.next() {
+            Some((index, line)) => {
+                let line = line.chain_err(|| invalid(index))?;
+                serde_json::from_str(&line).chain_err(|| invalid(index))?
+            }
+            None => bail!("Input recording {} is empty", path.display()),
+        };
+
+        let mut entries = Vec::new();
+        for (index, line) in lines {
+            let line = line.chain_err(|| invalid(index))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry = serde_json::from_str(&line).chain_err(|| invalid(index))?;
+            entries.push(entry);
+        }
+
+        let [width, height] = header.window_size;
+        window.set_size([width, height]);
+
+        Ok(InputReplay {
+            entries,
+            next: 0,
+            speed,
+            started: None,
+            warned_size: None,
+        })
+    }
+
+    /// The next recorded event, once it is due. `None` once the recording is over, or the window
+    /// was closed.
+    ///
+    /// The window's own events are polled and dropped, so that it keeps responding but does not
+    /// change what is replayed. Buffers are not swapped, since the main loop does that itself.
+    pub fn next(&mut self, window: &mut PistonWindow) -> Option<Event> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+
+        loop {
+            while Window::poll_event(window).is_some() {}
+            if window.should_close() {
+                return None;
+            }
+
+            let time = self.entries.get(self.next)?.time;
+            if let ReplaySpeed::Scaled(speed) = self.speed {
+                let due = started + Duration::from_millis((time / speed * 1000.0) as u64);
+                let now = Instant::now();
+                if due > now {
+                    thread::sleep((due - now).min(MAX_SLEEP));
+                    continue;
+                }
+            }
+
+            break;
+        }
+
+        let event = match self.entries[self.next].event {
+            RecordedEvent::Input(ref input) => {
+                if let Input::Resize(width, height) = *input {
+                    window.set_size([width, height]);
+                }
+                Event::Input(input.clone())
+            }
+            RecordedEvent::Loop(Loop::Render(mut args)) => {
+                // Drawing with the recorded size would be wrong if the window could not be
+                // resized to it, so the real size is used, and the difference warned about.
+                let (size, draw_size) = (window.size(), window.draw_size());
+                let recorded = [args.width, args.height];
+                if recorded != [size.width, size.height] && self.warned_size != Some(recorded) {
+                    warn!(
+                        "The window is {}x{}, but was {}x{} when recording, so the replay may \
+                         differ",
+                        size.width, size.height, args.width, args.height
+                    );
+                    self.warned_size = Some(recorded);
+                }
+
+                args.width = size.width;
+                args.height = size.height;
+                args.draw_width = draw_size.width;
+                args.draw_height = draw_size.height;
+                Event::Loop(Loop::Render(args))
+            }
+            RecordedEvent::Loop(loop_event) => Event::Loop(loop_event),
+        };
+        self.next += 1;
+
+        // What `PistonWindow::next` does with each event, such as resizing its render targets.
+        window.event(&event);
+        Some(event)
+    }
+}
@@ -0,0 +1,309 @@
+//! Thins out labels that would be drawn on top of each other, before gaia places them.
+//!
+//! gaia labels every place its chooser gives a style to, wherever they fall, so at medium zooms
+//! the names of nearby towns pile up. Each time the camera moves, the places that would be
+//! labeled are projected to the screen and sorted into a grid of square cells; in each cell
+//! with more than one, only the most prominent keeps its label. Capitals come first, then
+//! megacities, then the places Natural Earth ranks higher, then the more populous.
+
+use std::collections::{HashMap, HashSet};
+
+use cgmath::Matrix4;
+use gaia_assetgen::MultiLevelPoint;
+
+use min_zoom_curve::{self, ControlPoints};
+use projection;
+use props::PlaceProps;
+
+/// A place that might be labeled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub name: String,
+    /// The name of the country it is in, or "" if it has none.
+    pub country: String,
+    /// In map space.
+    pub point: [f32; 2],
+    /// As stored, before `labels.min_zoom_curve` remaps it.
+    pub min_zoom: f64,
+    pub is_capital: bool,
+    pub is_megacity: bool,
+    pub rank: Option<f64>,
+    pub population: Option<f64>,
+}
+
+impl Candidate {
+    /// What the places in a cell are compared by, the highest keeping its label.
+    fn priority(&self) -> (bool, bool, f64, f64) {
+        (
+            self.is_capital,
+            self.is_megacity,
+            -self.rank.unwrap_or(::std::f64::INFINITY),
+            self.population.unwrap_or(0.0),
+        )
+    }
+}
+
+/// The places among `points` that can be labeled: those with a name and a zoom level.
+pub fn candidates(points: &[MultiLevelPoint]) -> Vec<Candidate> {
+    points
+        .iter()
+        .filter_map(|point| {
+            let place = PlaceProps::read(&point.properties);
+            Some(Candidate {
+                name: place.name?.to_string(),
+                country: place.country.unwrap_or("").to_string(),
+                point: point.coordinates,
+                min_zoom: place.min_zoom?,
+                is_capital: place.is_capital,
+                is_megacity: place.is_megacity,
+                rank: place.rank,
+                population: place.population,
+            })
+        })
+        .collect()
+}
+
+/// Which candidates are labeled before decluttering, as the label style chooser decides.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shown {
+    pub camera_height: f32,
+    pub capitals_only: bool,
+    pub min_zoom_curve: ControlPoints,
+}
+
+impl Shown {
+    fn includes(&self, candidate: &Candidate) -> bool {
+        let min_zoom = min_zoom_curve::remap(&self.min_zoom_curve, candidate.min_zoom);
+        min_zoom_curve::is_labeled_at(min_zoom, self.camera_height)
+            && (candidate.is_capital || !self.capitals_only)
+    }
+}
+
+/// What a pass was worked out for, which it is only worked out again once it changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pass {
+    mvp: [[f32; 4]; 4],
+    look_at: [f32; 2],
+    view_size: [f64; 2],
+    cell: f64,
+    shown: Shown,
+}
+
+/// The labels the last pass left out, by name and then by country.
+#[derive(Debug, Default)]
+pub struct LabelDeclutter {
+    last: Option<Pass>,
+    suppressed: HashMap<String, HashSet<String>>,
+}
+
+impl LabelDeclutter {
+    /// Works out which of `candidates` to leave unlabeled, as drawn through `mvp` to a view
+    /// `view_size` pixels across, with cells `cell` pixels on a side. Only those `shown` labels
+    /// are counted, since the others cannot crowd anything out. Returns whether it was worked
+    /// out again, which it is not if nothing changed since the last pass.
+    pub fn update(
+        &mut self,
+        candidates: &[Candidate],
+        mvp: Matrix4<f32>,
+        look_at: [f32; 2],
+        view_size: [f64; 2],
+        cell: f64,
+        shown: Shown,
+    ) -> bool {
+        let pass = Pass {
+            mvp: mvp.into(),
+            look_at,
+            view_size,
+            cell,
+            shown,
+        };
+        if self.last == Some(pass) {
+            return false;
+        }
+        self.last = Some(pass);
+
+        // The most prominent candidate in each cell, and every other one there.
+        let mut kept: HashMap<(i64, i64), &Candidate> = HashMap::new();
+        let mut crowded: Vec<&Candidate> = vec![];
+        for candidate in candidates.iter().filter(|candidate| shown.includes(candidate)) {
+            let center = match project(candidate.point, mvp, look_at, view_size) {
+                Some(center) => center,
+                None => continue,
+            };
+            let key = ((center[0] / cell).floor() as i64, (center[1] / cell).floor() as i64);
+
+            match kept.get(&key).cloned() {
+                Some(best) if best.priority() >= candidate.priority() => crowded.push(candidate),
+                Some(best) => {
+                    crowded.push(best);
+                    kept.insert(key, candidate);
+                }
+                None => {
+                    kept.insert(key, candidate);
+                }
+            }
+        }
+
+        // Labels are told apart by name and country, so a place that shares both with one that
+        // is kept is kept too.
+        let kept: HashSet<(&str, &str)> = kept
+            .values()
+            .map(|candidate| (candidate.name.as_str(), candidate.country.as_str()))
+            .collect();
+        self.suppressed.clear();
+        for candidate in crowded {
+            if !kept.contains(&(candidate.name.as_str(), candidate.country.as_str())) {
+                self.suppressed
+                    .entry(candidate.name.clone())
+                    .or_default()
+                    .insert(candidate.country.clone());
+            }
+        }
+
+        true
+    }
+
+    /// Forgets the last pass, so that nothing is left out, as while decluttering is off.
+    pub fn clear(&mut self) {
+        self.last = None;
+        self.suppressed.clear();
+    }
+
+    /// Whether the label of `name`, in `country`, is left out.
+    pub fn suppresses(&self, name: &str, country: &str) -> bool {
+        self.suppressed.get(name).map_or(false, |countries| countries.contains(country))
+    }
+
+    /// How many labels are left out.
+    pub fn suppressed_count(&self) -> usize {
+        self.suppressed.values().map(HashSet::len).sum()
+    }
+}
+
+/// Where on a view `view_size` pixels across through `mvp` the map-space `point` is drawn, at
+/// its copy nearest `look_at`, or `None` if it is behind the camera or off the view.
+fn project(
+    point: [f32; 2],
+    mvp: Matrix4<f32>,
+    look_at: [f32; 2],
+    view_size: [f64; 2],
+) -> Option<[f64; 2]> {
+    // The map is twice as wide in world space, where it repeats.
+    let center = projection::project(mvp, look_at, [point[0] * 2.0, point[1]], view_size)?;
+
+    if projection::is_on_view(center, view_size, 0.0) {
+        Some(center)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::SquareMatrix;
+
+    const VIEW_SIZE: [f64; 2] = [1000.0, 1000.0];
+
+    const SHOWN: Shown = Shown {
+        camera_height: 0.1,
+        capitals_only: false,
+        min_zoom_curve: min_zoom_curve::IDENTITY,
+    };
+
+    /// A town at `point`, which the identity matrix draws 1000 pixels right of the middle of
+    /// the view for each unit of x, and 500 pixels up for each of y.
+    fn town(name: &str, point: [f32; 2], population: f64) -> Candidate {
+        Candidate {
+            name: name.to_string(),
+            country: "Country".to_string(),
+            point,
+            min_zoom: 5.0,
+            is_capital: false,
+            is_megacity: false,
+            rank: Some(8.0),
+            population: Some(population),
+        }
+    }
+
+    fn declutter(candidates: &[Candidate], shown: Shown) -> LabelDeclutter {
+        let mut declutter = LabelDeclutter::default();
+        declutter.update(candidates, Matrix4::identity(), [0.0, 0.0], VIEW_SIZE, 50.0, shown);
+        declutter
+    }
+
+    #[test]
+    fn crowded_cells_keep_their_most_prominent_label() {
+        let candidates = [
+            town("Small", [0.01, 0.01], 1e3),
+            town("Large", [0.02, 0.01], 1e6),
+            town("Ranked", [0.01, 0.02], 1e3),
+            town("Capital", [0.015, 0.015], 1e2),
+            town("Apart", [0.4, 0.5], 1e3),
+        ];
+        let mut ranked = candidates.to_vec();
+        ranked[2].rank = Some(2.0);
+
+        // Towns by population...
+        let decluttered = declutter(&candidates[..2], SHOWN);
+        assert!(decluttered.suppresses("Small", "Country"));
+        assert!(!decluttered.suppresses("Large", "Country"));
+        // ...under those Natural Earth ranks higher...
+        let decluttered = declutter(&ranked[..4], SHOWN);
+        assert!(decluttered.suppresses("Large", "Country"));
+        assert!(!decluttered.suppresses("Ranked", "Country"));
+        assert_eq!(decluttered.suppressed_count(), 3);
+
+        // ...under megacities, under capitals.
+        let mut cities = candidates.to_vec();
+        cities[0].is_megacity = true;
+        cities[3].is_capital = true;
+        let decluttered = declutter(&cities, SHOWN);
+        assert!(decluttered.suppresses("Small", "Country"));
+        assert!(!decluttered.suppresses("Capital", "Country"));
+        // A town on its own is left alone.
+        assert!(!decluttered.suppresses("Apart", "Country"));
+        assert_eq!(decluttered.suppressed_count(), 3);
+    }
+
+    #[test]
+    fn places_not_labeled_crowd_nothing_out() {
+        let mut candidates = vec![town("Town", [0.01, 0.01], 1e3), town("City", [0.02, 0.02], 1e6)];
+        candidates[1].min_zoom = 20.0;
+        assert_eq!(declutter(&candidates, SHOWN).suppressed_count(), 0);
+
+        candidates[1].min_zoom = 5.0;
+        candidates[0].is_capital = true;
+        let capitals_only = Shown {
+            capitals_only: true,
+            ..SHOWN
+        };
+        assert_eq!(declutter(&candidates, capitals_only).suppressed_count(), 0);
+        assert!(declutter(&candidates, SHOWN).suppresses("City", "Country"));
+
+        // Off the view, or behind the camera.
+        let mut behind = Matrix4::identity();
+        behind.w.w = -1.0;
+        let mut declutter = LabelDeclutter::default();
+        declutter.update(&candidates, behind, [0.0, 0.0], VIEW_SIZE, 50.0, SHOWN);
+        assert_eq!(declutter.suppressed_count(), 0);
+    }
+
+    #[test]
+    fn passes_are_only_worked_out_again_once_something_changes() {
+        let candidates = [town("Small", [0.01, 0.01], 1e3), town("Large", [0.02, 0.01], 1e6)];
+        let mut declutter = LabelDeclutter::default();
+        let update = |declutter: &mut LabelDeclutter, look_at: [f32; 2]| {
+            declutter.update(&candidates, Matrix4::identity(), look_at, VIEW_SIZE, 50.0, SHOWN)
+        };
+
+        assert!(update(&mut declutter, [0.0, 0.0]));
+        assert!(!update(&mut declutter, [0.0, 0.0]));
+        assert!(update(&mut declutter, [0.1, 0.0]));
+        assert!(declutter.suppresses("Small", "Country"));
+
+        declutter.clear();
+        assert!(!declutter.suppresses("Small", "Country"));
+        assert!(update(&mut declutter, [0.1, 0.0]));
+    }
+}
@@ -0,0 +1,213 @@
+//! Which labels are drawn, from the labels switch and the map mode's label policy.
+//!
+//! What decides, from first to last:
+//!
+//! 1. Pressing 0 since the map mode was last switched. It flips between some labels and none,
+//!    from whatever is shown, and sets the mode's policy aside until the mode is switched again.
+//! 2. The map mode's policy, from its adjustments in `modes.adjustments` or else from
+//!    `modes.labels`, applied whenever the mode is switched to, unless it is `Inherit`.
+//! 3. The labels switch: `labels.enabled` at first, and then wherever 0 last left it.
+//!
+//! A policy of `CapitalsOnly` labels only capitals. The capital the tour is at is labeled
+//! whenever any labels are.
+
+use MapMode;
+
+/// What a map mode does to labels when it is switched to. Written in snake case in the settings
+/// file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelPolicy {
+    /// Leaves labels as the labels switch has them.
+    Inherit,
+    /// Labels every place.
+    On,
+    /// Labels nothing.
+    Off,
+    /// Labels capitals, one for each country, but not other places.
+    CapitalsOnly,
+}
+
+impl LabelPolicy {
+    pub const ALL: [LabelPolicy; 4] = [
+        LabelPolicy::Inherit,
+        LabelPolicy::On,
+        LabelPolicy::Off,
+        LabelPolicy::CapitalsOnly,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LabelPolicy::Inherit => "as the switch has them",
+            LabelPolicy::On => "on",
+            LabelPolicy::Off => "off",
+            LabelPolicy::CapitalsOnly => "capitals only",
+        }
+    }
+
+    /// The policy `steps` on from this one in `ALL`, wrapping around.
+    pub fn cycle(&self, steps: isize) -> LabelPolicy {
+        let len = LabelPolicy::ALL.len() as isize;
+        let index = LabelPolicy::ALL.iter().position(|policy| policy == self).unwrap() as isize;
+
+        LabelPolicy::ALL[(index + steps).rem_euclid(len) as usize]
+    }
+}
+
+/// Which labels are drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LabelsShown {
+    None,
+    Capitals,
+    All,
+}
+
+impl LabelsShown {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LabelsShown::None => "off",
+            LabelsShown::Capitals => "capitals",
+            LabelsShown::All => "on",
+        }
+    }
+}
+
+/// Each map mode's label policy, in `modes.labels`. Income mode's is off by default, since its
+/// colors are hard to read under every place's name.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeLabelPolicies {
+    pub terrain: LabelPolicy,
+    pub all: LabelPolicy,
+    pub oecd: LabelPolicy,
+    pub income: LabelPolicy,
+    pub exceptional: LabelPolicy,
+    pub scripted: LabelPolicy,
+    pub daylight: LabelPolicy,
+    pub percentile: LabelPolicy,
+}
+
+impl Default for ModeLabelPolicies {
+    fn default() -> ModeLabelPolicies {
+        ModeLabelPolicies {
+            terrain: LabelPolicy::Inherit,
+            all: LabelPolicy::Inherit,
+            oecd: LabelPolicy::Inherit,
+            income: LabelPolicy::Off,
+            exceptional: LabelPolicy::Inherit,
+            scripted: LabelPolicy::Inherit,
+            daylight: LabelPolicy::Inherit,
+            percentile: LabelPolicy::Inherit,
+        }
+    }
+}
+
+impl ModeLabelPolicies {
+    pub fn of(&self, mode: MapMode) -> LabelPolicy {
+        match mode {
+            MapMode::Terrain => self.terrain,
+            MapMode::All => self.all,
+            MapMode::Oecd => self.oecd,
+            MapMode::Income => self.income,
+            MapMode::Exceptional => self.exceptional,
+            MapMode::Scripted => self.scripted,
+            MapMode::Daylight => self.daylight,
+            MapMode::Percentile => self.percentile,
+        }
+    }
+
+    pub fn of_mut(&mut self, mode: MapMode) -> &mut LabelPolicy {
+        match mode {
+            MapMode::Terrain => &mut self.terrain,
+            MapMode::All => &mut self.all,
+            MapMode::Oecd => &mut self.oecd,
+            MapMode::Income => &mut self.income,
+            MapMode::Exceptional => &mut self.exceptional,
+            MapMode::Scripted => &mut self.scripted,
+            MapMode::Daylight => &mut self.daylight,
+            MapMode::Percentile => &mut self.percentile,
+        }
+    }
+}
+
+/// Which labels are drawn with the labels switch `enabled`, under `policy`.
+pub fn resolve(enabled: bool, policy: LabelPolicy) -> LabelsShown {
+    match policy {
+        LabelPolicy::Inherit if enabled => LabelsShown::All,
+        LabelPolicy::Inherit => LabelsShown::None,
+        LabelPolicy::On => LabelsShown::All,
+        LabelPolicy::Off => LabelsShown::None,
+        LabelPolicy::CapitalsOnly => LabelsShown::Capitals,
+    }
+}
+
+/// The labels switch and policy after pressing 0 with `enabled` and `policy`: the switch flips
+/// from what is shown, and the policy is set aside.
+pub fn toggled(enabled: bool, policy: LabelPolicy) -> (bool, LabelPolicy) {
+    let enabled = resolve(enabled, policy) == LabelsShown::None;
+
+    (enabled, LabelPolicy::Inherit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICIES: [LabelPolicy; 4] = [
+        LabelPolicy::Inherit,
+        LabelPolicy::On,
+        LabelPolicy::Off,
+        LabelPolicy::CapitalsOnly,
+    ];
+
+    #[test]
+    fn inherit_follows_the_switch() {
+        assert_eq!(resolve(true, LabelPolicy::Inherit), LabelsShown::All);
+        assert_eq!(resolve(false, LabelPolicy::Inherit), LabelsShown::None);
+    }
+
+    #[test]
+    fn other_policies_override_the_switch() {
+        for &enabled in &[false, true] {
+            assert_eq!(resolve(enabled, LabelPolicy::On), LabelsShown::All);
+            assert_eq!(resolve(enabled, LabelPolicy::Off), LabelsShown::None);
+            assert_eq!(resolve(enabled, LabelPolicy::CapitalsOnly), LabelsShown::Capitals);
+        }
+    }
+
+    #[test]
+    fn toggling_flips_what_is_shown_under_any_policy() {
+        for &enabled in &[false, true] {
+            for &policy in &POLICIES {
+                let before = resolve(enabled, policy);
+                let (enabled, policy) = toggled(enabled, policy);
+
+                assert_eq!(policy, LabelPolicy::Inherit);
+                let hidden = resolve(enabled, policy) == LabelsShown::None;
+                assert_eq!(hidden, before != LabelsShown::None);
+            }
+        }
+    }
+
+    #[test]
+    fn toggling_twice_keeps_labels_with_the_policy_set_aside() {
+        // Forced off, 0 shows labels, and pressing it again hides them, regardless of the policy.
+        let (enabled, policy) = toggled(true, LabelPolicy::Off);
+        assert_eq!(resolve(enabled, policy), LabelsShown::All);
+
+        let (enabled, policy) = toggled(enabled, policy);
+        assert_eq!(resolve(enabled, policy), LabelsShown::None);
+
+        // Capitals only counts as shown, so 0 hides them.
+        let (enabled, policy) = toggled(false, LabelPolicy::CapitalsOnly);
+        assert_eq!(resolve(enabled, policy), LabelsShown::None);
+    }
+
+    #[test]
+    fn income_mode_hides_labels_by_default() {
+        let policies = ModeLabelPolicies::default();
+
+        assert_eq!(policies.of(MapMode::Income), LabelPolicy::Off);
+        assert_eq!(policies.of(MapMode::Terrain), LabelPolicy::Inherit);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet};
+
+use settings::{LabelOverflow, LabelWrap};
+
+const ELLIPSIS: char = '\u{2026}';
+
+/// The text long place names are labeled with, worked out once for each name rather than every
+/// frame, and again only when how names are wrapped changes. Names that fit are left out.
+///
+/// gaia's label style chooser can only hand back text borrowed from the place's properties, or
+/// text that is never freed, so the texts are leaked. Each distinct text is leaked once however
+/// often the names are wrapped again, which keeps what is leaked to a few kilobytes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LabelTexts {
+    wrap: LabelWrap,
+    /// By the place's name.
+    texts: HashMap<String, &'static str>,
+    /// Every text leaked so far.
+    leaked: HashSet<&'static str>,
+}
+
+impl LabelTexts {
+    /// Works out how each of `names` is labeled with `wrap`.
+    pub fn new(names: &[String], wrap: LabelWrap) -> LabelTexts {
+        let mut texts = LabelTexts::default();
+        texts.rewrap(names, wrap);
+        texts
+    }
+
+    /// Works out how each of `names` is labeled with `wrap` instead, reusing the texts leaked
+    /// before.
+    pub fn rewrap(&mut self, names: &[String], wrap: LabelWrap) {
+        self.wrap = wrap;
+        self.texts.clear();
+        for name in names {
+            if let Some(text) = fit(name, wrap) {
+                let text = self.leak(text);
+                self.texts.insert(name.clone(), text);
+            }
+        }
+    }
+
+    fn leak(&mut self, text: String) -> &'static str {
+        if let Some(&leaked) = self.leaked.get(text.as_str()) {
+            return leaked;
+        }
+
+        let leaked: &'static str = Box::leak(text.into_boxed_str());
+        self.leaked.insert(leaked);
+        leaked
+    }
+
+    /// How the names were wrapped.
+    pub fn wrap(&self) -> LabelWrap {
+        self.wrap
+    }
+
+    /// What the place named `name` is labeled with: `name` itself unless it is too long.
+    pub fn get<'a>(&self, name: &'a str) -> &'a str {
+        self.texts.get(name).cloned().unwrap_or(name)
+    }
+}
+
+/// `name` as `wrap` fits it on the map, or `None` if it fits as it is.
+pub fn fit(name: &str, wrap: LabelWrap) -> Option<String> {
+    let length = name.chars().count();
+    match wrap.overflow {
+        LabelOverflow::Wrap if wrap.width > 0 && length > wrap.width => {
+            Some(wrap_lines(name, wrap.width))
+        }
+        LabelOverflow::Ellipsize if wrap.max_length > 0 && length > wrap.max_length => {
+            Some(ellipsize(name, wrap.max_length))
+        }
+        _ => None,
+    }
+}
+
+/// Breaks `name` onto two lines at the last space that keeps the first no longer than `width`, or
+/// at the first space if none does. What is left over is shortened to `width` too, since
+/// labels are no more than two lines. A name without spaces is shortened to `width`.
+fn wrap_lines(name: &str, width: usize) -> String {
+    let spaces: Vec<usize> = name.match_indices(' ').map(|(index, _)| index).collect();
+    let fits = spaces
+        .iter()
+        .cloned()
+        .filter(|&index| name[..index].chars().count() <= width)
+        .last();
+
+    match fits.or_else(|| spaces.first().cloned()) {
+        Some(index) => {
+            let (first, rest) = (name[..index].trim_end(), name[index..].trim_start());
+            format!("{}\n{}", first, ellipsize(rest, width))
+        }
+        None => ellipsize(name, width),
+    }
+}
+
+/// `text`, cut short with an ellipsis if it is longer than `max_length` characters.
+fn ellipsize(text: &str, max_length: usize) -> String {
+    if text.chars().count() <= max_length {
+        return text.to_string();
+    }
+
+    let mut shortened: String = text.chars().take(max_length.saturating_sub(1)).collect();
+    shortened.truncate(shortened.trim_end().len());
+    shortened.push(ELLIPSIS);
+    shortened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG: &str = "South Georgia and the South Sandwich Islands";
+
+    fn wrap(width: usize) -> LabelWrap {
+        LabelWrap {
+            width,
+            ..LabelWrap::default()
+        }
+    }
+
+    #[test]
+    fn long_names_break_at_a_space_onto_two_lines() {
+        assert_eq!(fit(LONG, wrap(20)).unwrap(), "South Georgia and\nthe South Sandwich\u{2026}");
+        assert_eq!(fit(LONG, wrap(30)).unwrap(), "South Georgia and the South\nSandwich Islands");
+        assert_eq!(fit("Kuala Lumpur", wrap(6)).unwrap(), "Kuala\nLumpur");
+        assert_eq!(fit("Ouagadougou", wrap(6)).unwrap(), "Ouaga\u{2026}");
+    }
+
+    #[test]
+    fn names_that_fit_are_left_alone() {
+        assert_eq!(fit("Paris", wrap(20)), None);
+        assert_eq!(fit(LONG, wrap(0)), None);
+
+        let texts = LabelTexts::new(&["Paris".to_string(), LONG.to_string()], wrap(30));
+        assert_eq!(texts.get("Paris"), "Paris");
+        assert_eq!(texts.get(LONG), "South Georgia and the South\nSandwich Islands");
+        assert_eq!(texts.get("Unknown"), "Unknown");
+    }
+
+    #[test]
+    fn texts_are_leaked_once_each() {
+        let names = ["Paris".to_string(), LONG.to_string()];
+        let mut texts = LabelTexts::new(&names, wrap(30));
+        let wrapped = texts.get(LONG);
+
+        texts.rewrap(&names, wrap(20));
+        assert_ne!(texts.get(LONG), wrapped);
+        texts.rewrap(&names, wrap(30));
+        assert!(::std::ptr::eq(texts.get(LONG), wrapped));
+        assert_eq!(texts.leaked.len(), 2);
+    }
+
+    #[test]
+    fn names_can_be_shortened_instead() {
+        let wrap = LabelWrap {
+            overflow: LabelOverflow::Ellipsize,
+            width: 10,
+            max_length: 18,
+            ..LabelWrap::default()
+        };
+
+        assert_eq!(fit(LONG, wrap).unwrap(), "South Georgia and\u{2026}");
+        assert_eq!(fit("São Tomé and Príncipe", wrap).unwrap(), "São Tomé and Prín\u{2026}");
+        assert_eq!(fit("Kuala Lumpur", wrap), None);
+    }
+}
@@ -0,0 +1,228 @@
+use piston::input::keyboard::Key;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::{HudScale, HudStyle};
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+const SWATCH_SIZE: f64 = 10.0;
+const WIDTH: f64 = 260.0;
+
+/// Only categories that can be reached with Shift+1..Shift+9 can be toggled.
+const MAX_CATEGORIES: usize = 9;
+
+/// One category a map mode colors countries by.
+#[derive(Clone, Debug)]
+pub struct LegendEntry {
+    pub label: String,
+    pub color: [u8; 4],
+}
+
+/// Shows the categories of the current map mode, and lets each of them be hidden from the map.
+#[derive(Debug)]
+pub struct Legend {
+    visible: bool,
+    disabled: [bool; MAX_CATEGORIES],
+}
+
+impl Legend {
+    pub fn new() -> Legend {
+        Legend {
+            visible: false,
+            disabled: [false; MAX_CATEGORIES],
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Re-enables every category. Should be called whenever the map mode changes, since category
+    /// indices mean different things in each mode.
+    pub fn reset(&mut self) {
+        self.disabled = [false; MAX_CATEGORIES];
+    }
+
+    /// Toggles whether countries in `category` are shown. Does nothing if `category` is not one
+    /// of the `num_entries` categories of the current mode.
+    pub fn toggle_category(&mut self, category: usize, num_entries: usize) {
+        if category < num_entries.min(MAX_CATEGORIES) {
+            self.disabled[category] = !self.disabled[category];
+        }
+    }
+
+    /// The categories hidden from the map, in order.
+    pub fn hidden_categories(&self) -> Vec<usize> {
+        (0..MAX_CATEGORIES).filter(|&category| self.disabled[category]).collect()
+    }
+
+    /// Hides exactly `categories`, of the `num_entries` categories of the current mode.
+    pub fn set_hidden_categories(&mut self, categories: &[usize], num_entries: usize) {
+        self.reset();
+        for &category in categories {
+            if category < num_entries.min(MAX_CATEGORIES) {
+                self.disabled[category] = true;
+            }
+        }
+    }
+
+    pub fn is_enabled(&self, category: usize) -> bool {
+        category >= MAX_CATEGORIES || !self.disabled[category]
+    }
+
+    /// How much room `draw` needs to show `entries`.
+    pub fn size(entries: &[LegendEntry], scale: HudScale) -> [f64; 2] {
+        [
+            scale.len(WIDTH),
+            scale.len(LINE_HEIGHT * (entries.len() + 1) as f64),
+        ]
+    }
+
+    /// Draws `entries` with the top-left corner at `origin`. Disabled entries are grayed out and
+    /// struck through.
+    pub fn draw(
+        &self,
+        entries: &[LegendEntry],
+        origin: [f64; 2],
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let context = Legend::draw_panel(entries, origin, style, context, graphics);
+        self.draw_entries(entries, true, style, glyphs, context, graphics);
+    }
+
+    /// Draws `entries` as `draw` does, but as a key to colors that cannot be toggled, neither
+    /// numbered nor grayed out.
+    pub fn draw_key(
+        entries: &[LegendEntry],
+        origin: [f64; 2],
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let context = Legend::draw_panel(entries, origin, style, context, graphics);
+        Legend::new().draw_entries(entries, false, style, glyphs, context, graphics);
+    }
+
+    /// Draws the panel behind `entries` at `origin`, returning the context to draw them in.
+    fn draw_panel(
+        entries: &[LegendEntry],
+        origin: [f64; 2],
+        style: HudStyle,
+        context: Context,
+        graphics: &mut G2d,
+    ) -> Context {
+        let [width, height] = Legend::size(entries, style.scale);
+        let context = context.trans(origin[0], origin[1]);
+
+        ::piston_window::rectangle(
+            style.theme.panel_background,
+            [0.0, 0.0, width, height],
+            context.transform,
+            graphics,
+        );
+        context
+    }
+
+    /// Draws `entries` over whatever is already there, without a panel behind them, in the room
+    /// `size` gives them from the origin of `context`. If `numbered` is set, the entries that can
+    /// be toggled are numbered by their keys.
+    pub fn draw_entries(
+        &self,
+        entries: &[LegendEntry],
+        numbered: bool,
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let HudStyle { scale, theme } = style;
+        let swatch_size = scale.len(SWATCH_SIZE);
+        let text_x = scale.len(25.0);
+
+        for (i, entry) in entries.iter().enumerate() {
+            let enabled = self.is_enabled(i);
+            let y = scale.len(LINE_HEIGHT * (i + 1) as f64);
+
+            let [r, g, b, _] = entry.color;
+            let swatch_alpha = if enabled { 1.0 } else { 0.3 };
+            let swatch_color = [
+                f32::from(r) / 255.0,
+                f32::from(g) / 255.0,
+                f32::from(b) / 255.0,
+                swatch_alpha,
+            ];
+            ::piston_window::rectangle(
+                swatch_color,
+                [scale.len(10.0), y - swatch_size, swatch_size, swatch_size],
+                context.transform,
+                graphics,
+            );
+
+            let label = if numbered && i < MAX_CATEGORIES {
+                format!("{}. {}", i + 1, entry.label)
+            } else {
+                entry.label.to_string()
+            };
+            let text_color = if enabled {
+                theme.text
+            } else {
+                theme.muted_text()
+            };
+
+            scale.draw_text(
+                &label,
+                FONT_SIZE,
+                text_color,
+                context.trans(text_x, y),
+                glyphs,
+                graphics,
+            );
+
+            if !enabled {
+                let width = scale.text_width(FONT_SIZE, &label, glyphs);
+                let strike_y = y - scale.len(4.0);
+                ::piston_window::line(
+                    text_color,
+                    scale.len(0.5),
+                    [text_x, strike_y, text_x + width, strike_y],
+                    context.transform,
+                    graphics,
+                );
+            }
+        }
+    }
+}
+
+impl Default for Legend {
+    fn default() -> Legend {
+        Legend::new()
+    }
+}
+
+/// The category toggled by pressing Shift and `key`, if any.
+pub fn category_for_key(key: Key) -> Option<usize> {
+    match key {
+        Key::D1 => Some(0),
+        Key::D2 => Some(1),
+        Key::D3 => Some(2),
+        Key::D4 => Some(3),
+        Key::D5 => Some(4),
+        Key::D6 => Some(5),
+        Key::D7 => Some(6),
+        Key::D8 => Some(7),
+        Key::D9 => Some(8),
+        _ => None,
+    }
+}
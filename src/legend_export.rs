@@ -0,0 +1,142 @@
+use piston_window::{Glyphs, PistonWindow, Transformed, Viewport};
+
+use hud::{HudScale, HudStyle};
+use legend::{Legend, LegendEntry};
+use render_scale::ScaledTarget;
+use scale_bar;
+use screenshot::{self, Readback};
+use settings::FormattingSettings;
+use {MapMode, Palette, Result};
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+
+/// Space between the content and the image's edges.
+const PADDING: f64 = 5.0;
+
+/// Space between the legend and the scale bar.
+const SPACING: f64 = 5.0;
+
+/// The scale bar, as it is shown on the map.
+#[derive(Clone, Copy, Debug)]
+pub struct ScaleBar {
+    /// The ground distance covered by one point at the center of the window.
+    pub km_per_pixel: f64,
+    pub format: FormattingSettings,
+}
+
+/// The legend to export, as it is shown in the window.
+#[derive(Clone, Copy, Debug)]
+pub struct ShownLegend<'a> {
+    /// Which categories are hidden.
+    pub legend: &'a Legend,
+    pub mode: MapMode,
+    /// The colors the mode is drawn in.
+    pub palette: Palette,
+}
+
+/// Draws the legend of `shown.mode`, titled with its name and followed by `scale_bar` if given,
+/// onto a transparent image of just the room they take, `scale` times as large as `style` would
+/// draw them in the window. The scale bar measures the same distance as in the window, which
+/// makes it right for a high-resolution screenshot of the same `scale`. Hidden categories are
+/// grayed out, as in the window.
+pub fn render(
+    window: &mut PistonWindow,
+    glyphs: &mut Glyphs,
+    shown: ShownLegend,
+    scale_bar: Option<ScaleBar>,
+    style: HudStyle,
+    scale: u32,
+) -> Result<Readback> {
+    let ShownLegend {
+        legend,
+        mode,
+        palette,
+    } = shown;
+    let entries = mode.legend_entries_in(palette);
+    if entries.is_empty() {
+        bail!("{} mode has no legend", mode.name());
+    }
+
+    // The image is drawn in pixels, so text is rasterized at the size it is drawn.
+    let style = HudStyle {
+        scale: HudScale {
+            factor: style.scale.factor * f64::from(scale),
+            density: 1.0,
+        },
+        theme: style.theme,
+    };
+    let hud_scale = style.scale;
+    let [width, height] = size(&entries, scale_bar.is_some(), hud_scale);
+    let size = [width.ceil() as u32, height.ceil() as u32];
+    if size[0] > u32::from(u16::max_value()) || size[1] > u32::from(u16::max_value()) {
+        bail!("The legend would be {}x{}, too large to draw", size[0], size[1]);
+    }
+
+    let target = ScaledTarget::new(&mut window.factory, [size[0] as u16, size[1] as u16])?;
+    window.encoder.clear(&target.color, [0.0; 4]);
+    window.encoder.clear_stencil(&target.depth, 0);
+
+    let viewport = Viewport {
+        rect: [0, 0, size[0] as i32, size[1] as i32],
+        draw_size: size,
+        window_size: size,
+    };
+    window.g2d.draw(
+        &mut window.encoder,
+        &target.color,
+        &target.depth,
+        viewport,
+        |context, graphics| {
+            let padding = hud_scale.len(PADDING);
+            let context = context.trans(padding, padding);
+            hud_scale.draw_text(
+                mode.name(),
+                FONT_SIZE,
+                style.theme.text,
+                context.trans(0.0, hud_scale.len(LINE_HEIGHT)),
+                glyphs,
+                graphics,
+            );
+
+            let context = context.trans(0.0, hud_scale.len(LINE_HEIGHT));
+            legend.draw_entries(&entries, false, style, glyphs, context, graphics);
+
+            if let Some(ScaleBar { km_per_pixel, format }) = scale_bar {
+                let y = Legend::size(&entries, hud_scale)[1] + hud_scale.len(SPACING);
+                scale_bar::draw(
+                    km_per_pixel / f64::from(scale),
+                    format,
+                    [hud_scale.len(PADDING), y],
+                    style,
+                    glyphs,
+                    context,
+                    graphics,
+                );
+            }
+        },
+    );
+
+    let image = screenshot::read_back_target(
+        &mut window.factory,
+        &mut window.encoder,
+        &mut window.device,
+        &target.color,
+    )?;
+
+    Ok(image.unpremultiplied())
+}
+
+/// How large the image `render` draws is, in pixels at `scale`.
+fn size(entries: &[LegendEntry], with_scale_bar: bool, scale: HudScale) -> [f64; 2] {
+    let [legend_width, legend_height] = Legend::size(entries, scale);
+    let mut size = [legend_width, scale.len(LINE_HEIGHT) + legend_height];
+    if with_scale_bar {
+        let [bar_width, bar_height] = scale_bar::size(scale);
+        size[0] = size[0].max(scale.len(PADDING) + bar_width);
+        size[1] += scale.len(SPACING) + bar_height;
+    }
+
+    let padding = scale.len(PADDING);
+    [size[0] + 2.0 * padding, size[1] + 2.0 * padding]
+}
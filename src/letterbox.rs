@@ -0,0 +1,205 @@
+use std::fmt;
+use std::str::FromStr;
+
+use cgmath::{Matrix4, Vector3};
+use piston::window::Size;
+use piston_window::{Context, G2d};
+
+use hud::Insets;
+
+/// The width to height ratio `--lock-aspect` keeps the map at, such as 16:9, whatever the
+/// window's, with bars filling the rest of the window. Recordings then frame the map as it is
+/// presented.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AspectLock {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectLock {
+    pub fn ratio(&self) -> f64 {
+        f64::from(self.width) / f64::from(self.height)
+    }
+
+    /// The largest part of a window `size` across that has this ratio, centered in it, as
+    /// `[x, y, width, height]` from its top-left.
+    pub fn area(&self, size: [f64; 2]) -> [f64; 4] {
+        let [window_width, window_height] = size;
+        let width = window_width.min(window_height * self.ratio());
+        let height = window_height.min(window_width / self.ratio());
+
+        [(window_width - width) / 2.0, (window_height - height) / 2.0, width, height]
+    }
+}
+
+impl FromStr for AspectLock {
+    type Err = String;
+
+    /// Reads a ratio such as "16:9".
+    fn from_str(text: &str) -> ::std::result::Result<AspectLock, String> {
+        let invalid = || "expected a width and height above 0, such as 16:9".to_string();
+        let mut sides = text.splitn(2, ':').map(|side| side.trim().parse::<u32>());
+
+        match (sides.next(), sides.next()) {
+            (Some(Ok(width)), Some(Ok(height))) if width > 0 && height > 0 => {
+                Ok(AspectLock { width, height })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for AspectLock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.width, self.height)
+    }
+}
+
+/// The part of a window `size` across that the map is drawn in: what `lock` keeps it to, or the
+/// whole window without one.
+pub fn area(lock: Option<AspectLock>, size: [f64; 2]) -> [f64; 4] {
+    match lock {
+        Some(lock) => lock.area(size),
+        None => [0.0, 0.0, size[0], size[1]],
+    }
+}
+
+/// The size, in pixels, of the part of a window `draw_size` pixels across that the map is drawn
+/// in, which the projection is built for so that the map is not squashed into it.
+pub fn draw_size(lock: Option<AspectLock>, draw_size: Size) -> Size {
+    let size = [f64::from(draw_size.width), f64::from(draw_size.height)];
+    let [_, _, width, height] = area(lock, size);
+
+    Size {
+        width: (width.round() as u32).max(1),
+        height: (height.round() as u32).max(1),
+    }
+}
+
+/// Whether `point` is inside `area`, so that what is under it is on the map rather than a bar.
+pub fn contains(area: [f64; 4], point: [f64; 2]) -> bool {
+    let [x, y, width, height] = area;
+
+    point[0] >= x && point[0] <= x + width && point[1] >= y && point[1] <= y + height
+}
+
+/// How far `area` is in from each edge of a window `size` across, as `[top, right, bottom,
+/// left]`, so that the HUD keeps to it.
+pub fn insets(area: [f64; 4], size: [f64; 2]) -> Insets {
+    let [x, y, width, height] = area;
+
+    [y, size[0] - x - width, size[1] - y - height, x]
+}
+
+/// Maps the clip space of `area` onto that of the whole window `size` across, as
+/// `View::window_matrix` does for the halves of a split-screen.
+pub fn window_matrix(area: [f64; 4], size: [f64; 2]) -> Matrix4<f32> {
+    let [x, y, width, height] = area;
+    let [window_width, window_height] = [size[0].max(1.0), size[1].max(1.0)];
+    let center = [
+        (2.0 * (x + width / 2.0) / window_width - 1.0) as f32,
+        (1.0 - 2.0 * (y + height / 2.0) / window_height) as f32,
+    ];
+
+    Matrix4::from_translation(Vector3::new(center[0], center[1], 0.0))
+        * Matrix4::from_nonuniform_scale(
+            (width / window_width) as f32,
+            (height / window_height) as f32,
+            1.0,
+        )
+}
+
+/// The bars that fill the rest of a window `size` across around `area`, as
+/// `[x, y, width, height]`. None are left out, even when they are empty.
+pub fn bars(area: [f64; 4], size: [f64; 2]) -> [[f64; 4]; 4] {
+    let [x, y, width, height] = area;
+    let [window_width, window_height] = size;
+
+    [
+        [0.0, 0.0, window_width, y],
+        [0.0, y + height, window_width, window_height - y - height],
+        [0.0, y, x, height],
+        [x + width, y, window_width - x - width, height],
+    ]
+}
+
+/// Draws the bars around `area` in `color`, over whatever was drawn outside of it.
+pub fn draw_bars(area: [f64; 4], color: [f32; 4], context: Context, graphics: &mut G2d) {
+    for &bar in &bars(area, context.get_view_size()) {
+        if bar[2] > 0.0 && bar[3] > 0.0 {
+            ::piston_window::rectangle(color, bar, context.transform, graphics);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector4;
+
+    const SIXTEEN_NINE: AspectLock = AspectLock {
+        width: 16,
+        height: 9,
+    };
+
+    #[test]
+    fn ratios_are_read_as_width_and_height() {
+        assert_eq!("16:9".parse(), Ok(SIXTEEN_NINE));
+        assert_eq!(" 4 : 3 ".parse::<AspectLock>().map(|lock| lock.ratio()), Ok(4.0 / 3.0));
+        assert_eq!(SIXTEEN_NINE.to_string(), "16:9");
+
+        for text in &["16", "16:", ":9", "16:0", "0:9", "16:9:1", "wide", "-16:9"] {
+            assert!(text.parse::<AspectLock>().is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn the_area_is_the_largest_that_fits_centered() {
+        // A 16:10 projector shows 16:9 with bars above and below.
+        assert_eq!(SIXTEEN_NINE.area([1920.0, 1200.0]), [0.0, 60.0, 1920.0, 1080.0]);
+        // A window narrower than 16:9 is filled from side to side...
+        assert_eq!(SIXTEEN_NINE.area([1600.0, 900.0]), [0.0, 0.0, 1600.0, 900.0]);
+        // ...and a wider one from top to bottom.
+        assert_eq!(SIXTEEN_NINE.area([2560.0, 1080.0]), [320.0, 0.0, 1920.0, 1080.0]);
+
+        assert_eq!(area(None, [800.0, 600.0]), [0.0, 0.0, 800.0, 600.0]);
+        let size = |width, height| Size { width, height };
+        assert_eq!(draw_size(Some(SIXTEEN_NINE), size(3840, 2400)), size(3840, 2160));
+        assert_eq!(draw_size(Some(SIXTEEN_NINE), size(0, 0)), size(1, 1));
+        assert_eq!(draw_size(None, size(800, 600)), size(800, 600));
+    }
+
+    #[test]
+    fn the_bars_and_insets_surround_the_area() {
+        let size = [2560.0, 1080.0];
+        let area = SIXTEEN_NINE.area(size);
+
+        assert_eq!(insets(area, size), [0.0, 320.0, 0.0, 320.0]);
+        let covered: f64 = bars(area, size).iter().map(|bar| bar[2] * bar[3]).sum();
+        assert_eq!(covered + area[2] * area[3], size[0] * size[1]);
+    }
+
+    #[test]
+    fn the_areas_clip_space_lands_on_it_in_the_window() {
+        let size = [1920.0, 1200.0];
+        let matrix = window_matrix(SIXTEEN_NINE.area(size), size);
+        let to_window = |x: f32, y: f32| {
+            let point = matrix * Vector4::new(x, y, 0.5, 1.0);
+            [
+                (f64::from(point.x) + 1.0) / 2.0 * size[0],
+                (1.0 - f64::from(point.y)) / 2.0 * size[1],
+            ]
+        };
+
+        for &(clip, expected) in &[
+            ([-1.0, 1.0], [0.0, 60.0]),
+            ([1.0, -1.0], [1920.0, 1140.0]),
+            ([0.0, 0.0], [960.0, 600.0]),
+        ] {
+            let point = to_window(clip[0], clip[1]);
+            let distance = (point[0] - expected[0]).hypot(point[1] - expected[1]);
+            assert!(distance < 0.01, "{:?} landed at {:?}", clip, point);
+        }
+        assert_eq!(window_matrix(area(None, size), size), Matrix4::from_scale(1.0));
+    }
+}
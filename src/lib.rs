@@ -0,0 +1,499 @@
+//! Everything the demo does short of owning the window: the map modes and how they color
+//! countries, the choosers gaia draws the map with, the settings, and the state the main loop
+//! drives. The binary in `main.rs` only wires these up to a `piston_window` window.
+
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate percent_encoding;
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(unix)]
+extern crate libc;
+
+extern crate arboard;
+extern crate cgmath;
+extern crate chrono;
+extern crate clap;
+extern crate env_logger;
+extern crate fps_counter;
+extern crate gaia;
+extern crate gaia_assetgen;
+extern crate gfx;
+extern crate gif;
+extern crate glutin;
+extern crate hsl;
+extern crate image;
+extern crate piston;
+extern crate piston_window;
+extern crate rhai;
+extern crate rusttype;
+extern crate serde;
+extern crate serde_ignored;
+extern crate serde_json;
+extern crate toml;
+
+pub mod adaptive_quality;
+pub mod anaglyph;
+pub mod animation_clock;
+pub mod astro;
+pub mod benchmark;
+pub mod bindings;
+pub mod browser;
+pub mod camera_controller;
+pub mod chooser_benchmark;
+pub mod class_breaks;
+pub mod cli;
+pub mod clipboard;
+pub mod color_script;
+pub mod color_snapshot;
+pub mod command_palette;
+pub mod compass;
+pub mod coordinates;
+pub mod country_cycle;
+pub mod country_filter;
+pub mod country_match;
+pub mod crosshair;
+pub mod cursor_capture;
+pub mod cursor_point;
+pub mod effects;
+pub mod exec_script;
+pub mod export;
+pub mod features;
+pub mod font_fallback;
+pub mod frame_limiter;
+pub mod frame_recording;
+pub mod frame_times;
+pub mod formatting;
+pub mod fullscreen;
+pub mod geo_index;
+pub mod gl_context;
+pub mod graticule;
+pub mod headless;
+pub mod help_overlay;
+pub mod hi_res;
+pub mod hud;
+pub mod hud_budget;
+pub mod input_recording;
+pub mod info_panel;
+pub mod label_declutter;
+pub mod label_policy;
+pub mod label_text;
+pub mod legend;
+pub mod legend_export;
+pub mod letterbox;
+pub mod links;
+pub mod logging;
+pub mod memory_usage;
+pub mod min_zoom_curve;
+pub mod mode_adjustments;
+pub mod mode_parameters;
+pub mod named_session;
+pub mod outline;
+pub mod overview;
+pub mod parameter_animation;
+pub mod paths;
+pub mod percentile;
+pub mod pending_changes;
+pub mod persistence;
+pub mod points;
+pub mod preflight;
+pub mod profile;
+pub mod projection;
+pub mod property_listing;
+pub mod props;
+pub mod provenance;
+pub mod render_recovery;
+pub mod render_scale;
+pub mod replay_buffer;
+pub mod routes;
+pub mod run_summary;
+pub mod scale_bar;
+pub mod screenshot;
+pub mod self_check;
+pub mod session;
+pub mod session_picker;
+pub mod settings;
+pub mod settings_overlay;
+pub mod settings_watcher;
+pub mod spike_log;
+pub mod splash;
+pub mod split_view;
+pub mod starfield;
+pub mod state;
+pub mod terrain_clearance;
+pub mod theme;
+pub mod tile_loading;
+pub mod toasts;
+pub mod tour;
+pub mod window_placement;
+pub mod world_clock;
+
+use geo_index::CountryGeo;
+use legend::LegendEntry;
+use mode_parameters::ModeParameters;
+use props::{CountryProps, IncomeClass};
+
+use hsl::HSL;
+use piston_window::*;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+error_chain!{}
+
+/// Half the circumference of the earth at the equator, which is how far one unit of world X spans
+/// there.
+const KM_PER_WORLD_X: f64 = 20_037.5;
+
+/// Half of a meridian's circumference: how far one unit of world Y (pole to pole) spans.
+const KM_PER_WORLD_Y: f64 = 20_003.9;
+
+/// What the map shows about countries, which decides how each one is colored and what the legend
+/// lists. Written in lowercase in the settings file and on the command line.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapMode {
+    Terrain,
+    All,
+    Oecd,
+    Income,
+    Exceptional,
+    /// Colors come from the script in `modes.script`.
+    Scripted,
+    /// Countries where it is night are darkened.
+    Daylight,
+    /// Countries are colored by where their population ranks among the others'.
+    Percentile,
+}
+
+/// How each of `IncomeClass::ALL` is colored in `MapMode::Income`.
+const INCOME_COLORS: [[u8; 4]; 5] = [
+    [0, 255, 0, 100],
+    [50, 200, 0, 100],
+    [100, 150, 0, 100],
+    [150, 200, 0, 100],
+    [255, 0, 0, 100],
+];
+
+/// How each of `IncomeClass::ALL` is colored in `MapMode::Income` with
+/// `Palette::ColorblindSafe`: from light yellow to dark purple, so that the groups differ in
+/// lightness as well as in hue.
+const COLORBLIND_INCOME_COLORS: [[u8; 4]; 5] = [
+    [253, 231, 37, 100],
+    [94, 201, 98, 100],
+    [33, 145, 140, 100],
+    [59, 82, 139, 100],
+    [68, 1, 84, 100],
+];
+
+/// Which colors the map modes draw their categories in. Written in snake case in the settings
+/// file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Palette {
+    Standard,
+    /// Colors that can be told apart without telling red from green.
+    ColorblindSafe,
+}
+
+impl Palette {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Palette::Standard => "Standard",
+            Palette::ColorblindSafe => "Colorblind-safe",
+        }
+    }
+
+    pub fn toggled(&self) -> Palette {
+        match *self {
+            Palette::Standard => Palette::ColorblindSafe,
+            Palette::ColorblindSafe => Palette::Standard,
+        }
+    }
+}
+
+impl MapMode {
+    /// Every mode, in the order they are numbered unless `modes.order` says otherwise.
+    pub const ALL: [MapMode; 8] = [
+        MapMode::Terrain,
+        MapMode::All,
+        MapMode::Oecd,
+        MapMode::Income,
+        MapMode::Exceptional,
+        MapMode::Scripted,
+        MapMode::Daylight,
+        MapMode::Percentile,
+    ];
+
+    /// The name shown in the status line and the legend.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            MapMode::Terrain => "Terrain",
+            MapMode::All => "All countries",
+            MapMode::Oecd => "OECD",
+            MapMode::Income => "Income",
+            MapMode::Exceptional => "Exceptional",
+            MapMode::Scripted => "Scripted",
+            MapMode::Daylight => "Day and night",
+            MapMode::Percentile => "Population rank",
+        }
+    }
+
+    /// Whether this mode colors `country` at all, whatever its legend category.
+    pub fn should_show(&self, country: &CountryProps) -> bool {
+        match *self {
+            MapMode::Terrain => false,
+            MapMode::All | MapMode::Scripted | MapMode::Daylight => true,
+            // Countries whose income group is missing, or not one of Natural Earth's, are left
+            // uncolored.
+            MapMode::Income => self.category(country).is_some(),
+            MapMode::Oecd => country.income == Some(IncomeClass::HighOecd),
+            MapMode::Exceptional => country.admin == "United States of America",
+            // Countries without a population have no rank.
+            MapMode::Percentile => country.pop_est.is_some(),
+        }
+    }
+
+    /// The color of `country`, or `None` if this mode has none for it, as income mode has none
+    /// for countries without an income group. `tick` is the `animation_tick` of the frame being
+    /// drawn.
+    pub fn color(&self, country: &CountryProps, tick: Option<u64>) -> Option<[u8; 4]> {
+        self.tuned_color(country, tick, &ModeParameters::default())
+    }
+
+    /// `color`, with the mode's parameters as `parameters` has them instead of their defaults.
+    pub fn tuned_color(
+        &self,
+        country: &CountryProps,
+        tick: Option<u64>,
+        parameters: &ModeParameters,
+    ) -> Option<[u8; 4]> {
+        let parameter = |name| parameters.get(*self, name);
+        let opacity = || parameter("opacity").map(|opacity| opacity.round() as u8);
+        let shaded = |lightness: f32, opacity: u8| {
+            let (r, g, b) = HSL {
+                h: 360.0 * (f64::from(country.mapcolor13.unwrap_or(0)) / 13.0),
+                s: 1.0,
+                l: f64::from(lightness),
+            }.to_rgb();
+
+            [r, g, b, opacity]
+        };
+
+        let color = match *self {
+            MapMode::Terrain => shaded(0.3, 64),
+            MapMode::All | MapMode::Oecd => shaded(parameter("lightness")?, opacity()?),
+            MapMode::Income => {
+                let [r, g, b, _] = INCOME_COLORS[country.income?.index()];
+                [r, g, b, opacity()?]
+            }
+            MapMode::Exceptional => {
+                let secs = tick.unwrap_or(0) as f64;
+                let (r, g, b) = HSL {
+                    h: (secs * f64::from(parameter("hue_speed")?)) % 360.0,
+                    s: 1.0,
+                    l: 0.5,
+                }.to_rgb();
+
+                [r, g, b, opacity()?]
+            }
+            // `ColorScript` is asked instead, wherever there is one.
+            MapMode::Scripted => color_script::ERROR_COLOR,
+            // `daylight_color` is used instead, from the country's centroid.
+            MapMode::Daylight => NIGHT_COLOR,
+            // `percentile::color` is used instead, from the country's rank among the others.
+            MapMode::Percentile => percentile::color(0.0),
+        };
+
+        Some(color)
+    }
+
+    /// The color of `country`, or `None` if this mode does not show it, or `enabled` says its
+    /// legend category is hidden. `tick` is the `animation_tick` of the frame being drawn.
+    pub fn shown_color<F>(
+        &self,
+        country: &CountryProps,
+        enabled: F,
+        tick: Option<u64>,
+    ) -> Option<[u8; 4]>
+    where
+        F: Fn(usize) -> bool,
+    {
+        self.shown_tuned_color(country, enabled, tick, &ModeParameters::default())
+    }
+
+    /// `shown_color`, with the mode's parameters as `parameters` has them instead of their
+    /// defaults.
+    pub fn shown_tuned_color<F>(
+        &self,
+        country: &CountryProps,
+        enabled: F,
+        tick: Option<u64>,
+        parameters: &ModeParameters,
+    ) -> Option<[u8; 4]>
+    where
+        F: Fn(usize) -> bool,
+    {
+        if !self.should_show(country) {
+            return None;
+        }
+
+        if let Some(category) = self.category(country) {
+            if !enabled(category) {
+                return None;
+            }
+        }
+
+        self.tuned_color(country, tick, parameters)
+    }
+
+    /// `color`, the color of `country` in the standard palette, in `palette` instead. Its opacity
+    /// is kept.
+    pub fn recolored(&self, country: &CountryProps, color: [u8; 4], palette: Palette) -> [u8; 4] {
+        match (*self, palette, country.income) {
+            (MapMode::Income, Palette::ColorblindSafe, Some(income)) => {
+                let [r, g, b, _] = COLORBLIND_INCOME_COLORS[income.index()];
+                [r, g, b, color[3]]
+            }
+            _ => color,
+        }
+    }
+
+    /// For modes whose colors change over time, a number that changes whenever they do, as of
+    /// `now`. Read once per frame.
+    pub fn animation_tick(&self, now: SystemTime) -> Option<u64> {
+        match *self {
+            MapMode::Exceptional => {
+                let time = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+                Some(time.as_secs())
+            }
+            MapMode::Scripted => {
+                let time = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+                Some(time.as_millis() as u64 * color_script::TICKS_PER_SEC / 1000)
+            }
+            MapMode::Daylight => {
+                let time = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+                Some(time.as_secs() / DAYLIGHT_TICK_SECS)
+            }
+            _ => None,
+        }
+    }
+
+    /// The categories shown in the legend for this mode. Indices into this list are what
+    /// `category` returns.
+    pub fn legend_entries(&self) -> Vec<LegendEntry> {
+        match *self {
+            MapMode::Terrain | MapMode::Scripted => vec![],
+            MapMode::All => vec![LegendEntry {
+                label: "Countries".to_string(),
+                color: [128, 128, 128, 64],
+            }],
+            MapMode::Oecd => vec![LegendEntry {
+                label: "OECD members".to_string(),
+                color: [128, 128, 128, 64],
+            }],
+            MapMode::Income => IncomeClass::ALL
+                .iter()
+                .zip(&INCOME_COLORS)
+                .map(|(income, &color)| LegendEntry {
+                    label: income.label().to_string(),
+                    color,
+                })
+                .collect(),
+            MapMode::Exceptional => vec![LegendEntry {
+                label: "United States of America".to_string(),
+                color: [255, 0, 0, 100],
+            }],
+            MapMode::Daylight => vec![LegendEntry {
+                label: "Night".to_string(),
+                color: NIGHT_COLOR,
+            }],
+            MapMode::Percentile => percentile::legend_entries(),
+        }
+    }
+
+    /// `legend_entries`, in the colors of `palette`.
+    pub fn legend_entries_in(&self, palette: Palette) -> Vec<LegendEntry> {
+        let mut entries = self.legend_entries();
+        if let (MapMode::Income, Palette::ColorblindSafe) = (*self, palette) {
+            for (entry, &color) in entries.iter_mut().zip(&COLORBLIND_INCOME_COLORS) {
+                entry.color = color;
+            }
+        }
+
+        entries
+    }
+
+    /// What this mode shows about a country, for the info panel. Population rank mode's is
+    /// worked out from the other countries, with `percentile::describe`.
+    pub fn describe(&self, country: &CountryProps) -> Option<String> {
+        match *self {
+            MapMode::Terrain
+            | MapMode::All
+            | MapMode::Scripted
+            | MapMode::Daylight
+            | MapMode::Percentile => None,
+            MapMode::Oecd => Some(if self.should_show(country) {
+                "OECD member".to_string()
+            } else {
+                "Not an OECD member".to_string()
+            }),
+            MapMode::Income => country.income.map(|income| income.label().to_string()),
+            MapMode::Exceptional => Some(if self.should_show(country) {
+                "Exceptional".to_string()
+            } else {
+                "Unexceptional".to_string()
+            }),
+        }
+    }
+
+    /// Which of `legend_entries` a country belongs to. `None` if the mode has no legend, or if,
+    /// as in population rank mode, it depends on the other countries.
+    pub fn category(&self, country: &CountryProps) -> Option<usize> {
+        match *self {
+            MapMode::Terrain | MapMode::Scripted | MapMode::Percentile => None,
+            MapMode::All | MapMode::Oecd | MapMode::Exceptional | MapMode::Daylight => Some(0),
+            MapMode::Income => country.income.map(IncomeClass::index),
+        }
+    }
+}
+
+/// How many seconds of the day and night clock each of `MapMode::Daylight`'s animation ticks
+/// covers. The terminator moves a quarter degree in a minute.
+const DAYLIGHT_TICK_SECS: u64 = 60;
+
+/// What `MapMode::Daylight` draws countries in full night with, by default.
+const NIGHT_COLOR: [u8; 4] = [5, 10, 40, 180];
+
+/// The color of the country `geo` in `MapMode::Daylight` at animation `tick`: darker the further
+/// below the horizon the sun is from its centroid, or `None` where the sun is up.
+pub fn daylight_color(geo: &CountryGeo, tick: u64) -> Option<[u8; 4]> {
+    tuned_daylight_color(geo, tick, &ModeParameters::default())
+}
+
+/// `daylight_color`, with day and night mode's parameters as `parameters` has them instead of
+/// their defaults: how far below the horizon the sun is once night is at its darkest, which is
+/// the end of astronomical twilight by default, and how opaque full night is.
+pub fn tuned_daylight_color(
+    geo: &CountryGeo,
+    tick: u64,
+    parameters: &ModeParameters,
+) -> Option<[u8; 4]> {
+    let twilight = f64::from(parameters.get(MapMode::Daylight, "twilight")?);
+    let night_opacity = f64::from(parameters.get(MapMode::Daylight, "night_opacity")?);
+    let subsolar = astro::subsolar_point((tick * DAYLIGHT_TICK_SECS) as f64);
+    let (latitude, longitude) = geo.centroid;
+    let elevation = astro::elevation_below(subsolar, latitude, longitude);
+    if elevation >= 0.0 {
+        return None;
+    }
+
+    // Smoothed, so that twilight fades in and out instead of having visible edges.
+    let t = (-elevation / twilight).min(1.0);
+    let darkness = t * t * (3.0 - 2.0 * t);
+    let [r, g, b, _] = NIGHT_COLOR;
+    Some([r, g, b, (night_opacity * darkness).round() as u8])
+}
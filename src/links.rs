@@ -0,0 +1,158 @@
+use gaia_assetgen::Properties;
+use percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use serde_json::Value;
+
+use settings::LinkSettings;
+use Result;
+
+define_encode_set! {
+    /// What is percent-encoded in property values put into a link template: everything that
+    /// would end a path segment or a query parameter's value.
+    pub VALUE_ENCODE_SET = [PATH_SEGMENT_ENCODE_SET] | {'&', '=', '+', ';'}
+}
+
+/// The web page of the country with `properties`, named `name` in errors: its `links.property`,
+/// or else `links.template` filled in with its properties. Fails, with why, if it has neither, or
+/// if what it has is not a web address.
+pub fn url(properties: &Properties, name: &str, links: &LinkSettings) -> Result<String> {
+    let url = match string_property(properties, &links.property) {
+        Some(url) => url.trim().to_string(),
+        None => match links.template {
+            Some(ref template) => fill(template, properties)
+                .map_err(|e| format!("No link for {}: {}", name, e))?,
+            None => bail!(
+                "No link for {}: it has no {} property, and links.template is not set",
+                name,
+                links.property
+            ),
+        },
+    };
+
+    if !is_web_address(&url) {
+        bail!("Not opening {}'s link, which is not a web address: {:?}", name, url);
+    }
+    Ok(url)
+}
+
+/// `template` with each property named in braces, such as `{NAME}`, replaced by the value of it
+/// in `properties`, percent-encoded.
+fn fill(template: &str, properties: &Properties) -> Result<String> {
+    let mut filled = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => bail!("links.template has a {{ without a }}"),
+        };
+
+        let property = &rest[start + 1..end];
+        let value = match string_property(properties, property) {
+            Some(value) => value,
+            None => bail!("it has no {} property to put into links.template", property),
+        };
+        filled.extend(utf8_percent_encode(&value, VALUE_ENCODE_SET));
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+
+    Ok(filled)
+}
+
+/// The property `name` as text, or `None` if it is missing, empty, or marked missing with -99 as
+/// Natural Earth does.
+fn string_property(properties: &Properties, name: &str) -> Option<String> {
+    let value = match *properties.get(name)? {
+        Value::String(ref value) => value.clone(),
+        Value::Number(ref number) => number.to_string(),
+        _ => return None,
+    };
+
+    if value.trim().is_empty() || value == "-99" {
+        return None;
+    }
+    Some(value)
+}
+
+/// Whether `url` is an http or https address with a host, which is all that is opened, so that
+/// the dataset cannot have the browser run files or other programs.
+fn is_web_address(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    let rest = match ["http://", "https://"].iter().find(|scheme| lower.starts_with(*scheme)) {
+        Some(scheme) => &url[scheme.len()..],
+        None => return false,
+    };
+
+    let host = rest.split(|c| c == '/' || c == '?' || c == '#').next().unwrap_or("");
+    !host.is_empty() && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    fn country(json: &str) -> Properties {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn links(template: Option<&str>) -> LinkSettings {
+        LinkSettings {
+            template: template.map(str::to_string),
+            ..LinkSettings::default()
+        }
+    }
+
+    #[test]
+    fn template_values_are_percent_encoded() {
+        let template = links(Some("https://en.wikipedia.org/wiki/{NAME}?code={ISO}"));
+        let url = |json| url(&country(json), "it", &template).unwrap();
+
+        assert_eq!(
+            url(r#"{"NAME": "Côte d'Ivoire", "ISO": "CIV"}"#),
+            "https://en.wikipedia.org/wiki/C%C3%B4te%20d'Ivoire?code=CIV"
+        );
+        assert_eq!(
+            url(r#"{"NAME": "Bosnia & Herzegovina", "ISO": 70}"#),
+            "https://en.wikipedia.org/wiki/Bosnia%20%26%20Herzegovina?code=70"
+        );
+        // What would end the path segment or the query's value is encoded too.
+        assert_eq!(
+            url(r#"{"NAME": "a/b?c=d#e;f+g", "ISO": "100%"}"#),
+            "https://en.wikipedia.org/wiki/a%2Fb%3Fc%3Dd%23e%3Bf%2Bg?code=100%25"
+        );
+    }
+
+    #[test]
+    fn the_link_property_wins_over_the_template() {
+        let template = links(Some("https://example.com/{NAME}"));
+        let iceland = country(r#"{"NAME": "Iceland", "URL": " https://www.iceland.is "}"#);
+        assert_eq!(url(&iceland, "Iceland", &template).unwrap(), "https://www.iceland.is");
+
+        // A missing value, as Natural Earth marks them, falls back to the template.
+        let france = country(r#"{"NAME": "France", "URL": "-99"}"#);
+        assert_eq!(url(&france, "France", &template).unwrap(), "https://example.com/France");
+    }
+
+    #[test]
+    fn missing_and_unsafe_links_say_why() {
+        let error = |json: &str, settings: LinkSettings| {
+            url(&country(json), "Chad", &settings).unwrap_err().to_string()
+        };
+
+        assert!(error(r#"{"NAME": "Chad"}"#, links(None)).contains("has no URL property"));
+        let template = links(Some("https://example.com/{ADMIN}"));
+        assert!(error(r#"{"NAME": "Chad"}"#, template).contains("has no ADMIN property"));
+        let template = links(Some("https://example.com/{NAME"));
+        assert!(error(r#"{"NAME": "Chad"}"#, template).contains("without a }"));
+
+        let unsafe_urls = ["file:///etc/passwd", "javascript:alert(1)", "https:///a", "http://a b"];
+        for unsafe_url in &unsafe_urls {
+            let json = format!(r#"{{"URL": {:?}}}"#, unsafe_url);
+            assert!(error(&json, links(None)).contains("not a web address"), "{}", unsafe_url);
+        }
+        assert!(is_web_address("HTTPS://Example.com/path?q#frag"));
+    }
+}
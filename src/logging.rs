@@ -0,0 +1,92 @@
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use env_logger::filter::{Builder, Filter};
+use log::{self, Log, Metadata, Record};
+
+use {Result, ResultExt};
+
+/// What is logged when `RUST_LOG` is not set: the demo's own messages down to info, and only
+/// warnings from everything else.
+const DEFAULT_FILTER: &str = "warn,gaia_demo=info";
+
+/// The file given with `--log-file`, if any, which messages are also written to.
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Writes messages allowed by `RUST_LOG` to stderr, and to the log file if there is one.
+struct Logger {
+    filter: Filter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+
+        let line = format!(
+            "{} {:<5} {}: {}",
+            Local::now().format("%H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        eprintln!("{}", line);
+        if let Ok(mut file) = LOG_FILE.lock() {
+            if let Some(ref mut file) = *file {
+                // There is nowhere left to report a failure to write the log.
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = LOG_FILE.lock() {
+            if let Some(ref mut file) = *file {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Starts logging to stderr, filtered by `RUST_LOG` in the same syntax as `env_logger`.
+pub fn init() -> Result<()> {
+    let mut builder = Builder::new();
+    builder.parse(&env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_FILTER.to_string()));
+    let filter = builder.build();
+
+    log::set_max_level(filter.filter());
+    log::set_boxed_logger(Box::new(Logger { filter }))
+        .map_err(|e| format!("Could not start logging: {}", e).into())
+}
+
+/// Also writes messages to `path`, starting with a header saying which version of the demo is
+/// running where, for attaching to bug reports.
+pub fn tee_to(path: &Path) -> Result<()> {
+    let mut file = File::create(path)
+        .chain_err(|| format!("Could not create log file {}", path.display()))?;
+
+    let args: Vec<String> = env::args().collect();
+    writeln!(
+        file,
+        "{} {} on {} {}, started {}\nCommand line: {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH,
+        Local::now().to_rfc3339(),
+        args.join(" ")
+    ).chain_err(|| format!("Could not write log file {}", path.display()))?;
+
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
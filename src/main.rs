@@ -1,279 +1,1966 @@
 #[macro_use]
 extern crate error_chain;
+#[macro_use]
+extern crate log;
 
-extern crate cgmath;
 extern crate fps_counter;
 extern crate gaia;
-extern crate gaia_assetgen;
+extern crate gaia_demo;
 extern crate gfx;
-extern crate hsl;
 extern crate piston;
 extern crate piston_window;
 
-mod camera_controller;
-
-use camera_controller::CameraController;
+use gaia_demo::adaptive_quality::AdaptiveQuality;
+use gaia_demo::anaglyph::Anaglyph;
+use gaia_demo::animation_clock::AnimationClock;
+use gaia_demo::benchmark::{Benchmark, CameraPath};
+use gaia_demo::camera_controller::CameraController;
+use gaia_demo::chooser_benchmark::{self, Places};
+use gaia_demo::cli::Args;
+use gaia_demo::browser::SystemBrowser;
+use gaia_demo::clipboard::Clipboard;
+use gaia_demo::color_script::ColorScript;
+use gaia_demo::command_palette::CommandPalette;
+use gaia_demo::country_cycle::CountryCycle;
+use gaia_demo::cursor_capture::CursorCapture;
+use gaia_demo::cursor_point::CursorPoint;
+use gaia_demo::effects::EffectLayer;
+use gaia_demo::exec_script::{Command, ExecScript};
+use gaia_demo::features::Features;
+use gaia_demo::font_fallback::LabelFallbacks;
+use gaia_demo::frame_limiter::FrameLimiter;
+use gaia_demo::frame_recording::FrameRecording;
+use gaia_demo::frame_times::{FrameTimes, Phase};
+use gaia_demo::fullscreen::{Fullscreen, Monitors};
+use gaia_demo::geo_index::GeoIndex;
+use gaia_demo::graticule::Grid;
+use gaia_demo::help_overlay::HelpOverlay;
+use gaia_demo::hud::{Corner, HudLayout, HudScale, HudStyle, Icon};
+use gaia_demo::hud_budget::{self, HudBudget, Widget};
+use gaia_demo::input_recording::{InputRecorder, InputReplay};
+use gaia_demo::label_declutter::LabelDeclutter;
+use gaia_demo::label_policy::LabelsShown;
+use gaia_demo::label_text::LabelTexts;
+use gaia_demo::legend::Legend;
+use gaia_demo::legend_export::ShownLegend;
+use gaia_demo::letterbox;
+use gaia_demo::memory_usage::MemoryUsage;
+use gaia_demo::mode_adjustments::ModeAdjustments;
+use gaia_demo::overview::Overview;
+use gaia_demo::percentile::{self, Percentiles};
+use gaia_demo::points::PointLayer;
+use gaia_demo::preflight::Finding;
+use gaia_demo::profile::Profile;
+use gaia_demo::projection::Projection;
+use gaia_demo::provenance::{self, Provenance};
+use gaia_demo::render_recovery::RenderRecovery;
+use gaia_demo::render_scale::RenderScale;
+use gaia_demo::replay_buffer::{Progress, ReplayBuffer};
+use gaia_demo::run_summary::RunStats;
+use gaia_demo::screenshot::{Readback, Screenshots};
+use gaia_demo::session::Session;
+use gaia_demo::session_picker::SessionPicker;
+use gaia_demo::settings::{PathSettings, PointSettings, Settings};
+use gaia_demo::settings_overlay::SettingsOverlay;
+use gaia_demo::settings_watcher::SettingsWatcher;
+use gaia_demo::spike_log::SpikeLog;
+use gaia_demo::splash::{Shown, Splash};
+use gaia_demo::split_view::View;
+use gaia_demo::starfield::Starfield;
+use gaia_demo::state::{ColorCache, FrameInputs, State, WindowRequest};
+use gaia_demo::terrain_clearance::TerrainFilter;
+use gaia_demo::tile_loading::TileLoading;
+use gaia_demo::toasts::Toasts;
+use gaia_demo::world_clock::WorldClocks;
+use gaia_demo::{
+    anaglyph, benchmark, bindings, compass, crosshair, export, features, frame_times, gl_context,
+    graticule, hi_res, hud, info_panel, legend_export, logging, named_session, outline, overview,
+    paths, persistence, preflight, profile, property_listing, routes, scale_bar, screenshot,
+    self_check, session, spike_log, split_view, window_placement,
+};
+use gaia_demo::{daylight_color, MapMode, Result, ResultExt};
 
-use cgmath::{Angle, Matrix4, PerspectiveFov, Rad};
 use fps_counter::FPSCounter;
-use gaia_assetgen::Properties;
 use gfx::Device;
-use hsl::HSL;
 use piston::window::WindowSettings;
-use piston::input::Button;
-use piston::input::keyboard::Key;
 use piston_window::*;
 
-use std::time::{SystemTime, UNIX_EPOCH};
-
-error_chain!{}
+use std::cell::RefCell;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-enum MapMode {
-    Terrain,
-    All,
-    Oecd,
-    Income,
-    Exceptional,
+/// How a run ended, which decides the exit code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Finished,
+    /// `--check` found something the demo needs that does not work.
+    CheckFailed,
+    /// `--render-once` gave up waiting for tiles to load, and rendered anyway.
+    RenderedBeforeLoaded,
 }
 
-impl MapMode {
-    fn should_show(&self, properties: &Properties) -> bool {
-        match *self {
-            MapMode::Terrain => false,
-            MapMode::All | MapMode::Income => true,
-            MapMode::Oecd => properties["INCOME_GRP"].as_str().unwrap() == "1. High income: OECD",
-            MapMode::Exceptional => {
-                properties["ADMIN"].as_str().unwrap() == "United States of America"
-            }
-        }
+/// The exit code for `Outcome::RenderedBeforeLoaded`. Failures exit with 1.
+const EXIT_RENDERED_BEFORE_LOADED: i32 = 2;
+
+/// The longest to keep loading tiles for the start position behind the loading screen, so that a
+/// tile that never loads cannot hold up startup.
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn main() {
+    if let Err(ref e) = logging::init() {
+        eprintln!("error: {}", e);
     }
+    session::save_on_panic();
 
-    fn color(&self, properties: &Properties) -> [u8; 4] {
-        match *self {
-            MapMode::Terrain | MapMode::All | MapMode::Oecd => {
-                let color_num = properties["MAPCOLOR13"].as_f64().unwrap() as u8;
-                let (r, g, b) = HSL {
-                    h: 360.0 * (color_num as f64 / 13.0),
-                    s: 1.0,
-                    l: 0.3,
-                }.to_rgb();
+    match run() {
+        Ok(Outcome::Finished) => {}
+        Ok(Outcome::CheckFailed) => std::process::exit(1),
+        Ok(Outcome::RenderedBeforeLoaded) => {
+            log::logger().flush();
+            std::process::exit(EXIT_RENDERED_BEFORE_LOADED);
+        }
+        Err(ref e) => {
+            error!("{}", e);
 
-                [r, g, b, 64]
+            for e in e.iter().skip(1) {
+                error!("caused by: {}", e);
             }
-            MapMode::Income => match properties["INCOME_GRP"].as_str().unwrap() {
-                "1. High income: OECD" => [0, 255, 0, 100],
-                "2. High income: nonOECD" => [50, 200, 0, 100],
-                "3. Upper middle income" => [100, 150, 0, 100],
-                "4. Lower middle income" => [150, 200, 0, 100],
-                "5. Low income" => [255, 0, 0, 100],
-                _ => unreachable!(),
-            },
-            MapMode::Exceptional => {
-                let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
-                let secs = time.as_secs() as f64;
-                let (r, g, b) = HSL {
-                    h: (secs * 100.0) % 360.0,
-                    s: 1.0,
-                    l: 0.5,
-                }.to_rgb();
-
-                [r, g, b, 100]
+            if let Some(backtrace) = e.backtrace() {
+                error!("{:?}", backtrace);
             }
+
+            log::logger().flush();
+            std::process::exit(1);
         }
     }
 }
 
-struct State {
-    camera_controller: CameraController,
-    map_mode: MapMode,
-    labels_enabled: bool,
+/// The session to save for `state`, with where `window` is.
+fn session_of(state: &State, window: &PistonWindow, fullscreen: &Fullscreen) -> Session {
+    Session {
+        window: fullscreen.windowed_geometry(window),
+        ..state.session()
+    }
 }
 
-impl State {
-    pub fn event<E>(&mut self, e: &E)
-    where
-        E: GenericEvent,
-    {
-        self.camera_controller.event(e);
-
-        e.press(|button| match button {
-            Button::Keyboard(Key::D1) => {
-                self.map_mode = MapMode::Terrain;
+/// Handles a request from `state` that needs the window.
+fn handle_window_request(
+    request: WindowRequest,
+    state: &mut State,
+    window: &mut PistonWindow,
+    fullscreen: &mut Fullscreen,
+) {
+    match request {
+        WindowRequest::Screenshot
+        | WindowRequest::Export
+        | WindowRequest::HiResScreenshot
+        | WindowRequest::ToggleRecording
+        | WindowRequest::SaveReplay
+        | WindowRequest::ExportLegend
+        | WindowRequest::SwitchAssets => {}
+        WindowRequest::ToggleFullscreen => {
+            if let Err(ref e) = fullscreen.toggle(window) {
+                error!("{}", e);
+                state.toast(format!("Could not toggle fullscreen: {}", e));
             }
-            Button::Keyboard(Key::D2) => {
-                self.map_mode = MapMode::All;
+        }
+        WindowRequest::Escape => {
+            if fullscreen.is_fullscreen() && state.settings.window.esc_leaves_fullscreen {
+                fullscreen.leave(window);
+            } else {
+                window.set_should_close(true);
             }
-            Button::Keyboard(Key::D3) => {
-                self.map_mode = MapMode::Oecd;
+        }
+    }
+}
+
+fn run() -> Result<Outcome> {
+    let args = Args::from_env()?;
+    if let Some(ref path) = args.log_file {
+        logging::tee_to(path)?;
+    }
+
+    if args.check {
+        let report = self_check::run(&args);
+        print!("{}", report.text());
+        return Ok(if report.passed() {
+            Outcome::Finished
+        } else {
+            Outcome::CheckFailed
+        });
+    }
+
+    info!("Settings file: {}", args.config.display());
+    let file_settings = Settings::load(&args.config)?;
+
+    // Command-line options are applied to a copy, and only the file's settings are saved back,
+    // so that the options are not persisted.
+    let mut settings = file_settings.clone();
+    args.apply(&mut settings);
+    let settings = settings.validated();
+
+    if args.print_config {
+        print!("{}", settings.to_toml()?);
+        return Ok(Outcome::Finished);
+    }
+
+    let mut benchmark = if args.benchmark {
+        let path = match args.benchmark_path {
+            Some(ref path) => CameraPath::load(path)?,
+            None => CameraPath::built_in(),
+        };
+        Some(Benchmark::new(path))
+    } else {
+        None
+    };
+
+    debug!("Effective settings:\n{}", settings.to_toml()?);
+    let bindings = bindings::with_overrides(&settings.bindings, &settings.modes.shown())?;
+
+    // These are found before changing directories, since relative paths in the settings are
+    // relative to where the demo was started. The font is always in the default asset set.
+    let start_dir = env::current_dir().chain_err(|| "Could not read the working directory")?;
+    let default_assets_dir = paths::find_assets_dir(&settings.paths.assets_dir)?;
+    let font_root = default_assets_dir.parent().unwrap_or(&default_assets_dir);
+    let font = paths::find_font(&settings.hud.font, Some(font_root))?;
+    info!("Font: {}", font.display());
+    let font = paths::load_font(&font)?;
+    let fallback_font = settings.hud.font_fallback.as_ref().and_then(|fallback| {
+        let found = paths::find_fallback_font(fallback, Some(font_root));
+        match found.and_then(|path| paths::load_font(&path).map(|font| (path, font))) {
+            Ok((path, font)) => {
+                info!("Fallback font: {}", path.display());
+                Some(font)
             }
-            Button::Keyboard(Key::D4) => {
-                self.map_mode = MapMode::Income;
+            Err(ref e) => {
+                warn!("{}, so HUD text the font cannot draw is drawn with it anyway", e);
+                None
             }
-            Button::Keyboard(Key::D5) => {
-                self.map_mode = MapMode::Exceptional;
+        }
+    });
+
+    // The set in use, by name and by its directory as given in the settings.
+    let name = &settings.paths.asset_set;
+    let default_set = (PathSettings::DEFAULT_ASSET_SET.to_string(), default_assets_dir.clone());
+    let (mut asset_set, mut asset_set_dir) = match settings.paths.asset_set_dir(name) {
+        Some(dir) if name != PathSettings::DEFAULT_ASSET_SET => (name.clone(), dir.to_path_buf()),
+        Some(_) => default_set,
+        None => {
+            warn!("Unknown asset set {:?}, using the default set instead", name);
+            default_set
+        }
+    };
+    let mut assets_dir = paths::find_assets_dir(&asset_set_dir)?;
+    let mut assets_root = assets_dir.parent().unwrap_or(&assets_dir).to_path_buf();
+    info!("Assets directory: {} (asset set {:?})", assets_dir.display(), asset_set);
+    let mut provenance = read_provenance(&assets_dir);
+
+    // The renderer loads tiles from `assets` in the working directory.
+    env::set_current_dir(&assets_root)
+        .chain_err(|| format!("Could not change directory to {}", assets_root.display()))?;
+
+    if args.list_properties {
+        let data = features::load_data()?;
+        print!("{}", property_listing::list(&data, args.property.as_deref()));
+        return Ok(Outcome::Finished);
+    }
+    // Without a window, every legend category is shown.
+    if let Some(ref path) = args.export {
+        let (features, _, _) = load_features(&settings, args.strict)?;
+        let mode = settings.modes.default;
+        let tick = mode.animation_tick(args.clock.unwrap_or_else(SystemTime::now));
+        let script = match settings.modes.script {
+            Some(ref script) if mode == MapMode::Scripted => {
+                let mut script = ColorScript::new(start_dir.join(script));
+                script.load()?;
+                Some(script)
             }
-            Button::Keyboard(Key::D0) => {
-                self.labels_enabled = !self.labels_enabled;
+            Some(_) => None,
+            None if mode == MapMode::Scripted => bail!("Scripted mode needs modes.script"),
+            None => None,
+        };
+
+        let geo_index = GeoIndex::new(features.polygons());
+        let percentiles = Percentiles::of_population(features.countries());
+
+        let countries = features.filtered_countries();
+        let rows = export::shown_rows(countries, mode, |country| match script {
+            Some(ref script) => features.polygon_of(&country.id).and_then(|polygon| {
+                script.color(&country.id, features.properties(polygon), tick)
+            }),
+            None if mode == MapMode::Daylight => daylight_color(geo_index.get(&country.id)?, tick?),
+            None if mode == MapMode::Percentile => {
+                percentiles.get(&country.id).map(percentile::color)
             }
-            _ => {}
+            None => mode.shown_color(country, |_| true, tick),
         });
+        if let Some(e) = script.as_ref().and_then(ColorScript::take_error) {
+            warn!("Color script failed for {}", e);
+        }
+        export::write(path, &rows)?;
+        return Ok(Outcome::Finished);
     }
+    paths::check_assets(&assets_dir)?;
 
-    fn desired_level(&self, camera_height: f32) -> u8 {
-        if camera_height < 0.1 {
-            5
-        } else if camera_height < 0.2 {
-            4
-        } else if camera_height < 0.5 {
-            3
-        } else if camera_height < 0.7 {
-            2
-        } else {
-            1
-        }
+    let mut screenshots = Screenshots::new(args.screenshot_dir.clone())?;
+    let monitors = Monitors::list();
+    let mut fullscreen = Fullscreen::new(settings.window.monitor, &monitors)?;
+
+    // Escape is handled by `State`, so that it can leave fullscreen before quitting.
+    let size = [settings.window.width, settings.window.height];
+    let window_settings = WindowSettings::new(settings.window.title.clone(), size)
+        .exit_on_esc(false)
+        .vsync(settings.window.vsync)
+        .samples(settings.window.samples)
+        .srgb(settings.window.srgb);
+    let preferred: OpenGL = settings.window.gl_version.parse().map_err(|e| {
+        format!("Invalid window.gl_version {:?}: {}", settings.window.gl_version, e)
+    })?;
+    let gl_versions = gl_context::candidates(preferred, args.gl_version);
+    let (mut window, context_info) = gl_context::build_window(&window_settings, &gl_versions)
+        .chain_err(|| {
+            format!(
+                "Could not create a {}x{} window with OpenGL {}, {}x multisampling and sRGB {}",
+                size[0],
+                size[1],
+                settings.window.gl_version,
+                settings.window.samples,
+                if settings.window.srgb { "on" } else { "off" }
+            )
+        })?;
+    info!("Using {}", context_info);
+
+    // The session is neither restored nor saved when a run has to be repeatable.
+    let session_path = session::path_for(&args.config);
+    let repeatable = args.benchmark
+        || args.render_once.is_some()
+        || args.export_legend.is_some()
+        || args.record_input.is_some()
+        || args.replay_input.is_some()
+        || args.exec.is_some();
+    let saved_session = Session::load(&session_path).filter(|_| !repeatable);
+
+    let saved_window = saved_session.as_ref().and_then(|session| session.window);
+    window_placement::place(&mut window, &settings.window, saved_window, &monitors)?;
+    if settings.window.fullscreen {
+        fullscreen.enter(&mut window)?;
     }
 
-    fn get_mvp(&self, window: &PistonWindow) -> Matrix4<f32> {
-        let draw_size = window.window.draw_size();
-        let perspective = PerspectiveFov {
-            fovy: Rad::full_turn() / 8.0,
-            near: 0.001,
-            far: 100.0,
-            aspect: (draw_size.width as f32) / (draw_size.height as f32),
-        };
+    // The font is loaded first, since the loading screen needs it.
+    let label_font = font.clone();
+    let mut glyphs = Glyphs::from_font(
+        font,
+        window.factory.clone(),
+        piston_window::texture::TextureSettings::new(),
+    );
+    if let Some(fallback_font) = fallback_font {
+        hud::set_fallback_glyphs(Glyphs::from_font(
+            fallback_font,
+            window.factory.clone(),
+            piston_window::texture::TextureSettings::new(),
+        ));
+    }
 
-        Matrix4::from(perspective) * self.camera_controller.view_matrix()
+    // Errors that end startup are shown on it too, unless nobody is expected to be watching.
+    let splash = Splash::new(settings.sky.clear_color(), !repeatable);
+
+    if !splash.show("Loading countries", &mut window, &mut glyphs) {
+        return Ok(Outcome::Finished);
     }
+    let loaded = load_features(&settings, args.strict);
+    let (features, findings, places) = splash.failed(loaded, &mut window, &mut glyphs)?;
 
-    fn polygon_color_chooser(&self, properties: &Properties) -> Option<[u8; 4]> {
-        if self.map_mode.should_show(properties) {
-            Some(self.map_mode.color(properties))
-        } else {
-            None
+    if !splash.show("Loading assets", &mut window, &mut glyphs) {
+        return Ok(Outcome::Finished);
+    }
+    let renderer = gaia::Renderer::new(window.factory.clone()).chain_err(|| {
+        format!("Could not create renderer from the assets in {}", assets_dir.display())
+    });
+    let mut gaia_renderer = splash.failed(renderer, &mut window, &mut glyphs)?;
+
+    let sessions_dir = named_session::dir_for(&args.config);
+    let mut state = State {
+        camera_controller: CameraController::new(settings.camera),
+        terrain_filter: TerrainFilter::default(),
+        terrain_region: None,
+        map_mode: settings.modes.default,
+        labels_enabled: settings.labels.enabled,
+        label_policy: settings.modes.labels.of(settings.modes.default),
+        mode_tweaks: ModeAdjustments::default(),
+        graticule_enabled: settings.hud.widgets.graticule,
+        crosshair_enabled: settings.hud.widgets.crosshair,
+        overview_enabled: settings.hud.widgets.overview,
+        stereo: false,
+        frame_times: FrameTimes::new(),
+        frame_times_visible: settings.hud.widgets.frame_times,
+        run_stats: RunStats::new(),
+        spike_log: SpikeLog::new(spike_log::path_for(&args.config)),
+        memory_usage: MemoryUsage::new(),
+        needs_redraw: true,
+        drawn_solar_time: None,
+        drawn_clocks: vec![],
+        tile_loading: TileLoading::new(),
+        cursor: [0.0, 0.0],
+        cursor_point: CursorPoint::default(),
+        overview_rect: None,
+        compass_center: None,
+        more_info_rect: None,
+        view_size: [1.0, 1.0],
+        projection: Projection::new(letterbox::draw_size(
+            args.lock_aspect,
+            window.window.draw_size(),
+        )),
+        aspect_lock: args.lock_aspect,
+        aspect_locked: args.lock_aspect.is_some(),
+        pixel_density: HudScale::density(Some(window.window.window.hidpi_factor()), 0, 0),
+        hidpi_factor: Some(window.window.window.hidpi_factor()),
+        hud_scale_override: args.hud_scale,
+        formatting_override: Some(settings.formatting).filter(|_| {
+            settings.formatting != file_settings.formatting
+        }),
+        quality_override: Some(settings.quality.clone()).filter(|_| {
+            settings.quality != file_settings.quality
+        }),
+        profile_override: Some(Profile::HighContrast).filter(|_| args.high_contrast),
+        profiled_settings: profile::layered(&file_settings, settings.profiles.active),
+        adaptive_quality: AdaptiveQuality::new(settings.quality.clone()),
+        hud_budget: HudBudget::new(settings.quality.hud_budget_ms),
+        geo_index: GeoIndex::new(features.polygons()),
+        label_texts: LabelTexts::new(features.place_names(), settings.labels.wrap),
+        label_font: None,
+        label_fallbacks: LabelFallbacks::default(),
+        features,
+        selected_polygon: None,
+        country_cycle: CountryCycle::new(),
+        settings: file_settings,
+        settings_watcher: SettingsWatcher::new(args.config.clone()),
+        settings_path: args.config,
+        bindings,
+        settings_overlay: SettingsOverlay::new(settings.modes.default),
+        help_overlay: HelpOverlay::new(),
+        session_picker: SessionPicker::new(),
+        command_palette: CommandPalette::new(),
+        sessions_dir,
+        legend: Legend::new(),
+        split: None,
+        color_snapshot: None,
+        snapshot_diff: false,
+        effects: EffectLayer::new(),
+        selection_pulse: None,
+        color_cache: RefCell::new(ColorCache::default()),
+        label_declutter: RefCell::new(LabelDeclutter::default()),
+        percentiles: RefCell::new(None),
+        filtered_percentiles: RefCell::new(None),
+        clock: args.clock,
+        parameter_index: 0,
+        break_editor: None,
+        parameter_animation: None,
+        world_clocks: WorldClocks::default(),
+        animation_clock: AnimationClock::new(),
+        focused: true,
+        frozen_by_focus: false,
+        daylight_anchor: (SystemTime::now(), Duration::from_secs(0)),
+        shift_held: false,
+        ctrl_held: false,
+        window_requests: Vec::new(),
+        toasts: Toasts::new(),
+        clipboard: Box::new(Clipboard::new()),
+        browser: Box::new(SystemBrowser),
+        points: args.points.clone().map(PointLayer::new),
+        routes: Vec::new(),
+        route_tool: false,
+        route_start: None,
+        selected_route: None,
+        journey: None,
+        tour: None,
+        following: settings.routes.follow,
+        color_script: settings
+            .modes
+            .script
+            .as_ref()
+            .map(|path| ColorScript::new(start_dir.join(path))),
+    };
+    state.set_label_font(label_font);
+    state.check_color_script();
+    if state.map_mode == MapMode::Scripted && state.color_script.is_none() {
+        warn!("Starting in terrain mode, since scripted mode needs modes.script");
+        state.map_mode = MapMode::Terrain;
+    }
+    state.adjust_to_mode();
+
+    if settings.hud.widgets.legend {
+        state.legend.toggle();
+    }
+
+    state.toast_findings(&findings);
+    chooser_benchmark::run(&state, &places).log();
+
+    let has_start = if let Some(ref name) = args.session {
+        let loaded = state.try_load_named_session(name, false);
+        splash.failed(loaded, &mut window, &mut glyphs)?;
+        true
+    } else if let Some(start) = settings.camera.start {
+        state
+            .camera_controller
+            .move_to(start.latitude, start.longitude, start.height);
+        true
+    } else if let Some(session) = saved_session {
+        state
+            .camera_controller
+            .move_to(session.latitude, session.longitude, session.height);
+        state.camera_controller.set_heading(session.heading);
+        state.routes = session.routes;
+        state.selected_route = state.routes.len().checked_sub(1);
+        state.set_world_clocks(WorldClocks::new(session.clocks));
+        true
+    } else {
+        false
+    };
+
+    // The map is drawn behind the loading screen until the tiles for where it starts have
+    // probably loaded, so that it does not start out blurry. Repeatable runs skip this, since how
+    // long it takes varies.
+    // Made before the warm-up, which recovers from frames that fail to draw as the main loop does.
+    let mut render_recovery = RenderRecovery::new();
+    if has_start && !repeatable {
+        let warm_up_started = Instant::now();
+        let mut rendered = None;
+        let shown = loop {
+            let height = state.camera_controller.camera_height();
+            let level = state.desired_level(height);
+            state
+                .tile_loading
+                .update(level, state.camera_controller.look_at(), height);
+            if state.tile_loading.loading_level().is_none() {
+                break Shown::Drawn;
+            }
+            if warm_up_started.elapsed() >= WARM_UP_TIMEOUT {
+                warn!(
+                    "Tiles were still loading after {:.1}s, showing the map anyway",
+                    WARM_UP_TIMEOUT.as_secs_f64()
+                );
+                break Shown::Drawn;
+            }
+
+            let background = state.settings.sky.clear_color();
+            let draw_map = render_recovery.should_draw();
+            let shown = splash.show_over("Loading the map", &mut window, &mut glyphs, |window, e| {
+                if e.resize_args().is_some() {
+                    state.resize_projection(window.window.draw_size());
+                }
+
+                window.draw_3d(e, |window| {
+                    window.encoder.clear(&window.output_color, background);
+                    window.encoder.clear_depth(&window.output_stencil, 1.0);
+                    window.encoder.clear_stencil(&window.output_stencil, 0);
+                    if !draw_map {
+                        return;
+                    }
+
+                    rendered = Some(state.render_map(
+                        &mut gaia_renderer,
+                        &mut window.encoder,
+                        window.output_color.clone(),
+                        window.output_stencil.clone(),
+                        state.mvp_in(View::Whole),
+                        state.frame_inputs(View::Whole),
+                    ));
+                });
+            });
+            if let Some(rendered) = rendered.take() {
+                render_recovery.record(rendered.chain_err(|| {
+                    let tiles = assets_dir.join("generated").join("tiles");
+                    format!("Could not render the map from the tiles in {}", tiles.display())
+                }))?;
+            }
+
+            if shown != Shown::Drawn {
+                break shown;
+            }
+        };
+
+        let secs = warm_up_started.elapsed().as_secs_f64();
+        match shown {
+            Shown::Drawn => info!("Loaded the start tiles in {:.1}s", secs),
+            Shown::Skipped => info!("Skipped loading the start tiles after {:.1}s", secs),
+            Shown::Closed => return Ok(Outcome::Finished),
         }
     }
 
-    fn label_style_chooser<'a>(&self, properties: &'a Properties) -> Option<gaia::LabelStyle<'a>> {
-        if !self.labels_enabled {
-            return None;
+    let mut fps_counter = FPSCounter::new();
+    let mut fps = 0;
+    // Frames are only drawn when something changed, so the frame rate means little for a while
+    // after one was skipped.
+    let mut last_skipped: Option<Instant> = None;
+    let always_draw =
+        benchmark.is_some() || args.render_once.is_some() || args.export_legend.is_some();
+    let mut frame_drawn = false;
+
+    let mut cursor_capture = CursorCapture::new();
+    let mut screenshot_pending = false;
+    let mut hi_res_pending = false;
+    // Started from the command line only now, so that the tile warm-up is not recorded. It is
+    // stopped when dropped, however the loop ends.
+    let mut recording = match args.record_frames {
+        Some(ref dir) => Some(FrameRecording::start(dir.clone())?),
+        None => None,
+    };
+    let mut replay_buffer = if settings.diagnostics.replay_buffer {
+        Some(ReplayBuffer::new(settings.diagnostics))
+    } else {
+        None
+    };
+
+    let mut frame_limiter = FrameLimiter::new(settings.window.fps_limit, settings.window.idle_fps);
+    // Where the map is drawn when the render scale is below 1.
+    let mut offscreen = RenderScale::new();
+    let mut overview = Overview::new();
+    let mut anaglyph = Anaglyph::new();
+    let mut starfield = Starfield::new(0);
+    if benchmark.is_some() {
+        // Benchmark mode advances the event loop's clock by a fixed step per frame, and ignores
+        // input, so that every run draws the same frames.
+        window.set_bench_mode(true);
+        window.set_max_fps(benchmark::FRAMES_PER_SECOND);
+    } else {
+        window.set_max_fps(frame_limiter.event_loop_fps());
+    }
+    // Buffers are only swapped after frames that were drawn, so that skipped ones keep showing
+    // the last one.
+    window.set_swap_buffers(false);
+
+    let mut replay = match args.replay_input {
+        Some(ref path) => Some(InputReplay::load(path, args.replay_speed, &mut window)?),
+        None => None,
+    };
+    let mut recorder = match args.record_input {
+        Some(ref path) => Some(InputRecorder::create(path, window.size())?),
+        None => None,
+    };
+
+    // Dropped once every command has run.
+    let mut exec = args.exec.clone();
+    let render_started = Instant::now();
+    loop {
+        let e = match replay {
+            Some(ref mut replay) => replay.next(&mut window),
+            None => window.next(),
+        };
+        let e = match e {
+            Some(e) => e,
+            None => break,
+        };
+
+        if let Some(Err(err)) = recorder.as_mut().map(|recorder| recorder.record(&e)) {
+            error!("{}", err);
+            state.toast(format!("Stopped recording input: {}", err));
+            recorder = None;
         }
 
-        let height = self.camera_controller.camera_height();
-        let min_zoom = properties["min_zoom"].as_f64().unwrap();
+        if let (Some(benchmark), Some(_)) = (benchmark.as_mut(), e.render_args()) {
+            match benchmark.next_view(state.frame_times.current_phases()) {
+                Some((_, view)) => {
+                    state
+                        .camera_controller
+                        .move_to(view.latitude, view.longitude, view.height);
+                    state.camera_controller.set_heading(view.heading);
 
-        if height as f64 * min_zoom > 1.5 {
-            return None;
+                    let height = state.camera_controller.camera_height();
+                    benchmark.set_level(state.desired_level(height));
+                }
+                None => {
+                    let context = context_info.to_string();
+                    let report = benchmark.report(args.benchmark_format, &context)?;
+                    write_stats(&report, args.quiet, args.stats_out.as_deref())?;
+                    return Ok(Outcome::Finished);
+                }
+            }
         }
 
-        let text = properties["NAME"].as_str().unwrap();
-        let is_capital = properties["ADM0CAP"].as_f64().unwrap() == 1.0;
-        let (scale, text_color) = if is_capital {
-            (30.0, [1.0, 1.0, 0.0, 1.0])
-        } else {
-            (20.0, [1.0, 1.0, 1.0, 1.0])
-        };
+        let is_input = matches!(e, Event::Input(_));
+        if is_input {
+            frame_limiter.input();
+            state.needs_redraw = true;
+        }
+        // A replay is paced by its recording instead.
+        if e.render_args().is_some() && replay.is_none() {
+            frame_limiter.wait();
+        }
 
-        Some(gaia::LabelStyle {
-            text,
-            scale,
-            text_color,
-            border_color: [0.0, 0.0, 0.0, 1.0],
-            border_width: 1.0,
-        })
-    }
-}
+        // Changing the frame rate resets the event loop, so it is only done where that cannot
+        // drop a frame: after one has been shown, or on input.
+        if (is_input || e.after_render_args().is_some()) && replay.is_none() {
+            // Focus events reach the state below, so the focus is taken from them here.
+            let focused = e.focus_args().unwrap_or(state.focused);
+            let suspends = state.settings.window.unfocused.suspends();
+            frame_limiter.set_throttled(!focused && suspends && exec.is_none());
+            if let Some(fps) = frame_limiter.update(state.is_animating() || exec.is_some()) {
+                window.set_max_fps(fps);
+            }
+        }
 
-fn main() {
-    if let Err(ref e) = run() {
-        println!("error: {}", e);
+        if e.resize_args().is_some() {
+            state.resize_projection(window.window.draw_size());
+        }
 
-        for e in e.iter().skip(1) {
-            println!("caused by: {}", e);
+        let started = Instant::now();
+        if e.render_args().is_some() {
+            // Moving the window onto another monitor can change it.
+            state.hidpi_factor = Some(window.window.window.hidpi_factor());
         }
+        state.spike_log.event(&e);
+        state.event(&e);
+        if e.render_args().is_some() {
+            state.check_for_spike();
+            state.memory_usage.sample();
+        }
+        cursor_capture.event(&e);
+        cursor_capture.update(
+            &mut window,
+            state.camera_controller.is_rotating()
+                && state.settings.camera.capture_cursor
+                && replay.is_none(),
+        );
+        let phase = if e.update_args().is_some() || e.render_args().is_some() {
+            Phase::Update
+        } else {
+            Phase::Events
+        };
+        state.frame_times.time(phase, started);
 
-        if let Some(backtrace) = e.backtrace() {
-            println!("{:?}", backtrace);
+        if e.after_render_args().is_some() {
+            if frame_drawn {
+                let started = Instant::now();
+                Window::swap_buffers(&mut window);
+                state.frame_times.time(Phase::Swap, started);
+            }
+            state.check_settings_file();
+            state.check_points_file();
+            state.check_color_script();
+
+            if !repeatable {
+                session_of(&state, &window, &fullscreen).remember(&session_path);
+            }
         }
 
-        std::process::exit(1);
-    }
-}
+        for request in std::mem::take(&mut state.window_requests) {
+            if request == WindowRequest::Screenshot {
+                screenshot_pending = true;
+            }
+            if request == WindowRequest::Export {
+                export_countries(&mut state, &args.screenshot_dir);
+            }
+            if request == WindowRequest::HiResScreenshot {
+                hi_res_pending = true;
+            }
+            if request == WindowRequest::SaveReplay {
+                let saved = match replay_buffer {
+                    Some(ref mut buffer) => {
+                        let secs = buffer.secs();
+                        buffer.save(&args.screenshot_dir).map(|_| secs)
+                    }
+                    None => Err("The replay buffer is off. Turn on diagnostics.replay_buffer, \
+                                 or start with --replay-buffer on"
+                        .into()),
+                };
+                match saved {
+                    Ok(secs) => state.toasts.progress(format!("Saving the last {:.0}s...", secs)),
+                    Err(ref e) => {
+                        warn!("Could not save the replay: {}", e);
+                        state.toast(format!("Could not save the replay: {}", e));
+                    }
+                }
+            }
+            if request == WindowRequest::ExportLegend {
+                let scale = args.screenshot_scale.unwrap_or(hi_res::DEFAULT_SCALE);
+                let saved = export_legend(&mut window, &mut glyphs, &state, scale)
+                    .and_then(|image| screenshots.save_image(image, "-legend"));
+                match saved {
+                    Ok(path) => info!("Writing the legend to {}", path.display()),
+                    Err(ref e) => {
+                        error!("{}", e);
+                        state.toast(format!("Could not export the legend: {}", e));
+                    }
+                }
+            }
+            if request == WindowRequest::SwitchAssets {
+                let name = state.settings.paths.asset_set.clone();
+                let dir = if name == PathSettings::DEFAULT_ASSET_SET {
+                    Some(default_assets_dir.clone())
+                } else {
+                    state.settings.paths.asset_set_dir(&name).map(Path::to_path_buf)
+                };
 
-fn run() -> Result<()> {
-    let mut window: PistonWindow = WindowSettings::new("Gaia", [960, 520])
-        .exit_on_esc(true)
-        .opengl(OpenGL::V3_2)
-        .build()
-        .map_err(Error::from)?;
+                let loaded = match dir {
+                    Some(ref dir) if name == asset_set && *dir == asset_set_dir => continue,
+                    Some(dir) => {
+                        let step = format!("Loading asset set {}", name);
+                        // The main loop ends by itself once the window is closed.
+                        if !splash.show(&step, &mut window, &mut glyphs) {
+                            continue;
+                        }
+                        let switch = switch_asset_set(
+                            window.factory.clone(),
+                            &settings,
+                            &dir,
+                            &start_dir,
+                            &assets_root,
+                            gaia_renderer,
+                        )?;
+                        gaia_renderer = switch.renderer;
+                        switch.loaded.map(|loaded| (dir, loaded))
+                    }
+                    None => Err(format!("There is no asset set {:?}", name).into()),
+                };
 
-    let mut state = State {
-        camera_controller: CameraController::new(),
-        map_mode: MapMode::Terrain,
-        labels_enabled: false,
-    };
+                match loaded {
+                    Ok((dir, loaded)) => {
+                        let shown = loaded.assets_dir.display().to_string();
+                        info!("Assets directory: {} (asset set {:?})", shown, name);
+                        asset_set_dir = dir;
+                        assets_dir = loaded.assets_dir;
+                        assets_root = assets_dir.parent().unwrap_or(&assets_dir).to_path_buf();
+                        provenance = read_provenance(&assets_dir);
+                        asset_set = name.clone();
+                        state.replace_features(loaded.features);
 
-    let mut gaia_renderer =
-        gaia::Renderer::new(window.factory.clone()).chain_err(|| "Could not create renderer")?;
+                        state.toast(format!("Switched to asset set {}", name));
+                        state.toast_findings(&loaded.findings);
+                    }
+                    Err(ref e) => {
+                        let message =
+                            e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+                        error!("Could not load asset set {:?}: {}", name, message);
+                        state.toast(format!(
+                            "Could not load asset set {}, keeping {}: {}",
+                            name, asset_set, message
+                        ));
 
-    let mut fps_counter = FPSCounter::new();
-    let mut fps = 0;
+                        state.settings.paths.asset_set = asset_set.clone();
+                        state.apply_settings();
+                    }
+                }
+            }
+            if request == WindowRequest::ToggleRecording {
+                match recording.take() {
+                    Some(recorded) => stop_recording(recorded, &mut state, &mut window),
+                    None => {
+                        let dir = FrameRecording::dir_in(&args.screenshot_dir);
+                        match FrameRecording::start(dir) {
+                            Ok(started) => {
+                                state.toast(format!("Recording to {}", started.dir().display()));
+                                recording = Some(started);
+                            }
+                            Err(ref e) => {
+                                error!("{}", e);
+                                state.toast(format!("Could not start recording: {}", e));
+                            }
+                        }
+                    }
+                }
+            }
 
-    let mut glyphs = Glyphs::new(
-        "assets/fonts/FiraSans-Regular.ttf",
-        window.factory.clone(),
-        piston_window::texture::TextureSettings::new(),
-    ).map_err(|_err| Error::from("glyph error"))?;
+            handle_window_request(request, &mut state, &mut window, &mut fullscreen);
+        }
 
-    while let Some(e) = window.next() {
-        state.event(&e);
+        let progress = replay_buffer.as_mut().map_or_else(Vec::new, |buffer| buffer.progress());
+        for progress in progress {
+            match progress {
+                Progress::Encoded(done, total) => {
+                    state
+                        .toasts
+                        .progress(format!("Encoding the replay: {}/{} frames", done, total));
+                }
+                Progress::Finished(Ok(path)) => {
+                    state.toasts.end_progress();
+                    info!("Saved the replay to {}", path.display());
+                    state.toast(format!("Saved replay {}", path.display()));
+                }
+                Progress::Finished(Err(e)) => {
+                    state.toasts.end_progress();
+                    error!("{}", e);
+                    state.toast(format!("Could not save the replay: {}", e));
+                }
+            }
+        }
 
+        for result in screenshots.finished() {
+            match result {
+                Ok(path) => state.toast(format!("Saved screenshot {}", path.display())),
+                Err(e) => {
+                    error!("{}", e);
+                    state.toast(format!("Screenshot failed: {}", e));
+                }
+            }
+        }
+
+        if let Some(args) = e.render_args() {
+            // A minimized window has nothing to draw into, and nothing is drawn while it is.
+            let minimized = args.draw_width == 0 || args.draw_height == 0;
+            let needs_redraw = state.take_needs_redraw();
+            let paused = state.is_paused_unfocused() && exec.is_none() && recording.is_none();
+            frame_drawn = !minimized
+                && !paused
+                && (needs_redraw
+                    || always_draw
+                    || screenshot_pending
+                    || hi_res_pending
+                    || recording.is_some()
+                    || exec.is_some());
+            if !frame_drawn {
+                last_skipped = Some(Instant::now());
+                state.run_stats.frame_skipped();
+            }
+        }
+        // Nothing below applies to frames that are not drawn.
+        if !frame_drawn {
+            continue;
+        }
+
+        let render_scale = state.quality().render_scale;
+        if e.render_args().is_some() {
+            // The aspect ratio may have been locked or unlocked since the last frame.
+            state.resize_projection(window.window.draw_size());
+
+            // In red and cyan 3D, the eyes' targets are drawn to instead.
+            let views: &[View] = if state.stereo { &[] } else { state.views() };
+            let letterboxed = state.locked_aspect().is_some();
+            offscreen
+                .update(
+                    &mut window.factory,
+                    state.projection.draw_size(),
+                    render_scale,
+                    views,
+                    letterboxed,
+                )
+                .chain_err(|| "Could not draw the map at a lower resolution")?;
+            anaglyph
+                .update(
+                    &mut window.factory,
+                    state.projection.draw_size(),
+                    render_scale,
+                    state.stereo,
+                )
+                .chain_err(|| "Could not draw the map for red and cyan 3D")?;
+        }
+        let scaled_targets = offscreen.targets();
+        let eyes = anaglyph.eyes();
+
+        let hud_visible = state.settings.hud.visible
+            && !(screenshot_pending && state.settings.hud.hide_in_screenshots);
+        let overview_target = if e.render_args().is_some() && state.overview_shown(hud_visible) {
+            let target = overview
+                .target(&mut window.factory, state.hud_scale())
+                .chain_err(|| "Could not draw the overview")?;
+            Some(target)
+        } else {
+            None
+        };
+
+        let draw_map = e.render_args().is_some() && render_recovery.should_draw();
+        let background = state.settings.sky.clear_color();
+        let mut rendered = Ok(());
+        let started = Instant::now();
         window.draw_3d(&e, |window| {
-            window
-                .encoder
-                .clear(&window.output_color, [0.3, 0.3, 0.3, 1.0]);
+            fps = fps_counter.tick();
+            window.encoder.clear(&window.output_color, background);
             window.encoder.clear_depth(&window.output_stencil, 1.0);
             window.encoder.clear_stencil(&window.output_stencil, 0);
 
-            let mvp = state.get_mvp(&window);
-            gaia_renderer
-                .render(
+            // While recovering from a frame that failed, the HUD is drawn over a blank map.
+            if !draw_map {
+                return;
+            }
+
+            if let Some(eyes) = eyes {
+                let offsets = anaglyph::eye_offsets(
+                    state.settings.stereo,
+                    state.camera_controller.camera_height(),
+                );
+                for (eye, &offset) in eyes.iter().zip(&offsets) {
+                    window.encoder.clear(&eye.color, background);
+                    window.encoder.clear_depth(&eye.depth, 1.0);
+                    window.encoder.clear_stencil(&eye.depth, 0);
+
+                    rendered = state.render_map(
+                        &mut gaia_renderer,
+                        &mut window.encoder,
+                        eye.color.clone(),
+                        eye.depth.clone(),
+                        state.eye_mvp(offset),
+                        state.frame_inputs(View::Whole),
+                    );
+                    if rendered.is_err() {
+                        break;
+                    }
+                }
+            } else if scaled_targets.is_empty() {
+                rendered = state.render_map(
+                    &mut gaia_renderer,
                     &mut window.encoder,
                     window.output_color.clone(),
                     window.output_stencil.clone(),
-                    mvp,
-                    state.camera_controller.look_at(),
-                    state.camera_controller.camera_height(),
-                    &|properies| state.polygon_color_chooser(properies),
-                    &|properies| state.label_style_chooser(properies),
-                    &|camera_position| state.desired_level(camera_position),
-                )
-                .unwrap();
+                    state.mvp_in(View::Whole),
+                    state.frame_inputs(View::Whole),
+                );
+            }
+            for &(view, ref scaled) in scaled_targets {
+                window.encoder.clear(&scaled.color, background);
+                window.encoder.clear_depth(&scaled.depth, 1.0);
+                window.encoder.clear_stencil(&scaled.depth, 0);
 
-            window.device.cleanup();
+                rendered = state.render_map(
+                    &mut gaia_renderer,
+                    &mut window.encoder,
+                    scaled.color.clone(),
+                    scaled.depth.clone(),
+                    state.mvp_in(view),
+                    state.frame_inputs(view),
+                );
+                if rendered.is_err() {
+                    break;
+                }
+            }
+            if let (Some(inset), true) = (overview_target, rendered.is_ok()) {
+                window.encoder.clear(&inset.color, background);
+                window.encoder.clear_depth(&inset.depth, 1.0);
+                window.encoder.clear_stencil(&inset.depth, 0);
 
-            fps = fps_counter.tick();
+                rendered = state.render_overview(
+                    &mut gaia_renderer,
+                    &mut window.encoder,
+                    inset.color.clone(),
+                    inset.depth.clone(),
+                );
+            }
         });
+        state.frame_times.time(Phase::Render, started);
 
+        let km_per_pixel = state.km_per_pixel(&window);
+        let sky = state.settings.sky;
+        if sky.stars {
+            starfield.resize(sky.star_count);
+        }
+        let hud_levels = state.hud_budget.levels();
+        let mut hud_costs = hud_budget::Costs::default();
+        let started = Instant::now();
         window.draw_2d(&e, |context, graphics| {
-            piston_window::rectangle(
-                [1.0, 1.0, 1.0, 1.0],
-                [0.0, 0.0, 200.0, 15.0],
-                context.transform,
-                graphics,
-            );
-
-            let camera_height = state.camera_controller.camera_height();
-            text::Text::new_color([0.0, 0.0, 0.0, 1.0], 10)
-                .draw(
-                    &format!("FPS: {} - Camera height: {}", fps, camera_height),
+            let hud_scale = state.hud_scale();
+            let style = HudStyle {
+                scale: hud_scale,
+                theme: state.profiled_settings.hud.theme(),
+            };
+
+            let area = state.map_area();
+            if let Some(eyes) = eyes {
+                anaglyph::draw(eyes, area, context, graphics);
+            }
+            for &(view, ref scaled) in scaled_targets {
+                scaled.draw(view, area, context, graphics);
+            }
+            if state.locked_aspect().is_some() {
+                letterbox::draw_bars(area, style.theme.opaque_background(), context, graphics);
+            }
+
+            let widgets = state.settings.hud.widgets;
+            let insets = state.safe_area(context.get_view_size());
+            let mut layout = HudLayout::new(context, hud_visible, style, insets);
+
+            if let Some(warning) = render_recovery.warning() {
+                // Shown even with the HUD hidden, since it says why the map is missing.
+                let mut shown = HudLayout::new(context, true, style, insets);
+                let banner = if hud_visible { &mut layout } else { &mut shown };
+                banner.text_panel(Corner::TopLeft, &[warning], &mut glyphs, context, graphics);
+            }
+
+            // What is drawn over the map is drawn in each view, through its own projection, and
+            // kept inside it.
+            let mut hovered_point = None;
+            for &view in state.views() {
+                let mvp = state.window_mvp(view);
+                let under_cursor = state.view_at(state.cursor) == view;
+                let context = view.clip(area, context, hud_scale.density);
+
+                if sky.stars && hud_levels.of(Widget::Stars) == 0 {
+                    let widget_started = Instant::now();
+                    starfield.draw(
+                        state.sky_mvp(view),
+                        state.camera_controller.camera_position(),
+                        sky.star_brightness,
+                        context,
+                        graphics,
+                    );
+                    hud_costs.time(Widget::Stars, widget_started);
+                }
+                let grid = match hud_levels.of(Widget::Graticule) {
+                    0 => Some(Grid::new(state.camera_controller.camera_height())),
+                    1 => Some(Grid::new(state.camera_controller.camera_height()).coarser()),
+                    _ => None,
+                };
+                if let (true, Some(grid)) = (state.graticule_enabled, grid) {
+                    let widget_started = Instant::now();
+                    graticule::draw(
+                        mvp,
+                        state.camera_controller.look_at(),
+                        grid,
+                        state.settings.hud.graticule_color,
+                        &mut glyphs,
+                        context,
+                        graphics,
+                    );
+                    hud_costs.time(Widget::Graticule, widget_started);
+                }
+
+                if state.selected_polygon.is_some() {
+                    let widget_started = Instant::now();
+                    let lines = state.selected_outline(view, context.get_view_size());
+                    let width = outline::width(state.camera_controller.camera_height());
+                    outline::draw(&lines, width, style, context, graphics);
+                    hud_costs.time(Widget::Outline, widget_started);
+                }
+
+                if !state.routes.is_empty() || state.route_start.is_some() {
+                    let widget_started = Instant::now();
+                    let projected = state.projected_routes(view);
+                    let look_at = state.camera_controller.look_at();
+                    let view_size = context.get_view_size();
+                    let on_screen = |point| routes::project_point(mvp, look_at, point, view_size);
+
+                    let marks = routes::Marks {
+                        hovered: if state.route_tool && under_cursor {
+                            routes::hovered(&projected, state.cursor)
+                        } else {
+                            None
+                        },
+                        selected: state.selected_route,
+                        pending: state.route_start.and_then(on_screen),
+                        traveller: state.journey.and_then(|journey| {
+                            let route = &state.routes[journey.route];
+                            on_screen(route.point_at_km(journey.travelled_km))
+                        }),
+                    };
+                    routes::draw(&projected, marks, style, &mut glyphs, context, graphics);
+                    hud_costs.time(Widget::Routes, widget_started);
+                }
+
+                if let Some(ref layer) = state.points {
+                    let widget_started = Instant::now();
+                    let markers = layer.project(
+                        mvp,
+                        state.camera_controller.look_at(),
+                        state.camera_controller.camera_height(),
+                        hud_scale.len(f64::from(state.settings.points.marker_size)),
+                        context.get_view_size(),
+                    );
+                    let unlabeled;
+                    let settings = if hud_levels.of(Widget::Points) > 0 {
+                        unlabeled = PointSettings {
+                            labels: false,
+                            ..state.settings.points.clone()
+                        };
+                        &unlabeled
+                    } else {
+                        &state.settings.points
+                    };
+                    layer.draw(&markers, settings, style, &mut glyphs, context, graphics);
+                    if under_cursor {
+                        hovered_point =
+                            layer.hovered(&markers, state.cursor).map(|point| &point.name);
+                    }
+                    hud_costs.time(Widget::Points, widget_started);
+                }
+            }
+
+            if let Some(ref split) = state.split {
+                split_view::draw_divider(area, style, context, graphics);
+                if layout.is_visible() {
+                    let names = [state.map_mode.name(), split.mode.name()];
+                    split_view::draw_names(names, area, style, &mut glyphs, context, graphics);
+                }
+            }
+
+            if layout.is_visible() && state.crosshair_enabled {
+                let readout = state.crosshair_text();
+                crosshair::draw(&readout, style, &mut glyphs, context, graphics);
+            }
+
+            if widgets.position {
+                let idle = last_skipped.map_or(false, |skipped| {
+                    skipped.elapsed() < Duration::from_secs(1)
+                });
+                let fps_text = if idle {
+                    "FPS: idle".to_string()
+                } else {
+                    format!("FPS: {:>3}", fps)
+                };
+                let position_text = state.position_text();
+                layout.text_panel(
+                    Corner::TopLeft,
+                    &[&fps_text, &position_text],
                     &mut glyphs,
-                    &context.draw_state,
-                    context.transform.trans(10.0, 10.0),
+                    context,
                     graphics,
-                )
-                .unwrap();
+                );
+            }
+
+            if widgets.cursor_coordinates {
+                let cursor_text = format!("Cursor: {}", state.cursor_coordinates_text());
+                layout.text_panel(Corner::TopLeft, &[&cursor_text], &mut glyphs, context, graphics);
+            }
+
+            if let Some(journey) = state.journey_text() {
+                layout.text_panel(Corner::TopLeft, &[&journey], &mut glyphs, context, graphics);
+            }
+
+            if let Some(tour) = state.tour_text() {
+                layout.text_panel(Corner::TopLeft, &[&tour], &mut glyphs, context, graphics);
+            }
+
+            state.more_info_rect = None;
+            if let Some(lines) = state.info_panel_lines(hud_scale, &mut glyphs) {
+                let more_info = lines.last().map(String::as_str) == Some(info_panel::MORE_INFO);
+                let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+                let panel =
+                    layout.text_panel(Corner::TopLeft, &lines, &mut glyphs, context, graphics);
+                if more_info {
+                    state.more_info_rect =
+                        panel.map(|panel| layout.line_rect(panel, lines.len() - 1));
+                }
+            }
+
+            if widgets.status {
+                layout.text_panel(
+                    Corner::TopRight,
+                    &[&state.status_text()],
+                    &mut glyphs,
+                    context,
+                    graphics,
+                );
+            }
+
+            if widgets.solar_time {
+                let (icon, solar_time) = state.solar_time_text();
+                layout.icon_text_panel(
+                    Corner::TopRight,
+                    icon,
+                    &[&solar_time],
+                    &mut glyphs,
+                    context,
+                    graphics,
+                );
+            }
+
+            let clocks = state.world_clock_lines();
+            if !clocks.is_empty() {
+                let lines: Vec<&str> = clocks.iter().map(String::as_str).collect();
+                layout.text_panel(Corner::TopRight, &lines, &mut glyphs, context, graphics);
+            }
+
+            if state.animation_clock.is_frozen() {
+                layout.text_panel(Corner::TopRight, &["Paused"], &mut glyphs, context, graphics);
+            }
+
+            if let Some(level) = state.tile_loading.loading_level() {
+                let phase = state.tile_loading.spinner_phase();
+                layout.icon_text_panel(
+                    Corner::TopRight,
+                    Icon::Spinner { phase },
+                    &[&format!("Loading level {} tiles...", level)],
+                    &mut glyphs,
+                    context,
+                    graphics,
+                );
+            }
+
+            let scale_bar_origin = if widgets.scale_bar {
+                layout.place(Corner::BottomLeft, scale_bar::size(hud_scale))
+            } else {
+                None
+            };
+            if let Some(origin) = scale_bar_origin {
+                scale_bar::draw(
+                    km_per_pixel,
+                    state.formatting(),
+                    origin,
+                    style,
+                    &mut glyphs,
+                    context,
+                    graphics,
+                );
+            }
+
+            state.compass_center = if widgets.compass {
+                layout
+                    .place(Corner::BottomRight, compass::size(hud_scale))
+                    .map(|origin| compass::center(origin, hud_scale))
+            } else {
+                None
+            };
+            if let Some(center) = state.compass_center {
+                compass::draw(
+                    state.camera_controller.heading(),
+                    state.camera_controller.viewing_angle(),
+                    center,
+                    style,
+                    &mut glyphs,
+                    context,
+                    graphics,
+                );
+            }
+
+            let widget_started = Instant::now();
+            state.overview_rect = overview_target.and_then(|inset| {
+                let size = overview::size(hud_scale);
+                let [x, y] = layout.place(Corner::BottomRight, size)?;
+                let rect = [x, y, size[0], size[1]];
+
+                let look_at = state.camera_controller.look_at();
+                let to_inset = |point| overview::to_inset(rect, look_at[0], point);
+                let footprint: Vec<_> = state.view_footprint().into_iter().map(to_inset).collect();
+                let marker = to_inset(look_at);
+                overview::draw(inset, rect, &footprint, marker, style, context, graphics);
+                Some(rect)
+            });
+            hud_costs.time(Widget::Overview, widget_started);
+
+            if state.settings_overlay.is_adjusting_safe_area() {
+                hud::draw_safe_area_guide(layout.insets(), style, context, graphics);
+            }
+            if state.settings_overlay.is_visible() {
+                let size = state.settings_overlay.size(hud_scale);
+                if let Some(origin) = layout.place(Corner::TopLeft, size) {
+                    state.settings_overlay.draw(
+                        &state.settings,
+                        origin,
+                        style,
+                        &mut glyphs,
+                        context,
+                        graphics,
+                    );
+                }
+            }
+
+            if state.session_picker.is_visible() {
+                let size = state.session_picker.size(hud_scale);
+                if let Some(origin) = layout.place(Corner::TopLeft, size) {
+                    state
+                        .session_picker
+                        .draw(origin, style, &mut glyphs, context, graphics);
+                }
+            }
+            if state.command_palette.is_visible() {
+                let size = state.command_palette.size(hud_scale);
+                if let Some(origin) = layout.place(Corner::TopLeft, size) {
+                    state
+                        .command_palette
+                        .draw(origin, style, &mut glyphs, context, graphics);
+                }
+            }
+
+            if state.frame_times_visible {
+                let mut details = vec![
+                    context_info.to_string(),
+                    frame_limiter.status(),
+                    state.level_status(),
+                    state.terrain_status(),
+                    state.adaptive_quality.status(),
+                ];
+                details.extend(state.hud_budget.details());
+                details.extend(vec![
+                    offscreen.status(render_scale),
+                    screenshots.status(),
+                    state.memory_usage.status(),
+                    format!(
+                        "Assets: {}",
+                        provenance::describe_short(provenance.as_ref(), &assets_dir)
+                    ),
+                ]);
+                let size = frame_times::size(hud_scale, details.len());
+                if let Some(origin) = layout.place(Corner::TopRight, size) {
+                    state
+                        .frame_times
+                        .draw(&details, origin, style, &mut glyphs, context, graphics);
+                }
+            }
+
+            let widget_started = Instant::now();
+            let palette = state.profiled_settings.modes.palette;
+            // While countries are colored by how they changed since the snapshot, each legend
+            // explains that instead, whether or not legends are shown.
+            if let Some(legend_entries) = state.snapshot_legend_entries(state.map_mode) {
+                let size = Legend::size(&legend_entries, hud_scale);
+                if let Some(origin) = layout.place(Corner::BottomLeft, size) {
+                    Legend::draw_key(
+                        &legend_entries,
+                        origin,
+                        style,
+                        &mut glyphs,
+                        context,
+                        graphics,
+                    );
+                }
+            } else {
+                let legend_entries = state.legend_entries_in(state.map_mode, palette);
+                if state.legend.is_visible() && !legend_entries.is_empty() {
+                    let size = Legend::size(&legend_entries, hud_scale);
+                    if let Some(origin) = layout.place(Corner::BottomLeft, size) {
+                        state.legend.draw(
+                            &legend_entries,
+                            origin,
+                            style,
+                            &mut glyphs,
+                            context,
+                            graphics,
+                        );
+                    }
+                }
+            }
+            if let Some(lines) = state.break_editor_lines() {
+                let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+                layout.text_panel(Corner::BottomLeft, &lines, &mut glyphs, context, graphics);
+            }
+            // The right half's legend goes in the right half's corner.
+            if let Some(ref split) = state.split {
+                if let Some(legend_entries) = state.snapshot_legend_entries(split.mode) {
+                    let size = Legend::size(&legend_entries, hud_scale);
+                    if let Some(origin) = layout.place(Corner::BottomRight, size) {
+                        Legend::draw_key(
+                            &legend_entries,
+                            origin,
+                            style,
+                            &mut glyphs,
+                            context,
+                            graphics,
+                        );
+                    }
+                } else {
+                    let legend_entries = state.legend_entries_in(split.mode, palette);
+                    if state.legend.is_visible() && !legend_entries.is_empty() {
+                        let size = Legend::size(&legend_entries, hud_scale);
+                        if let Some(origin) = layout.place(Corner::BottomRight, size) {
+                            split.legend.draw(
+                                &legend_entries,
+                                origin,
+                                style,
+                                &mut glyphs,
+                                context,
+                                graphics,
+                            );
+                        }
+                    }
+                }
+            }
+            hud_costs.time(Widget::Legend, widget_started);
+
+            if state.help_overlay.is_visible() {
+                let footer = format!(
+                    "{} - Assets: {} - Settings are saved in {}",
+                    state.level_status(),
+                    provenance::describe(provenance.as_ref(), &assets_dir),
+                    state.settings_path.display()
+                );
+                state.help_overlay.draw(
+                    &state.bindings,
+                    &footer,
+                    style,
+                    &mut glyphs,
+                    context,
+                    graphics,
+                );
+            }
+
+            let overlay_visible = state.help_overlay.is_visible()
+                || state.settings_overlay.is_visible()
+                || state.session_picker.is_visible();
+            let hovered = hovered_point.map(String::as_str).or_else(|| state.hovered_country());
+            if let (Some(name), false) = (hovered, overlay_visible) {
+                hud::draw_tooltip(name, state.cursor, style, &mut glyphs, context, graphics);
+            }
+
+            if layout.is_visible() {
+                state.toasts.draw(style, layout.insets(), &mut glyphs, context, graphics);
+            }
+        });
+        state.frame_times.time(Phase::Hud, started);
+        if e.render_args().is_some() {
+            state.hud_budget.frame(hud_costs, started.elapsed().as_secs_f32() * 1000.0);
+        }
+        // How long drawing the frame took, the map and the HUD, but not swapping buffers, which
+        // waits for vsync and would keep every frame above the headroom at 60Hz. While idling,
+        // frames are drawn so rarely that their times say nothing about how fast drawing is.
+        if e.render_args().is_some() && !frame_limiter.is_idle() {
+            let ms = state.frame_times.current_phase(Phase::Render)
+                + state.frame_times.current_phase(Phase::Hud);
+            let level = state.desired_level(state.camera_controller.camera_height());
+            let now = render_started.elapsed().as_secs_f64();
+            state.adaptive_quality.frame(now, ms, level);
+        }
+
+        // A frame that fails to draw does not end the demo, unless too many in a row do. Text the
+        // HUD fails to draw is only left out, and does not count.
+        if draw_map {
+            // Tiles are read as they are needed, so a missing or broken one only shows up here.
+            let drawn = rendered.chain_err(|| {
+                let tiles = assets_dir.join("generated").join("tiles");
+                format!("Could not render the map from the tiles in {}", tiles.display())
+            });
+            if render_recovery.record(drawn)? {
+                state.toast("The map is drawn again".to_string());
+            }
+        }
+
+        // Only after the HUD has been flushed too, so that it frees what the whole frame used.
+        if e.render_args().is_some() {
+            let started = Instant::now();
+            window.device.cleanup();
+            state.frame_times.time(Phase::Swap, started);
+        }
+
+        if e.render_args().is_some() && screenshot_pending {
+            screenshot_pending = false;
+
+            match screenshots.capture(&mut window) {
+                Ok(true) => {}
+                Ok(false) => state.toast(
+                    "Screenshot dropped: earlier ones are still being written".to_string(),
+                ),
+                Err(ref e) => {
+                    error!("{}", e);
+                    state.toast(format!("Screenshot failed: {}", e));
+                }
+            }
+        }
+
+        if e.render_args().is_some() && hi_res_pending {
+            hi_res_pending = false;
+
+            let scale = args.screenshot_scale.unwrap_or(hi_res::DEFAULT_SCALE);
+            let saved = render_hi_res(
+                &mut window.factory,
+                &mut window.encoder,
+                &mut window.device,
+                &mut gaia_renderer,
+                &state,
+                scale,
+            ).and_then(|image| screenshots.save_image(image, &format!("-{}x", scale)));
+            match saved {
+                Ok(path) => info!("Writing {}x screenshot {}", scale, path.display()),
+                Err(ref e) => {
+                    error!("{}", e);
+                    state.toast(format!("High-resolution screenshot failed: {}", e));
+                }
+            }
+        }
+
+        if let (Some(buffer), Some(_)) = (replay_buffer.as_mut(), e.render_args()) {
+            if buffer.wants_frame() {
+                match screenshot::read_back(&mut window) {
+                    Ok(frame) => buffer.push(&frame),
+                    Err(e) => {
+                        error!("Stopped the replay buffer: {}", e);
+                        state.toast(format!("Stopped the replay buffer: {}", e));
+                        replay_buffer = None;
+                    }
+                }
+            }
+        }
+
+        if e.render_args().is_some() {
+            let failed = match recording {
+                Some(ref mut recording) => {
+                    if let Some(title) = recording.title(&state.settings.window.title) {
+                        window.set_title(title);
+                    }
+                    recording.frame(&mut window).err()
+                }
+                None => None,
+            };
+            if let Some(ref e) = failed {
+                error!("Stopped recording: {}", e);
+                state.toast(format!("Stopped recording: {}", e));
+                if let Some(recorded) = recording.take() {
+                    stop_recording(recorded, &mut state, &mut window);
+                }
+            }
+        }
+
+        if let (Some(path), Some(_)) = (args.export_legend.as_ref(), e.render_args()) {
+            let scale = args.screenshot_scale.unwrap_or(hi_res::DEFAULT_SCALE);
+            let image = export_legend(&mut window, &mut glyphs, &state, scale)?;
+            screenshot::write_png(path, image)?;
+            info!("Exported the legend to {}", path.display());
+
+            return Ok(Outcome::Finished);
+        }
+
+        // Tiles keep loading as frames are drawn, so frames are drawn until they settle, and only
+        // the last is written.
+        let mut frame_path = None;
+        let mut rendered_once = None;
+        if let (Some(path), Some(_)) = (args.render_once.as_ref(), e.render_args()) {
+            let loaded = state.tile_loading.loading_level().is_none();
+            if loaded || render_started.elapsed() >= args.render_timeout {
+                if !loaded {
+                    warn!(
+                        "Tiles were still loading after {:.1}s, rendering anyway",
+                        args.render_timeout.as_secs_f64()
+                    );
+                }
+
+                frame_path = Some(path.clone());
+                rendered_once = Some(if loaded {
+                    Outcome::Finished
+                } else {
+                    Outcome::RenderedBeforeLoaded
+                });
+            }
+        }
+
+        // Likewise, each --exec command waits for the flight and tiles of the last to settle.
+        if let (Some(script), Some(_)) = (exec.as_mut(), e.render_args()) {
+            let settled = !state.camera_controller.is_flying()
+                && state.tile_loading.loading_level().is_none();
+            let animating = state.is_finishing_animation();
+            match script.next(settled, animating, args.render_timeout, Instant::now()) {
+                Some(Command::Screenshot(path)) => frame_path = Some(path),
+                Some(Command::Quit) => break,
+                Some(command) => run_command(&mut state, command),
+                None => {}
+            }
+        }
+        if exec.as_ref().map_or(false, ExecScript::is_finished) {
+            exec = None;
+        }
+
+        if let Some(path) = frame_path {
+            match args.screenshot_scale {
+                Some(scale) => {
+                    let image = render_hi_res(
+                        &mut window.factory,
+                        &mut window.encoder,
+                        &mut window.device,
+                        &mut gaia_renderer,
+                        &state,
+                        scale,
+                    )?;
+                    screenshot::write_png(&path, image)?;
+                }
+                None => screenshot::save(&mut window, &path)?,
+            }
+            info!("Rendered {}", path.display());
+        }
+        if let Some(outcome) = rendered_once {
+            return Ok(outcome);
+        }
+    }
+
+    if benchmark.is_some() {
+        bail!("The window was closed before the benchmark finished");
+    }
+    if args.render_once.is_some() {
+        bail!("The window was closed before the frame was rendered");
+    }
+    if args.export_legend.is_some() {
+        bail!("The window was closed before the legend was exported");
+    }
+    if replay.is_some() {
+        info!("Replay finished");
+    }
+    if !repeatable {
+        if let Err(ref e) = session_of(&state, &window, &fullscreen).save(&session_path) {
+            error!("Could not save the session: {}", e);
+        }
+    }
+
+    let spikes = (state.spike_log.spikes(), state.spike_log.path());
+    state.memory_usage.sample();
+    let peak_memory = state.memory_usage.peak();
+    let summary = state.run_stats.summary(&state.frame_times, spikes, peak_memory);
+    info!("{}", summary.trim_end());
+    write_stats(&summary, args.quiet, args.stats_out.as_deref())?;
+
+    Ok(Outcome::Finished)
+}
+
+/// Loads the countries, after checking that the dataset has the properties that `settings` read,
+/// and logging what it lacks. With `strict`, lacking what the start mode or labels need is an
+/// error. Returns what was found lacking, and the places for the startup benchmark.
+fn load_features(settings: &Settings, strict: bool) -> Result<(Features, Vec<Finding>, Places)> {
+    let data = features::load_data()?;
+    let findings = preflight::check(&data, &preflight::Needs::of(settings));
+
+    for finding in &findings {
+        if finding.required {
+            warn!("{} ({})", finding.summary(), finding.consequence);
+        } else {
+            info!("{} ({})", finding.summary(), finding.consequence);
+        }
+    }
+
+    let required: Vec<String> = findings
+        .iter()
+        .filter(|finding| finding.required)
+        .map(Finding::summary)
+        .collect();
+    if strict && !required.is_empty() {
+        bail!("The dataset lacks what is needed (--strict): {}", required.join("; "));
+    }
+
+    let places = Places::sample(&data.points);
+    let features = Features::from_data(data).filtered(settings.countries.filter);
+    for warning in features.warnings() {
+        warn!("Could not read {}", warning);
+    }
+
+    Ok((features, findings, places))
+}
+
+/// The provenance of the assets in `assets_dir`, if they have any, warning about it if it could
+/// not be read.
+fn read_provenance(assets_dir: &Path) -> Option<Provenance> {
+    let provenance = Provenance::load(assets_dir).unwrap_or_else(|e| {
+        let message = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+        warn!("{}", message);
+        None
+    });
+    if let Some(ref provenance) = provenance {
+        info!("Assets: {}", provenance.summary());
+    }
+
+    provenance
+}
+
+/// A loaded asset set: its assets directory and its countries.
+struct AssetSet {
+    assets_dir: PathBuf,
+    features: Features,
+    findings: Vec<Finding>,
+}
+
+/// How switching asset sets went: the renderer to carry on with, and the set it draws if it is a
+/// new one.
+struct Switch<R: gfx::Resources, F: gfx::Factory<R>> {
+    renderer: gaia::Renderer<R, F>,
+    loaded: Result<AssetSet>,
+}
+
+/// Switches from the asset set in `previous_root`, drawn by `previous`, to the one in `dir`, which
+/// is relative to `start_dir` where the demo was started, and changes to its parent directory,
+/// since the renderer loads tiles from there while it runs. If the new set cannot be loaded, the
+/// one in use is kept. Only fails if the one in use cannot be drawn again either.
+fn switch_asset_set<R, F>(
+    factory: F,
+    settings: &Settings,
+    dir: &Path,
+    start_dir: &Path,
+    previous_root: &Path,
+    previous: gaia::Renderer<R, F>,
+) -> Result<Switch<R, F>>
+where
+    R: gfx::Resources,
+    F: gfx::Factory<R> + Clone,
+{
+    // Found and checked first, so that a set that is not there leaves everything as it was.
+    let found = paths::find_assets_dir_in(dir, start_dir)
+        .and_then(|assets_dir| paths::check_assets(&assets_dir).map(|()| assets_dir));
+    let assets_dir = match found {
+        Ok(assets_dir) => assets_dir,
+        Err(e) => {
+            return Ok(Switch {
+                renderer: previous,
+                loaded: Err(e),
+            })
+        }
+    };
+
+    // The renderer's tile fetching thread loads tiles relative to the working directory, and
+    // cannot be joined. So the renderer is dropped before the directory changes: whatever that
+    // thread loads afterwards has nowhere to go, rather than being drawn as part of either set.
+    drop(previous);
+    let root = assets_dir.parent().unwrap_or(&assets_dir).to_path_buf();
+    let loaded = env::set_current_dir(&root)
+        .chain_err(|| format!("Could not change directory to {}", root.display()))
+        .and_then(|()| load_features(settings, false))
+        .and_then(|(features, findings, _)| {
+            let renderer = gaia::Renderer::new(factory.clone()).chain_err(|| {
+                format!("Could not create renderer from the assets in {}", assets_dir.display())
+            })?;
+            Ok((renderer, features, findings))
         });
+
+    match loaded {
+        Ok((renderer, features, findings)) => Ok(Switch {
+            renderer,
+            loaded: Ok(AssetSet {
+                assets_dir,
+                features,
+                findings,
+            }),
+        }),
+        Err(e) => {
+            env::set_current_dir(previous_root).chain_err(|| {
+                format!("Could not change back to {}", previous_root.display())
+            })?;
+            let renderer = gaia::Renderer::new(factory).chain_err(|| {
+                format!("Could not create renderer again from {}", previous_root.display())
+            })?;
+            Ok(Switch {
+                renderer,
+                loaded: Err(e),
+            })
+        }
+    }
+}
+
+/// Draws the map at `scale` times the window's resolution, in as many parts as the GPU needs,
+/// with labels as much larger. The HUD is left out, since it is laid out for the window.
+fn render_hi_res<R, C, F, D, G>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    device: &mut D,
+    renderer: &mut gaia::Renderer<R, G>,
+    state: &State,
+    scale: u32,
+) -> Result<Readback>
+where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    F: gfx::Factory<R>,
+    D: gfx::Device<Resources = R, CommandBuffer = C>,
+    G: gfx::Factory<R> + Clone,
+{
+    let draw_size = state.projection.draw_size();
+    let size = [draw_size.width * scale, draw_size.height * scale];
+    let max_texture_size = device.get_capabilities().max_texture_size as u32;
+    let view = state.camera_controller.view_matrix();
+
+    hi_res::capture(
+        factory,
+        encoder,
+        device,
+        size,
+        max_texture_size,
+        state.settings.sky.clear_color(),
+        |encoder, target, projection| {
+            state
+                .render_map(
+                    renderer,
+                    encoder,
+                    target.color.clone(),
+                    target.depth.clone(),
+                    projection * view,
+                    FrameInputs {
+                        label_scale: scale as f32,
+                        ..state.frame_inputs(View::Whole)
+                    },
+                )
+                .chain_err(|| "Could not render the map")
+        },
+    )
+}
+
+/// Draws the legend of the current map mode, and the scale bar if it is shown, onto a transparent
+/// image at `scale` times the HUD's size.
+fn export_legend(
+    window: &mut PistonWindow,
+    glyphs: &mut Glyphs,
+    state: &State,
+    scale: u32,
+) -> Result<Readback> {
+    let scale_bar = if state.settings.hud.widgets.scale_bar {
+        Some(legend_export::ScaleBar {
+            km_per_pixel: state.km_per_pixel(window),
+            format: state.formatting(),
+        })
+    } else {
+        None
+    };
+    let style = HudStyle {
+        scale: state.hud_scale(),
+        theme: state.profiled_settings.hud.theme(),
+    };
+    let shown = ShownLegend {
+        legend: &state.legend,
+        mode: state.map_mode,
+        palette: state.profiled_settings.modes.palette,
+    };
+
+    legend_export::render(window, glyphs, shown, scale_bar, style, scale)
+}
+
+/// Writes `state.export_rows()` to a CSV and a JSON file in `dir`, named after the current time.
+fn export_countries(state: &mut State, dir: &Path) {
+    let rows = state.export_rows();
+    // The JSON file is named after the CSV, so both have to be free.
+    let csv = screenshot::timestamped_path(dir, "gaia-countries", "", ".csv", |path| {
+        path.exists() || path.with_extension("json").exists()
+    });
+
+    let written =
+        export::write(&csv, &rows).and_then(|_| export::write(&csv.with_extension("json"), &rows));
+    match written {
+        Ok(()) => state.toast(format!(
+            "Exported {} countries to {} and .json",
+            rows.len(),
+            csv.display()
+        )),
+        Err(ref e) => {
+            error!("{}", e);
+            state.toast(format!("Export failed: {}", e));
+        }
+    }
+}
+
+/// Runs an `--exec` command, other than the ones the main loop handles itself.
+fn run_command(state: &mut State, command: Command) {
+    match command {
+        Command::Act(action) => state.handle_action(action),
+        Command::Labels(on) => {
+            if (state.labels_shown() != LabelsShown::None) != on {
+                state.handle_action(bindings::Action::ToggleLabels);
+            }
+        }
+        Command::GoTo {
+            latitude,
+            longitude,
+            height,
+        } => {
+            let camera = &mut state.camera_controller;
+            let height = height.unwrap_or_else(|| camera.camera_height());
+            let heading = camera.heading().to_degrees();
+            camera.fly_to(latitude, longitude, height, heading);
+        }
+        Command::Animate(animation) => state.animate_parameter(animation),
+        Command::StopAnimation => {
+            state.stop_parameter_animation();
+        }
+        Command::Wait(_) | Command::WaitForAnimation | Command::Screenshot(_) | Command::Quit => {}
+    }
+}
+
+/// Stops `recording`, and shows where it went. The window's title no longer shows its status.
+fn stop_recording(recording: FrameRecording, state: &mut State, window: &mut PistonWindow) {
+    window.set_title(state.settings.window.title.clone());
+    match recording.stop() {
+        Ok(path) => state.toast(format!("Recording saved, frames listed in {}", path.display())),
+        Err(ref e) => {
+            error!("{}", e);
+            state.toast(format!("Could not finish the recording: {}", e));
+        }
+    }
+}
+
+/// Prints `stats` unless `quiet`, and writes them to `stats_out`, if given.
+fn write_stats(stats: &str, quiet: bool, stats_out: Option<&Path>) -> Result<()> {
+    if !quiet {
+        print!("{}", stats);
+    }
+
+    if let Some(path) = stats_out {
+        persistence::write_atomically(path, stats.as_bytes())?;
+        info!("Wrote statistics to {}", path.display());
     }
 
     Ok(())
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+/// The least time between two readings, so that showing memory usage every frame stays cheap.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps track of how much memory the process has resident, and the most it has had.
+///
+/// gaia does not say how many textures or buffers it holds, or how large they are, so only the
+/// process as a whole is measured.
+#[derive(Debug)]
+pub struct MemoryUsage {
+    last_sample: Option<Instant>,
+    /// In bytes, or `None` if it cannot be read on this platform.
+    resident: Option<u64>,
+    peak: Option<u64>,
+}
+
+impl MemoryUsage {
+    pub fn new() -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            last_sample: None,
+            resident: None,
+            peak: None,
+        };
+        usage.sample();
+
+        usage
+    }
+
+    /// Reads how much memory is resident, unless it was read less than `SAMPLE_INTERVAL` ago.
+    pub fn sample(&mut self) {
+        let now = Instant::now();
+        if let Some(last_sample) = self.last_sample {
+            if now.duration_since(last_sample) < SAMPLE_INTERVAL {
+                return;
+            }
+        }
+        self.last_sample = Some(now);
+
+        if let Some((resident, peak)) = read_resident() {
+            self.resident = Some(resident);
+            self.peak = Some(self.peak.unwrap_or(0).max(resident).max(peak));
+        }
+    }
+
+    /// The most memory the process has had resident, in bytes, if known.
+    pub fn peak(&self) -> Option<u64> {
+        self.peak
+    }
+
+    /// The latest reading, as shown in the performance overlay.
+    pub fn status(&self) -> String {
+        match (self.resident, self.peak) {
+            (Some(resident), Some(peak)) => format!(
+                "Memory: {} resident (peak {})",
+                mebibytes(resident),
+                mebibytes(peak)
+            ),
+            _ => "Memory: n/a".to_string(),
+        }
+    }
+}
+
+impl Default for MemoryUsage {
+    fn default() -> MemoryUsage {
+        MemoryUsage::new()
+    }
+}
+
+/// `bytes`, such as "512.3 MiB".
+pub fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// The bytes resident now and at most so far, from the kernel's accounting of the process.
+///
+/// `/proc/self/status` is read rather than `/proc/self/statm` since it is in kilobytes rather
+/// than pages, whose size Rust's standard library does not give, and it also has the peak.
+#[cfg(target_os = "linux")]
+fn read_resident() -> Option<(u64, u64)> {
+    let status = ::std::fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find(|line| line.starts_with(name))
+            .and_then(|line| line[name.len()..].trim().trim_end_matches("kB").trim().parse().ok())
+            .map(|kilobytes: u64| kilobytes * 1024)
+    };
+
+    let resident = field("VmRSS:")?;
+    Some((resident, field("VmHWM:").unwrap_or(resident)))
+}
+
+/// Other platforms need system calls that the standard library does not wrap, so memory usage is
+/// not shown on them.
+#[cfg(not(target_os = "linux"))]
+fn read_resident() -> Option<(u64, u64)> {
+    None
+}
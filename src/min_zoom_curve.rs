@@ -0,0 +1,169 @@
+//! Remaps the `min_zoom` that `gaia_assetgen` stored for each place to the one its label is
+//! shown by, so that labels can be tuned for a dataset without regenerating the assets. The
+//! curve goes straight between control points, each an `[stored, effective]` pair, and keeps
+//! the end points' offsets beyond them.
+
+/// How many control points a curve has.
+pub const POINTS: usize = 5;
+
+pub type ControlPoints = [[f32; 2]; POINTS];
+
+/// The curve that leaves every `min_zoom` as it was stored. Natural Earth's places have values
+/// from about 1 to 10.
+pub const IDENTITY: ControlPoints = [
+    [2.0, 2.0],
+    [4.0, 4.0],
+    [6.0, 6.0],
+    [8.0, 8.0],
+    [10.0, 10.0],
+];
+
+/// The lowest effective `min_zoom`, so that no label is shown however high the camera is.
+pub const MIN_EFFECTIVE: f32 = 0.1;
+
+/// The highest effective `min_zoom` a control point can have.
+pub const MAX_EFFECTIVE: f32 = 100.0;
+
+/// Places are labeled while the camera's height times their effective `min_zoom` is at most
+/// this.
+const LABEL_REACH: f64 = 1.5;
+
+/// Whether a place with the effective `min_zoom` is labeled with the camera at `camera_height`.
+pub fn is_labeled_at(min_zoom: f64, camera_height: f32) -> bool {
+    f64::from(camera_height) * min_zoom <= LABEL_REACH
+}
+
+/// The effective `min_zoom` of a place whose stored one is `min_zoom`. Between two control
+/// points, it goes straight from one to the other; beyond the first or last, it is offset as
+/// much as that point is. Never below `MIN_EFFECTIVE`.
+pub fn remap(points: &ControlPoints, min_zoom: f64) -> f64 {
+    let point = |i: usize| (f64::from(points[i][0]), f64::from(points[i][1]));
+
+    let (first_x, first_y) = point(0);
+    let (last_x, last_y) = point(POINTS - 1);
+    let effective = if min_zoom <= first_x {
+        min_zoom + first_y - first_x
+    } else if min_zoom >= last_x {
+        min_zoom + last_y - last_x
+    } else {
+        let i = (1..POINTS).find(|&i| min_zoom < point(i).0).unwrap_or(POINTS - 1);
+        let ((x0, y0), (x1, y1)) = (point(i - 1), point(i));
+        y0 + (y1 - y0) * (min_zoom - x0) / (x1 - x0)
+    };
+
+    effective.max(f64::from(MIN_EFFECTIVE))
+}
+
+/// `points` with their effective values clamped from `MIN_EFFECTIVE` to `MAX_EFFECTIVE`. If the
+/// stored values do not increase from one point to the next, which point a place falls between
+/// would be unclear, so the identity curve is used instead.
+pub fn validated(points: ControlPoints) -> ControlPoints {
+    let increasing = points.windows(2).all(|pair| pair[0][0] < pair[1][0]);
+    let finite = points.iter().all(|&[x, _]| x.is_finite() && x > 0.0);
+    if !increasing || !finite {
+        return IDENTITY;
+    }
+
+    let mut validated = points;
+    for point in &mut validated {
+        point[1] = if point[1].is_finite() {
+            point[1].max(MIN_EFFECTIVE).min(MAX_EFFECTIVE)
+        } else {
+            point[0]
+        };
+    }
+    validated
+}
+
+/// Whether `points` leaves every `min_zoom` as it is.
+pub fn is_identity(points: &ControlPoints) -> bool {
+    points.iter().all(|&[x, y]| x == y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stored values from well below the first control point to well above the last.
+    fn samples() -> Vec<f64> {
+        (0..=240).map(|i| f64::from(i) * 0.05).collect()
+    }
+
+    fn assert_monotonic(points: &ControlPoints) {
+        let remapped: Vec<_> = samples().into_iter().map(|x| remap(points, x)).collect();
+        for pair in remapped.windows(2) {
+            assert!(pair[0] <= pair[1], "{:?} falls from {} to {}", points, pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn the_default_curve_is_the_identity() {
+        for x in samples().into_iter().filter(|&x| x >= f64::from(MIN_EFFECTIVE)) {
+            assert!((remap(&IDENTITY, x) - x).abs() < 1e-9, "{}", x);
+        }
+        assert!(is_identity(&IDENTITY));
+    }
+
+    #[test]
+    fn goes_straight_between_control_points() {
+        let mut points = IDENTITY;
+        points[1][1] = 5.0;
+
+        assert!((remap(&points, 4.0) - 5.0).abs() < 1e-6);
+        assert!((remap(&points, 3.0) - 3.5).abs() < 1e-6);
+        assert!((remap(&points, 5.0) - 5.5).abs() < 1e-6);
+        assert!((remap(&points, 7.0) - 7.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn keeps_the_end_offsets_beyond_the_ends() {
+        let mut points = IDENTITY;
+        points[0][1] = 1.0;
+        points[4][1] = 12.0;
+
+        assert!((remap(&points, 1.5) - 0.5).abs() < 1e-6);
+        assert!((remap(&points, 11.0) - 13.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn is_never_below_the_least_effective_value() {
+        let mut points = IDENTITY;
+        points[0][1] = MIN_EFFECTIVE;
+
+        assert_eq!(remap(&points, 0.0), f64::from(MIN_EFFECTIVE));
+        assert_eq!(remap(&IDENTITY, -3.0), f64::from(MIN_EFFECTIVE));
+        assert!(samples().into_iter().all(|x| remap(&points, x) >= f64::from(MIN_EFFECTIVE)));
+    }
+
+    #[test]
+    fn is_monotonic_when_the_control_points_are() {
+        let curves: [ControlPoints; 5] = [
+            IDENTITY,
+            [[2.0, 1.0], [4.0, 1.5], [6.0, 6.0], [8.0, 9.0], [10.0, 20.0]],
+            [[2.0, 4.0], [4.0, 4.0], [6.0, 4.0], [8.0, 4.0], [10.0, 4.0]],
+            [[1.0, 0.1], [1.5, 0.2], [3.0, 9.0], [9.0, 9.5], [12.0, 50.0]],
+            [[2.0, 3.0], [4.0, 3.1], [6.0, 3.2], [8.0, 3.3], [10.0, 3.4]],
+        ];
+        for points in &curves {
+            assert_monotonic(points);
+            assert_monotonic(&validated(*points));
+        }
+    }
+
+    #[test]
+    fn validation_clamps_effective_values_and_rejects_unordered_points() {
+        let mut points = IDENTITY;
+        points[0][1] = -4.0;
+        points[2][1] = ::std::f32::NAN;
+        points[4][1] = 1e9;
+
+        let validated = validated(points);
+        assert_eq!(validated[0][1], MIN_EFFECTIVE);
+        assert_eq!(validated[2][1], 6.0);
+        assert_eq!(validated[4][1], MAX_EFFECTIVE);
+
+        let mut unordered = IDENTITY;
+        unordered[3][0] = 3.0;
+        assert_eq!(super::validated(unordered), IDENTITY);
+    }
+}
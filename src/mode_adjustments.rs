@@ -0,0 +1,194 @@
+//! How each map mode's look is adjusted, over the settings every mode shares.
+//!
+//! What decides each adjustment, from first to last:
+//!
+//! 1. A tweak made this session, while the mode is shown, which is not saved and is forgotten
+//!    when the mode is switched.
+//! 2. The adjustment saved for the mode, under its name in `modes.adjustments`, as the settings
+//!    overlay makes them.
+//! 3. The setting for every mode, such as `modes.palette`, from the settings file.
+//! 4. The built-in default for that setting.
+//!
+//! `layered` is the only place this is worked out. The active profile is layered over the
+//! result, as it is over every other setting.
+
+use label_policy::LabelPolicy;
+use settings::Settings;
+use {MapMode, Palette};
+
+/// Adjustments to how one map mode is drawn. Each left `None` is as the settings for every mode
+/// have it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeAdjustments {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+    /// The least opacity countries are drawn with, as in `modes.min_alpha`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_alpha: Option<u8>,
+    /// What the mode does to labels, as in `modes.labels`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<LabelPolicy>,
+}
+
+impl ModeAdjustments {
+    pub fn is_empty(&self) -> bool {
+        *self == ModeAdjustments::default()
+    }
+
+    /// These adjustments, with those of `over` wherever it has one.
+    pub fn overlaid(self, over: ModeAdjustments) -> ModeAdjustments {
+        ModeAdjustments {
+            palette: over.palette.or(self.palette),
+            min_alpha: over.min_alpha.or(self.min_alpha),
+            labels: over.labels.or(self.labels),
+        }
+    }
+}
+
+/// The adjustments saved for each map mode, in `modes.adjustments`. Modes with none are left out
+/// of the settings file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedAdjustments {
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub terrain: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub all: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub oecd: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub income: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub exceptional: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub scripted: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub daylight: ModeAdjustments,
+    #[serde(skip_serializing_if = "ModeAdjustments::is_empty")]
+    pub percentile: ModeAdjustments,
+}
+
+impl SavedAdjustments {
+    pub fn of(&self, mode: MapMode) -> ModeAdjustments {
+        match mode {
+            MapMode::Terrain => self.terrain,
+            MapMode::All => self.all,
+            MapMode::Oecd => self.oecd,
+            MapMode::Income => self.income,
+            MapMode::Exceptional => self.exceptional,
+            MapMode::Scripted => self.scripted,
+            MapMode::Daylight => self.daylight,
+            MapMode::Percentile => self.percentile,
+        }
+    }
+
+    pub fn of_mut(&mut self, mode: MapMode) -> &mut ModeAdjustments {
+        match mode {
+            MapMode::Terrain => &mut self.terrain,
+            MapMode::All => &mut self.all,
+            MapMode::Oecd => &mut self.oecd,
+            MapMode::Income => &mut self.income,
+            MapMode::Exceptional => &mut self.exceptional,
+            MapMode::Scripted => &mut self.scripted,
+            MapMode::Daylight => &mut self.daylight,
+            MapMode::Percentile => &mut self.percentile,
+        }
+    }
+}
+
+/// `settings` as `mode` is drawn with: the adjustments saved for it layered over the settings
+/// for every mode, and `session`'s over those.
+pub fn layered(settings: &Settings, mode: MapMode, session: ModeAdjustments) -> Settings {
+    let adjustments = settings.modes.adjustments.of(mode).overlaid(session);
+    let mut layered = settings.clone();
+    let modes = &mut layered.modes;
+
+    if let Some(palette) = adjustments.palette {
+        modes.palette = palette;
+    }
+    if let Some(min_alpha) = adjustments.min_alpha {
+        modes.min_alpha = min_alpha;
+    }
+    if let Some(labels) = adjustments.labels {
+        *modes.labels.of_mut(mode) = labels;
+    }
+
+    layered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn income(settings: &Settings, session: ModeAdjustments) -> Settings {
+        layered(settings, MapMode::Income, session)
+    }
+
+    #[test]
+    fn without_adjustments_the_settings_for_every_mode_apply() {
+        let mut settings = Settings::default();
+        assert_eq!(income(&settings, ModeAdjustments::default()), settings);
+
+        settings.modes.min_alpha = 40;
+        settings.modes.palette = Palette::ColorblindSafe;
+        let layered = income(&settings, ModeAdjustments::default());
+        assert_eq!(layered.modes.min_alpha, 40);
+        assert_eq!(layered.modes.palette, Palette::ColorblindSafe);
+        assert_eq!(layered.modes.labels.of(MapMode::Income), LabelPolicy::Off);
+    }
+
+    #[test]
+    fn saved_adjustments_win_over_the_settings_for_every_mode() {
+        let mut settings = Settings::default();
+        settings.modes.min_alpha = 40;
+        *settings.modes.adjustments.of_mut(MapMode::Income) = ModeAdjustments {
+            min_alpha: Some(204),
+            labels: Some(LabelPolicy::CapitalsOnly),
+            ..ModeAdjustments::default()
+        };
+
+        let layered = income(&settings, ModeAdjustments::default());
+        assert_eq!(layered.modes.min_alpha, 204);
+        assert_eq!(layered.modes.labels.of(MapMode::Income), LabelPolicy::CapitalsOnly);
+        // What is not adjusted is left alone.
+        assert_eq!(layered.modes.palette, Palette::Standard);
+
+        // Other modes keep the settings for every mode.
+        let terrain = super::layered(&settings, MapMode::Terrain, ModeAdjustments::default());
+        assert_eq!(terrain.modes.min_alpha, 40);
+        assert_eq!(terrain.modes.labels.of(MapMode::Income), LabelPolicy::Off);
+    }
+
+    #[test]
+    fn session_tweaks_win_over_saved_adjustments() {
+        let mut settings = Settings::default();
+        *settings.modes.adjustments.of_mut(MapMode::Income) = ModeAdjustments {
+            palette: Some(Palette::ColorblindSafe),
+            min_alpha: Some(204),
+            ..ModeAdjustments::default()
+        };
+        let session = ModeAdjustments {
+            min_alpha: Some(100),
+            labels: Some(LabelPolicy::On),
+            ..ModeAdjustments::default()
+        };
+
+        let layered = income(&settings, session);
+        assert_eq!(layered.modes.min_alpha, 100);
+        assert_eq!(layered.modes.labels.of(MapMode::Income), LabelPolicy::On);
+        assert_eq!(layered.modes.palette, Palette::ColorblindSafe);
+        // Layering leaves what was saved as it was.
+        assert_eq!(layered.modes.adjustments, settings.modes.adjustments);
+    }
+
+    #[test]
+    fn only_modes_with_adjustments_are_saved() {
+        let mut adjustments = SavedAdjustments::default();
+        adjustments.income.min_alpha = Some(204);
+
+        let toml = ::toml::to_string(&adjustments).unwrap();
+        assert_eq!(toml.trim(), "[income]\nmin_alpha = 204");
+        assert_eq!(::toml::from_str::<SavedAdjustments>(&toml).unwrap(), adjustments);
+    }
+}
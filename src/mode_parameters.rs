@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+
+use MapMode;
+
+/// A number a map mode's colors depend on, which can be tuned while the demo runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parameter {
+    /// What it is called in the settings file.
+    pub name: &'static str,
+    /// What it is called in the HUD.
+    pub label: &'static str,
+    pub range: (f32, f32),
+    /// How much one press of + or - changes it by.
+    pub step: f32,
+    pub default: f32,
+}
+
+const OPACITY: Parameter = Parameter {
+    name: "opacity",
+    label: "Opacity",
+    range: (0.0, 255.0),
+    step: 15.0,
+    default: 64.0,
+};
+
+const LIGHTNESS: Parameter = Parameter {
+    name: "lightness",
+    label: "Lightness",
+    range: (0.05, 0.95),
+    step: 0.05,
+    default: 0.3,
+};
+
+/// How quickly exceptional mode cycles through the hues, in degrees a second.
+const HUE_SPEED: Parameter = Parameter {
+    name: "hue_speed",
+    label: "Hue speed",
+    range: (0.0, 360.0),
+    step: 10.0,
+    default: 100.0,
+};
+
+/// How far below the horizon the sun is, in degrees, once night is at its darkest.
+const TWILIGHT: Parameter = Parameter {
+    name: "twilight",
+    label: "Twilight",
+    range: (1.0, 30.0),
+    step: 1.0,
+    default: 18.0,
+};
+
+const NIGHT_OPACITY: Parameter = Parameter {
+    name: "night_opacity",
+    label: "Night opacity",
+    range: (0.0, 255.0),
+    step: 15.0,
+    default: 180.0,
+};
+
+const SHADED: [Parameter; 2] = [OPACITY, LIGHTNESS];
+
+const INCOME: [Parameter; 1] = [Parameter {
+    default: 100.0,
+    ..OPACITY
+}];
+
+const EXCEPTIONAL: [Parameter; 2] = [
+    HUE_SPEED,
+    Parameter {
+        default: 100.0,
+        ..OPACITY
+    },
+];
+
+const DAYLIGHT: [Parameter; 2] = [TWILIGHT, NIGHT_OPACITY];
+
+/// The modes with parameters.
+const TUNABLE: [MapMode; 5] = [
+    MapMode::All,
+    MapMode::Oecd,
+    MapMode::Income,
+    MapMode::Exceptional,
+    MapMode::Daylight,
+];
+
+/// The parameters `mode` has, in the order PgUp and PgDn go through them.
+pub fn parameters(mode: MapMode) -> &'static [Parameter] {
+    match mode {
+        MapMode::Terrain | MapMode::Scripted | MapMode::Percentile => &[],
+        MapMode::All | MapMode::Oecd => &SHADED,
+        MapMode::Income => &INCOME,
+        MapMode::Exceptional => &EXCEPTIONAL,
+        MapMode::Daylight => &DAYLIGHT,
+    }
+}
+
+/// `mode`'s parameter `name`, if it has one.
+pub fn find(mode: MapMode, name: &str) -> Option<&'static Parameter> {
+    parameters(mode).iter().find(|parameter| parameter.name == name)
+}
+
+/// The values of each map mode's parameters, by name, as kept in `modes.parameters`. Parameters
+/// not given have their default.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeParameters {
+    pub all: BTreeMap<String, f32>,
+    pub oecd: BTreeMap<String, f32>,
+    pub income: BTreeMap<String, f32>,
+    pub exceptional: BTreeMap<String, f32>,
+    pub daylight: BTreeMap<String, f32>,
+}
+
+impl ModeParameters {
+    fn values(&self, mode: MapMode) -> Option<&BTreeMap<String, f32>> {
+        match mode {
+            MapMode::Terrain | MapMode::Scripted | MapMode::Percentile => None,
+            MapMode::All => Some(&self.all),
+            MapMode::Oecd => Some(&self.oecd),
+            MapMode::Income => Some(&self.income),
+            MapMode::Exceptional => Some(&self.exceptional),
+            MapMode::Daylight => Some(&self.daylight),
+        }
+    }
+
+    fn values_mut(&mut self, mode: MapMode) -> Option<&mut BTreeMap<String, f32>> {
+        match mode {
+            MapMode::Terrain | MapMode::Scripted | MapMode::Percentile => None,
+            MapMode::All => Some(&mut self.all),
+            MapMode::Oecd => Some(&mut self.oecd),
+            MapMode::Income => Some(&mut self.income),
+            MapMode::Exceptional => Some(&mut self.exceptional),
+            MapMode::Daylight => Some(&mut self.daylight),
+        }
+    }
+
+    /// The value of `mode`'s parameter `name`, or `None` if there is no such parameter.
+    pub fn get(&self, mode: MapMode, name: &str) -> Option<f32> {
+        let parameter = find(mode, name)?;
+
+        let value = self.values(mode).and_then(|values| values.get(name));
+        Some(value.map_or(parameter.default, |&value| clamp(parameter, value)))
+    }
+
+    /// Changes `mode`'s parameter at `index` in `parameters` by `steps` of its step, within its
+    /// range. Returns its new value, or `None` if there is no such parameter.
+    pub fn adjust(&mut self, mode: MapMode, index: usize, steps: i32) -> Option<f32> {
+        let parameter = parameters(mode).get(index)?;
+        let value = self.get(mode, parameter.name)?;
+        // Rounded to the step, so that steps do not pile up float error.
+        let stepped = ((value / parameter.step).round() + steps as f32) * parameter.step;
+        let value = clamp(parameter, stepped);
+
+        self.values_mut(mode)?.insert(parameter.name.to_string(), value);
+        Some(value)
+    }
+
+    /// Sets `mode`'s parameter `name` to `value`, within its range. Returns its new value, or
+    /// `None` if there is no such parameter.
+    pub fn set(&mut self, mode: MapMode, name: &str, value: f32) -> Option<f32> {
+        let value = clamp(find(mode, name)?, value);
+
+        self.values_mut(mode)?.insert(name.to_string(), value);
+        Some(value)
+    }
+
+    /// Forgets the values given for `mode`'s parameters, so that each has its default again.
+    /// Returns whether there were any.
+    pub fn reset(&mut self, mode: MapMode) -> bool {
+        match self.values_mut(mode) {
+            Some(values) if !values.is_empty() => {
+                values.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Clamps every value into its parameter's range, and drops values for parameters that do
+    /// not exist.
+    pub fn validated(mut self) -> ModeParameters {
+        for &mode in &TUNABLE {
+            if let Some(values) = self.values_mut(mode) {
+                *values = values
+                    .iter()
+                    .filter_map(|(name, &value)| {
+                        let parameter = parameters(mode).iter().find(|p| p.name == name)?;
+                        Some((name.clone(), clamp(parameter, value)))
+                    })
+                    .collect();
+            }
+        }
+        self
+    }
+}
+
+/// `value`, within `parameter`'s range, or its default if it is not a number.
+fn clamp(parameter: &Parameter, value: f32) -> f32 {
+    if value.is_finite() {
+        value.max(parameter.range.0).min(parameter.range.1)
+    } else {
+        parameter.default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use toml;
+
+    #[test]
+    fn values_are_clamped_into_range() {
+        let mut parameters = ModeParameters::default();
+        assert_eq!(parameters.get(MapMode::Income, "opacity"), Some(100.0));
+        assert_eq!(parameters.get(MapMode::Oecd, "opacity"), Some(64.0));
+        // Parameters some other mode has are not this one's.
+        assert_eq!(parameters.get(MapMode::Income, "lightness"), None);
+        assert_eq!(parameters.get(MapMode::Terrain, "opacity"), None);
+
+        assert_eq!(parameters.set(MapMode::Oecd, "lightness", 2.0), Some(0.95));
+        assert_eq!(parameters.set(MapMode::Oecd, "opacity", ::std::f32::NAN), Some(64.0));
+        assert_eq!(parameters.set(MapMode::Income, "lightness", 0.5), None);
+        assert_eq!(parameters.get(MapMode::Oecd, "lightness"), Some(0.95));
+
+        // Values put in by hand are clamped as they are read.
+        parameters.all.insert("opacity".to_string(), -10.0);
+        assert_eq!(parameters.get(MapMode::All, "opacity"), Some(0.0));
+    }
+
+    #[test]
+    fn adjusting_steps_within_the_range() {
+        let mut parameters = ModeParameters::default();
+        assert_eq!(parameters.adjust(MapMode::All, 0, 2), Some(90.0));
+        assert_eq!(parameters.adjust(MapMode::All, 1, -20), Some(0.05));
+        assert_eq!(parameters.adjust(MapMode::Daylight, 0, 100), Some(30.0));
+        assert_eq!(parameters.adjust(MapMode::Daylight, 2, 1), None);
+        assert_eq!(parameters.adjust(MapMode::Terrain, 0, 1), None);
+
+        // A value off the step is rounded onto it first, so that steps do not drift.
+        parameters.set(MapMode::All, "opacity", 62.0);
+        assert_eq!(parameters.adjust(MapMode::All, 0, 1), Some(75.0));
+
+        assert!(parameters.reset(MapMode::All));
+        assert!(!parameters.reset(MapMode::All));
+        assert_eq!(parameters.get(MapMode::All, "opacity"), Some(64.0));
+        assert_eq!(parameters.get(MapMode::Daylight, "twilight"), Some(30.0));
+    }
+
+    #[test]
+    fn values_survive_the_settings_file() {
+        let mut parameters = ModeParameters::default();
+        parameters.adjust(MapMode::Exceptional, 0, -3);
+        parameters.set(MapMode::Daylight, "night_opacity", 90.0);
+
+        let contents = toml::to_string(&parameters).unwrap();
+        let read: ModeParameters = toml::from_str(&contents).unwrap();
+        assert_eq!(read.validated(), parameters);
+
+        // What is out of range is clamped, and what no mode has is dropped.
+        let read: ModeParameters =
+            toml::from_str("[income]\nopacity = 1000.0\nlightness = 0.5\n").unwrap();
+        let mut expected = ModeParameters::default();
+        expected.income.insert("opacity".to_string(), 255.0);
+        assert_eq!(read.validated(), expected);
+    }
+}
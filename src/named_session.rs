@@ -0,0 +1,315 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use persistence;
+use session::Session;
+use settings::{HudWidgets, LabelSettings};
+use {MapMode, Result, ResultExt};
+
+/// What the directory of named sessions is called, next to the settings file.
+pub const SESSIONS_DIR_NAME: &str = "gaia-demo-sessions";
+
+/// The longest a session's name can be.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// The format version of session files. Files of other versions are not loaded.
+const VERSION: u32 = 1;
+
+const KIND: &str = "named session";
+
+/// Everything about how the map is being looked at, saved under a name to come back to later,
+/// such as "europe-income-setup".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NamedSession {
+    pub camera: Session,
+    pub mode: MapMode,
+    /// The legend categories of `mode` that are hidden from the map.
+    pub hidden_categories: Vec<usize>,
+    /// The `features::feature_id` of the selected country, if any.
+    pub selected: Option<String>,
+    pub labels_enabled: bool,
+    pub labels: LabelSettings,
+    pub hud_visible: bool,
+    /// Which widgets are shown, with those that can be toggled with a key as they were toggled.
+    pub widgets: HudWidgets,
+}
+
+impl NamedSession {
+    /// Loads the session named `name` from `dir`. Anything in it that cannot be read, such as a
+    /// map mode this version of the demo does not have, is taken from `fallback` instead, and
+    /// returned as a warning, so that one bad item does not lose the rest.
+    pub fn load(
+        dir: &Path,
+        name: &str,
+        fallback: &NamedSession,
+    ) -> Result<(NamedSession, Vec<String>)> {
+        let path = path_in(dir, name)?;
+        if !path.exists() {
+            bail!("There is no session named {:?} in {}", name, dir.display());
+        }
+        let data: Value = match persistence::load_versioned(&path, KIND, VERSION) {
+            Some(data) => data,
+            None => bail!("Could not read session {:?} from {}", name, path.display()),
+        };
+
+        let mut warnings = Vec::new();
+        let mut session = fallback.clone();
+        read(&data, "camera", &mut session.camera, &mut warnings);
+        read(&data, "mode", &mut session.mode, &mut warnings);
+        read(&data, "hidden_categories", &mut session.hidden_categories, &mut warnings);
+        read(&data, "selected", &mut session.selected, &mut warnings);
+        read(&data, "labels_enabled", &mut session.labels_enabled, &mut warnings);
+        read(&data, "labels", &mut session.labels, &mut warnings);
+        read(&data, "hud_visible", &mut session.hud_visible, &mut warnings);
+        read(&data, "widgets", &mut session.widgets, &mut warnings);
+
+        // Hidden categories are only kept if the mode they are loaded with has them.
+        let num_entries = session.mode.legend_entries().len();
+        let count = session.hidden_categories.len();
+        session.hidden_categories.retain(|&category| category < num_entries);
+        if session.hidden_categories.len() < count {
+            warnings.push(format!(
+                "Ignoring hidden legend categories that {} mode does not have",
+                session.mode.name()
+            ));
+        }
+
+        Ok((session, warnings))
+    }
+
+    /// Saves this as the session named `name` in `dir`, replacing any session of that name.
+    /// Returns where it was saved.
+    pub fn save(&self, dir: &Path, name: &str) -> Result<PathBuf> {
+        let path = path_in(dir, name)?;
+        persistence::save_versioned(&path, KIND, VERSION, self)?;
+
+        Ok(path)
+    }
+}
+
+/// Replaces `target` with the item `key` of `data`, or adds a warning to `warnings` if it is
+/// missing or not one.
+fn read<T: DeserializeOwned>(data: &Value, key: &str, target: &mut T, warnings: &mut Vec<String>) {
+    match data.get(key).map(|value| serde_json::from_value(value.clone())) {
+        Some(Ok(value)) => *target = value,
+        Some(Err(e)) => warnings.push(format!("Ignoring unreadable {}: {}", key, e)),
+        None => warnings.push(format!("Ignoring missing {}", key)),
+    }
+}
+
+/// Where named sessions are kept for the settings file at `settings_path`.
+pub fn dir_for(settings_path: &Path) -> PathBuf {
+    settings_path.with_file_name(SESSIONS_DIR_NAME)
+}
+
+/// Fails unless `name` can name a session: it must be non-empty, at most `MAX_NAME_LEN` long, and
+/// only have letters, digits, `-` and `_`, so that it is a file name on every platform.
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        bail!("Session names must be 1 to {} characters long", MAX_NAME_LEN);
+    }
+    if !name.chars().all(is_name_char) {
+        bail!(
+            "Invalid session name {:?}: only letters, digits, - and _ are allowed",
+            name
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether `c` can be part of a session's name.
+pub fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn path_in(dir: &Path, name: &str) -> Result<PathBuf> {
+    check_name(name)?;
+
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+/// The names of the sessions saved in `dir`, sorted. Empty if there is no such directory yet.
+pub fn list(dir: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == ::std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e).chain_err(|| format!("Could not list sessions in {}", dir.display()))
+        }
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "json"))
+        .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+        .filter(|name| check_name(name).is_ok())
+        .collect();
+    names.sort();
+
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use settings::Settings;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir()
+            .join(format!("gaia-demo-test-{}", process::id()))
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    fn fallback() -> NamedSession {
+        let settings = Settings::default();
+
+        NamedSession {
+            camera: Session {
+                latitude: 0.0,
+                longitude: 0.0,
+                height: 1.0,
+                heading: 0.0,
+                routes: vec![],
+                window: None,
+                clocks: vec![],
+            },
+            mode: MapMode::Terrain,
+            hidden_categories: vec![],
+            selected: None,
+            labels_enabled: true,
+            labels: settings.labels,
+            hud_visible: true,
+            widgets: settings.hud.widgets,
+        }
+    }
+
+    fn europe() -> NamedSession {
+        let mut session = fallback();
+        session.camera = Session {
+            latitude: 50.0,
+            longitude: 10.0,
+            height: 0.2,
+            heading: 30.0,
+            routes: vec![],
+            window: None,
+            clocks: vec![],
+        };
+        session.mode = MapMode::Income;
+        session.hidden_categories = vec![1, 4];
+        session.selected = Some("DEU".to_string());
+        session.labels_enabled = false;
+        session.labels.scale *= 2.0;
+        session.hud_visible = false;
+        session.widgets.graticule = true;
+
+        session
+    }
+
+    /// Saves `session`'s data as it would be, with `edit` applied to it first.
+    fn save_edited<F: FnOnce(&mut Value)>(dir: &Path, name: &str, edit: F) {
+        let mut data = serde_json::to_value(europe()).unwrap();
+        edit(&mut data);
+        persistence::save_versioned(&dir.join(format!("{}.json", name)), KIND, VERSION, &data)
+            .unwrap();
+    }
+
+    #[test]
+    fn saved_session_loads_back() {
+        let dir = temp_dir("round-trip");
+        europe().save(&dir, "europe-income-setup").unwrap();
+
+        let (loaded, warnings) = NamedSession::load(&dir, "europe-income-setup", &fallback())
+            .unwrap();
+        assert_eq!(loaded, europe());
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn saving_again_replaces_the_session() {
+        let dir = temp_dir("replace");
+        fallback().save(&dir, "setup").unwrap();
+        europe().save(&dir, "setup").unwrap();
+
+        let (loaded, _) = NamedSession::load(&dir, "setup", &fallback()).unwrap();
+        assert_eq!(loaded, europe());
+    }
+
+    #[test]
+    fn unknown_mode_falls_back() {
+        let dir = temp_dir("unknown-mode");
+        save_edited(&dir, "old", |data| {
+            data["mode"] = Value::from("population");
+            data["hidden_categories"] = Value::from(Vec::<usize>::new());
+        });
+
+        let (loaded, warnings) = NamedSession::load(&dir, "old", &fallback()).unwrap();
+        assert_eq!(loaded.mode, fallback().mode);
+        assert_eq!(loaded.camera, europe().camera);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn missing_items_fall_back() {
+        let dir = temp_dir("missing");
+        save_edited(&dir, "partial", |data| {
+            data.as_object_mut().unwrap().remove("labels");
+        });
+
+        let (loaded, warnings) = NamedSession::load(&dir, "partial", &fallback()).unwrap();
+        assert_eq!(loaded.labels, fallback().labels);
+        assert_eq!(loaded.mode, europe().mode);
+        assert_eq!(warnings, vec!["Ignoring missing labels".to_string()]);
+    }
+
+    #[test]
+    fn categories_the_mode_lacks_are_dropped() {
+        let dir = temp_dir("categories");
+        save_edited(&dir, "categories", |data| {
+            data["hidden_categories"] = Value::from(vec![2, 40]);
+        });
+
+        let (loaded, warnings) = NamedSession::load(&dir, "categories", &fallback()).unwrap();
+        assert_eq!(loaded.hidden_categories, vec![2]);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn missing_session_fails() {
+        let dir = temp_dir("none");
+
+        assert!(NamedSession::load(&dir, "nothing", &fallback()).is_err());
+    }
+
+    #[test]
+    fn sessions_are_listed_by_name() {
+        let dir = temp_dir("list");
+        assert!(list(&dir).unwrap().is_empty());
+
+        europe().save(&dir, "b").unwrap();
+        europe().save(&dir, "a").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        assert_eq!(list(&dir).unwrap(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn names_must_be_file_names() {
+        assert!(check_name("europe-income_setup2").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("../settings").is_err());
+        assert!(check_name("two words").is_err());
+        assert!(check_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+}
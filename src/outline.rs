@@ -0,0 +1,103 @@
+use cgmath::Matrix4;
+use piston_window::{Context, G2d};
+
+use hud::HudStyle;
+use projection;
+
+/// Vertices closer than this many pixels to the last one kept are dropped, since they would not
+/// change how the line looks.
+const MIN_SPACING: f64 = 1.5;
+
+/// How wide outlines are, in points, at a camera height of 1. They get wider closer in.
+const WIDTH: f64 = 1.5;
+
+/// How wide `outline::draw` draws outlines with the camera at `camera_height`, in points.
+pub fn width(camera_height: f32) -> f64 {
+    WIDTH * f64::from(camera_height.max(1e-3)).powf(-0.25)
+}
+
+/// Where `rings` are drawn through `mvp`, as lines of the 2D pass. The rings are in map space,
+/// as the polygons are: x from 0 to 1 west to east, and y from 0 to 1 south to north. The map
+/// repeats horizontally, so each ring is drawn at its copy nearest `look_at`.
+///
+/// A ring is cut where it goes behind the camera, and lines wholly off screen are left out.
+/// Vertices within `MIN_SPACING` pixels of the last one kept are dropped, so that a ring of
+/// thousands of vertices costs little once it is small on screen.
+pub fn project(
+    rings: &[&[(f32, f32)]],
+    mvp: Matrix4<f32>,
+    look_at: [f32; 2],
+    view_size: [f64; 2],
+) -> Vec<Vec<[f64; 2]>> {
+    let mut lines = vec![];
+    for ring in rings {
+        let first = match ring.first() {
+            Some(&(x, _)) => 2.0 * x,
+            None => continue,
+        };
+        let offset = projection::nearest_copy(first, look_at) - first;
+
+        let mut line: Vec<[f64; 2]> = vec![];
+        for (i, &(x, y)) in ring.iter().enumerate() {
+            let point = match projection::to_screen(mvp, [2.0 * x + offset, y], view_size) {
+                Some(point) => point,
+                None => {
+                    finish(&mut lines, &mut line, view_size);
+                    continue;
+                }
+            };
+
+            // The last vertex is kept, so that the ring still closes.
+            let far_enough = line.last().map_or(true, |last| {
+                (point[0] - last[0]).hypot(point[1] - last[1]) >= MIN_SPACING
+            });
+            if far_enough || i == ring.len() - 1 {
+                line.push(point);
+            }
+        }
+        finish(&mut lines, &mut line, view_size);
+    }
+
+    lines
+}
+
+/// Moves `line` into `lines`, if it is long enough to draw and some of it is on screen.
+fn finish(lines: &mut Vec<Vec<[f64; 2]>>, line: &mut Vec<[f64; 2]>, view_size: [f64; 2]) {
+    let line = ::std::mem::take(line);
+    if line.len() < 2 {
+        return;
+    }
+
+    let (mut min, mut max) = ([::std::f64::INFINITY; 2], [::std::f64::NEG_INFINITY; 2]);
+    for point in &line {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    if max[0] >= 0.0 && min[0] <= view_size[0] && max[1] >= 0.0 && min[1] <= view_size[1] {
+        lines.push(line);
+    }
+}
+
+/// Draws `lines` `width` points wide in the theme's text color, over lines a little wider in its
+/// opaque background, so that they stand out against any fill.
+pub fn draw(
+    lines: &[Vec<[f64; 2]>],
+    width: f64,
+    style: HudStyle,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+    let width = scale.len(width);
+
+    for (color, width) in &[(theme.opaque_background(), width + 1.5), (theme.text, width)] {
+        for line in lines {
+            for pair in line.windows(2) {
+                let segment = [pair[0][0], pair[0][1], pair[1][0], pair[1][1]];
+                ::piston_window::line(*color, *width, segment, context.transform, graphics);
+            }
+        }
+    }
+}
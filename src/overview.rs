@@ -0,0 +1,142 @@
+use cgmath::{self, Matrix4, Point3, Vector3};
+use gfx;
+use piston_window::{Context, Graphics, Line, Rectangle, Texture, Transformed};
+
+use hud::{HudScale, HudStyle};
+use render_scale::ScaledTarget;
+use Result;
+
+/// The inset's size at a HUD scale of 1, as wide for its height as the map.
+const SIZE: [f64; 2] = [200.0, 100.0];
+
+/// The level of detail the inset draws the map at.
+pub const LEVEL: u8 = 1;
+
+/// Above this camera height the main view already shows most of the map, so the inset is not
+/// drawn at all.
+pub const MAX_CAMERA_HEIGHT: f32 = 0.6;
+
+/// How high above the map the inset's camera is. It only has to clear the terrain.
+const CAMERA_HEIGHT: f32 = 10.0;
+
+/// The radius of the dot marking what the main camera looks at, at a HUD scale of 1.
+const LOOK_AT_RADIUS: f64 = 2.5;
+
+/// How much room `draw` needs at `scale`.
+pub fn size(scale: HudScale) -> [f64; 2] {
+    [scale.len(SIZE[0]), scale.len(SIZE[1])]
+}
+
+/// The matrix the inset draws the whole map through, from straight above. It is centered on
+/// `center_x`, the X of what the main camera looks at, so that what is being looked at is in the
+/// middle of it, with the map wrapping around either side.
+pub fn matrix(center_x: f32) -> Matrix4<f32> {
+    let view = Matrix4::look_at(
+        Point3::new(center_x, 0.5, CAMERA_HEIGHT),
+        Point3::new(center_x, 0.5, 0.0),
+        Vector3::unit_y(),
+    );
+
+    cgmath::ortho(-1.0, 1.0, -0.5, 0.5, 0.0, 2.0 * CAMERA_HEIGHT) * view
+}
+
+/// Where the world-space `point` is in the inset at `rect`, as `[x, y, width, height]`, when it
+/// is centered on `center_x`.
+pub fn to_inset(rect: [f64; 4], center_x: f32, point: [f32; 2]) -> [f64; 2] {
+    let [x, y, width, height] = rect;
+    // From the left edge, on whichever copy of the map is shown there.
+    let along = (f64::from(point[0] - center_x) + 1.0).rem_euclid(2.0) / 2.0;
+
+    [x + along * width, y + (1.0 - f64::from(point[1])) * height]
+}
+
+/// The point in map space shown at `position` in the inset at `rect`, when it is centered on
+/// `center_x`, or `None` if `position` is outside of it.
+pub fn from_inset(rect: [f64; 4], center_x: f32, position: [f64; 2]) -> Option<[f32; 2]> {
+    let [x, y, width, height] = rect;
+    let along = [(position[0] - x) / width, (position[1] - y) / height];
+    if !(0.0..=1.0).contains(&along[0]) || !(0.0..=1.0).contains(&along[1]) {
+        return None;
+    }
+
+    let world_x = f64::from(center_x) - 1.0 + 2.0 * along[0];
+    Some([(world_x / 2.0).rem_euclid(1.0) as f32, (1.0 - along[1]) as f32])
+}
+
+/// Keeps the offscreen target the inset is drawn to the size the HUD scale calls for.
+pub struct Overview<R: gfx::Resources> {
+    target: Option<([u16; 2], ScaledTarget<R>)>,
+}
+
+impl<R: gfx::Resources> Overview<R> {
+    pub fn new() -> Overview<R> {
+        Overview { target: None }
+    }
+
+    /// The target to draw the inset to at `scale`, recreated if the scale changed.
+    pub fn target<F>(&mut self, factory: &mut F, scale: HudScale) -> Result<&ScaledTarget<R>>
+    where
+        F: gfx::Factory<R>,
+    {
+        let [width, height] = size(scale);
+        let pixels = |n: f64| (n * scale.density).round().max(1.0).min(65535.0) as u16;
+        let size = [pixels(width), pixels(height)];
+
+        if self.target.as_ref().map(|&(old, _)| old) != Some(size) {
+            self.target = None;
+            self.target = Some((size, ScaledTarget::new(factory, size)?));
+        }
+
+        Ok(&self.target.as_ref().unwrap().1)
+    }
+}
+
+impl<R: gfx::Resources> Default for Overview<R> {
+    fn default() -> Overview<R> {
+        Overview::new()
+    }
+}
+
+/// Draws the inset at `rect`, outlining `footprint`, the part of the map the main view shows, and
+/// marking `look_at`, both already in the window's coordinates.
+pub fn draw<R, G>(
+    target: &ScaledTarget<R>,
+    rect: [f64; 4],
+    footprint: &[[f64; 2]],
+    look_at: [f64; 2],
+    style: HudStyle,
+    context: Context,
+    graphics: &mut G,
+) where
+    R: gfx::Resources,
+    G: Graphics<Texture = Texture<R>>,
+{
+    let HudStyle { scale, theme } = style;
+
+    target.draw_at(rect, context, graphics);
+    Rectangle::new_border(theme.opaque_background(), scale.len(1.0)).draw(
+        rect,
+        &context.draw_state,
+        context.transform,
+        graphics,
+    );
+
+    // Footprints that wrap around the edge of the inset are drawn the long way across it, so
+    // lines that jump more than half of it are left out.
+    let line = Line::new(theme.warning, scale.len(1.0) / 2.0);
+    for (i, from) in footprint.iter().enumerate() {
+        let to = footprint[(i + 1) % footprint.len()];
+        if (to[0] - from[0]).abs() < rect[2] / 2.0 {
+            let coords = [from[0], from[1], to[0], to[1]];
+            line.draw(coords, &context.draw_state, context.transform, graphics);
+        }
+    }
+
+    let radius = scale.len(LOOK_AT_RADIUS);
+    ::piston_window::ellipse(
+        theme.warning,
+        [-radius, -radius, 2.0 * radius, 2.0 * radius],
+        context.transform.trans(look_at[0], look_at[1]),
+        graphics,
+    );
+}
@@ -0,0 +1,257 @@
+use std::time::Duration;
+
+use mode_parameters::{self, Parameter};
+use {MapMode, Result};
+
+/// How often, at most, an animated parameter's new value is applied, since each recolors every
+/// country in its mode. A few times as often as this is no smoother to the eye.
+pub const STEP_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long Shift+P takes to sweep the selected parameter across its range.
+pub const SWEEP_DURATION: Duration = Duration::from_secs(10);
+
+/// How an animated parameter moves between its ends. Written in snake case in `--exec`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    Linear,
+    /// Starting and ending slowly.
+    EaseInOut,
+}
+
+impl Curve {
+    /// How far between its ends the parameter is `t` of the way through, from 0 to 1.
+    fn eased(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match *self {
+            Curve::Linear => t,
+            Curve::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// What an animation does once it reaches its end. Written in snake case in `--exec`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repeat {
+    /// Stops, keeping the end value as if it were set with + and -.
+    Once,
+    /// Starts over from the start, until stopped.
+    Loop,
+    /// Goes back to the start and then forth again, until stopped.
+    Bounce,
+}
+
+/// An animation of one of a map mode's parameters, from one value to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParameterAnimation {
+    pub mode: MapMode,
+    pub parameter: Parameter,
+    pub from: f32,
+    pub to: f32,
+    pub duration: Duration,
+    pub curve: Curve,
+    pub repeat: Repeat,
+}
+
+impl ParameterAnimation {
+    /// Animates `mode`'s parameter `name` from `from` to `to`, each kept within its range, over
+    /// `duration`, easing in and out once. Fails if `mode` has no such parameter.
+    pub fn new(
+        mode: MapMode,
+        name: &str,
+        from: f32,
+        to: f32,
+        duration: Duration,
+    ) -> Result<ParameterAnimation> {
+        let parameter = match mode_parameters::find(mode, name) {
+            Some(&parameter) => parameter,
+            None => bail!("{} mode has no parameter {:?}", mode.name(), name),
+        };
+        let clamp = |value: f32| value.max(parameter.range.0).min(parameter.range.1);
+
+        Ok(ParameterAnimation {
+            mode,
+            parameter,
+            from: clamp(from),
+            to: clamp(to),
+            duration,
+            curve: Curve::EaseInOut,
+            repeat: Repeat::Once,
+        })
+    }
+
+    /// Sweeps `parameter` of `mode` back and forth across its whole range, as Shift+P does.
+    pub fn sweep(mode: MapMode, parameter: Parameter) -> ParameterAnimation {
+        ParameterAnimation {
+            mode,
+            parameter,
+            from: parameter.range.0,
+            to: parameter.range.1,
+            duration: SWEEP_DURATION,
+            curve: Curve::EaseInOut,
+            repeat: Repeat::Bounce,
+        }
+    }
+
+    /// The parameter's value `elapsed` after the animation started.
+    pub fn value_at(&self, elapsed: Duration) -> f32 {
+        let duration = self.duration.as_secs_f64();
+        let runs = if duration > 0.0 { elapsed.as_secs_f64() / duration } else { 1.0 };
+        let t = match self.repeat {
+            Repeat::Once => runs.min(1.0),
+            Repeat::Loop if runs.fract() == 0.0 && runs >= 1.0 => 1.0,
+            Repeat::Loop => runs.fract(),
+            Repeat::Bounce => 1.0 - (runs % 2.0 - 1.0).abs(),
+        };
+
+        self.from + (self.to - self.from) * self.curve.eased(t as f32)
+    }
+
+    /// Whether the animation has reached its end `elapsed` after it started. Looping ones never
+    /// do.
+    pub fn is_finished(&self, elapsed: Duration) -> bool {
+        self.repeat == Repeat::Once && elapsed >= self.duration
+    }
+
+    /// Such as "Income opacity from 0 to 255 over 10s".
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} from {} to {} over {}s",
+            self.mode.name(),
+            self.parameter.label.to_lowercase(),
+            self.from,
+            self.to,
+            self.duration.as_secs_f64()
+        )
+    }
+}
+
+/// An animation under way, started at some time by the animation clock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunningAnimation {
+    pub animation: ParameterAnimation,
+    started: Duration,
+    /// When the value was last applied, by the animation clock, and what it was.
+    applied: Option<(Duration, f32)>,
+}
+
+impl RunningAnimation {
+    pub fn new(animation: ParameterAnimation, now: Duration) -> RunningAnimation {
+        RunningAnimation {
+            animation,
+            started: now,
+            applied: None,
+        }
+    }
+
+    /// The value to apply at `now`, by the animation clock, or `None` if the last one applied
+    /// is still shown: it has stayed the same, or was applied less than `STEP_INTERVAL` ago.
+    /// The end value is always applied.
+    pub fn step(&mut self, now: Duration) -> Option<f32> {
+        let elapsed = now.saturating_sub(self.started);
+        let value = self.animation.value_at(elapsed);
+        if let Some((applied_at, applied)) = self.applied {
+            let due = now.saturating_sub(applied_at) >= STEP_INTERVAL || self.is_finished(now);
+            if applied == value || !due {
+                return None;
+            }
+        }
+
+        self.applied = Some((now, value));
+        Some(value)
+    }
+
+    /// The value last applied, if any has been.
+    pub fn applied(&self) -> Option<f32> {
+        self.applied.map(|(_, value)| value)
+    }
+
+    pub fn is_finished(&self, now: Duration) -> bool {
+        self.animation.is_finished(now.saturating_sub(self.started))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    fn opacity(from: f32, to: f32) -> ParameterAnimation {
+        ParameterAnimation {
+            curve: Curve::Linear,
+            ..ParameterAnimation::new(MapMode::Income, "opacity", from, to, secs(10.0)).unwrap()
+        }
+    }
+
+    #[test]
+    fn values_run_from_the_start_to_the_end() {
+        let animation = opacity(0.0, 200.0);
+        assert_eq!(animation.value_at(secs(0.0)), 0.0);
+        assert_eq!(animation.value_at(secs(2.5)), 50.0);
+        assert_eq!(animation.value_at(secs(10.0)), 200.0);
+        assert_eq!(animation.value_at(secs(30.0)), 200.0);
+        assert!(!animation.is_finished(secs(9.9)));
+        assert!(animation.is_finished(secs(10.0)));
+
+        let eased = ParameterAnimation {
+            curve: Curve::EaseInOut,
+            ..animation
+        };
+        assert!(eased.value_at(secs(1.0)) < 20.0);
+        assert_eq!(eased.value_at(secs(5.0)), 100.0);
+        assert!(eased.value_at(secs(9.0)) > 180.0);
+    }
+
+    #[test]
+    fn looping_animations_repeat_until_stopped() {
+        let looped = ParameterAnimation {
+            repeat: Repeat::Loop,
+            ..opacity(0.0, 200.0)
+        };
+        assert_eq!(looped.value_at(secs(12.5)), 50.0);
+        assert_eq!(looped.value_at(secs(20.0)), 200.0);
+        assert!(!looped.is_finished(secs(100.0)));
+
+        let bounced = ParameterAnimation {
+            repeat: Repeat::Bounce,
+            ..looped
+        };
+        assert_eq!(bounced.value_at(secs(10.0)), 200.0);
+        assert_eq!(bounced.value_at(secs(12.5)), 150.0);
+        assert_eq!(bounced.value_at(secs(20.0)), 0.0);
+        assert_eq!(bounced.value_at(secs(22.5)), 50.0);
+    }
+
+    #[test]
+    fn only_registered_parameters_are_animated_within_their_range() {
+        assert!(ParameterAnimation::new(MapMode::Terrain, "opacity", 0.0, 1.0, secs(1.0)).is_err());
+        assert!(ParameterAnimation::new(MapMode::Income, "hue", 0.0, 1.0, secs(1.0)).is_err());
+
+        let animation = opacity(-100.0, 1000.0);
+        assert_eq!((animation.from, animation.to), (0.0, 255.0));
+    }
+
+    #[test]
+    fn steps_are_applied_at_most_every_interval() {
+        let animation = ParameterAnimation {
+            duration: secs(8.0),
+            ..opacity(0.0, 200.0)
+        };
+        let mut running = RunningAnimation::new(animation, secs(100.0));
+        assert_eq!(running.step(secs(100.0)), Some(0.0));
+        // A frame later, the colors are left as they are...
+        assert_eq!(running.step(secs(100.016)), None);
+        assert_eq!(running.applied(), Some(0.0));
+        // ...until the interval is up.
+        assert_eq!(running.step(secs(100.5)), Some(12.5));
+
+        // The end value is applied however soon it comes, and only once.
+        assert_eq!(running.step(secs(108.0)), Some(200.0));
+        assert!(running.is_finished(secs(108.0)));
+        assert_eq!(running.step(secs(108.01)), None);
+    }
+}
@@ -0,0 +1,323 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusttype::{Font, FontCollection};
+use serde_json::{self, Value};
+
+use {Result, ResultExt};
+
+/// Finds the assets directory `dir`. The renderer loads tiles from `assets` in the working
+/// directory, so the directory must have that name, and the demo changes to its parent.
+pub fn find_assets_dir(dir: &Path) -> Result<PathBuf> {
+    find_assets_dir_from(dir, env::current_dir().ok())
+}
+
+/// Finds the assets directory `dir` as `find_assets_dir` does, but with relative paths looked for
+/// in `start_dir` instead of the working directory, which the demo has since changed.
+pub fn find_assets_dir_in(dir: &Path, start_dir: &Path) -> Result<PathBuf> {
+    find_assets_dir_from(dir, Some(start_dir.to_path_buf()))
+}
+
+fn find_assets_dir_from(dir: &Path, base: Option<PathBuf>) -> Result<PathBuf> {
+    if dir.file_name().map_or(true, |name| name != "assets") {
+        bail!(
+            "Invalid assets directory {}: the renderer loads tiles from `assets`, so the \
+             directory must have that name",
+            dir.display()
+        );
+    }
+
+    let tried = candidates_from(dir, base, None);
+    match tried.iter().find(|path| path.is_dir()) {
+        Some(found) => Ok(found.clone()),
+        None => bail!(
+            "Could not find the assets directory {} (tried {}). Hint: start the demo from the \
+             directory containing `assets`, or give its path with --assets-dir",
+            dir.display(),
+            list(&tried)
+        ),
+    }
+}
+
+/// Checks that what the renderer loads at startup, and the coarsest tile it always draws, have
+/// been generated in `assets_dir`, so that a missing file is named instead of surfacing as a
+/// renderer error.
+pub fn check_assets(assets_dir: &Path) -> Result<()> {
+    let generated = assets_dir.join("generated");
+    let required = [
+        generated.join("features.json"),
+        generated.join("tiles").join("0_0_0.jpg"),
+        generated.join("tiles").join("0_0_0.gray"),
+        generated.join("tiles").join("0_0_0.json"),
+    ];
+
+    match required.iter().find(|path| !path.is_file()) {
+        Some(missing) => bail!(
+            "Missing {}: the assets in {} have not been generated. Hint: building the demo \
+             generates them, once the source data is in place",
+            missing.display(),
+            assets_dir.display()
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Reads every tile's metadata in `assets_dir`, checking that it parses and that the tile's
+/// imagery and elevation are beside it. Returns how many tiles there are. The renderer only
+/// reads tiles as they come into view, so a broken one would otherwise only show up there.
+pub fn check_tiles(assets_dir: &Path) -> Result<usize> {
+    let tiles = assets_dir.join("generated").join("tiles");
+    let entries =
+        fs::read_dir(&tiles).chain_err(|| format!("Could not list tiles in {}", tiles.display()))?;
+
+    let mut count = 0;
+    for entry in entries {
+        let path = entry
+            .chain_err(|| format!("Could not list tiles in {}", tiles.display()))?
+            .path();
+        if path.extension().map_or(true, |extension| extension != "json") {
+            continue;
+        }
+
+        let text = fs::read_to_string(&path)
+            .chain_err(|| format!("Could not read tile {}", path.display()))?;
+        serde_json::from_str::<Value>(&text)
+            .chain_err(|| format!("Could not parse tile {}", path.display()))?;
+        for extension in &["jpg", "gray"] {
+            let part = path.with_extension(extension);
+            if !part.is_file() {
+                bail!("Missing {}, beside {}", part.display(), path.display());
+            }
+        }
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Reads the font at `path`, which `find_font` found.
+pub fn load_font(path: &Path) -> Result<Font<'static>> {
+    let bytes = fs::read(path).chain_err(|| format!("Could not read font {}", path.display()))?;
+
+    // Only the first font of a collection, such as a .ttc, is used.
+    match FontCollection::from_bytes(bytes).font_at(0) {
+        Some(font) => Ok(font),
+        None => bail!("Could not load font {}: not a TrueType or OpenType font", path.display()),
+    }
+}
+
+/// Finds the font `font`, falling back to a common system font if it cannot be found. Relative
+/// paths are also looked for in `assets_root`, where the default font is.
+pub fn find_font(font: &Path, assets_root: Option<&Path>) -> Result<PathBuf> {
+    let tried = candidates(font, assets_root);
+    if let Some(found) = tried.iter().find(|path| path.is_file()) {
+        return Ok(found.clone());
+    }
+
+    let fallbacks = system_fonts();
+    match fallbacks.iter().find(|path| path.is_file()) {
+        Some(found) => {
+            warn!(
+                "Could not find font {} (tried {}), using {} instead",
+                font.display(),
+                list(&tried),
+                found.display()
+            );
+            Ok(found.clone())
+        }
+        None => bail!(
+            "Could not find font {} (tried {}, then the system fonts {})",
+            font.display(),
+            list(&tried),
+            list(&fallbacks)
+        ),
+    }
+}
+
+/// Finds the font `font` as `find_font` does, but without falling back to a system font, which
+/// would be no more likely to have the glyphs it is wanted for.
+pub fn find_fallback_font(font: &Path, assets_root: Option<&Path>) -> Result<PathBuf> {
+    let tried = candidates(font, assets_root);
+    match tried.iter().find(|path| path.is_file()) {
+        Some(found) => Ok(found.clone()),
+        None => bail!("Could not find font {} (tried {})", font.display(), list(&tried)),
+    }
+}
+
+/// Where `path` might be: just `path` if it is absolute, or else relative to the working
+/// directory, then to `extra_root`, then to the executable's directory.
+fn candidates(path: &Path, extra_root: Option<&Path>) -> Vec<PathBuf> {
+    candidates_from(path, env::current_dir().ok(), extra_root)
+}
+
+/// Where `path` might be, as `candidates` has it, but relative to `base` instead of the working
+/// directory.
+fn candidates_from(path: &Path, base: Option<PathBuf>, extra_root: Option<&Path>) -> Vec<PathBuf> {
+    if path.is_absolute() {
+        return vec![path.to_path_buf()];
+    }
+
+    let exe_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    let roots = base
+        .into_iter()
+        .chain(extra_root.map(Path::to_path_buf))
+        .chain(exe_dir);
+    for root in roots {
+        let candidate = root.join(path);
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+
+    candidates
+}
+
+/// Fonts that are usually installed, in the order they are tried.
+fn system_fonts() -> Vec<PathBuf> {
+    let fonts: Vec<String> = if cfg!(target_os = "windows") {
+        let windows = env::var("WINDIR").unwrap_or_else(|_| "C:\\Windows".to_string());
+        vec![
+            format!("{}\\Fonts\\segoeui.ttf", windows),
+            format!("{}\\Fonts\\arial.ttf", windows),
+        ]
+    } else if cfg!(target_os = "macos") {
+        vec![
+            "/System/Library/Fonts/Supplemental/Arial.ttf".to_string(),
+            "/Library/Fonts/Arial.ttf".to_string(),
+        ]
+    } else {
+        vec![
+            "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf".to_string(),
+            "/usr/share/fonts/TTF/DejaVuSans.ttf".to_string(),
+            "/usr/share/fonts/dejavu/DejaVuSans.ttf".to_string(),
+            "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf".to_string(),
+        ]
+    };
+
+    fonts.into_iter().map(PathBuf::from).collect()
+}
+
+fn list(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::process;
+
+    /// An empty directory of its own for each test.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gaia-demo-paths-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn missing_assets_dir_is_named_with_a_hint() {
+        let assets_dir = empty_dir("missing").join("assets");
+        let error = find_assets_dir(&assets_dir).unwrap_err().to_string();
+
+        assert!(error.contains(&assets_dir.display().to_string()), "{}", error);
+        assert!(error.contains("--assets-dir"), "{}", error);
+    }
+
+    #[test]
+    fn relative_assets_dirs_are_found_from_where_the_demo_started() {
+        let start_dir = empty_dir("start");
+        fs::create_dir_all(start_dir.join("sets").join("assets")).unwrap();
+
+        let dir = Path::new("sets").join("assets");
+        assert_eq!(find_assets_dir_in(&dir, &start_dir).unwrap(), start_dir.join(&dir));
+        assert!(find_assets_dir_in(&dir, &empty_dir("elsewhere")).is_err());
+    }
+
+    #[test]
+    fn misnamed_assets_dir_is_named() {
+        let dir = empty_dir("misnamed");
+        let error = find_assets_dir(&dir).unwrap_err().to_string();
+
+        assert!(error.contains(&dir.display().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn ungenerated_assets_name_the_missing_file() {
+        let assets_dir = empty_dir("ungenerated").join("assets");
+        fs::create_dir_all(&assets_dir).unwrap();
+        let error = check_assets(&assets_dir).unwrap_err().to_string();
+
+        let features = assets_dir.join("generated").join("features.json");
+        assert!(error.contains(&features.display().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn missing_tiles_are_named() {
+        let assets_dir = empty_dir("no-tiles").join("assets");
+        let generated = assets_dir.join("generated");
+        fs::create_dir_all(generated.join("tiles")).unwrap();
+        fs::write(generated.join("features.json"), "{}").unwrap();
+        fs::write(generated.join("tiles").join("0_0_0.jpg"), "").unwrap();
+        let error = check_assets(&assets_dir).unwrap_err().to_string();
+
+        let tile = generated.join("tiles").join("0_0_0.gray");
+        assert!(error.contains(&tile.display().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn relative_paths_are_tried_from_each_root_in_order_then_the_executable() {
+        let (base, extra) = (empty_dir("base"), empty_dir("extra"));
+        let path = Path::new("fonts").join("font.ttf");
+        let exe_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+
+        let tried = candidates_from(&path, Some(base.clone()), Some(&extra));
+        assert_eq!(tried, [base.join(&path), extra.join(&path), exe_dir.join(&path)]);
+
+        // A root given twice is only tried once.
+        let tried = candidates_from(&path, Some(base.clone()), Some(&base));
+        assert_eq!(tried, [base.join(&path), exe_dir.join(&path)]);
+
+        let absolute = base.join(&path);
+        assert_eq!(candidates_from(&absolute, None, Some(&extra)), [absolute.clone()]);
+    }
+
+    #[test]
+    fn relative_fonts_are_found_beside_the_assets() {
+        let assets_root = empty_dir("font-root");
+        fs::create_dir_all(assets_root.join("fonts")).unwrap();
+        fs::write(assets_root.join("fonts").join("gaia-test.ttf"), "").unwrap();
+
+        let font = Path::new("fonts").join("gaia-test.ttf");
+        let found = assets_root.join(&font);
+        assert_eq!(find_font(&font, Some(&assets_root)).unwrap(), found);
+        assert_eq!(find_fallback_font(&font, Some(&assets_root)).unwrap(), found);
+
+        // A fallback font is not swapped for a system font.
+        let missing = Path::new("fonts").join("gaia-missing.ttf");
+        assert!(find_fallback_font(&missing, Some(&assets_root)).is_err());
+    }
+
+    #[test]
+    fn missing_absolute_font_is_named() {
+        let font = empty_dir("font").join("missing.ttf");
+
+        // With no system font to fall back to, the error names the font; otherwise the fallback
+        // is used instead.
+        match find_font(&font, None) {
+            Ok(found) => assert!(found != font),
+            Err(e) => assert!(e.to_string().contains(&font.display().to_string()), "{}", e),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use settings::Settings;
+
+/// How long a change on trial lasts before it is reverted, unless it is confirmed.
+pub const TRIAL_TIME: Duration = Duration::from_secs(10);
+
+struct PendingChange {
+    name: &'static str,
+    /// Puts the setting back as it was before the change.
+    revert: Box<dyn Fn(&mut Settings)>,
+    /// When the change is reverted, by the animation clock.
+    deadline: Duration,
+}
+
+/// Changes to settings that are being tried out, as a monitor tries a new resolution: each takes
+/// effect at once, but is reverted once `TRIAL_TIME` is up unless it is confirmed first. Times
+/// are by the animation clock, so a frozen clock holds the countdowns.
+#[derive(Default)]
+pub struct PendingChanges {
+    changes: Vec<PendingChange>,
+}
+
+impl PendingChanges {
+    pub fn new() -> PendingChanges {
+        PendingChanges::default()
+    }
+
+    /// Starts the trial of a change to the setting `name`, which `revert` undoes, at `now`.
+    /// Changing a setting already on trial again restarts its countdown, but it is still
+    /// reverted to what it was before the first change.
+    pub fn try_change<F>(&mut self, name: &'static str, now: Duration, revert: F)
+    where
+        F: Fn(&mut Settings) + 'static,
+    {
+        let deadline = now + TRIAL_TIME;
+        match self.changes.iter_mut().find(|change| change.name == name) {
+            Some(change) => change.deadline = deadline,
+            None => self.changes.push(PendingChange {
+                name,
+                revert: Box::new(revert),
+                deadline,
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// How long is left at `now` until the change to `name` is reverted, if it is on trial.
+    pub fn remaining(&self, name: &str, now: Duration) -> Option<Duration> {
+        self.changes
+            .iter()
+            .find(|change| change.name == name)
+            .map(|change| change.deadline.saturating_sub(now))
+    }
+
+    /// Keeps every change on trial.
+    pub fn confirm(&mut self) {
+        self.changes.clear();
+    }
+
+    /// Reverts every change on trial in `settings`. Returns the settings that were reverted.
+    pub fn revert_all(&mut self, settings: &mut Settings) -> Vec<&'static str> {
+        self.revert_where(settings, |_| true)
+    }
+
+    /// Reverts the changes whose time was up at `now` in `settings`. Returns the settings that
+    /// were reverted.
+    pub fn revert_expired(&mut self, settings: &mut Settings, now: Duration) -> Vec<&'static str> {
+        self.revert_where(settings, |change| change.deadline <= now)
+    }
+
+    fn revert_where<P>(&mut self, settings: &mut Settings, expired: P) -> Vec<&'static str>
+    where
+        P: Fn(&PendingChange) -> bool,
+    {
+        let (reverted, kept) = self.changes.drain(..).partition(|change| expired(change));
+        self.changes = kept;
+
+        // The last changed is reverted first, so that reverting leaves things as they were.
+        let reverted: Vec<PendingChange> = reverted;
+        reverted
+            .iter()
+            .rev()
+            .map(|change| {
+                (change.revert)(settings);
+                change.name
+            })
+            .collect()
+    }
+}
+
+impl ::std::fmt::Debug for PendingChanges {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let names: Vec<_> = self.changes.iter().map(|change| change.name).collect();
+        f.debug_struct("PendingChanges").field("changes", &names).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn changes_are_reverted_once_their_time_is_up() {
+        let mut settings = Settings::default();
+        let mut pending = PendingChanges::new();
+
+        let scale = settings.hud.scale;
+        settings.hud.scale = 2.0;
+        pending.try_change("HUD scale", secs(0), move |settings| settings.hud.scale = scale);
+        assert_eq!(pending.remaining("HUD scale", secs(4)), Some(secs(6)));
+        assert_eq!(pending.remaining("vsync", secs(4)), None);
+
+        assert!(pending.revert_expired(&mut settings, secs(9)).is_empty());
+        assert_eq!(pending.revert_expired(&mut settings, secs(10)), ["HUD scale"]);
+        assert_eq!(settings.hud.scale, scale);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn changing_again_restarts_the_countdown_but_reverts_to_the_first_value() {
+        let mut settings = Settings::default();
+        let mut pending = PendingChanges::new();
+
+        let scale = settings.hud.scale;
+        settings.hud.scale = 2.0;
+        pending.try_change("HUD scale", secs(0), move |settings| settings.hud.scale = scale);
+        settings.hud.scale = 3.0;
+        pending.try_change("HUD scale", secs(5), |settings| settings.hud.scale = 2.0);
+
+        assert!(pending.revert_expired(&mut settings, secs(10)).is_empty());
+        assert_eq!(pending.revert_expired(&mut settings, secs(15)), ["HUD scale"]);
+        assert_eq!(settings.hud.scale, scale);
+    }
+
+    #[test]
+    fn the_last_change_is_reverted_first_and_confirmed_changes_are_kept() {
+        let mut settings = Settings::default();
+        let mut pending = PendingChanges::new();
+
+        let vsync = settings.window.vsync;
+        settings.window.vsync = !vsync;
+        pending.try_change("vsync", secs(0), move |settings| settings.window.vsync = vsync);
+        pending.try_change("HUD scale", secs(1), |_| {});
+        assert_eq!(pending.revert_all(&mut settings), ["HUD scale", "vsync"]);
+        assert_eq!(settings.window.vsync, vsync);
+
+        settings.window.vsync = !vsync;
+        pending.try_change("vsync", secs(0), move |settings| settings.window.vsync = vsync);
+        pending.confirm();
+        assert!(pending.revert_expired(&mut settings, secs(60)).is_empty());
+        assert_eq!(settings.window.vsync, !vsync);
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::{HashMap, HashSet};
+
+use class_breaks;
+use color_script;
+use formatting;
+use legend::LegendEntry;
+use props::CountryProps;
+use settings::FormattingSettings;
+use COLORBLIND_INCOME_COLORS;
+
+/// How many bands of percentiles the legend lists, each as wide as the others.
+pub const BANDS: usize = 5;
+
+/// The property population rank mode ranks countries by, which its class breaks split.
+pub const PROPERTY: &str = "POP_EST";
+
+const BAND_LABELS: [&str; BANDS] = [
+    "0-20th percentile",
+    "20-40th percentile",
+    "40-60th percentile",
+    "60-80th percentile",
+    "80-100th percentile",
+];
+
+/// How opaque countries are drawn, as in the modes with categories.
+const OPACITY: u8 = 100;
+
+/// Where each country with data ranks among the others, from 0 for the lowest value to 100 for the
+/// highest, so that skewed values are spread evenly over the colors. Countries with the same value
+/// share a rank.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Percentiles {
+    /// By `feature_id`.
+    ranks: HashMap<String, f64>,
+    /// The least value in each band but the first, which splits it from the band below.
+    breaks: Vec<f64>,
+    /// The lowest and highest values.
+    range: (f64, f64),
+}
+
+impl Percentiles {
+    /// Ranks `values`, each a country's `feature_id` and its value, if it has one. A country
+    /// given more than once, as countries with several polygons are, counts once, with its
+    /// first value.
+    pub fn new<'a, I>(values: I) -> Percentiles
+    where
+        I: IntoIterator<Item = (&'a str, Option<f64>)>,
+    {
+        let mut seen = HashSet::new();
+        let mut known: Vec<(&str, f64)> = vec![];
+        for (id, value) in values {
+            if !seen.insert(id) {
+                continue;
+            }
+            if let Some(value) = value.filter(|value| value.is_finite()) {
+                known.push((id, value));
+            }
+        }
+        known.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        // Each rank is how many countries have a lower value, so that ties share the lowest.
+        // Bands no country reaches start beyond every value.
+        let mut ranks = HashMap::new();
+        let mut breaks = vec![::std::f64::INFINITY; BANDS - 1];
+        let top = known.len().saturating_sub(1) as f64;
+        let mut below = 0;
+        for (i, &(id, value)) in known.iter().enumerate() {
+            if i > 0 && value > known[i - 1].1 {
+                below = i;
+            }
+            let rank = if top > 0.0 { 100.0 * below as f64 / top } else { 100.0 };
+            ranks.insert(id.to_string(), rank);
+
+            for (limit, index) in breaks.iter_mut().zip(1..) {
+                if limit.is_infinite() && band(rank) >= index {
+                    *limit = value;
+                }
+            }
+        }
+
+        let range = match (known.first(), known.last()) {
+            (Some(&(_, lowest)), Some(&(_, highest))) => (lowest, highest),
+            _ => (0.0, 0.0),
+        };
+        Percentiles {
+            ranks,
+            breaks,
+            range,
+        }
+    }
+
+    /// Ranks `countries` by their estimated population.
+    pub fn of_population<'a, I>(countries: I) -> Percentiles
+    where
+        I: IntoIterator<Item = &'a CountryProps>,
+    {
+        let values = countries.into_iter().map(|country| (country.id.as_str(), country.pop_est));
+        Percentiles::new(values)
+    }
+
+    /// The percentile of the country `id`, or `None` if it has no value.
+    pub fn get(&self, id: &str) -> Option<f64> {
+        self.ranks.get(id).cloned()
+    }
+
+    /// How many countries have a value.
+    pub fn count(&self) -> usize {
+        self.ranks.len()
+    }
+
+    /// The values that split the bands, as the breaks between classes: a country is in the band
+    /// of `class_breaks::class` under them.
+    pub fn quantile_breaks(&self) -> &[f64] {
+        &self.breaks
+    }
+
+    /// The lowest and highest values, which class breaks are kept between.
+    pub fn range(&self) -> (f64, f64) {
+        self.range
+    }
+}
+
+/// Which of the legend's bands `percentile` is in.
+pub fn band(percentile: f64) -> usize {
+    ((percentile / 100.0 * BANDS as f64) as usize).min(BANDS - 1)
+}
+
+/// The color countries at `percentile` are drawn in: from light for the lowest to dark for the
+/// highest, along the colorblind-safe ramp.
+pub fn color(percentile: f64) -> [u8; 4] {
+    let [r, g, b, _] = color_script::ramp_color(&COLORBLIND_INCOME_COLORS, percentile / 100.0);
+    [r, g, b, OPACITY]
+}
+
+/// The color of the middle of `band`, which countries in it are drawn in when the class breaks
+/// are edited.
+pub fn band_color(band: usize) -> [u8; 4] {
+    color((band as f64 + 0.5) * 100.0 / BANDS as f64)
+}
+
+/// The bands of percentiles, in the color of the middle of each.
+pub fn legend_entries() -> Vec<LegendEntry> {
+    BAND_LABELS
+        .iter()
+        .enumerate()
+        .map(|(band, &label)| LegendEntry {
+            label: label.to_string(),
+            color: band_color(band),
+        })
+        .collect()
+}
+
+/// The classes under edited `breaks`, labeled with the values that split them.
+pub fn break_legend_entries(breaks: &[f64], format: FormattingSettings) -> Vec<LegendEntry> {
+    class_breaks::labels(breaks, format)
+        .into_iter()
+        .enumerate()
+        .map(|(band, label)| LegendEntry {
+            label,
+            color: band_color(band),
+        })
+        .collect()
+}
+
+/// `value` and where it ranks among the `count` countries called `noun` that are ranked, as in
+/// "67M, 73rd percentile of 194 countries", or "67M, not one of the 193 UN members" if it is not
+/// ranked among them.
+pub fn describe(
+    value: f64,
+    percentile: Option<f64>,
+    count: usize,
+    noun: &str,
+    format: FormattingSettings,
+) -> String {
+    let value = formatting::count(value, format);
+    match percentile {
+        Some(percentile) => format!(
+            "{}, {} percentile of {} {}",
+            value,
+            ordinal(percentile.round() as u32),
+            count,
+            noun
+        ),
+        None => format!("{}, not one of the {} {}", value, count, noun),
+    }
+}
+
+/// `n` as in "1st", "22nd" or "13th".
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_run_from_the_lowest_to_the_highest() {
+        let percentiles = Percentiles::new(vec![
+            ("A", Some(30.0)),
+            ("B", Some(10.0)),
+            ("C", Some(20.0)),
+            ("D", Some(1e9)),
+            ("E", Some(40.0)),
+        ]);
+
+        assert_eq!(percentiles.count(), 5);
+        assert_eq!(percentiles.get("B"), Some(0.0));
+        assert_eq!(percentiles.get("C"), Some(25.0));
+        assert_eq!(percentiles.get("A"), Some(50.0));
+        assert_eq!(percentiles.get("E"), Some(75.0));
+        assert_eq!(percentiles.get("D"), Some(100.0));
+    }
+
+    #[test]
+    fn ties_share_a_rank() {
+        let percentiles = Percentiles::new(vec![
+            ("A", Some(5.0)),
+            ("B", Some(7.0)),
+            ("C", Some(5.0)),
+            ("D", Some(9.0)),
+            ("E", Some(7.0)),
+        ]);
+
+        assert_eq!(percentiles.get("A"), Some(0.0));
+        assert_eq!(percentiles.get("C"), Some(0.0));
+        assert_eq!(percentiles.get("B"), Some(50.0));
+        assert_eq!(percentiles.get("E"), Some(50.0));
+        assert_eq!(percentiles.get("D"), Some(100.0));
+
+        let all_tied = Percentiles::new(vec![("A", Some(1.0)), ("B", Some(1.0))]);
+        assert_eq!(all_tied.get("A"), Some(0.0));
+        assert_eq!(all_tied.get("B"), Some(0.0));
+    }
+
+    #[test]
+    fn countries_without_data_are_left_out() {
+        let percentiles = Percentiles::new(vec![
+            ("A", Some(1.0)),
+            ("B", None),
+            ("C", Some(::std::f64::NAN)),
+            ("D", Some(3.0)),
+            ("E", Some(2.0)),
+        ]);
+
+        assert_eq!(percentiles.count(), 3);
+        assert_eq!(percentiles.get("B"), None);
+        assert_eq!(percentiles.get("C"), None);
+        assert_eq!(percentiles.get("Z"), None);
+        assert_eq!(percentiles.get("E"), Some(50.0));
+        assert_eq!(percentiles.get("D"), Some(100.0));
+
+        assert_eq!(Percentiles::new(vec![("A", None)]).count(), 0);
+        assert_eq!(Percentiles::new(vec![("A", Some(4.0))]).get("A"), Some(100.0));
+    }
+
+    #[test]
+    fn a_country_given_twice_counts_once() {
+        let percentiles =
+            Percentiles::new(vec![("A", Some(1.0)), ("B", Some(2.0)), ("A", Some(3.0))]);
+
+        assert_eq!(percentiles.count(), 2);
+        assert_eq!(percentiles.get("A"), Some(0.0));
+        assert_eq!(percentiles.get("B"), Some(100.0));
+    }
+
+    #[test]
+    fn bands_cover_every_percentile() {
+        assert_eq!(band(0.0), 0);
+        assert_eq!(band(19.9), 0);
+        assert_eq!(band(20.0), 1);
+        assert_eq!(band(99.0), BANDS - 1);
+        assert_eq!(band(100.0), BANDS - 1);
+        assert_eq!(legend_entries().len(), BANDS);
+    }
+
+    #[test]
+    fn ordinals_have_their_suffixes() {
+        let ordinals: Vec<_> = [0, 1, 2, 3, 4, 11, 12, 13, 21, 22, 73, 100, 101, 111]
+            .iter()
+            .map(|&n| ordinal(n))
+            .collect();
+        assert_eq!(
+            ordinals,
+            [
+                "0th", "1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "73rd",
+                "100th", "101st", "111th",
+            ]
+        );
+    }
+}
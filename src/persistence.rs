@@ -0,0 +1,194 @@
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use {Result, ResultExt};
+
+/// Replaces `path` with `contents` so that it is never left half-written: the contents go to a
+/// temporary file beside it, which is flushed to disk and then renamed over it.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .chain_err(|| format!("Could not create directory {}", dir.display()))?;
+    }
+
+    let temp_path = temp_path(path);
+    let written = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if let Err(e) = written {
+        // The temporary file is useless now, and there is nothing to do if it cannot go.
+        let _ = fs::remove_file(&temp_path);
+        return Err(e).chain_err(|| format!("Could not write {}", path.display()));
+    }
+
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+
+    path.with_file_name(name)
+}
+
+/// How a versioned file is laid out: a header saying what it holds, around the data itself.
+#[derive(Debug, Serialize, Deserialize)]
+struct Versioned<T> {
+    kind: String,
+    version: u32,
+    data: T,
+}
+
+/// Writes `data` to `path` as JSON, atomically, under a header naming its `kind` and format
+/// `version`.
+pub fn save_versioned<T: Serialize>(path: &Path, kind: &str, version: u32, data: &T) -> Result<()> {
+    let versioned = Versioned {
+        kind: kind.to_string(),
+        version,
+        data,
+    };
+    let contents = serde_json::to_vec_pretty(&versioned)
+        .chain_err(|| format!("Could not serialize {}", kind))?;
+
+    write_atomically(path, &contents)
+}
+
+/// Reads what `save_versioned` wrote with `kind` and `version`. `None` if the file does not exist,
+/// and also, with a warning, if it cannot be read, is corrupted, or is of another kind or version,
+/// so that a bad file loses what was in it instead of stopping the demo from starting.
+pub fn load_versioned<T: DeserializeOwned>(path: &Path, kind: &str, version: u32) -> Option<T> {
+    let mut contents = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut contents)) {
+        Ok(_) => {}
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Ignoring {} file {}: {}", kind, path.display(), e);
+            return None;
+        }
+    }
+
+    // The header is checked before the data, so that a file from another version is reported as
+    // such rather than as corrupted.
+    let header = match serde_json::from_str::<Versioned<Value>>(&contents) {
+        Ok(header) => header,
+        Err(e) => {
+            warn!("Ignoring corrupted {} file {}: {}", kind, path.display(), e);
+            return None;
+        }
+    };
+
+    if header.kind != kind {
+        warn!(
+            "Ignoring {} file {}: it holds `{}` instead",
+            kind,
+            path.display(),
+            header.kind
+        );
+        return None;
+    }
+    if header.version != version {
+        warn!(
+            "Ignoring {} file {}: it is format version {}, and this version of the demo reads {}",
+            kind,
+            path.display(),
+            header.version,
+            version
+        );
+        return None;
+    }
+
+    match serde_json::from_value(header.data) {
+        Ok(data) => Some(data),
+        Err(e) => {
+            warn!("Ignoring corrupted {} file {}: {}", kind, path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Data {
+        name: String,
+        count: u32,
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gaia-demo-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        dir.join(name)
+    }
+
+    fn data() -> Data {
+        Data {
+            name: "Paris".to_string(),
+            count: 3,
+        }
+    }
+
+    #[test]
+    fn saved_data_loads_back() {
+        let path = temp_file("saved.json");
+        save_versioned(&path, "test", 2, &data()).unwrap();
+
+        assert_eq!(load_versioned(&path, "test", 2), Some(data()));
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = temp_file("missing.json");
+
+        assert_eq!(load_versioned::<Data>(&path, "test", 1), None);
+    }
+
+    #[test]
+    fn old_format_is_empty() {
+        let path = temp_file("old.json");
+        save_versioned(&path, "test", 1, &data()).unwrap();
+
+        assert_eq!(load_versioned::<Data>(&path, "test", 2), None);
+    }
+
+    #[test]
+    fn unversioned_file_is_empty() {
+        let path = temp_file("unversioned.json");
+        write_atomically(&path, br#"{"name": "Paris", "count": 3}"#).unwrap();
+
+        assert_eq!(load_versioned::<Data>(&path, "test", 1), None);
+    }
+
+    #[test]
+    fn corrupted_file_is_empty() {
+        let path = temp_file("corrupted.json");
+        save_versioned(&path, "test", 1, &data()).unwrap();
+        let contents = fs::read(&path).unwrap();
+        write_atomically(&path, &contents[..contents.len() / 2]).unwrap();
+
+        assert_eq!(load_versioned::<Data>(&path, "test", 1), None);
+    }
+
+    #[test]
+    fn wrong_data_is_empty() {
+        let path = temp_file("wrong.json");
+        save_versioned(&path, "test", 1, &vec![1, 2, 3]).unwrap();
+
+        assert_eq!(load_versioned::<Data>(&path, "test", 1), None);
+    }
+}
@@ -0,0 +1,369 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use cgmath::Matrix4;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+use serde_json::{self, Value};
+
+use coordinates;
+use hud::HudStyle;
+use projection;
+use settings::PointSettings;
+use {Result, ResultExt};
+
+/// How often the points file's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+const FONT_SIZE: u32 = 10;
+
+/// How many offending features a warning lists, before saying how many more there are.
+const MAX_LISTED: usize = 10;
+
+/// A point from the points file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    /// The feature's `name` property, or its index if it has none.
+    pub name: String,
+    /// The feature's `category` property, which picks its color.
+    pub category: Option<String>,
+    pub world_point: [f32; 2],
+}
+
+/// Where a point is drawn this frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Marker {
+    /// The point's index in `PointLayer::points`.
+    pub index: usize,
+    /// In the 2D pass's coordinates.
+    pub center: [f64; 2],
+    pub radius: f64,
+}
+
+/// Points from a GeoJSON file, drawn over the map as markers, and reloaded whenever the file is
+/// changed.
+#[derive(Debug)]
+pub struct PointLayer {
+    path: PathBuf,
+    /// When the file was last modified, as of the last check. `None` until it is first loaded.
+    modified: Option<SystemTime>,
+    last_poll: Option<Instant>,
+    points: Vec<Point>,
+}
+
+impl PointLayer {
+    /// A layer of the points in `path`, which are loaded by the first `poll`.
+    pub fn new(path: PathBuf) -> PointLayer {
+        PointLayer {
+            path,
+            modified: None,
+            last_poll: None,
+            points: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+
+    /// Reloads the points if the file was modified since the last call, returning warnings about
+    /// the features that were skipped. If it cannot be read, or is not GeoJSON, the points are
+    /// left as they were.
+    pub fn poll(&mut self) -> Option<Result<Vec<String>>> {
+        if self.last_poll.map_or(false, |last| last.elapsed() < POLL_INTERVAL) {
+            return None;
+        }
+        self.last_poll = Some(Instant::now());
+
+        // A file that is briefly missing while an editor replaces it leaves the points as they
+        // were.
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+
+        Some(load(&self.path).map(|(points, warnings)| {
+            self.points = points;
+            warnings
+        }))
+    }
+
+    /// Where each point that is in front of the camera and on screen is drawn, through `mvp`,
+    /// `size` across at a camera height of 1 and larger closer in. The map repeats
+    /// horizontally, so each point is drawn at its copy nearest `look_at`.
+    pub fn project(
+        &self,
+        mvp: Matrix4<f32>,
+        look_at: [f32; 2],
+        camera_height: f32,
+        size: f64,
+        view_size: [f64; 2],
+    ) -> Vec<Marker> {
+        let radius = size / 2.0 * f64::from(camera_height.max(1e-3)).powf(-0.25);
+
+        self.points
+            .iter()
+            .enumerate()
+            .filter_map(|(index, point)| {
+                let center = projection::project(mvp, look_at, point.world_point, view_size)?;
+
+                if projection::is_on_view(center, view_size, radius) {
+                    Some(Marker {
+                        index,
+                        center,
+                        radius,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Draws `markers`, colored by their category, with their names beside them if labels are
+    /// on.
+    pub fn draw(
+        &self,
+        markers: &[Marker],
+        settings: &PointSettings,
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let HudStyle { scale, theme } = style;
+        let outline = theme.opaque_background();
+
+        for marker in markers {
+            let point = &self.points[marker.index];
+            let [x, y] = marker.center;
+            let r = marker.radius;
+
+            ::piston_window::ellipse(
+                outline,
+                [x - r - 1.0, y - r - 1.0, 2.0 * (r + 1.0), 2.0 * (r + 1.0)],
+                context.transform,
+                graphics,
+            );
+            ::piston_window::ellipse(
+                settings.color(point.category.as_deref()),
+                [x - r, y - r, 2.0 * r, 2.0 * r],
+                context.transform,
+                graphics,
+            );
+
+            if settings.labels {
+                scale.draw_text(
+                    &point.name,
+                    FONT_SIZE,
+                    theme.text,
+                    context.trans(x + r + scale.len(3.0), y + scale.len(3.0)),
+                    glyphs,
+                    graphics,
+                );
+            }
+        }
+    }
+
+    /// The point whose marker `cursor` is over, the nearest if it is over several.
+    pub fn hovered(&self, markers: &[Marker], cursor: [f64; 2]) -> Option<&Point> {
+        let distance = |marker: &Marker| {
+            (marker.center[0] - cursor[0]).hypot(marker.center[1] - cursor[1])
+        };
+
+        markers
+            .iter()
+            .filter(|marker| distance(marker) <= marker.radius + 1.0)
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .map(|marker| &self.points[marker.index])
+    }
+}
+
+/// Reads the points in the GeoJSON file at `path`, with warnings about the features that were
+/// skipped.
+pub fn load(path: &Path) -> Result<(Vec<Point>, Vec<String>)> {
+    let text = fs::read_to_string(path)
+        .chain_err(|| format!("Could not read points file {}", path.display()))?;
+
+    parse(&text).chain_err(|| format!("Could not load {}", path.display()))
+}
+
+/// The points in the GeoJSON `text`, and warnings about the features that were skipped because
+/// they are not points. Fails if it is not a GeoJSON feature collection.
+pub fn parse(text: &str) -> Result<(Vec<Point>, Vec<String>)> {
+    let data: Value = serde_json::from_str(text).chain_err(|| "Invalid JSON")?;
+    if data.get("type").and_then(Value::as_str) != Some("FeatureCollection") {
+        bail!("Expected a GeoJSON FeatureCollection");
+    }
+    let features = match data.get("features").and_then(Value::as_array) {
+        Some(features) => features,
+        None => bail!("Expected the FeatureCollection to have an array of features"),
+    };
+
+    let mut points = Vec::new();
+    let (mut not_points, mut invalid) = (Vec::new(), Vec::new());
+    for (i, feature) in features.iter().enumerate() {
+        let geometry = &feature["geometry"];
+        match geometry.get("type").and_then(Value::as_str) {
+            Some("Point") => {}
+            Some(_) => {
+                not_points.push(i);
+                continue;
+            }
+            None => {
+                invalid.push(i);
+                continue;
+            }
+        }
+
+        let coordinates = geometry["coordinates"].as_array().map(|coordinates| {
+            let number = |n: usize| coordinates.get(n).and_then(Value::as_f64);
+            (number(0), number(1))
+        });
+        let (longitude, latitude) = match coordinates {
+            Some((Some(longitude), Some(latitude)))
+                if longitude.abs() <= 180.0 && latitude.abs() <= 90.0 =>
+            {
+                (longitude, latitude)
+            }
+            _ => {
+                invalid.push(i);
+                continue;
+            }
+        };
+
+        let properties = &feature["properties"];
+        let text = |key: &str| properties.get(key).and_then(Value::as_str).map(str::to_string);
+        points.push(Point {
+            name: text("name").unwrap_or_else(|| format!("#{}", i)),
+            category: text("category"),
+            world_point: coordinates::to_world_point(latitude, longitude),
+        });
+    }
+
+    let mut warnings = Vec::new();
+    if !not_points.is_empty() {
+        warnings.push(format!(
+            "Skipped {}: their geometry is not a Point",
+            list(&not_points)
+        ));
+    }
+    if !invalid.is_empty() {
+        warnings.push(format!(
+            "Skipped {}: they have no valid Point geometry",
+            list(&invalid)
+        ));
+    }
+
+    Ok((points, warnings))
+}
+
+/// The features at `indices`, such as "features 3, 8 and 9".
+fn list(indices: &[usize]) -> String {
+    let shown: Vec<String> = indices.iter().take(MAX_LISTED).map(usize::to_string).collect();
+    let listed = match (indices.len(), shown.split_last()) {
+        (1, _) => return format!("feature {}", indices[0]),
+        (len, _) if len > MAX_LISTED => {
+            format!("{} and {} more", shown.join(", "), len - MAX_LISTED)
+        }
+        (_, Some((last, rest))) => format!("{} and {}", rest.join(", "), last),
+        (_, None) => String::new(),
+    };
+
+    format!("features {}", listed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::SquareMatrix;
+    use std::env;
+    use std::process;
+
+    const POINTS: &str = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"name": "Null Island", "category": "odd"},
+         "geometry": {"type": "Point", "coordinates": [0, 0]}},
+        {"type": "Feature", "properties": {},
+         "geometry": {"type": "Point", "coordinates": [-180, 45]}},
+        {"type": "Feature", "properties": {"name": "A line"},
+         "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}},
+        {"type": "Feature", "properties": {"name": "Off the map"},
+         "geometry": {"type": "Point", "coordinates": [0, 95]}},
+        {"type": "Feature", "properties": {"name": "No geometry"}}
+    ]}"#;
+
+    #[test]
+    fn points_are_read_and_the_rest_are_skipped_with_warnings() {
+        let (points, warnings) = parse(POINTS).unwrap();
+
+        assert_eq!(
+            points,
+            [
+                Point {
+                    name: "Null Island".to_string(),
+                    category: Some("odd".to_string()),
+                    world_point: [1.0, 0.5],
+                },
+                Point {
+                    name: "#1".to_string(),
+                    category: None,
+                    world_point: [0.0, 0.75],
+                },
+            ]
+        );
+        assert_eq!(
+            warnings,
+            [
+                "Skipped feature 2: their geometry is not a Point",
+                "Skipped features 3 and 4: they have no valid Point geometry",
+            ]
+        );
+
+        assert!(parse("{}").unwrap_err().to_string().contains("FeatureCollection"));
+        assert!(parse(r#"{"type": "FeatureCollection"}"#).is_err());
+        assert!(parse("[").is_err());
+    }
+
+    #[test]
+    fn long_lists_of_features_are_cut_short() {
+        assert_eq!(list(&[3]), "feature 3");
+        assert_eq!(list(&[3, 8, 9]), "features 3, 8 and 9");
+        let many: Vec<usize> = (0..12).collect();
+        assert_eq!(list(&many), "features 0, 1, 2, 3, 4, 5, 6, 7, 8, 9 and 2 more");
+    }
+
+    #[test]
+    fn markers_are_drawn_at_the_copy_nearest_the_camera_and_hovered() {
+        let path = env::temp_dir().join(format!("gaia-demo-points-{}.geojson", process::id()));
+        fs::write(&path, POINTS).unwrap();
+        let mut layer = PointLayer::new(path.clone());
+        assert_eq!(layer.poll().map(|warnings| warnings.unwrap().len()), Some(2));
+        // Checks are spaced out, so the file is not read again straight away.
+        assert!(layer.poll().is_none());
+        fs::remove_file(&path).unwrap();
+
+        // Through the identity, the window shows world space from -1 to 1. Looking at x = 1.5,
+        // the nearest copy of the point on the antimeridian is at x = 2, off the window.
+        let mvp = Matrix4::identity();
+        let markers = layer.project(mvp, [1.5, 0.5], 1.0, 10.0, [200.0, 100.0]);
+        assert_eq!(markers.len(), 1);
+        assert_eq!((markers[0].index, markers[0].center), (0, [200.0, 25.0]));
+
+        // Looking at x = 0, it is in the middle, and Null Island's nearest copy is at x = -1.
+        let markers = layer.project(mvp, [0.0, 0.5], 1.0, 10.0, [200.0, 100.0]);
+        let centers: Vec<[f64; 2]> = markers.iter().map(|marker| marker.center).collect();
+        assert_eq!(centers, [[0.0, 25.0], [100.0, 12.5]]);
+
+        let hovered = |cursor| layer.hovered(&markers, cursor).map(|point| point.name.as_str());
+        assert_eq!(hovered([101.0, 14.0]), Some("#1"));
+        assert_eq!(hovered([150.0, 50.0]), None);
+    }
+}
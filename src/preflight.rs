@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+
+use gaia_assetgen::{FeaturesData, Properties};
+use serde_json::Value;
+
+use country_filter::CountryFilter;
+use label_policy::{self, LabelsShown};
+use settings::Settings;
+use MapMode;
+
+/// How many names a finding lists, before saying how many more there are.
+const MAX_NAMED: usize = 5;
+
+/// What the features a property is read from are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layer {
+    Countries,
+    /// The cities and other places labels are drawn for.
+    Places,
+}
+
+impl Layer {
+    fn name(&self) -> &'static str {
+        match *self {
+            Layer::Countries => "countries",
+            Layer::Places => "places",
+        }
+    }
+}
+
+/// What kind of value a property must have to be of use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Text,
+    Number,
+}
+
+/// A property something in the demo reads, and what it does for the features without it.
+#[derive(Clone, Copy, Debug)]
+struct Use {
+    layer: Layer,
+    key: &'static str,
+    kind: Kind,
+    /// What reads it, such as "Income mode".
+    user: &'static str,
+    /// What happens to the features that do not have it.
+    fallback: &'static str,
+}
+
+/// Every property the demo reads from the dataset, other than the info panel's fields.
+const USES: [Use; 12] = [
+    Use {
+        layer: Layer::Countries,
+        key: "INCOME_GRP",
+        kind: Kind::Text,
+        user: "OECD and Income modes",
+        fallback: "they are left uncolored",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "ADMIN",
+        kind: Kind::Text,
+        user: "Exceptional mode",
+        fallback: "they are not exceptional, and are not told apart without an ISO code",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "MAPCOLOR13",
+        kind: Kind::Number,
+        user: "All countries and OECD modes",
+        fallback: "they get the first of the 13 colors",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "NAME",
+        kind: Kind::Text,
+        user: "the info panel and exports",
+        fallback: "their ADMIN name is shown instead",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "ISO_A3",
+        kind: Kind::Text,
+        user: "sessions, selection and exports",
+        fallback: "they are told apart by their ADMIN name instead",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "POP_EST",
+        kind: Kind::Number,
+        user: "exports and population rank mode",
+        fallback: "their population is left empty, and they are left uncolored",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "SOVEREIGNT",
+        kind: Kind::Text,
+        user: "the sovereign states and UN members filters",
+        fallback: "they are left out as if they were dependencies",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "TYPE",
+        kind: Kind::Text,
+        user: "the sovereign states and UN members filters",
+        fallback: "they are kept if they are their own sovereign, even if they are disputed",
+    },
+    Use {
+        layer: Layer::Countries,
+        key: "UN_A3",
+        kind: Kind::Text,
+        user: "the UN members filter",
+        fallback: "they are left out as if they were not members",
+    },
+    Use {
+        layer: Layer::Places,
+        key: "NAME",
+        kind: Kind::Text,
+        user: "labels",
+        fallback: "they are not labeled",
+    },
+    Use {
+        layer: Layer::Places,
+        key: "min_zoom",
+        kind: Kind::Number,
+        user: "labels",
+        fallback: "they are not labeled",
+    },
+    Use {
+        layer: Layer::Places,
+        key: "ADM0CAP",
+        kind: Kind::Number,
+        user: "labels",
+        fallback: "they are labeled as if they were not capitals",
+    },
+];
+
+/// What the run needs from the dataset: the properties the start mode and labels read are
+/// required, and the rest are only reported.
+#[derive(Clone, Debug)]
+pub struct Needs<'a> {
+    pub mode: MapMode,
+    pub labels: bool,
+    /// The info panel's fields.
+    pub fields: &'a [String],
+    pub filter: CountryFilter,
+}
+
+impl<'a> Needs<'a> {
+    /// What a run with `settings` needs.
+    pub fn of(settings: &'a Settings) -> Needs<'a> {
+        let mode = settings.modes.default;
+        let policy = settings.modes.labels.of(mode);
+
+        Needs {
+            mode,
+            labels: label_policy::resolve(settings.labels.enabled, policy) != LabelsShown::None,
+            fields: &settings.hud.info_panel_fields,
+            filter: settings.countries.filter,
+        }
+    }
+
+    fn requires(&self, property: &Use) -> bool {
+        match (property.layer, property.key) {
+            (Layer::Places, _) => self.labels,
+            (Layer::Countries, "INCOME_GRP") => {
+                self.mode == MapMode::Oecd || self.mode == MapMode::Income
+            }
+            (Layer::Countries, "ADMIN") => self.mode == MapMode::Exceptional,
+            (Layer::Countries, "POP_EST") => self.mode == MapMode::Percentile,
+            (Layer::Countries, "MAPCOLOR13") => {
+                self.mode == MapMode::All || self.mode == MapMode::Oecd
+            }
+            (Layer::Countries, "SOVEREIGNT") => self.filter != CountryFilter::All,
+            (Layer::Countries, "UN_A3") => self.filter == CountryFilter::UnMembers,
+            _ => false,
+        }
+    }
+}
+
+/// Some features do not have a property that is read from them, or have it with the wrong kind
+/// of value.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub layer: Layer,
+    pub key: String,
+    /// Whether what the run starts with needs it.
+    pub required: bool,
+    /// The names of the features without it, each listed once even if it has several
+    /// polygons.
+    pub missing: Vec<String>,
+    /// What reads the property, and what happens without it.
+    pub consequence: String,
+}
+
+impl Finding {
+    /// Such as "3 countries missing INCOME_GRP: Kosovo, N. Cyprus, Somaliland".
+    pub fn summary(&self) -> String {
+        let mut names = self.missing[..self.missing.len().min(MAX_NAMED)].join(", ");
+        if self.missing.len() > MAX_NAMED {
+            names.push_str(&format!(" and {} more", self.missing.len() - MAX_NAMED));
+        }
+
+        format!(
+            "{} {} missing {}: {}",
+            self.missing.len(),
+            self.layer.name(),
+            self.key,
+            names
+        )
+    }
+}
+
+/// Checks that every feature in `data` has the properties `needs` reads. Findings for required
+/// properties come first.
+pub fn check(data: &FeaturesData, needs: &Needs) -> Vec<Finding> {
+    let countries: Vec<&Properties> = data.polygons.iter().map(|p| &p.properties).collect();
+    let places: Vec<&Properties> = data.points.iter().map(|p| &p.properties).collect();
+    let features = |layer| match layer {
+        Layer::Countries => &countries,
+        Layer::Places => &places,
+    };
+
+    let mut findings: Vec<Finding> = USES
+        .iter()
+        .filter_map(|property| {
+            let present = |value: &Value| match property.kind {
+                // Natural Earth gives some countries, such as France, an ISO code of "-99".
+                Kind::Text if property.key == "ISO_A3" => {
+                    value.as_str().map_or(false, |code| code != "-99" && !code.is_empty())
+                }
+                Kind::Text => value.is_string(),
+                Kind::Number => value.is_number(),
+            };
+
+            finding(features(property.layer), property.layer, property.key, present).map(
+                |mut finding| {
+                    finding.required = needs.requires(property);
+                    finding.consequence = format!("{}: {}", property.user, property.fallback);
+                    finding
+                },
+            )
+        })
+        .collect();
+
+    for field in needs.fields {
+        let already = USES
+            .iter()
+            .any(|property| property.layer == Layer::Countries && property.key == field);
+        if already {
+            continue;
+        }
+
+        if let Some(mut finding) = finding(&countries, Layer::Countries, field, |_| true) {
+            finding.consequence = "the info panel: it shows a dash".to_string();
+            findings.push(finding);
+        }
+    }
+
+    findings.sort_by_key(|finding| !finding.required);
+    findings
+}
+
+/// The finding for the features that `present` says have no usable `key`, if any.
+fn finding<F>(features: &[&Properties], layer: Layer, key: &str, present: F) -> Option<Finding>
+where
+    F: Fn(&Value) -> bool,
+{
+    let mut seen = HashSet::new();
+    let missing: Vec<String> = features
+        .iter()
+        .enumerate()
+        .filter(|&(_, properties)| !properties.get(key).map_or(false, &present))
+        .map(|(i, properties)| name(properties, i))
+        .filter(|name| seen.insert(name.clone()))
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+
+    Some(Finding {
+        layer,
+        key: key.to_string(),
+        required: false,
+        missing,
+        consequence: String::new(),
+    })
+}
+
+/// What a feature is called in findings.
+fn name(properties: &Properties, index: usize) -> String {
+    ["NAME", "ADMIN"]
+        .iter()
+        .filter_map(|key| properties.get(*key).and_then(Value::as_str))
+        .next()
+        .map_or_else(|| format!("#{}", index), str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gaia_assetgen::{MultiLevelPoint, MultiLevelPolygon, MAX_LEVEL};
+    use serde_json;
+
+    fn data(countries: &str, places: &str) -> FeaturesData {
+        let countries: Vec<Properties> = serde_json::from_str(countries).unwrap();
+        let places: Vec<Properties> = serde_json::from_str(places).unwrap();
+
+        FeaturesData {
+            polygons: countries
+                .into_iter()
+                .map(|properties| MultiLevelPolygon {
+                    properties,
+                    bounding_box: [(0.5, 0.5), (0.5, 0.5)],
+                    levels: vec![vec![]; MAX_LEVEL as usize + 1],
+                })
+                .collect(),
+            points: places
+                .into_iter()
+                .map(|properties| MultiLevelPoint {
+                    properties,
+                    coordinates: [0.5, 0.5],
+                    levels: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn needs(labels: bool) -> Needs<'static> {
+        Needs {
+            mode: MapMode::All,
+            labels,
+            fields: &[],
+            filter: CountryFilter::All,
+        }
+    }
+
+    fn find<'f>(findings: &'f [Finding], layer: Layer, key: &str) -> Option<&'f Finding> {
+        findings.iter().find(|finding| finding.layer == layer && finding.key == key)
+    }
+
+    const COUNTRY: &str = r#"{"NAME": "Iceland", "ADMIN": "Iceland", "ISO_A3": "ISL",
+        "INCOME_GRP": "1. High income: OECD", "MAPCOLOR13": 9, "POP_EST": 339747,
+        "SOVEREIGNT": "Iceland", "TYPE": "Sovereign country", "UN_A3": "352"}"#;
+
+    #[test]
+    fn places_without_a_name_or_min_zoom_are_found_when_labels_are_shown() {
+        let data = data(
+            &format!("[{}]", COUNTRY),
+            r#"[{"NAME": "Reykjavik", "min_zoom": 3, "ADM0CAP": 1},
+                {"min_zoom": 5, "ADM0CAP": 0},
+                {"NAME": "Akureyri", "min_zoom": "7", "ADM0CAP": 0}]"#,
+        );
+        let findings = check(&data, &needs(true));
+
+        let name = find(&findings, Layer::Places, "NAME").unwrap();
+        assert!(name.required);
+        assert_eq!(name.missing, ["#1"]);
+        assert_eq!(name.consequence, "labels: they are not labeled");
+
+        // A min_zoom that is not a number is as good as none.
+        let min_zoom = find(&findings, Layer::Places, "min_zoom").unwrap();
+        assert!(min_zoom.required);
+        assert_eq!(min_zoom.summary(), "1 places missing min_zoom: Akureyri");
+
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+    }
+
+    #[test]
+    fn places_are_only_reported_without_labels() {
+        let data = data(&format!("[{}]", COUNTRY), r#"[{"ADM0CAP": 1}]"#);
+        let findings = check(&data, &needs(false));
+
+        assert!(findings.iter().all(|finding| !finding.required));
+        assert!(find(&findings, Layer::Places, "NAME").is_some());
+        assert!(find(&findings, Layer::Places, "min_zoom").is_some());
+    }
+
+    #[test]
+    fn required_findings_come_first_and_names_are_listed_once() {
+        let data = data(
+            r#"[{"NAME": "France", "ISO_A3": "-99", "MAPCOLOR13": 1},
+                {"NAME": "France", "ISO_A3": "-99", "MAPCOLOR13": 1},
+                {"NAME": "Kosovo", "ISO_A3": "", "MAPCOLOR13": "2"}]"#,
+            "[]",
+        );
+        let findings = check(&data, &needs(false));
+
+        assert_eq!(findings[0].key, "MAPCOLOR13");
+        assert!(findings[0].required);
+        assert!(findings[1..].iter().all(|finding| !finding.required));
+        let iso_a3 = find(&findings, Layer::Countries, "ISO_A3").unwrap();
+        assert_eq!(iso_a3.missing, ["France", "Kosovo"]);
+    }
+
+    #[test]
+    fn info_panel_fields_are_checked_too() {
+        let data = data(&format!("[{}]", COUNTRY), "[]");
+        let fields = ["NAME".to_string(), "CONTINENT".to_string()];
+        let needs = Needs {
+            fields: &fields,
+            ..needs(false)
+        };
+        let findings = check(&data, &needs);
+
+        let continent = find(&findings, Layer::Countries, "CONTINENT").unwrap();
+        assert_eq!(continent.consequence, "the info panel: it shows a dash");
+        assert!(!continent.required);
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+    }
+
+    #[test]
+    fn summaries_name_only_the_first_few() {
+        let finding = Finding {
+            layer: Layer::Countries,
+            key: "INCOME_GRP".to_string(),
+            required: true,
+            missing: (1..=7).map(|i| format!("C{}", i)).collect(),
+            consequence: String::new(),
+        };
+
+        assert_eq!(
+            finding.summary(),
+            "7 countries missing INCOME_GRP: C1, C2, C3, C4, C5 and 2 more"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use settings::Settings;
+use theme::ThemeName;
+use Palette;
+
+/// A bundle of settings switched on and off as one. A profile is layered over the settings
+/// rather than saved into them, so that switching it off leaves them exactly as they were.
+/// Written in snake case in the settings file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    /// Solid colors and a high-contrast HUD, with larger labels and a colorblind-safe palette.
+    HighContrast,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 1] = [Profile::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Profile::HighContrast => "High contrast",
+        }
+    }
+
+    /// `settings`, with this profile's changes layered over them.
+    pub fn apply(&self, settings: &Settings) -> Settings {
+        let mut settings = settings.clone();
+
+        match *self {
+            Profile::HighContrast => {
+                let profile = settings.profiles.high_contrast;
+                let labels = &mut settings.labels;
+                labels.scale *= profile.label_scale;
+                labels.capital_scale *= profile.label_scale;
+                labels.border_width *= profile.border_scale;
+
+                settings.hud.theme = ThemeName::HighContrast;
+                settings.modes.palette = Palette::ColorblindSafe;
+                settings.modes.min_alpha = settings.modes.min_alpha.max(profile.min_alpha);
+            }
+        }
+
+        settings
+    }
+
+    /// The profile `steps` places after `profile` in `ALL`, where no profile comes first,
+    /// wrapping around.
+    pub fn cycle(profile: Option<Profile>, steps: isize) -> Option<Profile> {
+        let len = Profile::ALL.len() as isize + 1;
+        let index = profile
+            .and_then(|profile| Profile::ALL.iter().position(|&p| p == profile))
+            .map_or(0, |index| index as isize + 1);
+
+        match (index + steps).rem_euclid(len) {
+            0 => None,
+            index => Some(Profile::ALL[index as usize - 1]),
+        }
+    }
+}
+
+/// `settings` with `profile`, if any, layered over them.
+pub fn layered(settings: &Settings, profile: Option<Profile>) -> Settings {
+    match profile {
+        Some(profile) => profile.apply(settings),
+        None => settings.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_high_contrast_profile_is_layered_over_the_settings() {
+        let mut settings = Settings::default();
+        settings.modes.min_alpha = 100;
+        let high_contrast = layered(&settings, Some(Profile::HighContrast));
+
+        let profile = settings.profiles.high_contrast;
+        assert_eq!(high_contrast.labels.scale, settings.labels.scale * profile.label_scale);
+        let border_width = settings.labels.border_width * profile.border_scale;
+        assert_eq!(high_contrast.labels.border_width, border_width);
+        assert_eq!(high_contrast.hud.theme, ThemeName::HighContrast);
+        assert_eq!(high_contrast.modes.palette, Palette::ColorblindSafe);
+        assert_eq!(high_contrast.modes.min_alpha, profile.min_alpha);
+
+        // Colors already more opaque than the profile asks for are left so.
+        settings.modes.min_alpha = 250;
+        assert_eq!(Profile::HighContrast.apply(&settings).modes.min_alpha, 250);
+
+        assert_eq!(layered(&settings, None), settings);
+    }
+
+    #[test]
+    fn profiles_cycle_through_none_first() {
+        assert_eq!(Profile::cycle(None, 1), Some(Profile::HighContrast));
+        assert_eq!(Profile::cycle(Some(Profile::HighContrast), 1), None);
+        assert_eq!(Profile::cycle(None, -1), Some(Profile::HighContrast));
+        assert_eq!(Profile::cycle(None, 2), None);
+    }
+}
@@ -0,0 +1,252 @@
+use cgmath::{self, Angle, Matrix4, PerspectiveFov, Rad, Vector4};
+use piston::window::Size;
+
+use split_view::View;
+
+const NEAR: f32 = 0.001;
+const FAR: f32 = 100.0;
+
+/// Points closer to the camera plane than this, in clip space, are behind it.
+pub const MIN_W: f32 = 1e-4;
+
+/// The widest the view gets, as its width over its height. Wider windows see less from top to
+/// bottom instead of more from side to side, so that the globe is not stretched at their edges.
+const MAX_ASPECT: f32 = 3.0;
+
+/// The camera's vertical field of view, in windows no wider than `MAX_ASPECT`.
+pub fn field_of_view() -> Rad<f32> {
+    Rad::full_turn() / 8.0
+}
+
+/// The width over the height of `draw_size`. A side with no pixels, as a minimized window has,
+/// counts as one pixel, so that the ratio is never zero or infinite.
+pub fn aspect(draw_size: Size) -> f32 {
+    draw_size.width.max(1) as f32 / draw_size.height.max(1) as f32
+}
+
+/// The camera's vertical field of view in a view `aspect` times wider than it is tall.
+pub fn vertical_fov(aspect: f32) -> Rad<f32> {
+    if aspect <= MAX_ASPECT {
+        return field_of_view();
+    }
+
+    // The horizontal field of view stays what it is at `MAX_ASPECT`.
+    let half_width = (field_of_view() / 2.0).tan() * MAX_ASPECT;
+    Rad::atan(half_width / aspect) * 2.0
+}
+
+/// The projection matrix for the window's draw size, which only changes when the window is
+/// resized, so it is kept rather than rebuilt every frame.
+#[derive(Debug)]
+pub struct Projection {
+    /// In pixels.
+    draw_size: Size,
+    matrix: Matrix4<f32>,
+    /// The matrix for each half of a split-screen, which is half as wide.
+    half_matrix: Matrix4<f32>,
+}
+
+impl Projection {
+    pub fn new(draw_size: Size) -> Projection {
+        Projection {
+            draw_size,
+            matrix: perspective(draw_size),
+            half_matrix: perspective(View::Left.draw_size(draw_size)),
+        }
+    }
+
+    /// Rebuilds the matrix if `draw_size` differs from the last one. A window with no area, such
+    /// as a minimized one, keeps the last matrix, since it has no aspect ratio.
+    pub fn resize(&mut self, draw_size: Size) {
+        if draw_size == self.draw_size || draw_size.width == 0 || draw_size.height == 0 {
+            return;
+        }
+
+        *self = Projection::new(draw_size);
+    }
+
+    pub fn draw_size(&self) -> Size {
+        self.draw_size
+    }
+
+    /// The matrix for drawing the map in `view`.
+    pub fn matrix_in(&self, view: View) -> Matrix4<f32> {
+        match view {
+            View::Whole => self.matrix,
+            View::Left | View::Right => self.half_matrix,
+        }
+    }
+}
+
+/// The part of the projection for an image of `size` that covers `region` of it, as
+/// `[x, y, width, height]` in pixels from its top-left, stretched over a whole target. Drawing
+/// the regions of a grid, and putting the pictures side by side, makes the picture the whole
+/// projection would.
+pub fn region_matrix(size: [u32; 2], region: [u32; 4]) -> Matrix4<f32> {
+    let [width, height] = [size[0].max(1) as f32, size[1].max(1) as f32];
+    let [x, y, region_width, region_height] = [
+        region[0] as f32,
+        region[1] as f32,
+        region[2] as f32,
+        region[3] as f32,
+    ];
+
+    let top = NEAR * (vertical_fov(width / height) / 2.0).tan();
+    let right = top * width / height;
+    let along = |from: f32, to: f32, fraction: f32| from + (to - from) * fraction;
+
+    cgmath::frustum(
+        along(-right, right, x / width),
+        along(-right, right, (x + region_width) / width),
+        along(top, -top, (y + region_height) / height),
+        along(top, -top, y / height),
+        NEAR,
+        FAR,
+    )
+}
+
+/// The world-space point as a point of the 2D pass through `mvp`, on a view `view_size` across,
+/// or `None` if it is behind the camera.
+pub fn to_screen(mvp: Matrix4<f32>, [x, y]: [f32; 2], view_size: [f64; 2]) -> Option<[f64; 2]> {
+    let clip = mvp * Vector4::new(x, y, 0.0, 1.0);
+    if clip.w < MIN_W {
+        return None;
+    }
+
+    Some(clip_to_view(clip, view_size))
+}
+
+/// Like `to_screen`, but at the point's copy nearest `look_at`, since the map repeats
+/// horizontally.
+pub fn project(
+    mvp: Matrix4<f32>,
+    look_at: [f32; 2],
+    [x, y]: [f32; 2],
+    view_size: [f64; 2],
+) -> Option<[f64; 2]> {
+    to_screen(mvp, [nearest_copy(x, look_at), y], view_size)
+}
+
+/// `x`, in world space, moved to the copy of the map nearest `look_at`. The map repeats every 2
+/// units of x.
+pub fn nearest_copy(x: f32, look_at: [f32; 2]) -> f32 {
+    x + 2.0 * ((look_at[0] - x) / 2.0).round()
+}
+
+/// The point in front of the camera at `clip`, in clip space, as a point of the 2D pass on a view
+/// `view_size` across.
+pub fn clip_to_view(clip: Vector4<f32>, view_size: [f64; 2]) -> [f64; 2] {
+    [
+        (f64::from(clip.x / clip.w) + 1.0) / 2.0 * view_size[0],
+        (1.0 - f64::from(clip.y / clip.w)) / 2.0 * view_size[1],
+    ]
+}
+
+/// Whether `point`, in the 2D pass's coordinates, is on a view `view_size` across, or no more
+/// than `margin` off it.
+pub fn is_on_view(point: [f64; 2], view_size: [f64; 2], margin: f64) -> bool {
+    point[0] >= -margin
+        && point[0] <= view_size[0] + margin
+        && point[1] >= -margin
+        && point[1] <= view_size[1] + margin
+}
+
+fn perspective(draw_size: Size) -> Matrix4<f32> {
+    Matrix4::from(PerspectiveFov {
+        fovy: vertical_fov(aspect(draw_size)),
+        near: NEAR,
+        far: FAR,
+        aspect: aspect(draw_size),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::Zero;
+
+    fn size(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+
+    #[test]
+    fn resizing_rebuilds_the_matrix() {
+        let mut projection = Projection::new(size(800, 600));
+        projection.resize(size(1920, 1080));
+
+        assert_eq!(projection.draw_size(), size(1920, 1080));
+        assert_eq!(projection.matrix_in(View::Whole), perspective(size(1920, 1080)));
+        assert_ne!(projection.matrix_in(View::Whole), perspective(size(800, 600)));
+    }
+
+    #[test]
+    fn an_empty_window_keeps_the_last_matrix() {
+        let mut projection = Projection::new(size(800, 600));
+        projection.resize(size(0, 0));
+
+        assert_eq!(projection.draw_size(), size(800, 600));
+        assert_eq!(projection.matrix_in(View::Whole), perspective(size(800, 600)));
+    }
+
+    fn is_finite(matrix: Matrix4<f32>) -> bool {
+        let columns: &[[f32; 4]; 4] = matrix.as_ref();
+        columns.iter().flat_map(|column| column.iter()).all(|n| n.is_finite())
+    }
+
+    #[test]
+    fn degenerate_sizes_have_a_usable_aspect() {
+        assert_eq!(aspect(size(1, 1)), 1.0);
+        assert_eq!(aspect(size(0, 0)), 1.0);
+        assert_eq!(aspect(size(5000, 0)), 5000.0);
+        assert_eq!(aspect(size(5000, 100)), 50.0);
+
+        for &draw_size in &[size(1, 1), size(0, 0), size(5000, 100), size(100, 5000)] {
+            let projection = Projection::new(draw_size);
+            assert!(is_finite(projection.matrix_in(View::Whole)), "{:?}", draw_size);
+            assert!(is_finite(projection.matrix_in(View::Left)), "{:?}", draw_size);
+        }
+    }
+
+    #[test]
+    fn wide_windows_keep_the_horizontal_field_of_view() {
+        let half_tan = |fov: Rad<f32>| (fov / 2.0).tan();
+        assert_eq!(vertical_fov(1.0), field_of_view());
+        assert_eq!(vertical_fov(MAX_ASPECT), field_of_view());
+
+        let horizontal = half_tan(field_of_view()) * MAX_ASPECT;
+        assert!((half_tan(vertical_fov(50.0)) * 50.0 - horizontal).abs() < 1e-5);
+        assert!(vertical_fov(50.0) < field_of_view());
+    }
+
+    #[test]
+    fn points_are_projected_at_their_nearest_copy() {
+        let view_size = [800.0, 600.0];
+        let mvp = Matrix4::from_scale(0.5);
+        assert_eq!(to_screen(mvp, [0.0, 0.0], view_size), Some([400.0, 300.0]));
+        assert_eq!(to_screen(mvp, [1.0, 1.0], view_size), Some([600.0, 150.0]));
+        assert_eq!(to_screen(Matrix4::zero(), [0.0, 0.0], view_size), None);
+
+        // The map repeats every 2 units, so a point near one edge is near the other too.
+        assert_eq!(nearest_copy(1.9, [0.1, 0.5]), 1.9 - 2.0);
+        assert_eq!(nearest_copy(0.3, [4.2, 0.5]), 4.3);
+        assert_eq!(project(mvp, [-0.5, 0.0], [1.5, 0.0], view_size), Some([300.0, 300.0]));
+
+        assert!(is_on_view([800.0, 0.0], view_size, 0.0));
+        assert!(!is_on_view([805.0, 0.0], view_size, 0.0));
+        assert!(is_on_view([805.0, -5.0], view_size, 5.0));
+    }
+
+    #[test]
+    fn the_whole_region_is_the_whole_projection() {
+        for &[width, height] in &[[800, 600], [5000, 100], [1, 1]] {
+            let whole = region_matrix([width, height], [0, 0, width, height]);
+            let expected = perspective(size(width, height));
+            let columns: &[[f32; 4]; 4] = whole.as_ref();
+            let expected: &[[f32; 4]; 4] = expected.as_ref();
+            for (a, b) in columns.iter().flat_map(|c| c.iter()).zip(expected.iter().flatten()) {
+                assert!((a - b).abs() < 1e-3, "{}x{}: {} != {}", width, height, a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use gaia_assetgen::{FeaturesData, Properties};
+use serde_json::Value;
+
+/// How many example values are listed for each property.
+const EXAMPLES: usize = 3;
+
+/// Example strings longer than this many characters are cut short.
+const MAX_EXAMPLE_LEN: usize = 24;
+
+/// What is known about one property across a class of features.
+#[derive(Debug, Default)]
+struct PropertyStats {
+    /// How many features have the property, of each type.
+    types: BTreeMap<&'static str, usize>,
+    /// How many features have each string value.
+    strings: BTreeMap<String, usize>,
+    numbers: Vec<f64>,
+    /// The first few distinct values, as they are printed.
+    examples: Vec<String>,
+}
+
+impl PropertyStats {
+    fn add(&mut self, value: &Value) {
+        *self.types.entry(type_name(value)).or_insert(0) += 1;
+
+        match *value {
+            Value::String(ref string) => {
+                *self.strings.entry(string.clone()).or_insert(0) += 1;
+            }
+            Value::Number(ref number) => {
+                if let Some(number) = number.as_f64() {
+                    self.numbers.push(number);
+                }
+            }
+            _ => {}
+        }
+
+        let example = example(value);
+        if self.examples.len() < EXAMPLES && !self.examples.contains(&example) {
+            self.examples.push(example);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.types.values().sum()
+    }
+
+    /// The property's type, or the types it has if more than one.
+    fn type_text(&self) -> String {
+        self.types.keys().cloned().collect::<Vec<_>>().join("/")
+    }
+}
+
+/// Describes the properties of each class of features in `data`, or if `key` is given, the values
+/// of that property.
+pub fn list(data: &FeaturesData, key: Option<&str>) -> String {
+    let countries: Vec<&Properties> =
+        data.polygons.iter().map(|polygon| &polygon.properties).collect();
+    let places: Vec<&Properties> = data.points.iter().map(|point| &point.properties).collect();
+    let classes = [("Countries", countries), ("Populated places", places)];
+
+    let sections: Vec<String> = classes
+        .iter()
+        .map(|&(class, ref features)| match key {
+            Some(key) => distribution(class, key, features),
+            None => summary(class, features),
+        })
+        .collect();
+
+    sections.join("\n")
+}
+
+/// The properties of `features`, each with its type, how many features have it, and some
+/// example values.
+fn summary(class: &str, features: &[&Properties]) -> String {
+    let stats = collect(features);
+    let name_width = stats.keys().map(String::len).max().unwrap_or(0);
+
+    let mut text = format!("{} ({} features):\n", class, features.len());
+    if stats.is_empty() {
+        text.push_str("  (no properties)\n");
+    }
+
+    for (name, stats) in &stats {
+        writeln!(
+            text,
+            "  {:<name_width$}  {:<13}  {:>6}  {}",
+            name,
+            stats.type_text(),
+            stats.count(),
+            stats.examples.join(", "),
+            name_width = name_width
+        ).unwrap();
+    }
+
+    text
+}
+
+/// Every value `key` has among `features`: how many features have each string, and the range
+/// and mean of numbers.
+fn distribution(class: &str, key: &str, features: &[&Properties]) -> String {
+    let mut stats = PropertyStats::default();
+    for properties in features {
+        if let Some(value) = properties.get(key) {
+            stats.add(value);
+        }
+    }
+
+    let mut text = format!(
+        "{}: `{}` ({} of {} features)\n",
+        class,
+        key,
+        stats.count(),
+        features.len()
+    );
+    if stats.count() == 0 {
+        return text;
+    }
+
+    if !stats.numbers.is_empty() {
+        let min = stats.numbers.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+        let max = stats.numbers.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+        let mean = stats.numbers.iter().sum::<f64>() / stats.numbers.len() as f64;
+
+        writeln!(
+            text,
+            "  {} numbers: min {}, max {}, mean {:.3}",
+            stats.numbers.len(),
+            min,
+            max,
+            mean
+        ).unwrap();
+    }
+
+    // Most common first, then alphabetically.
+    let mut strings: Vec<(&String, &usize)> = stats.strings.iter().collect();
+    strings.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    for (value, count) in strings {
+        writeln!(text, "  {:>6}  {:?}", count, value).unwrap();
+    }
+
+    for (type_name, count) in &stats.types {
+        if *type_name != "string" && *type_name != "number" {
+            writeln!(text, "  {:>6}  ({})", count, type_name).unwrap();
+        }
+    }
+
+    text
+}
+
+fn collect(features: &[&Properties]) -> BTreeMap<String, PropertyStats> {
+    let mut stats: BTreeMap<String, PropertyStats> = BTreeMap::new();
+
+    for properties in features {
+        for (key, value) in properties.iter() {
+            stats.entry(key.clone()).or_insert_with(Default::default).add(value);
+        }
+    }
+
+    stats
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn example(value: &Value) -> String {
+    match *value {
+        Value::String(ref string) if string.chars().count() > MAX_EXAMPLE_LEN => {
+            let start: String = string.chars().take(MAX_EXAMPLE_LEN).collect();
+            format!("{:?}...", start)
+        }
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gaia_assetgen::{MultiLevelPolygon, MAX_LEVEL};
+    use serde_json;
+
+    fn data(countries: &str) -> FeaturesData {
+        let countries: Vec<Properties> = serde_json::from_str(countries).unwrap();
+        let polygons = countries
+            .into_iter()
+            .map(|properties| MultiLevelPolygon {
+                properties,
+                bounding_box: [(0.5, 0.5), (0.5, 0.5)],
+                levels: vec![vec![]; MAX_LEVEL as usize + 1],
+            })
+            .collect();
+
+        FeaturesData {
+            polygons,
+            points: vec![],
+        }
+    }
+
+    #[test]
+    fn summaries_give_each_property_its_types_count_and_examples() {
+        let data = data(
+            r#"[{"NAME": "Iceland", "POP_EST": 339747},
+                {"NAME": "France", "POP_EST": null},
+                {"NAME": "Chad", "ECONOMY": "7. Least developed region of the world"}]"#,
+        );
+        let listing = list(&data, None);
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines[0], "Countries (3 features):");
+        assert!(lines[1].starts_with("  ECONOMY  string"), "{}", lines[1]);
+        assert!(lines[1].ends_with(r#""7. Least developed regio"..."#), "{}", lines[1]);
+        assert!(lines[2].ends_with(r#"3  "Iceland", "France", "Chad""#), "{}", lines[2]);
+        assert!(lines[3].starts_with("  POP_EST  null/number"), "{}", lines[3]);
+        assert_eq!(&lines[4..], ["", "Populated places (0 features):", "  (no properties)"]);
+    }
+
+    #[test]
+    fn distributions_count_strings_and_summarize_numbers() {
+        let data = data(
+            r#"[{"GROUP": "b", "POP": 10}, {"GROUP": "a", "POP": 20},
+                {"GROUP": "b", "POP": 30}, {"GROUP": true}]"#,
+        );
+
+        let groups = list(&data, Some("GROUP"));
+        let lines: Vec<&str> = groups.lines().collect();
+        assert_eq!(
+            &lines[..4],
+            [
+                "Countries: `GROUP` (4 of 4 features)",
+                r#"       2  "b""#,
+                r#"       1  "a""#,
+                "       1  (bool)",
+            ]
+        );
+
+        let populations = list(&data, Some("POP"));
+        assert!(
+            populations.contains("  3 numbers: min 10, max 30, mean 20.000\n"),
+            "{}",
+            populations
+        );
+        assert!(list(&data, Some("AREA")).starts_with("Countries: `AREA` (0 of 4 features)\n"));
+    }
+}
@@ -0,0 +1,404 @@
+use gaia_assetgen::Properties;
+use serde_json::Value;
+
+// The Natural Earth properties map modes, labels and exports read.
+const NAME: &str = "NAME";
+const NAMEASCII: &str = "NAMEASCII";
+const ADMIN: &str = "ADMIN";
+const ISO_A3: &str = "ISO_A3";
+const POP_EST: &str = "POP_EST";
+const INCOME_GRP: &str = "INCOME_GRP";
+const MAPCOLOR13: &str = "MAPCOLOR13";
+const MIN_ZOOM: &str = "min_zoom";
+const ADM0CAP: &str = "ADM0CAP";
+const ADM0NAME: &str = "ADM0NAME";
+const MEGACITY: &str = "MEGACITY";
+const SCALERANK: &str = "SCALERANK";
+const POP_MAX: &str = "POP_MAX";
+const SOVEREIGNT: &str = "SOVEREIGNT";
+const TYPE: &str = "TYPE";
+const UN_A3: &str = "UN_A3";
+
+/// The `TYPE`s of countries that are not sovereign states in themselves, even where Natural
+/// Earth gives them as their own sovereign.
+const NOT_SOVEREIGN: [&str; 4] = ["Dependency", "Disputed", "Indeterminate", "Lease"];
+
+/// What Natural Earth puts in place of values it does not have.
+const UNKNOWN: f64 = -99.0;
+
+/// A country's income group, as the World Bank classifies it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IncomeClass {
+    HighOecd,
+    HighNonOecd,
+    UpperMiddle,
+    LowerMiddle,
+    Low,
+}
+
+impl IncomeClass {
+    /// From richest to poorest.
+    pub const ALL: [IncomeClass; 5] = [
+        IncomeClass::HighOecd,
+        IncomeClass::HighNonOecd,
+        IncomeClass::UpperMiddle,
+        IncomeClass::LowerMiddle,
+        IncomeClass::Low,
+    ];
+
+    /// The class Natural Earth writes as `value`, such as "1. High income: OECD".
+    pub fn from_value(value: &str) -> Option<IncomeClass> {
+        IncomeClass::ALL.iter().cloned().find(|class| class.value() == value)
+    }
+
+    /// As Natural Earth writes it.
+    pub fn value(self) -> &'static str {
+        match self {
+            IncomeClass::HighOecd => "1. High income: OECD",
+            IncomeClass::HighNonOecd => "2. High income: nonOECD",
+            IncomeClass::UpperMiddle => "3. Upper middle income",
+            IncomeClass::LowerMiddle => "4. Lower middle income",
+            IncomeClass::Low => "5. Low income",
+        }
+    }
+
+    /// As it is labeled in the legend and the info panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            IncomeClass::HighOecd => "High income: OECD",
+            IncomeClass::HighNonOecd => "High income: non-OECD",
+            IncomeClass::UpperMiddle => "Upper middle income",
+            IncomeClass::LowerMiddle => "Lower middle income",
+            IncomeClass::Low => "Low income",
+        }
+    }
+
+    /// Where it is in `ALL`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The properties of a country that map modes and exports read, converted once when the
+/// countries are loaded instead of looked up whenever a color is chosen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountryProps {
+    /// The country's `feature_id`.
+    pub id: String,
+    /// Empty if Natural Earth gives it none.
+    pub name: String,
+    /// The full name, as in "United States of America".
+    pub admin: String,
+    /// The ISO 3166-1 alpha-3 code. Natural Earth gives some countries, such as France, none.
+    pub iso_a3: Option<String>,
+    pub pop_est: Option<f64>,
+    pub income: Option<IncomeClass>,
+    /// Which of 13 colors Natural Earth suggests for the country, so that neighbors differ.
+    pub mapcolor13: Option<u8>,
+    /// Whether the country is its own sovereign, by `SOVEREIGNT`, rather than a dependency or a
+    /// disputed territory, by `TYPE`.
+    pub sovereign: bool,
+    /// Whether the country is a sovereign state with a UN code, by `UN_A3`. This is as close as
+    /// Natural Earth comes to UN membership.
+    pub un_member: bool,
+}
+
+impl CountryProps {
+    /// The country with `properties`. Properties that are there but cannot be read, such as a
+    /// population that is not a number, are left out, with why added to `warnings`.
+    pub fn from_properties(properties: &Properties, warnings: &mut Vec<String>) -> CountryProps {
+        let mut reader = Reader {
+            properties,
+            warnings,
+            feature: "?".to_string(),
+        };
+        let admin = reader.string(ADMIN).unwrap_or_default();
+        if !admin.is_empty() {
+            reader.feature = admin.clone();
+        }
+
+        let iso_a3 = reader.string(ISO_A3).filter(|code| !code.is_empty() && code != "-99");
+        let income = reader.string(INCOME_GRP).and_then(|value| {
+            let class = IncomeClass::from_value(&value);
+            if class.is_none() {
+                reader.warn(INCOME_GRP, &format!("unknown income group {:?}", value));
+            }
+            class
+        });
+        let mapcolor13 = reader.number(MAPCOLOR13).and_then(|number| {
+            let color = Some(number as u8).filter(|&color| {
+                f64::from(color) == number && (1..=13).contains(&color)
+            });
+            if color.is_none() {
+                reader.warn(MAPCOLOR13, &format!("{} is not a whole number from 1 to 13", number));
+            }
+            color
+        });
+
+        let kind = reader.string(TYPE);
+        let sovereign = reader.string(SOVEREIGNT).map_or(false, |sovereign| sovereign == admin)
+            && kind.map_or(true, |kind| !NOT_SOVEREIGN.contains(&kind.as_str()));
+        let un_code = reader.string(UN_A3).filter(|code| !code.is_empty() && code != "-099");
+
+        CountryProps {
+            id: iso_a3.clone().unwrap_or_else(|| admin.clone()),
+            name: reader.string(NAME).unwrap_or_default(),
+            admin,
+            iso_a3,
+            pop_est: reader.number(POP_EST).filter(|&population| population >= 0.0),
+            income,
+            mapcolor13,
+            sovereign,
+            un_member: sovereign && un_code.is_some(),
+        }
+    }
+}
+
+/// The properties of a place that its label reads.
+///
+/// The renderer's labels borrow their text, so unlike `CountryProps` this is read from the
+/// renderer's own copy of the properties as each label is drawn, and only borrows the name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaceProps<'a> {
+    pub name: Option<&'a str>,
+    /// The name in Latin letters alone, for fonts that cannot draw `name`.
+    pub latin_name: Option<&'a str>,
+    /// How far out the place is still labeled: the lower, the further.
+    pub min_zoom: Option<f64>,
+    pub is_capital: bool,
+    /// The name of the country the place is in.
+    pub country: Option<&'a str>,
+    /// Whether Natural Earth counts the place as a city of over about ten million.
+    pub is_megacity: bool,
+    /// How prominent the place is: the lower, the more.
+    pub rank: Option<f64>,
+    /// The population of the place's whole urban area.
+    pub population: Option<f64>,
+}
+
+impl<'a> PlaceProps<'a> {
+    /// The place with `properties`. Properties that are there but cannot be read are left out,
+    /// with why added to `warnings`.
+    pub fn from_properties(
+        properties: &'a Properties,
+        warnings: &mut Vec<String>,
+    ) -> PlaceProps<'a> {
+        let name = properties.get(NAME).and_then(Value::as_str);
+        let mut reader = Reader {
+            properties,
+            warnings,
+            feature: name.unwrap_or("?").to_string(),
+        };
+        for &key in &[NAME, NAMEASCII, ADM0NAME] {
+            if properties.get(key).map_or(false, |value| !value.is_string()) {
+                reader.warn(key, "is not a string");
+            }
+        }
+
+        PlaceProps {
+            name,
+            latin_name: properties.get(NAMEASCII).and_then(Value::as_str),
+            min_zoom: reader.number(MIN_ZOOM),
+            is_capital: reader.number(ADM0CAP) == Some(1.0),
+            country: properties.get(ADM0NAME).and_then(Value::as_str),
+            is_megacity: reader.number(MEGACITY) == Some(1.0),
+            rank: reader.number(SCALERANK),
+            population: reader.number(POP_MAX).filter(|&population| population >= 0.0),
+        }
+    }
+
+    /// The place with `properties`, for drawing, where problems were already reported when the
+    /// places were loaded.
+    pub fn read(properties: &'a Properties) -> PlaceProps<'a> {
+        PlaceProps {
+            name: properties.get(NAME).and_then(Value::as_str),
+            latin_name: properties.get(NAMEASCII).and_then(Value::as_str),
+            min_zoom: properties.get(MIN_ZOOM).and_then(Value::as_f64),
+            is_capital: properties.get(ADM0CAP).and_then(Value::as_f64) == Some(1.0),
+            country: properties.get(ADM0NAME).and_then(Value::as_str),
+            is_megacity: properties.get(MEGACITY).and_then(Value::as_f64) == Some(1.0),
+            rank: properties.get(SCALERANK).and_then(Value::as_f64).filter(|&rank| rank != UNKNOWN),
+            population: properties
+                .get(POP_MAX)
+                .and_then(Value::as_f64)
+                .filter(|&population| population >= 0.0),
+        }
+    }
+}
+
+/// Reads properties of one feature, noting those of the wrong type.
+struct Reader<'a, 'w> {
+    properties: &'a Properties,
+    warnings: &'w mut Vec<String>,
+    /// What the feature is called in warnings.
+    feature: String,
+}
+
+impl<'a, 'w> Reader<'a, 'w> {
+    fn warn(&mut self, key: &str, problem: &str) {
+        self.warnings.push(format!("{}: {} {}", self.feature, key, problem));
+    }
+
+    fn string(&mut self, key: &str) -> Option<String> {
+        match *self.properties.get(key)? {
+            Value::Null => None,
+            Value::String(ref string) => Some(string.clone()),
+            _ => {
+                self.warn(key, "is not a string");
+                None
+            }
+        }
+    }
+
+    /// The number `key` is, or `None` if it is missing or Natural Earth marks it as unknown.
+    fn number(&mut self, key: &str) -> Option<f64> {
+        match *self.properties.get(key)? {
+            Value::Null => None,
+            Value::Number(ref number) => number.as_f64().filter(|&number| number != UNKNOWN),
+            _ => {
+                self.warn(key, "is not a number");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Properties {
+        ::serde_json::from_str(json).unwrap()
+    }
+
+    fn country(json: &str) -> (CountryProps, Vec<String>) {
+        let mut warnings = vec![];
+        let props = CountryProps::from_properties(&parse(json), &mut warnings);
+        (props, warnings)
+    }
+
+    #[test]
+    fn reads_a_country() {
+        let (props, warnings) = country(
+            r#"{"NAME": "Chad", "ADMIN": "Chad", "ISO_A3": "TCD", "POP_EST": 10329208,
+                "INCOME_GRP": "5. Low income", "MAPCOLOR13": 6, "SOVEREIGNT": "Chad",
+                "TYPE": "Sovereign country", "UN_A3": "148"}"#,
+        );
+
+        assert_eq!(
+            props,
+            CountryProps {
+                id: "TCD".to_string(),
+                name: "Chad".to_string(),
+                admin: "Chad".to_string(),
+                iso_a3: Some("TCD".to_string()),
+                pop_est: Some(10_329_208.0),
+                income: Some(IncomeClass::Low),
+                mapcolor13: Some(6),
+                sovereign: true,
+                un_member: true,
+            }
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn unknown_values_are_missing() {
+        let (props, warnings) = country(
+            r#"{"ADMIN": "France", "ISO_A3": "-99", "POP_EST": -99, "MAPCOLOR13": -99}"#,
+        );
+
+        assert_eq!(props.id, "France");
+        assert_eq!(props.name, "");
+        assert_eq!(props.iso_a3, None);
+        assert_eq!(props.pop_est, None);
+        assert_eq!(props.mapcolor13, None);
+        assert_eq!(props.income, None);
+        assert!(!props.sovereign);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn dependencies_and_disputed_territories_are_not_sovereign() {
+        let read = |json: &str| {
+            let (props, _) = country(json);
+            (props.sovereign, props.un_member)
+        };
+
+        assert_eq!(
+            read(r#"{"ADMIN": "Greenland", "SOVEREIGNT": "Denmark", "TYPE": "Country",
+                "UN_A3": "304"}"#),
+            (false, false)
+        );
+        assert_eq!(
+            read(r#"{"ADMIN": "Kosovo", "SOVEREIGNT": "Kosovo", "TYPE": "Disputed",
+                "UN_A3": "-099"}"#),
+            (false, false)
+        );
+        assert_eq!(
+            read(r#"{"ADMIN": "Taiwan", "SOVEREIGNT": "Taiwan", "TYPE": "Sovereign country",
+                "UN_A3": "-099"}"#),
+            (true, false)
+        );
+    }
+
+    #[test]
+    fn conversion_problems_are_warnings() {
+        let (props, warnings) = country(
+            r#"{"ADMIN": "Nowhere", "NAME": 7, "POP_EST": "lots",
+                "INCOME_GRP": "6. Very rich", "MAPCOLOR13": 14.5}"#,
+        );
+
+        assert_eq!(props.name, "");
+        assert_eq!(props.pop_est, None);
+        assert_eq!(props.income, None);
+        assert_eq!(props.mapcolor13, None);
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert!(warnings.iter().all(|warning| warning.starts_with("Nowhere: ")));
+    }
+
+    #[test]
+    fn income_classes_round_trip() {
+        for (index, &class) in IncomeClass::ALL.iter().enumerate() {
+            assert_eq!(IncomeClass::from_value(class.value()), Some(class));
+            assert_eq!(class.index(), index);
+        }
+        assert_eq!(IncomeClass::from_value("High income: OECD"), None);
+    }
+
+    #[test]
+    fn reads_a_place() {
+        let properties = parse(
+            r#"{"NAME": "Paris", "NAMEASCII": "Paris", "min_zoom": 1.7, "ADM0CAP": 1,
+                "ADM0NAME": "France", "MEGACITY": 1, "SCALERANK": 0, "POP_MAX": 11000000}"#,
+        );
+        let mut warnings = vec![];
+        let place = PlaceProps::from_properties(&properties, &mut warnings);
+
+        assert_eq!((place.name, place.latin_name), (Some("Paris"), Some("Paris")));
+        assert_eq!(place.min_zoom, Some(1.7));
+        assert!(place.is_capital);
+        assert_eq!(place.country, Some("France"));
+        assert!(place.is_megacity);
+        assert_eq!((place.rank, place.population), (Some(0.0), Some(11e6)));
+        assert!(warnings.is_empty());
+        assert_eq!(PlaceProps::read(&properties), place);
+
+        let properties = parse(r#"{"NAME": ["Paris"], "min_zoom": "1.7"}"#);
+        let place = PlaceProps::from_properties(&properties, &mut warnings);
+        assert_eq!(
+            place,
+            PlaceProps {
+                name: None,
+                latin_name: None,
+                min_zoom: None,
+                is_capital: false,
+                country: None,
+                is_megacity: false,
+                rank: None,
+                population: None,
+            }
+        );
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    }
+}
@@ -0,0 +1,220 @@
+//! Where the loaded assets came from, so that it is clear which of several builds is in use.
+//!
+//! gaia_assetgen records nothing of the sort itself, so whoever builds the assets can drop a
+//! `provenance.json` into the assets directory, beside `generated`:
+//!
+//! ```json
+//! {
+//!     "sources": ["Natural Earth"],
+//!     "scale": "1:10m",
+//!     "generated": "2024-03-02T14:30:00Z"
+//! }
+//! ```
+//!
+//! Every field can be left out. `generated` is in RFC 3339, or just a date such as "2024-03-02".
+//! The help overlay and `--check` show it in full, and the frame times overlay in short, as in
+//! "NE 1:10m, built 2024-03-02". Assets without it are shown by their directory instead.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate};
+use serde_json;
+
+use {Result, ResultExt};
+
+/// The name of the file provenance is read from, in the assets directory.
+pub const FILE_NAME: &str = "provenance.json";
+
+/// Where an asset set came from, as its `provenance.json` has it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Provenance {
+    /// The datasets the assets were generated from, such as "Natural Earth".
+    pub sources: Vec<String>,
+    /// The scale of the source data, such as "1:10m".
+    pub scale: Option<String>,
+    /// When the assets were generated.
+    pub generated: Option<String>,
+}
+
+impl Provenance {
+    /// Reads the provenance of the assets in `assets_dir`, or `None` if they have none.
+    pub fn load(assets_dir: &Path) -> Result<Option<Provenance>> {
+        let path = assets_dir.join(FILE_NAME);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).chain_err(|| format!("Could not read {}", path.display()));
+            }
+        };
+
+        Provenance::parse(&text)
+            .map(Some)
+            .chain_err(|| format!("Could not load {}", path.display()))
+    }
+
+    /// The provenance in the JSON `text`. Fails if a field is not as documented, so that a typo
+    /// is not silently left out.
+    pub fn parse(text: &str) -> Result<Provenance> {
+        let provenance: Provenance = serde_json::from_str(text).chain_err(|| "Invalid JSON")?;
+        if let Some(ref generated) = provenance.generated {
+            if generated_date(generated).is_none() {
+                bail!(
+                    "Invalid generated {:?}: expected RFC 3339, such as 2024-03-02T14:30:00Z, \
+                     or a date, such as 2024-03-02",
+                    generated
+                );
+            }
+        }
+
+        Ok(provenance)
+    }
+
+    /// Such as "Natural Earth, 1:10m, generated 2024-03-02T14:30:00Z".
+    pub fn summary(&self) -> String {
+        let mut parts = vec![];
+        if !self.sources.is_empty() {
+            parts.push(self.sources.join(" and "));
+        }
+        parts.extend(self.scale.clone());
+        parts.extend(self.generated.as_ref().map(|generated| format!("generated {}", generated)));
+
+        parts.join(", ")
+    }
+
+    /// Such as "NE 1:10m, built 2024-03-02", with each source of more than one word shortened
+    /// to its initials.
+    pub fn short_summary(&self) -> String {
+        let sources: Vec<String> = self.sources.iter().map(|source| initials(source)).collect();
+        let mut what = vec![];
+        if !sources.is_empty() {
+            what.push(sources.join("+"));
+        }
+        what.extend(self.scale.clone());
+
+        let mut parts = vec![];
+        if !what.is_empty() {
+            parts.push(what.join(" "));
+        }
+        let generated = self.generated.as_ref().and_then(|generated| generated_date(generated));
+        parts.extend(generated.map(|date| format!("built {}", date.format("%Y-%m-%d"))));
+
+        parts.join(", ")
+    }
+}
+
+/// What the help overlay and `--check` say the assets in `assets_dir`, with `provenance`, are:
+/// its summary, or the directory if it has none.
+pub fn describe(provenance: Option<&Provenance>, assets_dir: &Path) -> String {
+    provenance
+        .map(Provenance::summary)
+        .filter(|summary| !summary.is_empty())
+        .unwrap_or_else(|| assets_dir.display().to_string())
+}
+
+/// `describe`, in short.
+pub fn describe_short(provenance: Option<&Provenance>, assets_dir: &Path) -> String {
+    provenance
+        .map(Provenance::short_summary)
+        .filter(|summary| !summary.is_empty())
+        .unwrap_or_else(|| assets_dir.display().to_string())
+}
+
+/// The day of `generated`, in RFC 3339 or a date alone, as it was written.
+fn generated_date(generated: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(generated)
+        .map(|datetime| datetime.naive_local().date())
+        .or_else(|_| NaiveDate::parse_from_str(generated, "%Y-%m-%d"))
+        .ok()
+}
+
+/// "NE" for "Natural Earth", and a name of one word as it is.
+fn initials(name: &str) -> String {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    if words.len() < 2 {
+        return name.trim().to_string();
+    }
+
+    words.iter().filter_map(|word| word.chars().next()).flat_map(char::to_uppercase).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    const NATURAL_EARTH: &str = r#"{
+        "sources": ["Natural Earth"],
+        "scale": "1:10m",
+        "generated": "2024-03-02T14:30:00Z"
+    }"#;
+
+    /// An empty directory of its own for each test.
+    fn empty_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("gaia-demo-provenance-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn provenance_is_summarized_in_full_and_in_short() {
+        let provenance = Provenance::parse(NATURAL_EARTH).unwrap();
+        assert_eq!(provenance.sources, ["Natural Earth"]);
+        assert_eq!(provenance.summary(), "Natural Earth, 1:10m, generated 2024-03-02T14:30:00Z");
+        assert_eq!(provenance.short_summary(), "NE 1:10m, built 2024-03-02");
+
+        let provenance = Provenance {
+            sources: vec!["Natural Earth".to_string(), "SRTM".to_string()],
+            scale: None,
+            generated: Some("2024-03-02".to_string()),
+        };
+        assert_eq!(provenance.summary(), "Natural Earth and SRTM, generated 2024-03-02");
+        assert_eq!(provenance.short_summary(), "NE+SRTM, built 2024-03-02");
+    }
+
+    #[test]
+    fn every_field_can_be_left_out() {
+        let provenance = Provenance::parse(r#"{"scale": "1:50m"}"#).unwrap();
+        assert_eq!(provenance.short_summary(), "1:50m");
+
+        let empty = Provenance::parse("{}").unwrap();
+        assert_eq!(empty, Provenance::default());
+        let dir = Path::new("sets/coarse/assets");
+        assert_eq!(describe(Some(&empty), dir), "sets/coarse/assets");
+        assert_eq!(describe_short(None, dir), "sets/coarse/assets");
+    }
+
+    #[test]
+    fn fields_not_as_documented_are_errors() {
+        for text in &[
+            "not json",
+            r#"{"sources": "Natural Earth"}"#,
+            r#"{"scael": "1:10m"}"#,
+            r#"{"generated": "March 2nd"}"#,
+        ] {
+            assert!(Provenance::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn provenance_is_read_from_the_assets_directory() {
+        let dir = empty_dir("load");
+        assert_eq!(Provenance::load(&dir).unwrap(), None);
+
+        fs::write(dir.join(FILE_NAME), NATURAL_EARTH).unwrap();
+        let provenance = Provenance::load(&dir).unwrap().unwrap();
+        assert_eq!(describe_short(Some(&provenance), &dir), "NE 1:10m, built 2024-03-02");
+
+        fs::write(dir.join(FILE_NAME), "{").unwrap();
+        let error = Provenance::load(&dir).unwrap_err().to_string();
+        assert!(error.contains(FILE_NAME), "{}", error);
+    }
+}
@@ -0,0 +1,168 @@
+use {Error, Result, ResultExt};
+
+/// How many frames in a row can fail to draw before the demo gives up.
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 8;
+
+/// Keeps the demo running through errors drawing a frame, such as a corrupt tile or a texture
+/// that could not be allocated. After a failure, the map is not drawn for a while, which doubles
+/// with each failure in a row, before it is tried again. The HUD is still drawn meanwhile, so that
+/// the warning can be seen. Only once `MAX_CONSECUTIVE_FAILURES` frames in a row have failed is
+/// the error returned, to end the demo.
+#[derive(Debug, Default)]
+pub struct RenderRecovery {
+    /// How many frames in a row have failed to draw.
+    failures: u32,
+    /// How many more frames to skip drawing the map in before trying again.
+    skip: u32,
+    /// What went wrong last, while it is still going wrong.
+    warning: Option<String>,
+}
+
+impl RenderRecovery {
+    pub fn new() -> RenderRecovery {
+        RenderRecovery {
+            failures: 0,
+            skip: 0,
+            warning: None,
+        }
+    }
+
+    /// Whether to try drawing the map in the frame about to be drawn. Called once per frame.
+    pub fn should_draw(&mut self) -> bool {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// Records how drawing a frame went. Returns whether a failure was recovered from, or the
+    /// error to end the demo with once too many frames in a row have failed.
+    pub fn record(&mut self, drawn: Result<()>) -> Result<bool> {
+        let e = match drawn {
+            Ok(()) => {
+                let recovered = self.failures > 0;
+                if recovered {
+                    info!("Drawing recovered after {} failed frames", self.failures);
+                }
+                *self = RenderRecovery::new();
+                return Ok(recovered);
+            }
+            Err(e) => e,
+        };
+
+        self.failures += 1;
+        if self.failures >= MAX_CONSECUTIVE_FAILURES {
+            let failures = self.failures;
+            return Err(e).chain_err(|| {
+                format!("Gave up after {} frames in a row failed to draw", failures)
+            });
+        }
+
+        let message = describe(&e);
+        self.skip = 1 << (self.failures - 1);
+        warn!(
+            "Frame {} in a row failed to draw, trying again in {} frames: {}",
+            self.failures, self.skip, message
+        );
+        self.warning = Some(format!("Could not draw the map: {}", message));
+        Ok(false)
+    }
+
+    /// The warning to show while frames are failing to draw.
+    pub fn warning(&self) -> Option<&str> {
+        self.warning.as_deref()
+    }
+}
+
+/// `e` and what caused it, on one line.
+fn describe(e: &Error) -> String {
+    e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the renderer, failing to draw the first `failures` frames it is asked to.
+    struct FailingRenderer {
+        failures: u32,
+        calls: u32,
+    }
+
+    impl FailingRenderer {
+        fn new(failures: u32) -> FailingRenderer {
+            FailingRenderer { failures, calls: 0 }
+        }
+
+        fn render(&mut self) -> Result<()> {
+            self.calls += 1;
+            if self.failures == 0 {
+                return Ok(());
+            }
+
+            self.failures -= 1;
+            Err("Could not read tile 3/1/2".into())
+        }
+    }
+
+    /// Runs `frames` frames with `renderer`. Returns which of them the map was drawn in, or the
+    /// error the demo would end with.
+    fn run(
+        recovery: &mut RenderRecovery,
+        renderer: &mut FailingRenderer,
+        frames: usize,
+    ) -> Result<Vec<bool>> {
+        let mut drawn = vec![];
+        for _ in 0..frames {
+            if recovery.should_draw() {
+                let result = renderer.render();
+                drawn.push(result.is_ok());
+                recovery.record(result)?;
+            } else {
+                drawn.push(false);
+            }
+        }
+        Ok(drawn)
+    }
+
+    #[test]
+    fn backs_off_and_recovers_from_failing_frames() {
+        let mut recovery = RenderRecovery::new();
+        let mut renderer = FailingRenderer::new(3);
+
+        let drawn = run(&mut recovery, &mut renderer, 11).unwrap();
+        // Tried in frames 0, 2, 5 and 10, skipping 1, 2 and then 4 frames.
+        assert_eq!(drawn, (0..11).map(|frame| frame == 10).collect::<Vec<_>>());
+        assert_eq!(renderer.calls, 4);
+        assert_eq!(recovery.warning(), None);
+    }
+
+    #[test]
+    fn warns_while_frames_fail() {
+        let mut recovery = RenderRecovery::new();
+        let mut renderer = FailingRenderer::new(1);
+
+        assert!(recovery.should_draw());
+        assert!(!recovery.record(renderer.render()).unwrap());
+        assert_eq!(recovery.warning(), Some("Could not draw the map: Could not read tile 3/1/2"));
+
+        assert!(!recovery.should_draw());
+        assert!(recovery.should_draw());
+        assert!(recovery.record(renderer.render()).unwrap());
+        assert_eq!(recovery.warning(), None);
+    }
+
+    #[test]
+    fn gives_up_after_too_many_failures_in_a_row() {
+        let mut recovery = RenderRecovery::new();
+        let mut renderer = FailingRenderer::new(u32::max_value());
+
+        let e = run(&mut recovery, &mut renderer, 1000).unwrap_err();
+        assert_eq!(renderer.calls, MAX_CONSECUTIVE_FAILURES);
+        assert_eq!(
+            describe(&e),
+            "Gave up after 8 frames in a row failed to draw: Could not read tile 3/1/2"
+        );
+    }
+}
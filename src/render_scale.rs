@@ -0,0 +1,166 @@
+use gfx;
+use gfx::format::{DepthStencil, Srgba8};
+use gfx::handle::{DepthStencilView, RenderTargetView};
+use gfx::traits::FactoryExt;
+use piston::window::Size;
+use piston_window::{Context, Graphics, Image, Texture, Transformed};
+
+use split_view::View;
+use Result;
+
+/// An offscreen target the map is drawn to at a fraction of the window's resolution, and then
+/// stretched over the window, so that slow GPUs have fewer pixels to fill. The HUD is drawn over
+/// it at the window's own resolution, so that text stays sharp.
+pub struct ScaledTarget<R: gfx::Resources> {
+    /// In pixels.
+    size: [u16; 2],
+    pub color: RenderTargetView<R, Srgba8>,
+    pub depth: DepthStencilView<R, DepthStencil>,
+    /// `color`, as a texture to draw over the window.
+    texture: Texture<R>,
+}
+
+impl<R: gfx::Resources> ScaledTarget<R> {
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, size: [u16; 2]) -> Result<ScaledTarget<R>> {
+        let [width, height] = size;
+        let (surface, view, color) = factory
+            .create_render_target::<Srgba8>(width, height)
+            .map_err(|e| format!("Could not create the scaled color target: {:?}", e))?;
+        let depth = factory
+            .create_depth_stencil_view_only::<DepthStencil>(width, height)
+            .map_err(|e| format!("Could not create the scaled depth target: {:?}", e))?;
+
+        Ok(ScaledTarget {
+            size,
+            color,
+            depth,
+            texture: Texture {
+                surface,
+                sampler: factory.create_sampler_linear(),
+                view,
+            },
+        })
+    }
+
+    /// Stretches what was drawn over the part of `area`, where the map is drawn in the view of
+    /// `context`, that `view` covers.
+    pub fn draw<G>(&self, view: View, area: [f64; 4], context: Context, graphics: &mut G)
+    where
+        G: Graphics<Texture = Texture<R>>,
+    {
+        self.draw_at(view.rect(area), context, graphics);
+    }
+
+    /// Stretches what was drawn over `rect`, as `[x, y, width, height]` in the view of `context`.
+    pub fn draw_at<G>(&self, rect: [f64; 4], context: Context, graphics: &mut G)
+    where
+        G: Graphics<Texture = Texture<R>>,
+    {
+        self.draw_tinted(rect, [1.0; 4], context, graphics);
+    }
+
+    /// Like `draw_at`, with each pixel's color multiplied by `tint`, and blended as the draw
+    /// state of `context` says.
+    pub fn draw_tinted<G>(
+        &self,
+        rect: [f64; 4],
+        tint: [f32; 4],
+        context: Context,
+        graphics: &mut G,
+    ) where
+        G: Graphics<Texture = Texture<R>>,
+    {
+        let [x, y, width, height] = rect;
+        // OpenGL stores the rows it renders bottom to top, so the image is flipped to be upright.
+        let transform = context.transform.trans(x, y + height).scale(
+            width / f64::from(self.size[0]),
+            -height / f64::from(self.size[1]),
+        );
+
+        Image::new_color(tint).draw(&self.texture, &context.draw_state, transform, graphics);
+    }
+}
+
+/// Keeps a `ScaledTarget` for each view the map is drawn in, the size that the render scale and
+/// the window's size call for, recreating them when either changes.
+pub struct RenderScale<R: gfx::Resources> {
+    targets: Vec<(View, ScaledTarget<R>)>,
+}
+
+impl<R: gfx::Resources> RenderScale<R> {
+    pub fn new() -> RenderScale<R> {
+        RenderScale { targets: vec![] }
+    }
+
+    /// Makes `targets` draw the map at `scale` times `draw_size`, in pixels, in each of `views`.
+    /// The halves of a split-screen, and a map `letterboxed` to less than the whole window,
+    /// always get a target, even at a scale of 1, since the map is drawn to the whole of
+    /// whatever it is drawn to.
+    pub fn update<F>(
+        &mut self,
+        factory: &mut F,
+        draw_size: Size,
+        scale: f32,
+        views: &[View],
+        letterboxed: bool,
+    ) -> Result<()>
+    where
+        F: gfx::Factory<R>,
+    {
+        if scale >= 1.0 && views == [View::Whole] && !letterboxed {
+            self.targets.clear();
+            return Ok(());
+        }
+
+        let scale = scale.min(1.0);
+        let scaled = |pixels: u32| (pixels as f32 * scale).round().max(1.0).min(65535.0) as u16;
+        let wanted: Vec<_> = views
+            .iter()
+            .map(|&view| {
+                let size = view.draw_size(draw_size);
+                (view, [scaled(size.width), scaled(size.height)])
+            })
+            .collect();
+        let current: Vec<_> = self
+            .targets
+            .iter()
+            .map(|&(view, ref target)| (view, target.size))
+            .collect();
+        if current != wanted {
+            // The old targets are freed first, so that both are never held at once.
+            self.targets.clear();
+            for (view, size) in wanted {
+                self.targets.push((view, ScaledTarget::new(factory, size)?));
+                debug!("Drawing the map at {}x{} in {:?}", size[0], size[1], view);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The targets to draw the map to, with the views they are drawn over, or none to draw it
+    /// straight to the window at a scale of 1.
+    pub fn targets(&self) -> &[(View, ScaledTarget<R>)] {
+        &self.targets
+    }
+
+    /// `scale`, and the resolution the map is drawn at, as shown in the performance overlay.
+    pub fn status(&self, scale: f32) -> String {
+        match self.targets.first() {
+            Some((_, target)) => format!(
+                "Render scale: {:.0}% ({}{}x{})",
+                scale.min(1.0) * 100.0,
+                if self.targets.len() > 1 { "2 × " } else { "" },
+                target.size[0],
+                target.size[1]
+            ),
+            None => "Render scale: 100%".to_string(),
+        }
+    }
+}
+
+impl<R: gfx::Resources> Default for RenderScale<R> {
+    fn default() -> RenderScale<R> {
+        RenderScale::new()
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gif::{self, Encoder, Repeat, SetParameter};
+
+use screenshot::{self, Readback};
+use settings::DiagnosticsSettings;
+use {Result, ResultExt};
+
+/// The most memory the frames are allowed to take, whatever the settings and window size. The
+/// oldest frames are dropped to stay under it.
+const MAX_BYTES: usize = 256 * 1024 * 1024;
+
+/// A frame kept in the buffer.
+struct Frame {
+    image: Readback,
+    captured: Instant,
+}
+
+/// How saving the buffer is going.
+#[derive(Debug)]
+pub enum Progress {
+    /// So many of the frames have been encoded, out of the total.
+    Encoded(usize, usize),
+    /// The GIF was written to the path, or could not be.
+    Finished(Result<PathBuf>),
+}
+
+/// Keeps the last few seconds of frames in memory, shrunk and at a lower frame rate, so that
+/// they can be saved as an animated GIF after something unexpected happened. The frames are
+/// encoded on a background thread, since quantizing their colors takes a while.
+pub struct ReplayBuffer {
+    settings: DiagnosticsSettings,
+    frames: VecDeque<Frame>,
+    /// How much memory `frames` take.
+    bytes: usize,
+    last_capture: Option<Instant>,
+    /// Progress from the thread saving the buffer, while it is.
+    saving: Option<mpsc::Receiver<Progress>>,
+}
+
+impl ReplayBuffer {
+    pub fn new(settings: DiagnosticsSettings) -> ReplayBuffer {
+        ReplayBuffer {
+            settings,
+            frames: VecDeque::new(),
+            bytes: 0,
+            last_capture: None,
+            saving: None,
+        }
+    }
+
+    /// Whether enough time has passed since the last frame was captured that the next one drawn
+    /// should be.
+    pub fn wants_frame(&self) -> bool {
+        let interval = Duration::from_secs_f32(1.0 / self.settings.replay_fps);
+
+        self.last_capture.map_or(true, |last| last.elapsed() >= interval)
+    }
+
+    /// Adds `frame`, read back at the window's resolution, shrinking it first. Frames older
+    /// than the buffer holds are dropped.
+    pub fn push(&mut self, frame: &Readback) {
+        let now = Instant::now();
+        self.last_capture = Some(now);
+
+        let factor = (1.0 / self.settings.replay_scale).round().max(1.0) as u32;
+        let image = frame.downscaled(factor);
+        self.bytes += image.len_bytes();
+        self.frames.push_back(Frame {
+            image,
+            captured: now,
+        });
+
+        let max_age = Duration::from_secs_f32(self.settings.replay_secs);
+        while let Some(oldest) = self.frames.front() {
+            if now.duration_since(oldest.captured) <= max_age && self.bytes <= MAX_BYTES {
+                break;
+            }
+
+            self.bytes -= oldest.image.len_bytes();
+            self.frames.pop_front();
+        }
+    }
+
+    /// How many seconds of frames are held.
+    pub fn secs(&self) -> f64 {
+        match (self.frames.front(), self.frames.back()) {
+            (Some(first), Some(last)) => last.captured.duration_since(first.captured).as_secs_f64(),
+            _ => 0.0,
+        }
+    }
+
+    /// Starts saving the frames held so far to a GIF in `dir`, named after the current time,
+    /// while frames keep being captured. Fails if there are none, or they are still being saved
+    /// from before.
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        if self.saving.is_some() {
+            bail!("The last replay is still being saved");
+        }
+        if self.frames.is_empty() {
+            bail!("No frames have been captured yet");
+        }
+
+        let path = screenshot::timestamped_path(dir, "gaia-replay", "", ".gif", Path::exists);
+        let frames: Vec<(Readback, Instant)> = self
+            .frames
+            .iter()
+            .map(|frame| (frame.image.clone(), frame.captured))
+            .collect();
+        let ended = Instant::now();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("replay-gif".to_string())
+            .spawn(move || {
+                let written = write_gif(&path, &frames, ended, &sender).map(|_| path);
+                let _ = sender.send(Progress::Finished(written));
+            })
+            .chain_err(|| "Could not start saving the replay")?;
+        self.saving = Some(receiver);
+
+        Ok(())
+    }
+
+    /// The progress made saving the buffer since last called.
+    pub fn progress(&mut self) -> Vec<Progress> {
+        let progress: Vec<Progress> = match self.saving {
+            Some(ref receiver) => receiver.try_iter().collect(),
+            None => return Vec::new(),
+        };
+
+        let finished = progress.iter().any(|progress| match *progress {
+            Progress::Finished(_) => true,
+            Progress::Encoded(_, _) => false,
+        });
+        if finished {
+            self.saving = None;
+        }
+
+        progress
+    }
+}
+
+/// Writes `frames` to `path` as a looping GIF, each shown until the next was captured, and the
+/// last until `ended`. Frames of another size than the last, from before the window was resized,
+/// are left out, since a GIF has a single size.
+fn write_gif(
+    path: &Path,
+    frames: &[(Readback, Instant)],
+    ended: Instant,
+    progress: &mpsc::Sender<Progress>,
+) -> Result<()> {
+    let size = match frames.last() {
+        Some((last, _)) => (last.width(), last.height()),
+        None => bail!("No frames to save"),
+    };
+    let frames: Vec<&(Readback, Instant)> = frames
+        .iter()
+        .filter(|&(image, _)| (image.width(), image.height()) == size)
+        .collect();
+
+    let file = File::create(path).chain_err(|| format!("Could not create {}", path.display()))?;
+    let mut encoder = Encoder::new(BufWriter::new(file), size.0 as u16, size.1 as u16, &[])
+        .chain_err(|| format!("Could not write {}", path.display()))?;
+    encoder
+        .set(Repeat::Infinite)
+        .chain_err(|| format!("Could not write {}", path.display()))?;
+
+    for (i, &&(ref image, captured)) in frames.iter().enumerate() {
+        let next = frames.get(i + 1).map_or(ended, |&&(_, next)| next);
+        let mut pixels = image.top_down_pixels();
+        let mut frame = gif::Frame::from_rgba(size.0 as u16, size.1 as u16, &mut pixels);
+        // In hundredths of a second.
+        frame.delay = (next.duration_since(captured).as_millis() / 10).max(1) as u16;
+
+        encoder
+            .write_frame(&frame)
+            .chain_err(|| format!("Could not write {}", path.display()))?;
+        let _ = progress.send(Progress::Encoded(i + 1, frames.len()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,482 @@
+use cgmath::Matrix4;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use coordinates;
+use formatting;
+use hud::HudStyle;
+use projection::{self, to_screen};
+use settings::FormattingSettings;
+
+const FONT_SIZE: u32 = 10;
+
+/// The earth's mean radius.
+const EARTH_RADIUS_KM: f64 = 6_371.0;
+
+/// How long each drawn segment of a route is, in world units at a camera height of 1. Segments
+/// get shorter closer in, so that routes stay smooth however far the camera is zoomed in.
+const SEGMENT_LENGTH: f64 = 0.02;
+
+/// The fewest and most segments a route is drawn with.
+const SEGMENTS_RANGE: (usize, usize) = (16, 4_096);
+
+/// How far from a route's line the cursor can be, in points, and still be over it.
+const HOVER_DISTANCE: f64 = 5.0;
+
+/// The radius of the marker drawn for a route's first endpoint while the second is picked.
+const PENDING_RADIUS: f64 = 4.0;
+
+/// The radius of the marker travelling along a route.
+const TRAVELLER_RADIUS: f64 = 6.0;
+
+/// How far apart, as a fraction of a route, the points its heading is worked out between are.
+const HEADING_STEP: f64 = 1e-4;
+
+/// The shortest path over the globe between two points, along a great circle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    /// Latitude and longitude, in degrees.
+    pub from: [f64; 2],
+    pub to: [f64; 2],
+}
+
+impl Route {
+    /// How long the route is over the ground, on a spherical earth.
+    pub fn distance_km(&self) -> f64 {
+        self.central_angle() * EARTH_RADIUS_KM
+    }
+
+    /// The angle between the endpoints seen from the center of the earth, in radians.
+    fn central_angle(&self) -> f64 {
+        let ([from_lat, from_lon], [to_lat, to_lon]) = (self.from, self.to);
+        let (from_lat, to_lat) = (from_lat.to_radians(), to_lat.to_radians());
+        let half_lat = (to_lat - from_lat) / 2.0;
+        let half_lon = (to_lon - from_lon).to_radians() / 2.0;
+
+        // The haversine formula, which stays accurate for short routes.
+        let a = half_lat.sin().powi(2) + from_lat.cos() * to_lat.cos() * half_lon.sin().powi(2);
+        2.0 * a.sqrt().min(1.0).asin()
+    }
+
+    /// The latitude and longitude `fraction` of the way along the route.
+    fn point_along(&self, fraction: f64) -> [f64; 2] {
+        let angle = self.central_angle();
+        if angle < 1e-12 {
+            return self.from;
+        }
+
+        let [from, to] = [unit_vector(self.from), unit_vector(self.to)];
+        let (a, b) = (
+            ((1.0 - fraction) * angle).sin() / angle.sin(),
+            (fraction * angle).sin() / angle.sin(),
+        );
+        let [x, y, z] = [
+            a * from[0] + b * to[0],
+            a * from[1] + b * to[1],
+            a * from[2] + b * to[2],
+        ];
+
+        [z.atan2(x.hypot(y)).to_degrees(), y.atan2(x).to_degrees()]
+    }
+
+    /// The latitude and longitude `km` along the route, or at its end if it is not that long.
+    pub fn point_at_km(&self, km: f64) -> [f64; 2] {
+        self.point_along(self.fraction_at_km(km))
+    }
+
+    /// Which way the route heads on the map `km` along it, in degrees clockwise from north. The
+    /// map stretches east to west away from the equator, so this is not the compass bearing.
+    pub fn heading_at_km(&self, km: f64) -> f64 {
+        let fraction = self.fraction_at_km(km).min(1.0 - HEADING_STEP);
+        let [from_lat, from_lon] = self.point_along(fraction);
+        let [to_lat, to_lon] = self.point_along(fraction + HEADING_STEP);
+        let east = (to_lon - from_lon + 180.0).rem_euclid(360.0) - 180.0;
+
+        east.atan2(to_lat - from_lat).to_degrees()
+    }
+
+    fn fraction_at_km(&self, km: f64) -> f64 {
+        let distance = self.distance_km();
+        if distance > 0.0 {
+            (km / distance).max(0.0).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// How many segments to draw the route with at `camera_height`.
+    fn segments(&self, camera_height: f32) -> usize {
+        let (min, max) = SEGMENTS_RANGE;
+        // A radian of a great circle is at most 1 / pi world units long, along the equator.
+        let length = self.central_angle() / ::std::f64::consts::PI;
+        let segment = SEGMENT_LENGTH * f64::from(camera_height.max(1e-3));
+
+        ((length / segment).ceil() as usize).max(min).min(max)
+    }
+
+    /// The route as world-space points, with each longitude within half a turn of the previous
+    /// one's, so that a route over the antimeridian carries on past it instead of jumping.
+    fn world_points(&self, camera_height: f32) -> Vec<[f32; 2]> {
+        let segments = self.segments(camera_height);
+        let mut points: Vec<[f32; 2]> = Vec::with_capacity(segments + 1);
+
+        for i in 0..=segments {
+            let [latitude, longitude] = self.point_along(i as f64 / segments as f64);
+            let [mut x, y] = coordinates::to_world_point(latitude, longitude);
+            if let Some(&[previous, _]) = points.last() {
+                x += 2.0 * ((previous - x) / 2.0).round();
+            }
+            points.push([x, y]);
+        }
+        points
+    }
+}
+
+/// A marker travelling along one of the routes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Journey {
+    /// The index of the route in the routes it was started on.
+    pub route: usize,
+    pub travelled_km: f64,
+    pub paused: bool,
+}
+
+impl Journey {
+    /// A journey from the start of the route with index `route`.
+    pub fn new(route: usize) -> Journey {
+        Journey {
+            route,
+            travelled_km: 0.0,
+            paused: false,
+        }
+    }
+
+    /// Travels `dt` seconds at `km_per_hour` along `route`, unless paused, stopping at its end.
+    /// Returns whether the end was reached by this call.
+    pub fn advance(&mut self, route: &Route, km_per_hour: f64, dt: f64) -> bool {
+        if self.paused || self.is_finished(route) {
+            return false;
+        }
+
+        let travelled = self.travelled_km + km_per_hour * dt / 3600.0;
+        self.travelled_km = travelled.min(route.distance_km());
+        self.is_finished(route)
+    }
+
+    pub fn is_finished(&self, route: &Route) -> bool {
+        self.travelled_km >= route.distance_km()
+    }
+
+    /// Whether the marker is moving.
+    pub fn is_moving(&self, route: &Route) -> bool {
+        !self.paused && !self.is_finished(route)
+    }
+
+    /// Such as "Travelled 1,204 km, 9,311 km to go".
+    pub fn status(&self, route: &Route, format: FormattingSettings) -> String {
+        let remaining = route.distance_km() - self.travelled_km;
+        let mut status = format!(
+            "Travelled {}, {} to go",
+            formatting::distance(self.travelled_km, 0, format),
+            formatting::distance(remaining, 0, format)
+        );
+        if self.paused {
+            status.push_str(" (paused)");
+        }
+        status
+    }
+}
+
+fn unit_vector([latitude, longitude]: [f64; 2]) -> [f64; 3] {
+    let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+
+    [
+        latitude.cos() * longitude.cos(),
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+    ]
+}
+
+/// Where a route is drawn this frame.
+#[derive(Clone, Debug)]
+pub struct ProjectedRoute {
+    /// The route's index in the list it was projected from.
+    pub index: usize,
+    /// The parts of the route in front of the camera, in the 2D pass's coordinates.
+    pub lines: Vec<Vec<[f64; 2]>>,
+    /// The route's distance, written beside its middle.
+    pub label: String,
+    /// Where `label` is written, if the middle of the route is in front of the camera.
+    pub label_at: Option<[f64; 2]>,
+}
+
+/// The latitude and longitude, in degrees, as a point of the 2D pass through `mvp`, at its copy
+/// nearest `look_at`. `None` if it is behind the camera.
+pub fn project_point(
+    mvp: Matrix4<f32>,
+    look_at: [f32; 2],
+    [latitude, longitude]: [f64; 2],
+    view_size: [f64; 2],
+) -> Option<[f64; 2]> {
+    let point = coordinates::to_world_point(latitude, longitude);
+    projection::project(mvp, look_at, point, view_size)
+}
+
+/// Where each of `routes` is drawn through `mvp`, sampled more finely the lower `camera_height`
+/// is, with its distance written in `format`. The map repeats horizontally, so each route is
+/// drawn at its copy nearest `look_at`, and the parts of it behind the camera are left out.
+pub fn project(
+    routes: &[Route],
+    mvp: Matrix4<f32>,
+    look_at: [f32; 2],
+    camera_height: f32,
+    view_size: [f64; 2],
+    format: FormattingSettings,
+) -> Vec<ProjectedRoute> {
+    routes
+        .iter()
+        .enumerate()
+        .map(|(index, route)| {
+            let points = route.world_points(camera_height);
+            let middle = points[points.len() / 2];
+            let offset = projection::nearest_copy(middle[0], look_at) - middle[0];
+
+            let mut lines = vec![];
+            let mut line = vec![];
+            for &[x, y] in &points {
+                match to_screen(mvp, [x + offset, y], view_size) {
+                    Some(point) => line.push(point),
+                    None if line.len() > 1 => lines.push(::std::mem::take(&mut line)),
+                    None => line.clear(),
+                }
+            }
+            if line.len() > 1 {
+                lines.push(line);
+            }
+
+            ProjectedRoute {
+                index,
+                lines,
+                label: formatting::distance(route.distance_km(), 0, format),
+                label_at: to_screen(mvp, [middle[0] + offset, middle[1]], view_size),
+            }
+        })
+        .collect()
+}
+
+/// What is drawn along with the routes, in the 2D pass's coordinates where it is a point.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Marks {
+    /// The index of the route under the cursor, which is highlighted.
+    pub hovered: Option<usize>,
+    /// The index of the route that plays, which is drawn thicker.
+    pub selected: Option<usize>,
+    /// The first endpoint of a route still being picked.
+    pub pending: Option<[f64; 2]>,
+    /// The marker travelling along a route.
+    pub traveller: Option<[f64; 2]>,
+}
+
+/// Draws each projected route as a line with its distance beside its middle, and `marks` over
+/// them.
+pub fn draw(
+    projected: &[ProjectedRoute],
+    marks: Marks,
+    style: HudStyle,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+    let outline = theme.opaque_background();
+
+    for route in projected {
+        let color = if marks.hovered == Some(route.index) {
+            theme.warning
+        } else {
+            theme.accent
+        };
+        let width = if marks.selected == Some(route.index) {
+            scale.len(2.0)
+        } else {
+            scale.len(1.0)
+        };
+
+        for line in &route.lines {
+            for pair in line.windows(2) {
+                let segment = [pair[0][0], pair[0][1], pair[1][0], pair[1][1]];
+                ::piston_window::line(outline, width + 1.0, segment, context.transform, graphics);
+            }
+            for pair in line.windows(2) {
+                let segment = [pair[0][0], pair[0][1], pair[1][0], pair[1][1]];
+                ::piston_window::line(color, width, segment, context.transform, graphics);
+            }
+        }
+
+        if let Some([x, y]) = route.label_at {
+            scale.draw_text(
+                &route.label,
+                FONT_SIZE,
+                theme.text,
+                context.trans(x + scale.len(4.0), y - scale.len(4.0)),
+                glyphs,
+                graphics,
+            );
+        }
+    }
+
+    if let Some([x, y]) = marks.pending {
+        let r = scale.len(PENDING_RADIUS);
+        ::piston_window::ellipse(
+            theme.accent,
+            [x - r, y - r, 2.0 * r, 2.0 * r],
+            context.transform,
+            graphics,
+        );
+    }
+
+    if let Some([x, y]) = marks.traveller {
+        let r = scale.len(TRAVELLER_RADIUS);
+        ::piston_window::ellipse(
+            outline,
+            [x - r - 1.0, y - r - 1.0, 2.0 * (r + 1.0), 2.0 * (r + 1.0)],
+            context.transform,
+            graphics,
+        );
+        ::piston_window::ellipse(
+            theme.warning,
+            [x - r, y - r, 2.0 * r, 2.0 * r],
+            context.transform,
+            graphics,
+        );
+    }
+}
+
+/// The index of the route whose line `cursor` is over, the nearest if it is over several.
+pub fn hovered(projected: &[ProjectedRoute], cursor: [f64; 2]) -> Option<usize> {
+    projected
+        .iter()
+        .filter_map(|route| {
+            route
+                .lines
+                .iter()
+                .flat_map(|line| line.windows(2))
+                .map(|pair| distance_to_segment(cursor, pair[0], pair[1]))
+                .fold(None, |nearest: Option<f64>, d| Some(nearest.map_or(d, |n| n.min(d))))
+                .filter(|&distance| distance <= HOVER_DISTANCE)
+                .map(|distance| (route.index, distance))
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(index, _)| index)
+}
+
+fn distance_to_segment(point: [f64; 2], start: [f64; 2], end: [f64; 2]) -> f64 {
+    let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((point[0] - start[0]) * dx + (point[1] - start[1]) * dy) / length_squared)
+            .max(0.0)
+            .min(1.0)
+    } else {
+        0.0
+    };
+
+    (start[0] + t * dx - point[0]).hypot(start[1] + t * dy - point[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: [f64; 2] = [51.5074, -0.1278];
+    const NEW_YORK: [f64; 2] = [40.7128, -74.006];
+
+    fn route(from: [f64; 2], to: [f64; 2]) -> Route {
+        Route { from, to }
+    }
+
+    fn assert_near(a: f64, b: f64, within: f64) {
+        assert!((a - b).abs() <= within, "{} is not within {} of {}", a, within, b);
+    }
+
+    #[test]
+    fn routes_follow_great_circles() {
+        let atlantic = route(LONDON, NEW_YORK);
+        assert_near(atlantic.distance_km(), 5570.0, 5.0);
+
+        // The great circle bows north of both cities.
+        let [latitude, _] = atlantic.point_at_km(atlantic.distance_km() / 2.0);
+        assert!(latitude > LONDON[0], "{}", latitude);
+        let [latitude, longitude] = atlantic.point_at_km(1e9);
+        assert_near(latitude, NEW_YORK[0], 1e-9);
+        assert_near(longitude, NEW_YORK[1], 1e-9);
+
+        let equator = route([0.0, 0.0], [0.0, 90.0]);
+        let [latitude, longitude] = equator.point_at_km(equator.distance_km() / 3.0);
+        assert_near(latitude, 0.0, 1e-9);
+        assert_near(longitude, 30.0, 1e-9);
+        assert_near(equator.heading_at_km(0.0), 90.0, 1e-3);
+        assert_near(route([0.0, 0.0], [10.0, 0.0]).heading_at_km(100.0), 0.0, 1e-3);
+
+        let nowhere = route(LONDON, LONDON);
+        assert_eq!(nowhere.distance_km(), 0.0);
+        assert_eq!(nowhere.point_at_km(10.0), LONDON);
+    }
+
+    #[test]
+    fn routes_over_the_antimeridian_carry_on_past_it() {
+        let pacific = route([0.0, 170.0], [0.0, -170.0]);
+        assert_near(pacific.distance_km(), 20.0 * EARTH_RADIUS_KM.to_radians(), 1e-6);
+
+        let points = pacific.world_points(1.0);
+        assert_eq!(points.len(), SEGMENTS_RANGE.0 + 1);
+        assert!(points.windows(2).all(|pair| pair[1][0] > pair[0][0]), "{:?}", points);
+        assert!(points.windows(2).all(|pair| pair[1][0] - pair[0][0] < 0.01), "{:?}", points);
+
+        // Lower down, a route is drawn with more segments.
+        assert!(pacific.segments(0.01) > pacific.segments(1.0));
+        assert_eq!(route(LONDON, NEW_YORK).segments(0.0), SEGMENTS_RANGE.1);
+    }
+
+    #[test]
+    fn journeys_travel_to_the_end_and_stop() {
+        let equator = route([0.0, 0.0], [0.0, 10.0]);
+        let mut journey = Journey::new(0);
+        let format = FormattingSettings::default();
+        assert_eq!(journey.status(&equator, format), "Travelled 0 km, 1,112 km to go");
+
+        // An hour at 600 km/h.
+        assert!(!journey.advance(&equator, 600.0, 3600.0));
+        assert_near(journey.travelled_km, 600.0, 1e-9);
+
+        journey.paused = true;
+        assert!(!journey.advance(&equator, 600.0, 3600.0));
+        assert!(!journey.is_moving(&equator));
+        assert_eq!(journey.status(&equator, format), "Travelled 600 km, 512 km to go (paused)");
+
+        journey.paused = false;
+        assert!(journey.advance(&equator, 600.0, 3600.0));
+        assert_eq!(journey.travelled_km, equator.distance_km());
+        assert!(!journey.advance(&equator, 600.0, 3600.0));
+        assert!(journey.is_finished(&equator) && !journey.is_moving(&equator));
+    }
+
+    #[test]
+    fn the_nearest_route_under_the_cursor_is_hovered() {
+        let projected = |index, line: Vec<[f64; 2]>| ProjectedRoute {
+            index,
+            lines: vec![line],
+            label: String::new(),
+            label_at: None,
+        };
+        let routes = [
+            projected(3, vec![[0.0, 0.0], [100.0, 0.0]]),
+            projected(5, vec![[0.0, 4.0], [50.0, 4.0], [100.0, 4.0]]),
+        ];
+
+        assert_eq!(hovered(&routes, [50.0, 1.0]), Some(3));
+        assert_eq!(hovered(&routes, [50.0, 3.0]), Some(5));
+        assert_eq!(hovered(&routes, [104.0, 0.0]), Some(3));
+        assert_eq!(hovered(&routes, [50.0, 20.0]), None);
+        assert_eq!(hovered(&[], [50.0, 1.0]), None);
+    }
+}
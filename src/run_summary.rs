@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use frame_times::{FrameTimes, Phase};
+use memory_usage;
+
+/// Counts what happened during a run, to be summarized when the window closes. Recording a frame
+/// only adds to a few counters, so this can always be on.
+#[derive(Debug)]
+pub struct RunStats {
+    started: Instant,
+    frames: u64,
+    /// How many of `frames` were not drawn, because nothing had changed.
+    skipped_frames: u64,
+    /// When the last frame started, and the level of detail it was drawn at.
+    last_frame: Option<(Instant, u8)>,
+    /// How long frames were drawn at each level of detail.
+    level_times: BTreeMap<u8, Duration>,
+    mode_switches: u32,
+}
+
+impl RunStats {
+    pub fn new() -> RunStats {
+        RunStats {
+            started: Instant::now(),
+            frames: 0,
+            skipped_frames: 0,
+            last_frame: None,
+            level_times: BTreeMap::new(),
+            mode_switches: 0,
+        }
+    }
+
+    /// Records that a frame at `level` is starting now. The time until the next frame counts
+    /// towards `level`.
+    pub fn frame(&mut self, level: u8) {
+        let now = Instant::now();
+
+        if let Some((last_frame, last_level)) = self.last_frame {
+            *self.level_times.entry(last_level).or_insert_with(Duration::default) +=
+                now.duration_since(last_frame);
+        }
+
+        self.frames += 1;
+        self.last_frame = Some((now, level));
+    }
+
+    /// Records that the frame that just started was not drawn.
+    pub fn frame_skipped(&mut self) {
+        self.skipped_frames += 1;
+    }
+
+    pub fn mode_switched(&mut self) {
+        self.mode_switches += 1;
+    }
+
+    /// A few lines about the run so far. Frame time percentiles are of the frames in
+    /// `frame_times`, which only keeps the most recent ones. `spikes` is how many frames were
+    /// slow enough to be logged, and where. `peak_memory` is the most memory the process had
+    /// resident, in bytes, if known.
+    ///
+    /// gaia does not say how many tiles are waiting to load, so that is not included.
+    pub fn summary(
+        &self,
+        frame_times: &FrameTimes,
+        spikes: (u32, &Path),
+        peak_memory: Option<u64>,
+    ) -> String {
+        let run_time = seconds(self.started.elapsed());
+
+        let mut text = "Run summary:\n".to_string();
+        writeln!(text, "  Run time: {:.1} s", run_time).unwrap();
+        let drawn = self.frames - self.skipped_frames;
+        if run_time > 0.0 {
+            writeln!(
+                text,
+                "  Frames drawn: {} ({:.1} per second)",
+                drawn,
+                drawn as f64 / run_time
+            ).unwrap();
+        } else {
+            writeln!(text, "  Frames drawn: {}", drawn).unwrap();
+        }
+        writeln!(text, "  Frames skipped, unchanged: {}", self.skipped_frames).unwrap();
+
+        if let Some(summary) = frame_times.summary() {
+            writeln!(
+                text,
+                "  Frame time, over the last {} frames: average {:.1} ms, 95th percentile \
+                 {:.1} ms, 99th percentile {:.1} ms",
+                summary.frames, summary.average, summary.percentile_95, summary.percentile_99
+            ).unwrap();
+
+            let phases: Vec<String> = Phase::ALL
+                .iter()
+                .filter_map(|&phase| {
+                    frame_times.phase_summary(phase).map(|summary| {
+                        format!(
+                            "{} {:.1}/{:.1}/{:.1}",
+                            phase.name(),
+                            summary.average,
+                            summary.percentile_95,
+                            summary.percentile_99
+                        )
+                    })
+                })
+                .collect();
+            writeln!(
+                text,
+                "  Frame phases, average/95th/99th percentile in ms: {}",
+                phases.join(", ")
+            ).unwrap();
+        }
+
+        if !self.level_times.is_empty() {
+            let levels: Vec<String> = self.level_times
+                .iter()
+                .map(|(level, time)| format!("{}: {:.1} s", level, seconds(*time)))
+                .collect();
+            writeln!(text, "  Time at each level of detail: {}", levels.join(", ")).unwrap();
+        }
+
+        writeln!(text, "  Mode switches: {}", self.mode_switches).unwrap();
+
+        match spikes {
+            (0, _) => writeln!(text, "  Frame time spikes: 0").unwrap(),
+            (spikes, path) => writeln!(
+                text,
+                "  Frame time spikes: {}, logged to {}",
+                spikes,
+                path.display()
+            ).unwrap(),
+        }
+
+        match peak_memory {
+            Some(bytes) => writeln!(
+                text,
+                "  Peak memory: {} resident",
+                memory_usage::mebibytes(bytes)
+            ).unwrap(),
+            None => writeln!(text, "  Peak memory: n/a").unwrap(),
+        }
+
+        text
+    }
+}
+
+impl Default for RunStats {
+    fn default() -> RunStats {
+        RunStats::new()
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_summary_counts_what_happened() {
+        let mut stats = RunStats::new();
+        stats.frame(3);
+        stats.frame(3);
+        stats.frame_skipped();
+        stats.frame(5);
+        stats.mode_switched();
+
+        let log = Path::new("spikes.log");
+        let summary = stats.summary(&FrameTimes::new(), (0, log), Some(3 * 1024 * 1024));
+        assert!(summary.contains("  Frames drawn: 2"), "{}", summary);
+        assert!(summary.contains("  Frames skipped, unchanged: 1\n"), "{}", summary);
+        // The frame still being drawn does not count towards its level yet.
+        assert!(summary.contains("  Time at each level of detail: 3: "), "{}", summary);
+        assert!(!summary.contains("5: "), "{}", summary);
+        assert!(summary.contains("  Mode switches: 1\n"), "{}", summary);
+        assert!(summary.contains("  Frame time spikes: 0\n"), "{}", summary);
+        assert!(summary.contains("  Peak memory: 3.0 MiB resident\n"), "{}", summary);
+        // With no frames timed, there are no frame times to give.
+        assert!(!summary.contains("Frame time,"), "{}", summary);
+    }
+
+    #[test]
+    fn spikes_say_where_they_were_logged() {
+        let log = Path::new("spikes.log");
+        let summary = RunStats::new().summary(&FrameTimes::new(), (2, log), None);
+
+        assert!(summary.contains("  Frame time spikes: 2, logged to spikes.log\n"), "{}", summary);
+        assert!(summary.contains("  Peak memory: n/a\n"), "{}", summary);
+        assert!(!summary.contains("level of detail"), "{}", summary);
+    }
+}
@@ -0,0 +1,126 @@
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::{HudScale, HudStyle};
+use formatting;
+use settings::FormattingSettings;
+
+/// The bar is as long as the largest round distance that fits in this many pixels.
+const MAX_WIDTH: f64 = 150.0;
+
+/// The longest bar plus its label, at a HUD scale of 1.
+const SIZE: [f64; 2] = [MAX_WIDTH + 70.0, 15.0];
+
+const FONT_SIZE: u32 = 10;
+
+const TICK_HEIGHT: f64 = 6.0;
+
+/// How much room `draw` needs at `scale`.
+pub fn size(scale: HudScale) -> [f64; 2] {
+    [scale.len(SIZE[0]), scale.len(SIZE[1])]
+}
+
+/// Draws a scale bar with its top-left corner at `origin`. `km_per_pixel` is the ground distance
+/// covered by one pixel at the center of the screen.
+pub fn draw(
+    km_per_pixel: f64,
+    format: FormattingSettings,
+    origin: [f64; 2],
+    style: HudStyle,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+    let (width, label) = match bar(km_per_pixel, scale.len(MAX_WIDTH), format) {
+        Some(bar) => bar,
+        None => return,
+    };
+
+    let context = context.trans(origin[0], origin[1] + scale.len(SIZE[1] - 2.0));
+    let color = theme.text;
+    let (line_width, tick_height) = (scale.len(1.0), scale.len(TICK_HEIGHT));
+
+    ::piston_window::line(color, line_width, [0.0, 0.0, width, 0.0], context.transform, graphics);
+    for &x in &[0.0, width / 2.0, width] {
+        ::piston_window::line(
+            color,
+            line_width,
+            [x, 0.0, x, -tick_height],
+            context.transform,
+            graphics,
+        );
+    }
+
+    scale.draw_text(
+        &label,
+        FONT_SIZE,
+        color,
+        context.trans(width + scale.len(5.0), 0.0),
+        glyphs,
+        graphics,
+    );
+}
+
+/// How many pixels long the bar is at `km_per_pixel`, no more than `max_width`, and its label in
+/// the units of `format`. `None` if the camera is somewhere with no distance to show.
+fn bar(km_per_pixel: f64, max_width: f64, format: FormattingSettings) -> Option<(f64, String)> {
+    if !(km_per_pixel.is_finite() && km_per_pixel > 0.0) {
+        return None;
+    }
+
+    let (km_per_unit, _) = formatting::distance_unit(format.units);
+    let unit_per_pixel = km_per_pixel / km_per_unit;
+    let distance = round_distance(max_width * unit_per_pixel);
+    let width = (distance / unit_per_pixel).round();
+    // Distances below 1 need as many decimals as it takes to show their first digit.
+    let decimals = (-distance.log10()).ceil().max(0.0) as usize;
+
+    Some((width, formatting::distance(distance * km_per_unit, decimals, format)))
+}
+
+/// The largest distance of the form 1, 2 or 5 times a power of ten that is at most `max`.
+fn round_distance(max: f64) -> f64 {
+    let magnitude = 10f64.powf(max.log10().floor());
+
+    [5.0, 2.0, 1.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&distance| distance <= max)
+        .unwrap_or(magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use settings::Units;
+
+    #[test]
+    fn distances_are_rounded_down_to_one_two_or_five() {
+        assert_eq!(round_distance(150.0), 100.0);
+        assert_eq!(round_distance(499.0), 200.0);
+        assert_eq!(round_distance(500.0), 500.0);
+        assert_eq!(round_distance(9.99), 5.0);
+        assert_eq!(round_distance(1.0), 1.0);
+        assert!((round_distance(0.037) - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn the_bar_is_as_long_as_its_round_distance() {
+        let metric = FormattingSettings::default();
+        assert_eq!(bar(2.0, 150.0, metric), Some((100.0, "200 km".to_string())));
+        // Close in, short distances get the decimals their first digit needs.
+        assert_eq!(bar(0.001, 150.0, metric), Some((100.0, "0.1 km".to_string())));
+
+        let imperial = FormattingSettings {
+            units: Units::Imperial,
+            ..metric
+        };
+        let (width, label) = bar(2.0, 150.0, imperial).unwrap();
+        assert_eq!(label, "100 mi");
+        assert_eq!(width, (100.0 * formatting::distance_unit(Units::Imperial).0 / 2.0).round());
+
+        assert_eq!(bar(0.0, 150.0, metric), None);
+        assert_eq!(bar(::std::f64::INFINITY, 150.0, metric), None);
+    }
+}
@@ -0,0 +1,501 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use chrono::Local;
+use gfx;
+use gfx::format::{ChannelType, Srgba8};
+use gfx::handle::RenderTargetView;
+use gfx::memory::Typed;
+use gfx::traits::FactoryExt;
+use image;
+use piston_window::PistonWindow;
+
+use {Error, Result, ResultExt};
+
+/// An 8-bit RGBA image read back from the GPU, with rows stored bottom to top as OpenGL returns
+/// them.
+#[derive(Clone)]
+pub struct Readback {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Readback {
+    /// A transparent black image, in pixels.
+    pub fn blank(width: u32, height: u32) -> Readback {
+        Readback {
+            width,
+            height,
+            pixels: vec![0; 4 * width as usize * height as usize],
+        }
+    }
+
+    /// An image of `pixels`, with rows from top to bottom as image files store them.
+    pub fn from_top_down_pixels(width: u32, height: u32, pixels: &[u8]) -> Readback {
+        let row_len = 4 * width as usize;
+        assert_eq!(pixels.len(), row_len * height as usize);
+
+        Readback {
+            width,
+            height,
+            pixels: pixels
+                .chunks(row_len)
+                .rev()
+                .flat_map(|row| row.iter().cloned())
+                .collect(),
+        }
+    }
+
+    /// Copies `part` over this image, with its top-left corner at `x` and `y` pixels from this
+    /// image's top-left. Whatever falls outside of this image is left out.
+    pub fn paste(&mut self, part: &Readback, x: u32, y: u32) {
+        let width = part.width.min(self.width.saturating_sub(x)) as usize;
+        let height = part.height.min(self.height.saturating_sub(y));
+
+        // Rows are counted from the top, but stored bottom to top in both.
+        for row in 0..height {
+            let to_row = (self.height - 1 - (y + row)) as usize;
+            let from_row = (part.height - 1 - row) as usize;
+            let to = 4 * (to_row * self.width as usize + x as usize);
+            let from = 4 * from_row * part.width as usize;
+
+            self.pixels[to..to + 4 * width].copy_from_slice(&part.pixels[from..from + 4 * width]);
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// How much memory the pixels take.
+    pub fn len_bytes(&self) -> usize {
+        self.pixels.len()
+    }
+
+    /// This image shrunk to one pixel for every `factor` by `factor` block of it, each the
+    /// average of its block. Pixels past the last whole block are left out.
+    pub fn downscaled(&self, factor: u32) -> Readback {
+        let factor = factor.max(1);
+        let (width, height) = (self.width / factor, self.height / factor);
+        let mut pixels = Vec::with_capacity(4 * width as usize * height as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 4];
+                for from_y in y * factor..(y + 1) * factor {
+                    let row = 4 * (from_y * self.width) as usize;
+                    for from_x in x * factor..(x + 1) * factor {
+                        let i = row + 4 * from_x as usize;
+                        for (total, &channel) in sum.iter_mut().zip(&self.pixels[i..i + 4]) {
+                            *total += u32::from(channel);
+                        }
+                    }
+                }
+
+                let count = factor * factor;
+                pixels.extend(sum.iter().map(|&total| (total / count) as u8));
+            }
+        }
+
+        Readback {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// The pixels, with rows from top to bottom as image files store them.
+    pub fn top_down_pixels(&self) -> Vec<u8> {
+        let row_len = 4 * self.width as usize;
+
+        self.pixels
+            .chunks(row_len)
+            .rev()
+            .flat_map(|row| row.iter().cloned())
+            .collect()
+    }
+
+    /// This image with its colors divided by their alpha. Drawing over a transparent target
+    /// blends the colors with its black, so that edges would otherwise come out dark. Blending
+    /// happens in linear color, so the colors are divided there.
+    pub fn unpremultiplied(mut self) -> Readback {
+        let to_linear = |c: u8| (f32::from(c) / 255.0).powf(2.2);
+        let to_srgb = |c: f32| (c.min(1.0).powf(1.0 / 2.2) * 255.0).round() as u8;
+
+        for pixel in self.pixels.chunks_mut(4) {
+            let alpha = f32::from(pixel[3]) / 255.0;
+            if alpha > 0.0 && alpha < 1.0 {
+                for c in &mut pixel[..3] {
+                    *c = to_srgb(to_linear(*c) / alpha);
+                }
+            }
+        }
+
+        self
+    }
+}
+
+/// A path in `dir` named `{prefix}-{time}{suffix}{extension}`, with the current time to the
+/// second, as in `gaia-20261014-153012.png`. If `taken` says that path is already used, `-2`,
+/// `-3` and so on are put after the time until one is not, so that files made in the same second
+/// do not overwrite each other.
+pub fn timestamped_path<F>(
+    dir: &Path,
+    prefix: &str,
+    suffix: &str,
+    extension: &str,
+    taken: F,
+) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    let time = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    numbered_path(dir, &format!("{}-{}", prefix, time), suffix, extension, taken)
+}
+
+/// `dir` joined with `{stem}{suffix}{extension}`, or with `-2`, `-3` and so on after `stem` if
+/// `taken` says that is already used.
+fn numbered_path<F>(dir: &Path, stem: &str, suffix: &str, extension: &str, taken: F) -> PathBuf
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut path = dir.join(format!("{}{}{}", stem, suffix, extension));
+    let mut n = 1;
+    while taken(&path) {
+        n += 1;
+        path = dir.join(format!("{}-{}{}{}", stem, n, suffix, extension));
+    }
+
+    path
+}
+
+/// How many captures can wait to be written before more are dropped.
+const QUEUE_LEN: usize = 8;
+
+/// How many threads encode and write PNGs.
+const WORKERS: usize = 2;
+
+/// A capture waiting to be written.
+struct Job {
+    path: PathBuf,
+    readback: Readback,
+}
+
+/// Captures the window's color target and writes PNGs on background threads, so that encoding
+/// and disk I/O never stall the render loop. Only reading back the pixels happens on the render
+/// thread, since it needs the OpenGL context.
+///
+/// When dropped, this waits for the captures still waiting to be written, so that none are lost.
+pub struct Screenshots {
+    dir: PathBuf,
+    /// `None` once shutting down.
+    job_sender: Option<mpsc::SyncSender<Job>>,
+    workers: Vec<thread::JoinHandle<()>>,
+    result_receiver: mpsc::Receiver<Result<PathBuf>>,
+    /// Captures sent to the workers whose results have not been received yet.
+    pending: usize,
+    /// Captures that were dropped because too many were already waiting.
+    dropped: u32,
+    /// Every path handed out, since those still queued are not on disk yet for a capture made at
+    /// the same time to see that they are taken.
+    issued: HashSet<PathBuf>,
+}
+
+impl Screenshots {
+    pub fn new(dir: PathBuf) -> Result<Screenshots> {
+        Screenshots::with_capacity(dir, QUEUE_LEN, WORKERS)
+    }
+
+    /// Like `new`, but with `queue_len` captures waiting at most, written by `workers` threads.
+    pub fn with_capacity(dir: PathBuf, queue_len: usize, workers: usize) -> Result<Screenshots> {
+        let (job_sender, job_receiver) = mpsc::sync_channel::<Job>(queue_len);
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let worker_count = workers;
+        let mut workers = Vec::with_capacity(worker_count);
+        for i in 0..worker_count {
+            let job_receiver = Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+
+            let worker = thread::Builder::new()
+                .name(format!("screenshot-{}", i))
+                .spawn(move || loop {
+                    // The lock is only held while waiting, so that the others can write meanwhile.
+                    let job = match job_receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    // Every sender is gone once shutting down, after the queue has been emptied.
+                    let Job { path, readback } = match job {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+
+                    let result = write_png(&path, readback).map(|_| path);
+                    // The receiver only goes away when the program is shutting down.
+                    result_sender.send(result).ok();
+                })
+                .chain_err(|| "Error creating screenshot writer thread")?;
+            workers.push(worker);
+        }
+
+        Ok(Screenshots {
+            dir,
+            job_sender: Some(job_sender),
+            workers,
+            result_receiver,
+            pending: 0,
+            dropped: 0,
+            issued: HashSet::new(),
+        })
+    }
+
+    /// Reads back the current contents of `window`'s color target and queues them to be written
+    /// to a timestamped PNG. Should be called after all drawing for the frame is complete.
+    /// Returns false if the capture was dropped instead, because the disk cannot keep up.
+    pub fn capture(&mut self, window: &mut PistonWindow) -> Result<bool> {
+        let path = self.timestamped_path("");
+
+        let captured = self.capture_to(window, path)?;
+        if !captured {
+            warn!("Dropped a screenshot, since {} are still being written", self.pending);
+        }
+
+        Ok(captured)
+    }
+
+    /// Like `capture`, but written to `path`, and without warning when dropped.
+    pub fn capture_to(&mut self, window: &mut PistonWindow, path: PathBuf) -> Result<bool> {
+        let readback = read_back(window)?;
+
+        let sent = match self.job_sender {
+            Some(ref job_sender) => job_sender.try_send(Job { path, readback }),
+            None => bail!("Screenshots are shutting down"),
+        };
+        match sent {
+            Ok(()) => {
+                self.pending += 1;
+                Ok(true)
+            }
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.dropped += 1;
+                Ok(false)
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => bail!("The screenshot writers stopped"),
+        }
+    }
+
+    /// Queues `image` to be written to a timestamped PNG, whose name ends in `suffix`, waiting
+    /// for room in the queue rather than dropping it. Returns where it will be written.
+    pub fn save_image(&mut self, image: Readback, suffix: &str) -> Result<PathBuf> {
+        let path = self.timestamped_path(suffix);
+
+        match self.job_sender {
+            Some(ref job_sender) => job_sender
+                .send(Job {
+                    path: path.clone(),
+                    readback: image,
+                })
+                .map_err(|_| Error::from("The screenshot writers stopped"))?,
+            None => bail!("Screenshots are shutting down"),
+        }
+        self.pending += 1;
+
+        Ok(path)
+    }
+
+    fn timestamped_path(&mut self, suffix: &str) -> PathBuf {
+        let path = {
+            let issued = &self.issued;
+            timestamped_path(&self.dir, "gaia", suffix, ".png", |path| {
+                issued.contains(path) || path.exists()
+            })
+        };
+        self.issued.insert(path.clone());
+
+        path
+    }
+
+    /// The paths written, or errors encountered, by captures that finished since the last call.
+    pub fn finished(&mut self) -> Vec<Result<PathBuf>> {
+        let results: Vec<_> = self.result_receiver.try_iter().collect();
+        self.pending -= results.len().min(self.pending);
+
+        results
+    }
+
+    /// Waits for the captures still waiting to be written, and returns their results as
+    /// `finished` does. Nothing can be captured afterwards.
+    pub fn flush(&mut self) -> Vec<Result<PathBuf>> {
+        // Once the sender is gone, the workers finish what is queued and stop.
+        self.job_sender = None;
+        for worker in self.workers.drain(..) {
+            worker.join().ok();
+        }
+
+        self.finished()
+    }
+
+    /// How many captures were dropped because too many were already waiting.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// How many captures are being written, and how many were dropped, as shown in the
+    /// performance overlay.
+    pub fn status(&self) -> String {
+        format!(
+            "Screenshots: {} being written, {} dropped",
+            self.pending, self.dropped
+        )
+    }
+}
+
+impl Drop for Screenshots {
+    fn drop(&mut self) {
+        if self.pending > 0 {
+            info!("Flushing captures...");
+        }
+
+        for result in self.flush() {
+            match result {
+                Ok(path) => info!("Saved screenshot {}", path.display()),
+                Err(e) => error!("{}", e),
+            }
+        }
+    }
+}
+
+/// Reads back the current contents of `window`'s color target and writes them to `path`, waiting
+/// until the PNG is written.
+pub fn save(window: &mut PistonWindow, path: &Path) -> Result<()> {
+    let readback = read_back(window)?;
+
+    write_png(path, readback)
+}
+
+/// Reads back what has been drawn to `window` so far.
+pub fn read_back(window: &mut PistonWindow) -> Result<Readback> {
+    read_back_target(
+        &mut window.factory,
+        &mut window.encoder,
+        &mut window.device,
+        &window.output_color,
+    )
+}
+
+/// Reads back the current contents of `target`, after drawing everything queued in `encoder`.
+pub fn read_back_target<R, C, F, D>(
+    factory: &mut F,
+    encoder: &mut gfx::Encoder<R, C>,
+    device: &mut D,
+    target: &RenderTargetView<R, Srgba8>,
+) -> Result<Readback>
+where
+    R: gfx::Resources,
+    C: gfx::CommandBuffer<R>,
+    F: gfx::Factory<R>,
+    D: gfx::Device<Resources = R, CommandBuffer = C>,
+{
+    let texture = target.raw().get_texture().clone();
+    let (width, height, _, _) = target.get_dimensions();
+    let image_info = texture.get_info().to_raw_image_info(ChannelType::Srgb, 0);
+
+    let buffer = factory
+        .create_download_buffer::<[u8; 4]>(width as usize * height as usize)
+        .map_err(|e| Error::from(format!("Could not create readback buffer: {:?}", e)))?;
+
+    encoder
+        .copy_texture_to_buffer_raw(&texture, None, image_info, buffer.raw(), 0)
+        .map_err(|e| Error::from(format!("Could not copy color target: {:?}", e)))?;
+    encoder.flush(device);
+
+    let reader = factory
+        .read_mapping(&buffer)
+        .map_err(|e| Error::from(format!("Could not map readback buffer: {:?}", e)))?;
+
+    let mut pixels = Vec::with_capacity(4 * reader.len());
+    for pixel in reader.iter() {
+        pixels.extend_from_slice(pixel);
+    }
+
+    Ok(Readback {
+        width: u32::from(width),
+        height: u32::from(height),
+        pixels,
+    })
+}
+
+/// Writes `readback` to `path`, waiting until the PNG is written.
+pub fn write_png(path: &Path, readback: Readback) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .chain_err(|| format!("Could not create screenshot directory {}", dir.display()))?;
+    }
+
+    image::save_buffer(
+        path,
+        &readback.top_down_pixels(),
+        readback.width,
+        readback.height,
+        image::RGBA(8),
+    ).chain_err(|| format!("Could not write screenshot {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_already_taken_are_numbered() {
+        let dir = Path::new("shots");
+        let taken = [dir.join("gaia-1.png"), dir.join("gaia-1-2.png")];
+        let path = numbered_path(dir, "gaia-1", "", ".png", |path| taken.contains(&path.into()));
+        assert_eq!(path, dir.join("gaia-1-3.png"));
+
+        // The number goes before the suffix, so that files stay sorted by when they were made.
+        let path = numbered_path(dir, "gaia-1", "-legend", ".png", |_| false);
+        assert_eq!(path, dir.join("gaia-1-legend.png"));
+
+        let name = timestamped_path(dir, "gaia", "", ".png", |_| false);
+        let name = name.file_name().unwrap().to_str().unwrap();
+        // Such as gaia-20261014-153012.png.
+        assert_eq!(name.len(), "gaia-20261014-153012.png".len(), "{}", name);
+        assert!(name.starts_with("gaia-") && name.ends_with(".png"), "{}", name);
+        assert!(name[5..20].chars().all(|c| c.is_ascii_digit() || c == '-'), "{}", name);
+        assert_eq!(&name[13..14], "-", "{}", name);
+
+        // Another in the same second is numbered.
+        let taken = |path: &Path| !path.to_string_lossy().ends_with("-2.png");
+        let again = timestamped_path(dir, "gaia", "", ".png", taken);
+        let again = again.file_name().unwrap().to_str().unwrap();
+        assert_eq!(again.len(), "gaia-20261014-153012-2.png".len(), "{}", again);
+    }
+
+    #[test]
+    fn captures_queued_at_the_same_time_are_all_written() {
+        let dir = ::std::env::temp_dir().join(format!("gaia-demo-shots-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        // Both are queued before either is written, and written by different workers.
+        let mut screenshots = Screenshots::with_capacity(dir.clone(), 4, 2).unwrap();
+        let first = screenshots.save_image(Readback::blank(2, 2), "").unwrap();
+        let second = screenshots.save_image(Readback::blank(2, 2), "").unwrap();
+        assert_ne!(first, second);
+
+        let written: Vec<PathBuf> = screenshots.flush().into_iter().map(Result::unwrap).collect();
+        assert_eq!(written.len(), 2);
+        assert!(written.contains(&first) && written.contains(&second));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,248 @@
+use std::env;
+use std::path::Path;
+
+use benchmark::CameraPath;
+use bindings;
+use chooser_benchmark::{self, Places};
+use cli::Args;
+use color_script::ColorScript;
+use features::{self, Features};
+use paths;
+use points;
+use preflight::{self, Needs};
+use provenance::{self, Provenance};
+use settings::{PathSettings, Settings};
+use state::State;
+use {Result, ResultExt};
+
+/// How one item checked by `--check` went.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub name: String,
+    pub passed: bool,
+    /// Whether the demo would fail to start if it did not pass. Items that are not required are
+    /// warned about instead.
+    pub required: bool,
+    /// What was found, or what went wrong.
+    pub detail: String,
+}
+
+impl Item {
+    /// Such as "PASS  Font: assets/DejaVuSans.ttf".
+    pub fn line(&self) -> String {
+        let status = match (self.passed, self.required) {
+            (true, _) => "PASS",
+            (false, true) => "FAIL",
+            (false, false) => "WARN",
+        };
+
+        format!("{}  {}: {}", status, self.name, self.detail)
+    }
+}
+
+/// What `--check` found: every step of starting the demo short of opening a window, done the
+/// same way as when starting it, with how each went.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub items: Vec<Item>,
+}
+
+impl Report {
+    fn push(&mut self, name: &str, passed: bool, required: bool, detail: String) {
+        self.items.push(Item {
+            name: name.to_string(),
+            passed,
+            required,
+            detail,
+        });
+    }
+
+    /// Records how `result` went as the item `name`, described by `detail` if it passed.
+    /// Returns its value if it passed.
+    fn record<T, D>(
+        &mut self,
+        name: &str,
+        required: bool,
+        result: Result<T>,
+        detail: D,
+    ) -> Option<T>
+    where
+        D: FnOnce(&T) -> String,
+    {
+        match result {
+            Ok(value) => {
+                let detail = detail(&value);
+                self.push(name, true, required, detail);
+                Some(value)
+            }
+            Err(e) => {
+                let detail = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+                self.push(name, false, required, detail);
+                None
+            }
+        }
+    }
+
+    /// Whether everything required passed.
+    pub fn passed(&self) -> bool {
+        self.items.iter().all(|item| item.passed || !item.required)
+    }
+
+    /// A line for each item, then how many passed.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for item in &self.items {
+            text.push_str(&item.line());
+            text.push('\n');
+        }
+
+        let count = |f: fn(&Item) -> bool| self.items.iter().filter(|&item| f(item)).count();
+        text.push_str(&format!(
+            "{} passed, {} failed, {} warnings\n",
+            count(|item| item.passed),
+            count(|item| !item.passed && item.required),
+            count(|item| !item.passed && !item.required),
+        ));
+        text
+    }
+}
+
+/// Runs every step of starting the demo with `args`, short of opening a window: reading the
+/// settings, finding and reading the assets and every tile, loading the font and the dataset,
+/// and reading the files the settings and options name. Steps that depend on one that failed
+/// are skipped. Changes the working directory to the assets' parent, as starting does.
+pub fn run(args: &Args) -> Report {
+    let mut report = Report::default();
+
+    let config = &args.config;
+    let settings = report.record("Settings file", true, Settings::load(config), |_| {
+        if config.exists() {
+            config.display().to_string()
+        } else {
+            format!("{} does not exist, so the defaults are used", config.display())
+        }
+    });
+    let settings = match settings {
+        Some(mut settings) => {
+            args.apply(&mut settings);
+            settings.validated()
+        }
+        None => return report,
+    };
+
+    let bindings = bindings::with_overrides(&settings.bindings, &settings.modes.shown());
+    report.record("Key bindings", true, bindings, |bindings| {
+        format!("{} keys bound", bindings.len())
+    });
+
+    let start_dir = env::current_dir().ok();
+    if let Some(ref script) = settings.modes.script {
+        let path = start_dir.as_ref().map_or(script.clone(), |dir| dir.join(script));
+        let mut color_script = ColorScript::new(path.clone());
+        report.record("Color script", true, color_script.load(), |_| {
+            path.display().to_string()
+        });
+    }
+    if let Some(ref path) = args.points {
+        report.record("Points file", true, points::load(path), |(points, _)| {
+            format!("{} points in {}", points.len(), path.display())
+        });
+    }
+    if let Some(ref path) = args.benchmark_path {
+        report.record("Benchmark path", true, CameraPath::load(path), |_| {
+            path.display().to_string()
+        });
+    }
+
+    let found = paths::find_assets_dir(&settings.paths.assets_dir);
+    let found = report.record("Assets directory", true, found, |dir| dir.display().to_string());
+    let default_assets_dir = match found {
+        Some(dir) => dir,
+        None => return report,
+    };
+
+    let font_root = default_assets_dir.parent().unwrap_or(&default_assets_dir);
+    let font = paths::find_font(&settings.hud.font, Some(font_root))
+        .and_then(|path| paths::load_font(&path).map(|_| path));
+    report.record("Font", true, font, |path| path.display().to_string());
+    if let Some(ref fallback) = settings.hud.font_fallback {
+        let font = paths::find_fallback_font(fallback, Some(font_root))
+            .and_then(|path| paths::load_font(&path).map(|_| path));
+        report.record("Fallback font", false, font, |path| path.display().to_string());
+    }
+
+    // Every asset set can be switched to, but only the one in use has to work to start.
+    let selected = &settings.paths.asset_set;
+    let mut selected_dir = None;
+    for name in settings.paths.asset_set_names() {
+        let required = name == selected
+            || (name == PathSettings::DEFAULT_ASSET_SET
+                && settings.paths.asset_set_dir(selected).is_none());
+        let dir = match settings.paths.asset_set_dir(name) {
+            Some(_) if name == PathSettings::DEFAULT_ASSET_SET => Ok(default_assets_dir.clone()),
+            Some(dir) => paths::find_assets_dir(dir),
+            None => continue,
+        };
+        let assets = dir.and_then(|dir| {
+            paths::check_assets(&dir)?;
+            let tiles = paths::check_tiles(&dir)?;
+            Ok((dir, tiles))
+        });
+
+        let item = format!("Asset set {:?}", name);
+        let assets = report.record(&item, required, assets, |(dir, tiles)| {
+            format!("{} tiles in {}", tiles, dir.display())
+        });
+        if required {
+            selected_dir = assets.map(|(dir, _)| dir);
+        }
+    }
+
+    if let Some(dir) = selected_dir {
+        report.record("Asset provenance", false, Provenance::load(&dir), |provenance| {
+            provenance::describe(provenance.as_ref(), &dir)
+        });
+        check_features(&mut report, &settings, args.strict, &dir);
+    }
+    report
+}
+
+/// Checks the countries and places in `assets_dir`, and that they have the properties the
+/// settings read. Lacking properties that the start mode or labels need is a failure with
+/// `strict`, as it is when starting. Then times the choosers over them, as starting does, with a
+/// warning if they are too slow for 60 FPS.
+fn check_features(report: &mut Report, settings: &Settings, strict: bool, assets_dir: &Path) {
+    // The dataset is read from `assets` in the working directory, as the renderer reads it.
+    let root = assets_dir.parent().unwrap_or(assets_dir);
+    let data = env::set_current_dir(root)
+        .chain_err(|| format!("Could not change directory to {}", root.display()))
+        .and_then(|_| features::load_data());
+    let data = report.record("Dataset", true, data, |data| {
+        format!("{} countries and {} places", data.polygons.len(), data.points.len())
+    });
+    let data = match data {
+        Some(data) => data,
+        None => return,
+    };
+
+    let findings = preflight::check(&data, &Needs::of(settings));
+    for finding in &findings {
+        let detail = format!("{} ({})", finding.summary(), finding.consequence);
+        report.push("Dataset properties", false, finding.required && strict, detail);
+    }
+
+    let places = Places::sample(&data.points);
+    let features = Features::from_data(data);
+    for warning in features.warnings() {
+        report.push("Dataset properties", false, false, format!("Could not read {}", warning));
+    }
+    if findings.is_empty() && features.warnings().is_empty() {
+        let detail = "every feature has what the demo reads".to_string();
+        report.push("Dataset properties", true, true, detail);
+    }
+
+    // Nothing is written under the directory, since no frames are drawn.
+    let state = State::headless(settings.clone(), features, &env::temp_dir());
+    let results = chooser_benchmark::run(&state, &places);
+    report.push("Chooser throughput", !results.is_slow(), false, results.summary());
+}
@@ -0,0 +1,90 @@
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log;
+
+use persistence;
+use routes::Route;
+use window_placement::WindowGeometry;
+use world_clock::PinnedClock;
+use Result;
+
+/// What the session file is called, next to the settings file.
+pub const SESSION_FILE_NAME: &str = "gaia-demo-session.json";
+
+/// The session file's format version. Files of other versions are ignored.
+const VERSION: u32 = 1;
+
+const KIND: &str = "session";
+
+/// The latest session, and where to save it, for the panic hook.
+static LATEST: Mutex<Option<(PathBuf, Session)>> = Mutex::new(None);
+
+/// Where the camera was when the demo last exited, the routes drawn and where the window was, so
+/// that it can start there next time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// In degrees.
+    pub latitude: f32,
+    pub longitude: f32,
+    pub height: f32,
+    /// In degrees clockwise from north.
+    pub heading: f32,
+    /// Sessions saved before routes were added have none.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// Where the window was in windowed mode, if known. Sessions saved before this was added
+    /// have none.
+    #[serde(default)]
+    pub window: Option<WindowGeometry>,
+    /// The cities whose clocks are pinned. Sessions saved before clocks could be pinned have
+    /// none.
+    #[serde(default)]
+    pub clocks: Vec<PinnedClock>,
+}
+
+impl Session {
+    /// The session saved at `path`. `None` if there is none, or it could not be read.
+    pub fn load(path: &Path) -> Option<Session> {
+        persistence::load_versioned(path, KIND, VERSION)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        persistence::save_versioned(path, KIND, VERSION, self)
+    }
+
+    /// Makes this the session saved if the demo panics.
+    pub fn remember(&self, path: &Path) {
+        if let Ok(mut latest) = LATEST.lock() {
+            *latest = Some((path.to_path_buf(), self.clone()));
+        }
+    }
+}
+
+/// Where the session is kept for the settings file at `settings_path`.
+pub fn path_for(settings_path: &Path) -> PathBuf {
+    settings_path.with_file_name(SESSION_FILE_NAME)
+}
+
+/// Saves the last remembered session if the demo panics, after reporting the panic as usual.
+pub fn save_on_panic() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        // The panic may have happened while the lock was held, so this does not wait for it.
+        let latest = match LATEST.try_lock() {
+            Ok(latest) => latest.clone(),
+            Err(_) => return,
+        };
+        if let Some((path, session)) = latest {
+            match session.save(&path) {
+                Ok(()) => info!("Saved the session to {}", path.display()),
+                Err(e) => error!("Could not save the session: {}", e),
+            }
+            log::logger().flush();
+        }
+    }));
+}
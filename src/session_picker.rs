@@ -0,0 +1,159 @@
+use piston::input::keyboard::Key;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::{HudScale, HudStyle};
+use named_session::{self, MAX_NAME_LEN};
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+const WIDTH: f64 = 260.0;
+
+/// The most session names listed at once. The list scrolls to keep the selected one shown.
+const MAX_SHOWN: usize = 10;
+
+/// What the user chose in the picker.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Choice {
+    Load(String),
+    Save(String),
+}
+
+/// Lists the saved sessions to load one, with Up/Down and Return, and saves the current one
+/// under a name typed into it.
+#[derive(Debug)]
+pub struct SessionPicker {
+    visible: bool,
+    names: Vec<String>,
+    selected: usize,
+    /// The name typed so far, to save the session as.
+    typed: String,
+}
+
+impl SessionPicker {
+    pub fn new() -> SessionPicker {
+        SessionPicker {
+            visible: false,
+            names: Vec::new(),
+            selected: 0,
+            typed: String::new(),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Opens the picker, listing `names`.
+    pub fn open(&mut self, names: Vec<String>) {
+        self.visible = true;
+        self.names = names;
+        self.selected = 0;
+        self.typed.clear();
+    }
+
+    pub fn close(&mut self) {
+        self.visible = false;
+    }
+
+    /// Handles a key press while the picker is open. All keys are swallowed so that nothing
+    /// happens behind the picker, since letters are typed into it. Choosing a session closes it.
+    pub fn key_press(&mut self, key: Key) -> Option<Choice> {
+        match key {
+            Key::F4 | Key::Escape => self.close(),
+            Key::Up if !self.names.is_empty() => {
+                self.selected = (self.selected + self.names.len() - 1) % self.names.len();
+            }
+            Key::Down if !self.names.is_empty() => {
+                self.selected = (self.selected + 1) % self.names.len();
+            }
+            Key::Backspace => {
+                self.typed.pop();
+            }
+            Key::Return => {
+                let choice = if !self.typed.is_empty() {
+                    Some(Choice::Save(self.typed.clone()))
+                } else {
+                    self.names.get(self.selected).cloned().map(Choice::Load)
+                };
+                if choice.is_some() {
+                    self.close();
+                }
+
+                return choice;
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Adds what was typed to the name to save as, skipping characters names cannot have.
+    pub fn text(&mut self, text: &str) {
+        for c in text.chars().filter(|&c| named_session::is_name_char(c)) {
+            if self.typed.len() < MAX_NAME_LEN {
+                self.typed.push(c);
+            }
+        }
+    }
+
+    /// How much room `draw` needs.
+    pub fn size(&self, scale: HudScale) -> [f64; 2] {
+        let rows = self.names.len().clamp(1, MAX_SHOWN);
+
+        [scale.len(WIDTH), scale.len(LINE_HEIGHT * (rows + 4) as f64)]
+    }
+
+    /// Draws the picker with its top-left corner at `origin`.
+    pub fn draw(
+        &self,
+        origin: [f64; 2],
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let HudStyle { scale, theme } = style;
+        let [width, height] = self.size(scale);
+        let context = context.trans(origin[0], origin[1]);
+        let line_height = scale.len(LINE_HEIGHT);
+        let indent = scale.len(10.0);
+
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [0.0, 0.0, width, height],
+            context.transform,
+            graphics,
+        );
+
+        let mut draw_line = |text: &str, color, row: usize, graphics: &mut G2d| {
+            let y = line_height * (row + 1) as f64;
+            scale.draw_text(text, FONT_SIZE, color, context.trans(indent, y), glyphs, graphics);
+        };
+
+        draw_line("Sessions (F4 to close)", theme.text, 0, graphics);
+        draw_line(&format!("Save as: {}_", self.typed), theme.accent, 1, graphics);
+
+        if self.names.is_empty() {
+            draw_line("No saved sessions. Type a name to save one", theme.text, 3, graphics);
+            return;
+        }
+
+        let first = (self.selected + 1).saturating_sub(MAX_SHOWN);
+        let shown = self.names.iter().enumerate().skip(first).take(MAX_SHOWN);
+        for (row, (i, name)) in shown.enumerate() {
+            let (marker, color) = if i == self.selected && self.typed.is_empty() {
+                ("> ", theme.accent)
+            } else {
+                ("  ", theme.text)
+            };
+
+            draw_line(&format!("{}{}", marker, name), color, row + 3, graphics);
+        }
+    }
+}
+
+impl Default for SessionPicker {
+    fn default() -> SessionPicker {
+        SessionPicker::new()
+    }
+}
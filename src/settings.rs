@@ -0,0 +1,2012 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use gaia_assetgen::MAX_LEVEL;
+use serde_ignored;
+use toml::{self, Value};
+
+use bindings::Action;
+use class_breaks::ModeBreaks;
+use country_filter::CountryFilter;
+use gl_context::SAMPLE_COUNTS;
+use label_policy::ModeLabelPolicies;
+use min_zoom_curve::{self, ControlPoints};
+use mode_adjustments::SavedAdjustments;
+use mode_parameters::ModeParameters;
+use persistence;
+use profile::Profile;
+use terrain_clearance::{self, TerrainRegion};
+use theme::{Theme, ThemeName};
+use {MapMode, Palette, Result, ResultExt};
+
+/// The name of the settings file.
+pub const SETTINGS_FILE_NAME: &str = "gaia-demo.toml";
+
+/// Going from the highest to the lowest camera height takes about as many scroll notches with
+/// this multiplier as it did when scrolling changed the height by a fixed amount.
+const DEFAULT_ZOOM_PER_NOTCH: f32 = 1.035;
+
+/// Settings that are only read when the demo starts, and so are not applied when the settings file
+/// is reloaded. Each covers every key under it.
+const STARTUP_ONLY: [&str; 23] = [
+    "window.width",
+    "window.height",
+    "window.position",
+    "window.monitor",
+    "window.restore_geometry",
+    "window.title",
+    "window.fullscreen",
+    "window.gl_version",
+    "window.vsync",
+    "window.samples",
+    "window.srgb",
+    "window.fps_limit",
+    "window.idle_fps",
+    "camera.start",
+    "hud.font",
+    "hud.font_fallback",
+    "modes.default",
+    "modes.script",
+    "paths.assets_dir",
+    "diagnostics.replay_buffer",
+    "diagnostics.replay_secs",
+    "diagnostics.replay_fps",
+    "diagnostics.replay_scale",
+];
+
+/// Settings that have moved since settings files were first written, from where they were to where
+/// they are now. Files with settings where they were are read as though they were where they are.
+const MOVED: [(&str, &str); 20] = [
+    ("display.esc_leaves_fullscreen", "window.esc_leaves_fullscreen"),
+    ("display.units", "formatting.units"),
+    ("display.time_format", "hud.time_format"),
+    ("display.coordinate_format", "hud.coordinate_format"),
+    ("display.graticule_color", "hud.graticule_color"),
+    ("display.hud_scale", "hud.scale"),
+    ("display.hud_visible", "hud.visible"),
+    ("display.hide_hud_in_screenshots", "hud.hide_in_screenshots"),
+    ("display.info_panel_fields", "hud.info_panel_fields"),
+    ("display.theme", "hud.theme"),
+    ("display.custom_theme", "hud.custom_theme"),
+    ("startup.width", "window.width"),
+    ("startup.height", "window.height"),
+    ("startup.title", "window.title"),
+    ("startup.fullscreen", "window.fullscreen"),
+    ("startup.mode", "modes.default"),
+    ("startup.labels", "labels.enabled"),
+    ("startup.font", "hud.font"),
+    ("startup.start", "camera.start"),
+    ("hud.units", "formatting.units"),
+];
+
+/// Everything configurable from the settings file.
+///
+/// Settings are layered: built-in defaults, then the settings file, then the command line. Only
+/// the first two are saved back when settings are changed from the settings overlay.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub camera: CameraSettings,
+    pub terrain: TerrainSettings,
+    pub hud: HudSettings,
+    pub labels: LabelSettings,
+    pub formatting: FormattingSettings,
+    pub quality: QualitySettings,
+    pub diagnostics: DiagnosticsSettings,
+    pub points: PointSettings,
+    pub routes: RouteSettings,
+    pub tour: TourSettings,
+    pub modes: ModeSettings,
+    pub split: SplitSettings,
+    pub stereo: StereoSettings,
+    pub sky: SkySettings,
+    pub links: LinkSettings,
+    pub countries: CountrySettings,
+    pub profiles: ProfileSettings,
+    pub paths: PathSettings,
+
+    /// Key bindings, from key name (such as "F5", "G" or "D1" for the 1 key) to action (such as
+    /// "toggle_graticule"). These add to the built-in bindings, replacing any on the same key.
+    pub bindings: BTreeMap<String, Action>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    /// The window's size, in points.
+    pub width: u32,
+    pub height: u32,
+
+    /// Where the window's top-left corner opens, in pixels from the top-left corner of `monitor`,
+    /// or of the desktop if no monitor is given. If not given, the window opens centered on
+    /// `monitor`, or else where it was last time.
+    pub position: Option<[i32; 2]>,
+
+    /// The index of the monitor to open the window on, and to go fullscreen on. If not given, the
+    /// window's current monitor is used.
+    pub monitor: Option<usize>,
+
+    /// Whether to open the window where it was, and at the size it was, when the demo last
+    /// exited, when neither `position` nor `monitor` is given. A window saved on a monitor that
+    /// is no longer there is moved onto the primary one.
+    pub restore_geometry: bool,
+
+    pub title: String,
+
+    pub fullscreen: bool,
+
+    /// If true, pressing Escape while fullscreen returns to windowed mode, and only a second
+    /// press quits. If false, Escape always quits.
+    pub esc_leaves_fullscreen: bool,
+
+    /// The OpenGL version to try first, such as "3.2". If the driver does not support it, newer
+    /// and then older versions are tried.
+    pub gl_version: String,
+
+    /// Whether to wait for the display's refresh before showing each frame.
+    pub vsync: bool,
+
+    /// How many samples per pixel to multisample with: 0 (off), 2, 4 or 8. If the driver does
+    /// not support the count, lower ones are tried.
+    pub samples: u8,
+
+    /// Whether to ask for an sRGB framebuffer. The HUD and map colors are chosen for one, so
+    /// they look wrong without it, but some drivers cannot provide one.
+    pub srgb: bool,
+
+    /// At most how many frames to draw per second, or 0 for no limit, leaving vsync to pace
+    /// them, as by default.
+    pub fps_limit: u32,
+
+    /// How many frames to draw per second while there is no input and nothing is animating, or
+    /// 0 to always draw at the full rate.
+    pub idle_fps: u32,
+
+    /// What the demo does while the window is not focused.
+    pub unfocused: Unfocused,
+}
+
+impl Default for WindowSettings {
+    fn default() -> WindowSettings {
+        WindowSettings {
+            width: 960,
+            height: 520,
+            position: None,
+            monitor: None,
+            restore_geometry: true,
+            title: "Gaia".to_string(),
+            fullscreen: false,
+            esc_leaves_fullscreen: true,
+            gl_version: "3.2".to_string(),
+            vsync: false,
+            samples: 0,
+            srgb: true,
+            fps_limit: 0,
+            idle_fps: 10,
+            unfocused: Unfocused::default(),
+        }
+    }
+}
+
+/// What the demo does while its window is not focused. Whatever it is, drags and held keys are
+/// let go of, since their releases are not delivered. Written in lowercase in the settings file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unfocused {
+    /// Draws nothing, and freezes the animation clock, and with it the tour.
+    Pause,
+    /// Draws at the idle frame rate, and freezes the animation clock, and with it the tour.
+    #[default]
+    Throttle,
+    /// Carries on as if focused, as for a kiosk left running behind another window.
+    Continue,
+}
+
+impl Unfocused {
+    /// Whether the animation clock is frozen, and frames slowed, while unfocused.
+    pub fn suspends(&self) -> bool {
+        *self != Unfocused::Continue
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// Multiplies how far the map moves per pixel of middle-button drag.
+    pub pan_sensitivity: f32,
+
+    /// How much a single scroll notch multiplies (zooming out) or divides (zooming in) the camera
+    /// height by.
+    pub zoom_per_notch: f32,
+
+    /// Multiplies how far the view turns per pixel of right-button drag.
+    pub rotate_sensitivity: f32,
+
+    /// Whether to capture the cursor while rotating, so the drag can go past the window edge.
+    /// Can be turned off on platforms where capturing misbehaves.
+    pub capture_cursor: bool,
+
+    /// Whether flicking the map sideways while zoomed out sends the globe spinning about its
+    /// axis, slowing down by `spin_decay`.
+    pub spin: bool,
+
+    /// What fraction of the globe's spin is left after each second.
+    pub spin_decay: f32,
+
+    /// How low and high the camera can go, as heights shown in the HUD.
+    pub min_height: f32,
+    pub max_height: f32,
+
+    /// Where the camera starts out. If not given, it starts at the southwest corner of the map,
+    /// zoomed all the way out.
+    pub start: Option<StartPosition>,
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            pan_sensitivity: 1.0,
+            zoom_per_notch: DEFAULT_ZOOM_PER_NOTCH,
+            rotate_sensitivity: 1.0,
+            capture_cursor: true,
+            spin: true,
+            spin_decay: 0.6,
+            min_height: 0.05,
+            max_height: 1.0,
+            start: None,
+        }
+    }
+}
+
+/// How the camera is kept clear of high terrain, which is approximated by regions since the
+/// renderer has no elevations to sample.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainSettings {
+    /// How far above the terrain the camera is kept, as a camera height. `camera.min_height`
+    /// still applies everywhere.
+    pub clearance: f32,
+
+    /// The regions of high terrain, each with how high it is as a camera height. Where they
+    /// overlap, the highest applies.
+    pub regions: Vec<TerrainRegion>,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> TerrainSettings {
+        TerrainSettings {
+            clearance: 0.02,
+            regions: terrain_clearance::default_regions(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StartPosition {
+    /// In degrees, from -90 to 90.
+    pub latitude: f32,
+    /// In degrees, from -180 to 180.
+    pub longitude: f32,
+    /// The camera height, as shown in the HUD.
+    pub height: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HudSettings {
+    /// Whether the HUD is shown. Toggled with F10 for clean captures.
+    pub visible: bool,
+
+    /// How much larger than normal to draw HUD widgets, for high-DPI screens.
+    pub scale: f32,
+
+    /// Which colors the HUD is drawn in. `custom` uses `custom_theme`.
+    pub theme: ThemeName,
+
+    /// The TrueType font the HUD is drawn with. If it cannot be found, a common system font is
+    /// used instead.
+    pub font: PathBuf,
+
+    /// A font to draw HUD text with that `font` has no glyphs for, such as one that covers CJK
+    /// scripts. If it cannot be found, such text is drawn with `font` as it is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_fallback: Option<PathBuf>,
+
+    /// If true, screenshots are taken with the HUD hidden, even when it is otherwise shown.
+    pub hide_in_screenshots: bool,
+
+    /// Whether times, such as the local solar time, are shown on a 12- or 24-hour clock.
+    pub time_format: TimeFormat,
+
+    /// How coordinates, such as the crosshair readout, are written.
+    pub coordinate_format: CoordinateFormat,
+
+    /// The RGBA color of latitude and longitude lines, each component between 0 and 1.
+    pub graticule_color: [f32; 4],
+
+    /// Which properties of the selected country the info panel shows, by their name in the
+    /// dataset.
+    pub info_panel_fields: Vec<String>,
+
+    /// The colors used when `theme` is `custom`.
+    pub custom_theme: Theme,
+
+    pub widgets: HudWidgets,
+
+    pub safe_area: SafeArea,
+}
+
+impl Default for HudSettings {
+    fn default() -> HudSettings {
+        HudSettings {
+            visible: true,
+            scale: 1.0,
+            theme: ThemeName::Dark,
+            font: PathBuf::from("assets/fonts/FiraSans-Regular.ttf"),
+            font_fallback: None,
+            hide_in_screenshots: false,
+            time_format: TimeFormat::TwentyFourHour,
+            coordinate_format: CoordinateFormat::Decimal,
+            graticule_color: [1.0, 1.0, 1.0, 0.3],
+            info_panel_fields: ::info_panel::default_fields(),
+            custom_theme: Theme::default(),
+            widgets: HudWidgets::default(),
+            safe_area: SafeArea::default(),
+        }
+    }
+}
+
+/// Which HUD widgets are shown. Those that can be toggled with a key only say whether they start
+/// out shown.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HudWidgets {
+    /// The FPS counter, camera position and level of detail.
+    pub position: bool,
+    /// The map mode and toggles.
+    pub status: bool,
+    pub solar_time: bool,
+    pub scale_bar: bool,
+    pub compass: bool,
+    /// Toggled with F3.
+    pub frame_times: bool,
+    /// Toggled with L.
+    pub legend: bool,
+    /// Toggled with C.
+    pub crosshair: bool,
+    /// Toggled with G.
+    pub graticule: bool,
+    /// The name of the country under the cursor, beside it.
+    pub country_tooltip: bool,
+    /// The coordinates under the cursor, wherever it is.
+    pub cursor_coordinates: bool,
+    /// A small overview of the whole map, marking what the main view shows. Toggled with O.
+    pub overview: bool,
+}
+
+impl Default for HudWidgets {
+    fn default() -> HudWidgets {
+        HudWidgets {
+            position: true,
+            status: true,
+            solar_time: true,
+            scale_bar: true,
+            compass: true,
+            frame_times: false,
+            legend: false,
+            crosshair: false,
+            graticule: false,
+            country_tooltip: false,
+            cursor_coordinates: false,
+            overview: false,
+        }
+    }
+}
+
+/// How far the HUD keeps in from each window edge, for displays and capture setups that crop or
+/// cover them. Panels, the legend, the scale bar and toasts all stay inside.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafeArea {
+    /// What the margins are measured in.
+    pub unit: SafeAreaUnit,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SafeAreaUnit {
+    /// Percent of the window's height for the top and bottom, and of its width for the sides.
+    Percent,
+    /// Physical pixels, however large the HUD is drawn.
+    Pixels,
+}
+
+impl Default for SafeArea {
+    fn default() -> SafeArea {
+        SafeArea {
+            unit: SafeAreaUnit::Percent,
+            top: 0.0,
+            right: 0.0,
+            bottom: 0.0,
+            left: 0.0,
+        }
+    }
+}
+
+/// How map labels are drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LabelSettings {
+    /// Whether labels start out shown. Toggled with 0.
+    pub enabled: bool,
+
+    /// The text size of most labels, and of capitals.
+    pub scale: f32,
+    pub capital_scale: f32,
+
+    /// RGBA colors, each component between 0 and 1.
+    pub color: [f32; 4],
+    pub capital_color: [f32; 4],
+    pub border_color: [f32; 4],
+
+    pub border_width: f32,
+
+    /// Remaps the `min_zoom` stored with each place to the one its label is shown by, as
+    /// `[stored, effective]` control points. See `min_zoom_curve`.
+    pub min_zoom_curve: ControlPoints,
+
+    /// Whether to leave out all but the most prominent of the labels that would fall close
+    /// together on screen. See `label_declutter`.
+    pub declutter: bool,
+    /// How close together, in pixels, labels are left out: those in the same square this many
+    /// pixels across, on a grid over the view.
+    pub declutter_cell: f32,
+
+    /// How names too long for one line are labeled.
+    pub wrap: LabelWrap,
+}
+
+/// How long place names are fit on the map, so that they do not run across everything near them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LabelWrap {
+    pub overflow: LabelOverflow,
+    /// Names longer than this many characters are wrapped onto a second line, which is no longer
+    /// either. 0 never wraps them.
+    pub width: usize,
+    /// With `LabelOverflow::Ellipsize`, names longer than this many characters are cut short. 0
+    /// never cuts them.
+    pub max_length: usize,
+    /// How large wrapped labels are drawn next to others, since gaia draws each label in one
+    /// size, so that the second line is no larger than the first.
+    pub wrapped_scale: f32,
+}
+
+/// What is done with names longer than `LabelWrap` allows.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelOverflow {
+    /// Broken onto two lines at a space.
+    Wrap,
+    /// Cut short with an ellipsis, on one line.
+    Ellipsize,
+}
+
+impl Default for LabelWrap {
+    fn default() -> LabelWrap {
+        LabelWrap {
+            overflow: LabelOverflow::Wrap,
+            width: 0,
+            max_length: 24,
+            wrapped_scale: 0.85,
+        }
+    }
+}
+
+impl LabelWrap {
+    pub const MAX_WIDTH: usize = 200;
+
+    pub fn validated(self) -> LabelWrap {
+        let defaults = LabelWrap::default();
+
+        LabelWrap {
+            width: self.width.min(Self::MAX_WIDTH),
+            max_length: self.max_length.min(Self::MAX_WIDTH),
+            wrapped_scale: clamp_setting((0.25, 1.0), defaults.wrapped_scale, self.wrapped_scale),
+            ..self
+        }
+    }
+}
+
+impl Default for LabelSettings {
+    fn default() -> LabelSettings {
+        LabelSettings {
+            enabled: false,
+            scale: 20.0,
+            capital_scale: 30.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+            capital_color: [1.0, 1.0, 0.0, 1.0],
+            border_color: [0.0, 0.0, 0.0, 1.0],
+            border_width: 1.0,
+            min_zoom_curve: min_zoom_curve::IDENTITY,
+            declutter: true,
+            declutter_cell: 48.0,
+            wrap: LabelWrap::default(),
+        }
+    }
+}
+
+/// How detail is traded for speed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualitySettings {
+    /// Whether to draw the map in less detail than the camera height calls for while frames take
+    /// longer than `frame_budget_ms`, until they speed up again.
+    pub adaptive: bool,
+
+    /// How long drawing a frame may take, in milliseconds: drawing the map and the HUD, not
+    /// counting time spent waiting for the frame limit or for vsync.
+    pub frame_budget_ms: f32,
+
+    /// How long the 2D pass over the map may take, in milliseconds. While it takes longer, the
+    /// costliest optional parts of the HUD are drawn in less detail or left out, until it speeds
+    /// up again. 0 never leaves anything out.
+    pub hud_budget_ms: f32,
+
+    /// What fraction of the window's resolution to draw the map at, before stretching it over
+    /// the window. The HUD is always drawn at the full resolution.
+    pub render_scale: f32,
+
+    /// The least and most detailed levels drawn, whatever the camera height calls for.
+    pub min_level: u8,
+    pub max_level: u8,
+
+    /// Which level the camera height calls for: the level of the last threshold the camera is
+    /// below, or `min_level` if it is above them all. Heights must decrease and levels increase.
+    pub levels: Vec<LevelThreshold>,
+}
+
+impl Default for QualitySettings {
+    fn default() -> QualitySettings {
+        QualitySettings {
+            adaptive: true,
+            frame_budget_ms: 20.0,
+            hud_budget_ms: 4.0,
+            render_scale: 1.0,
+            min_level: 1,
+            max_level: MAX_LEVEL,
+            levels: vec![
+                LevelThreshold::new(0.7, 2),
+                LevelThreshold::new(0.5, 3),
+                LevelThreshold::new(0.2, 4),
+                LevelThreshold::new(0.1, 5),
+            ],
+        }
+    }
+}
+
+/// What is recorded to help diagnose problems.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsSettings {
+    /// Frames that take longer than this many milliseconds to draw are recorded in the spike log,
+    /// with what was happening at the time. 0 records none.
+    pub spike_threshold_ms: f32,
+
+    /// Whether to keep the last `replay_secs` of frames in memory, shrunk, so that F9 can save
+    /// them as an animated GIF after something went wrong. Off by default, since it reads back
+    /// `replay_fps` frames every second.
+    pub replay_buffer: bool,
+    pub replay_secs: f32,
+    pub replay_fps: f32,
+    /// The fraction of the window's resolution the frames are kept at.
+    pub replay_scale: f32,
+}
+
+impl Default for DiagnosticsSettings {
+    fn default() -> DiagnosticsSettings {
+        DiagnosticsSettings {
+            spike_threshold_ms: 100.0,
+            replay_buffer: false,
+            replay_secs: 15.0,
+            replay_fps: 10.0,
+            replay_scale: 0.25,
+        }
+    }
+}
+
+/// How the points given with `--points` are drawn.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PointSettings {
+    /// Whether each point's name is drawn beside it.
+    pub labels: bool,
+    /// How many points across markers are, with the camera at its highest. They grow as it
+    /// gets lower.
+    pub marker_size: f32,
+    /// The RGBA color of points whose category is not in `category_colors`, each component
+    /// between 0 and 1.
+    pub default_color: [f32; 4],
+    /// The RGBA color of points by their `category` property.
+    pub category_colors: BTreeMap<String, [f32; 4]>,
+}
+
+impl Default for PointSettings {
+    fn default() -> PointSettings {
+        PointSettings {
+            labels: true,
+            marker_size: 8.0,
+            default_color: [1.0, 0.3, 0.2, 1.0],
+            category_colors: BTreeMap::new(),
+        }
+    }
+}
+
+/// How a marker travels along a route when one is played.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteSettings {
+    /// How fast the marker travels over the ground, in km/h.
+    pub speed_kmh: f32,
+    /// Whether the camera starts out following the marker, facing the way it travels. Toggled
+    /// with F.
+    pub follow: bool,
+    /// The camera height to follow the marker from.
+    pub follow_height: f32,
+}
+
+impl Default for RouteSettings {
+    fn default() -> RouteSettings {
+        RouteSettings {
+            speed_kmh: 900.0,
+            follow: false,
+            follow_height: 0.1,
+        }
+    }
+}
+
+/// How the capitals tour goes from capital to capital.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TourSettings {
+    /// The camera height each capital is looked at from.
+    pub height: f32,
+    /// How long the tour stays at each capital, in seconds, once it gets there.
+    pub dwell_secs: f32,
+    /// How many times its usual size the capital's label is drawn while the tour is there.
+    pub label_scale: f32,
+}
+
+impl Default for TourSettings {
+    fn default() -> TourSettings {
+        TourSettings {
+            height: 0.15,
+            dwell_secs: 4.0,
+            label_scale: 2.0,
+        }
+    }
+}
+
+/// A level of detail, drawn while the camera is lower than `below_height`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelThreshold {
+    pub below_height: f32,
+    pub level: u8,
+}
+
+impl LevelThreshold {
+    pub fn new(below_height: f32, level: u8) -> LevelThreshold {
+        LevelThreshold {
+            below_height,
+            level,
+        }
+    }
+}
+
+/// How numbers and distances are written in the HUD, such as "1,234,567.8 km" or
+/// "1.234.567,8 km".
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormattingSettings {
+    /// What separates whole numbers from their fractions, such as "." or ",".
+    pub decimal_separator: char,
+
+    /// What groups thousands, such as ",", "." or " ".
+    pub thousands_separator: char,
+
+    /// Whether thousands are grouped at all.
+    pub group_thousands: bool,
+
+    /// Whether large counts, such as populations, are shortened, as in "1.2M".
+    pub compact_numbers: bool,
+
+    /// Which units distances, such as on the scale bar, are shown in.
+    pub units: Units,
+}
+
+impl Default for FormattingSettings {
+    fn default() -> FormattingSettings {
+        FormattingSettings {
+            decimal_separator: '.',
+            thousands_separator: ',',
+            group_thousands: true,
+            compact_numbers: true,
+            units: Units::Metric,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeSettings {
+    /// Which map mode to start in. It can be one of `hidden`.
+    pub default: MapMode,
+    /// The order the number keys and M go through the map modes in. Modes left out follow in
+    /// their usual order.
+    pub order: Vec<MapMode>,
+    /// Modes left out of the number keys and of M, as for a kiosk that only shows some.
+    pub hidden: Vec<MapMode>,
+    /// The rhai script scripted mode colors countries with. A relative path is relative to the
+    /// working directory the demo was started in.
+    pub script: Option<PathBuf>,
+    /// How many times faster than real time day and night mode's clock runs.
+    pub time_acceleration: f32,
+    /// Which colors countries are drawn in, in modes with categories of their own.
+    pub palette: Palette,
+    /// The least opacity country colors are drawn with, from 0 to 255. Day and night mode, whose
+    /// opacity shows how dark it is, is left as it is.
+    pub min_alpha: u8,
+    /// Each mode's tunable parameters, adjusted with PgUp, PgDn, + and -.
+    pub parameters: ModeParameters,
+    /// What each mode does to labels when it is switched to.
+    pub labels: ModeLabelPolicies,
+    /// Class breaks edited in the legend, with B.
+    pub breaks: ModeBreaks,
+    /// What the settings overlay's adjustments for each mode change of those above, when it is
+    /// shown.
+    pub adjustments: SavedAdjustments,
+}
+
+impl Default for ModeSettings {
+    fn default() -> ModeSettings {
+        ModeSettings {
+            default: MapMode::Terrain,
+            order: vec![],
+            hidden: vec![],
+            script: None,
+            time_acceleration: 1.0,
+            palette: Palette::Standard,
+            min_alpha: 0,
+            parameters: ModeParameters::default(),
+            labels: ModeLabelPolicies::default(),
+            breaks: ModeBreaks::default(),
+            adjustments: SavedAdjustments::default(),
+        }
+    }
+}
+
+impl ModeSettings {
+    /// From a standstill to a day every second.
+    pub const TIME_ACCELERATION_RANGE: (f32, f32) = (0.0, 86_400.0);
+
+    /// The modes the number keys and M go through, in order.
+    pub fn shown(&self) -> Vec<MapMode> {
+        let mut shown: Vec<MapMode> = vec![];
+        for &mode in self.order.iter().chain(&MapMode::ALL) {
+            if !shown.contains(&mode) && !self.hidden.contains(&mode) {
+                shown.push(mode);
+            }
+        }
+        shown
+    }
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    /// Modes listed twice in `order` are kept where they first are, and if every mode is hidden,
+    /// none is.
+    pub fn validated(self) -> ModeSettings {
+        let defaults = ModeSettings::default();
+
+        let mut order: Vec<MapMode> = vec![];
+        for &mode in &self.order {
+            if !order.contains(&mode) {
+                order.push(mode);
+            }
+        }
+        let hidden = if MapMode::ALL.iter().all(|mode| self.hidden.contains(mode)) {
+            vec![]
+        } else {
+            self.hidden.clone()
+        };
+
+        ModeSettings {
+            order,
+            hidden,
+            time_acceleration: clamp_setting(
+                Self::TIME_ACCELERATION_RANGE,
+                defaults.time_acceleration,
+                self.time_acceleration,
+            ),
+            parameters: self.parameters.validated(),
+            breaks: self.breaks.validated(),
+            ..self
+        }
+    }
+}
+
+/// Bundles of settings switched on and off as one, layered over the rest of the settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileSettings {
+    /// The profile in use, if any. The high contrast profile is toggled with F7.
+    pub active: Option<Profile>,
+    pub high_contrast: HighContrastSettings,
+}
+
+/// What `Profile::HighContrast` changes, beyond the theme and palette it switches to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighContrastSettings {
+    /// How many times their usual size labels are drawn.
+    pub label_scale: f32,
+    /// How many times their usual width label borders are drawn.
+    pub border_scale: f32,
+    /// The least opacity country colors are drawn with, from 0 to 255.
+    pub min_alpha: u8,
+}
+
+impl Default for HighContrastSettings {
+    fn default() -> HighContrastSettings {
+        HighContrastSettings {
+            label_scale: 1.5,
+            border_scale: 2.0,
+            min_alpha: 220,
+        }
+    }
+}
+
+impl HighContrastSettings {
+    pub const SCALE_RANGE: (f32, f32) = (1.0, 4.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> HighContrastSettings {
+        let defaults = HighContrastSettings::default();
+
+        HighContrastSettings {
+            label_scale: clamp_setting(Self::SCALE_RANGE, defaults.label_scale, self.label_scale),
+            border_scale: clamp_setting(
+                Self::SCALE_RANGE,
+                defaults.border_scale,
+                self.border_scale,
+            ),
+            ..self
+        }
+    }
+}
+
+/// How split-screen, which compares two map modes side by side, starts out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitSettings {
+    /// The map mode the right half starts in. The left half shows the current mode.
+    pub mode: MapMode,
+    /// Which halves labels are drawn in.
+    pub labels: SplitLabels,
+}
+
+impl Default for SplitSettings {
+    fn default() -> SplitSettings {
+        SplitSettings {
+            mode: MapMode::Income,
+            labels: SplitLabels::Both,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitLabels {
+    Both,
+    Left,
+    Right,
+}
+
+/// What is drawn behind the map, where the view looks past it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SkySettings {
+    /// The color the window is cleared to, as RGB, each component between 0 and 1.
+    pub color: [f32; 3],
+    /// Whether stars are drawn.
+    pub stars: bool,
+    /// How many stars there are over the whole sky, of which the view shows a part.
+    pub star_count: u32,
+    /// How bright the brightest stars are, from 0 to 1.
+    pub star_brightness: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> SkySettings {
+        SkySettings {
+            color: [0.3, 0.3, 0.3],
+            stars: false,
+            star_count: 3000,
+            star_brightness: 0.8,
+        }
+    }
+}
+
+impl SkySettings {
+    pub const MAX_STAR_COUNT: u32 = 20_000;
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> SkySettings {
+        let defaults = SkySettings::default();
+        let mut color = self.color;
+        for (component, default) in color.iter_mut().zip(&defaults.color) {
+            *component = clamp_setting((0.0, 1.0), *default, *component);
+        }
+
+        SkySettings {
+            color,
+            star_count: self.star_count.min(Self::MAX_STAR_COUNT),
+            star_brightness: clamp_setting(
+                (0.0, 1.0),
+                defaults.star_brightness,
+                self.star_brightness,
+            ),
+            ..self
+        }
+    }
+
+    /// The color the window is cleared to, as RGBA.
+    pub fn clear_color(&self) -> [f32; 4] {
+        let [r, g, b] = self.color;
+
+        [r, g, b, 1.0]
+    }
+}
+
+/// Opening the selected country's web page in the browser, with Enter or from its info panel.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkSettings {
+    /// Whether countries' web pages can be opened at all. Kiosks should switch this off, so that
+    /// no browser can be opened over the demo.
+    pub enabled: bool,
+    /// The country property holding each country's web page.
+    pub property: String,
+    /// The web page of countries without `property`, with each property named in braces, such as
+    /// `{NAME}`, replaced by the country's, percent-encoded.
+    pub template: Option<String>,
+}
+
+impl Default for LinkSettings {
+    fn default() -> LinkSettings {
+        LinkSettings {
+            enabled: true,
+            property: "URL".to_string(),
+            template: None,
+        }
+    }
+}
+
+/// Which countries are gone through and counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CountrySettings {
+    /// Which countries cycling through them, exports and statistics go by. Adjusted in the
+    /// settings overlay.
+    pub filter: CountryFilter,
+}
+
+/// How the red and cyan anaglyph mode places its two eyes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StereoSettings {
+    /// How far apart the eyes are, as a fraction of the camera height, so that the depth looks
+    /// the same at any zoom.
+    pub eye_separation: f32,
+    /// How far away the eyes' lines of sight cross, as a multiple of the distance to the point
+    /// looked at. What is nearer stands out of the screen, and what is further sinks into it.
+    pub convergence: f32,
+}
+
+impl Default for StereoSettings {
+    fn default() -> StereoSettings {
+        StereoSettings {
+            eye_separation: 0.03,
+            convergence: 1.0,
+        }
+    }
+}
+
+impl StereoSettings {
+    pub const EYE_SEPARATION_RANGE: (f32, f32) = (0.0, 0.2);
+    pub const CONVERGENCE_RANGE: (f32, f32) = (0.25, 4.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> StereoSettings {
+        let defaults = StereoSettings::default();
+
+        StereoSettings {
+            eye_separation: clamp_setting(
+                Self::EYE_SEPARATION_RANGE,
+                defaults.eye_separation,
+                self.eye_separation,
+            ),
+            convergence: clamp_setting(
+                Self::CONVERGENCE_RANGE,
+                defaults.convergence,
+                self.convergence,
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    Metric,
+    Imperial,
+}
+
+impl Units {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Units::Metric => "Metric",
+            Units::Imperial => "Imperial",
+        }
+    }
+
+    pub fn toggled(&self) -> Units {
+        match *self {
+            Units::Metric => Units::Imperial,
+            Units::Imperial => Units::Metric,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TimeFormat {
+    #[serde(rename = "24h")]
+    TwentyFourHour,
+    #[serde(rename = "12h")]
+    TwelveHour,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoordinateFormat {
+    /// Decimal degrees, such as 51.50740°N.
+    Decimal,
+    /// Degrees, minutes and seconds, such as 51°30'26.6"N.
+    Dms,
+}
+
+impl SafeAreaUnit {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SafeAreaUnit::Percent => "Percent",
+            SafeAreaUnit::Pixels => "Pixels",
+        }
+    }
+
+    /// What margins in this unit are written with.
+    pub fn suffix(&self) -> &'static str {
+        match *self {
+            SafeAreaUnit::Percent => "%",
+            SafeAreaUnit::Pixels => " px",
+        }
+    }
+
+    pub fn toggled(&self) -> SafeAreaUnit {
+        match *self {
+            SafeAreaUnit::Percent => SafeAreaUnit::Pixels,
+            SafeAreaUnit::Pixels => SafeAreaUnit::Percent,
+        }
+    }
+
+    /// How far each margin can go in this unit.
+    pub fn range(&self) -> (f32, f32) {
+        match *self {
+            SafeAreaUnit::Percent => (0.0, 25.0),
+            SafeAreaUnit::Pixels => (0.0, 500.0),
+        }
+    }
+
+    /// How much the settings overlay changes a margin by per key press.
+    pub fn step(&self) -> f32 {
+        match *self {
+            SafeAreaUnit::Percent => 0.5,
+            SafeAreaUnit::Pixels => 5.0,
+        }
+    }
+}
+
+impl SafeArea {
+    /// The edges' names, in `[top, right, bottom, left]` order, as `edge_mut` takes them.
+    pub const EDGES: [&'static str; 4] = ["top", "right", "bottom", "left"];
+
+    pub fn edges(&self) -> [f32; 4] {
+        [self.top, self.right, self.bottom, self.left]
+    }
+
+    /// The margin with index `edge` in `EDGES`.
+    pub fn edge_mut(&mut self, edge: usize) -> &mut f32 {
+        match edge {
+            0 => &mut self.top,
+            1 => &mut self.right,
+            2 => &mut self.bottom,
+            _ => &mut self.left,
+        }
+    }
+
+    /// The margins in points, for a window `view_size` points large with `density` physical
+    /// pixels per point, as `[top, right, bottom, left]`. Each is limited to a quarter of the
+    /// window, so that at least half of it is always left for the HUD.
+    pub fn insets(&self, view_size: [f64; 2], density: f64) -> [f64; 4] {
+        let [width, height] = view_size;
+        let [top, right, bottom, left] = self.edges();
+        let inset = |margin: f32, across: f64| {
+            let margin = f64::from(margin);
+            let points = match self.unit {
+                SafeAreaUnit::Percent => margin / 100.0 * across,
+                SafeAreaUnit::Pixels => margin / density.max(1e-3),
+            };
+            points.min(across / 4.0).max(0.0)
+        };
+
+        [inset(top, height), inset(right, width), inset(bottom, height), inset(left, width)]
+    }
+
+    /// Clamps every margin into its unit's range. Non-finite values are replaced with 0.
+    pub fn validated(self) -> SafeArea {
+        let mut validated = self;
+        for edge in 0..4 {
+            let margin = validated.edge_mut(edge);
+            *margin = clamp_setting(self.unit.range(), 0.0, *margin);
+        }
+        validated
+    }
+}
+
+impl CoordinateFormat {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CoordinateFormat::Decimal => "Decimal degrees",
+            CoordinateFormat::Dms => "Degrees, minutes, seconds",
+        }
+    }
+
+    pub fn toggled(&self) -> CoordinateFormat {
+        match *self {
+            CoordinateFormat::Decimal => CoordinateFormat::Dms,
+            CoordinateFormat::Dms => CoordinateFormat::Decimal,
+        }
+    }
+}
+
+impl TimeFormat {
+    pub fn format(&self, hours: u32, minutes: u32) -> String {
+        match *self {
+            TimeFormat::TwentyFourHour => format!("{:02}:{:02}", hours, minutes),
+            TimeFormat::TwelveHour => {
+                let suffix = if hours < 12 { "AM" } else { "PM" };
+                let hours = match hours % 12 {
+                    0 => 12,
+                    hours => hours,
+                };
+
+                format!("{:2}:{:02} {}", hours, minutes, suffix)
+            }
+        }
+    }
+}
+
+/// Where to load files from. Relative paths are looked for in the working directory, then next
+/// to the executable.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathSettings {
+    /// The directory generated tiles and the default font are loaded from. It must be named
+    /// `assets`.
+    pub assets_dir: PathBuf,
+    /// Which asset set to load: one of `asset_sets`, or `DEFAULT_ASSET_SET` for `assets_dir`.
+    pub asset_set: String,
+    /// Other directories of generated assets to switch between while running, by name, such as
+    /// ones generated at other Natural Earth scales. Each must be named `assets` too. The font is
+    /// always loaded from `assets_dir`.
+    pub asset_sets: BTreeMap<String, PathBuf>,
+}
+
+impl Default for PathSettings {
+    fn default() -> PathSettings {
+        PathSettings {
+            assets_dir: PathBuf::from("assets"),
+            asset_set: PathSettings::DEFAULT_ASSET_SET.to_string(),
+            asset_sets: BTreeMap::new(),
+        }
+    }
+}
+
+impl PathSettings {
+    /// The name of the asset set in `assets_dir`.
+    pub const DEFAULT_ASSET_SET: &'static str = "default";
+
+    /// The directory of the asset set named `name`, if there is one.
+    pub fn asset_set_dir(&self, name: &str) -> Option<&Path> {
+        if name == Self::DEFAULT_ASSET_SET {
+            Some(&self.assets_dir)
+        } else {
+            self.asset_sets.get(name).map(PathBuf::as_path)
+        }
+    }
+
+    /// The names of every asset set, `DEFAULT_ASSET_SET` first.
+    pub fn asset_set_names(&self) -> Vec<&str> {
+        let named = self
+            .asset_sets
+            .keys()
+            .map(String::as_str)
+            .filter(|&name| name != Self::DEFAULT_ASSET_SET);
+
+        Some(Self::DEFAULT_ASSET_SET).into_iter().chain(named).collect()
+    }
+
+    /// Selects the asset set `steps` after the selected one, wrapping around.
+    pub fn cycle_asset_set(&mut self, steps: isize) {
+        let names = self.asset_set_names();
+        let current = names.iter().position(|&name| name == self.asset_set).unwrap_or(0) as isize;
+        let next = (current + steps).rem_euclid(names.len() as isize) as usize;
+
+        self.asset_set = names[next].to_string();
+    }
+}
+
+impl WindowSettings {
+    /// Fixes values that cannot work.
+    pub fn validated(self) -> WindowSettings {
+        let samples = self.samples;
+
+        WindowSettings {
+            width: self.width.max(1),
+            height: self.height.max(1),
+            samples: SAMPLE_COUNTS
+                .iter()
+                .cloned()
+                .find(|&count| count <= samples)
+                .unwrap_or(0),
+            ..self
+        }
+    }
+}
+
+impl CameraSettings {
+    pub const PAN_SENSITIVITY_RANGE: (f32, f32) = (0.1, 10.0);
+    pub const ZOOM_PER_NOTCH_RANGE: (f32, f32) = (1.001, 2.0);
+    pub const ROTATE_SENSITIVITY_RANGE: (f32, f32) = (0.1, 10.0);
+    pub const HEIGHT_RANGE: (f32, f32) = (0.01, 1.0);
+    pub const SPIN_DECAY_RANGE: (f32, f32) = (0.01, 0.99);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> CameraSettings {
+        let defaults = CameraSettings::default();
+        let min_height = clamp_setting(Self::HEIGHT_RANGE, defaults.min_height, self.min_height);
+        let max_height = clamp_setting(
+            (min_height, Self::HEIGHT_RANGE.1),
+            defaults.max_height.max(min_height),
+            self.max_height,
+        );
+        let start = self.start.map(|start| StartPosition {
+            latitude: clamp_setting((-90.0, 90.0), 0.0, start.latitude),
+            longitude: clamp_setting((-180.0, 180.0), 0.0, start.longitude),
+            height: clamp_setting((min_height, max_height), max_height, start.height),
+        });
+
+        CameraSettings {
+            pan_sensitivity: clamp_setting(
+                Self::PAN_SENSITIVITY_RANGE,
+                defaults.pan_sensitivity,
+                self.pan_sensitivity,
+            ),
+            zoom_per_notch: clamp_setting(
+                Self::ZOOM_PER_NOTCH_RANGE,
+                defaults.zoom_per_notch,
+                self.zoom_per_notch,
+            ),
+            rotate_sensitivity: clamp_setting(
+                Self::ROTATE_SENSITIVITY_RANGE,
+                defaults.rotate_sensitivity,
+                self.rotate_sensitivity,
+            ),
+            spin_decay: clamp_setting(Self::SPIN_DECAY_RANGE, defaults.spin_decay, self.spin_decay),
+            min_height,
+            max_height,
+            start,
+            ..self
+        }
+    }
+}
+
+impl TerrainSettings {
+    pub const CLEARANCE_RANGE: (f32, f32) = (0.0, 1.0);
+
+    /// Clamps the clearance into its sane range, and leaves out regions whose bounds are out of
+    /// order or off the map.
+    pub fn validated(self) -> TerrainSettings {
+        let defaults = TerrainSettings::default();
+
+        TerrainSettings {
+            clearance: clamp_setting(Self::CLEARANCE_RANGE, defaults.clearance, self.clearance),
+            regions: self.regions.into_iter().filter(TerrainRegion::is_valid).collect(),
+        }
+    }
+}
+
+impl HudSettings {
+    pub const SCALE_RANGE: (f32, f32) = (0.5, 3.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> HudSettings {
+        let defaults = HudSettings::default();
+        let custom = self.custom_theme;
+        let default_custom = defaults.custom_theme;
+
+        HudSettings {
+            graticule_color: clamp_color(defaults.graticule_color, self.graticule_color),
+            scale: clamp_setting(Self::SCALE_RANGE, defaults.scale, self.scale),
+            safe_area: self.safe_area.validated(),
+            custom_theme: Theme {
+                panel_background: clamp_color(
+                    default_custom.panel_background,
+                    custom.panel_background,
+                ),
+                text: clamp_color(default_custom.text, custom.text),
+                accent: clamp_color(default_custom.accent, custom.accent),
+                warning: clamp_color(default_custom.warning, custom.warning),
+            },
+            ..self
+        }
+    }
+
+    /// The colors of the selected `theme`.
+    pub fn theme(&self) -> Theme {
+        match self.theme {
+            ThemeName::Dark => Theme::dark(),
+            ThemeName::Light => Theme::light(),
+            ThemeName::HighContrast => Theme::high_contrast(),
+            ThemeName::Custom => self.custom_theme,
+        }
+    }
+}
+
+impl LabelSettings {
+    pub const DECLUTTER_CELL_RANGE: (f32, f32) = (8.0, 400.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> LabelSettings {
+        let defaults = LabelSettings::default();
+
+        LabelSettings {
+            scale: clamp_setting((1.0, 200.0), defaults.scale, self.scale),
+            capital_scale: clamp_setting((1.0, 200.0), defaults.capital_scale, self.capital_scale),
+            color: clamp_color(defaults.color, self.color),
+            capital_color: clamp_color(defaults.capital_color, self.capital_color),
+            border_color: clamp_color(defaults.border_color, self.border_color),
+            border_width: clamp_setting((0.0, 10.0), defaults.border_width, self.border_width),
+            min_zoom_curve: min_zoom_curve::validated(self.min_zoom_curve),
+            wrap: self.wrap.validated(),
+            declutter_cell: clamp_setting(
+                Self::DECLUTTER_CELL_RANGE,
+                defaults.declutter_cell,
+                self.declutter_cell,
+            ),
+            ..self
+        }
+    }
+}
+
+impl QualitySettings {
+    pub const FRAME_BUDGET_RANGE: (f32, f32) = (1.0, 1000.0);
+    pub const HUD_BUDGET_RANGE: (f32, f32) = (0.0, 1000.0);
+    pub const RENDER_SCALE_RANGE: (f32, f32) = (0.25, 1.0);
+
+    /// Why the level thresholds cannot be used, if they cannot: which level a height calls for
+    /// is unclear unless their heights decrease and their levels increase.
+    pub fn levels_error(&self) -> Option<String> {
+        for (i, threshold) in self.levels.iter().enumerate() {
+            if !(threshold.below_height.is_finite() && threshold.below_height > 0.0) {
+                return Some(format!("threshold {}'s height must be above 0", i + 1));
+            }
+            if threshold.level > MAX_LEVEL {
+                return Some(format!(
+                    "threshold {}'s level must be at most {}",
+                    i + 1,
+                    MAX_LEVEL
+                ));
+            }
+        }
+
+        let unordered = self.levels.windows(2).position(|pair| {
+            pair[0].below_height <= pair[1].below_height || pair[0].level >= pair[1].level
+        });
+        unordered.map(|i| {
+            format!(
+                "thresholds {} and {} are out of order: heights must decrease and levels increase",
+                i + 1,
+                i + 2
+            )
+        })
+    }
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default,
+    /// and so are level thresholds that `levels_error` says cannot be used.
+    pub fn validated(self) -> QualitySettings {
+        let defaults = QualitySettings::default();
+        let max_level = self.max_level.min(MAX_LEVEL);
+        let levels_usable = self.levels_error().is_none();
+
+        QualitySettings {
+            frame_budget_ms: clamp_setting(
+                Self::FRAME_BUDGET_RANGE,
+                defaults.frame_budget_ms,
+                self.frame_budget_ms,
+            ),
+            hud_budget_ms: clamp_setting(
+                Self::HUD_BUDGET_RANGE,
+                defaults.hud_budget_ms,
+                self.hud_budget_ms,
+            ),
+            render_scale: clamp_setting(
+                Self::RENDER_SCALE_RANGE,
+                defaults.render_scale,
+                self.render_scale,
+            ),
+            min_level: self.min_level.min(max_level),
+            max_level,
+            levels: if levels_usable {
+                self.levels
+            } else {
+                defaults.levels
+            },
+            ..self
+        }
+    }
+
+    /// The level the thresholds call for at `camera_height`, before `min_level` and `max_level`
+    /// are applied.
+    pub fn threshold_level(&self, camera_height: f32) -> u8 {
+        self.levels
+            .iter()
+            .filter(|threshold| camera_height < threshold.below_height)
+            .map(|threshold| threshold.level)
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// The level to draw at `camera_height`.
+    pub fn level_for_height(&self, camera_height: f32) -> u8 {
+        self.threshold_level(camera_height)
+            .max(self.min_level)
+            .min(self.max_level)
+    }
+}
+
+impl DiagnosticsSettings {
+    pub const SPIKE_THRESHOLD_RANGE: (f32, f32) = (0.0, 10_000.0);
+    pub const REPLAY_SECS_RANGE: (f32, f32) = (1.0, 60.0);
+    pub const REPLAY_FPS_RANGE: (f32, f32) = (1.0, 30.0);
+    pub const REPLAY_SCALE_RANGE: (f32, f32) = (0.1, 1.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> DiagnosticsSettings {
+        let defaults = DiagnosticsSettings::default();
+
+        DiagnosticsSettings {
+            spike_threshold_ms: clamp_setting(
+                Self::SPIKE_THRESHOLD_RANGE,
+                defaults.spike_threshold_ms,
+                self.spike_threshold_ms,
+            ),
+            replay_buffer: self.replay_buffer,
+            replay_secs: clamp_setting(
+                Self::REPLAY_SECS_RANGE,
+                defaults.replay_secs,
+                self.replay_secs,
+            ),
+            replay_fps: clamp_setting(
+                Self::REPLAY_FPS_RANGE,
+                defaults.replay_fps,
+                self.replay_fps,
+            ),
+            replay_scale: clamp_setting(
+                Self::REPLAY_SCALE_RANGE,
+                defaults.replay_scale,
+                self.replay_scale,
+            ),
+        }
+    }
+}
+
+impl RouteSettings {
+    pub const SPEED_RANGE: (f32, f32) = (1.0, 10_000_000.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> RouteSettings {
+        let defaults = RouteSettings::default();
+
+        RouteSettings {
+            speed_kmh: clamp_setting(Self::SPEED_RANGE, defaults.speed_kmh, self.speed_kmh),
+            follow_height: clamp_setting(
+                CameraSettings::HEIGHT_RANGE,
+                defaults.follow_height,
+                self.follow_height,
+            ),
+            ..self
+        }
+    }
+}
+
+impl TourSettings {
+    pub const DWELL_RANGE: (f32, f32) = (0.0, 600.0);
+    pub const LABEL_SCALE_RANGE: (f32, f32) = (0.5, 8.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> TourSettings {
+        let defaults = TourSettings::default();
+
+        TourSettings {
+            height: clamp_setting(CameraSettings::HEIGHT_RANGE, defaults.height, self.height),
+            dwell_secs: clamp_setting(Self::DWELL_RANGE, defaults.dwell_secs, self.dwell_secs),
+            label_scale: clamp_setting(
+                Self::LABEL_SCALE_RANGE,
+                defaults.label_scale,
+                self.label_scale,
+            ),
+        }
+    }
+}
+
+impl PointSettings {
+    pub const MARKER_SIZE_RANGE: (f32, f32) = (1.0, 40.0);
+
+    /// Clamps every value into its sane range. Non-finite values are replaced with the default.
+    pub fn validated(self) -> PointSettings {
+        let defaults = PointSettings::default();
+        let default_color = clamp_color(defaults.default_color, self.default_color);
+
+        PointSettings {
+            labels: self.labels,
+            marker_size: clamp_setting(
+                Self::MARKER_SIZE_RANGE,
+                defaults.marker_size,
+                self.marker_size,
+            ),
+            category_colors: self
+                .category_colors
+                .into_iter()
+                .map(|(category, color)| (category, clamp_color(default_color, color)))
+                .collect(),
+            default_color,
+        }
+    }
+
+    /// The color of points in `category`.
+    pub fn color(&self, category: Option<&str>) -> [f32; 4] {
+        category
+            .and_then(|category| self.category_colors.get(category))
+            .cloned()
+            .unwrap_or(self.default_color)
+    }
+}
+
+impl FormattingSettings {
+    /// Falls back to the default separators if they are digits, signs, or the same as each other,
+    /// since numbers could not be read back then.
+    pub fn validated(self) -> FormattingSettings {
+        let defaults = FormattingSettings::default();
+        let unusable = |c: char| c.is_ascii_digit() || c == '-' || c == '+';
+
+        if unusable(self.decimal_separator)
+            || unusable(self.thousands_separator)
+            || self.decimal_separator == self.thousands_separator
+        {
+            FormattingSettings {
+                decimal_separator: defaults.decimal_separator,
+                thousands_separator: defaults.thousands_separator,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+}
+
+fn clamp_color(default: [f32; 4], mut color: [f32; 4]) -> [f32; 4] {
+    for (component, default) in color.iter_mut().zip(&default) {
+        *component = clamp_setting((0.0, 1.0), *default, *component);
+    }
+
+    color
+}
+
+fn clamp_setting((min, max): (f32, f32), default: f32, n: f32) -> f32 {
+    if n.is_finite() {
+        min.max(max.min(n))
+    } else {
+        default
+    }
+}
+
+impl Settings {
+    /// Reads settings from `path`, falling back to the defaults if the file does not exist.
+    /// Unknown keys are warned about, so that a typo does not make the whole file unusable.
+    pub fn load(path: &Path) -> Result<Settings> {
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+
+        let mut contents = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .chain_err(|| format!("Could not read settings file {}", path.display()))?;
+
+        let (contents, moved) = migrate(&contents);
+        for (from, to) in moved {
+            warn!(
+                "Setting `{}` in {} has moved to `{}`, and was read from where it was. Saving \
+                 from the settings overlay writes it where it is now",
+                from,
+                path.display(),
+                to
+            );
+        }
+
+        let (settings, unknown_keys) = Settings::parse(&contents)
+            .chain_err(|| format!("Could not parse settings file {}", path.display()))?;
+
+        for key in unknown_keys {
+            warn!("Ignoring unknown setting `{}` in {}", key, path.display());
+        }
+
+        let levels_error = settings.quality.levels_error();
+        if let Some(ref e) = levels_error {
+            warn!(
+                "Setting `quality.levels` in {} cannot be used, and the default thresholds are \
+                 used instead: {}",
+                path.display(),
+                e
+            );
+        }
+
+        let validated = settings.clone().validated();
+        if let (Ok(before), Ok(after)) = (Value::try_from(&settings), Value::try_from(&validated)) {
+            let mut adjusted = Vec::new();
+            changed_keys("", &before, &after, &mut adjusted);
+            if levels_error.is_some() {
+                adjusted.retain(|key| key != "quality.levels");
+            }
+
+            for key in adjusted {
+                warn!("Setting `{}` in {} is out of range, and was adjusted", key, path.display());
+            }
+        }
+
+        Ok(validated)
+    }
+
+    /// Parses settings, without validating them, also returning the path of every key that was
+    /// not recognized.
+    fn parse(contents: &str) -> Result<(Settings, Vec<String>)> {
+        let mut unknown_keys = Vec::new();
+        let settings: Settings = {
+            let mut deserializer = toml::Deserializer::new(contents);
+            serde_ignored::deserialize(&mut deserializer, |path| {
+                unknown_keys.push(path.to_string());
+            }).map_err(|e| e.to_string())?
+        };
+
+        Ok((settings, unknown_keys))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = self.to_toml()?;
+
+        persistence::write_atomically(path, contents.as_bytes())
+            .chain_err(|| format!("Could not save settings file {}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).chain_err(|| "Could not serialize settings")
+    }
+
+    /// The settings that differ between `self` and `other`, but are only read at startup.
+    pub fn startup_only_changes(&self, other: &Settings) -> Vec<String> {
+        let mut changed = Vec::new();
+        if let (Ok(before), Ok(after)) = (Value::try_from(self), Value::try_from(other)) {
+            changed_keys("", &before, &after, &mut changed);
+        }
+
+        changed.retain(|key| {
+            STARTUP_ONLY.iter().any(|startup_only| {
+                key == startup_only || key.starts_with(&format!("{}.", startup_only))
+            })
+        });
+        changed
+    }
+
+    pub fn validated(self) -> Settings {
+        Settings {
+            window: self.window.validated(),
+            camera: self.camera.validated(),
+            terrain: self.terrain.validated(),
+            hud: self.hud.validated(),
+            labels: self.labels.validated(),
+            formatting: self.formatting.validated(),
+            quality: self.quality.validated(),
+            diagnostics: self.diagnostics.validated(),
+            points: self.points.validated(),
+            routes: self.routes.validated(),
+            tour: self.tour.validated(),
+            modes: self.modes.validated(),
+            stereo: self.stereo.validated(),
+            sky: self.sky.validated(),
+            profiles: ProfileSettings {
+                high_contrast: self.profiles.high_contrast.validated(),
+                ..self.profiles
+            },
+            ..self
+        }
+    }
+}
+
+/// `contents`, a settings file, with every setting in `MOVED` moved from where it was to where it
+/// is now, and which settings were moved. A setting given in both places is read from where it
+/// is now. Files with nothing to move, or that are not valid TOML, are returned as they are, so
+/// that errors in them point at their lines.
+fn migrate(contents: &str) -> (String, Vec<(&'static str, &'static str)>) {
+    let mut value: Value = match contents.parse() {
+        Ok(value) => value,
+        Err(_) => return (contents.to_string(), vec![]),
+    };
+
+    let mut moved = vec![];
+    for &(from, to) in &MOVED {
+        if let Some(setting) = take_key(&mut value, from) {
+            put_key(&mut value, to, setting);
+            moved.push((from, to));
+        }
+    }
+    if moved.is_empty() {
+        return (contents.to_string(), moved);
+    }
+
+    // Sections that everything moved out of are left out, rather than warned about as unknown.
+    if let Value::Table(ref mut sections) = value {
+        sections.retain(|_, section| section.as_table().map_or(true, |keys| !keys.is_empty()));
+    }
+    match toml::to_string(&value) {
+        Ok(migrated) => (migrated, moved),
+        Err(_) => (contents.to_string(), vec![]),
+    }
+}
+
+/// Removes the setting at the dotted `path` from `value`, returning it if it was there.
+fn take_key(value: &mut Value, path: &str) -> Option<Value> {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let key = keys.pop()?;
+    let mut table = value.as_table_mut()?;
+    for section in keys {
+        table = table.get_mut(section)?.as_table_mut()?;
+    }
+
+    table.remove(key)
+}
+
+/// Sets the setting at the dotted `path` in `value` to `setting`, unless it is already set.
+fn put_key(value: &mut Value, path: &str, setting: Value) {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let key = match keys.pop() {
+        Some(key) => key,
+        None => return,
+    };
+    let mut table = match value.as_table_mut() {
+        Some(table) => table,
+        None => return,
+    };
+    for section in keys {
+        let section = table
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Table(Default::default()));
+        table = match section.as_table_mut() {
+            Some(table) => table,
+            None => return,
+        };
+    }
+
+    table.entry(key.to_string()).or_insert(setting);
+}
+
+/// Adds the path, under `prefix`, of every key whose value differs between `before` and `after`
+/// to `changed`.
+fn changed_keys(prefix: &str, before: &Value, after: &Value, changed: &mut Vec<String>) {
+    match (before, after) {
+        (Value::Table(before), Value::Table(after)) => {
+            for (key, after) in after {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+
+                match before.get(key) {
+                    Some(before) => changed_keys(&path, before, after, changed),
+                    None => changed.push(path),
+                }
+            }
+
+            for key in before.keys().filter(|key| !after.contains_key(*key)) {
+                if prefix.is_empty() {
+                    changed.push(key.clone());
+                } else {
+                    changed.push(format!("{}.{}", prefix, key));
+                }
+            }
+        }
+        _ if before != after => changed.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+/// Where settings are read from when no file is given with `--config`: the platform's config
+/// directory if there is a settings file there, or else alongside the executable if there is one
+/// there. If there is neither, settings are saved to the platform's config directory.
+pub fn default_path() -> PathBuf {
+    let candidates: Vec<PathBuf> = platform_config_dir()
+        .into_iter()
+        .chain(env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)))
+        .map(|dir| dir.join(SETTINGS_FILE_NAME))
+        .collect();
+
+    candidates
+        .iter()
+        .find(|path| path.exists())
+        .or_else(|| candidates.first())
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(SETTINGS_FILE_NAME))
+}
+
+/// Where the platform keeps per-user configuration, with a directory for the demo.
+fn platform_config_dir() -> Option<PathBuf> {
+    let dir = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    dir.map(|dir| dir.join("gaia-demo"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_config_is_the_defaults() {
+        let (settings, unknown_keys) =
+            Settings::parse(include_str!("../gaia-demo.example.toml")).unwrap();
+
+        assert_eq!(unknown_keys, Vec::<String>::new());
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_but_not_fatal() {
+        let (settings, unknown_keys) = Settings::parse(
+            "[camera]\npan_sensitivity = 2.0\npan_sensitivty = 3.0\n\n[colour]\nred = 1\n",
+        ).unwrap();
+
+        assert_eq!(settings.camera.pan_sensitivity, 2.0);
+        assert_eq!(unknown_keys, vec!["camera.pan_sensitivty", "colour"]);
+    }
+
+    #[test]
+    fn settings_where_they_used_to_be_are_moved() {
+        let old = "[display]\nhud_scale = 2.0\ntheme = \"light\"\n\n\
+                   [display.custom_theme]\ntext = [1.0, 0.0, 0.0, 1.0]\n\n\
+                   [startup]\nwidth = 1280\nmode = \"income\"\nlabels = true\n\n\
+                   [startup.start]\nlatitude = 48.85\nlongitude = 2.35\nheight = 0.2\n\n\
+                   [window]\nwidth = 1600\n";
+        let (migrated, moved) = migrate(old);
+        assert!(moved.contains(&("display.hud_scale", "hud.scale")), "{:?}", moved);
+        assert!(moved.contains(&("startup.start", "camera.start")), "{:?}", moved);
+
+        let (settings, unknown_keys) = Settings::parse(&migrated).unwrap();
+        assert_eq!(unknown_keys, Vec::<String>::new());
+        assert_eq!(settings.hud.scale, 2.0);
+        assert_eq!(settings.hud.theme, ThemeName::Light);
+        assert_eq!(settings.hud.custom_theme.text, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(settings.modes.default, MapMode::Income);
+        assert!(settings.labels.enabled);
+        assert_eq!(settings.camera.start.map(|start| start.latitude), Some(48.85));
+        // Where a setting is given in both places, where it is now wins.
+        assert_eq!(settings.window.width, 1600);
+
+        // Units moved again, out of the HUD's settings.
+        let (migrated, moved) = migrate("[hud]\nscale = 1.5\nunits = \"imperial\"\n");
+        assert_eq!(moved, vec![("hud.units", "formatting.units")]);
+        let (settings, unknown_keys) = Settings::parse(&migrated).unwrap();
+        assert_eq!(unknown_keys, Vec::<String>::new());
+        assert_eq!(settings.formatting.units, Units::Imperial);
+        assert_eq!(settings.hud.scale, 1.5);
+
+        // Current files are left as they are.
+        let current = "[hud]\nscale = 2.0\n";
+        assert_eq!(migrate(current), (current.to_string(), vec![]));
+        assert_eq!(migrate("[hud\n").1, vec![]);
+    }
+
+    #[test]
+    fn the_custom_theme_is_filled_in_and_kept_in_range() {
+        let (settings, _) = Settings::parse(
+            "[hud]\ntheme = \"custom\"\n\n[hud.custom_theme]\ntext = [2.0, -1.0, 0.5, 1.0]\n",
+        ).unwrap();
+        let theme = settings.validated().hud.theme();
+
+        assert_eq!(theme.text, [1.0, 0.0, 0.5, 1.0]);
+        // What is not given is the default theme's.
+        assert_eq!(theme.accent, Theme::default().accent);
+
+        let hud = HudSettings {
+            theme: ThemeName::HighContrast,
+            ..HudSettings::default()
+        };
+        assert_eq!(hud.theme(), Theme::high_contrast());
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let error = Settings::parse("[hud]\nscale = \"big\"\n").unwrap_err();
+
+        assert!(error.to_string().contains("hud.scale"), "{}", error);
+    }
+
+    #[test]
+    fn clashing_separators_fall_back_to_the_defaults() {
+        let (settings, _) = Settings::parse(
+            "[formatting]\ndecimal_separator = \",\"\nthousands_separator = \",\"\n",
+        ).unwrap();
+        let formatting = settings.validated().formatting;
+
+        assert_eq!(formatting.decimal_separator, '.');
+        assert_eq!(formatting.thousands_separator, ',');
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let mut settings = Settings::default();
+        settings.camera.start = Some(StartPosition {
+            latitude: 48.85,
+            longitude: 2.35,
+            height: 0.2,
+        });
+        settings.bindings.insert("F5".to_string(), Action::ToggleGraticule);
+
+        let (loaded, unknown_keys) = Settings::parse(&settings.to_toml().unwrap()).unwrap();
+
+        assert_eq!(unknown_keys, Vec::<String>::new());
+        assert_eq!(loaded, settings);
+    }
+}
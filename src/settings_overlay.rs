@@ -0,0 +1,492 @@
+use std::time::Duration;
+
+use piston::input::keyboard::Key;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use formatting;
+use hud::{HudScale, HudStyle};
+use mode_adjustments::{self, ModeAdjustments};
+use pending_changes::PendingChanges;
+use profile::Profile;
+use settings::{SafeArea, Settings};
+use world_clock::PinnedClock;
+use MapMode;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Entry {
+    PanSensitivity,
+    ZoomPerNotch,
+    RotateSensitivity,
+    HudScale,
+    SafeAreaUnit,
+    /// The safe area's margin at this index in `SafeArea::EDGES`.
+    SafeAreaEdge(usize),
+    Theme,
+    Profile,
+    CoordinateFormat,
+    NumberFormat,
+    CompactNumbers,
+    Units,
+    CountryFilter,
+    /// The pinned clock at this index, which Return unpins.
+    Clock(usize),
+    /// The shown map mode's adjustments, saved for it in `modes.adjustments`.
+    ModePalette,
+    ModeMinAlpha,
+    ModeLabels,
+    /// Forgets the shown map mode's adjustments and the values of its parameters.
+    ResetMode,
+    /// The control point of `labels.min_zoom_curve` with this index.
+    MinZoomPoint(usize),
+    LabelDeclutter,
+    RenderScale,
+    AdaptiveQuality,
+    FrameBudget,
+    EyeSeparation,
+    Convergence,
+    AssetSet,
+    ResetToDefaults,
+}
+
+impl Entry {
+    /// What changes to the entry are called while on trial, if they are tried out before they
+    /// are kept, since they can make the demo too slow to use.
+    fn trial_name(&self) -> Option<&'static str> {
+        match *self {
+            Entry::RenderScale => Some("render scale"),
+            Entry::AdaptiveQuality => Some("adaptive quality"),
+            Entry::FrameBudget => Some("frame budget"),
+            _ => None,
+        }
+    }
+}
+
+const ENTRIES: [Entry; 36] = [
+    Entry::PanSensitivity,
+    Entry::ZoomPerNotch,
+    Entry::RotateSensitivity,
+    Entry::HudScale,
+    Entry::SafeAreaUnit,
+    Entry::SafeAreaEdge(0),
+    Entry::SafeAreaEdge(1),
+    Entry::SafeAreaEdge(2),
+    Entry::SafeAreaEdge(3),
+    Entry::Theme,
+    Entry::Profile,
+    Entry::CoordinateFormat,
+    Entry::NumberFormat,
+    Entry::CompactNumbers,
+    Entry::Units,
+    Entry::CountryFilter,
+    Entry::Clock(0),
+    Entry::Clock(1),
+    Entry::Clock(2),
+    Entry::ModePalette,
+    Entry::ModeMinAlpha,
+    Entry::ModeLabels,
+    Entry::ResetMode,
+    Entry::MinZoomPoint(0),
+    Entry::MinZoomPoint(1),
+    Entry::MinZoomPoint(2),
+    Entry::MinZoomPoint(3),
+    Entry::MinZoomPoint(4),
+    Entry::LabelDeclutter,
+    Entry::RenderScale,
+    Entry::AdaptiveQuality,
+    Entry::FrameBudget,
+    Entry::EyeSeparation,
+    Entry::Convergence,
+    Entry::AssetSet,
+    Entry::ResetToDefaults,
+];
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 15.0;
+const WIDTH: f64 = 260.0;
+
+/// How much a step changes the least opacity of a mode, about 5%.
+const MIN_ALPHA_STEP: i32 = 13;
+
+/// A list of adjustable settings, navigated with the arrow keys: Up/Down selects an entry,
+/// Left/Right adjusts it, Return activates actions such as resetting to defaults, and Escape
+/// closes it. Changes to the quality entries are on trial: they are reverted after a while, or
+/// when the overlay is closed, unless Return keeps them first.
+#[derive(Debug)]
+pub struct SettingsOverlay {
+    visible: bool,
+    selected: usize,
+    pending: PendingChanges,
+    /// The map mode shown, whose adjustments the overlay makes.
+    mode: MapMode,
+    /// The names of the cities whose clocks are pinned.
+    clocks: Vec<String>,
+    /// The clock Return unpinned, for the state to unpin.
+    removed_clock: Option<usize>,
+    /// The animation clock's time at the last key press or `revert_expired`, to count down
+    /// from.
+    now: Duration,
+}
+
+impl SettingsOverlay {
+    pub fn new(mode: MapMode) -> SettingsOverlay {
+        SettingsOverlay {
+            visible: false,
+            selected: 0,
+            pending: PendingChanges::new(),
+            mode,
+            clocks: vec![],
+            removed_clock: None,
+            now: Duration::from_secs(0),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Makes the adjustments for `mode` from now on, as it is switched to.
+    pub fn set_mode(&mut self, mode: MapMode) {
+        self.mode = mode;
+    }
+
+    /// Lists `clocks` as the pinned ones, as they are pinned and unpinned.
+    pub fn set_clocks(&mut self, clocks: &[PinnedClock]) {
+        self.clocks = clocks.iter().map(|clock| clock.name.clone()).collect();
+    }
+
+    /// The index of the clock Return unpinned since this was last called, if it did.
+    pub fn take_removed_clock(&mut self) -> Option<usize> {
+        self.removed_clock.take()
+    }
+
+    /// Opens the overlay if it is closed, and closes it if it is open, reverting the changes on
+    /// trial in `settings`. Returns the settings that were reverted.
+    pub fn toggle(&mut self, settings: &mut Settings) -> Vec<&'static str> {
+        self.visible = !self.visible;
+        if self.visible {
+            vec![]
+        } else {
+            self.pending.revert_all(settings)
+        }
+    }
+
+    /// Whether one of the safe area's entries is selected, so that the safe area is outlined
+    /// while it is adjusted.
+    pub fn is_adjusting_safe_area(&self) -> bool {
+        match ENTRIES[self.selected] {
+            Entry::SafeAreaUnit | Entry::SafeAreaEdge(_) => self.visible,
+            _ => false,
+        }
+    }
+
+    /// Whether changes are on trial, so that they are not saved yet and the countdown is shown.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Reverts the changes on trial whose time was up at `now`, by the animation clock, in
+    /// `settings`. Called every update. Returns the settings that were reverted.
+    pub fn revert_expired(&mut self, settings: &mut Settings, now: Duration) -> Vec<&'static str> {
+        self.now = now;
+        self.pending.revert_expired(settings, now)
+    }
+
+    /// Forgets the changes on trial without reverting them, as when the settings file replaces
+    /// the settings they were made to.
+    pub fn forget_pending(&mut self) {
+        self.pending.confirm();
+    }
+
+    /// Whether the overlay takes `key` while it is open, rather than letting it through to the
+    /// bindings: the arrow keys, Return, and Escape, which closes it.
+    pub fn takes(key: Key) -> bool {
+        matches!(
+            key,
+            Key::Up | Key::Down | Key::Left | Key::Right | Key::Return | Key::Escape
+        )
+    }
+
+    /// Handles a key press while the overlay is open, at `now` by the animation clock. Returns
+    /// whether `settings` was changed, or the changes on trial kept.
+    pub fn key_press(&mut self, key: Key, settings: &mut Settings, now: Duration) -> bool {
+        let before = settings.clone();
+        self.now = now;
+
+        match key {
+            Key::Up => {
+                self.selected = (self.selected + ENTRIES.len() - 1) % ENTRIES.len();
+            }
+            Key::Down => {
+                self.selected = (self.selected + 1) % ENTRIES.len();
+            }
+            Key::Left => {
+                self.adjust(settings, -1.0);
+            }
+            Key::Right => {
+                self.adjust(settings, 1.0);
+            }
+            Key::Return if self.has_pending() => {
+                self.pending.confirm();
+                return true;
+            }
+            Key::Return => match ENTRIES[self.selected] {
+                Entry::ResetToDefaults => *settings = Settings::default(),
+                Entry::ResetMode => {
+                    *settings.modes.adjustments.of_mut(self.mode) = ModeAdjustments::default();
+                    settings.modes.parameters.reset(self.mode);
+                }
+                Entry::Clock(i) if i < self.clocks.len() => self.removed_clock = Some(i),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        *settings != before
+    }
+
+    fn adjust(&mut self, settings: &mut Settings, direction: f32) {
+        let before = settings.quality.clone();
+        let adjusted = mode_adjustments::layered(settings, self.mode, ModeAdjustments::default());
+        {
+            let camera = &mut settings.camera;
+            let hud = &mut settings.hud;
+            let format = &mut settings.formatting;
+            let quality = &mut settings.quality;
+            let stereo = &mut settings.stereo;
+
+            match ENTRIES[self.selected] {
+                Entry::PanSensitivity => camera.pan_sensitivity += 0.1 * direction,
+                Entry::ZoomPerNotch => camera.zoom_per_notch += 0.005 * direction,
+                Entry::RotateSensitivity => camera.rotate_sensitivity += 0.1 * direction,
+                Entry::HudScale => hud.scale += 0.1 * direction,
+                Entry::SafeAreaUnit => hud.safe_area.unit = hud.safe_area.unit.toggled(),
+                Entry::SafeAreaEdge(i) => {
+                    let step = hud.safe_area.unit.step();
+                    *hud.safe_area.edge_mut(i) += step * direction;
+                }
+                Entry::Theme => hud.theme = hud.theme.cycle(direction as isize),
+                Entry::Profile => {
+                    let profiles = &mut settings.profiles;
+                    profiles.active = Profile::cycle(profiles.active, direction as isize);
+                }
+                Entry::CoordinateFormat => {
+                    hud.coordinate_format = hud.coordinate_format.toggled()
+                }
+                Entry::NumberFormat => {
+                    *format = formatting::cycle_style(*format, direction as isize)
+                }
+                Entry::CompactNumbers => format.compact_numbers = !format.compact_numbers,
+                Entry::Units => format.units = format.units.toggled(),
+                Entry::CountryFilter => {
+                    let countries = &mut settings.countries;
+                    countries.filter = countries.filter.cycle(direction as isize);
+                }
+                Entry::ModePalette => {
+                    let palette = adjusted.modes.palette.toggled();
+                    settings.modes.adjustments.of_mut(self.mode).palette = Some(palette);
+                }
+                Entry::ModeMinAlpha => {
+                    let stepped = i32::from(adjusted.modes.min_alpha)
+                        + MIN_ALPHA_STEP * direction as i32;
+                    let min_alpha = stepped.max(0).min(255) as u8;
+                    settings.modes.adjustments.of_mut(self.mode).min_alpha = Some(min_alpha);
+                }
+                Entry::ModeLabels => {
+                    let policy = adjusted.modes.labels.of(self.mode).cycle(direction as isize);
+                    settings.modes.adjustments.of_mut(self.mode).labels = Some(policy);
+                }
+                Entry::MinZoomPoint(i) => settings.labels.min_zoom_curve[i][1] += 0.1 * direction,
+                Entry::LabelDeclutter => settings.labels.declutter = !settings.labels.declutter,
+                Entry::RenderScale => quality.render_scale += 0.05 * direction,
+                Entry::AdaptiveQuality => quality.adaptive = !quality.adaptive,
+                Entry::FrameBudget => quality.frame_budget_ms += 2.0 * direction,
+                Entry::EyeSeparation => stereo.eye_separation += 0.005 * direction,
+                Entry::Convergence => stereo.convergence += 0.05 * direction,
+                Entry::AssetSet => settings.paths.cycle_asset_set(direction as isize),
+                Entry::Clock(_) | Entry::ResetMode | Entry::ResetToDefaults => {}
+            }
+        }
+
+        *settings = settings.clone().validated();
+
+        let entry = ENTRIES[self.selected];
+        if let (Some(name), true) = (entry.trial_name(), settings.quality != before) {
+            self.pending.try_change(name, self.now, move |settings| {
+                let quality = &mut settings.quality;
+                match entry {
+                    Entry::RenderScale => quality.render_scale = before.render_scale,
+                    Entry::AdaptiveQuality => quality.adaptive = before.adaptive,
+                    _ => quality.frame_budget_ms = before.frame_budget_ms,
+                }
+            });
+        }
+    }
+
+    /// How much room `draw` needs.
+    pub fn size(&self, scale: HudScale) -> [f64; 2] {
+        [
+            scale.len(WIDTH),
+            scale.len(LINE_HEIGHT * (ENTRIES.len() + 2) as f64),
+        ]
+    }
+
+    /// Draws the overlay with its top-left corner at `origin`.
+    pub fn draw(
+        &self,
+        settings: &Settings,
+        origin: [f64; 2],
+        style: HudStyle,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let lines: Vec<_> = ENTRIES
+            .iter()
+            .map(|entry| {
+                let line = describe(*entry, settings, self.mode, &self.clocks);
+                let name = entry.trial_name();
+                match name.and_then(|name| self.pending.remaining(name, self.now)) {
+                    Some(remaining) => {
+                        format!("{} (reverts in {}s)", line, remaining.as_secs_f64().ceil())
+                    }
+                    None => line,
+                }
+            })
+            .collect();
+        let title = if self.has_pending() {
+            "Settings (Return to keep changes)"
+        } else {
+            "Settings (F2 to close)"
+        };
+
+        let HudStyle { scale, theme } = style;
+        let [width, height] = self.size(scale);
+        let context = context.trans(origin[0], origin[1]);
+        let line_height = scale.len(LINE_HEIGHT);
+        let indent = scale.len(10.0);
+
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [0.0, 0.0, width, height],
+            context.transform,
+            graphics,
+        );
+
+        scale.draw_text(
+            title,
+            FONT_SIZE,
+            theme.text,
+            context.trans(indent, line_height),
+            glyphs,
+            graphics,
+        );
+
+        for (i, line) in lines.iter().enumerate() {
+            let (marker, color) = if i == self.selected {
+                ("> ", theme.accent)
+            } else {
+                ("  ", theme.text)
+            };
+
+            scale.draw_text(
+                &format!("{}{}", marker, line),
+                FONT_SIZE,
+                color,
+                context.trans(indent, line_height * (i + 2) as f64),
+                glyphs,
+                graphics,
+            );
+        }
+    }
+}
+
+fn describe(entry: Entry, settings: &Settings, mode: MapMode, clocks: &[String]) -> String {
+    let camera = &settings.camera;
+
+    match entry {
+        Entry::PanSensitivity => format!("Pan sensitivity: {:.1}", camera.pan_sensitivity),
+        Entry::ZoomPerNotch => format!("Zoom per scroll notch: {:.3}x", camera.zoom_per_notch),
+        Entry::RotateSensitivity => {
+            format!("Rotate sensitivity: {:.1}", camera.rotate_sensitivity)
+        }
+        Entry::HudScale => format!("HUD scale: {:.1}x", settings.hud.scale),
+        Entry::SafeAreaUnit => format!("Safe area in: {}", settings.hud.safe_area.unit.name()),
+        Entry::SafeAreaEdge(i) => format!(
+            "Safe area {}: {}{}",
+            SafeArea::EDGES[i],
+            settings.hud.safe_area.edges()[i],
+            settings.hud.safe_area.unit.suffix()
+        ),
+        Entry::Theme => format!("Theme: {}", settings.hud.theme.name()),
+        Entry::Profile => format!(
+            "Profile: {}",
+            settings.profiles.active.map_or("None", |profile| profile.name())
+        ),
+        Entry::CoordinateFormat => format!(
+            "Coordinates: {}",
+            settings.hud.coordinate_format.name()
+        ),
+        Entry::NumberFormat => format!(
+            "Numbers: {}",
+            formatting::number(1_234_567.8, 1, settings.formatting)
+        ),
+        Entry::CompactNumbers => format!(
+            "Large numbers: {}",
+            formatting::count(1_234_567.0, settings.formatting)
+        ),
+        Entry::Units => format!("Units: {}", settings.formatting.units.name()),
+        Entry::CountryFilter => format!("Countries counted: {}", settings.countries.filter.name()),
+        Entry::Clock(i) => match clocks.get(i) {
+            Some(name) => format!("Clock {}: {} (Return unpins)", i + 1, name),
+            None => format!("Clock {}: none (Shift+T pins)", i + 1),
+        },
+        Entry::MinZoomPoint(i) => {
+            let [stored, effective] = settings.labels.min_zoom_curve[i];
+            format!("Label min_zoom {:.1} becomes: {:.1}", stored, effective)
+        }
+        Entry::LabelDeclutter => format!(
+            "Declutter labels: {}",
+            if settings.labels.declutter { "On" } else { "Off" }
+        ),
+        Entry::RenderScale => format!(
+            "Map render scale: {:.0}%",
+            settings.quality.render_scale * 100.0
+        ),
+        Entry::AdaptiveQuality => format!(
+            "Adaptive quality: {}",
+            if settings.quality.adaptive { "On" } else { "Off" }
+        ),
+        Entry::FrameBudget => format!("Frame budget: {:.0} ms", settings.quality.frame_budget_ms),
+        Entry::EyeSeparation => format!(
+            "Stereo eye separation: {:.3}",
+            settings.stereo.eye_separation
+        ),
+        Entry::Convergence => format!("Stereo convergence: {:.2}x", settings.stereo.convergence),
+        Entry::AssetSet => format!("Asset set: {}", settings.paths.asset_set),
+        Entry::ModePalette | Entry::ModeMinAlpha | Entry::ModeLabels => {
+            let adjusted = mode_adjustments::layered(settings, mode, ModeAdjustments::default());
+            let adjustments = settings.modes.adjustments.of(mode);
+            let (name, value, saved) = match entry {
+                Entry::ModePalette => (
+                    "palette",
+                    adjusted.modes.palette.name().to_string(),
+                    adjustments.palette.is_some(),
+                ),
+                Entry::ModeMinAlpha => (
+                    "least opacity",
+                    format!("{:.0}%", f64::from(adjusted.modes.min_alpha) / 2.55),
+                    adjustments.min_alpha.is_some(),
+                ),
+                _ => (
+                    "labels",
+                    adjusted.modes.labels.of(mode).name().to_string(),
+                    adjustments.labels.is_some(),
+                ),
+            };
+            let marker = if saved { " (adjusted)" } else { "" };
+            format!("{} {}: {}{}", mode.name(), name, value, marker)
+        }
+        Entry::ResetMode => format!("Reset {} mode's adjustments (Return)", mode.name()),
+        Entry::ResetToDefaults => "Reset to defaults (Return)".to_string(),
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use settings::Settings;
+use Result;
+
+/// How often the settings file's modification time is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Notices when the settings file is changed by something other than the demo, by polling its
+/// modification time.
+#[derive(Debug)]
+pub struct SettingsWatcher {
+    path: PathBuf,
+    /// When the file was last modified, as of the last check. `None` if it did not exist.
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl SettingsWatcher {
+    pub fn new(path: PathBuf) -> SettingsWatcher {
+        SettingsWatcher {
+            modified: modified(&path),
+            path,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Records that the demo wrote the file itself, so that it is not reloaded.
+    pub fn saved(&mut self) {
+        self.modified = modified(&self.path);
+    }
+
+    /// The settings file's contents, if it was modified since the last call.
+    pub fn poll(&mut self) -> Option<Result<Settings>> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        // A file that disappears, or is briefly missing while an editor replaces it, leaves the
+        // settings as they were instead of resetting them to the defaults.
+        let modified = modified(&self.path)?;
+        if self.modified == Some(modified) {
+            return None;
+        }
+
+        self.modified = Some(modified);
+        Some(Settings::load(&self.path))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
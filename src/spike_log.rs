@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use piston_window::Event;
+
+use frame_times::{Phase, PhaseTimes};
+use {Result, ResultExt};
+
+/// What the spike log is called, next to the settings file.
+pub const SPIKE_LOG_FILE_NAME: &str = "gaia-demo-spikes.log";
+
+/// How many of the latest events are kept, to be written with each spike.
+const RECENT_EVENTS: usize = 20;
+
+/// The least time between two captures. Spikes in between are only counted, so that a machine
+/// that is always slow does not write a capture every frame.
+const MIN_CAPTURE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Once the log is this large, it is moved aside to `<name>.old`, replacing any older one, and a
+/// new log is started. At most about twice this is kept.
+const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// What was happening when a frame spiked.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Latitude, longitude and height of the camera, and its heading in degrees.
+    pub camera: [f32; 4],
+    pub level: u8,
+    pub mode: String,
+}
+
+/// Watches for frames that take much longer than they should, and appends what was happening at
+/// the time to a log, so that intermittent hitches can be reported.
+pub struct SpikeLog {
+    path: PathBuf,
+    recent_events: VecDeque<(Instant, Event)>,
+    last_capture: Option<Instant>,
+    /// Spikes since the last capture that were not captured themselves.
+    uncaptured: u32,
+    spikes: u32,
+    /// Set once writing fails, so that a log that cannot be written is only warned about once.
+    failed: bool,
+}
+
+impl SpikeLog {
+    pub fn new(path: PathBuf) -> SpikeLog {
+        SpikeLog {
+            path,
+            recent_events: VecDeque::with_capacity(RECENT_EVENTS),
+            last_capture: None,
+            uncaptured: 0,
+            spikes: 0,
+            failed: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How many frames have spiked, whether or not they were captured.
+    pub fn spikes(&self) -> u32 {
+        self.spikes
+    }
+
+    /// Remembers `e` as one of the latest events.
+    pub fn event(&mut self, e: &Event) {
+        if self.recent_events.len() == RECENT_EVENTS {
+            self.recent_events.pop_front();
+        }
+        self.recent_events.push_back((Instant::now(), e.clone()));
+    }
+
+    /// Records a frame that spent `phases` in each phase, if they took longer than `threshold_ms`
+    /// in all. `snapshot` is only called for frames that are captured.
+    pub fn frame<F>(&mut self, threshold_ms: f32, phases: PhaseTimes, snapshot: F)
+    where
+        F: FnOnce() -> Snapshot,
+    {
+        let ms: f32 = phases.iter().sum();
+        if threshold_ms <= 0.0 || ms <= threshold_ms {
+            return;
+        }
+
+        self.spikes += 1;
+        let now = Instant::now();
+        let too_soon = self.last_capture
+            .map_or(false, |last_capture| now.duration_since(last_capture) < MIN_CAPTURE_INTERVAL);
+        if too_soon || self.failed {
+            self.uncaptured += 1;
+            return;
+        }
+
+        let capture = self.capture(&phases, &snapshot(), now);
+        self.last_capture = Some(now);
+        self.uncaptured = 0;
+
+        if let Err(e) = self.append(&capture) {
+            let message = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+            warn!("Stopped logging frame time spikes: {}", message);
+            self.failed = true;
+        }
+    }
+
+    fn capture(&self, phases: &PhaseTimes, snapshot: &Snapshot, now: Instant) -> String {
+        let [latitude, longitude, height, heading] = snapshot.camera;
+        let ms: f32 = phases.iter().sum();
+        let phases: Vec<String> = Phase::ALL
+            .iter()
+            .zip(phases)
+            .map(|(phase, ms)| format!("{} {:.1}", phase.name(), ms))
+            .collect();
+
+        let mut text = String::new();
+        writeln!(
+            text,
+            "{} Frame took {:.1} ms ({})",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            ms,
+            phases.join(", ")
+        ).unwrap();
+        writeln!(
+            text,
+            "  Camera: latitude {:.4}, longitude {:.4}, height {:.4}, heading {:.0}, level {}",
+            latitude, longitude, height, heading, snapshot.level
+        ).unwrap();
+        writeln!(text, "  Mode: {}", snapshot.mode).unwrap();
+        if self.uncaptured > 0 {
+            writeln!(text, "  Spikes since the last capture: {}", self.uncaptured).unwrap();
+        }
+
+        writeln!(text, "  Latest events:").unwrap();
+        for &(at, ref e) in &self.recent_events {
+            let age = now.duration_since(at).as_secs_f64() * 1000.0;
+            writeln!(text, "    {:>8.1} ms ago: {:?}", age, e).unwrap();
+        }
+
+        text
+    }
+
+    fn append(&self, capture: &str) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .chain_err(|| format!("Could not create directory {}", dir.display()))?;
+        }
+
+        let size = fs::metadata(&self.path).map(|metadata| metadata.len()).unwrap_or(0);
+        if size >= MAX_LOG_BYTES {
+            let mut old_name = self.path.file_name().unwrap_or_default().to_os_string();
+            old_name.push(".old");
+            fs::rename(&self.path, self.path.with_file_name(old_name))
+                .chain_err(|| format!("Could not move {} aside", self.path.display()))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .chain_err(|| format!("Could not open {}", self.path.display()))?;
+        writeln!(file, "{}", capture)
+            .chain_err(|| format!("Could not write to {}", self.path.display()))?;
+
+        info!("Frame time spike logged to {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Where the spike log is kept for the settings file at `settings_path`.
+pub fn path_for(settings_path: &Path) -> PathBuf {
+    settings_path.with_file_name(SPIKE_LOG_FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    use piston::input::Input;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            camera: [48.8566, 2.3522, 0.2, 90.0],
+            level: 3,
+            mode: "Income".to_string(),
+        }
+    }
+
+    fn log_in(name: &str) -> SpikeLog {
+        let dir = env::temp_dir().join(format!("gaia-demo-spikes-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SpikeLog::new(dir.join(SPIKE_LOG_FILE_NAME))
+    }
+
+    #[test]
+    fn spikes_too_soon_after_a_capture_are_only_counted() {
+        let mut log = log_in("rate");
+        log.event(&Event::Input(Input::Focus(true)));
+
+        // Frames within the threshold, or with no threshold, are not spikes at all.
+        log.frame(30.0, [5.0, 5.0, 5.0, 5.0, 5.0], snapshot);
+        log.frame(0.0, [50.0, 0.0, 0.0, 0.0, 0.0], snapshot);
+        assert_eq!(log.spikes(), 0);
+        assert!(!log.path().exists());
+
+        log.frame(30.0, [20.0, 20.0, 0.0, 0.0, 0.0], snapshot);
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], || panic!("captured too soon"));
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], || panic!("captured too soon"));
+        assert_eq!(log.spikes(), 3);
+        let text = fs::read_to_string(log.path()).unwrap();
+        assert_eq!(text.matches("Frame took").count(), 1);
+        assert!(text.contains("Frame took 40.0 ms"), "{}", text);
+        assert!(text.contains("latitude 48.8566, longitude 2.3522"), "{}", text);
+        assert!(text.contains("  Mode: Income"), "{}", text);
+        assert!(text.contains("Focus(true)"), "{}", text);
+        assert!(!text.contains("Spikes since the last capture"), "{}", text);
+
+        // Once the interval has passed, the next spike is captured with how many were not.
+        log.last_capture = log.last_capture.map(|at| at - MIN_CAPTURE_INTERVAL);
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], snapshot);
+        let text = fs::read_to_string(log.path()).unwrap();
+        assert_eq!(text.matches("Frame took").count(), 2);
+        assert!(text.contains("  Spikes since the last capture: 2"), "{}", text);
+
+        fs::remove_dir_all(log.path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_full_log_is_moved_aside() {
+        let mut log = log_in("rotation");
+        let dir = log.path().parent().unwrap().to_path_buf();
+        let old = dir.join(format!("{}.old", SPIKE_LOG_FILE_NAME));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&old, "older").unwrap();
+        fs::write(log.path(), vec![b'x'; MAX_LOG_BYTES as usize]).unwrap();
+
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], snapshot);
+        assert_eq!(fs::metadata(&old).unwrap().len(), MAX_LOG_BYTES);
+        let text = fs::read_to_string(log.path()).unwrap();
+        assert!(text.starts_with(&Local::now().format("%Y-").to_string()), "{}", text);
+        assert!(!log.failed);
+
+        // A log still under the limit is appended to.
+        log.last_capture = None;
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], snapshot);
+        assert_eq!(fs::read_to_string(log.path()).unwrap().matches("Frame took").count(), 2);
+        assert_eq!(fs::metadata(&old).unwrap().len(), MAX_LOG_BYTES);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_log_that_cannot_be_written_is_given_up_on() {
+        let mut log = log_in("failed");
+        // A directory where the log should be cannot be opened as a file.
+        fs::create_dir_all(log.path()).unwrap();
+
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], snapshot);
+        assert!(log.failed);
+        log.last_capture = None;
+        log.frame(30.0, [40.0, 0.0, 0.0, 0.0, 0.0], || panic!("captured after failing"));
+        assert_eq!(log.spikes(), 2);
+
+        fs::remove_dir_all(log.path().parent().unwrap()).unwrap();
+    }
+}
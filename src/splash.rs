@@ -0,0 +1,212 @@
+use std::time::Instant;
+
+use piston_window::{
+    clear, Context, Event, G2d, Glyphs, PistonWindow, PressEvent, RenderArgs, RenderEvent,
+    Transformed,
+};
+
+use hud::HudScale;
+use {Error, Result};
+
+const FONT_SIZE: u32 = 16;
+
+/// How far apart the lines of an error are, in points.
+const LINE_HEIGHT: f64 = 24.0;
+
+/// The color errors are drawn in.
+const ERROR_COLOR: [f32; 4] = [1.0, 0.45, 0.4, 1.0];
+
+/// How many times a second another dot is added to the ellipsis.
+const DOTS_PER_SEC: f64 = 3.0;
+
+/// A loading screen shown between the slow steps of startup, so that the window is drawn and
+/// keeps handling events instead of sitting frozen.
+///
+/// The renderer has to be built on the thread that owns the OpenGL context, so the steps cannot
+/// run in the background. Instead, `show` is called between them.
+#[derive(Debug)]
+pub struct Splash {
+    started: Instant,
+    /// The color the window is cleared to behind the message.
+    background: [f32; 4],
+    /// Whether `failed` shows errors.
+    show_errors: bool,
+}
+
+/// How showing a frame of the loading screen ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shown {
+    Drawn,
+    /// A key or button was pressed first, to skip what is being waited for.
+    Skipped,
+    Closed,
+}
+
+impl Splash {
+    /// A loading screen that shows errors that end startup if `show_errors` is set.
+    pub fn new(background: [f32; 4], show_errors: bool) -> Splash {
+        Splash {
+            started: Instant::now(),
+            background,
+            show_errors,
+        }
+    }
+
+    /// Handles window events until a frame of the loading screen, with `step` describing what is
+    /// about to happen, has been drawn. Returns false if the window was closed instead.
+    pub fn show(&self, step: &str, window: &mut PistonWindow, glyphs: &mut Glyphs) -> bool {
+        self.show_frame(step, window, glyphs, false, |_, _| {}) != Shown::Closed
+    }
+
+    /// Like `show`, but calls `behind` with the render event first, so that it can draw what the
+    /// loading screen then hides, such as the map while its tiles load. Pressing a key or button
+    /// skips the frame.
+    pub fn show_over<F>(
+        &self,
+        step: &str,
+        window: &mut PistonWindow,
+        glyphs: &mut Glyphs,
+        behind: F,
+    ) -> Shown
+    where
+        F: FnMut(&mut PistonWindow, &Event),
+    {
+        self.show_frame(step, window, glyphs, true, behind)
+    }
+
+    fn show_frame<F>(
+        &self,
+        step: &str,
+        window: &mut PistonWindow,
+        glyphs: &mut Glyphs,
+        skippable: bool,
+        mut behind: F,
+    ) -> Shown
+    where
+        F: FnMut(&mut PistonWindow, &Event),
+    {
+        while let Some(e) = window.next() {
+            if skippable && e.press_args().is_some() {
+                return Shown::Skipped;
+            }
+
+            let args = match e.render_args() {
+                Some(args) => args,
+                None => continue,
+            };
+
+            // As of when the frame is drawn, however long the events before it took.
+            let dots = (self.started.elapsed().as_secs_f64() * DOTS_PER_SEC) as usize % 4;
+            let message = format!("{}{}", step, ".".repeat(dots));
+
+            behind(window, &e);
+            let hidpi_factor = window.window.window.hidpi_factor();
+            window.draw_2d(&e, |context, graphics| {
+                clear(self.background, graphics);
+                draw_message(&message, scale(args, hidpi_factor), glyphs, context, graphics);
+            });
+
+            return Shown::Drawn;
+        }
+
+        Shown::Closed
+    }
+
+    /// Returns `result`, which ends startup if it is an error, after showing the error, if any.
+    pub fn failed<T>(
+        &self,
+        result: Result<T>,
+        window: &mut PistonWindow,
+        glyphs: &mut Glyphs,
+    ) -> Result<T> {
+        if let (Err(ref e), true) = (&result, self.show_errors) {
+            self.show_error(e, window, glyphs);
+        }
+
+        result
+    }
+
+    /// Shows `error`, one cause to a line, until a key or button is pressed or the window is
+    /// closed, so that it is not only in the log.
+    fn show_error(&self, error: &Error, window: &mut PistonWindow, glyphs: &mut Glyphs) {
+        let mut lines = vec!["Could not start the demo".to_string()];
+        lines.extend(error.iter().map(|e| e.to_string()));
+        lines.push(String::new());
+        lines.push("Press any key to exit".to_string());
+
+        while let Some(e) = window.next() {
+            if e.press_args().is_some() {
+                return;
+            }
+
+            if let Some(args) = e.render_args() {
+                let hidpi_factor = window.window.window.hidpi_factor();
+                window.draw_2d(&e, |context, graphics| {
+                    clear(self.background, graphics);
+                    draw_lines(&lines, scale(args, hidpi_factor), glyphs, context, graphics);
+                });
+            }
+        }
+    }
+}
+
+/// The HUD scale for a frame of `args` in a window with `hidpi_factor`, with text at its size in
+/// points.
+fn scale(args: RenderArgs, hidpi_factor: f32) -> HudScale {
+    HudScale {
+        factor: 1.0,
+        density: HudScale::density(Some(hidpi_factor), args.draw_height, args.height),
+    }
+}
+
+/// Draws `lines` centered in the window, those between the first and the last, which are what
+/// went wrong, in `ERROR_COLOR`.
+fn draw_lines(
+    lines: &[String],
+    scale: HudScale,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let [view_width, view_height] = context.get_view_size();
+    let line_height = scale.len(LINE_HEIGHT);
+    let top = view_height / 2.0 - line_height * (lines.len() as f64 - 1.0) / 2.0;
+
+    for (i, line) in lines.iter().enumerate() {
+        let width = scale.text_width(FONT_SIZE, line, glyphs);
+        let x = ((view_width - width) / 2.0).max(0.0).round();
+        let y = (top + line_height * i as f64).round();
+        let color = if i == 0 || i == lines.len() - 1 {
+            [1.0, 1.0, 1.0, 1.0]
+        } else {
+            ERROR_COLOR
+        };
+
+        scale.draw_text(line, FONT_SIZE, color, context.trans(x, y), glyphs, graphics);
+    }
+}
+
+fn draw_message(
+    message: &str,
+    scale: HudScale,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let [view_width, view_height] = context.get_view_size();
+
+    // Centered as if the ellipsis were complete, so that the text does not shift as it grows.
+    let width = scale.text_width(FONT_SIZE, message.trim_end_matches('.'), glyphs)
+        + scale.text_width(FONT_SIZE, "...", glyphs);
+    let x = ((view_width - width) / 2.0).round();
+    let y = (view_height / 2.0).round();
+
+    scale.draw_text(
+        message,
+        FONT_SIZE,
+        [1.0, 1.0, 1.0, 1.0],
+        context.trans(x, y),
+        glyphs,
+        graphics,
+    );
+}
@@ -0,0 +1,141 @@
+use cgmath::{Matrix4, Vector3};
+use piston::window::Size;
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::HudStyle;
+
+const FONT_SIZE: u32 = 10;
+
+/// Space between the top of the window and each half's mode name, and around the name.
+const PADDING: f64 = 5.0;
+
+/// How much of the window the map is drawn to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    Whole,
+    /// The left half of the window, in split-screen.
+    Left,
+    /// The right half of the window, in split-screen.
+    Right,
+}
+
+impl View {
+    /// The halves of the window, in split-screen, in order.
+    pub const HALVES: [View; 2] = [View::Left, View::Right];
+
+    /// Which view `cursor` is over, with the map drawn in `area`, as `letterbox::area` gives it.
+    /// In split-screen, that is whichever half it is in.
+    pub fn under(cursor: [f64; 2], area: [f64; 4], split: bool) -> View {
+        match (split, cursor[0] < area[0] + area[2] / 2.0) {
+            (false, _) => View::Whole,
+            (true, true) => View::Left,
+            (true, false) => View::Right,
+        }
+    }
+
+    /// The draw size of the map in this view, as the projection should be built for. Each half
+    /// has its own aspect ratio, so that the map is not squashed.
+    pub fn draw_size(&self, window: Size) -> Size {
+        match *self {
+            View::Whole => window,
+            View::Left | View::Right => Size {
+                width: (window.width / 2).max(1),
+                height: window.height,
+            },
+        }
+    }
+
+    /// The part of `area`, the part of the window the map is drawn in as `letterbox::area` gives
+    /// it, that this view covers. Both are `[x, y, width, height]` from the window's top-left.
+    pub fn rect(&self, area: [f64; 4]) -> [f64; 4] {
+        let [x, y, width, height] = area;
+
+        match *self {
+            View::Whole => area,
+            View::Left => [x, y, (width / 2.0).floor(), height],
+            View::Right => {
+                let left = (width / 2.0).floor();
+                [x + left, y, width - left, height]
+            }
+        }
+    }
+
+    /// Maps this view's clip space onto its part of the whole window's, so that drawing through
+    /// it on the 2D pass, and picking through it, land in this view's half.
+    pub fn window_matrix(&self) -> Matrix4<f32> {
+        let shift = match *self {
+            View::Whole => return Matrix4::from_scale(1.0),
+            View::Left => -0.5,
+            View::Right => 0.5,
+        };
+
+        Matrix4::from_translation(Vector3::new(shift, 0.0, 0.0))
+            * Matrix4::from_nonuniform_scale(0.5, 1.0, 1.0)
+    }
+
+    /// `context`, with anything drawn through it kept inside this view's part of `area`.
+    /// `density` is physical pixels per point, which the scissor works in.
+    pub fn clip(&self, area: [f64; 4], context: Context, density: f64) -> Context {
+        let [window_width, window_height] = context.get_view_size();
+        if *self == View::Whole && area == [0.0, 0.0, window_width, window_height] {
+            return context;
+        }
+
+        let [x, y, width, height] = self.rect(area);
+        let pixels = |n: f64| (n * density).round().max(0.0) as u32;
+        Context {
+            draw_state: context
+                .draw_state
+                .scissor([pixels(x), pixels(y), pixels(width), pixels(height)]),
+            ..context
+        }
+    }
+}
+
+/// Draws the line between the halves of `area`.
+pub fn draw_divider(area: [f64; 4], style: HudStyle, context: Context, graphics: &mut G2d) {
+    let [middle, top, _, height] = View::Right.rect(area);
+
+    ::piston_window::line(
+        style.theme.opaque_background(),
+        style.scale.len(1.0),
+        [middle, top, middle, top + height],
+        context.transform,
+        graphics,
+    );
+}
+
+/// Draws the name of the mode each half of `area` shows at the top of it.
+pub fn draw_names(
+    names: [&str; 2],
+    area: [f64; 4],
+    style: HudStyle,
+    glyphs: &mut Glyphs,
+    context: Context,
+    graphics: &mut G2d,
+) {
+    let HudStyle { scale, theme } = style;
+
+    for (view, name) in View::HALVES.iter().zip(&names) {
+        let [x, top, width, _] = view.rect(area);
+        let padding = scale.len(PADDING);
+        let text_width = scale.text_width(FONT_SIZE, name, glyphs);
+        let size = [text_width + 2.0 * padding, scale.len(FONT_SIZE as f64) + 2.0 * padding];
+        let left = (x + (width - size[0]) / 2.0).round();
+
+        ::piston_window::rectangle(
+            theme.panel_background,
+            [left, top + padding, size[0], size[1]],
+            context.transform,
+            graphics,
+        );
+        scale.draw_text(
+            name,
+            FONT_SIZE,
+            theme.text,
+            context.trans(left + padding, top + size[1] - scale.len(1.0)),
+            glyphs,
+            graphics,
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::f32::consts::PI;
+
+use cgmath::{Matrix4, Vector3, Vector4};
+use piston_window::{rectangle, Context, G2d};
+
+use projection::{self, MIN_W};
+
+/// How wide each star is drawn, in points.
+const STAR_SIZE: f64 = 1.5;
+
+/// How bright the dimmest stars are, as a fraction of the brightest.
+const MIN_BRIGHTNESS: f32 = 0.2;
+
+/// A star, infinitely far away in `direction` from wherever the camera is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Star {
+    /// A unit vector in world space.
+    direction: Vector3<f32>,
+    /// From `MIN_BRIGHTNESS` to 1, as a fraction of the brightest stars' brightness.
+    brightness: f32,
+}
+
+impl Star {
+    /// The `index`th star. Stars are scattered evenly over the sky by a fixed pseudorandom
+    /// sequence, so that the sky is the same every run, and adding stars keeps those there were.
+    fn nth(index: u32) -> Star {
+        let mut state = u64::from(index);
+        let mut random = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            ((z ^ (z >> 31)) >> 40) as f32 / (1u64 << 24) as f32
+        };
+
+        let z = 2.0 * random() - 1.0;
+        let angle = 2.0 * PI * random();
+        let across = (1.0 - z * z).max(0.0).sqrt();
+        // Most stars are dim, and only a few bright.
+        let brightness = MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * random().powi(3);
+
+        Star {
+            direction: Vector3::new(across * angle.cos(), across * angle.sin(), z),
+            brightness,
+        }
+    }
+}
+
+/// Stars over the whole sky, drawn behind the map in the 2D pass. They are infinitely far away,
+/// so they turn as the camera turns but stay put as it pans and zooms.
+#[derive(Clone, Debug, Default)]
+pub struct Starfield {
+    stars: Vec<Star>,
+}
+
+impl Starfield {
+    pub fn new(count: u32) -> Starfield {
+        Starfield {
+            stars: (0..count).map(Star::nth).collect(),
+        }
+    }
+
+    /// Adds or removes stars, so that there are `count`.
+    pub fn resize(&mut self, count: u32) {
+        let len = self.stars.len() as u32;
+        if count < len {
+            self.stars.truncate(count as usize);
+        } else {
+            self.stars.extend((len..count).map(Star::nth));
+        }
+    }
+
+    /// Draws the stars not hidden by the map, seen from `camera`, in world space. `sky_mvp` takes
+    /// directions in world space to the window's clip space, with the camera's rotation but not
+    /// its position. The brightest stars are drawn at `brightness`, from 0 to 1.
+    pub fn draw(
+        &self,
+        sky_mvp: Matrix4<f32>,
+        camera: [f32; 3],
+        brightness: f32,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let [width, height] = context.get_view_size();
+
+        for star in &self.stars {
+            if hidden_by_map(camera, star.direction) {
+                continue;
+            }
+
+            let direction = star.direction;
+            let clip = sky_mvp * Vector4::new(direction.x, direction.y, direction.z, 0.0);
+            if clip.w < MIN_W {
+                continue;
+            }
+
+            let position = projection::clip_to_view(clip, [width, height]);
+            if !projection::is_on_view(position, [width, height], 0.0) {
+                continue;
+            }
+            rectangle(
+                [1.0, 1.0, 1.0, brightness * star.brightness],
+                [
+                    position[0] - STAR_SIZE / 2.0,
+                    position[1] - STAR_SIZE / 2.0,
+                    STAR_SIZE,
+                    STAR_SIZE,
+                ],
+                context.transform,
+                graphics,
+            );
+        }
+    }
+}
+
+/// Whether looking in `direction` from `camera` meets the map, which is the plane at zero height
+/// from the south pole to the north, repeating east and west forever.
+fn hidden_by_map(camera: [f32; 3], direction: Vector3<f32>) -> bool {
+    if direction.z >= 0.0 {
+        return false;
+    }
+
+    let t = -camera[2] / direction.z;
+    let y = camera[1] + t * direction.y;
+    (0.0..=1.0).contains(&y)
+}
@@ -0,0 +1,3660 @@
+use adaptive_quality::AdaptiveQuality;
+use animation_clock::{self, AnimationClock};
+use bindings::{Action, Binding};
+use camera_controller::CameraController;
+use browser::{Browser, MemoryBrowser};
+use clipboard::{MemoryClipboard, TextClipboard};
+use color_script::ColorScript;
+use color_snapshot::{self, Class, ColorSnapshot};
+use command_palette::{CommandPalette, PaletteCommand, PaletteEntry};
+use country_cycle::CountryCycle;
+use country_filter::CountryFilter;
+use country_match::{CountryMatch, Strategy};
+use cursor_point::CursorPoint;
+use effects::{Easing, Effect, EffectId, EffectLayer, Paint};
+use features::Features;
+use font_fallback::{self, LabelFallbacks};
+use frame_times::FrameTimes;
+use geo_index::{self, GeoIndex};
+use help_overlay::HelpOverlay;
+use hud::{HudScale, Icon, Insets};
+use hud_budget::{HudBudget, Widget};
+use label_declutter::{self, LabelDeclutter};
+use label_policy::{self, LabelPolicy, LabelsShown};
+use label_text::LabelTexts;
+use legend::{Legend, LegendEntry};
+use letterbox::{self, AspectLock};
+use memory_usage::MemoryUsage;
+use min_zoom_curve;
+use mode_adjustments::{self, ModeAdjustments};
+use mode_parameters;
+use named_session::NamedSession;
+use outline;
+use parameter_animation::{ParameterAnimation, Repeat, RunningAnimation};
+use percentile::{self, Percentiles};
+use points::PointLayer;
+use preflight::Finding;
+use profile::Profile;
+use projection::{vertical_fov, Projection};
+use props::{CountryProps, PlaceProps};
+use routes::{Journey, Route};
+use run_summary::RunStats;
+use session::Session;
+use session_picker::{Choice, SessionPicker};
+use settings::{FormattingSettings, QualitySettings, Settings, SplitLabels, Unfocused};
+use settings_overlay::SettingsOverlay;
+use settings_watcher::SettingsWatcher;
+use spike_log::{Snapshot, SpikeLog};
+use split_view::View;
+use terrain_clearance::{self, TerrainFilter};
+use tile_loading::TileLoading;
+use toasts::Toasts;
+use tour::{Capital, Tour, TourStep};
+use world_clock::{self, Toggled, WorldClocks};
+use {
+    astro, bindings, class_breaks, compass, coordinates, country_cycle, cursor_point, export,
+    features, formatting, info_panel, legend, links, named_session, overview, profile, routes,
+    toasts,
+};
+use {tuned_daylight_color, MapMode, Palette, Result, KM_PER_WORLD_X, KM_PER_WORLD_Y};
+
+use cgmath::{Matrix4, SquareMatrix, Vector4};
+use gaia_assetgen::Properties;
+use gfx::format::{DepthStencil, Srgba8};
+use gfx::handle::{DepthStencilView, RenderTargetView};
+use piston::input::keyboard::Key;
+use piston::input::mouse::MouseButton;
+use piston::input::Button;
+use piston_window::*;
+use rusttype::Font;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How much more than a country's bounding box is in view once the camera has flown to it.
+const FRAME_MARGIN: f32 = 1.2;
+
+/// How the selected country lights up when it is selected.
+const SELECTION_PULSE: Effect = Effect {
+    paint: Paint::Color([255, 255, 255, 160]),
+    duration: Some(Duration::from_millis(600)),
+    easing: Easing::Pulse,
+};
+
+/// Something `State` wants done that requires access to the window, which the main loop owns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowRequest {
+    Screenshot,
+    Export,
+    HiResScreenshot,
+    ToggleRecording,
+    SaveReplay,
+    ExportLegend,
+    /// The asset set in the settings changed, and should be loaded.
+    SwitchAssets,
+    ToggleFullscreen,
+    /// Escape was pressed: leave fullscreen or quit, depending on settings.
+    Escape,
+}
+
+/// Everything the demo shows and how it is being interacted with, short of the window and the
+/// renderer, which the main loop owns and hands in as they are needed.
+pub struct State {
+    pub camera_controller: CameraController,
+    /// The height of the terrain under the camera, smoothed, which the camera is kept above.
+    pub terrain_filter: TerrainFilter,
+    /// The terrain region under the camera, if any, for the frame times overlay.
+    pub terrain_region: Option<String>,
+    pub map_mode: MapMode,
+    /// The labels switch, toggled with 0. See `label_policy` for how it and the map mode decide
+    /// which labels are drawn.
+    pub labels_enabled: bool,
+    /// The map mode's label policy, or `Inherit` once 0 has been pressed since switching to it.
+    pub label_policy: LabelPolicy,
+    /// What has been tweaked of the map mode's adjustments since switching to it, which is not
+    /// saved. See `mode_adjustments`.
+    pub mode_tweaks: ModeAdjustments,
+    pub graticule_enabled: bool,
+    pub crosshair_enabled: bool,
+    pub overview_enabled: bool,
+    /// Whether the map is drawn in red and cyan, for 3D glasses.
+    pub stereo: bool,
+    pub frame_times: FrameTimes,
+    pub frame_times_visible: bool,
+    pub run_stats: RunStats,
+    pub spike_log: SpikeLog,
+    pub memory_usage: MemoryUsage,
+    /// Whether something drawn may have changed since the last frame was drawn, other than by
+    /// animating. Set by input and by settings changes.
+    pub needs_redraw: bool,
+    /// The solar time as of the last frame drawn, since it changes by itself.
+    pub drawn_solar_time: Option<String>,
+    /// The pinned clocks as of the last frame drawn, for the same reason.
+    pub drawn_clocks: Vec<String>,
+    pub tile_loading: TileLoading,
+    /// The cursor position, in the 2D pass's coordinates, for hit-testing clicks on HUD widgets.
+    pub cursor: [f64; 2],
+    /// The point on the map under the cursor, while the cursor coordinates are shown.
+    pub cursor_point: CursorPoint,
+    /// Where the overview inset was last drawn, as `[x, y, width, height]`, or `None` if it is
+    /// hidden.
+    pub overview_rect: Option<[f64; 4]>,
+    /// Where the compass was last drawn, or `None` if it is hidden.
+    pub compass_center: Option<[f64; 2]>,
+    /// Where the info panel's "More info" line was last drawn, as `[x, y, width, height]`, or
+    /// `None` if it is not shown.
+    pub more_info_rect: Option<[f64; 4]>,
+    /// The window size in the 2D pass's coordinates, as of the last frame.
+    pub view_size: [f64; 2],
+    /// Built for the part of the window the map is drawn in.
+    pub projection: Projection,
+    /// The aspect ratio given with `--lock-aspect`, which the map is letterboxed to while
+    /// `aspect_locked` is on.
+    pub aspect_lock: Option<AspectLock>,
+    pub aspect_locked: bool,
+    /// Physical pixels per 2D pass coordinate, as of the last frame.
+    pub pixel_density: f64,
+    /// The window's hidpi factor, as of the last frame, if the window system gives one. The HUD
+    /// is rasterized for it.
+    pub hidpi_factor: Option<f32>,
+    /// The HUD scale given with `--hud-scale`, which wins over the settings until the HUD scale
+    /// is changed in the settings overlay.
+    pub hud_scale_override: Option<f32>,
+    /// The formatting given on the command line, which wins over the settings until formatting
+    /// is changed in the settings overlay.
+    pub formatting_override: Option<FormattingSettings>,
+    /// The quality settings given on the command line, or implied by a run that has to be
+    /// repeatable, which win over the settings.
+    pub quality_override: Option<QualitySettings>,
+    /// The profile given on the command line, which wins over the settings until the profile is
+    /// changed in the settings overlay or toggled.
+    pub profile_override: Option<Profile>,
+    pub adaptive_quality: AdaptiveQuality,
+    /// Leaves parts of the HUD out while drawing it takes too long.
+    pub hud_budget: HudBudget,
+    pub features: Features,
+    /// What the places in `features` are labeled with, where their names are too long.
+    pub label_texts: LabelTexts,
+    /// The font labels are taken to be drawn with, if it is known, for working out which names
+    /// it cannot draw.
+    pub label_font: Option<Font<'static>>,
+    /// What the places in `features` whose names `label_font` cannot draw are labeled with.
+    pub label_fallbacks: LabelFallbacks,
+    /// Where each of `features` is.
+    pub geo_index: GeoIndex,
+    /// The index in `features` of the country clicked on, if any.
+    pub selected_polygon: Option<usize>,
+    /// Repeats going through the countries alphabetically while its key is held.
+    pub country_cycle: CountryCycle,
+    /// The settings as loaded from the settings file, without command-line options applied.
+    pub settings: Settings,
+    /// `settings`, with the profile in use layered over them, which labels, the HUD's theme and
+    /// country colors are drawn with.
+    pub profiled_settings: Settings,
+    /// Where `settings` were loaded from, and are saved to.
+    pub settings_path: PathBuf,
+    pub settings_watcher: SettingsWatcher,
+    /// The key bindings, with those from the settings file.
+    pub bindings: Vec<Binding>,
+    pub settings_overlay: SettingsOverlay,
+    pub help_overlay: HelpOverlay,
+    pub session_picker: SessionPicker,
+    pub command_palette: CommandPalette,
+    /// Where named sessions are saved to and loaded from.
+    pub sessions_dir: PathBuf,
+    pub legend: Legend,
+    /// The right half of the window, while it is split to compare two map modes. The left half
+    /// shows `map_mode`.
+    pub split: Option<SplitView>,
+    /// The colors of the map mode snapshotted with V, kept through mode switches until it is
+    /// taken again or forgotten with Shift+V.
+    pub color_snapshot: Option<ColorSnapshot>,
+    /// Whether countries are colored by how they changed since `color_snapshot`, toggled with D.
+    pub snapshot_diff: bool,
+    /// Changes to countries' colors for a while, on top of their map mode's.
+    pub effects: EffectLayer,
+    /// The selected country lighting up, while it does.
+    pub selection_pulse: Option<EffectId>,
+    /// The points given with `--points`, if any.
+    pub points: Option<PointLayer>,
+    /// The great-circle routes drawn over the map, saved with the session.
+    pub routes: Vec<Route>,
+    /// Whether clicks pick route endpoints instead of selecting countries.
+    pub route_tool: bool,
+    /// The first endpoint of the route being picked, as a latitude and longitude.
+    pub route_start: Option<[f64; 2]>,
+    /// The index of the route that plays, which is the last one drawn unless another was
+    /// clicked.
+    pub selected_route: Option<usize>,
+    /// The marker travelling along a route, if one was played.
+    pub journey: Option<Journey>,
+    /// Whether the camera follows the marker while a route plays.
+    pub following: bool,
+    /// The tour of capitals, while it runs.
+    pub tour: Option<Tour>,
+    /// The script in `modes.script`, which colors countries in scripted mode.
+    pub color_script: Option<ColorScript>,
+    /// Each country's color in the current map mode, so that it is not worked out again every
+    /// frame.
+    pub color_cache: RefCell<ColorCache>,
+    /// Which labels are left out for crowding others, as of the last frame the camera moved.
+    pub label_declutter: RefCell<LabelDeclutter>,
+    /// Where each country's population ranks, for population rank mode. Worked out when it is
+    /// first needed after the mode is switched to.
+    pub percentiles: RefCell<Option<Percentiles>>,
+    /// Where each country `countries.filter` keeps ranks among the others it keeps, which the info
+    /// panel gives rather than its rank among all of them. Coloring still ranks every country.
+    pub filtered_percentiles: RefCell<Option<Percentiles>>,
+    /// The time given with `--clock`, which stands in for the current time so that renders can be
+    /// repeated.
+    pub clock: Option<SystemTime>,
+    /// The index in `mode_parameters::parameters` of the map mode's parameter that + and -
+    /// adjust.
+    pub parameter_index: usize,
+    /// Which of the map mode's class breaks the arrow keys move, while they are edited.
+    pub break_editor: Option<usize>,
+    /// The mode parameter being animated, whose values are only saved once it finishes.
+    pub parameter_animation: Option<RunningAnimation>,
+    /// The cities whose local times are shown.
+    pub world_clocks: WorldClocks,
+    /// What everything animated on the map runs by, so that it can all be frozen at once.
+    pub animation_clock: AnimationClock,
+    /// Whether the window has focus.
+    pub focused: bool,
+    /// Whether losing focus froze `animation_clock`, which regaining it starts again.
+    pub frozen_by_focus: bool,
+    /// The time day and night mode's clock was last set to, and how far `animation_clock` had
+    /// got then, from which it runs `modes.time_acceleration` times faster.
+    pub daylight_anchor: (SystemTime, Duration),
+    pub shift_held: bool,
+    pub ctrl_held: bool,
+    pub window_requests: Vec<WindowRequest>,
+    pub toasts: Toasts,
+    pub clipboard: Box<dyn TextClipboard>,
+    pub browser: Box<dyn Browser>,
+}
+
+/// The label style chooser for while labels are disabled.
+fn no_labels(_properties: &Properties) -> Option<gaia::LabelStyle> {
+    None
+}
+
+/// A label style chooser, as gaia takes it.
+pub type LabelChooser<'s> =
+    Box<dyn for<'a> Fn(&'a Properties) -> Option<gaia::LabelStyle<'a>> + 's>;
+
+/// What the choosers read that changes from frame to frame.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameInputs {
+    /// Which part of the window is being drawn, which decides the map mode.
+    pub view: View,
+    pub camera_height: f32,
+    /// The map mode's `MapMode::animation_tick`.
+    pub animation_tick: Option<u64>,
+    /// How many times their usual size labels are drawn, for screenshots with more pixels than
+    /// the window.
+    pub label_scale: f32,
+    /// The `AnimationClock::elapsed` the frame is drawn at, which effects are applied as of.
+    pub now: Duration,
+}
+
+/// The colors `State::polygon_color_chooser` chose, by `features::feature_id`. Cleared whenever
+/// something they depend on changes: the map mode, which legend categories are enabled, or the
+/// mode's animation.
+#[derive(Debug, Default)]
+pub struct ColorCache {
+    /// The `MapMode::animation_tick` the colors are for.
+    tick: Option<u64>,
+    colors: HashMap<String, Option<[u8; 4]>>,
+}
+
+impl ColorCache {
+    fn clear(&mut self) {
+        self.colors.clear();
+    }
+
+    /// Clears the colors if they are for another animation tick than `tick`.
+    fn set_tick(&mut self, tick: Option<u64>) {
+        if tick != self.tick {
+            self.tick = tick;
+            self.colors.clear();
+        }
+    }
+}
+
+/// The right half of a split-screen, which shows a map mode of its own, with a legend of its
+/// own.
+#[derive(Debug)]
+pub struct SplitView {
+    pub mode: MapMode,
+    pub legend: Legend,
+    pub color_cache: RefCell<ColorCache>,
+    pub label_declutter: RefCell<LabelDeclutter>,
+}
+
+impl SplitView {
+    fn new(mode: MapMode) -> SplitView {
+        SplitView {
+            mode,
+            legend: Legend::new(),
+            color_cache: RefCell::new(ColorCache::default()),
+            label_declutter: RefCell::new(LabelDeclutter::default()),
+        }
+    }
+}
+
+impl State {
+    /// A state as the demo starts with `settings` and `features`, as far as it can be without a
+    /// window: the window is taken to be 800 by 600, nothing is copied to the system clipboard,
+    /// and the files the demo would write go under `dir`.
+    pub fn headless(settings: Settings, features: Features, dir: &Path) -> State {
+        let features = features.filtered(settings.countries.filter);
+        let mut state = State {
+            camera_controller: CameraController::new(settings.camera),
+            terrain_filter: TerrainFilter::default(),
+            terrain_region: None,
+            map_mode: settings.modes.default,
+            labels_enabled: settings.labels.enabled,
+            label_policy: settings.modes.labels.of(settings.modes.default),
+            mode_tweaks: ModeAdjustments::default(),
+            graticule_enabled: settings.hud.widgets.graticule,
+            crosshair_enabled: settings.hud.widgets.crosshair,
+            overview_enabled: settings.hud.widgets.overview,
+            stereo: false,
+            frame_times: FrameTimes::new(),
+            frame_times_visible: settings.hud.widgets.frame_times,
+            run_stats: RunStats::new(),
+            spike_log: SpikeLog::new(dir.join("spikes.log")),
+            memory_usage: MemoryUsage::new(),
+            needs_redraw: true,
+            drawn_solar_time: None,
+            drawn_clocks: vec![],
+            tile_loading: TileLoading::new(),
+            cursor: [0.0, 0.0],
+            cursor_point: CursorPoint::default(),
+            overview_rect: None,
+            compass_center: None,
+            more_info_rect: None,
+            view_size: [800.0, 600.0],
+            projection: Projection::new(Size {
+                width: 800,
+                height: 600,
+            }),
+            aspect_lock: None,
+            aspect_locked: false,
+            pixel_density: 1.0,
+            hidpi_factor: None,
+            hud_scale_override: None,
+            formatting_override: None,
+            quality_override: None,
+            profile_override: None,
+            profiled_settings: profile::layered(&settings, settings.profiles.active),
+            adaptive_quality: AdaptiveQuality::new(settings.quality.clone()),
+            hud_budget: HudBudget::new(settings.quality.hud_budget_ms),
+            geo_index: GeoIndex::new(features.polygons()),
+            label_texts: LabelTexts::new(features.place_names(), settings.labels.wrap),
+            label_font: None,
+            label_fallbacks: LabelFallbacks::default(),
+            features,
+            selected_polygon: None,
+            country_cycle: CountryCycle::new(),
+            settings_watcher: SettingsWatcher::new(dir.join("settings.toml")),
+            settings_path: dir.join("settings.toml"),
+            bindings: bindings::DEFAULT_BINDINGS.to_vec(),
+            settings_overlay: SettingsOverlay::new(settings.modes.default),
+            help_overlay: HelpOverlay::new(),
+            session_picker: SessionPicker::new(),
+            command_palette: CommandPalette::new(),
+            sessions_dir: dir.join("sessions"),
+            legend: Legend::new(),
+            split: None,
+            color_snapshot: None,
+            snapshot_diff: false,
+            effects: EffectLayer::new(),
+            selection_pulse: None,
+            color_cache: RefCell::new(ColorCache::default()),
+            label_declutter: RefCell::new(LabelDeclutter::default()),
+            percentiles: RefCell::new(None),
+            filtered_percentiles: RefCell::new(None),
+            clock: None,
+            parameter_index: 0,
+            break_editor: None,
+            parameter_animation: None,
+            world_clocks: WorldClocks::default(),
+            animation_clock: AnimationClock::new(),
+            focused: true,
+            frozen_by_focus: false,
+            daylight_anchor: (SystemTime::now(), Duration::from_secs(0)),
+            shift_held: false,
+            ctrl_held: false,
+            window_requests: Vec::new(),
+            toasts: Toasts::new(),
+            clipboard: Box::<MemoryClipboard>::default(),
+            browser: Box::<MemoryBrowser>::default(),
+            points: None,
+            routes: Vec::new(),
+            route_tool: false,
+            route_start: None,
+            selected_route: None,
+            journey: None,
+            tour: None,
+            following: settings.routes.follow,
+            color_script: None,
+            settings,
+        };
+        state.adjust_to_mode();
+        state
+    }
+
+    pub fn event<E>(&mut self, e: &E)
+    where
+        E: GenericEvent,
+    {
+        e.update(|args| self.update_terrain_floor(args.dt));
+        self.camera_controller.event(e);
+
+        e.update(|args| {
+            if !self.animation_clock.is_frozen() {
+                self.advance_animations(args.dt);
+            }
+            self.revert_expired_settings();
+            for action in self.country_cycle.update(args.dt) {
+                self.handle_action(action);
+            }
+        });
+
+        if let Some(args) = e.render_args() {
+            self.frame_times.frame();
+
+            let camera_height = self.camera_controller.camera_height();
+            self.run_stats.frame(self.desired_level(camera_height));
+            self.tile_loading.update(
+                self.desired_level(camera_height),
+                self.camera_controller.look_at(),
+                camera_height,
+            );
+            self.view_size = [f64::from(args.width), f64::from(args.height)];
+            let factor = self.hidpi_factor;
+            self.pixel_density = HudScale::density(factor, args.draw_height, args.height);
+            // The camera may have moved under a cursor that did not.
+            self.update_cursor_point();
+        }
+
+        e.mouse_cursor(|x, y| {
+            self.cursor = [x, y];
+            self.update_cursor_point();
+        });
+
+        e.press(|button| {
+            if button == Button::Mouse(MouseButton::Left) {
+                let action = self.click_action(self.cursor);
+                self.handle_action(action);
+            }
+        });
+
+        e.press(|button| match button {
+            Button::Keyboard(Key::LShift) | Button::Keyboard(Key::RShift) => {
+                self.shift_held = true;
+            }
+            Button::Keyboard(Key::LCtrl) | Button::Keyboard(Key::RCtrl) => {
+                self.ctrl_held = true;
+            }
+            _ => {}
+        });
+
+        e.release(|button| match button {
+            Button::Keyboard(Key::LShift) | Button::Keyboard(Key::RShift) => {
+                self.shift_held = false;
+            }
+            Button::Keyboard(Key::LCtrl) | Button::Keyboard(Key::RCtrl) => {
+                self.ctrl_held = false;
+            }
+            Button::Keyboard(key) => {
+                if self.country_cycle.release(key) {
+                    self.handle_action(Action::FrameSelected);
+                }
+            }
+            _ => {}
+        });
+
+        // The release would be missed if focus is lost while Shift, Ctrl or a key cycling through
+        // the countries is held.
+        e.focus(|focused| {
+            if !focused {
+                self.shift_held = false;
+                self.ctrl_held = false;
+                self.country_cycle.cancel();
+            }
+            self.set_focused(focused);
+        });
+
+        if self.session_picker.is_visible() {
+            e.text(|text| {
+                self.session_picker.text(text);
+            });
+        }
+        // Ctrl+P may also type a P, depending on the platform.
+        if self.command_palette.is_visible() && !self.ctrl_held {
+            e.text(|text| {
+                self.command_palette.text(text);
+            });
+        }
+
+        e.press(|button| {
+            if let Button::Keyboard(key) = button {
+                if self.command_palette.is_visible() {
+                    if self.chord_action(key) == Some(Action::TogglePalette) {
+                        self.command_palette.close();
+                    } else if let Some(command) = self.command_palette.key_press(key) {
+                        self.run_palette_command(command);
+                    }
+                    return;
+                }
+                if self.session_picker.is_visible() {
+                    match self.session_picker.key_press(key) {
+                        Some(Choice::Load(name)) => self.load_named_session(&name, true),
+                        Some(Choice::Save(name)) => self.save_named_session(&name),
+                        None => {}
+                    }
+                    return;
+                }
+                if self.help_overlay.is_visible() {
+                    self.help_overlay.key_press(key);
+                    return;
+                }
+                if !self.shift_held && self.edit_breaks(key) {
+                    return;
+                }
+
+                if let Some(action) = self.chord_action(key) {
+                    self.handle_action(action);
+                    return;
+                }
+
+                if self.settings_overlay.is_visible() && SettingsOverlay::takes(key) {
+                    if key == Key::Escape {
+                        self.toggle_settings_overlay();
+                        return;
+                    }
+                    let (before, now) = (self.settings.clone(), self.animation_clock.elapsed());
+                    if self.settings_overlay.key_press(key, &mut self.settings, now) {
+                        self.overlay_changed(&before);
+                    }
+                    if let Some(index) = self.settings_overlay.take_removed_clock() {
+                        self.unpin_clock(index);
+                    }
+                    return;
+                }
+
+                match bindings::action_for(&self.bindings, key) {
+                    Some(action @ Action::PreviousCountry) | Some(action @ Action::NextCountry) => {
+                        if self.country_cycle.press(key, action) {
+                            self.handle_action(action);
+                        }
+                    }
+                    Some(action) => self.handle_action(action),
+                    None => {}
+                }
+            }
+        });
+    }
+
+    /// The action `key` triggers with the modifiers held, if it is one of the chords that take
+    /// precedence over the bindings table.
+    fn chord_action(&self, key: Key) -> Option<Action> {
+        let (ctrl, shift) = (self.ctrl_held, self.shift_held);
+
+        if ctrl && shift && key == Key::C {
+            return Some(Action::CopySelected);
+        }
+        if ctrl && key == Key::R {
+            return Some(Action::ToggleRecording);
+        }
+        if ctrl && key == Key::P {
+            return Some(Action::TogglePalette);
+        }
+        if shift && key == Key::F12 {
+            return Some(Action::HiResScreenshot);
+        }
+        if shift && key == Key::F11 {
+            return Some(Action::ToggleAspectLock);
+        }
+        if shift && key == Key::L {
+            return Some(Action::ExportLegend);
+        }
+        if shift && key == Key::V {
+            return Some(Action::ClearSnapshot);
+        }
+        if shift && key == Key::M {
+            return Some(Action::PreviousMode);
+        }
+        if shift && key == Key::B {
+            return Some(Action::ResetBreaks);
+        }
+        if shift && key == Key::P {
+            return Some(Action::AnimateParameter);
+        }
+        if shift && key == Key::T {
+            return Some(Action::PinClock);
+        }
+        if shift && (key == Key::Return || key == Key::NumPadEnter) {
+            return Some(Action::FrameSelected);
+        }
+        if shift && key == Key::Space && self.animation_clock.is_frozen() {
+            return Some(Action::StepFrozen);
+        }
+        // The settings overlay moves between settings with the arrow keys.
+        if self.tour.is_some() && !self.settings_overlay.is_visible() {
+            match key {
+                Key::Left => return Some(Action::PreviousCapital),
+                Key::Right => return Some(Action::NextCapital),
+                _ => {}
+            }
+        }
+
+        if ctrl && self.split.is_some() {
+            let mode = bindings::action_for(&self.bindings, key).and_then(bindings::mode_shown_by);
+            if let Some(mode) = mode {
+                return Some(Action::ShowInRightHalf(mode));
+            }
+        }
+
+        if shift && self.legend.is_visible() {
+            if let Some(category) = legend::category_for_key(key) {
+                return Some(if ctrl && self.split.is_some() {
+                    Action::ToggleRightCategory(category)
+                } else {
+                    Action::ToggleCategory(category)
+                });
+            }
+        }
+
+        None
+    }
+
+    /// The action a left click at `cursor` triggers: whichever HUD widget is there, or else the
+    /// map.
+    fn click_action(&self, cursor: [f64; 2]) -> Action {
+        let on_compass = self.compass_center
+            .map_or(false, |center| compass::contains(center, self.hud_scale(), cursor));
+        if on_compass {
+            return Action::SnapNorth;
+        }
+        let on_more_info = self.more_info_rect.map_or(false, |[x, y, width, height]| {
+            cursor[0] >= x && cursor[0] <= x + width && cursor[1] >= y && cursor[1] <= y + height
+        });
+        if on_more_info {
+            return Action::OpenSelectedLink;
+        }
+
+        let on_overview = self.overview_rect.and_then(|rect| {
+            let center_x = self.camera_controller.look_at()[0];
+            overview::from_inset(rect, center_x, cursor)
+        });
+        if let Some(point) = on_overview {
+            let (latitude, longitude) = coordinates::from_map_point(point);
+            return Action::FlyTo {
+                latitude,
+                longitude,
+            };
+        }
+
+        Action::ClickMap(cursor)
+    }
+
+    /// Does what `action` says to. Every input, whichever way it came in, ends up here.
+    pub fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::ShowTerrain => {
+                self.set_map_mode(MapMode::Terrain);
+            }
+            Action::ShowAllCountries => {
+                self.set_map_mode(MapMode::All);
+            }
+            Action::ShowOecd => {
+                self.set_map_mode(MapMode::Oecd);
+            }
+            Action::ShowIncome => {
+                self.set_map_mode(MapMode::Income);
+            }
+            Action::ShowExceptional => {
+                self.set_map_mode(MapMode::Exceptional);
+            }
+            Action::ShowScripted => {
+                if self.color_script_missing() {
+                    return;
+                }
+                self.set_map_mode(MapMode::Scripted);
+            }
+            Action::ShowDaylight => {
+                self.set_map_mode(MapMode::Daylight);
+            }
+            Action::ShowPercentile => {
+                self.set_map_mode(MapMode::Percentile);
+            }
+            Action::ToggleRouteTool => {
+                self.route_tool = !self.route_tool;
+                self.route_start = None;
+
+                let message = if self.route_tool {
+                    "Route tool on: click two points to draw the route between them"
+                } else {
+                    "Route tool off"
+                };
+                self.toast(message.to_string());
+            }
+            Action::DeleteRoute => match self.route_at(self.cursor) {
+                Some(index) => self.delete_route(index),
+                None => self.toast("Point at a route to delete it".to_string()),
+            },
+            Action::PlayRoute => self.play_route(),
+            Action::ToggleFollow => {
+                self.following = !self.following;
+                // Only what moves the camera from now on stops it following.
+                self.camera_controller.take_interrupted();
+
+                let message = if self.following {
+                    "Flight mode on: the camera follows a playing route"
+                } else {
+                    "Flight mode off"
+                };
+                self.toast(message.to_string());
+            }
+            Action::ToggleSplit => {
+                self.split = match self.split {
+                    Some(_) => None,
+                    None => Some(SplitView::new(self.settings.split.mode)),
+                };
+                // Each eye draws the whole window.
+                self.stereo = false;
+
+                let message = if self.split.is_some() {
+                    "Split-screen on: hold Ctrl while picking a mode to change the right half's"
+                } else {
+                    "Split-screen off"
+                };
+                self.toast(message.to_string());
+            }
+            Action::ToggleStereo => {
+                self.stereo = !self.stereo;
+                if self.stereo {
+                    self.split = None;
+                }
+
+                let message = if self.stereo {
+                    "Red and cyan 3D on: eye separation and convergence are in the settings (F2)"
+                } else {
+                    "Red and cyan 3D off"
+                };
+                self.toast(message.to_string());
+            }
+            Action::ToggleLabels => {
+                let (enabled, policy) =
+                    label_policy::toggled(self.labels_enabled, self.label_policy);
+                self.labels_enabled = enabled;
+                self.label_policy = policy;
+                self.mode_tweaks.labels = Some(policy);
+
+                let message = if self.labels_enabled { "Labels on" } else { "Labels off" };
+                self.toast(message.to_string());
+            }
+            Action::SnapNorth => {
+                self.camera_controller.snap_north();
+            }
+            Action::ToggleGraticule => {
+                self.graticule_enabled = !self.graticule_enabled;
+            }
+            Action::TakeSnapshot => {
+                self.take_snapshot();
+            }
+            Action::ClearSnapshot => {
+                let message = if self.color_snapshot.take().is_some() {
+                    "Snapshot forgotten"
+                } else {
+                    "No snapshot to forget"
+                };
+                self.snapshot_diff = false;
+                self.clear_colors();
+                self.toast(message.to_string());
+            }
+            Action::ToggleSnapshotDiff => {
+                let message = match self.color_snapshot {
+                    Some(ref snapshot) if !self.snapshot_diff => {
+                        format!("Changes since the {} snapshot", snapshot.mode().name())
+                    }
+                    Some(_) => "Changes since the snapshot hidden".to_string(),
+                    None => "No snapshot. Press V to take one".to_string(),
+                };
+                self.snapshot_diff = !self.snapshot_diff && self.color_snapshot.is_some();
+                self.clear_colors();
+                self.toast(message);
+            }
+            Action::ToggleStars => {
+                self.settings.sky.stars = !self.settings.sky.stars;
+                self.apply_settings();
+            }
+            Action::ToggleCrosshair => {
+                self.crosshair_enabled = !self.crosshair_enabled;
+            }
+            Action::ToggleOverview => {
+                self.overview_enabled = !self.overview_enabled;
+                if self.overview_enabled {
+                    self.tile_loading.expect_tiles();
+                }
+            }
+            Action::ToggleHighContrast => {
+                let profile = match self.profile() {
+                    Some(Profile::HighContrast) => None,
+                    _ => Some(Profile::HighContrast),
+                };
+                self.profile_override = None;
+                self.settings.profiles.active = profile;
+                self.toast(match profile {
+                    Some(profile) => format!("Profile: {}", profile.name()),
+                    None => "Profile: none".to_string(),
+                });
+                self.apply_settings();
+            }
+            Action::ToggleFreeze => {
+                self.animation_clock.toggle();
+                self.needs_redraw = true;
+            }
+            Action::StepFrozen => {
+                if self.animation_clock.step() {
+                    self.advance_animations(animation_clock::STEP_SECS);
+                    self.needs_redraw = true;
+                }
+            }
+            Action::PreviousParameter => self.select_parameter(-1),
+            Action::NextParameter => self.select_parameter(1),
+            Action::IncreaseParameter => self.adjust_parameter(1),
+            Action::DecreaseParameter => self.adjust_parameter(-1),
+            Action::EditBreaks => self.toggle_break_editor(),
+            Action::ResetBreaks => self.reset_breaks(),
+            Action::AnimateParameter => {
+                if !self.stop_parameter_animation() {
+                    let mode = self.map_mode;
+                    match mode_parameters::parameters(mode).get(self.parameter_index) {
+                        Some(&parameter) => {
+                            self.animate_parameter(ParameterAnimation::sweep(mode, parameter))
+                        }
+                        None => self.toast(format!("{} mode has no parameters", mode.name())),
+                    }
+                }
+            }
+            Action::PinClock => self.pin_clock(),
+            Action::TogglePalette => {
+                if self.command_palette.is_visible() {
+                    self.command_palette.close();
+                } else {
+                    let entries = self.palette_entries();
+                    self.show_hud();
+                    self.command_palette.open(entries);
+                }
+            }
+            Action::ToggleHud => {
+                self.settings.hud.visible = !self.settings.hud.visible;
+                if !self.settings.hud.visible {
+                    self.close_hud_overlays();
+                }
+                self.apply_settings();
+            }
+            Action::TogglePerformance => {
+                self.frame_times_visible = !self.frame_times_visible;
+            }
+            Action::ToggleLegend => {
+                self.legend.toggle();
+            }
+            Action::ToggleHelp => {
+                self.help_overlay.toggle();
+            }
+            Action::ToggleSettings => {
+                if !self.settings_overlay.is_visible() {
+                    self.show_hud();
+                }
+                self.toggle_settings_overlay();
+            }
+            Action::ToggleSessions => match named_session::list(&self.sessions_dir) {
+                Ok(names) => {
+                    self.show_hud();
+                    self.session_picker.open(names);
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    self.toast(format!("Could not list sessions: {}", e));
+                }
+            },
+            Action::ToggleFullscreen => {
+                self.window_requests.push(WindowRequest::ToggleFullscreen);
+            }
+            Action::ToggleAspectLock => self.toggle_aspect_lock(),
+            Action::Screenshot => {
+                self.window_requests.push(WindowRequest::Screenshot);
+            }
+            Action::Export => {
+                self.window_requests.push(WindowRequest::Export);
+            }
+            Action::SaveReplay => {
+                self.window_requests.push(WindowRequest::SaveReplay);
+            }
+            Action::NextAssetSet => {
+                if self.settings.paths.asset_set_names().len() < 2 {
+                    let message = "No other asset sets. Add some under [paths.asset_sets]";
+                    self.toast(message.to_string());
+                    return;
+                }
+
+                self.settings.paths.cycle_asset_set(1);
+                self.apply_settings();
+                self.window_requests.push(WindowRequest::SwitchAssets);
+            }
+            Action::Escape => {
+                self.window_requests.push(WindowRequest::Escape);
+            }
+            Action::CopySelected => self.copy_selected(),
+            Action::OpenSelectedLink => self.open_selected_link(),
+            Action::ToggleRecording => {
+                self.window_requests.push(WindowRequest::ToggleRecording);
+            }
+            Action::HiResScreenshot => {
+                self.window_requests.push(WindowRequest::HiResScreenshot);
+            }
+            Action::ExportLegend => {
+                self.window_requests.push(WindowRequest::ExportLegend);
+            }
+            Action::NextMode => self.step_map_mode(1),
+            Action::PreviousMode => self.step_map_mode(-1),
+            Action::ShowInRightHalf(mode) => {
+                if self.split.is_some() {
+                    self.set_split_mode(mode);
+                }
+            }
+            Action::ToggleCategory(category) | Action::ToggleRightCategory(category) => {
+                let (mode, legend) = match self.split {
+                    Some(ref mut split) if action == Action::ToggleRightCategory(category) => {
+                        (split.mode, &mut split.legend)
+                    }
+                    _ => (self.map_mode, &mut self.legend),
+                };
+                legend.toggle_category(category, mode.legend_entries().len());
+                self.clear_colors();
+            }
+            Action::FlyTo {
+                latitude,
+                longitude,
+            } => {
+                let camera = &mut self.camera_controller;
+                let (height, heading) = (camera.camera_height(), camera.heading());
+                camera.fly_to(latitude as f32, longitude as f32, height, heading.to_degrees());
+            }
+            Action::ClickMap(cursor) => {
+                if self.route_tool {
+                    self.pick_route_endpoint(cursor);
+                } else {
+                    self.select_at(cursor);
+                }
+            }
+            Action::PreviousCountry => {
+                self.step_selection(-1);
+            }
+            Action::NextCountry => {
+                self.step_selection(1);
+            }
+            Action::FrameSelected => {
+                self.frame_selected();
+            }
+            Action::ToggleTour => {
+                self.toggle_tour();
+            }
+            Action::PreviousCapital => {
+                self.skip_capitals(-1);
+            }
+            Action::NextCapital => {
+                self.skip_capitals(1);
+            }
+        }
+    }
+
+    /// Switches to the mode `steps` after the current one among those shown, skipping scripted
+    /// mode while there is no color script. From a hidden mode, goes to the first or the last.
+    fn step_map_mode(&mut self, steps: isize) {
+        let mut modes = self.settings.modes.shown();
+        if self.color_script.is_none() {
+            modes.retain(|&mode| mode != MapMode::Scripted);
+        }
+        if modes.is_empty() {
+            return;
+        }
+
+        let count = modes.len() as isize;
+        let index = match modes.iter().position(|&mode| mode == self.map_mode) {
+            Some(index) => (index as isize + steps).rem_euclid(count),
+            None if steps > 0 => 0,
+            None => count - 1,
+        };
+        self.handle_action(bindings::show_mode(modes[index as usize]));
+    }
+
+    fn set_map_mode(&mut self, map_mode: MapMode) {
+        debug!("Map mode: {}", map_mode.name());
+        self.toast(format!("Mode: {}", map_mode.name()));
+        if map_mode != self.map_mode {
+            self.run_stats.mode_switched();
+            self.map_mode = map_mode;
+            self.adjust_to_mode();
+        }
+        self.legend.reset();
+        self.color_cache.borrow_mut().clear();
+        self.parameter_index = 0;
+        self.break_editor = None;
+        if map_mode == MapMode::Percentile {
+            self.percentiles.replace(None);
+        }
+    }
+
+    /// Selects the map mode's parameter `steps` on from the selected one, wrapping around, and
+    /// shows it.
+    fn select_parameter(&mut self, steps: isize) {
+        let count = mode_parameters::parameters(self.map_mode).len() as isize;
+        if count == 0 {
+            self.toast(format!("{} mode has no parameters", self.map_mode.name()));
+            return;
+        }
+
+        let index = (self.parameter_index as isize + steps).rem_euclid(count);
+        self.parameter_index = index as usize;
+        self.show_parameter();
+    }
+
+    /// Changes the selected parameter of the map mode by `steps` of its step, and shows it.
+    fn adjust_parameter(&mut self, steps: i32) {
+        let (mode, index) = (self.map_mode, self.parameter_index);
+        let parameters = &mut self.settings.modes.parameters;
+        if parameters.adjust(mode, index, steps).is_none() {
+            self.toast(format!("{} mode has no parameters", mode.name()));
+            return;
+        }
+
+        self.apply_settings();
+        self.show_parameter();
+    }
+
+    /// Shows the selected parameter of the map mode, and its value.
+    fn show_parameter(&mut self) {
+        let parameters = mode_parameters::parameters(self.map_mode);
+        if let Some(parameter) = parameters.get(self.parameter_index) {
+            let value = self.settings.modes.parameters.get(self.map_mode, parameter.name);
+            let value = value.unwrap_or(parameter.default);
+            let decimals = if parameter.step < 1.0 { 2 } else { 0 };
+            self.toasts.progress(format!(
+                "{} {}/{}: {} {}",
+                self.map_mode.name(),
+                self.parameter_index + 1,
+                parameters.len(),
+                parameter.label,
+                formatting::number(f64::from(value), decimals, self.formatting()),
+            ));
+        }
+    }
+
+    /// Starts editing the map mode's class breaks in the legend, or stops.
+    fn toggle_break_editor(&mut self) {
+        if self.break_editor.take().is_some() {
+            return;
+        }
+        if self.class_breaks().is_none() {
+            self.toast(format!("{} mode has no class breaks", self.map_mode.name()));
+            return;
+        }
+
+        self.legend.set_visible(true);
+        self.break_editor = Some(0);
+    }
+
+    /// Handles `key` while the class breaks are edited: Up and Down pick a break, Left and Right
+    /// move it, and Escape stops editing. Returns whether `key` was one of those.
+    fn edit_breaks(&mut self, key: Key) -> bool {
+        let (index, count) = match (self.break_editor, self.class_breaks()) {
+            (Some(index), Some((breaks, _))) => (index, breaks.len()),
+            _ => return false,
+        };
+
+        match key {
+            Key::Up => self.break_editor = Some((index + count - 1) % count),
+            Key::Down => self.break_editor = Some((index + 1) % count),
+            Key::Left => self.nudge_break(index, -1),
+            Key::Right => self.nudge_break(index, 1),
+            Key::Escape => self.break_editor = None,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Moves class break `index` by `steps` presses, no further than the breaks beside it, and
+    /// keeps the breaks as edited ones.
+    fn nudge_break(&mut self, index: usize, steps: i32) {
+        let (mut breaks, _) = match self.class_breaks() {
+            Some(breaks) => breaks,
+            None => return,
+        };
+        let range = self.with_percentiles(Percentiles::range);
+        // Bands no country reaches start beyond every value, which cannot be saved.
+        for limit in &mut breaks {
+            *limit = limit.min(range.1);
+        }
+
+        class_breaks::nudge(&mut breaks, index, steps, range);
+        let modes = &mut self.settings.modes;
+        modes
+            .breaks
+            .set(self.map_mode, percentile::PROPERTY, &breaks);
+        self.apply_settings();
+    }
+
+    /// Starts `animation`, in place of any other, from now by the animation clock.
+    pub fn animate_parameter(&mut self, animation: ParameterAnimation) {
+        let now = self.animation_clock.elapsed();
+        self.parameter_animation = Some(RunningAnimation::new(animation, now));
+        self.toast(format!("Animating {}", animation.describe()));
+        self.update_parameter_animation();
+    }
+
+    /// Stops animating a parameter, returning it to the value it had before. Returns whether one
+    /// was being animated.
+    pub fn stop_parameter_animation(&mut self) -> bool {
+        if self.parameter_animation.take().is_none() {
+            return false;
+        }
+
+        self.layer_profile();
+        self.toast("Animation stopped".to_string());
+        true
+    }
+
+    /// Whether a parameter is being animated that will finish by itself, and not in a loop.
+    pub fn is_finishing_animation(&self) -> bool {
+        let now = self.animation_clock.elapsed();
+        self.parameter_animation.map_or(false, |running| {
+            running.animation.repeat == Repeat::Once && !running.is_finished(now)
+        })
+    }
+
+    /// Applies the animated parameter's value for now, if it is time to recolor, and saves the
+    /// end value once the animation finishes.
+    fn update_parameter_animation(&mut self) {
+        let now = self.animation_clock.elapsed();
+        let (value, finished, animation) = match self.parameter_animation {
+            Some(ref mut running) => {
+                (running.step(now), running.is_finished(now), running.animation)
+            }
+            None => return,
+        };
+        let name = animation.parameter.name;
+
+        if finished {
+            self.parameter_animation = None;
+            self.settings.modes.parameters.set(animation.mode, name, animation.to);
+            self.apply_settings();
+            self.toast(format!("Finished animating {}", animation.describe()));
+        } else if let Some(value) = value {
+            self.profiled_settings.modes.parameters.set(animation.mode, name, value);
+            self.clear_colors();
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Returns the map mode's class breaks to the ones it works out for itself.
+    fn reset_breaks(&mut self) {
+        let modes = &mut self.settings.modes;
+        if modes.breaks.reset(self.map_mode, percentile::PROPERTY) {
+            self.apply_settings();
+            self.toast("Class breaks reset to quantiles".to_string());
+        } else {
+            self.toast(format!(
+                "{} mode has no edited class breaks",
+                self.map_mode.name()
+            ));
+        }
+    }
+
+    /// The map mode's class breaks, lowest first, and whether they were edited, or `None` if the
+    /// mode has none.
+    pub fn class_breaks(&self) -> Option<(Vec<f64>, bool)> {
+        if self.map_mode != MapMode::Percentile {
+            return None;
+        }
+
+        Some(match self.edited_breaks() {
+            Some(breaks) => (breaks.to_vec(), true),
+            None => (
+                self.with_percentiles(|p| p.quantile_breaks().to_vec()),
+                false,
+            ),
+        })
+    }
+
+    /// The class breaks population rank mode's were edited to, if they were. Ignored if there
+    /// are not as many as it has.
+    fn edited_breaks(&self) -> Option<&[f64]> {
+        let breaks = &self.profiled_settings.modes.breaks;
+        breaks
+            .get(MapMode::Percentile, percentile::PROPERTY)
+            .filter(|breaks| breaks.len() == percentile::BANDS - 1)
+    }
+
+    /// What the legend shows for `mode`: its categories in `palette`, or the classes of its
+    /// edited class breaks.
+    pub fn legend_entries_in(&self, mode: MapMode, palette: Palette) -> Vec<LegendEntry> {
+        match (mode, self.edited_breaks()) {
+            (MapMode::Percentile, Some(breaks)) => {
+                percentile::break_legend_entries(breaks, self.formatting())
+            }
+            _ => mode.legend_entries_in(palette),
+        }
+    }
+
+    /// Lines for the panel shown while the class breaks are edited: the break being moved, and
+    /// the keys.
+    pub fn break_editor_lines(&self) -> Option<Vec<String>> {
+        let index = self.break_editor?;
+        let (breaks, edited) = self.class_breaks()?;
+        let value = formatting::count(*breaks.get(index)?, self.formatting());
+
+        Some(vec![
+            format!(
+                "Class break {}/{}: {} ({})",
+                index + 1,
+                breaks.len(),
+                value,
+                if edited { "manual" } else { "quantiles" }
+            ),
+            "Up/Down: pick, Left/Right: move, Shift+B: reset, B: done".to_string(),
+        ])
+    }
+
+    /// Switches the right half of the split-screen to `map_mode`.
+    fn set_split_mode(&mut self, map_mode: MapMode) {
+        if map_mode == MapMode::Scripted && self.color_script_missing() {
+            return;
+        }
+
+        debug!("Right half's map mode: {}", map_mode.name());
+        self.toast(format!("Right half: {}", map_mode.name()));
+        if let Some(ref mut split) = self.split {
+            split.mode = map_mode;
+            split.legend.reset();
+            split.color_cache.borrow_mut().clear();
+        }
+        if map_mode == MapMode::Percentile {
+            self.percentiles.replace(None);
+        }
+    }
+
+    /// Whether there is no color script for scripted mode to color countries with, in which case
+    /// the user is told how to add one.
+    fn color_script_missing(&mut self) -> bool {
+        if self.color_script.is_some() {
+            return false;
+        }
+
+        self.toast("No color script. Set modes.script in the settings".to_string());
+        true
+    }
+
+    /// Snapshots how the map mode colors every country, as the whole map or the left half shows
+    /// it, replacing any snapshot there was.
+    fn take_snapshot(&mut self) {
+        let frame = self.frame_inputs(View::Whole);
+        let classes = self.features.countries().iter().map(|country| {
+            let color = self.polygon_color(country, frame);
+            (country.id.clone(), Class::of(self.map_mode, country, color))
+        });
+        let snapshot = ColorSnapshot::new(self.map_mode, classes);
+
+        self.color_snapshot = Some(snapshot);
+        if self.snapshot_diff {
+            self.clear_colors();
+        }
+        self.toast(format!("Snapshot of {} taken. Press D to compare", self.map_mode.name()));
+    }
+
+    /// The snapshot countries are colored by how they changed since, while they are.
+    fn shown_snapshot(&self) -> Option<&ColorSnapshot> {
+        self.color_snapshot.as_ref().filter(|_| self.snapshot_diff)
+    }
+
+    /// The legend of the diff view between the snapshot and `mode`, while countries are colored
+    /// by how they changed since it.
+    pub fn snapshot_legend_entries(&self, mode: MapMode) -> Option<Vec<LegendEntry>> {
+        self.shown_snapshot().map(|snapshot| snapshot.legend_entries(mode))
+    }
+
+    /// Forgets the colors of every view, for when something they all depend on changes.
+    pub fn clear_colors(&self) {
+        self.color_cache.borrow_mut().clear();
+        if let Some(ref split) = self.split {
+            split.color_cache.borrow_mut().clear();
+        }
+    }
+
+    /// Records whether the window has focus. Unless `window.unfocused` is "continue", losing it
+    /// freezes the animation clock, and with it the tour and everything else animated, and
+    /// regaining it starts the clock again where it was, if losing it was what froze it.
+    pub fn set_focused(&mut self, focused: bool) {
+        if focused == self.focused {
+            return;
+        }
+        self.focused = focused;
+
+        if !focused && self.settings.window.unfocused.suspends() {
+            if !self.animation_clock.is_frozen() {
+                self.animation_clock.toggle();
+                self.frozen_by_focus = true;
+            }
+        } else if focused && self.frozen_by_focus {
+            self.frozen_by_focus = false;
+            if self.animation_clock.is_frozen() {
+                self.animation_clock.toggle();
+            }
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Whether frames are left undrawn, as `window.unfocused` "pause" has it, while the window
+    /// is not focused.
+    pub fn is_paused_unfocused(&self) -> bool {
+        !self.focused && self.settings.window.unfocused == Unfocused::Pause
+    }
+
+    /// Whether anything on screen changes without input, so that frames must keep coming at the
+    /// full rate. Exceptional mode's colors cycle, and the tile loading spinner turns.
+    pub fn is_animating(&self) -> bool {
+        let clock_running = !self.animation_clock.is_frozen()
+            && (self
+                .views()
+                .iter()
+                .any(|&view| self.animation_tick(self.mode_in(view).0).is_some())
+                || self.journey.map_or(false, |journey| {
+                    journey.is_moving(&self.routes[journey.route]) || self.following
+                })
+                || self.tour.is_some()
+                || self.parameter_animation.is_some()
+                || self.effects.is_animating());
+
+        self.camera_controller.is_animating()
+            || clock_running
+            || self.toasts.is_showing()
+            || self.tile_loading.loading_level().is_some()
+            || self.settings_overlay.has_pending()
+    }
+
+    /// Whether the frame about to be drawn could differ from the last one drawn. Clears what made
+    /// it so, so that it is only drawn once.
+    ///
+    /// Animations, such as exceptional mode's colors, count as changes for as long as they run,
+    /// and so does the frame time graph, which moves every frame.
+    pub fn take_needs_redraw(&mut self) -> bool {
+        let solar_time = if self.settings.hud.widgets.solar_time {
+            Some(self.solar_time_text().1)
+        } else {
+            None
+        };
+        let clocks = self.world_clock_lines();
+        let clock_changed = solar_time != self.drawn_solar_time || clocks != self.drawn_clocks;
+        self.drawn_solar_time = solar_time;
+        self.drawn_clocks = clocks;
+
+        // The frame after the last effect is over is drawn without it.
+        let effects_expired = self.effects.expire(self.animation_clock.elapsed());
+
+        let needs_redraw = self.needs_redraw
+            || clock_changed
+            || effects_expired
+            || self.is_animating()
+            || self.frame_times_visible;
+        self.needs_redraw = false;
+        needs_redraw
+    }
+
+    /// Opens or closes the settings overlay. Closing it reverts the changes still on trial.
+    fn toggle_settings_overlay(&mut self) {
+        let before = self.settings.clone();
+        let reverted = self.settings_overlay.toggle(&mut self.settings);
+        if !reverted.is_empty() {
+            self.toast(format!("Reverted the {}", reverted.join(" and ")));
+            self.overlay_changed(&before);
+        }
+    }
+
+    /// Shows the HUD, if hidden, for an overlay drawn in it to be opened.
+    fn show_hud(&mut self) {
+        if !self.settings.hud.visible {
+            self.settings.hud.visible = true;
+            self.apply_settings();
+        }
+    }
+
+    /// Closes the overlays drawn in the HUD, as it is hidden, so that they do not go on taking
+    /// keys unseen.
+    fn close_hud_overlays(&mut self) {
+        if self.settings_overlay.is_visible() {
+            self.toggle_settings_overlay();
+        }
+        self.session_picker.close();
+        self.command_palette.close();
+    }
+
+    /// Pushes changed settings to everything that uses them, and persists them.
+    pub fn apply_settings(&mut self) {
+        self.needs_redraw = true;
+        self.camera_controller.set_settings(self.settings.camera);
+        self.layer_profile();
+
+        // Changes on trial are only saved once kept, so that one that makes the demo unusable is
+        // not kept by closing it.
+        if self.settings_overlay.has_pending() {
+            return;
+        }
+        match self.settings.save(&self.settings_path) {
+            Ok(()) => self.settings_watcher.saved(),
+            Err(ref e) => error!("{}", e),
+        }
+    }
+
+    /// Applies a change the settings overlay made to the settings, which were `before`.
+    fn overlay_changed(&mut self, before: &Settings) {
+        if self.settings.hud.scale != before.hud.scale {
+            self.hud_scale_override = None;
+        }
+        if self.settings.formatting != before.formatting {
+            self.formatting_override = None;
+        }
+        if self.settings.profiles.active != before.profiles.active {
+            self.profile_override = None;
+        }
+        // The other quality settings given on the command line still apply.
+        if self.settings.quality != before.quality {
+            let changed = &self.settings.quality;
+            if let Some(ref mut quality) = self.quality_override {
+                quality.render_scale = changed.render_scale;
+                quality.adaptive = changed.adaptive;
+                quality.frame_budget_ms = changed.frame_budget_ms;
+            }
+            let quality = self.quality().clone();
+            self.hud_budget.set_budget(quality.hud_budget_ms);
+            self.adaptive_quality.set_settings(quality);
+        }
+        if self.settings.paths.asset_set != before.paths.asset_set {
+            self.window_requests.push(WindowRequest::SwitchAssets);
+        }
+        // Saving an adjustment for the mode replaces what was tweaked of it this session.
+        let (saved, was) = (
+            self.settings.modes.adjustments.of(self.map_mode),
+            before.modes.adjustments.of(self.map_mode),
+        );
+        if saved != was {
+            self.mode_tweaks = ModeAdjustments::default();
+            if saved.labels != was.labels {
+                self.label_policy = self.mode_label_policy(&self.settings);
+            }
+        }
+
+        self.apply_settings();
+    }
+
+    /// Reverts the settings overlay's changes on trial whose time is up.
+    fn revert_expired_settings(&mut self) {
+        if !self.settings_overlay.has_pending() {
+            return;
+        }
+
+        let before = self.settings.clone();
+        let now = self.animation_clock.elapsed();
+        let reverted = self.settings_overlay.revert_expired(&mut self.settings, now);
+        if !reverted.is_empty() {
+            self.toast(format!("Reverted the {}", reverted.join(" and ")));
+            self.overlay_changed(&before);
+        }
+    }
+
+    /// Applies the settings file if something else changed it. If it cannot be loaded, the
+    /// settings are left as they were.
+    pub fn check_settings_file(&mut self) {
+        match self.settings_watcher.poll() {
+            Some(Ok(settings)) => self.reload_settings(settings),
+            Some(Err(e)) => {
+                let message = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+                warn!("Settings not reloaded: {}", message);
+                self.toast(format!("Settings not reloaded: {}", message));
+            }
+            None => {}
+        }
+    }
+
+    /// Reloads the points file if it changed. If it cannot be loaded, the points are left as they
+    /// were.
+    pub fn check_points_file(&mut self) {
+        let (reloaded, count, path) = match self.points {
+            Some(ref mut layer) => match layer.poll() {
+                Some(reloaded) => (reloaded, layer.points().len(), layer.path().to_path_buf()),
+                None => return,
+            },
+            None => return,
+        };
+        self.needs_redraw = true;
+
+        match reloaded {
+            Ok(warnings) => {
+                info!("Loaded {} points from {}", count, path.display());
+                for warning in &warnings {
+                    warn!("{}: {}", path.display(), warning);
+                }
+
+                if warnings.is_empty() {
+                    self.toast(format!("Loaded {} points", count));
+                } else {
+                    self.toast(format!(
+                        "Loaded {} points, with {} warnings in the log",
+                        count,
+                        warnings.len()
+                    ));
+                }
+            }
+            Err(e) => {
+                let message = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+                warn!("Points not reloaded: {}", message);
+                self.toast(format!("Points not reloaded: {}", message));
+            }
+        }
+    }
+
+    /// Reloads the color script if it changed, and shows the first country it failed for since it
+    /// was loaded. If it cannot be loaded, the script is left as it was.
+    pub fn check_color_script(&mut self) {
+        let (reloaded, path) = match self.color_script {
+            Some(ref mut script) => match script.poll() {
+                Some(reloaded) => (reloaded, script.path().to_path_buf()),
+                None => {
+                    if let Some(e) = script.take_error() {
+                        warn!("Color script failed for {}", e);
+                        self.toast(format!("Color script failed for {}", e));
+                    }
+                    return;
+                }
+            },
+            None => return,
+        };
+
+        match reloaded {
+            Ok(()) => {
+                info!("Loaded color script {}", path.display());
+                if self.map_mode == MapMode::Scripted {
+                    self.toast("Color script reloaded".to_string());
+                }
+                self.clear_colors();
+                self.needs_redraw = true;
+            }
+            Err(e) => {
+                let message = e.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(": ");
+                warn!("Color script not reloaded: {}", message);
+                self.toast(format!("Color script not reloaded: {}", message));
+            }
+        }
+    }
+
+    /// Replaces the settings with `settings`, reloaded from the settings file.
+    fn reload_settings(&mut self, settings: Settings) {
+        if settings == self.settings {
+            return;
+        }
+
+        // Everything that can fail is done first, so that a failure applies nothing.
+        let bindings = match bindings::with_overrides(&settings.bindings, &settings.modes.shown()) {
+            Ok(bindings) => bindings,
+            Err(e) => {
+                warn!("Settings not reloaded: {}", e);
+                self.toast(format!("Settings not reloaded: {}", e));
+                return;
+            }
+        };
+        let startup_only = self.settings.startup_only_changes(&settings);
+        let (old_paths, new_paths) = (&self.settings.paths, &settings.paths);
+        if new_paths.asset_set != old_paths.asset_set
+            || new_paths.asset_set_dir(&new_paths.asset_set)
+                != old_paths.asset_set_dir(&new_paths.asset_set)
+        {
+            self.window_requests.push(WindowRequest::SwitchAssets);
+        }
+
+        // What can also be toggled while running follows the file only where the file changed.
+        let old_policy = self.mode_label_policy(&self.settings);
+        let new_policy = self.mode_label_policy(&settings);
+        let (old, new) = (&self.settings, &settings);
+        if new.labels.enabled != old.labels.enabled {
+            self.labels_enabled = new.labels.enabled;
+            self.label_policy = LabelPolicy::Inherit;
+        }
+        if new_policy != old_policy {
+            self.label_policy = new_policy;
+        }
+        if new.hud.widgets.graticule != old.hud.widgets.graticule {
+            self.graticule_enabled = new.hud.widgets.graticule;
+        }
+        if new.hud.widgets.crosshair != old.hud.widgets.crosshair {
+            self.crosshair_enabled = new.hud.widgets.crosshair;
+        }
+        if new.hud.widgets.overview != old.hud.widgets.overview {
+            self.overview_enabled = new.hud.widgets.overview;
+        }
+        if new.hud.widgets.frame_times != old.hud.widgets.frame_times {
+            self.frame_times_visible = new.hud.widgets.frame_times;
+        }
+        if new.hud.widgets.legend != old.hud.widgets.legend
+            && new.hud.widgets.legend != self.legend.is_visible()
+        {
+            self.legend.toggle();
+        }
+        if new.hud.scale != old.hud.scale {
+            self.hud_scale_override = None;
+        }
+        if new.formatting != old.formatting {
+            self.formatting_override = None;
+        }
+        if new.profiles.active != old.profiles.active {
+            self.profile_override = None;
+        }
+        if new.modes.time_acceleration != old.modes.time_acceleration {
+            // Carries on from the time shown, instead of jumping to where the new speed would
+            // have got to since the start.
+            self.daylight_anchor = (self.daylight_time(), self.animation_clock.elapsed());
+        }
+        self.settings = settings;
+        self.settings_overlay.forget_pending();
+        if !self.settings.hud.visible {
+            self.close_hud_overlays();
+        }
+        let quality = self.quality().clone();
+        self.hud_budget.set_budget(quality.hud_budget_ms);
+        self.adaptive_quality.set_settings(quality);
+        self.bindings = bindings;
+        self.needs_redraw = true;
+        self.camera_controller.set_settings(self.settings.camera);
+        self.layer_profile();
+
+        info!("Reloaded settings from {}", self.settings_path.display());
+        if startup_only.is_empty() {
+            self.toast("Settings reloaded".to_string());
+        } else {
+            self.toast(format!("Settings reloaded. Restart to apply {}", startup_only.join(", ")));
+        }
+    }
+
+    /// How large to draw the HUD this frame.
+    pub fn hud_scale(&self) -> HudScale {
+        let factor = self.hud_scale_override
+            .unwrap_or(self.settings.hud.scale);
+
+        HudScale {
+            factor: f64::from(factor),
+            density: self.pixel_density,
+        }
+    }
+
+    /// How far in from the edges of a window `view_size` points large the HUD keeps this frame.
+    pub fn safe_area(&self, view_size: [f64; 2]) -> Insets {
+        // The HUD keeps to the part of the window the map is drawn in, not the bars around it.
+        let area = letterbox::area(self.locked_aspect(), view_size);
+        let bars = letterbox::insets(area, view_size);
+        let safe_area = self.profiled_settings.hud.safe_area;
+        let mut insets = safe_area.insets([area[2], area[3]], self.pixel_density);
+        for (inset, bar) in insets.iter_mut().zip(&bars) {
+            *inset += bar;
+        }
+        insets
+    }
+
+    /// How numbers and distances are written this frame.
+    pub fn formatting(&self) -> FormattingSettings {
+        self.formatting_override.unwrap_or(self.settings.formatting)
+    }
+
+    /// The profile in use, if any.
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile_override.or(self.settings.profiles.active)
+    }
+
+    /// Applies the map mode's adjustments, as when it is switched to: what was tweaked of the mode
+    /// before is forgotten, and its label policy applies.
+    pub fn adjust_to_mode(&mut self) {
+        self.mode_tweaks = ModeAdjustments::default();
+        self.settings_overlay.set_mode(self.map_mode);
+        self.layer_profile();
+        self.label_policy = self.mode_label_policy(&self.settings);
+    }
+
+    /// The map mode's label policy under `settings`, with what the mode's adjustments make it.
+    fn mode_label_policy(&self, settings: &Settings) -> LabelPolicy {
+        let adjusted = mode_adjustments::layered(settings, self.map_mode, self.mode_tweaks);
+        adjusted.modes.labels.of(self.map_mode)
+    }
+
+    /// Layers the map mode's adjustments and the profile in use over the settings again, now that
+    /// any of them may have changed.
+    fn layer_profile(&mut self) {
+        let adjusted = mode_adjustments::layered(&self.settings, self.map_mode, self.mode_tweaks);
+        self.profiled_settings = profile::layered(&adjusted, self.profile());
+        if let Some(running) = self.parameter_animation {
+            let animation = running.animation;
+            if let Some(value) = running.applied() {
+                let parameters = &mut self.profiled_settings.modes.parameters;
+                parameters.set(animation.mode, animation.parameter.name, value);
+            }
+        }
+        // The palette, opacity and mode parameters may have changed.
+        self.clear_colors();
+
+        let wrap = self.profiled_settings.labels.wrap;
+        if wrap != self.label_texts.wrap() {
+            self.label_texts.rewrap(self.features.place_names(), wrap);
+        }
+
+        let filter = self.settings.countries.filter;
+        if filter != self.features.filter() {
+            self.features.set_filter(filter);
+            self.filtered_percentiles.replace(None);
+        }
+    }
+
+    /// The quality settings in effect.
+    pub fn quality(&self) -> &QualitySettings {
+        self.quality_override
+            .as_ref()
+            .unwrap_or(&self.settings.quality)
+    }
+
+    /// Where the camera is, to be restored next time.
+    pub fn session(&self) -> Session {
+        let camera = &self.camera_controller;
+
+        Session {
+            latitude: camera.latitude(),
+            longitude: camera.longitude(),
+            height: camera.camera_height(),
+            heading: camera.heading().to_degrees(),
+            routes: self.routes.clone(),
+            window: None,
+            clocks: self.world_clocks.clocks().to_vec(),
+        }
+    }
+
+    /// Copies everything known about the selected country to the clipboard, as JSON.
+    fn copy_selected(&mut self) {
+        let selected = match self.selected_polygon {
+            Some(selected) => selected,
+            None => {
+                self.toast("Select a country to copy".to_string());
+                return;
+            }
+        };
+        let country = self.features.country(selected);
+        let name = country.name.clone();
+
+        let properties = self.features.properties(selected);
+        let geo = self.geo_index.get(&country.id);
+        let copied = export::country_json(properties, country, geo, self.map_mode)
+            .and_then(|json| {
+                let len = json.len();
+                self.clipboard.set_text(json).map(|_| len)
+            });
+        match copied {
+            Ok(len) => self.toast(format!("Copied {} as JSON ({} bytes)", name, len)),
+            Err(e) => {
+                error!("{}", e);
+                self.toast(format!("Could not copy {}: {}", name, e));
+            }
+        }
+    }
+
+    /// Opens the selected country's web page in the browser, unless `links.enabled` is off.
+    fn open_selected_link(&mut self) {
+        if !self.settings.links.enabled {
+            self.toast("Opening web pages is switched off in links.enabled".to_string());
+            return;
+        }
+        let selected = match self.selected_polygon {
+            Some(selected) => selected,
+            None => {
+                self.toast("Select a country to open its web page".to_string());
+                return;
+            }
+        };
+        let name = self.features.country(selected).admin.clone();
+
+        let properties = self.features.properties(selected);
+        let opened = links::url(properties, &name, &self.settings.links)
+            .and_then(|url| self.browser.open(&url).map(|_| url));
+        match opened {
+            Ok(url) => self.toast(format!("Opening {} in the browser", url)),
+            Err(e) => {
+                warn!("{}", e);
+                self.toast(e.to_string());
+            }
+        }
+    }
+
+    /// Everything a named session saves about how the map is being looked at.
+    fn named_session(&self) -> NamedSession {
+        let mut widgets = self.settings.hud.widgets;
+        widgets.frame_times = self.frame_times_visible;
+        widgets.legend = self.legend.is_visible();
+        widgets.crosshair = self.crosshair_enabled;
+        widgets.graticule = self.graticule_enabled;
+        widgets.overview = self.overview_enabled;
+
+        NamedSession {
+            camera: self.session(),
+            mode: self.map_mode,
+            hidden_categories: self.legend.hidden_categories(),
+            selected: self
+                .selected_polygon
+                .map(|polygon| self.features.country(polygon).id.clone()),
+            labels_enabled: self.labels_shown() != LabelsShown::None,
+            labels: self.settings.labels,
+            hud_visible: self.settings.hud.visible,
+            widgets,
+        }
+    }
+
+    fn save_named_session(&mut self, name: &str) {
+        match self.named_session().save(&self.sessions_dir, name) {
+            Ok(path) => {
+                info!("Saved session {:?} to {}", name, path.display());
+                self.toast(format!("Saved session {}", name));
+            }
+            Err(e) => {
+                error!("{}", e);
+                self.toast(format!("Could not save session {}: {}", name, e));
+            }
+        }
+    }
+
+    /// Loads the named session `name`, flying the camera to it if `animate` is set and jumping
+    /// there otherwise. Whatever in it cannot be applied, such as a country that is no longer in
+    /// the data, is left as it is, with a warning.
+    fn load_named_session(&mut self, name: &str, animate: bool) {
+        if let Err(e) = self.try_load_named_session(name, animate) {
+            error!("{}", e);
+            self.toast(format!("Could not load session {}: {}", name, e));
+        }
+    }
+
+    pub fn try_load_named_session(&mut self, name: &str, animate: bool) -> Result<()> {
+        let current = self.named_session();
+        let (session, mut warnings) = NamedSession::load(&self.sessions_dir, name, &current)?;
+
+        let camera = session.camera;
+        if animate {
+            self.camera_controller
+                .fly_to(camera.latitude, camera.longitude, camera.height, camera.heading);
+        } else {
+            self.camera_controller
+                .move_to(camera.latitude, camera.longitude, camera.height);
+            self.camera_controller.set_heading(camera.heading);
+        }
+        self.routes = camera.routes;
+        self.set_world_clocks(WorldClocks::new(camera.clocks));
+        self.route_start = None;
+        self.selected_route = self.routes.len().checked_sub(1);
+        self.journey = None;
+
+        if session.mode != self.map_mode {
+            self.run_stats.mode_switched();
+            self.map_mode = session.mode;
+            self.adjust_to_mode();
+        }
+        self.parameter_index = 0;
+        let num_entries = self.map_mode.legend_entries().len();
+        self.legend
+            .set_hidden_categories(&session.hidden_categories, num_entries);
+        self.color_cache.borrow_mut().clear();
+
+        self.selected_polygon = match session.selected {
+            Some(ref id) => match self.find_country(id) {
+                Ok(polygon) => Some(polygon),
+                Err(closest) => {
+                    warnings.push(format!(
+                        "Ignoring selected country {:?}, not in the data{}",
+                        id, closest
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        self.labels_enabled = session.labels_enabled;
+        self.label_policy = LabelPolicy::Inherit;
+        self.frame_times_visible = session.widgets.frame_times;
+        self.legend.set_visible(session.widgets.legend);
+        self.crosshair_enabled = session.widgets.crosshair;
+        self.graticule_enabled = session.widgets.graticule;
+        self.overview_enabled = session.widgets.overview;
+
+        // The widgets toggled with a key are saved only as they start out, so they are kept.
+        let mut widgets = session.widgets;
+        let saved = self.settings.hud.widgets;
+        widgets.frame_times = saved.frame_times;
+        widgets.legend = saved.legend;
+        widgets.crosshair = saved.crosshair;
+        widgets.graticule = saved.graticule;
+        widgets.overview = saved.overview;
+        if (session.labels, session.hud_visible, widgets)
+            != (self.settings.labels, self.settings.hud.visible, saved)
+        {
+            if !session.hud_visible {
+                self.close_hud_overlays();
+            }
+            self.settings.labels = session.labels;
+            self.settings.hud.visible = session.hud_visible;
+            self.settings.hud.widgets = widgets;
+            self.settings = self.settings.clone().validated();
+            self.apply_settings();
+        }
+        self.needs_redraw = true;
+
+        for warning in &warnings {
+            warn!("Session {:?}: {}", name, warning);
+        }
+        info!("Loaded session {:?}", name);
+        if warnings.is_empty() {
+            self.toast(format!("Loaded session {}", name));
+        } else {
+            self.toast(format!(
+                "Loaded session {}, with {} warnings (see the log)",
+                name,
+                warnings.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The current map mode, with the key that switches to it, and toggles, as shown in the
+    /// top-right of the HUD.
+    pub fn status_text(&self) -> String {
+        let action = bindings::show_mode(self.map_mode);
+        let name = self.map_mode.name();
+        let mode = match self.bindings.iter().find(|binding| binding.action == action) {
+            Some(binding) => format!("{} ({})", name, bindings::key_name(binding.key)),
+            None => name.to_string(),
+        };
+        format!("Mode: {} - Labels: {}", mode, self.labels_shown().name())
+    }
+
+    /// Where the camera is looking, how high it is, and which level of detail that calls for.
+    /// Fields are padded to a fixed width so the text doesn't jitter as the values change.
+    pub fn position_text(&self) -> String {
+        let latitude = self.camera_controller.latitude();
+        let longitude = self.camera_controller.longitude();
+        let height = self.camera_controller.camera_height();
+        let format = self.formatting();
+
+        format!(
+            "{:>5}\u{b0}{} {:>6}\u{b0}{} - Height: {} - Level: {}",
+            formatting::number(f64::from(latitude.abs()), 2, format),
+            if latitude < 0.0 { 'S' } else { 'N' },
+            formatting::number(f64::from(longitude.abs()), 2, format),
+            if longitude < 0.0 { 'W' } else { 'E' },
+            formatting::number(f64::from(height), 3, format),
+            self.desired_level(height),
+        )
+    }
+
+    /// The coordinates under the exact center of the window, or a dash if the center of the
+    /// window is not over the map.
+    pub fn crosshair_text(&self) -> String {
+        let [x, y, width, height] = self.map_area();
+        self.coordinates_text(self.map_point_at([x + width / 2.0, y + height / 2.0]))
+    }
+
+    /// The coordinates under the cursor, or a dash if it is not over the map.
+    pub fn cursor_coordinates_text(&self) -> String {
+        self.coordinates_text(self.cursor_point.map_point())
+    }
+
+    fn coordinates_text(&self, point: Option<[f32; 2]>) -> String {
+        match point {
+            Some(point) => {
+                let (latitude, longitude) = coordinates::from_map_point(point);
+                let format = self.settings.hud.coordinate_format;
+                coordinates::format(latitude, longitude, format, self.formatting())
+            }
+            None => "\u{2014}".to_string(),
+        }
+    }
+
+    /// Finds the point under the cursor again, if the cursor or the camera moved, when the
+    /// cursor coordinates are shown.
+    fn update_cursor_point(&mut self) {
+        if !self.settings.hud.widgets.cursor_coordinates {
+            return;
+        }
+        if !letterbox::contains(self.map_area(), self.cursor) {
+            self.cursor_point = CursorPoint::default();
+            return;
+        }
+
+        let mvp = self.window_mvp(self.view_at(self.cursor));
+        self.cursor_point.update(mvp, self.cursor, self.view_size);
+    }
+
+    /// The time the map shows, which is the animation clock's unless `--clock` gave another.
+    fn now(&self) -> SystemTime {
+        self.clock.unwrap_or_else(|| self.animation_clock.now())
+    }
+
+    /// The time day and night mode shows, which runs `modes.time_acceleration` times faster than
+    /// the animation clock, unless `--clock` fixed it.
+    fn daylight_time(&self) -> SystemTime {
+        if let Some(clock) = self.clock {
+            return clock;
+        }
+
+        let (time, since) = self.daylight_anchor;
+        let acceleration = f64::from(self.settings.modes.time_acceleration);
+        let elapsed = self.animation_clock.elapsed().saturating_sub(since);
+        time + Duration::from_secs_f64(elapsed.as_secs_f64() * acceleration)
+    }
+
+    /// The time the map mode shows: `daylight_time` in day and night mode, and `now` otherwise.
+    fn map_time(&self) -> SystemTime {
+        if self.map_mode == MapMode::Daylight {
+            self.daylight_time()
+        } else {
+            self.now()
+        }
+    }
+
+    /// A line for each pinned clock, such as "Cairo: ~14:04".
+    pub fn world_clock_lines(&self) -> Vec<String> {
+        self.world_clocks.lines(self.now(), self.settings.hud.time_format)
+    }
+
+    /// Replaces the pinned clocks, as a session is restored.
+    pub fn set_world_clocks(&mut self, clocks: WorldClocks) {
+        self.world_clocks = clocks;
+        self.settings_overlay.set_clocks(self.world_clocks.clocks());
+        self.needs_redraw = true;
+    }
+
+    /// Pins a clock at the capital the tour is at, or that of the selected country, or unpins
+    /// it if one is.
+    fn pin_clock(&mut self) {
+        let capital = match self.toured_capital().or_else(|| self.selected_capital()) {
+            Some(capital) => capital.clone(),
+            None => {
+                self.toast("Select a country with a capital to pin its clock".to_string());
+                return;
+            }
+        };
+
+        let message = match self.world_clocks.toggle(&capital) {
+            Toggled::Pinned => format!("Pinned a clock at {}", capital.name),
+            Toggled::Unpinned => format!("Unpinned the clock at {}", capital.name),
+            Toggled::Full => format!(
+                "Up to {} clocks can be pinned; unpin one first",
+                world_clock::MAX_PINNED
+            ),
+        };
+        self.settings_overlay.set_clocks(self.world_clocks.clocks());
+        self.needs_redraw = true;
+        self.toast(message);
+    }
+
+    fn unpin_clock(&mut self, index: usize) {
+        if let Some(clock) = self.world_clocks.remove(index) {
+            self.settings_overlay.set_clocks(self.world_clocks.clocks());
+            self.needs_redraw = true;
+            self.toast(format!("Unpinned the clock at {}", clock.name));
+        }
+    }
+
+    /// The capital of the selected country, if one is selected and the places data has it.
+    fn selected_capital(&self) -> Option<&Capital> {
+        let country = self.features.country(self.selected_polygon?);
+        self.features.capitals().iter().find(|capital| {
+            !capital.country.is_empty()
+                && (capital.country == country.admin || capital.country == country.name)
+        })
+    }
+
+    /// The approximate local solar time where the camera is looking, and whether the sun is up
+    /// there.
+    pub fn solar_time_text(&self) -> (Icon, String) {
+        let now = self.map_time().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let unix_time = now.as_secs() as f64 + f64::from(now.subsec_nanos()) * 1e-9;
+        let latitude = f64::from(self.camera_controller.latitude());
+        let longitude = f64::from(self.camera_controller.longitude());
+
+        let icon = if astro::solar_elevation(unix_time, latitude, longitude) > 0.0 {
+            Icon::Sun
+        } else {
+            Icon::Moon
+        };
+        let (hours, minutes) = astro::local_solar_time(unix_time, longitude);
+        let time = self.settings.hud.time_format.format(hours, minutes);
+
+        (icon, format!("Solar time: {}", time))
+    }
+
+    /// Logs the frame that just ended if it took too long.
+    pub fn check_for_spike(&mut self) {
+        let phases = match self.frame_times.last_phases() {
+            Some(phases) => phases,
+            None => return,
+        };
+
+        let threshold = self.settings.diagnostics.spike_threshold_ms;
+        let camera = &self.camera_controller;
+        let level = self.desired_level(camera.camera_height());
+        let mode = self.map_mode.name();
+        self.spike_log.frame(threshold, phases, || Snapshot {
+            camera: [
+                camera.latitude(),
+                camera.longitude(),
+                camera.camera_height(),
+                camera.heading(),
+            ],
+            level,
+            mode: mode.to_string(),
+        });
+    }
+
+    /// Briefly shows `message` at the bottom of the window.
+    pub fn toast(&mut self, message: String) {
+        self.toasts.push(message, toasts::DEFAULT_DURATION);
+    }
+
+    /// Shows the first of `findings` that is required, if any, and how many more there are.
+    pub fn toast_findings(&mut self, findings: &[Finding]) {
+        let required = findings.iter().filter(|finding| finding.required).count();
+        if let Some(finding) = findings.iter().find(|finding| finding.required) {
+            let more = if required > 1 {
+                format!(", and {} more findings in the log", required - 1)
+            } else {
+                String::new()
+            };
+            self.toast(format!("{}{}", finding.summary(), more));
+        }
+    }
+
+    /// Replaces the countries with `features`, from another asset set. The selection is kept if
+    /// the country is in both, and tiles are loaded again around the camera.
+    pub fn replace_features(&mut self, features: Features) {
+        let selected = self.selected_polygon.map(|polygon| {
+            let country = self.features.country(polygon);
+            (country.id.clone(), country.admin.clone())
+        });
+
+        self.geo_index = GeoIndex::new(features.polygons());
+        let wrap = self.label_texts.wrap();
+        self.label_texts.rewrap(features.place_names(), wrap);
+        self.features = features.filtered(self.settings.countries.filter);
+        self.update_label_fallbacks();
+        // Asset sets generated from other Natural Earth releases or scales may give the country
+        // another ID, but it can still be found by its name.
+        self.selected_polygon = selected.and_then(|(id, admin)| {
+            self.find_country(&id).or_else(|_| self.find_country(&admin)).ok()
+        });
+        self.percentiles.replace(None);
+        self.filtered_percentiles.replace(None);
+        if let Some(ref script) = self.color_script {
+            script.forget_countries();
+        }
+        self.clear_colors();
+        self.tile_loading = TileLoading::new();
+        self.needs_redraw = true;
+    }
+
+    /// The level of detail to draw at, capped while frames are too slow.
+    pub fn desired_level(&self, camera_height: f32) -> u8 {
+        self.adaptive_quality
+            .cap(self.quality().level_for_height(camera_height))
+    }
+
+    /// The level of detail drawn, and the most detailed allowed, as shown in the overlays.
+    /// Keeps the camera clear of the terrain under the point it looks at, `dt` seconds after
+    /// it last was.
+    fn update_terrain_floor(&mut self, dt: f64) {
+        let camera = &mut self.camera_controller;
+        let terrain = &self.settings.terrain;
+        let (height, region) = terrain_clearance::terrain_height(
+            &terrain.regions,
+            camera.latitude(),
+            camera.longitude(),
+        );
+        let height = self.terrain_filter.update(height, dt as f32);
+
+        camera.set_floor(height + terrain.clearance);
+        self.terrain_region = region.map(|region| region.name.clone());
+    }
+
+    /// Such as "Terrain clearance: holding the camera at 0.081 over the Himalayas", for the frame
+    /// times overlay.
+    pub fn terrain_status(&self) -> String {
+        let camera = &self.camera_controller;
+        if !camera.is_held_up() {
+            return "Terrain clearance: not needed".to_string();
+        }
+
+        let mut status =
+            format!("Terrain clearance: holding the camera at {:.3}", camera.camera_height());
+        if let Some(ref region) = self.terrain_region {
+            status.push_str(&format!(" over {}", region));
+        }
+
+        status
+    }
+
+    pub fn level_status(&self) -> String {
+        let height = self.camera_controller.camera_height();
+        let quality = self.quality();
+
+        let mut status = format!(
+            "Level: {} (max {})",
+            self.desired_level(height),
+            quality.max_level
+        );
+        if quality.threshold_level(height) > quality.max_level {
+            status.push_str(", limited by max_level");
+        }
+
+        status
+    }
+
+    /// The parts of the window the map is drawn in: the whole of it, or both halves while it is
+    /// split.
+    pub fn views(&self) -> &'static [View] {
+        if self.split.is_some() {
+            &View::HALVES
+        } else {
+            &[View::Whole]
+        }
+    }
+
+    /// The view `cursor` is over.
+    pub fn view_at(&self, cursor: [f64; 2]) -> View {
+        View::under(cursor, self.map_area(), self.split.is_some())
+    }
+
+    /// The aspect ratio the map is letterboxed to, if it is.
+    pub fn locked_aspect(&self) -> Option<AspectLock> {
+        self.aspect_lock.filter(|_| self.aspect_locked)
+    }
+
+    /// The part of the window the map is drawn in, as `letterbox::area` gives it: all of it,
+    /// unless its aspect ratio is locked.
+    pub fn map_area(&self) -> [f64; 4] {
+        letterbox::area(self.locked_aspect(), self.view_size)
+    }
+
+    /// Builds the projection again for a window `draw_size` pixels across, if that or the
+    /// locked aspect ratio changed the size of the map.
+    pub fn resize_projection(&mut self, draw_size: Size) {
+        self.projection
+            .resize(letterbox::draw_size(self.locked_aspect(), draw_size));
+    }
+
+    /// Letterboxes the map to `--lock-aspect`, or stops letterboxing it.
+    fn toggle_aspect_lock(&mut self) {
+        let lock = match self.aspect_lock {
+            Some(lock) => lock,
+            None => {
+                self.toast("Start with --lock-aspect W:H to letterbox the map".to_string());
+                return;
+            }
+        };
+
+        self.aspect_locked = !self.aspect_locked;
+        self.toast(if self.aspect_locked {
+            format!("Aspect ratio locked to {}", lock)
+        } else {
+            "Aspect ratio unlocked".to_string()
+        });
+    }
+
+    /// The map mode drawn in `view`, and the legend whose categories it shows.
+    fn mode_in(&self, view: View) -> (MapMode, &Legend) {
+        match (view, self.split.as_ref()) {
+            (View::Right, Some(split)) => (split.mode, &split.legend),
+            _ => (self.map_mode, &self.legend),
+        }
+    }
+
+    /// Which labels are drawn, from the labels switch and the map mode's label policy.
+    pub fn labels_shown(&self) -> LabelsShown {
+        label_policy::resolve(self.labels_enabled, self.label_policy)
+    }
+
+    /// Whether labels are drawn in `view`.
+    pub fn labels_in(&self, view: View) -> bool {
+        match (view, self.settings.split.labels) {
+            (View::Left, SplitLabels::Right) | (View::Right, SplitLabels::Left) => false,
+            _ => self.labels_shown() != LabelsShown::None,
+        }
+    }
+
+    /// The matrix to draw the map in `view` with, to a target covering just that view.
+    pub fn mvp_in(&self, view: View) -> Matrix4<f32> {
+        self.projection.matrix_in(view) * self.camera_controller.view_matrix()
+    }
+
+    /// The matrix to draw the map with for the eye `offset` along the camera's right, in red and
+    /// cyan 3D.
+    pub fn eye_mvp(&self, offset: f32) -> Matrix4<f32> {
+        let convergence = self.settings.stereo.convergence;
+
+        self.projection.matrix_in(View::Whole)
+            * self.camera_controller.eye_view_matrix(offset, convergence)
+    }
+
+    /// The matrix from the map to the whole window's clip space, through `view`, for what the 2D
+    /// pass draws over the map and for finding what is under the cursor.
+    pub fn window_mvp(&self, view: View) -> Matrix4<f32> {
+        self.area_matrix() * view.window_matrix() * self.mvp_in(view)
+    }
+
+    /// Maps the clip space of the part of the window the map is drawn in onto the window's.
+    fn area_matrix(&self) -> Matrix4<f32> {
+        letterbox::window_matrix(self.map_area(), self.view_size)
+    }
+
+    /// The outline of the selected country in `view`, as lines of the 2D pass `view_size`
+    /// across, at the level of detail the map is drawn at. Empty if nothing is selected.
+    pub fn selected_outline(&self, view: View, view_size: [f64; 2]) -> Vec<Vec<[f64; 2]>> {
+        let selected = match self.selected_polygon {
+            Some(selected) => selected,
+            None => return vec![],
+        };
+
+        let id = features::feature_id(self.features.properties(selected));
+        let level = self.desired_level(self.camera_controller.camera_height());
+        let rings = geo_index::country_rings(self.features.polygons(), id, level);
+        let look_at = self.camera_controller.look_at();
+        outline::project(&rings, self.window_mvp(view), look_at, view_size)
+    }
+
+    /// Like `window_mvp`, but with only the camera's rotation, for directions rather than points,
+    /// such as those of the stars.
+    pub fn sky_mvp(&self, view: View) -> Matrix4<f32> {
+        let mut rotation = self.camera_controller.view_matrix();
+        rotation.w = Vector4::unit_w();
+
+        self.area_matrix() * view.window_matrix() * self.projection.matrix_in(view) * rotation
+    }
+
+    /// The point on the map under `cursor`, in map space, or `None` if the cursor is above the
+    /// horizon or past a pole. Terrain elevation is ignored.
+    fn map_point_at(&self, cursor: [f64; 2]) -> Option<[f32; 2]> {
+        if !letterbox::contains(self.map_area(), cursor) {
+            return None;
+        }
+
+        let inverse = self.window_mvp(self.view_at(cursor)).invert()?;
+        cursor_point::ground_at(inverse, cursor, self.view_size).map(cursor_point::map_point)
+    }
+
+    /// The name of the country under the cursor, if the country tooltip is on and the camera is
+    /// not being dragged.
+    pub fn hovered_country(&self) -> Option<&str> {
+        if !self.settings.hud.widgets.country_tooltip || self.camera_controller.is_rotating() {
+            return None;
+        }
+
+        let polygon = self.map_point_at(self.cursor)
+            .and_then(|point| self.features.polygon_at(point))?;
+        let country = self.features.country(polygon);
+        Some(if country.name.is_empty() { &country.admin } else { &country.name })
+    }
+
+    /// Starts a route at the point on the map under `cursor`, or ends the one started there.
+    /// Clicking a route instead of starting one selects it.
+    fn pick_route_endpoint(&mut self, cursor: [f64; 2]) {
+        if self.route_start.is_none() {
+            if let Some(index) = self.route_at(cursor) {
+                self.selected_route = Some(index);
+                let distance = self.route_distance(&self.routes[index]);
+                self.toast(format!("Selected a {} route. P plays it", distance));
+                return;
+            }
+        }
+
+        let (latitude, longitude) = match self.map_point_at(cursor) {
+            Some(point) => coordinates::from_map_point(point),
+            None => return,
+        };
+
+        match self.route_start.take() {
+            Some(from) => {
+                let route = Route {
+                    from,
+                    to: [latitude, longitude],
+                };
+                self.toast(format!("Route: {}", self.route_distance(&route)));
+                self.routes.push(route);
+                self.selected_route = Some(self.routes.len() - 1);
+            }
+            None => self.route_start = Some([latitude, longitude]),
+        }
+    }
+
+    /// Deletes the route with index `index`, along with the marker travelling along it.
+    fn delete_route(&mut self, index: usize) {
+        let route = self.routes.remove(index);
+        self.toast(format!("Deleted a {} route", self.route_distance(&route)));
+
+        // Later routes move down one.
+        let shifted = |other: usize| match other {
+            other if other == index => None,
+            other if other > index => Some(other - 1),
+            other => Some(other),
+        };
+        self.selected_route = self.selected_route.and_then(shifted);
+        self.journey = self.journey.and_then(|journey| {
+            shifted(journey.route).map(|route| Journey { route, ..journey })
+        });
+    }
+
+    /// Pauses or resumes the marker on the selected route, or starts it over if it arrived or
+    /// is on another route.
+    fn play_route(&mut self) {
+        let selected = match self.selected_route {
+            Some(selected) => selected,
+            None => {
+                self.toast("Draw a route with the route tool (R) to play it".to_string());
+                return;
+            }
+        };
+
+        match self.journey {
+            Some(ref mut journey)
+                if journey.route == selected && !journey.is_finished(&self.routes[selected]) =>
+            {
+                journey.paused = !journey.paused;
+            }
+            _ => {
+                self.journey = Some(Journey::new(selected));
+                self.camera_controller.take_interrupted();
+            }
+        }
+
+        let paused = self.journey.map_or(false, |journey| journey.paused);
+        let (km_per_unit, unit) = formatting::distance_unit(self.formatting().units);
+        let speed = f64::from(self.settings.routes.speed_kmh) / km_per_unit;
+        self.toast(if paused {
+            "Paused".to_string()
+        } else {
+            format!("Playing at {} {}/h", formatting::number(speed, 0, self.formatting()), unit)
+        });
+    }
+
+    /// Moves whatever runs by the animation clock on by `dt` seconds. The animated map modes and
+    /// day and night mode read the clock themselves.
+    fn advance_animations(&mut self, dt: f64) {
+        self.update_journey(dt);
+        self.update_tour(dt);
+        self.update_parameter_animation();
+    }
+
+    /// Moves the marker along its route by `dt` seconds, with the camera following it in flight
+    /// mode until something else moves the camera.
+    fn update_journey(&mut self, dt: f64) {
+        let mut journey = match self.journey {
+            Some(journey) => journey,
+            None => return,
+        };
+        let route = self.routes[journey.route];
+
+        let speed = f64::from(self.settings.routes.speed_kmh);
+        if journey.advance(&route, speed, dt) {
+            self.toast(format!("Arrived after {}", self.route_distance(&route)));
+        }
+        self.journey = Some(journey);
+
+        if !self.following {
+            return;
+        }
+        if self.camera_controller.take_interrupted() {
+            // The camera is left where it is, so that it does not jump on being let go.
+            self.following = false;
+            self.toast("Flight mode off".to_string());
+            return;
+        }
+
+        let [latitude, longitude] = route.point_at_km(journey.travelled_km);
+        self.camera_controller.follow(
+            latitude as f32,
+            longitude as f32,
+            self.settings.routes.follow_height,
+            route.heading_at_km(journey.travelled_km) as f32,
+            dt as f32,
+        );
+    }
+
+    /// Starts the tour at the first capital east of where the camera looks, or stops it.
+    fn toggle_tour(&mut self) {
+        if self.tour.take().is_some() {
+            self.toast("Tour stopped".to_string());
+            return;
+        }
+
+        let capitals = self.features.capitals();
+        if capitals.is_empty() {
+            self.toast("There are no capitals in the places data".to_string());
+            return;
+        }
+        let x = (self.camera_controller.look_at()[0] / 2.0).rem_euclid(1.0);
+        let stop = capitals
+            .iter()
+            .position(|capital| capital.point[0] >= x)
+            .unwrap_or(0);
+
+        self.journey = None;
+        self.following = false;
+        self.tour = Some(Tour::new(stop));
+        self.fly_to_capital(stop);
+    }
+
+    /// Skips `steps` capitals forward or back on the tour, wrapping around either end.
+    fn skip_capitals(&mut self, steps: isize) {
+        let len = self.features.capitals().len() as isize;
+        let stop = match self.tour {
+            Some(ref mut tour) => {
+                let stop = (tour.stop as isize + steps).rem_euclid(len) as usize;
+                tour.go_to(stop);
+                stop
+            }
+            None => return,
+        };
+
+        self.fly_to_capital(stop);
+    }
+
+    fn fly_to_capital(&mut self, stop: usize) {
+        let point = self.features.capitals()[stop].point;
+        let (latitude, longitude) = coordinates::from_map_point(point);
+        let height = self.settings.tour.height;
+        self.camera_controller
+            .fly_to(latitude as f32, longitude as f32, height, 0.0);
+    }
+
+    /// Moves the tour on by `dt` seconds. It waits while help is shown, so that no stop is missed
+    /// reading it.
+    fn update_tour(&mut self, dt: f64) {
+        if self.help_overlay.is_visible() {
+            return;
+        }
+        let flying = self.camera_controller.is_flying();
+        let dwell_secs = f64::from(self.settings.tour.dwell_secs);
+        let len = self.features.capitals().len();
+        let (step, stop, next) = match self.tour {
+            Some(ref mut tour) => {
+                let step = tour.update(dt, flying, dwell_secs);
+                (step, tour.stop, tour.next_stop(len))
+            }
+            None => return,
+        };
+
+        match step {
+            TourStep::Stay => {}
+            TourStep::Arrived => {
+                let point = self.features.capitals()[stop].point;
+                self.selected_polygon = self.features.polygon_at(point);
+                self.pulse_selected();
+                self.needs_redraw = true;
+            }
+            TourStep::Leave => match next {
+                Some(next) => {
+                    if let Some(ref mut tour) = self.tour {
+                        tour.go_to(next);
+                    }
+                    self.fly_to_capital(next);
+                }
+                None => {
+                    self.tour = None;
+                    self.toast("Tour finished".to_string());
+                }
+            },
+        }
+    }
+
+    /// The capital the tour is at, once the camera has got there.
+    fn toured_capital(&self) -> Option<&Capital> {
+        self.tour
+            .filter(Tour::is_dwelling)
+            .map(|tour| &self.features.capitals()[tour.stop])
+    }
+
+    /// Which capital the tour is at, or on its way to, as in "Capital 37/195: Nairobi, Kenya".
+    pub fn tour_text(&self) -> Option<String> {
+        let tour = self.tour?;
+        let capitals = self.features.capitals();
+        let capital = &capitals[tour.stop];
+        let place = if capital.country.is_empty() {
+            capital.name.clone()
+        } else {
+            format!("{}, {}", capital.name, capital.country)
+        };
+
+        Some(format!("Capital {}/{}: {}", tour.stop + 1, capitals.len(), place))
+    }
+
+    /// How far the marker has travelled along its route and has to go, for the HUD.
+    pub fn journey_text(&self) -> Option<String> {
+        let journey = self.journey?;
+        Some(journey.status(&self.routes[journey.route], self.formatting()))
+    }
+
+    /// The length of `route`, as it is labeled on the map.
+    fn route_distance(&self, route: &Route) -> String {
+        formatting::distance(route.distance_km(), 0, self.formatting())
+    }
+
+    /// Where each route is drawn in `view` this frame.
+    pub fn projected_routes(&self, view: View) -> Vec<routes::ProjectedRoute> {
+        routes::project(
+            &self.routes,
+            self.window_mvp(view),
+            self.camera_controller.look_at(),
+            self.camera_controller.camera_height(),
+            self.view_size,
+            self.formatting(),
+        )
+    }
+
+    /// The index of the route under `cursor`, if any.
+    fn route_at(&self, cursor: [f64; 2]) -> Option<usize> {
+        routes::hovered(&self.projected_routes(self.view_at(cursor)), cursor)
+    }
+
+    /// Whether the overview inset is drawn this frame, given whether the HUD is.
+    pub fn overview_shown(&self, hud_visible: bool) -> bool {
+        self.overview_enabled
+            && hud_visible
+            && self.hud_budget.levels().of(Widget::Overview) == 0
+            && self.camera_controller.camera_height() <= overview::MAX_CAMERA_HEIGHT
+    }
+
+    /// The outline of the part of the map the window shows, in world space, as points along the
+    /// window's edges. Points above the horizon are left out.
+    pub fn view_footprint(&self) -> Vec<[f32; 2]> {
+        const STEPS: usize = 8;
+        let [x, y, width, height] = self.map_area();
+        let along = |i: usize| i as f64 / STEPS as f64;
+
+        let top = (0..STEPS).map(|i| [x + along(i) * width, y]);
+        let right = (0..STEPS).map(|i| [x + width, y + along(i) * height]);
+        let bottom = (0..STEPS).map(|i| [x + (1.0 - along(i)) * width, y + height]);
+        let left = (0..STEPS).map(|i| [x, y + (1.0 - along(i)) * height]);
+        top.chain(right)
+            .chain(bottom)
+            .chain(left)
+            .filter_map(|cursor| self.map_point_at(cursor))
+            .map(|[x, y]| [2.0 * x, y])
+            .collect()
+    }
+
+    /// A polygon of the country `name` names, from a session or another asset set. How it was
+    /// matched is logged, unless it was by its ID. If nothing matched, returns the closest
+    /// country, if any, to tell alongside.
+    fn find_country(&self, name: &str) -> ::std::result::Result<usize, String> {
+        match self.features.find_country(name) {
+            CountryMatch::Found {
+                index,
+                strategy: Strategy::Id,
+            } => Ok(index),
+            CountryMatch::Found { index, strategy } => {
+                let admin = &self.features.country(index).admin;
+                info!("Matched the country {:?} to {} by {}", name, admin, strategy);
+                Ok(index)
+            }
+            CountryMatch::Unmatched {
+                closest: Some((index, confidence)),
+            } => Err(format!(
+                " (the closest is {}, {:.0}% alike)",
+                self.features.country(index).admin,
+                100.0 * confidence
+            )),
+            CountryMatch::Unmatched { closest: None } => Err(String::new()),
+        }
+    }
+
+    /// Selects the country under `cursor`, or clears the selection if there is none.
+    fn select_at(&mut self, cursor: [f64; 2]) {
+        self.selected_polygon = self.map_point_at(cursor)
+            .and_then(|point| self.features.polygon_at(point));
+        self.pulse_selected();
+    }
+
+    /// Briefly lights up the selected country, if there is one, to show which it is.
+    fn pulse_selected(&mut self) {
+        let selected = match self.selected_polygon {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        if let Some(pulse) = self.selection_pulse.take() {
+            self.effects.remove(pulse);
+        }
+        let id = &self.features.country(selected).id;
+        let now = self.animation_clock.elapsed();
+        self.selection_pulse = Some(self.effects.add(id, SELECTION_PULSE, now));
+    }
+
+    /// Selects the country `steps` on from the selected one, in alphabetical order.
+    /// Everything the command palette lists: every action, then every map mode shown, then every
+    /// country, each with the key or chord that does the same, if there is one.
+    fn palette_entries(&self) -> Vec<PaletteEntry> {
+        let keys = |action| {
+            bindings::key_for(&self.bindings, action)
+                .map(bindings::key_name)
+                .or_else(|| bindings::chord_for(action).map(str::to_string))
+        };
+        let actions = bindings::PALETTE_ACTIONS.iter().map(|&action| PaletteEntry {
+            label: action.description().to_string(),
+            alias: bindings::action_name(action).map(|name| name.replace('_', " ")),
+            keys: keys(action),
+            command: PaletteCommand::Run(action),
+        });
+        let modes = self.settings.modes.shown().into_iter().map(|mode| PaletteEntry {
+            label: format!("Mode: {}", mode.name()),
+            alias: None,
+            keys: keys(bindings::show_mode(mode)),
+            command: PaletteCommand::ShowMode(mode),
+        });
+        let countries = self.features.alphabetical().iter().map(|&polygon| PaletteEntry {
+            label: format!("Country: {}", self.features.country(polygon).admin),
+            alias: None,
+            keys: None,
+            command: PaletteCommand::SelectCountry(polygon),
+        });
+
+        actions.chain(modes).chain(countries).collect()
+    }
+
+    /// Does what `command`, picked in the command palette, says to.
+    fn run_palette_command(&mut self, command: PaletteCommand) {
+        match command {
+            PaletteCommand::Run(action) => self.handle_action(action),
+            PaletteCommand::ShowMode(mode) => self.handle_action(bindings::show_mode(mode)),
+            PaletteCommand::SelectCountry(polygon) => {
+                self.selected_polygon = Some(polygon);
+                self.frame_selected();
+            }
+        }
+    }
+
+    fn step_selection(&mut self, steps: isize) {
+        // Countries made of several polygons are gone through once, by one of them.
+        let current = self.selected_polygon
+            .and_then(|selected| self.features.polygon_of(&self.features.country(selected).id));
+        self.selected_polygon = country_cycle::step(self.features.alphabetical(), current, steps);
+
+        if let Some(selected) = self.selected_polygon {
+            let name = self.features.country(selected).admin.clone();
+            self.toasts.progress(name);
+        }
+    }
+
+    /// Flies to where the whole of the selected country's bounding box is in view, facing north.
+    fn frame_selected(&mut self) {
+        let selected = match self.selected_polygon {
+            Some(selected) => selected,
+            None => return,
+        };
+        let [(min_x, max_x), (min_y, max_y)] = self.features.polygons()[selected].bounding_box;
+        let center = [(min_x + max_x) / 2.0, (min_y + max_y) / 2.0];
+        let (latitude, longitude) = coordinates::from_map_point(center);
+
+        let view = self.views()[0];
+        let [_, _, width, height] = view.rect(self.map_area());
+        let aspect = (width / height.max(1.0)) as f32;
+        // The map is twice as wide in world space as in map space.
+        let extent = (2.0 * (max_x - min_x) / aspect).max(max_y - min_y);
+        let half_fov = vertical_fov(aspect).0 / 2.0;
+        let camera_height = FRAME_MARGIN * extent / (2.0 * half_fov.tan());
+
+        self.camera_controller
+            .fly_to(latitude as f32, longitude as f32, camera_height, 0.0);
+    }
+
+    /// The lines of the selected country's info panel, or `None` if nothing is selected. The last
+    /// is `info_panel::MORE_INFO` while `links.enabled` is on.
+    pub fn info_panel_lines(&self, scale: HudScale, glyphs: &mut Glyphs) -> Option<Vec<String>> {
+        let selected = self.selected_polygon?;
+        let properties = self.features.properties(selected);
+        let country = self.features.country(selected);
+        let mode_value = match self.map_mode {
+            MapMode::Percentile => country.pop_est.and_then(|population| {
+                let (percentile, count) = self.population_rank(country)?;
+                let noun = self.features.filter().noun();
+                Some(percentile::describe(population, percentile, count, noun, self.formatting()))
+            }),
+            mode => mode.describe(country),
+        };
+        let mode_value = mode_value.map(|value| (self.map_mode.name(), value));
+
+        let mut lines = info_panel::lines(
+            properties,
+            &self.settings.hud.info_panel_fields,
+            mode_value,
+            self.formatting(),
+            scale,
+            glyphs,
+        );
+        if self.settings.links.enabled {
+            lines.push(info_panel::MORE_INFO.to_string());
+        }
+        Some(lines)
+    }
+
+    /// The ground distance covered by one horizontal pixel of the 2D pass at the center of the
+    /// window.
+    pub fn km_per_pixel(&self, window: &PistonWindow) -> f64 {
+        let size = window.window.size();
+        let size = [f64::from(size.width), f64::from(size.height)];
+        let [_, _, width, height] = letterbox::area(self.locked_aspect(), size);
+        let camera = self.camera_controller.camera_position();
+        let look_at = self.camera_controller.look_at();
+
+        let distance = f64::from(
+            (camera[0] - look_at[0]).hypot(camera[1] - look_at[1]).hypot(camera[2]),
+        );
+        let half_fov = f64::from(vertical_fov((width / height.max(1.0)) as f32).0) / 2.0;
+        let world_per_pixel = 2.0 * distance * half_fov.tan() / height.max(1.0);
+
+        // Screen-horizontal is along the camera's right axis on the ground, which the
+        // equirectangular projection stretches differently in X and Y.
+        let heading = f64::from(self.camera_controller.heading());
+        let latitude = f64::from(self.camera_controller.latitude()).to_radians();
+        let km_x = heading.cos() * KM_PER_WORLD_X * latitude.cos();
+        let km_y = heading.sin() * KM_PER_WORLD_Y;
+
+        world_per_pixel * km_x.hypot(km_y)
+    }
+
+    /// What the choosers read that changes from frame to frame, read once per frame instead of
+    /// once per feature, for drawing the map in `view`.
+    pub fn frame_inputs(&self, view: View) -> FrameInputs {
+        FrameInputs {
+            view,
+            camera_height: self.camera_controller.camera_height(),
+            animation_tick: self.animation_tick(self.mode_in(view).0),
+            label_scale: 1.0,
+            now: self.animation_clock.elapsed(),
+        }
+    }
+
+    /// `mode`'s `MapMode::animation_tick`, unless the color script says its colors do not
+    /// change over time.
+    fn animation_tick(&self, mode: MapMode) -> Option<u64> {
+        let invariant = self.color_script.as_ref().map_or(false, ColorScript::is_time_invariant);
+        if mode == MapMode::Scripted && invariant {
+            return None;
+        }
+
+        mode.animation_tick(self.map_time())
+    }
+
+    /// Draws the map to `target` and `depth` through `mvp`, as `frame` says to.
+    pub fn render_map<R, F, C>(
+        &self,
+        renderer: &mut gaia::Renderer<R, F>,
+        encoder: &mut gfx::Encoder<R, C>,
+        target: RenderTargetView<R, Srgba8>,
+        depth: DepthStencilView<R, DepthStencil>,
+        mvp: Matrix4<f32>,
+        frame: FrameInputs,
+    ) -> gaia::Result<()>
+    where
+        R: gfx::Resources,
+        F: gfx::Factory<R> + Clone,
+        C: gfx::CommandBuffer<R>,
+    {
+        if let Some(ref script) = self.color_script {
+            script.start_drawing();
+        }
+        if self.labels_in(frame.view) {
+            self.declutter_labels(frame, mvp);
+        }
+
+        renderer.render(
+            encoder,
+            target,
+            depth,
+            mvp,
+            self.camera_controller.look_at(),
+            self.camera_controller.camera_height(),
+            &|properties| self.polygon_color_chooser(properties, frame),
+            &*self.label_chooser(frame),
+            &|camera_position| self.desired_level(camera_position),
+        )
+    }
+
+    /// The label style chooser to draw the view `frame` is for with: `label_style_chooser` if
+    /// labels are drawn there, and `no_labels` if not. gaia still goes through every place to
+    /// label, but with labels off it gets nothing back without any place being looked at.
+    pub fn label_chooser(&self, frame: FrameInputs) -> LabelChooser {
+        if self.labels_in(frame.view) {
+            Box::new(move |properties| self.label_style_chooser(properties, frame))
+        } else {
+            Box::new(no_labels)
+        }
+    }
+
+    /// Works out which labels to leave out in the view `frame` is for, drawn through `mvp`, if
+    /// the camera moved since they last were.
+    fn declutter_labels(&self, frame: FrameInputs, mvp: Matrix4<f32>) {
+        let mut declutter = self.label_declutter_in(frame.view).borrow_mut();
+        let settings = &self.settings.labels;
+        if !settings.declutter {
+            declutter.clear();
+            return;
+        }
+
+        let size = self.projection.draw_size();
+        let shown = label_declutter::Shown {
+            camera_height: frame.camera_height,
+            capitals_only: self.labels_shown() == LabelsShown::Capitals,
+            min_zoom_curve: settings.min_zoom_curve,
+        };
+        declutter.update(
+            self.features.label_candidates(),
+            mvp,
+            self.camera_controller.look_at(),
+            [f64::from(size.width), f64::from(size.height)],
+            f64::from(settings.declutter_cell * frame.label_scale),
+            shown,
+        );
+    }
+
+    fn label_declutter_in(&self, view: View) -> &RefCell<LabelDeclutter> {
+        match (view, self.split.as_ref()) {
+            (View::Right, Some(split)) => &split.label_declutter,
+            _ => &self.label_declutter,
+        }
+    }
+
+    /// Takes labels to be drawn with `font`, and works out which names it cannot draw.
+    pub fn set_label_font(&mut self, font: Font<'static>) {
+        self.label_font = Some(font);
+        self.update_label_fallbacks();
+    }
+
+    /// Works out again which names `label_font` cannot draw, and logs what they fall back to.
+    fn update_label_fallbacks(&mut self) {
+        self.label_fallbacks = match self.label_font {
+            Some(ref font) => {
+                self.features.label_fallbacks(|text| font_fallback::covers(font, text))
+            }
+            None => LabelFallbacks::default(),
+        };
+        if let Some(summary) = self.label_fallbacks.summary() {
+            warn!("{}", summary);
+        }
+    }
+
+    /// Draws the whole map to `target` and `depth`, for the overview inset.
+    pub fn render_overview<R, F, C>(
+        &self,
+        renderer: &mut gaia::Renderer<R, F>,
+        encoder: &mut gfx::Encoder<R, C>,
+        target: RenderTargetView<R, Srgba8>,
+        depth: DepthStencilView<R, DepthStencil>,
+    ) -> gaia::Result<()>
+    where
+        R: gfx::Resources,
+        F: gfx::Factory<R> + Clone,
+        C: gfx::CommandBuffer<R>,
+    {
+        let frame = self.frame_inputs(View::Whole);
+        let center_x = self.camera_controller.look_at()[0];
+
+        renderer.render(
+            encoder,
+            target,
+            depth,
+            overview::matrix(center_x),
+            [center_x, 0.5],
+            1.0,
+            &|properties| self.polygon_color_chooser(properties, frame),
+            &no_labels,
+            &|_| overview::LEVEL,
+        )
+    }
+
+    /// The polygon color chooser gaia draws the map with: the color of the country with
+    /// `properties` in the view `frame` is for, or `None` to leave it uncolored.
+    pub fn polygon_color_chooser(
+        &self,
+        properties: &Properties,
+        frame: FrameInputs,
+    ) -> Option<[u8; 4]> {
+        let mut cache = match (frame.view, self.split.as_ref()) {
+            (View::Right, Some(split)) => split.color_cache.borrow_mut(),
+            _ => self.color_cache.borrow_mut(),
+        };
+        cache.set_tick(frame.animation_tick);
+
+        // Effects change from frame to frame, so they are applied over the cached colors.
+        let id = features::feature_id(properties);
+        if let Some(&color) = cache.colors.get(id) {
+            return self.effects.apply(id, color, frame.now);
+        }
+
+        let country = self.features.country_with(properties);
+        let mut color = country.and_then(|country| self.polygon_color(country, frame));
+        if let (Some(snapshot), Some(country)) = (self.shown_snapshot(), country) {
+            let mode = self.mode_in(frame.view).0;
+            let change = snapshot.change(&country.id, mode, Class::of(mode, country, color));
+            color = Some(color_snapshot::diff_color(change));
+        }
+        cache.colors.insert(id.to_string(), color);
+        self.effects.apply(id, color, frame.now)
+    }
+
+    fn polygon_color(&self, country: &CountryProps, frame: FrameInputs) -> Option<[u8; 4]> {
+        let mode = self.mode_in(frame.view).0;
+        let modes = &self.profiled_settings.modes;
+        let [r, g, b, a] = mode.recolored(country, self.mode_color(country, frame)?, modes.palette);
+
+        // Day and night mode's opacity is how dark it is, which raising it would hide.
+        if mode == MapMode::Daylight {
+            return Some([r, g, b, a]);
+        }
+        Some([r, g, b, a.max(modes.min_alpha)])
+    }
+
+    /// The color of `country` in the view `frame` is for, as its map mode has it.
+    fn mode_color(&self, country: &CountryProps, frame: FrameInputs) -> Option<[u8; 4]> {
+        let (mode, legend) = self.mode_in(frame.view);
+        let parameters = &self.profiled_settings.modes.parameters;
+        if let (MapMode::Scripted, Some(script)) = (mode, self.color_script.as_ref()) {
+            let polygon = self.features.polygon_of(&country.id)?;
+            let properties = self.features.properties(polygon);
+            return script.color(&country.id, properties, frame.animation_tick);
+        }
+        if mode == MapMode::Daylight {
+            if !legend.is_enabled(0) {
+                return None;
+            }
+            let geo = self.geo_index.get(&country.id)?;
+            return tuned_daylight_color(geo, frame.animation_tick?, parameters);
+        }
+        if mode == MapMode::Percentile {
+            // Every country is ranked, whichever ones `countries.filter` keeps.
+            let percentile = self.with_percentiles(|percentiles| percentiles.get(&country.id))?;
+            let edited = self.edited_breaks();
+            let band = match edited {
+                Some(breaks) => class_breaks::class(breaks, country.pop_est?),
+                None => percentile::band(percentile),
+            };
+            if !legend.is_enabled(band) {
+                return None;
+            }
+            // Edited breaks can put any rank in a band, so each band is drawn in its own color.
+            return Some(match edited {
+                Some(_) => percentile::band_color(band),
+                None => percentile::color(percentile),
+            });
+        }
+
+        mode.shown_tuned_color(
+            country,
+            |category| legend.is_enabled(category),
+            frame.animation_tick,
+            parameters,
+        )
+    }
+
+    /// Where `country`'s population ranks among the countries `countries.filter` keeps, and among
+    /// how many of them with one. The rank is `None` if the filter leaves `country` out, and the
+    /// whole is `None` if it has no population.
+    fn population_rank(&self, country: &CountryProps) -> Option<(Option<f64>, usize)> {
+        let filter = self.features.filter();
+        let rank = |percentiles: &Percentiles| {
+            let percentile = percentiles.get(&country.id);
+            if filter.includes(country) {
+                Some((Some(percentile?), percentiles.count()))
+            } else {
+                Some((None, percentiles.count()))
+            }
+        };
+
+        if filter == CountryFilter::All {
+            return self.with_percentiles(rank);
+        }
+        let mut percentiles = self.filtered_percentiles.borrow_mut();
+        rank(percentiles.get_or_insert_with(|| {
+            Percentiles::of_population(self.features.filtered_countries())
+        }))
+    }
+
+    /// Calls `f` with where each country's population ranks, working it out if it has not been
+    /// since the mode was switched to.
+    fn with_percentiles<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&Percentiles) -> T,
+    {
+        let mut percentiles = self.percentiles.borrow_mut();
+        f(
+            percentiles
+                .get_or_insert_with(|| Percentiles::of_population(self.features.countries())),
+        )
+    }
+
+    /// The rows to export: the selected country, or if none is, every country that is shown.
+    pub fn export_rows(&self) -> Vec<export::Row> {
+        let frame = self.frame_inputs(View::Whole);
+
+        match self.selected_polygon {
+            Some(selected) => {
+                let country = self.features.country(selected);
+                let color = self.polygon_color(country, frame);
+                vec![export::Row::new(country, self.map_mode, color)]
+            }
+            None => {
+                let countries = self.features.filtered_countries();
+                export::shown_rows(countries, self.map_mode, |country| {
+                    self.polygon_color(country, frame)
+                })
+            }
+        }
+    }
+
+    /// The label style chooser gaia draws the map with. Labels are read from `properties` rather
+    /// than converted ahead of time, since their text has to be borrowed from them. Only used
+    /// while labels are enabled; `no_labels` is used otherwise.
+    pub fn label_style_chooser<'a>(
+        &self,
+        properties: &'a Properties,
+        frame: FrameInputs,
+    ) -> Option<gaia::LabelStyle<'a>> {
+        // Places without a name or zoom level, which the preflight check reports, are not
+        // labeled.
+        let place = PlaceProps::read(properties);
+        let curve = &self.settings.labels.min_zoom_curve;
+        let min_zoom = min_zoom_curve::remap(curve, place.min_zoom?);
+        let name = place.name?;
+
+        // The capital the tour is at is labeled however high the camera is, and larger.
+        let toured = self.toured_capital().map_or(false, |capital| {
+            place.is_capital
+                && capital.name == name
+                && capital.country == place.country.unwrap_or("")
+        });
+        if !toured && !min_zoom_curve::is_labeled_at(min_zoom, frame.camera_height) {
+            return None;
+        }
+        if !toured && !place.is_capital && self.labels_shown() == LabelsShown::Capitals {
+            return None;
+        }
+        let country = place.country.unwrap_or("");
+        if !toured && self.label_declutter_in(frame.view).borrow().suppresses(name, country) {
+            return None;
+        }
+
+        let style = &self.profiled_settings.labels;
+        let (mut scale, text_color) = if place.is_capital {
+            (style.capital_scale, style.capital_color)
+        } else {
+            (style.scale, style.color)
+        };
+        if toured {
+            scale *= self.settings.tour.label_scale;
+        }
+        // Names the label font cannot draw are labeled with their Latin names, if it can draw
+        // those.
+        let text = self.label_texts.get(self.label_fallbacks.choose(name, place.latin_name)?);
+        if text.contains('\n') {
+            scale *= style.wrap.wrapped_scale;
+        }
+
+        Some(gaia::LabelStyle {
+            text,
+            scale: scale * frame.label_scale,
+            text_color,
+            border_color: style.border_color,
+            border_width: style.border_width * frame.label_scale,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    use gaia_assetgen::{FeaturesData, MultiLevelPoint, MultiLevelPolygon, MAX_LEVEL};
+    use piston::input::{Loop, UpdateArgs};
+
+    fn state() -> State {
+        let features = Features::from_data(FeaturesData {
+            polygons: vec![],
+            points: vec![],
+        });
+        State::headless(Settings::default(), features, Path::new("target/tmp/state"))
+    }
+
+    /// A state with France selected, whose web page is opened in a browser the test can see.
+    fn state_with_link() -> (State, Rc<RefCell<Vec<String>>>) {
+        let properties = r#"{"ADMIN": "France", "ISO_A3": "FRA", "URL": "https://www.france.fr"}"#;
+        let features = Features::from_data(FeaturesData {
+            polygons: vec![MultiLevelPolygon {
+                properties: ::serde_json::from_str(properties).unwrap(),
+                bounding_box: [(0.5, 0.5), (0.5, 0.5)],
+                levels: vec![vec![]; MAX_LEVEL as usize + 1],
+            }],
+            points: vec![],
+        });
+        let dir = Path::new("target/tmp/state");
+        let mut state = State::headless(Settings::default(), features, dir);
+        let opened = Rc::new(RefCell::new(vec![]));
+        state.browser = Box::new(SharedBrowser(opened.clone()));
+        state.selected_polygon = Some(0);
+
+        (state, opened)
+    }
+
+    /// Keeps the addresses opened in it where the test that made it can still see them.
+    struct SharedBrowser(Rc<RefCell<Vec<String>>>);
+
+    impl Browser for SharedBrowser {
+        fn open(&mut self, url: &str) -> ::Result<()> {
+            self.0.borrow_mut().push(url.to_string());
+            Ok(())
+        }
+    }
+
+    fn press(key: Key) -> Event {
+        Event::Input(Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            scancode: None,
+        }))
+    }
+
+    fn handle_all(state: &mut State, actions: &[Action]) {
+        for &action in actions {
+            state.handle_action(action);
+        }
+    }
+
+    #[test]
+    fn mode_actions_switch_modes() {
+        let mut state = state();
+
+        handle_all(&mut state, &[Action::ShowIncome]);
+        assert_eq!(state.map_mode, MapMode::Income);
+
+        // Without `modes.script`, scripted mode is refused.
+        handle_all(&mut state, &[Action::ShowOecd, Action::ShowScripted]);
+        assert_eq!(state.map_mode, MapMode::Oecd);
+    }
+
+    #[test]
+    fn toggles_toggle() {
+        let mut state = state();
+        let (labels, graticule) = (state.labels_enabled, state.graticule_enabled);
+
+        handle_all(&mut state, &[Action::ToggleLabels, Action::ToggleGraticule]);
+        assert_eq!(state.labels_enabled, !labels);
+        assert_eq!(state.graticule_enabled, !graticule);
+
+        handle_all(&mut state, &[Action::ToggleLabels, Action::ToggleGraticule]);
+        assert_eq!(state.labels_enabled, labels);
+        assert_eq!(state.graticule_enabled, graticule);
+    }
+
+    #[test]
+    fn mode_label_policy_applies_until_labels_are_toggled() {
+        let mut state = state();
+        state.labels_enabled = true;
+
+        // Income mode hides labels by default, until 0 shows them again.
+        handle_all(&mut state, &[Action::ShowIncome]);
+        assert_eq!(state.labels_shown(), LabelsShown::None);
+        handle_all(&mut state, &[Action::ToggleLabels]);
+        assert_eq!(state.labels_shown(), LabelsShown::All);
+
+        // Switching back to income mode applies its policy again.
+        handle_all(&mut state, &[Action::ShowTerrain, Action::ShowIncome]);
+        assert_eq!(state.labels_shown(), LabelsShown::None);
+
+        // Terrain mode inherits whatever 0 left the switch at.
+        handle_all(&mut state, &[Action::ShowTerrain]);
+        assert_eq!(state.labels_shown(), LabelsShown::All);
+
+        state.settings.modes.labels.oecd = LabelPolicy::CapitalsOnly;
+        handle_all(&mut state, &[Action::ShowOecd]);
+        assert_eq!(state.labels_shown(), LabelsShown::Capitals);
+        assert_eq!(state.status_text(), "Mode: OECD (3) - Labels: capitals");
+    }
+
+    #[test]
+    fn a_modes_adjustments_apply_while_it_is_shown() {
+        let mut state = state();
+        state.settings.modes.adjustments.income = ModeAdjustments {
+            palette: Some(Palette::ColorblindSafe),
+            min_alpha: Some(204),
+            labels: Some(LabelPolicy::CapitalsOnly),
+        };
+
+        handle_all(&mut state, &[Action::ShowIncome]);
+        assert_eq!(state.profiled_settings.modes.palette, Palette::ColorblindSafe);
+        assert_eq!(state.profiled_settings.modes.min_alpha, 204);
+        assert_eq!(state.labels_shown(), LabelsShown::Capitals);
+
+        // Toggling labels is a tweak for this session, forgotten on switching modes.
+        handle_all(&mut state, &[Action::ToggleLabels]);
+        assert_eq!(state.mode_tweaks.labels, Some(LabelPolicy::Inherit));
+        handle_all(&mut state, &[Action::ShowTerrain]);
+        assert_eq!(state.mode_tweaks, ModeAdjustments::default());
+        assert_eq!(state.profiled_settings.modes.palette, Palette::Standard);
+        assert_eq!(state.profiled_settings.modes.min_alpha, 0);
+
+        handle_all(&mut state, &[Action::ShowIncome]);
+        assert_eq!(state.labels_shown(), LabelsShown::Capitals);
+    }
+
+    #[test]
+    fn losing_focus_suspends_animations_until_it_is_regained() {
+        let mut state = state();
+        let unfocus = Event::Input(Input::Focus(false));
+        state.shift_held = true;
+
+        state.event(&unfocus);
+        assert!(!state.focused && !state.shift_held);
+        assert!(state.animation_clock.is_frozen());
+        let frozen_at = state.animation_clock.elapsed();
+        state.event(&Event::Input(Input::Focus(true)));
+        assert!(!state.animation_clock.is_frozen());
+        assert_eq!(state.animation_clock.elapsed().as_secs(), frozen_at.as_secs());
+
+        // A clock frozen beforehand stays frozen.
+        state.animation_clock.toggle();
+        state.event(&unfocus);
+        state.event(&Event::Input(Input::Focus(true)));
+        assert!(state.animation_clock.is_frozen());
+
+        // Carrying on leaves the clock running, but frames are only paused by "pause".
+        state.animation_clock.toggle();
+        state.settings.window.unfocused = Unfocused::Continue;
+        state.event(&unfocus);
+        assert!(!state.animation_clock.is_frozen() && !state.is_paused_unfocused());
+        state.settings.window.unfocused = Unfocused::Pause;
+        assert!(state.is_paused_unfocused());
+    }
+
+    #[test]
+    fn a_frozen_clock_freezes_effects() {
+        let mut state = state();
+        let pulse = Effect {
+            duration: Some(Duration::from_secs(1)),
+            ..SELECTION_PULSE
+        };
+        state.effects.add("FRA", pulse, state.animation_clock.elapsed());
+        state.animation_clock.pass(Duration::from_millis(300));
+        state.animation_clock.toggle();
+        let color = |state: &State| {
+            let now = state.frame_inputs(View::Whole).now;
+            state.effects.apply("FRA", Some([0, 0, 0, 255]), now)
+        };
+        let frozen = color(&state);
+        assert_ne!(frozen, Some([0, 0, 0, 255]));
+
+        // Long after the pulse would have ended, it is still where it was frozen.
+        state.animation_clock.pass(Duration::from_secs(10));
+        assert!(!state.effects.expire(state.animation_clock.elapsed()));
+        assert_eq!(color(&state), frozen);
+        assert!(!state.is_animating());
+
+        state.animation_clock.toggle();
+        assert!(state.is_animating());
+        state.animation_clock.pass(Duration::from_secs(1));
+        assert!(state.effects.expire(state.animation_clock.elapsed()));
+    }
+
+    #[test]
+    fn the_command_palette_takes_the_keyboard_and_runs_what_is_picked() {
+        let mut state = state();
+        let open = |state: &mut State| {
+            state.ctrl_held = true;
+            state.event(&press(Key::P));
+            state.ctrl_held = false;
+        };
+        let legend_shown = state.legend.is_visible();
+
+        open(&mut state);
+        assert!(state.command_palette.is_visible());
+        // L would otherwise toggle the legend.
+        state.event(&press(Key::L));
+        state.event(&Event::Input(Input::Text("toggle legend".to_string())));
+        assert_eq!(state.legend.is_visible(), legend_shown);
+        state.event(&press(Key::Return));
+        assert_eq!(state.legend.is_visible(), !legend_shown);
+        assert!(!state.command_palette.is_visible());
+
+        // An overlay opened from the palette stays open after it closes.
+        open(&mut state);
+        state.event(&Event::Input(Input::Text("toggle settings".to_string())));
+        state.event(&press(Key::Return));
+        assert!(state.settings_overlay.is_visible());
+        assert!(!state.command_palette.is_visible());
+    }
+
+    #[test]
+    fn keys_the_settings_overlay_takes_go_no_further() {
+        let (mut state, opened) = state_with_link();
+        state.event(&press(Key::Return));
+        assert_eq!(*opened.borrow(), ["https://www.france.fr"]);
+
+        handle_all(&mut state, &[Action::ToggleSettings]);
+        let scale = state.settings.quality.render_scale;
+        // Up from the first entry wraps around to the last, and the render scale is seven up.
+        for _ in 0..7 {
+            state.event(&press(Key::Up));
+        }
+        state.event(&press(Key::Left));
+        assert!(state.settings_overlay.has_pending());
+        assert!(state.settings.quality.render_scale < scale);
+
+        state.event(&press(Key::Return));
+        assert!(!state.settings_overlay.has_pending());
+        assert!(state.settings_overlay.is_visible());
+        assert_eq!(opened.borrow().len(), 1);
+        assert!(state.window_requests.is_empty());
+    }
+
+    #[test]
+    fn escape_closes_the_settings_overlay_rather_than_the_demo() {
+        let (mut state, opened) = state_with_link();
+        state.settings.hud.scale = 2.0;
+
+        // Return on the last entry resets the settings, and opens no web page.
+        handle_all(&mut state, &[Action::ToggleSettings]);
+        state.event(&press(Key::Up));
+        state.event(&press(Key::Return));
+        assert_eq!(state.settings.hud.scale, Settings::default().hud.scale);
+        assert!(opened.borrow().is_empty());
+
+        state.event(&press(Key::Escape));
+        assert!(!state.settings_overlay.is_visible());
+        assert!(state.window_requests.is_empty());
+
+        state.event(&press(Key::Escape));
+        assert_eq!(state.window_requests, [WindowRequest::Escape]);
+    }
+
+    #[test]
+    fn the_tour_goes_all_the_way_around_and_waits_while_help_is_shown() {
+        let capital = |name: &str, x: f32| MultiLevelPoint {
+            properties: ::serde_json::from_str(&format!(r#"{{"NAME": "{}", "ADM0CAP": 1}}"#, name))
+                .unwrap(),
+            coordinates: [x, 0.5],
+            levels: vec![],
+        };
+        let features = Features::from_data(FeaturesData {
+            polygons: vec![],
+            points: vec![capital("West", 0.1), capital("Middle", 0.5), capital("East", 0.9)],
+        });
+        let dir = Path::new("target/tmp/state");
+        let mut state = State::headless(Settings::default(), features, dir);
+        let second = Event::Loop(Loop::Update(UpdateArgs { dt: 1.0 }));
+
+        // Over the middle of the map, the tour starts in the middle.
+        state.camera_controller.move_to(0.0, 0.0, 1.0);
+        handle_all(&mut state, &[Action::ToggleTour, Action::ToggleHelp]);
+        let first = state.tour.unwrap().stop;
+        assert_eq!(first, 1);
+        for _ in 0..30 {
+            state.event(&second);
+        }
+        assert_eq!(state.tour.map(|tour| (tour.stop, tour.is_dwelling())), Some((first, false)));
+
+        handle_all(&mut state, &[Action::ToggleHelp]);
+        let mut stops = vec![first];
+        for _ in 0..60 {
+            state.event(&second);
+            match state.tour {
+                Some(tour) if Some(&tour.stop) != stops.last() => stops.push(tour.stop),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        // Every capital is visited once, wrapping around past the east end.
+        assert_eq!(stops, [first, (first + 1) % 3, (first + 2) % 3]);
+        assert!(state.tour.is_none());
+    }
+
+    #[test]
+    fn the_overlays_in_the_hud_close_as_it_is_hidden() {
+        let mut state = state();
+        let overlays_visible = |state: &State| {
+            state.settings_overlay.is_visible()
+                || state.session_picker.is_visible()
+                || state.command_palette.is_visible()
+        };
+
+        handle_all(&mut state, &[Action::ToggleSettings, Action::ToggleHud]);
+        assert!(!state.settings.hud.visible);
+        assert!(!overlays_visible(&state));
+
+        // Opening one shows the HUD again, so that it is not taking keys unseen.
+        handle_all(&mut state, &[Action::TogglePalette]);
+        assert!(state.settings.hud.visible);
+        assert!(state.command_palette.is_visible());
+        handle_all(&mut state, &[Action::ToggleHud]);
+        assert!(!overlays_visible(&state));
+    }
+
+    #[test]
+    fn split_and_stereo_turn_each_other_off() {
+        let mut state = state();
+
+        handle_all(&mut state, &[Action::ToggleSplit, Action::ShowInRightHalf(MapMode::Oecd)]);
+        assert_eq!(state.split.as_ref().map(|split| split.mode), Some(MapMode::Oecd));
+
+        handle_all(&mut state, &[Action::ToggleStereo]);
+        assert!(state.stereo && state.split.is_none());
+
+        // With no right half, there is nothing to pick a mode for.
+        handle_all(&mut state, &[Action::ShowInRightHalf(MapMode::Income)]);
+        assert!(state.split.is_none());
+
+        handle_all(&mut state, &[Action::ToggleSplit]);
+        assert!(!state.stereo && state.split.is_some());
+    }
+
+    #[test]
+    fn categories_are_toggled_in_their_own_legend() {
+        let mut state = state();
+        handle_all(&mut state, &[Action::ShowIncome, Action::ToggleSplit]);
+        state.set_split_mode(MapMode::Income);
+
+        handle_all(&mut state, &[Action::ToggleCategory(1), Action::ToggleRightCategory(3)]);
+        assert!(!state.legend.is_enabled(1) && state.legend.is_enabled(3));
+        let split = state.split.as_ref().unwrap();
+        assert!(split.legend.is_enabled(1) && !split.legend.is_enabled(3));
+    }
+
+    #[test]
+    fn window_actions_are_requested_in_order() {
+        let mut state = state();
+
+        handle_all(
+            &mut state,
+            &[
+                Action::Screenshot,
+                Action::HiResScreenshot,
+                Action::ToggleRecording,
+                Action::ExportLegend,
+                Action::Escape,
+            ],
+        );
+        assert_eq!(
+            state.window_requests,
+            [
+                WindowRequest::Screenshot,
+                WindowRequest::HiResScreenshot,
+                WindowRequest::ToggleRecording,
+                WindowRequest::ExportLegend,
+                WindowRequest::Escape,
+            ]
+        );
+    }
+
+    #[test]
+    fn chords_take_precedence_over_bindings() {
+        let mut state = state();
+        assert_eq!(state.chord_action(Key::C), None);
+        assert_eq!(state.chord_action(Key::D2), None);
+
+        state.ctrl_held = true;
+        assert_eq!(state.chord_action(Key::R), Some(Action::ToggleRecording));
+        // Without a split-screen, Ctrl and a mode key switch the mode as usual.
+        assert_eq!(state.chord_action(Key::D4), None);
+        state.handle_action(Action::ToggleSplit);
+        assert_eq!(state.chord_action(Key::D4), Some(Action::ShowInRightHalf(MapMode::Income)));
+
+        state.shift_held = true;
+        assert_eq!(state.chord_action(Key::C), Some(Action::CopySelected));
+        state.handle_action(Action::ToggleLegend);
+        // Keys bound to modes still pick the right half's mode.
+        assert_eq!(state.chord_action(Key::D2), Some(Action::ShowInRightHalf(MapMode::All)));
+        assert_eq!(state.chord_action(Key::D9), Some(Action::ToggleRightCategory(8)));
+
+        state.ctrl_held = false;
+        assert_eq!(state.chord_action(Key::D2), Some(Action::ToggleCategory(1)));
+        assert_eq!(state.chord_action(Key::F12), Some(Action::HiResScreenshot));
+        assert_eq!(state.chord_action(Key::L), Some(Action::ExportLegend));
+    }
+
+    #[test]
+    fn clicks_go_to_the_widget_under_them() {
+        let mut state = state();
+        assert_eq!(state.click_action([400.0, 300.0]), Action::ClickMap([400.0, 300.0]));
+
+        state.compass_center = Some([700.0, 500.0]);
+        assert_eq!(state.click_action([700.0, 500.0]), Action::SnapNorth);
+
+        state.overview_rect = Some([0.0, 0.0, 200.0, 100.0]);
+        match state.click_action([100.0, 50.0]) {
+            Action::FlyTo { latitude, .. } => assert!(latitude.abs() < 1e-6),
+            action => panic!("{:?}", action),
+        }
+    }
+}
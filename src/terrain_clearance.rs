@@ -0,0 +1,161 @@
+//! Keeping the camera clear of high terrain. The least camera height is the same everywhere, so
+//! over high mountains the camera could otherwise come down almost onto them.
+//!
+//! gaia has no elevations to sample, so the terrain is approximated by the regions in
+//! `terrain.regions`, each with how high the terrain in it is, as a camera height. The camera is
+//! kept `terrain.clearance` above the highest region under the point it looks at.
+
+/// How many degrees beyond its edges a region's height fades out over, so that the camera is
+/// eased up as it nears one rather than pushed up at its edge.
+const FEATHER_DEGREES: f32 = 5.0;
+
+/// What fraction of the way from the terrain height last used to the one under the camera is
+/// still left to go after a second, so that the camera does not jitter as it moves.
+const SMOOTHING_PER_SECOND: f32 = 0.02;
+
+/// A region of high terrain, from `south` to `north` and `west` to `east`, in degrees. Regions
+/// do not cross the antimeridian.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TerrainRegion {
+    pub name: String,
+    pub south: f32,
+    pub west: f32,
+    pub north: f32,
+    pub east: f32,
+    /// How high the terrain is, as a camera height.
+    pub height: f32,
+}
+
+impl TerrainRegion {
+    pub fn new(name: &str, [south, west, north, east]: [f32; 4], height: f32) -> TerrainRegion {
+        TerrainRegion {
+            name: name.to_string(),
+            south,
+            west,
+            north,
+            east,
+            height,
+        }
+    }
+
+    /// Whether the region's bounds are in order and within the map, and its height is not
+    /// below the ground.
+    pub fn is_valid(&self) -> bool {
+        let finite = [self.south, self.west, self.north, self.east, self.height]
+            .iter()
+            .all(|n| n.is_finite());
+
+        finite
+            && -90.0 <= self.south
+            && self.south <= self.north
+            && self.north <= 90.0
+            && -180.0 <= self.west
+            && self.west <= self.east
+            && self.east <= 180.0
+            && self.height >= 0.0
+    }
+
+    /// How much of the region's height applies at `latitude` and `longitude`: all of it inside,
+    /// fading to none `FEATHER_DEGREES` outside.
+    fn weight(&self, latitude: f32, longitude: f32) -> f32 {
+        let beyond_latitude = (self.south - latitude).max(latitude - self.north).max(0.0);
+        let beyond_longitude = (self.west - longitude).max(longitude - self.east).max(0.0);
+        let t = (1.0 - beyond_latitude.hypot(beyond_longitude) / FEATHER_DEGREES).max(0.0);
+
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+/// The world's highest mountain ranges, roughly.
+pub fn default_regions() -> Vec<TerrainRegion> {
+    vec![
+        TerrainRegion::new("Himalayas", [26.0, 70.0, 40.0, 100.0], 0.06),
+        TerrainRegion::new("Andes", [-45.0, -80.0, 5.0, -63.0], 0.045),
+        TerrainRegion::new("Rocky Mountains", [35.0, -125.0, 60.0, -104.0], 0.04),
+        TerrainRegion::new("Alps", [43.5, 5.0, 48.0, 16.0], 0.04),
+    ]
+}
+
+/// How high the terrain is at `latitude` and `longitude`, as a camera height, and the region it
+/// is highest for, if any.
+pub fn terrain_height(
+    regions: &[TerrainRegion],
+    latitude: f32,
+    longitude: f32,
+) -> (f32, Option<&TerrainRegion>) {
+    regions
+        .iter()
+        .map(|region| (region.height * region.weight(latitude, longitude), region))
+        .filter(|&(height, _)| height > 0.0)
+        .fold((0.0, None), |highest, (height, region)| {
+            if height > highest.0 {
+                (height, Some(region))
+            } else {
+                highest
+            }
+        })
+}
+
+/// The terrain height under the camera, smoothed over time so that it changes gradually however
+/// the camera moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TerrainFilter {
+    height: Option<f32>,
+}
+
+impl TerrainFilter {
+    /// Moves on `dt` seconds towards the terrain height `target`, and returns the height to keep
+    /// the camera above. The first height is used as it is.
+    pub fn update(&mut self, target: f32, dt: f32) -> f32 {
+        let height = match self.height {
+            Some(height) if dt.is_finite() => {
+                target + (height - target) * SMOOTHING_PER_SECOND.powf(dt.max(0.0))
+            }
+            _ => target,
+        };
+        self.height = Some(height);
+
+        height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_fade_out_beyond_their_edges() {
+        let regions = default_regions();
+        let (height, region) = terrain_height(&regions, 28.0, 86.9);
+        assert_eq!((height, region.map(|region| region.name.as_str())), (0.06, Some("Himalayas")));
+
+        // Halfway through the feather, half of the height applies.
+        let (height, _) = terrain_height(&regions, 42.5, 86.9);
+        assert!((height - 0.03).abs() < 1e-6, "{}", height);
+
+        assert_eq!(terrain_height(&regions, 0.0, 0.0), (0.0, None));
+        assert_eq!(terrain_height(&[], 28.0, 86.9), (0.0, None));
+        assert!(regions.iter().all(TerrainRegion::is_valid));
+        assert!(!TerrainRegion::new("Upside down", [10.0, 0.0, -10.0, 5.0], 0.1).is_valid());
+    }
+
+    #[test]
+    fn the_filtered_height_changes_gradually() {
+        let mut filter = TerrainFilter::default();
+        assert_eq!(filter.update(0.0, 1.0 / 60.0), 0.0);
+
+        // Crossing into a region, the height rises over a few frames rather than at once...
+        let mut heights = vec![];
+        for _ in 0..6 {
+            heights.push(filter.update(0.06, 1.0 / 60.0));
+        }
+        assert!(heights.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", heights);
+        assert!(heights[0] < 0.01, "{:?}", heights);
+
+        // ...and settles on it within a couple of seconds.
+        for _ in 0..120 {
+            filter.update(0.06, 1.0 / 60.0);
+        }
+        assert!((filter.update(0.06, 1.0 / 60.0) - 0.06).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,134 @@
+/// The colors HUD elements are drawn in. Every color is RGBA, each component between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Behind panels, overlays and toasts.
+    pub panel_background: [f32; 4],
+
+    /// Most text, and lines such as the scale bar.
+    pub text: [f32; 4],
+
+    /// Highlights, such as the selected settings entry or key names in the help overlay.
+    pub accent: [f32; 4],
+
+    /// Things that need attention, such as slow frames.
+    pub warning: [f32; 4],
+}
+
+impl Theme {
+    /// A translucent dark panel with light text, which reads well over most terrain.
+    pub fn dark() -> Theme {
+        Theme {
+            panel_background: [0.0, 0.0, 0.0, 0.7],
+            text: [1.0, 1.0, 1.0, 1.0],
+            accent: [1.0, 1.0, 0.0, 1.0],
+            warning: [1.0, 0.3, 0.0, 1.0],
+        }
+    }
+
+    /// A translucent white panel with black text.
+    pub fn light() -> Theme {
+        Theme {
+            panel_background: [1.0, 1.0, 1.0, 0.8],
+            text: [0.0, 0.0, 0.0, 1.0],
+            accent: [0.0, 0.3, 0.8, 1.0],
+            warning: [0.8, 0.2, 0.0, 1.0],
+        }
+    }
+
+    /// An opaque black panel with white text and bright highlights, for readability.
+    pub fn high_contrast() -> Theme {
+        Theme {
+            panel_background: [0.0, 0.0, 0.0, 1.0],
+            text: [1.0, 1.0, 1.0, 1.0],
+            accent: [1.0, 1.0, 0.0, 1.0],
+            warning: [1.0, 0.5, 0.0, 1.0],
+        }
+    }
+
+    /// `text`, faded for things that are disabled or less important.
+    pub fn muted_text(&self) -> [f32; 4] {
+        let [r, g, b, a] = self.text;
+
+        [r, g, b, a * 0.5]
+    }
+
+    /// `panel_background` without any translucency, for drawing over things on a panel as if
+    /// erasing them.
+    pub fn opaque_background(&self) -> [f32; 4] {
+        let [r, g, b, _] = self.panel_background;
+
+        [r, g, b, 1.0]
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
+
+/// Which theme the HUD uses.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeName {
+    Dark,
+    Light,
+    HighContrast,
+    /// The colors given in the settings' `custom_theme`.
+    Custom,
+}
+
+impl ThemeName {
+    pub const ALL: [ThemeName; 4] = [
+        ThemeName::Dark,
+        ThemeName::Light,
+        ThemeName::HighContrast,
+        ThemeName::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ThemeName::Dark => "Dark",
+            ThemeName::Light => "Light",
+            ThemeName::HighContrast => "High contrast",
+            ThemeName::Custom => "Custom",
+        }
+    }
+
+    /// The theme `steps` places after this one in `ALL`, wrapping around.
+    pub fn cycle(&self, steps: isize) -> ThemeName {
+        let len = ThemeName::ALL.len() as isize;
+        let index = ThemeName::ALL.iter().position(|name| name == self).unwrap() as isize;
+
+        ThemeName::ALL[(index + steps).rem_euclid(len) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn themes_cycle_through_every_one_and_wrap_around() {
+        let mut name = ThemeName::Dark;
+        let mut seen = vec![];
+        for _ in 0..ThemeName::ALL.len() {
+            seen.push(name);
+            name = name.cycle(1);
+        }
+        assert_eq!(seen, ThemeName::ALL);
+        assert_eq!(name, ThemeName::Dark);
+
+        assert_eq!(ThemeName::Dark.cycle(-1), ThemeName::Custom);
+        assert_eq!(ThemeName::Light.cycle(-5), ThemeName::Dark);
+    }
+
+    #[test]
+    fn derived_colors_change_only_the_alpha() {
+        let theme = Theme::light();
+
+        assert_eq!(theme.muted_text(), [0.0, 0.0, 0.0, 0.5]);
+        assert_eq!(theme.opaque_background(), [1.0, 1.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+/// How long after the view last changed a lot the tiles it needs are assumed to still be loading.
+const SETTLE_TIME: Duration = Duration::from_millis(1200);
+
+/// Moving the look-at point by more than this many camera heights in one frame is assumed to
+/// need tiles that are not loaded yet.
+const FAST_MOVE: f32 = 0.05;
+
+/// How long the spinner takes to go around once.
+const SPIN_SECS: f64 = 1.0;
+
+/// Guesses whether the renderer is still loading tiles for the view.
+///
+/// `gaia::Renderer` does not say which tiles are pending, so this assumes tiles are loading for a
+/// short while after the level of detail changes or the camera moves quickly.
+///
+/// Tiles that never arrive are not detected either. gaia fetches them on a thread of its own,
+/// whose `fetch_tiles` unwraps as it goes, so a fetch that fails there panics that thread instead
+/// of making `render` return an error, and the view stays at whatever level was loaded by then.
+#[derive(Debug)]
+pub struct TileLoading {
+    level: Option<u8>,
+    look_at: Option<[f32; 2]>,
+    changed_at: Option<Instant>,
+    /// Whether the last `update` found tiles loading, so that changes can be logged.
+    was_loading: bool,
+    /// What the spinner's rotation is measured from.
+    created_at: Instant,
+}
+
+impl TileLoading {
+    pub fn new() -> TileLoading {
+        TileLoading {
+            level: None,
+            look_at: None,
+            changed_at: None,
+            was_loading: false,
+            created_at: Instant::now(),
+        }
+    }
+
+    /// Records where the camera is this frame.
+    pub fn update(&mut self, level: u8, look_at: [f32; 2], camera_height: f32) {
+        let moved_fast = self.look_at.map_or(true, |previous| {
+            (look_at[0] - previous[0]).hypot(look_at[1] - previous[1]) > FAST_MOVE * camera_height
+        });
+
+        if self.level != Some(level) {
+            debug!("Level of detail: {}", level);
+        }
+        if self.level != Some(level) || moved_fast {
+            self.changed_at = Some(Instant::now());
+        }
+
+        self.level = Some(level);
+        self.look_at = Some(look_at);
+
+        let loading = self.loading_level().is_some();
+        if loading != self.was_loading {
+            if loading {
+                debug!("Tiles for level {} are probably loading", level);
+            } else {
+                debug!("Tiles for level {} have probably loaded", level);
+            }
+            self.was_loading = loading;
+        }
+    }
+
+    /// Assumes tiles are loading from now, as they are for a view that was just shown.
+    pub fn expect_tiles(&mut self) {
+        self.changed_at = Some(Instant::now());
+    }
+
+    /// The level being loaded, or `None` if the view has settled.
+    pub fn loading_level(&self) -> Option<u8> {
+        let changed_at = self.changed_at?;
+
+        if changed_at.elapsed() < SETTLE_TIME {
+            self.level
+        } else {
+            None
+        }
+    }
+
+    /// How far around the spinner is, from 0 to 1.
+    pub fn spinner_phase(&self) -> f64 {
+        let elapsed = self.created_at.elapsed();
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+
+        (secs / SPIN_SECS).fract()
+    }
+}
+
+impl Default for TileLoading {
+    fn default() -> TileLoading {
+        TileLoading::new()
+    }
+}
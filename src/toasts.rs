@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use piston_window::{Context, G2d, Glyphs, Transformed};
+
+use hud::{HudScale, HudStyle, Insets};
+
+/// How long a toast stays up when no particular duration is needed.
+pub const DEFAULT_DURATION: Duration = Duration::from_millis(2500);
+
+/// At most this many toasts are shown at once. Pushing another drops the oldest.
+const MAX_VISIBLE: usize = 3;
+
+/// How long toasts take to fade in, and again to fade out.
+const FADE_SECS: f64 = 0.2;
+
+const FONT_SIZE: u32 = 10;
+const LINE_HEIGHT: f64 = 20.0;
+const PADDING: f64 = 10.0;
+
+#[derive(Debug)]
+struct Toast {
+    message: String,
+    shown_at: Instant,
+    duration: Duration,
+    /// Whether it shows the progress of something still going on, and so is replaced by the
+    /// next progress message instead of stacking up.
+    progress: bool,
+}
+
+impl Toast {
+    /// How opaque the toast is, between 0 and 1, or `None` once it has expired.
+    fn opacity(&self, now: Instant) -> Option<f64> {
+        let elapsed = duration_secs(now.duration_since(self.shown_at));
+        let duration = duration_secs(self.duration);
+
+        if elapsed >= duration {
+            return None;
+        }
+
+        let fade_in = elapsed / FADE_SECS;
+        let fade_out = (duration - elapsed) / FADE_SECS;
+        Some(fade_in.min(fade_out).min(1.0))
+    }
+}
+
+fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
+
+/// Short status messages, stacked at the bottom-center of the window.
+#[derive(Debug)]
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn new() -> Toasts {
+        Toasts {
+            toasts: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, message: String, duration: Duration) {
+        if self.toasts.len() == MAX_VISIBLE {
+            self.toasts.pop_front();
+        }
+
+        self.toasts.push_back(Toast {
+            message,
+            shown_at: Instant::now(),
+            duration,
+            progress: false,
+        });
+    }
+
+    /// Shows how something is progressing, replacing the progress shown before, if it is still
+    /// the newest toast, so that it stays up without fading in again.
+    pub fn progress(&mut self, message: String) {
+        let now = Instant::now();
+        if let Some(toast) = self.toasts.back_mut() {
+            if toast.progress && toast.opacity(now).is_some() {
+                toast.message = message;
+                toast.duration = now.duration_since(toast.shown_at) + DEFAULT_DURATION;
+                return;
+            }
+        }
+
+        self.push(message, DEFAULT_DURATION);
+        if let Some(toast) = self.toasts.back_mut() {
+            toast.progress = true;
+        }
+    }
+
+    /// Takes down any progress shown, once what it was for is done.
+    pub fn end_progress(&mut self) {
+        self.toasts.retain(|toast| !toast.progress);
+    }
+
+    /// Whether any toast is still up, and so may be fading.
+    pub fn is_showing(&self) -> bool {
+        let now = Instant::now();
+
+        self.toasts.iter().any(|toast| toast.opacity(now).is_some())
+    }
+
+    /// Draws every live toast, newest at the bottom, and forgets expired ones. They are centered
+    /// in the safe area inside `insets`, and shortened to fit across it.
+    pub fn draw(
+        &mut self,
+        style: HudStyle,
+        insets: Insets,
+        glyphs: &mut Glyphs,
+        context: Context,
+        graphics: &mut G2d,
+    ) {
+        let now = Instant::now();
+        self.toasts.retain(|toast| toast.opacity(now).is_some());
+
+        let [view_width, view_height] = context.get_view_size();
+        let [_, right, bottom, left] = insets;
+        let safe_width = (view_width - left - right).max(0.0);
+        let HudStyle { scale, theme } = style;
+        let (padding, line_height) = (scale.len(PADDING), scale.len(LINE_HEIGHT));
+        let max_text_width = (safe_width - 4.0 * padding).max(0.0);
+
+        for (i, toast) in self.toasts.iter().rev().enumerate() {
+            let opacity = toast.opacity(now).unwrap_or(0.0) as f32;
+            let fade = |[r, g, b, a]: [f32; 4]| [r, g, b, a * opacity];
+            let message = truncate_to_width(&toast.message, max_text_width, scale, glyphs);
+            let text_width = scale.text_width(FONT_SIZE, &message, glyphs);
+
+            let width = text_width + 2.0 * padding;
+            let x = (left + (safe_width - width) / 2.0).round();
+            let y = view_height - bottom - padding - line_height * (i + 1) as f64;
+            let context = context.trans(x, y);
+
+            ::piston_window::rectangle(
+                fade(theme.panel_background),
+                [0.0, 0.0, width, line_height - scale.len(2.0)],
+                context.transform,
+                graphics,
+            );
+
+            scale.draw_text(
+                &message,
+                FONT_SIZE,
+                fade(theme.text),
+                context.trans(padding, scale.len(13.0)),
+                glyphs,
+                graphics,
+            );
+        }
+    }
+}
+
+impl Default for Toasts {
+    fn default() -> Toasts {
+        Toasts::new()
+    }
+}
+
+/// `message`, shortened with an ellipsis if it is wider than `max_width`.
+fn truncate_to_width(
+    message: &str,
+    max_width: f64,
+    scale: HudScale,
+    glyphs: &mut Glyphs,
+) -> String {
+    if scale.text_width(FONT_SIZE, message, glyphs) <= max_width {
+        return message.to_string();
+    }
+
+    let mut truncated: String = message.to_string();
+    while !truncated.is_empty() {
+        truncated.pop();
+
+        let candidate = format!("{}...", truncated);
+        if scale.text_width(FONT_SIZE, &candidate, glyphs) <= max_width {
+            return candidate;
+        }
+    }
+
+    "...".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(toasts: &Toasts) -> Vec<&str> {
+        toasts.toasts.iter().map(|toast| toast.message.as_str()).collect()
+    }
+
+    #[test]
+    fn toasts_fade_in_and_out_and_then_expire() {
+        let shown_at = Instant::now();
+        let toast = Toast {
+            message: "Income".to_string(),
+            shown_at,
+            duration: Duration::from_secs(2),
+            progress: false,
+        };
+        let at = |secs: f64| toast.opacity(shown_at + Duration::from_secs_f64(secs));
+
+        assert_eq!(at(0.0), Some(0.0));
+        assert!((at(0.1).unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(at(1.0), Some(1.0));
+        assert!((at(1.9).unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(at(2.0), None);
+        assert_eq!(at(60.0), None);
+    }
+
+    #[test]
+    fn only_the_newest_toasts_stack_up() {
+        let mut toasts = Toasts::new();
+        for message in &["One", "Two", "Three", "Four"] {
+            toasts.push(message.to_string(), DEFAULT_DURATION);
+        }
+        assert_eq!(messages(&toasts), ["Two", "Three", "Four"]);
+        assert!(toasts.is_showing());
+
+        // Once every toast has expired, none is showing.
+        for toast in &mut toasts.toasts {
+            toast.shown_at -= DEFAULT_DURATION;
+        }
+        assert!(!toasts.is_showing());
+    }
+
+    #[test]
+    fn progress_replaces_itself_until_it_ends() {
+        let mut toasts = Toasts::new();
+        toasts.push("Income".to_string(), DEFAULT_DURATION);
+        toasts.progress("Loading 1/3".to_string());
+        toasts.progress("Loading 2/3".to_string());
+        assert_eq!(messages(&toasts), ["Income", "Loading 2/3"]);
+
+        // Progress that is no longer the newest toast is not replaced, but shown again.
+        toasts.push("Labels on".to_string(), DEFAULT_DURATION);
+        toasts.progress("Loading 3/3".to_string());
+        assert_eq!(messages(&toasts), ["Loading 2/3", "Labels on", "Loading 3/3"]);
+
+        toasts.end_progress();
+        assert_eq!(messages(&toasts), ["Labels on"]);
+    }
+}
@@ -0,0 +1,191 @@
+use gaia_assetgen::MultiLevelPoint;
+
+use props::PlaceProps;
+
+/// A capital city, as a stop on the capitals tour.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capital {
+    pub name: String,
+    /// Empty if the places data does not say.
+    pub country: String,
+    /// In map space.
+    pub point: [f32; 2],
+}
+
+/// Every named capital in `points`, from west to east, so that the tour sweeps around the globe.
+/// Capitals at the same longitude go from north to south.
+pub fn capitals(points: &[MultiLevelPoint]) -> Vec<Capital> {
+    let mut capitals: Vec<Capital> = points
+        .iter()
+        .filter_map(|point| {
+            let place = PlaceProps::read(&point.properties);
+            if !place.is_capital {
+                return None;
+            }
+
+            Some(Capital {
+                name: place.name?.to_string(),
+                country: place.country.unwrap_or("").to_string(),
+                point: point.coordinates,
+            })
+        })
+        .collect();
+
+    capitals.sort_by(|a, b| {
+        let key = |capital: &Capital| [capital.point[0], -capital.point[1]];
+        key(a).partial_cmp(&key(b)).unwrap_or_else(|| a.name.cmp(&b.name))
+    });
+    capitals
+}
+
+/// Where the tour is at its stop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    /// On the way to it.
+    Flying,
+    /// There, for another `remaining` seconds.
+    Dwelling { remaining: f64 },
+}
+
+/// What the tour does next, as decided by `Tour::update`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TourStep {
+    Stay,
+    /// The camera just got to the stop.
+    Arrived,
+    /// It is time to move on to the next stop.
+    Leave,
+}
+
+/// The built-in tour of every capital in the places data, flying to each in turn and staying a
+/// while before moving on. It goes on past the last capital to the first, and finishes once it
+/// is back where it started.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tour {
+    /// The index of the stop in `Features::capitals`.
+    pub stop: usize,
+    /// The stop the tour started at.
+    first: usize,
+    phase: Phase,
+}
+
+impl Tour {
+    /// Starts at `stop`, where the camera should now fly to.
+    pub fn new(stop: usize) -> Tour {
+        Tour {
+            stop,
+            first: stop,
+            phase: Phase::Flying,
+        }
+    }
+
+    /// Moves on to `stop`, where the camera should now fly to.
+    pub fn go_to(&mut self, stop: usize) {
+        self.stop = stop;
+        self.phase = Phase::Flying;
+    }
+
+    /// The stop after this one, of `len`, or `None` if the tour is back where it started and has
+    /// finished.
+    pub fn next_stop(&self, len: usize) -> Option<usize> {
+        let next = (self.stop + 1) % len;
+        if next == self.first {
+            None
+        } else {
+            Some(next)
+        }
+    }
+
+    /// Whether the camera is at the stop, rather than on its way to it.
+    pub fn is_dwelling(&self) -> bool {
+        match self.phase {
+            Phase::Flying => false,
+            Phase::Dwelling { .. } => true,
+        }
+    }
+
+    /// Lets `dt` seconds pass, while the camera is still `flying` to the stop or not, staying
+    /// `dwell_secs` at each stop.
+    pub fn update(&mut self, dt: f64, flying: bool, dwell_secs: f64) -> TourStep {
+        match self.phase {
+            Phase::Flying if flying => TourStep::Stay,
+            Phase::Flying => {
+                self.phase = Phase::Dwelling {
+                    remaining: dwell_secs,
+                };
+                TourStep::Arrived
+            }
+            Phase::Dwelling { remaining } if remaining > dt => {
+                self.phase = Phase::Dwelling {
+                    remaining: remaining - dt,
+                };
+                TourStep::Stay
+            }
+            Phase::Dwelling { .. } => TourStep::Leave,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    fn point(properties: &str, coordinates: [f32; 2]) -> MultiLevelPoint {
+        MultiLevelPoint {
+            properties: serde_json::from_str(properties).unwrap(),
+            coordinates,
+            levels: vec![],
+        }
+    }
+
+    #[test]
+    fn capitals_go_from_west_to_east_then_north_to_south() {
+        let points = [
+            point(r#"{"NAME": "Nairobi", "ADM0CAP": 1, "ADM0NAME": "Kenya"}"#, [0.6, 0.5]),
+            point(r#"{"NAME": "Mombasa", "ADM0CAP": 0, "ADM0NAME": "Kenya"}"#, [0.61, 0.5]),
+            point(r#"{"NAME": "Lima", "ADM0CAP": 1}"#, [0.29, 0.47]),
+            point(r#"{"ADM0CAP": 1}"#, [0.5, 0.5]),
+            point(r#"{"NAME": "Oslo", "ADM0CAP": 1, "ADM0NAME": "Norway"}"#, [0.53, 0.83]),
+            point(r#"{"NAME": "Kinshasa", "ADM0CAP": 1}"#, [0.53, 0.48]),
+        ];
+
+        let capitals = capitals(&points);
+        let names: Vec<&str> = capitals.iter().map(|capital| capital.name.as_str()).collect();
+        assert_eq!(names, ["Lima", "Oslo", "Kinshasa", "Nairobi"]);
+        assert_eq!(capitals[0].country, "");
+        assert_eq!(capitals[3].country, "Kenya");
+        assert_eq!(capitals[3].point, [0.6, 0.5]);
+    }
+
+    #[test]
+    fn each_stop_is_flown_to_then_dwelt_at() {
+        let mut tour = Tour::new(2);
+        assert_eq!(tour.update(1.0, true, 3.0), TourStep::Stay);
+        assert!(!tour.is_dwelling());
+
+        assert_eq!(tour.update(1.0, false, 3.0), TourStep::Arrived);
+        assert!(tour.is_dwelling());
+        assert_eq!(tour.update(2.0, false, 3.0), TourStep::Stay);
+        assert_eq!(tour.update(2.0, false, 3.0), TourStep::Leave);
+
+        tour.go_to(3);
+        assert_eq!((tour.stop, tour.is_dwelling()), (3, false));
+        assert_eq!(tour.update(1.0, true, 3.0), TourStep::Stay);
+    }
+
+    #[test]
+    fn the_tour_wraps_around_and_finishes_where_it_started() {
+        let mut tour = Tour::new(2);
+        let mut stops = vec![tour.stop];
+        while let Some(next) = tour.next_stop(4) {
+            tour.go_to(next);
+            stops.push(next);
+        }
+        assert_eq!(stops, [2, 3, 0, 1]);
+
+        // A tour of one capital finishes after it.
+        assert_eq!(Tour::new(0).next_stop(1), None);
+    }
+}
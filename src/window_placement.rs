@@ -0,0 +1,224 @@
+use glutin::MonitorId;
+use piston_window::PistonWindow;
+
+use fullscreen::Monitors;
+use settings::WindowSettings;
+use Result;
+
+/// How far down from the window's top-left corner to look for its title bar, in pixels, when
+/// checking whether it can still be grabbed.
+const TITLE_BAR_HEIGHT: i32 = 16;
+
+/// Where the window is and how big, in pixels of the desktop.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Of the window's top-left corner, from the desktop's.
+    pub position: [i32; 2],
+    /// Of what the window shows, without its title bar and borders.
+    pub size: [u32; 2],
+}
+
+/// The part of the desktop a monitor shows.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Area {
+    pub position: [i32; 2],
+    pub size: [u32; 2],
+}
+
+impl Area {
+    fn of(monitor: &MonitorId) -> Area {
+        let (x, y) = monitor.get_position();
+        let (width, height) = monitor.get_dimensions();
+
+        Area {
+            position: [x, y],
+            size: [width, height],
+        }
+    }
+
+    fn contains(&self, [x, y]: [i32; 2]) -> bool {
+        let [left, top] = self.position;
+        left <= x
+            && x < left + self.size[0] as i32
+            && top <= y
+            && y < top + self.size[1] as i32
+    }
+
+    /// Where a window of `size` is centered on this area.
+    fn centered(&self, size: [u32; 2]) -> [i32; 2] {
+        let offset = |i: usize| (self.size[i] as i32 - size[i] as i32) / 2;
+        [self.position[0] + offset(0), self.position[1] + offset(1)]
+    }
+
+    /// `geometry`, shrunk to fit on this area and moved as little as it takes to be wholly on it.
+    fn clamp(&self, geometry: WindowGeometry) -> WindowGeometry {
+        let size = [
+            geometry.size[0].min(self.size[0]),
+            geometry.size[1].min(self.size[1]),
+        ];
+        let clamp = |i: usize| {
+            let min = self.position[i];
+            let max = min + (self.size[i] - size[i]) as i32;
+            geometry.position[i].max(min).min(max)
+        };
+
+        WindowGeometry {
+            position: [clamp(0), clamp(1)],
+            size,
+        }
+    }
+}
+
+/// Where the window opens and how big, given `settings`, the area of the monitor the settings
+/// choose if any, and the geometry `saved` in the session. The window is `size` when created.
+/// `None` if the window system should place it as usual.
+pub fn choose(
+    settings: &WindowSettings,
+    monitor: Option<Area>,
+    saved: Option<WindowGeometry>,
+    monitors: &[Area],
+    primary: Area,
+    size: [u32; 2],
+) -> Option<WindowGeometry> {
+    if let Some([x, y]) = settings.position {
+        let [left, top] = monitor.map_or([0, 0], |monitor| monitor.position);
+        return Some(WindowGeometry {
+            position: [left + x, top + y],
+            size,
+        });
+    }
+
+    if let Some(monitor) = monitor {
+        return Some(WindowGeometry {
+            position: monitor.centered(size),
+            size,
+        });
+    }
+
+    let saved = saved.filter(|_| settings.restore_geometry)?;
+
+    // A window whose title bar is on no monitor, such as one left on a monitor since unplugged,
+    // could not be moved back, so it is put on the primary monitor instead.
+    let title_bar = [
+        saved.position[0] + saved.size[0] as i32 / 2,
+        saved.position[1] + TITLE_BAR_HEIGHT / 2,
+    ];
+    if monitors.iter().any(|monitor| monitor.contains(title_bar)) {
+        Some(saved)
+    } else {
+        Some(primary.clamp(saved))
+    }
+}
+
+/// Moves and resizes the freshly created `window` to where `settings` and the geometry `saved`
+/// in the session say it should open, on one of `monitors`.
+pub fn place(
+    window: &mut PistonWindow,
+    settings: &WindowSettings,
+    saved: Option<WindowGeometry>,
+    monitors: &Monitors,
+) -> Result<()> {
+    let monitor = match settings.monitor {
+        Some(index) => Some(Area::of(&monitors.by_index(index)?)),
+        None => None,
+    };
+
+    let primary = Area::of(monitors.primary());
+    let monitors: Vec<Area> = monitors.available().iter().map(Area::of).collect();
+
+    let gl_window = &window.window.window;
+    let size = match gl_window.get_inner_size() {
+        Some((width, height)) => [width, height],
+        None => return Ok(()),
+    };
+
+    if let Some(geometry) = choose(settings, monitor, saved, &monitors, primary, size) {
+        if geometry.size != size {
+            gl_window.set_inner_size(geometry.size[0], geometry.size[1]);
+        }
+        gl_window.set_position(geometry.position[0], geometry.position[1]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRIMARY: Area = Area {
+        position: [0, 0],
+        size: [1920, 1080],
+    };
+    const SECOND: Area = Area {
+        position: [1920, 0],
+        size: [1280, 1024],
+    };
+
+    fn restoring() -> WindowSettings {
+        WindowSettings {
+            restore_geometry: true,
+            ..WindowSettings::default()
+        }
+    }
+
+    fn geometry(position: [i32; 2], size: [u32; 2]) -> WindowGeometry {
+        WindowGeometry { position, size }
+    }
+
+    #[test]
+    fn saved_windows_are_restored_while_their_title_bar_is_on_a_monitor() {
+        let saved = geometry([2000, 100], [800, 600]);
+        let choice = choose(&restoring(), None, Some(saved), &[PRIMARY, SECOND], PRIMARY, [1, 1]);
+        assert_eq!(choice, Some(saved));
+
+        // Hanging off the right of the second monitor, but still grabbable.
+        let saved = geometry([2700, 900], [800, 600]);
+        let choice = choose(&restoring(), None, Some(saved), &[PRIMARY, SECOND], PRIMARY, [1, 1]);
+        assert_eq!(choice, Some(saved));
+    }
+
+    #[test]
+    fn windows_left_on_no_monitor_are_clamped_onto_the_primary_one() {
+        let saved = geometry([2000, 100], [800, 600]);
+        let choice = choose(&restoring(), None, Some(saved), &[PRIMARY], PRIMARY, [1, 1]);
+        assert_eq!(choice, Some(geometry([1120, 100], [800, 600])));
+
+        let saved = geometry([-500, -300], [2560, 1440]);
+        let choice = choose(&restoring(), None, Some(saved), &[PRIMARY], PRIMARY, [1, 1]);
+        assert_eq!(choice, Some(geometry([0, 0], [1920, 1080])));
+    }
+
+    #[test]
+    fn areas_keep_windows_that_fit_where_they_are() {
+        let fits = geometry([2000, 50], [640, 480]);
+        assert_eq!(SECOND.clamp(fits), fits);
+        let below = geometry([0, 900], [640, 480]);
+        assert_eq!(SECOND.clamp(below), geometry([1920, 544], [640, 480]));
+        assert!(SECOND.contains([1920, 0]));
+        assert!(!SECOND.contains([3200, 0]));
+        assert!(!SECOND.contains([1919, 10]));
+    }
+
+    #[test]
+    fn settings_take_precedence_over_the_saved_geometry() {
+        let saved = Some(geometry([2000, 100], [800, 600]));
+        let size = [1024, 768];
+
+        let settings = WindowSettings {
+            position: Some([10, 20]),
+            ..restoring()
+        };
+        let choice = choose(&settings, Some(SECOND), saved, &[PRIMARY, SECOND], PRIMARY, size);
+        assert_eq!(choice, Some(geometry([1930, 20], size)));
+
+        let choice = choose(&restoring(), Some(SECOND), saved, &[PRIMARY, SECOND], PRIMARY, size);
+        assert_eq!(choice, Some(geometry([2048, 128], size)));
+
+        let settings = WindowSettings {
+            restore_geometry: false,
+            ..WindowSettings::default()
+        };
+        assert_eq!(choose(&settings, None, saved, &[PRIMARY], PRIMARY, size), None);
+        assert_eq!(choose(&restoring(), None, None, &[PRIMARY], PRIMARY, size), None);
+    }
+}
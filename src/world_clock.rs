@@ -0,0 +1,174 @@
+//! Clocks pinned to cities, so that the demo can run as a world clock. Each shows the city's
+//! name and local time, in a HUD panel of its own.
+//!
+//! The places data has no time zones, so local times are worked out from each city's longitude,
+//! an hour for every 15 degrees, and written with a `~` before them to say so. That is right for
+//! most cities to within an hour.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use coordinates;
+use settings::TimeFormat;
+use tour::Capital;
+
+/// How many clocks can be pinned at once.
+pub const MAX_PINNED: usize = 3;
+
+/// What is written before a local time that was approximated from longitude.
+const APPROXIMATE: &str = "~";
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A city whose local time is shown.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PinnedClock {
+    pub name: String,
+    /// The name of the country the city is in, or "" if the places data does not say.
+    pub country: String,
+    /// In degrees.
+    pub longitude: f64,
+}
+
+impl PinnedClock {
+    pub fn at(capital: &Capital) -> PinnedClock {
+        PinnedClock {
+            name: capital.name.clone(),
+            country: capital.country.clone(),
+            longitude: coordinates::from_map_point(capital.point).1,
+        }
+    }
+
+    /// How far ahead of UTC the city's clocks are taken to be, in seconds: the nearest whole
+    /// hour to its longitude.
+    pub fn utc_offset(&self) -> i64 {
+        (self.longitude / 15.0).round() as i64 * 60 * 60
+    }
+
+    /// The city and its local time at `now`, as in "Cairo: ~14:04".
+    pub fn line(&self, now: SystemTime, format: TimeFormat) -> String {
+        let unix_time = match now.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        let seconds = (unix_time + self.utc_offset()).rem_euclid(SECONDS_PER_DAY);
+        let time = format.format((seconds / 3600) as u32, (seconds / 60 % 60) as u32);
+
+        format!("{}: {}{}", self.name, APPROXIMATE, time)
+    }
+
+    fn is_at(&self, capital: &Capital) -> bool {
+        self.name == capital.name && self.country == capital.country
+    }
+}
+
+/// What `WorldClocks::toggle` did.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Toggled {
+    Pinned,
+    Unpinned,
+    /// Nothing, since `MAX_PINNED` clocks already are.
+    Full,
+}
+
+/// The pinned clocks, in the order they were pinned.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorldClocks {
+    clocks: Vec<PinnedClock>,
+}
+
+impl WorldClocks {
+    /// The first `MAX_PINNED` of `clocks`, as a session saved them.
+    pub fn new(mut clocks: Vec<PinnedClock>) -> WorldClocks {
+        clocks.truncate(MAX_PINNED);
+        WorldClocks { clocks }
+    }
+
+    pub fn clocks(&self) -> &[PinnedClock] {
+        &self.clocks
+    }
+
+    /// Pins a clock at `capital`, or unpins it if one is.
+    pub fn toggle(&mut self, capital: &Capital) -> Toggled {
+        if let Some(index) = self.clocks.iter().position(|clock| clock.is_at(capital)) {
+            self.clocks.remove(index);
+            return Toggled::Unpinned;
+        }
+        if self.clocks.len() >= MAX_PINNED {
+            return Toggled::Full;
+        }
+
+        self.clocks.push(PinnedClock::at(capital));
+        Toggled::Pinned
+    }
+
+    /// Unpins the clock at `index`, if there is one.
+    pub fn remove(&mut self, index: usize) -> Option<PinnedClock> {
+        if index < self.clocks.len() {
+            Some(self.clocks.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// A line for each clock, as of `now`.
+    pub fn lines(&self, now: SystemTime, format: TimeFormat) -> Vec<String> {
+        self.clocks.iter().map(|clock| clock.line(now, format)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn capital(name: &str, longitude: f64) -> Capital {
+        Capital {
+            name: name.to_string(),
+            country: "Country".to_string(),
+            point: [((longitude + 180.0) / 360.0) as f32, 0.5],
+        }
+    }
+
+    /// 2024-03-02, at `hours` and `minutes` past midnight, UTC.
+    fn at(hours: u64, minutes: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_709_337_600 + hours * 3600 + minutes * 60)
+    }
+
+    #[test]
+    fn local_times_are_approximated_from_longitude() {
+        let cairo = PinnedClock::at(&capital("Cairo", 31.2));
+        assert_eq!(cairo.utc_offset(), 2 * 3600);
+        assert_eq!(cairo.line(at(12, 4), TimeFormat::TwentyFourHour), "Cairo: ~14:04");
+
+        // Across midnight, either way.
+        let papeete = PinnedClock::at(&capital("Papeete", -149.6));
+        assert_eq!(papeete.line(at(3, 30), TimeFormat::TwentyFourHour), "Papeete: ~17:30");
+        assert_eq!(papeete.line(at(3, 30), TimeFormat::TwelveHour), "Papeete: ~ 5:30 PM");
+        let wellington = PinnedClock::at(&capital("Wellington", 174.8));
+        assert_eq!(wellington.line(at(13, 0), TimeFormat::TwentyFourHour), "Wellington: ~01:00");
+    }
+
+    #[test]
+    fn up_to_three_clocks_are_pinned_and_unpinned_again() {
+        let mut clocks = WorldClocks::default();
+        let capitals: Vec<Capital> =
+            ["A", "B", "C", "D"].iter().map(|&name| capital(name, 0.0)).collect();
+
+        for capital in &capitals[..3] {
+            assert_eq!(clocks.toggle(capital), Toggled::Pinned);
+        }
+        assert_eq!(clocks.toggle(&capitals[3]), Toggled::Full);
+        assert_eq!(clocks.toggle(&capitals[1]), Toggled::Unpinned);
+        assert_eq!(clocks.toggle(&capitals[3]), Toggled::Pinned);
+
+        let names: Vec<&str> = clocks.clocks().iter().map(|clock| clock.name.as_str()).collect();
+        assert_eq!(names, ["A", "C", "D"]);
+        assert_eq!(clocks.remove(0).map(|clock| clock.name), Some("A".to_string()));
+        assert_eq!(clocks.remove(2), None);
+        assert_eq!(clocks.lines(at(0, 0), TimeFormat::TwentyFourHour), ["C: ~00:00", "D: ~00:00"]);
+
+        let saved = vec![PinnedClock::at(&capitals[0]); 5];
+        assert_eq!(WorldClocks::new(saved).clocks().len(), MAX_PINNED);
+    }
+}
@@ -0,0 +1,250 @@
+//! Renders a fixed set of views with `--render-once`, as of a fixed time, and compares them with
+//! the golden images in `tests/golden`. It needs an OpenGL context and the generated assets, so
+//! it is ignored unless asked for:
+//!
+//! ```text
+//! cargo test --test golden -- --ignored --exact views_match_golden_images
+//! ```
+//!
+//! The golden images are made, or replaced after a change that is meant to change what is drawn,
+//! by blessing what is drawn now, and checking in the new images:
+//!
+//! ```text
+//! cargo test --test golden -- --ignored --exact bless_golden_images
+//! ```
+//!
+//! Drivers rasterize and blend slightly differently, so pixels may be a little off, and a few
+//! may be further off. When an image differs by more than that, the actual image and a diff, with
+//! the differing pixels in red, are written to `target/tmp/golden`.
+
+extern crate image;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use image::{Rgba, RgbaImage};
+
+/// The time the views are drawn as of, in seconds since the Unix epoch, so that exceptional
+/// mode's colors are the same on every run.
+const CLOCK: u64 = 1_500_000_000;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+
+/// How much any channel of a pixel may be off by before it counts as differing.
+const CHANNEL_TOLERANCE: u8 = 8;
+
+/// The largest fraction of pixels that may differ, such as along edges that are antialiased
+/// differently.
+const MAX_DIFFERING_FRACTION: f64 = 0.002;
+
+struct View {
+    /// What its golden image is called in `tests/golden`, without the extension.
+    name: &'static str,
+    /// As given with `--view`.
+    view: &'static str,
+    labels: bool,
+}
+
+const VIEWS: [View; 5] = [
+    View {
+        name: "world-all",
+        view: "lat=20 lon=0 height=1 mode=all",
+        labels: false,
+    },
+    View {
+        name: "europe-terrain",
+        view: "lat=48 lon=10 height=0.3 mode=terrain",
+        labels: false,
+    },
+    View {
+        name: "africa-income",
+        view: "lat=5 lon=20 height=0.6 mode=income",
+        labels: false,
+    },
+    View {
+        name: "america-exceptional",
+        view: "lat=39 lon=-98 height=0.25 mode=exceptional",
+        labels: false,
+    },
+    View {
+        name: "japan-oecd-labels",
+        view: "lat=35.7 lon=139.7 height=0.05 mode=oecd",
+        labels: true,
+    },
+];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+/// Renders `view` to `path`, with the settings in `config`. Fails unless every tile loaded.
+fn render(view: &View, config: &Path, path: &Path) -> Result<(), String> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_gaia_demo"));
+    command
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("--config")
+        .arg(config)
+        .arg("--render-once")
+        .arg(path)
+        .args(["--view", view.view])
+        .args(["--width", &WIDTH.to_string(), "--height", &HEIGHT.to_string()])
+        .args(["--clock", &CLOCK.to_string()])
+        .arg("--quiet");
+    if view.labels {
+        command.arg("--labels");
+    }
+
+    let status = command
+        .status()
+        .map_err(|e| format!("could not run the demo: {}", e))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(2) => Err("tiles were still loading when it was rendered".to_string()),
+        _ => Err(format!("the demo failed with {}", status)),
+    }
+}
+
+/// Compares `actual` with `golden`. If they differ by more than the tolerance allows, writes a
+/// diff to `diff_path` and says by how much.
+fn compare(actual: &RgbaImage, golden: &RgbaImage, diff_path: &Path) -> Result<(), String> {
+    if actual.dimensions() != golden.dimensions() {
+        return Err(format!(
+            "it is {:?}, but the golden image is {:?}",
+            actual.dimensions(),
+            golden.dimensions()
+        ));
+    }
+
+    let differs = |a: &Rgba<u8>, b: &Rgba<u8>| {
+        a.data
+            .iter()
+            .zip(b.data.iter())
+            .any(|(&a, &b)| (i16::from(a) - i16::from(b)).abs() > i16::from(CHANNEL_TOLERANCE))
+    };
+    let differing = actual
+        .pixels()
+        .zip(golden.pixels())
+        .filter(|&(a, b)| differs(a, b))
+        .count();
+    let fraction = differing as f64 / f64::from(actual.width() * actual.height());
+    if fraction <= MAX_DIFFERING_FRACTION {
+        return Ok(());
+    }
+
+    // Differing pixels are red, over a faded copy of the golden image.
+    let diff = RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let golden_pixel = golden.get_pixel(x, y);
+        if differs(actual.get_pixel(x, y), golden_pixel) {
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = golden_pixel.data;
+            let gray = ((u16::from(r) + u16::from(g) + u16::from(b)) / 6) as u8;
+            Rgba([gray, gray, gray, 255])
+        }
+    });
+    diff.save(diff_path)
+        .map_err(|e| format!("could not write {}: {}", diff_path.display(), e))?;
+
+    Err(format!(
+        "{:.2}% of pixels differ, more than {:.2}%. See {}",
+        fraction * 100.0,
+        MAX_DIFFERING_FRACTION * 100.0,
+        diff_path.display()
+    ))
+}
+
+fn load(path: &Path) -> Result<RgbaImage, String> {
+    image::open(path)
+        .map(|image| image.to_rgba())
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+/// Renders `view` and compares it with its golden image, or replaces the golden image if
+/// `bless` is set.
+fn check(view: &View, config: &Path, bless: bool) -> Result<(), String> {
+    let golden_path = golden_dir().join(format!("{}.png", view.name));
+    let actual_path = output_dir().join(format!("{}-actual.png", view.name));
+    let diff_path = output_dir().join(format!("{}-diff.png", view.name));
+    let _ = fs::remove_file(&diff_path);
+
+    render(view, config, &actual_path)?;
+
+    if bless {
+        fs::create_dir_all(golden_dir()).map_err(|e| e.to_string())?;
+        fs::copy(&actual_path, &golden_path)
+            .map_err(|e| format!("could not write {}: {}", golden_path.display(), e))?;
+        println!("Updated {}", golden_path.display());
+        let _ = fs::remove_file(&actual_path);
+        return Ok(());
+    }
+
+    if !golden_path.exists() {
+        return Err(format!(
+            "there is no golden image {}. Run `bless_golden_images` to create it",
+            golden_path.display()
+        ));
+    }
+
+    compare(&load(&actual_path)?, &load(&golden_path)?, &diff_path)?;
+    // Only the images of views that failed are kept.
+    let _ = fs::remove_file(&actual_path);
+
+    Ok(())
+}
+
+/// Checks, or with `bless` replaces, the golden image of every view.
+fn check_all(bless: bool) -> Vec<String> {
+    // The HUD shows the frame rate, which differs from run to run, so it is hidden.
+    fs::create_dir_all(output_dir()).unwrap();
+    let config = output_dir().join("settings.toml");
+    fs::write(&config, "[hud]\nvisible = false\n\n[labels]\nenabled = false\n").unwrap();
+
+    VIEWS
+        .iter()
+        .filter_map(|view| {
+            check(view, &config, bless)
+                .err()
+                .map(|e| format!("{}: {}", view.name, e))
+        })
+        .collect()
+}
+
+#[test]
+#[ignore]
+fn views_match_golden_images() {
+    assert!(
+        golden_dir().exists(),
+        "There are no golden images in {} yet. Run `bless_golden_images` where the assets have \
+         been generated, check that the images look right, and check them in",
+        golden_dir().display()
+    );
+
+    let failures = check_all(false);
+    assert!(
+        failures.is_empty(),
+        "{} of {} views differ from their golden images:\n{}",
+        failures.len(),
+        VIEWS.len(),
+        failures.join("\n")
+    );
+}
+
+/// Replaces the golden images with what is drawn now. Only run when asked for by name.
+#[test]
+#[ignore]
+fn bless_golden_images() {
+    let failures = check_all(true);
+    assert!(
+        failures.is_empty(),
+        "{} of {} views could not be drawn:\n{}",
+        failures.len(),
+        VIEWS.len(),
+        failures.join("\n")
+    );
+}
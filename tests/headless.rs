@@ -0,0 +1,87 @@
+//! Drives the demo's state without a window, as the main loop would, and checks what the map
+//! would be drawn with.
+
+extern crate gaia_demo;
+extern crate serde_json;
+
+use std::path::Path;
+
+use gaia_demo::bindings::Action;
+use gaia_demo::headless::Headless;
+use gaia_demo::settings::Settings;
+use gaia_demo::split_view::View;
+use gaia_demo::state::WindowRequest;
+use gaia_demo::MapMode;
+
+fn headless() -> Headless {
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/countries.json");
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/target/tmp/headless");
+    Headless::with_fixture(Settings::default(), Path::new(fixture), Path::new(dir)).unwrap()
+}
+
+fn place(json: &str) -> serde_json::Map<String, serde_json::Value> {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn fixture_countries_are_loaded() {
+    let headless = headless();
+    let ids = headless.country_ids();
+
+    assert_eq!(ids.len(), 7);
+    assert!(ids.contains(&"USA"));
+    assert!(ids.contains(&"France"));
+    assert!(headless.state.features.warnings().is_empty());
+}
+
+#[test]
+fn mode_actions_recolor_countries() {
+    let mut headless = headless();
+
+    headless.handle(Action::ShowIncome);
+    headless.update(1.0 / 60.0);
+    assert_eq!(headless.country_color("TCD", View::Whole), Some([255, 0, 0, 100]));
+
+    headless.handle(Action::ShowOecd);
+    assert!(headless.country_color("USA", View::Whole).is_some());
+    assert_eq!(headless.country_color("TCD", View::Whole), None);
+
+    headless.handle(Action::ShowTerrain);
+    assert_eq!(headless.country_color("USA", View::Whole), None);
+    assert_eq!(headless.country_color("Nowhere", View::Whole), None);
+}
+
+#[test]
+fn split_screen_colors_each_half() {
+    let mut headless = headless();
+    headless.handle(Action::ShowIncome);
+    headless.handle(Action::ToggleSplit);
+    headless.handle(Action::ShowInRightHalf(MapMode::Terrain));
+
+    assert!(headless.country_color("TCD", View::Left).is_some());
+    assert_eq!(headless.country_color("TCD", View::Right), None);
+}
+
+#[test]
+fn labels_follow_the_toggle_and_camera_height() {
+    let mut headless = headless();
+    let capital = place(r#"{"NAME": "Paris", "min_zoom": 0.1, "ADM0CAP": 1}"#);
+    let village = place(r#"{"NAME": "Giverny", "min_zoom": 1000}"#);
+
+    // Labels start off.
+    assert!(headless.label_style(&capital, View::Whole).is_none());
+
+    headless.handle(Action::ToggleLabels);
+    let style = headless.label_style(&capital, View::Whole).unwrap();
+    assert_eq!(style.text, "Paris");
+    assert!(headless.label_style(&village, View::Whole).is_none());
+}
+
+#[test]
+fn window_requests_are_collected() {
+    let mut headless = headless();
+    headless.handle(Action::Screenshot);
+
+    assert_eq!(headless.take_window_requests(), [WindowRequest::Screenshot]);
+    assert!(headless.take_window_requests().is_empty());
+}
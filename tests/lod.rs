@@ -0,0 +1,93 @@
+//! Checks which level of detail is drawn as the camera sweeps from far above the map down to it.
+
+extern crate gaia_assetgen;
+extern crate gaia_demo;
+
+use gaia_demo::settings::{LevelThreshold, QualitySettings};
+
+/// Camera heights from well above the highest threshold down to nearly the ground, highest first.
+fn heights() -> Vec<f32> {
+    (0..200).map(|i| 2.0 * 0.97f32.powi(i)).collect()
+}
+
+fn levels(quality: &QualitySettings) -> Vec<u8> {
+    heights().into_iter().map(|height| quality.level_for_height(height)).collect()
+}
+
+#[test]
+fn levels_increase_as_the_camera_descends() {
+    let quality = QualitySettings::default();
+    let levels = levels(&quality);
+
+    assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", levels);
+    assert_eq!(levels.first(), Some(&quality.min_level));
+    assert_eq!(levels.last(), Some(&5));
+}
+
+#[test]
+fn default_thresholds() {
+    let quality = QualitySettings::default();
+    let at = |height| quality.level_for_height(height);
+
+    assert_eq!(at(1.0), 1);
+    assert_eq!(at(0.6), 2);
+    assert_eq!(at(0.5), 2);
+    assert_eq!(at(0.3), 3);
+    assert_eq!(at(0.15), 4);
+    assert_eq!(at(0.05), 5);
+}
+
+#[test]
+fn levels_stay_within_min_and_max() {
+    let quality = QualitySettings {
+        min_level: 2,
+        max_level: 3,
+        ..QualitySettings::default()
+    }.validated();
+
+    for (height, level) in heights().into_iter().zip(levels(&quality)) {
+        assert!((2..=3).contains(&level), "level {} at height {}", level, height);
+        if quality.threshold_level(height) > 3 {
+            assert_eq!(level, 3);
+        }
+    }
+}
+
+#[test]
+fn unordered_thresholds_are_reported_and_fall_back_to_the_defaults() {
+    let quality = QualitySettings {
+        levels: vec![LevelThreshold::new(0.2, 4), LevelThreshold::new(0.5, 3)],
+        ..QualitySettings::default()
+    };
+    assert_eq!(
+        quality.levels_error().unwrap(),
+        "thresholds 1 and 2 are out of order: heights must decrease and levels increase"
+    );
+
+    let quality = quality.validated();
+
+    assert_eq!(quality.levels, QualitySettings::default().levels);
+    assert_eq!(levels(&quality), levels(&QualitySettings::default()));
+}
+
+#[test]
+fn thresholds_that_cannot_be_used_say_why() {
+    assert_eq!(QualitySettings::default().levels_error(), None);
+
+    let error = |levels: Vec<LevelThreshold>| {
+        QualitySettings {
+            levels,
+            ..QualitySettings::default()
+        }.levels_error()
+    };
+    assert_eq!(
+        error(vec![LevelThreshold::new(0.5, 1), LevelThreshold::new(0.0, 2)]).unwrap(),
+        "threshold 2's height must be above 0"
+    );
+    assert_eq!(
+        error(vec![LevelThreshold::new(0.5, 200)]).unwrap(),
+        format!("threshold 1's level must be at most {}", gaia_assetgen::MAX_LEVEL)
+    );
+    assert!(error(vec![LevelThreshold::new(0.5, 1), LevelThreshold::new(0.5, 2)]).is_some());
+    assert_eq!(error(vec![]), None);
+}
@@ -0,0 +1,144 @@
+//! Checks each map mode's colors for the countries in `tests/fixtures/countries.json`, which have
+//! the properties Natural Earth gives them that the modes read.
+
+extern crate gaia_demo;
+extern crate serde_json;
+
+use std::fs::File;
+
+use gaia_demo::props::{CountryProps, IncomeClass};
+use gaia_demo::MapMode;
+
+fn countries() -> Vec<CountryProps> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/countries.json");
+    let file = File::open(path).unwrap();
+    let properties: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_reader(file).unwrap();
+
+    let mut warnings = vec![];
+    let countries = properties
+        .iter()
+        .map(|properties| CountryProps::from_properties(properties, &mut warnings))
+        .collect();
+    assert!(warnings.is_empty(), "{:?}", warnings);
+
+    countries
+}
+
+fn country(admin: &str) -> CountryProps {
+    countries()
+        .into_iter()
+        .find(|country| country.admin == admin)
+        .unwrap()
+}
+
+/// The names of the countries `mode` colors at all, with every legend category enabled.
+fn shown(mode: MapMode) -> Vec<String> {
+    countries()
+        .into_iter()
+        .filter(|country| mode.shown_color(country, |_| true, Some(0)).is_some())
+        .map(|country| country.admin)
+        .collect()
+}
+
+#[test]
+fn fixture_properties_are_read() {
+    let usa = country("United States of America");
+    assert_eq!(usa.id, "USA");
+    assert_eq!(usa.income, Some(IncomeClass::HighOecd));
+    assert_eq!(usa.mapcolor13, Some(1));
+
+    // Countries without an ISO code go by their full name.
+    assert_eq!(country("France").id, "France");
+
+    let antarctica = country("Antarctica");
+    assert_eq!(antarctica.income, None);
+    assert_eq!(antarctica.mapcolor13, None);
+    assert_eq!(antarctica.pop_est, None);
+}
+
+#[test]
+fn terrain_colors_nothing() {
+    assert!(shown(MapMode::Terrain).is_empty());
+    assert!(MapMode::Terrain.legend_entries().is_empty());
+}
+
+#[test]
+fn all_countries_are_colored_by_map_color() {
+    assert_eq!(shown(MapMode::All).len(), countries().len());
+
+    let mode = MapMode::All;
+    assert_eq!(mode.color(&country("Antarctica"), None), Some([153, 0, 0, 64]));
+    assert_eq!(mode.color(&country("United States of America"), None), Some([153, 71, 0, 64]));
+    // Neighbors are told apart by map color alone.
+    assert_eq!(
+        mode.color(&country("Saudi Arabia"), None),
+        mode.color(&country("Brazil"), None)
+    );
+}
+
+#[test]
+fn oecd_shows_only_members() {
+    assert_eq!(shown(MapMode::Oecd), ["United States of America", "France"]);
+    assert_eq!(
+        MapMode::Oecd.describe(&country("Brazil")),
+        Some("Not an OECD member".to_string())
+    );
+}
+
+#[test]
+fn income_groups_have_their_own_colors() {
+    let mode = MapMode::Income;
+    let colors = [
+        ("United States of America", [0, 255, 0, 100]),
+        ("France", [0, 255, 0, 100]),
+        ("Saudi Arabia", [50, 200, 0, 100]),
+        ("Brazil", [100, 150, 0, 100]),
+        ("India", [150, 200, 0, 100]),
+        ("Chad", [255, 0, 0, 100]),
+    ];
+    for &(admin, color) in &colors {
+        assert_eq!(mode.shown_color(&country(admin), |_| true, None), Some(color), "{}", admin);
+    }
+
+    // Countries without an income group are left uncolored.
+    assert_eq!(mode.shown_color(&country("Antarctica"), |_| true, None), None);
+    assert_eq!(mode.category(&country("Antarctica")), None);
+    // Even asked for directly, rather than through `shown_color`.
+    assert_eq!(mode.color(&country("Antarctica"), None), None);
+}
+
+#[test]
+fn income_legend_matches_categories() {
+    let mode = MapMode::Income;
+    let entries = mode.legend_entries();
+    assert_eq!(entries.len(), 5);
+
+    for country in countries() {
+        if let Some(category) = mode.category(&country) {
+            assert_eq!(Some(entries[category].color), mode.shown_color(&country, |_| true, None));
+        }
+    }
+}
+
+#[test]
+fn hidden_categories_are_not_colored() {
+    let mode = MapMode::Income;
+    let low_income = mode.category(&country("Chad")).unwrap();
+    let enabled = |category| category != low_income;
+
+    assert_eq!(mode.shown_color(&country("Chad"), enabled, None), None);
+    assert!(mode.shown_color(&country("India"), enabled, None).is_some());
+}
+
+#[test]
+fn exceptional_cycles_through_hues() {
+    assert_eq!(shown(MapMode::Exceptional), ["United States of America"]);
+
+    let usa = country("United States of America");
+    let mode = MapMode::Exceptional;
+    assert_eq!(mode.color(&usa, Some(0)), Some([255, 0, 0, 100]));
+    assert_ne!(mode.color(&usa, Some(1)), mode.color(&usa, Some(0)));
+    // The hue goes around every 18 seconds.
+    assert_eq!(mode.color(&usa, Some(18)), mode.color(&usa, Some(0)));
+}